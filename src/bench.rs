@@ -0,0 +1,70 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// ---------------------------------------------------------------------------
+// Bench — timing helpers for the ignored `bench_` tests
+// ---------------------------------------------------------------------------
+//
+// Each module benchmarks its own hot paths in `#[ignore]`d tests named
+// `bench_*`, run in release mode with:
+//
+//     cargo test --release -- --ignored --nocapture --test-threads=1 bench_
+//
+// A benchmark fails when an operation takes longer than its budget, which is
+// set well above the current timing so only real regressions trip it.
+
+/// Time `f` over `iters` runs, print the mean and return it.
+pub fn measure<R>(label: &str, iters: u32, mut f: impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iters {
+        black_box(f());
+    }
+    let mean = start.elapsed() / iters;
+    println!("{:<36} {:>12.3?}", label, mean);
+    mean
+}
+
+/// Like `measure`, failing when the mean exceeds `budget`.
+pub fn within<R>(label: &str, iters: u32, budget: Duration, f: impl FnMut() -> R) {
+    let mean = measure(label, iters, f);
+    assert!(
+        mean <= budget,
+        "{} took {:?}, over its {:?} budget",
+        label,
+        mean,
+        budget
+    );
+}
+
+/// `lines` lines of varied code, like a large source file.
+pub fn large_text(lines: usize) -> String {
+    let mut rng = Rng::new(lines as u64);
+    (0..lines)
+        .map(|i| {
+            let indent = " ".repeat(rng.below(4) * 4);
+            format!(
+                "{}let value_{} = compute({}, \"item\");\n",
+                indent,
+                i,
+                rng.below(1000)
+            )
+        })
+        .collect()
+}
+
+/// Xorshift generator, so runs are repeatable without a rand dependency.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n.max(1) as u64) as usize
+    }
+}
@@ -0,0 +1,126 @@
+// ---------------------------------------------------------------------------
+// Bidi — display order for lines with right-to-left text
+// ---------------------------------------------------------------------------
+//
+// Lines are laid out left to right, as code is. A run of right-to-left
+// letters (Hebrew, Arabic, ...) is drawn reversed so it reads correctly,
+// along with the spaces and punctuation between its letters; numbers inside
+// a run keep their left-to-right order. This is the common case of the
+// Unicode bidi algorithm without explicit embeddings, mirrored brackets or
+// right-to-left paragraphs.
+//
+// The buffer, cursor and selection stay in logical order. Only the mapping
+// between characters and screen columns changes.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Rtl,
+    Ltr,
+    Number,
+    Neutral,
+}
+
+/// Whether `c` is written right to left.
+pub fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{05FF}'     // Hebrew
+        | '\u{0600}'..='\u{065F}'   // Arabic, before the Arabic-Indic digits
+        | '\u{066A}'..='\u{06EF}'
+        | '\u{06FA}'..='\u{08FF}'   // Arabic, Syriac, Thaana, NKo, Samaritan, Mandaic
+        | '\u{200F}'                // right-to-left mark
+        | '\u{FB1D}'..='\u{FDFF}'   // Hebrew and Arabic presentation forms
+        | '\u{FE70}'..='\u{FEFE}')
+}
+
+fn class(c: char) -> Class {
+    match c {
+        '0'..='9' | '\u{0660}'..='\u{0669}' | '\u{06F0}'..='\u{06F9}' => Class::Number,
+        _ if is_rtl(c) => Class::Rtl,
+        _ if c.is_alphanumeric() => Class::Ltr,
+        _ => Class::Neutral,
+    }
+}
+
+/// For each screen column of `line`, the index of the character drawn
+/// there, or None when the line has no right-to-left text.
+fn visual_order(line: &str) -> Option<Vec<usize>> {
+    if !line.chars().any(is_rtl) {
+        return None;
+    }
+    let classes: Vec<Class> = line.chars().map(class).collect();
+    let mut order: Vec<usize> = (0..classes.len()).collect();
+    let mut start = 0;
+    while start < classes.len() {
+        if classes[start] != Class::Rtl {
+            start += 1;
+            continue;
+        }
+        // The run ends at its last right-to-left letter before a
+        // left-to-right one
+        let mut end = start + 1;
+        for (i, &c) in classes.iter().enumerate().skip(start + 1) {
+            match c {
+                Class::Ltr => break,
+                Class::Rtl => end = i + 1,
+                _ => {}
+            }
+        }
+        let run = &mut order[start..end];
+        run.reverse();
+        // Put numbers back in reading order
+        let mut i = 0;
+        while i < run.len() {
+            let digits = run[i..]
+                .iter()
+                .take_while(|&&c| classes[c] == Class::Number)
+                .count();
+            run[i..i + digits].reverse();
+            i += digits.max(1);
+        }
+        start = end;
+    }
+    Some(order)
+}
+
+/// The characters of `line` with their byte offsets, in the order they are
+/// drawn from left to right.
+pub fn visual_chars(line: &str) -> Vec<(usize, char)> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    match visual_order(line) {
+        Some(order) => order.into_iter().map(|i| chars[i]).collect(),
+        None => chars,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visual(line: &str) -> String {
+        visual_chars(line).into_iter().map(|(_, c)| c).collect()
+    }
+
+    #[test]
+    fn test_visual_order() {
+        assert_eq!(visual("let x = 1;"), "let x = 1;");
+        assert_eq!(visual("שלום"), "םולש");
+        // Spaces between right-to-left words belong to the run; the ones
+        // around it stay put
+        assert_eq!(visual("say שלום עולם now"), "say םלוע םולש now");
+        assert_eq!(visual("// مرحبا"), "// ابحرم");
+        // Numbers read left to right inside a run, and after it
+        assert_eq!(visual("א 12 ב"), "ב 12 א");
+        assert_eq!(visual("שלום 42"), "םולש 42");
+    }
+
+    #[test]
+    fn test_visual_chars_keep_offsets() {
+        // "a" then three two-byte Hebrew letters
+        let chars = visual_chars("aאבג");
+        assert_eq!(chars, [(0, 'a'), (5, 'ג'), (3, 'ב'), (1, 'א')]);
+    }
+}
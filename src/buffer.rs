@@ -1,15 +1,52 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
 use std::path::{Path, PathBuf};
 
+use crate::filetype;
+
 const INITIAL_GAP: usize = 1024;
+/// Edits kept for `edits_since`.
+const MAX_EDITS: usize = 256;
+/// Bytes at either end of the text looked at to detect the filetype.
+const DETECT_BYTES: usize = 1024;
+
+/// One change to the text: `deleted` bytes at `pos` replaced by `inserted`
+/// bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub pos: usize,
+    pub deleted: usize,
+    pub inserted: usize,
+}
 
 pub struct Buffer {
     data: Vec<u8>,
     gap_start: usize,
     gap_end: usize,
     lines: Vec<usize>,
+    /// Per line: edited since the last load or save.
+    changed: Vec<bool>,
     modified: bool,
+    version: u64,
+    /// The latest edits, oldest first; the last one made `version`.
+    edits: Vec<Edit>,
     file_path: Option<PathBuf>,
+    name: Option<String>,
+    /// Filetype chosen by the user, overriding the one detected from the path.
+    filetype: Option<String>,
+    /// Tab and reflow widths chosen for this buffer, over the configured ones.
+    tab_width: Option<usize>,
+    text_width: Option<usize>,
+    read_only: bool,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Buffer {
@@ -20,11 +57,38 @@ impl Buffer {
             gap_start: 0,
             gap_end: INITIAL_GAP,
             lines: vec![0],
+            changed: vec![false],
             modified: false,
+            version: 0,
+            edits: Vec::new(),
             file_path: None,
+            name: None,
+            filetype: None,
+            tab_width: None,
+            text_width: None,
+            read_only: false,
         }
     }
 
+    /// Create an unnamed-file buffer holding `text`, labelled `name` in the UI.
+    /// Scratch buffers start unmodified so they never block quitting.
+    pub fn scratch(name: &str, text: &str) -> Buffer {
+        let mut buf = Buffer::new();
+        buf.insert(0, text);
+        buf.name = Some(name.to_string());
+        buf.mark_saved();
+        buf
+    }
+
+    /// A buffer holding unsaved `text` recovered from a lost session, to be
+    /// saved to `path`. It starts modified.
+    pub fn recovered(path: Option<PathBuf>, text: &str) -> Buffer {
+        let mut buf = Buffer::new();
+        buf.insert(0, text);
+        buf.file_path = path;
+        buf
+    }
+
     pub fn from_file(path: &Path) -> Result<Buffer, String> {
         let content = fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
         let content_len = content.len();
@@ -38,10 +102,19 @@ impl Buffer {
             gap_start: content_len,
             gap_end: content_len + gap_size,
             lines: Vec::new(),
+            changed: Vec::new(),
             modified: false,
+            version: 0,
+            edits: Vec::new(),
             file_path: Some(path.to_path_buf()),
+            name: None,
+            filetype: None,
+            tab_width: None,
+            text_width: None,
+            read_only: false,
         };
         buf.rebuild_lines();
+        buf.changed = vec![false; buf.lines.len()];
         Ok(buf)
     }
 
@@ -50,26 +123,114 @@ impl Buffer {
             .file_path
             .as_ref()
             .ok_or_else(|| "No file path set".to_string())?;
-        fs::write(path, self.text_bytes()).map_err(|e| format!("Failed to write file: {}", e))
-    }
-
-    pub fn save_to(&mut self, path: &Path) -> Result<(), String> {
-        fs::write(path, self.text_bytes()).map_err(|e| format!("Failed to write file: {}", e))?;
-        self.file_path = Some(path.to_path_buf());
-        self.modified = false;
-        Ok(())
+        write_file(path, &self.text_bytes()).map_err(|e| format!("Failed to write file: {}", e))
     }
 
     pub fn file_path(&self) -> Option<&Path> {
         self.file_path.as_deref()
     }
 
+    /// Name the buffer by its file's real path when it was opened through
+    /// a symlink.
+    pub fn resolve_symlink(&mut self) {
+        if let Some(ref path) = self.file_path
+            && path.is_symlink()
+            && let Ok(target) = fs::canonicalize(path)
+        {
+            self.file_path = Some(target);
+        }
+    }
+
+    /// Display name for buffers without a file (e.g. "[Output]").
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The buffer's filetype: the override if one is set, else the one
+    /// detected from the file path and the text at either end.
+    pub fn filetype(&self) -> String {
+        if let Some(ref filetype) = self.filetype {
+            return filetype.clone();
+        }
+        let len = self.len();
+        let head = self.slice(0, len.min(DETECT_BYTES));
+        let tail = self.slice(len.saturating_sub(DETECT_BYTES).max(head.len()), len);
+        filetype::detect_in(self.file_path(), &head, &tail)
+    }
+
+    /// Override the detected filetype; None goes back to detection.
+    pub fn set_filetype(&mut self, filetype: Option<String>) {
+        self.filetype = filetype;
+    }
+
+    /// The tab width set for this buffer, if any.
+    pub fn tab_width(&self) -> Option<usize> {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, width: Option<usize>) {
+        self.tab_width = width;
+    }
+
+    /// The reflow width set for this buffer, if any.
+    pub fn text_width(&self) -> Option<usize> {
+        self.text_width
+    }
+
+    pub fn set_text_width(&mut self, width: Option<usize>) {
+        self.text_width = width;
+    }
+
+    /// Whether lines end in CRLF, judged by the first one.
+    pub fn is_crlf(&self) -> bool {
+        self.line_end(0)
+            .and_then(|end| end.checked_sub(1))
+            .is_some_and(|last| self.char_at(last) == Some('\r'))
+    }
+
     pub fn is_modified(&self) -> bool {
         self.modified
     }
 
+    /// Counter bumped by every edit, so observers (the language server
+    /// sync) can tell whether the contents changed since they last looked.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The edits made since `version`, oldest first. None when some of
+    /// them are no longer kept.
+    pub fn edits_since(&self, version: u64) -> Option<&[Edit]> {
+        let count = usize::try_from(self.version.checked_sub(version)?).ok()?;
+        let first = self.edits.len().checked_sub(count)?;
+        Some(&self.edits[first..])
+    }
+
+    fn record_edit(&mut self, edit: Edit) {
+        if self.edits.len() == MAX_EDITS {
+            self.edits.remove(0);
+        }
+        self.edits.push(edit);
+        self.version += 1;
+    }
+
+    /// Read-only buffers (the help screen) refuse editing commands.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
     pub fn mark_saved(&mut self) {
         self.modified = false;
+        self.changed.iter_mut().for_each(|c| *c = false);
+    }
+
+    /// Whether `line` was edited (or added) since the last load or save.
+    pub fn is_line_changed(&self, line: usize) -> bool {
+        self.changed.get(line).copied().unwrap_or(false)
     }
 
     // --- Text access ---
@@ -82,46 +243,82 @@ impl Buffer {
         self.len() == 0
     }
 
-    pub fn get_line(&self, line: usize) -> Option<String> {
-        if line >= self.lines.len() {
-            return None;
-        }
-        let start = self.lines[line];
-        let end = if line + 1 < self.lines.len() {
-            // Line ends just before the '\n' that starts the next line
-            self.lines[line + 1] - 1
-        } else {
-            self.len()
-        };
-        let mut result = Vec::with_capacity(end - start);
-        for i in start..end {
-            if let Some(b) = self.byte_at(i) {
-                result.push(b);
-            }
-        }
-        Some(String::from_utf8_lossy(&result).into_owned())
+    /// The text of `line` without its newline, borrowed from the buffer
+    /// unless it straddles the gap.
+    pub fn get_line(&self, line: usize) -> Option<Cow<'_, str>> {
+        let start = self.line_start(line)?;
+        let end = self.line_end(line)?;
+        Some(self.str_range(start, end))
     }
 
-    /// Extract text between byte offsets `[start, end)` without modifying the buffer.
+    /// The text of `line` up to `max_len` bytes into it (widened to a whole
+    /// character), so the start of a huge line can be looked at without
+    /// copying or checking all of it.
+    pub fn get_line_head(&self, line: usize, max_len: usize) -> Option<Cow<'_, str>> {
+        let start = self.line_start(line)?;
+        let end = self.line_end(line)?;
+        Some(self.str_range(start, end.min(start.saturating_add(max_len))))
+    }
+
+    /// Extract text between byte offsets `[start, end)` without modifying the
+    /// buffer, widened to whole characters.
     pub fn slice(&self, start: usize, end: usize) -> String {
-        let start = start.min(self.len());
-        let end = end.min(self.len());
-        if start >= end {
-            return String::new();
-        }
-        let mut result = Vec::with_capacity(end - start);
-        for i in start..end {
-            if let Some(b) = self.byte_at(i) {
-                result.push(b);
-            }
-        }
-        String::from_utf8_lossy(&result).into_owned()
+        self.str_range(start, end).into_owned()
+    }
+
+    /// The bytes of `[start, end)`, read in place without copying.
+    pub fn bytes(&self, start: usize, end: usize) -> impl Iterator<Item = u8> + '_ {
+        let (before, after) = self.chunks(start, end);
+        before.iter().chain(after).copied()
     }
 
     pub fn text(&self) -> String {
         String::from_utf8_lossy(&self.text_bytes()).into_owned()
     }
 
+    /// The whole text, borrowed when the gap sits at either end (see
+    /// `make_contiguous`) and copied otherwise.
+    pub fn contents(&self) -> Cow<'_, str> {
+        self.str_range(0, self.len())
+    }
+
+    /// Move the gap to the end so `contents` can borrow the text. Edits
+    /// move it back, so this is cheap to repeat between them.
+    pub fn make_contiguous(&mut self) {
+        self.move_gap(self.len());
+    }
+
+    /// Whether `pos` starts a character (or is the end of the text), so
+    /// edits and slices there keep the text valid UTF-8.
+    pub fn is_char_boundary(&self, pos: usize) -> bool {
+        self.byte_at(pos).is_none_or(|b| b & 0xC0 != 0x80)
+    }
+
+    /// The start of the character containing `pos`.
+    pub fn floor_char_boundary(&self, pos: usize) -> usize {
+        let mut pos = pos.min(self.len());
+        // A character spans at most four bytes
+        for _ in 0..3 {
+            if self.is_char_boundary(pos) {
+                break;
+            }
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// The end of the character containing `pos`, or `pos` on a boundary.
+    pub fn ceil_char_boundary(&self, pos: usize) -> usize {
+        let mut pos = pos.min(self.len());
+        for _ in 0..3 {
+            if self.is_char_boundary(pos) {
+                break;
+            }
+            pos += 1;
+        }
+        pos
+    }
+
     pub fn char_at(&self, byte_pos: usize) -> Option<char> {
         if byte_pos >= self.len() {
             return None;
@@ -132,8 +329,8 @@ impl Buffer {
             return Some(first as char);
         }
         let mut bytes = [first, 0, 0, 0];
-        for i in 1..char_len {
-            bytes[i] = self.byte_at(byte_pos + i)?; // indexing both bytes[] and byte_pos offset
+        for (i, slot) in bytes.iter_mut().enumerate().take(char_len).skip(1) {
+            *slot = self.byte_at(byte_pos + i)?;
         }
         std::str::from_utf8(&bytes[..char_len])
             .ok()
@@ -141,15 +338,28 @@ impl Buffer {
     }
 
     // --- Editing ---
+    //
+    // Offsets inside a character snap to its start, and deletes widen to
+    // whole characters, so no edit can split one.
 
     pub fn insert(&mut self, pos: usize, text: &str) {
-        let pos = pos.min(self.len());
+        let pos = self.floor_char_boundary(pos);
         let bytes = text.as_bytes();
+        let line = self.byte_to_line(pos);
+        let added = bytes.iter().filter(|&&b| b == b'\n').count();
+        self.changed[line] = true;
+        self.changed
+            .splice(line + 1..line + 1, std::iter::repeat_n(true, added));
         self.ensure_gap(bytes.len());
         self.move_gap(pos);
         self.data[self.gap_start..self.gap_start + bytes.len()].copy_from_slice(bytes);
         self.gap_start += bytes.len();
         self.modified = true;
+        self.record_edit(Edit {
+            pos,
+            deleted: 0,
+            inserted: bytes.len(),
+        });
         self.rebuild_lines();
     }
 
@@ -157,19 +367,23 @@ impl Buffer {
         if len == 0 || pos >= self.len() {
             return String::new();
         }
-        let len = len.min(self.len() - pos);
-        // Collect the bytes being deleted
-        let mut deleted = Vec::with_capacity(len);
-        for i in pos..pos + len {
-            if let Some(b) = self.byte_at(i) {
-                deleted.push(b);
-            }
-        }
+        let end = self.ceil_char_boundary(pos.saturating_add(len));
+        let pos = self.floor_char_boundary(pos);
+        let len = end - pos;
+        let (first, last) = (self.byte_to_line(pos), self.byte_to_line(pos + len));
+        self.changed.drain(first + 1..=last);
+        self.changed[first] = true;
+        let deleted = self.slice(pos, pos + len);
         self.move_gap(pos);
         self.gap_end += len;
         self.modified = true;
+        self.record_edit(Edit {
+            pos,
+            deleted: len,
+            inserted: 0,
+        });
         self.rebuild_lines();
-        String::from_utf8_lossy(&deleted).into_owned()
+        deleted
     }
 
     // --- Line info ---
@@ -221,6 +435,27 @@ impl Buffer {
         }
     }
 
+    /// The parts of `[start, end)` before and after the gap.
+    fn chunks(&self, start: usize, end: usize) -> (&[u8], &[u8]) {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        let before = &self.data[start.min(self.gap_start)..end.min(self.gap_start)];
+        let after = &self.data[self.logical_to_physical(start.max(self.gap_start))
+            ..self.logical_to_physical(end.max(self.gap_start))];
+        (before, after)
+    }
+
+    fn str_range(&self, start: usize, end: usize) -> Cow<'_, str> {
+        let end = self.ceil_char_boundary(end);
+        let start = self.floor_char_boundary(start.min(end));
+        match self.chunks(start, end) {
+            (bytes, []) | ([], bytes) => String::from_utf8_lossy(bytes),
+            (before, after) => String::from_utf8_lossy(&[before, after].concat())
+                .into_owned()
+                .into(),
+        }
+    }
+
     fn byte_at(&self, pos: usize) -> Option<u8> {
         if pos >= self.len() {
             return None;
@@ -290,6 +525,81 @@ impl Buffer {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Saving — new contents for the file, not a new file
+// ---------------------------------------------------------------------------
+
+/// Write `bytes` as the contents of `path`, keeping what the name stands
+/// for. Through a symlink the target is written and the link kept. A file
+/// with other hard links, or without write permission, is overwritten in
+/// place, so the other names see the change and the permission still
+/// counts. Any other file is replaced by a finished copy with its mode and
+/// owner, so a crash mid-save leaves the old text or the new, not half.
+pub fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let Ok(meta) = fs::metadata(&target) else {
+        return fs::write(&target, bytes);
+    };
+    if meta.nlink() > 1 || meta.permissions().readonly() {
+        return fs::write(&target, bytes);
+    }
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = target.with_file_name(name);
+    let file = match create_copy(&temp, &meta) {
+        Ok(file) => file,
+        // An unwritable directory, or an owner only root could give the
+        // copy: write in place after all
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return fs::write(&target, bytes);
+        }
+        Err(e) => return Err(e),
+    };
+    // Past this point a failure (a full disk) leaves the original alone
+    let result = replace_with(file, &temp, &target, bytes);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Create the copy at `temp` with the original's owner and mode before
+/// anything is written to it, so text from a private file is never
+/// readable by others, even briefly. On failure nothing this made is
+/// left behind, and a file already at `temp` is never touched.
+fn create_copy(temp: &Path, meta: &fs::Metadata) -> io::Result<fs::File> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(meta.mode() & 0o777)
+        .open(temp)?;
+    let owned = std::os::unix::fs::fchown(&file, Some(meta.uid()), Some(meta.gid()))
+        .and_then(|()| file.set_permissions(meta.permissions()));
+    if let Err(e) = owned {
+        let _ = fs::remove_file(temp);
+        return Err(e);
+    }
+    Ok(file)
+}
+
+fn replace_with(mut file: fs::File, temp: &Path, target: &Path, bytes: &[u8]) -> io::Result<()> {
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(temp, target)
+}
+
+/// The read-only file `path` is a symlink to, if it is one. Saving
+/// through the link would write that file.
+pub fn read_only_target(path: &Path) -> Option<PathBuf> {
+    if !path.is_symlink() {
+        return None;
+    }
+    let target = fs::canonicalize(path).ok()?;
+    let read_only = fs::metadata(&target).ok()?.permissions().readonly();
+    read_only.then_some(target)
+}
+
 fn utf8_char_len(first_byte: u8) -> usize {
     if first_byte & 0x80 == 0 {
         1
@@ -305,6 +615,10 @@ fn utf8_char_len(first_byte: u8) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bench;
+    use std::time::Duration;
+
+    const MS: Duration = Duration::from_millis(1);
     use std::io::Write;
 
     #[test]
@@ -315,7 +629,7 @@ mod tests {
         assert_eq!(buf.line_count(), 1);
         assert!(!buf.is_modified());
         assert_eq!(buf.text(), "");
-        assert_eq!(buf.get_line(0), Some(String::new()));
+        assert_eq!(buf.get_line(0), Some("".into()));
         assert_eq!(buf.get_line(1), None);
     }
 
@@ -336,7 +650,7 @@ mod tests {
         assert_eq!(buf.line_count(), 3);
         assert_eq!(buf.get_line(0), Some("hello".into()));
         assert_eq!(buf.get_line(1), Some("world".into()));
-        assert_eq!(buf.get_line(2), Some(String::new()));
+        assert_eq!(buf.get_line(2), Some("".into()));
         assert_eq!(buf.text(), "hello\nworld\n");
     }
 
@@ -392,6 +706,11 @@ mod tests {
         assert_eq!(buf.get_line(1), Some("second".into()));
         assert_eq!(buf.get_line(2), Some("third".into()));
         assert_eq!(buf.get_line(3), None);
+        assert_eq!(buf.get_line_head(1, 3), Some("sec".into()));
+        assert_eq!(buf.get_line_head(0, 99), Some("first".into()));
+        // Cut within a character, the head keeps all of it
+        buf.insert(0, "é");
+        assert_eq!(buf.get_line_head(0, 1), Some("é".into()));
     }
 
     #[test]
@@ -437,16 +756,103 @@ mod tests {
         assert!(!buf.is_modified());
         assert_eq!(buf.file_path(), Some(path.as_path()));
 
-        // Save to different file
-        let path2 = dir.join("zelux_test_buffer2.txt");
-        let mut buf = buf;
-        buf.save_to(&path2).unwrap();
-        let buf2 = Buffer::from_file(&path2).unwrap();
-        assert_eq!(buf2.text(), content);
-
         // Cleanup
         let _ = fs::remove_file(&path);
-        let _ = fs::remove_file(&path2);
+    }
+
+    #[test]
+    fn test_save_keeps_links_and_mode() {
+        use std::os::unix::fs::{PermissionsExt, symlink};
+        let dir = std::env::temp_dir().join(format!("zelux_test_save_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (file, link, hard) = (dir.join("file"), dir.join("link"), dir.join("hard"));
+        fs::write(&file, "old\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&file, &link).unwrap();
+
+        // Through the link: the target is replaced, mode and all
+        let mut buf = Buffer::from_file(&link).unwrap();
+        buf.insert(0, "new ");
+        buf.save().unwrap();
+        assert!(link.is_symlink());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new old\n");
+        let meta = fs::metadata(&file).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+        assert_eq!(read_only_target(&link), None);
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            2,
+            "no temporary file left"
+        );
+
+        // A hard-linked file is written in place, so both names change
+        fs::hard_link(&file, &hard).unwrap();
+        let mut buf = Buffer::from_file(&file).unwrap();
+        buf.insert(0, "x");
+        buf.save().unwrap();
+        assert_eq!(fs::read_to_string(&hard).unwrap(), "xnew old\n");
+        assert_eq!(fs::metadata(&hard).unwrap().ino(), meta.ino());
+
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o444)).unwrap();
+        assert_eq!(
+            read_only_target(&link),
+            Some(fs::canonicalize(&file).unwrap())
+        );
+        assert_eq!(read_only_target(&file), None);
+
+        // Resolved, the buffer names the real file
+        let mut buf = Buffer::from_file(&link).unwrap();
+        buf.resolve_symlink();
+        assert_eq!(
+            buf.file_path(),
+            Some(fs::canonicalize(&file).unwrap().as_path())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_save_keeps_original() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("zelux_test_save_fail_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("private");
+        fs::write(&file, "old\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+        let meta = fs::metadata(&file).unwrap();
+
+        // The copy is private before it holds any text
+        let copy = create_copy(&dir.join("copy"), &meta).unwrap();
+        assert_eq!(copy.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(copy.metadata().unwrap().len(), 0);
+
+        // A copy that cannot be made is an error, not a write in place
+        let temp = format!(".private.{}.tmp", std::process::id());
+        fs::create_dir(dir.join(&temp)).unwrap();
+        assert!(write_file(&file, b"new\n").is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old\n");
+
+        // Someone else's file at the temporary name survives the failure
+        fs::remove_dir(dir.join(&temp)).unwrap();
+        fs::write(dir.join(&temp), "theirs\n").unwrap();
+        assert!(write_file(&file, b"new\n").is_err());
+        assert_eq!(fs::read_to_string(dir.join(&temp)).unwrap(), "theirs\n");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_filetype_override() {
+        let mut buf = Buffer::new();
+        assert_eq!(buf.filetype(), "text");
+        buf.file_path = Some(PathBuf::from("script.PY"));
+        assert_eq!(buf.filetype(), "py");
+        buf.set_filetype(Some("sh".to_string()));
+        assert_eq!(buf.filetype(), "sh");
+        buf.set_filetype(None);
+        assert_eq!(buf.filetype(), "py");
+        buf.file_path = Some(PathBuf::from("script"));
+        buf.insert(0, "#!/bin/sh\n");
+        assert_eq!(buf.filetype(), "sh");
     }
 
     #[test]
@@ -461,6 +867,68 @@ mod tests {
         assert!(buf.is_modified());
     }
 
+    #[test]
+    fn test_changed_lines() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "a\nb\nc\nd");
+        buf.mark_saved();
+        buf.insert(buf.line_start(1).unwrap(), "x\ny");
+        // "a", "x", "yb", "c", "d"
+        let changed: Vec<bool> = (0..5).map(|l| buf.is_line_changed(l)).collect();
+        assert_eq!(changed, [false, true, true, false, false]);
+        // Joining "c" and "d" marks the joined line only
+        let c_end = buf.line_end(3).unwrap();
+        buf.delete(c_end, 1);
+        let changed: Vec<bool> = (0..4).map(|l| buf.is_line_changed(l)).collect();
+        assert_eq!(changed, [false, true, true, true]);
+        buf.mark_saved();
+        assert!(!buf.is_line_changed(1));
+    }
+
+    #[test]
+    fn test_version_counts_edits() {
+        let mut buf = Buffer::new();
+        assert_eq!(buf.version(), 0);
+        buf.insert(0, "ab");
+        buf.mark_saved();
+        buf.delete(0, 1);
+        assert_eq!(buf.version(), 2);
+        // A no-op delete is not an edit
+        buf.delete(5, 1);
+        assert_eq!(buf.version(), 2);
+    }
+
+    #[test]
+    fn test_edits_since() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "héllo");
+        buf.delete(1, 1); // widens to the whole `é`
+        assert_eq!(
+            buf.edits_since(0),
+            Some(
+                &[
+                    Edit {
+                        pos: 0,
+                        deleted: 0,
+                        inserted: 6
+                    },
+                    Edit {
+                        pos: 1,
+                        deleted: 2,
+                        inserted: 0
+                    }
+                ][..]
+            )
+        );
+        assert_eq!(buf.edits_since(2), Some(&[][..]));
+        assert_eq!(buf.edits_since(3), None);
+        for _ in 0..MAX_EDITS {
+            buf.insert(0, "x");
+        }
+        assert_eq!(buf.edits_since(1), None);
+        assert_eq!(buf.edits_since(2).map(|e| e.len()), Some(MAX_EDITS));
+    }
+
     #[test]
     fn test_large_insert() {
         let mut buf = Buffer::new();
@@ -486,6 +954,168 @@ mod tests {
         assert_eq!(buf.slice(0, 100), "hello world"); // clamped
     }
 
+    #[test]
+    fn test_bytes_across_gap() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "hello world");
+        buf.insert(5, ",");
+        let bytes: Vec<u8> = buf.bytes(3, 9).collect();
+        assert_eq!(bytes, b"lo, wo");
+        assert_eq!(buf.bytes(10, 99).count(), 2);
+        assert_eq!(buf.bytes(7, 2).count(), 0);
+    }
+
+    #[test]
+    fn test_borrowed_access() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "one\ntwo\nthree");
+        buf.insert(5, "X");
+        // Lines away from the gap are borrowed; the one it splits is copied
+        assert!(matches!(buf.get_line(0), Some(Cow::Borrowed("one"))));
+        assert!(matches!(buf.get_line(1), Some(Cow::Owned(ref l)) if l == "tXwo"));
+        assert!(matches!(buf.contents(), Cow::Owned(_)));
+        buf.make_contiguous();
+        assert!(matches!(buf.contents(), Cow::Borrowed("one\ntXwo\nthree")));
+        assert!(matches!(buf.get_line(1), Some(Cow::Borrowed("tXwo"))));
+    }
+
+    /// Check `buf` against the `model` text it should hold.
+    fn assert_matches_model(buf: &mut Buffer, model: &str) {
+        assert_eq!(buf.len(), model.len());
+        assert_eq!(buf.text(), model);
+        let lines: Vec<&str> = model.split('\n').collect();
+        assert_eq!(buf.line_count(), lines.len());
+        assert_eq!(buf.changed.len(), lines.len());
+        let mut start = 0;
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(buf.line_start(i), Some(start));
+            assert_eq!(buf.line_end(i), Some(start + line.len()));
+            assert_eq!(buf.get_line(i).as_deref(), Some(*line));
+            assert_eq!(buf.byte_to_line(start + line.len()), i);
+            start += line.len() + 1;
+        }
+        for (pos, ch) in model.char_indices() {
+            assert_eq!(buf.char_at(pos), Some(ch));
+        }
+        // Edits at character boundaries keep the text valid UTF-8, so it
+        // borrows without a lossy copy
+        buf.make_contiguous();
+        assert!(matches!(buf.contents(), Cow::Borrowed(text) if text == model));
+    }
+
+    #[test]
+    fn fuzz_edits() {
+        // A random character boundary, as the editor edits at
+        fn boundary(rng: &mut bench::Rng, model: &str) -> usize {
+            let mut pos = rng.below(model.len() + 1);
+            while !model.is_char_boundary(pos) {
+                pos -= 1;
+            }
+            pos
+        }
+        crate::fuzz::run(|rng| {
+            let mut buf = Buffer::new();
+            let mut model = String::new();
+            for _ in 0..rng.below(20) {
+                let pos = boundary(rng, &model);
+                if rng.below(3) == 0 {
+                    let end = boundary(rng, &model).max(pos);
+                    assert_eq!(buf.delete(pos, end - pos), model[pos..end]);
+                    model.replace_range(pos..end, "");
+                } else {
+                    // Long inserts now and then, to outgrow the gap
+                    let max = if rng.below(8) == 0 { 2000 } else { 8 };
+                    let text = crate::fuzz::text(rng, max);
+                    buf.insert(pos, &text);
+                    model.insert_str(pos, &text);
+                }
+                assert_matches_model(&mut buf, &model);
+            }
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_borrowed_access() {
+        let mut buf = Buffer::new();
+        buf.insert(0, &bench::large_text(100_000));
+        buf.insert(buf.len() / 2, "x");
+
+        // One frame's worth of visible lines, copied and borrowed
+        let lines = || 50_000..50_050;
+        bench::measure("buffer: 50 lines, copied", 100, || {
+            lines()
+                .map(|l| {
+                    let (start, end) = (buf.line_start(l).unwrap(), buf.line_end(l).unwrap());
+                    String::from_utf8_lossy(&buf.bytes(start, end).collect::<Vec<u8>>())
+                        .into_owned()
+                })
+                .count()
+        });
+        bench::within("buffer: 50 lines, borrowed", 100, MS, || {
+            lines().filter_map(|l| buf.get_line(l)).count()
+        });
+
+        // The text a search scans
+        bench::measure("buffer: whole text, copied", 20, || buf.text());
+        buf.make_contiguous();
+        bench::within("buffer: whole text, borrowed", 20, 20 * MS, || {
+            buf.contents().len()
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_edit_at_random_positions() {
+        let mut buf = Buffer::new();
+        buf.insert(0, &bench::large_text(100_000));
+        let mut rng = bench::Rng::new(7);
+        bench::within("buffer: insert at random position", 200, 20 * MS, || {
+            let pos = rng.below(buf.len());
+            buf.insert(pos, "x\n");
+        });
+        bench::within("buffer: delete at random position", 200, 20 * MS, || {
+            let pos = rng.below(buf.len() - 2);
+            buf.delete(pos, 2)
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_rebuild_lines() {
+        let mut buf = Buffer::new();
+        buf.insert(0, &bench::large_text(100_000));
+        bench::within("buffer: rebuild line index", 50, 20 * MS, || {
+            buf.rebuild_lines()
+        });
+    }
+
+    #[test]
+    fn test_edits_snap_to_char_boundaries() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "a😀日本b");
+        // 😀 is bytes 1..5, 日 5..8, 本 8..11
+        buf.insert(3, "x");
+        assert_eq!(buf.text(), "ax😀日本b");
+        assert_eq!(buf.delete(7, 2), "日");
+        assert_eq!(buf.text(), "ax😀本b");
+        // A delete from inside one character into another takes both
+        assert_eq!(buf.delete(4, 4), "😀本");
+        assert_eq!(buf.text(), "axb");
+        assert!(buf.is_char_boundary(1) && buf.is_char_boundary(3));
+    }
+
+    #[test]
+    fn test_slice_snaps_to_char_boundaries() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "日本語 😀!");
+        assert_eq!(buf.slice(1, 4), "日本");
+        assert_eq!(buf.slice(10, 12), "😀");
+        assert_eq!(buf.floor_char_boundary(2), 0);
+        assert_eq!(buf.ceil_char_boundary(2), 3);
+        assert_eq!(buf.ceil_char_boundary(99), buf.len());
+    }
+
     #[test]
     fn test_slice_utf8() {
         let mut buf = Buffer::new();
@@ -495,6 +1125,15 @@ mod tests {
         assert_eq!(buf.slice(6, 8), "ñ");
     }
 
+    #[test]
+    fn test_scratch_buffer() {
+        let buf = Buffer::scratch("[Output]", "line 1\nline 2\n");
+        assert_eq!(buf.name(), Some("[Output]"));
+        assert_eq!(buf.file_path(), None);
+        assert_eq!(buf.line_count(), 3);
+        assert!(!buf.is_modified());
+    }
+
     #[test]
     fn test_sequential_inserts() {
         let mut buf = Buffer::new();
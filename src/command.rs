@@ -0,0 +1,667 @@
+use crate::input::{Key, KeyEvent};
+
+// ---------------------------------------------------------------------------
+// Command — every editor action that can be bound to a key
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    // Movement
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    CursorWordLeft,
+    CursorWordRight,
+    CursorWordEnd,
+    CursorHome,
+    CursorLineStart,
+    CursorEnd,
+    CursorFileStart,
+    CursorFileEnd,
+    PageUp,
+    PageDown,
+    RecenterTopBottom,
+    ScrollCursorCenter,
+    ScrollCursorTop,
+    ScrollCursorBottom,
+
+    // Editing
+    InsertNewline,
+    InsertTab,
+    PrevTabStop,
+    DeleteBackward,
+    DeleteForward,
+    DeleteWordBackward,
+    DeleteWordForward,
+    DeleteToLineStart,
+    DeleteToLineEnd,
+    Copy,
+    Cut,
+    Paste,
+    PasteColumn,
+    SelectAll,
+    SelectHome,
+    SelectEnd,
+    SelectToFileStart,
+    SelectToFileEnd,
+    Complete,
+    SurroundSelection,
+    DeleteSurround,
+    ChangeSurround,
+    Undo,
+    Redo,
+
+    // Line operations
+    MoveLineUp,
+    MoveLineDown,
+    DuplicateLine,
+    JoinLines,
+    DeleteLine,
+    ToggleComment,
+
+    // Text transforms
+    UpperCase,
+    LowerCase,
+    TitleCase,
+    SwapCase,
+    TransposeChars,
+    TransposeWords,
+    SortLines,
+    SortLinesDescending,
+    SortLinesNumeric,
+    SortLinesIgnoreCase,
+    UniqueLines,
+    UniqueLinesAdjacent,
+    IncrementNumber,
+    DecrementNumber,
+    ReflowParagraph,
+    FormatJson,
+    MinifyJson,
+    InsertChar,
+    Digraph,
+    DescribeChar,
+
+    // Files & buffers
+    Save,
+    Quit,
+    OpenFile,
+    OpenUnderCursor,
+    InsertFile,
+    WriteSelection,
+    Export,
+    CloseBuffer,
+    DeleteFile,
+    UndoDeleteFile,
+    PrevBuffer,
+    NextBuffer,
+    ToggleFollow,
+    ToggleColumnView,
+    SetFiletype,
+    BufferOptions,
+
+    // Search
+    Find,
+    Replace,
+    ReplaceInBuffers,
+    UndoReplaceInBuffers,
+    RenameInProject,
+    FindNext,
+    FindPrev,
+    JumpToSymbol,
+    FindFile,
+
+    // Tools
+    RunShell,
+    Build,
+    NextError,
+    PrevError,
+    HidePanel,
+    ShowMessages,
+    DocumentStats,
+    ProfileReport,
+    ShowHelp,
+    RunCommandByName,
+
+    // Language server
+    ShowDiagnostics,
+    DiagnosticsPanel,
+    NextDiagnostic,
+    PrevDiagnostic,
+    GotoDefinition,
+    Hover,
+
+    // Spelling
+    ToggleSpellCheck,
+    NextMisspelling,
+    PrevMisspelling,
+    SpellSuggest,
+    AddWordToDictionary,
+    AddWordToProject,
+
+    // Jump list
+    JumpBack,
+    JumpForward,
+    JumpBackInBuffer,
+    JumpForwardInBuffer,
+
+    // Git
+    ToggleBlame,
+    CopyBlameHash,
+    NextHunk,
+    PrevHunk,
+    GotoPatchSource,
+    StageHunk,
+    RevertHunk,
+
+    // User commands: a script from the config, by index
+    Script(usize),
+}
+
+pub struct CommandInfo {
+    pub command: Command,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// The command registry: stable names and one-line descriptions.
+#[rustfmt::skip]
+pub const COMMANDS: &[CommandInfo] = &[
+    info(Command::CursorUp, "cursor-up", "Move up one line"),
+    info(Command::CursorDown, "cursor-down", "Move down one line"),
+    info(Command::CursorLeft, "cursor-left", "Move left one character"),
+    info(Command::CursorRight, "cursor-right", "Move right one character"),
+    info(Command::CursorWordLeft, "cursor-word-left", "Move to the previous word start"),
+    info(Command::CursorWordRight, "cursor-word-right", "Move to the next word start"),
+    info(Command::CursorWordEnd, "cursor-word-end", "Move to the end of the word"),
+    info(Command::CursorHome, "cursor-home", "Move to the first non-blank, then column 1"),
+    info(Command::CursorLineStart, "cursor-line-start", "Move to column 1"),
+    info(Command::CursorEnd, "cursor-end", "Move to the end of the line"),
+    info(Command::CursorFileStart, "cursor-file-start", "Move to the start of the file"),
+    info(Command::CursorFileEnd, "cursor-file-end", "Move to the end of the file"),
+    info(Command::PageUp, "page-up", "Scroll up one screen"),
+    info(Command::PageDown, "page-down", "Scroll down one screen"),
+    info(Command::RecenterTopBottom, "recenter-top-bottom", "Put the cursor line at the center, then top, then bottom"),
+    info(Command::ScrollCursorCenter, "scroll-cursor-center", "Scroll so the cursor line is centered"),
+    info(Command::ScrollCursorTop, "scroll-cursor-top", "Scroll so the cursor line is at the top"),
+    info(Command::ScrollCursorBottom, "scroll-cursor-bottom", "Scroll so the cursor line is at the bottom"),
+    info(Command::InsertNewline, "insert-newline", "Insert a line break"),
+    info(Command::InsertTab, "insert-tab", "Expand a snippet, jump to the next tab stop, or indent"),
+    info(Command::PrevTabStop, "prev-tab-stop", "Jump to the previous snippet tab stop"),
+    info(Command::DeleteBackward, "delete-backward", "Delete the character before the cursor"),
+    info(Command::DeleteForward, "delete-forward", "Delete the character under the cursor"),
+    info(Command::DeleteWordBackward, "delete-word-backward", "Delete to the previous word start"),
+    info(Command::DeleteWordForward, "delete-word-forward", "Delete to the next word start"),
+    info(Command::DeleteToLineStart, "delete-to-line-start", "Delete from column 1 to the cursor"),
+    info(Command::DeleteToLineEnd, "delete-to-line-end", "Delete to the end of the line (or the line break)"),
+    info(Command::Copy, "copy", "Copy the selection (or line)"),
+    info(Command::Cut, "cut", "Cut the selection (or line)"),
+    info(Command::Paste, "paste", "Paste the clipboard"),
+    info(Command::PasteColumn, "paste-column", "Paste the clipboard's lines one below the other at the cursor's column"),
+    info(Command::SelectAll, "select-all", "Select the whole buffer"),
+    info(Command::SelectHome, "select-home", "Select to the first non-blank, then column 1"),
+    info(Command::SelectEnd, "select-end", "Select to the end of the line"),
+    info(Command::SelectToFileStart, "select-to-file-start", "Select to the start of the file"),
+    info(Command::SelectToFileEnd, "select-to-file-end", "Select to the end of the file"),
+    info(Command::Complete, "complete", "Complete the word at the cursor from open buffers"),
+    info(Command::SurroundSelection, "surround-selection", "Wrap the selection in a pair (asks for the pair)"),
+    info(Command::DeleteSurround, "delete-surround", "Delete the pair around the cursor (asks which)"),
+    info(Command::ChangeSurround, "change-surround", "Replace the pair around the cursor (asks old, then new)"),
+    info(Command::Undo, "undo", "Undo the last change"),
+    info(Command::Redo, "redo", "Redo the last undone change"),
+    info(Command::MoveLineUp, "move-line-up", "Move the line (or selected lines) up"),
+    info(Command::MoveLineDown, "move-line-down", "Move the line (or selected lines) down"),
+    info(Command::DuplicateLine, "duplicate-line", "Duplicate the line (or selected lines) below"),
+    info(Command::JoinLines, "join-lines", "Join the next line (or selected lines), collapsing whitespace"),
+    info(Command::DeleteLine, "delete-line", "Delete the line (or selected lines)"),
+    info(Command::ToggleComment, "toggle-comment", "Comment or uncomment the line (or selected lines)"),
+    info(Command::UpperCase, "upper-case", "Upper-case the selection (or word)"),
+    info(Command::LowerCase, "lower-case", "Lower-case the selection (or word)"),
+    info(Command::TitleCase, "title-case", "Title-case the selection (or word)"),
+    info(Command::SwapCase, "swap-case", "Swap the case of the selection (or word)"),
+    info(Command::TransposeChars, "transpose-chars", "Swap the characters around the cursor"),
+    info(Command::TransposeWords, "transpose-words", "Swap the word at the cursor with the next"),
+    info(Command::SortLines, "sort-lines", "Sort the selected lines (or the buffer)"),
+    info(Command::SortLinesDescending, "sort-lines-descending", "Sort the selected lines in reverse"),
+    info(Command::SortLinesNumeric, "sort-lines-numeric", "Sort the selected lines by their leading number"),
+    info(Command::SortLinesIgnoreCase, "sort-lines-ignore-case", "Sort the selected lines ignoring case"),
+    info(Command::UniqueLines, "unique-lines", "Remove repeated lines from the selection (or buffer)"),
+    info(Command::UniqueLinesAdjacent, "unique-lines-adjacent", "Remove lines equal to the line above"),
+    info(Command::IncrementNumber, "increment-number", "Add one to the number at or after the cursor"),
+    info(Command::DecrementNumber, "decrement-number", "Subtract one from the number at or after the cursor"),
+    info(Command::InsertChar, "insert-char", "Insert a character by code point (U+...) or name"),
+    info(Command::Digraph, "digraph", "Insert a character by typing its two-character digraph (a' for á)"),
+    info(Command::DescribeChar, "describe-char", "Show the code point, bytes and width of the character at the cursor"),
+    info(Command::ReflowParagraph, "reflow-paragraph", "Rewrap the paragraph (or selection) to the text width"),
+    info(Command::FormatJson, "format-json", "Pretty-print the JSON in the selection (or buffer)"),
+    info(Command::MinifyJson, "minify-json", "Remove the whitespace from the JSON in the selection (or buffer)"),
+    info(Command::Save, "save", "Save the buffer"),
+    info(Command::Quit, "quit", "Quit the editor"),
+    info(Command::OpenFile, "open-file", "Open a file"),
+    info(Command::OpenUnderCursor, "open-under-cursor", "Open the file (path:line works too) or URL under the cursor"),
+    info(Command::InsertFile, "insert-file", "Insert a file's contents at the cursor"),
+    info(Command::WriteSelection, "write-selection", "Write the selection to a file"),
+    info(Command::Export, "export", "Write the buffer with syntax colors to an HTML page (.html) or ANSI text"),
+    info(Command::CloseBuffer, "close-buffer", "Close the current buffer"),
+    info(Command::DeleteFile, "delete-file", "Move the buffer's file to the trash"),
+    info(Command::UndoDeleteFile, "undo-delete-file", "Bring back the file last moved to the trash"),
+    info(Command::PrevBuffer, "prev-buffer", "Switch to the previous buffer"),
+    info(Command::NextBuffer, "next-buffer", "Switch to the next buffer"),
+    info(Command::ToggleFollow, "toggle-follow", "Follow the file as it grows, like tail -f"),
+    info(Command::ToggleColumnView, "toggle-column-view", "Show a CSV or TSV file as aligned columns"),
+    info(Command::SetFiletype, "set-filetype", "Change the filetype of the current buffer (\"auto\" to detect it again)"),
+    info(Command::BufferOptions, "buffer-options", "Show and change the buffer's widths, filetype, line ending and read-only flag"),
+    info(Command::Find, "find", "Search in the buffer"),
+    info(Command::Replace, "replace", "Search and replace"),
+    info(Command::ReplaceInBuffers, "replace-in-buffers", "Search and replace in all open buffers, or the ones named"),
+    info(Command::UndoReplaceInBuffers, "undo-replace-in-buffers", "Undo the last replace-in-buffers in every buffer it changed"),
+    info(Command::RenameInProject, "rename-in-project", "Rename text in every project file, previewing each changed line to accept or reject"),
+    info(Command::FindNext, "find-next", "Jump to the next match"),
+    info(Command::FindPrev, "find-prev", "Jump to the previous match"),
+    info(Command::JumpToSymbol, "jump-to-symbol", "Pick a symbol from the outline or tags file"),
+    info(Command::FindFile, "find-file", "Pick a project file by fuzzy name"),
+    info(Command::RunShell, "run-shell", "Run a shell command"),
+    info(Command::Build, "build", "Run the build command"),
+    info(Command::NextError, "next-error", "Jump to the next build error"),
+    info(Command::PrevError, "prev-error", "Jump to the previous build error"),
+    info(Command::HidePanel, "hide-panel", "Hide the bottom panel"),
+    info(Command::ShowMessages, "show-messages", "List earlier messages in a buffer"),
+    info(Command::DocumentStats, "document-stats", "Count the lines, words, characters and bytes of the buffer and selection"),
+    info(Command::ProfileReport, "profile-report", "Show the timings recorded with --profile"),
+    info(Command::RunCommandByName, "execute-command", "Run a command by name (or go to a line number)"),
+    info(Command::ShowHelp, "help", "List every command with its key bindings"),
+    info(Command::ShowDiagnostics, "show-diagnostics", "Show the diagnostics on the cursor line"),
+    info(Command::DiagnosticsPanel, "diagnostics-panel", "List the diagnostics of every file in a panel"),
+    info(Command::NextDiagnostic, "next-diagnostic", "Jump to the next diagnostic, in any file"),
+    info(Command::PrevDiagnostic, "prev-diagnostic", "Jump to the previous diagnostic, in any file"),
+    info(Command::GotoDefinition, "goto-definition", "Jump to the definition (language server, else tags)"),
+    info(Command::Hover, "hover", "Show documentation for the symbol"),
+    info(Command::ToggleSpellCheck, "toggle-spell-check", "Turn spell checking on or off"),
+    info(Command::NextMisspelling, "next-misspelling", "Jump to the next misspelled word"),
+    info(Command::PrevMisspelling, "prev-misspelling", "Jump to the previous misspelled word"),
+    info(Command::SpellSuggest, "spell-suggest", "Suggest corrections for the misspelled word"),
+    info(Command::AddWordToDictionary, "add-word-to-dictionary", "Add the word under the cursor to your dictionary"),
+    info(Command::AddWordToProject, "add-word-to-project", "Add the word under the cursor to the project's word list"),
+    info(Command::JumpBack, "jump-back", "Go back to the previous jump position"),
+    info(Command::JumpForward, "jump-forward", "Go forward again in the jump list"),
+    info(Command::JumpBackInBuffer, "jump-back-in-buffer", "Go back to the previous jump position in this buffer"),
+    info(Command::JumpForwardInBuffer, "jump-forward-in-buffer", "Go forward in the jump list within this buffer"),
+    info(Command::ToggleBlame, "toggle-blame", "Show or hide the git blame column"),
+    info(Command::CopyBlameHash, "copy-blame-hash", "Copy the commit hash of the line"),
+    info(Command::NextHunk, "next-hunk", "Jump to the next git change, or hunk in a patch"),
+    info(Command::PrevHunk, "prev-hunk", "Jump to the previous git change, or hunk in a patch"),
+    info(Command::GotoPatchSource, "goto-patch-source", "Open the file and line the patch hunk under the cursor changes"),
+    info(Command::StageHunk, "stage-hunk", "Stage the git change under the cursor"),
+    info(Command::RevertHunk, "revert-hunk", "Revert the git change under the cursor"),
+];
+
+const fn info(command: Command, name: &'static str, description: &'static str) -> CommandInfo {
+    CommandInfo {
+        command,
+        name,
+        description,
+    }
+}
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS.iter().find(|c| c.name == name).map(|c| c.command)
+    }
+
+    /// The registry name; "script" for user scripts.
+    pub fn name(self) -> &'static str {
+        COMMANDS
+            .iter()
+            .find(|c| c.command == self)
+            .map_or("script", |c| c.name)
+    }
+
+    /// Commands that change the buffer, refused in read-only buffers.
+    pub fn is_edit(self) -> bool {
+        matches!(
+            self,
+            Command::InsertNewline
+                | Command::InsertTab
+                | Command::DeleteBackward
+                | Command::DeleteForward
+                | Command::DeleteWordBackward
+                | Command::DeleteWordForward
+                | Command::DeleteToLineStart
+                | Command::DeleteToLineEnd
+                | Command::Cut
+                | Command::Paste
+                | Command::PasteColumn
+                | Command::Complete
+                | Command::SurroundSelection
+                | Command::DeleteSurround
+                | Command::ChangeSurround
+                | Command::Undo
+                | Command::Redo
+                | Command::MoveLineUp
+                | Command::MoveLineDown
+                | Command::DuplicateLine
+                | Command::JoinLines
+                | Command::DeleteLine
+                | Command::ToggleComment
+                | Command::UpperCase
+                | Command::LowerCase
+                | Command::TitleCase
+                | Command::SwapCase
+                | Command::TransposeChars
+                | Command::TransposeWords
+                | Command::SortLines
+                | Command::SortLinesDescending
+                | Command::SortLinesNumeric
+                | Command::SortLinesIgnoreCase
+                | Command::UniqueLines
+                | Command::UniqueLinesAdjacent
+                | Command::IncrementNumber
+                | Command::DecrementNumber
+                | Command::ReflowParagraph
+                | Command::FormatJson
+                | Command::MinifyJson
+                | Command::InsertChar
+                | Command::Digraph
+                | Command::InsertFile
+                | Command::Replace
+                | Command::SpellSuggest
+                | Command::RevertHunk
+        )
+    }
+}
+
+/// The help screen: every registered command, then the user's `scripts`
+/// by name, with its bindings and description, one per line.
+pub fn help_text(keymap: &Keymap, scripts: &[&str]) -> String {
+    let keys = |cmd| {
+        let keys: Vec<String> = keymap.keys_for(cmd).map(|k| k.to_string()).collect();
+        keys.join(", ")
+    };
+    let mut rows: Vec<(&str, String, &str)> = COMMANDS
+        .iter()
+        .map(|c| (c.name, keys(c.command), c.description))
+        .collect();
+    for (i, name) in scripts.iter().enumerate() {
+        rows.push((
+            name,
+            keys(Command::Script(i)),
+            "User script from config.json",
+        ));
+    }
+    let name_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let keys_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+    let mut text = String::from("zelux commands (search with Ctrl+F, close with Ctrl+W)\n\n");
+    text.push_str(&format!(
+        "{:name_width$}  {:keys_width$}  {}\n",
+        "Command", "Keys", "Description"
+    ));
+    for (name, keys, description) in rows {
+        text.push_str(&format!(
+            "{:name_width$}  {:keys_width$}  {}\n",
+            name, keys, description
+        ));
+    }
+    text
+}
+
+// ---------------------------------------------------------------------------
+// Keymap — key bindings for commands
+// ---------------------------------------------------------------------------
+
+pub struct Keymap {
+    bindings: Vec<(KeyEvent, Command)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let plain = |k| key(k, false, false, false);
+        let ctrl = |c| key(Key::Char(c), true, false, false);
+        let alt = |c| key(Key::Char(c), false, true, false);
+        Keymap {
+            bindings: vec![
+                (plain(Key::Up), Command::CursorUp),
+                (plain(Key::Down), Command::CursorDown),
+                (plain(Key::Left), Command::CursorLeft),
+                (plain(Key::Right), Command::CursorRight),
+                (key(Key::Left, true, false, false), Command::CursorWordLeft),
+                (
+                    key(Key::Right, true, false, false),
+                    Command::CursorWordRight,
+                ),
+                (plain(Key::Home), Command::CursorHome),
+                (plain(Key::End), Command::CursorEnd),
+                (key(Key::Home, true, false, false), Command::CursorFileStart),
+                (key(Key::End, true, false, false), Command::CursorFileEnd),
+                (plain(Key::PageUp), Command::PageUp),
+                (plain(Key::PageDown), Command::PageDown),
+                (key(Key::PageUp, true, false, false), Command::PrevBuffer),
+                (key(Key::PageDown, true, false, false), Command::NextBuffer),
+                (plain(Key::Enter), Command::InsertNewline),
+                (plain(Key::Tab), Command::InsertTab),
+                (key(Key::Tab, false, false, true), Command::PrevTabStop),
+                (plain(Key::Backspace), Command::DeleteBackward),
+                (plain(Key::Delete), Command::DeleteForward),
+                (
+                    key(Key::Backspace, true, false, false),
+                    Command::DeleteWordBackward,
+                ),
+                (
+                    key(Key::Backspace, false, true, false),
+                    Command::DeleteWordBackward,
+                ),
+                (
+                    key(Key::Delete, true, false, false),
+                    Command::DeleteWordForward,
+                ),
+                (ctrl('u'), Command::DeleteToLineStart),
+                (ctrl('k'), Command::DeleteToLineEnd),
+                (ctrl('c'), Command::Copy),
+                (ctrl('x'), Command::Cut),
+                (ctrl('v'), Command::Paste),
+                (alt('y'), Command::PasteColumn),
+                (ctrl('a'), Command::SelectAll),
+                (key(Key::Home, false, false, true), Command::SelectHome),
+                (key(Key::End, false, false, true), Command::SelectEnd),
+                (
+                    key(Key::Home, true, false, true),
+                    Command::SelectToFileStart,
+                ),
+                (key(Key::End, true, false, true), Command::SelectToFileEnd),
+                (ctrl('l'), Command::RecenterTopBottom),
+                (ctrl(' '), Command::Complete),
+                (alt('('), Command::SurroundSelection),
+                (alt(')'), Command::DeleteSurround),
+                (alt('%'), Command::ChangeSurround),
+                (ctrl('z'), Command::Undo),
+                (ctrl('y'), Command::Redo),
+                (key(Key::Up, false, true, false), Command::MoveLineUp),
+                (key(Key::Down, false, true, false), Command::MoveLineDown),
+                (
+                    key(Key::Char('d'), true, false, true),
+                    Command::DuplicateLine,
+                ),
+                (ctrl('j'), Command::JoinLines),
+                (key(Key::Char('k'), true, false, true), Command::DeleteLine),
+                (ctrl('/'), Command::ToggleComment),
+                (alt('u'), Command::UpperCase),
+                (alt('l'), Command::LowerCase),
+                (alt('t'), Command::TransposeWords),
+                (alt('+'), Command::IncrementNumber),
+                (alt('-'), Command::DecrementNumber),
+                (alt('q'), Command::ReflowParagraph),
+                (key(Key::Char('u'), true, false, true), Command::InsertChar),
+                (ctrl('d'), Command::Digraph),
+                (ctrl('s'), Command::Save),
+                (ctrl('q'), Command::Quit),
+                (ctrl('o'), Command::OpenFile),
+                (alt('o'), Command::OpenUnderCursor),
+                (ctrl('w'), Command::CloseBuffer),
+                (alt('f'), Command::ToggleFollow),
+                (alt('v'), Command::ToggleColumnView),
+                (alt(','), Command::BufferOptions),
+                (ctrl('f'), Command::Find),
+                (ctrl('h'), Command::Replace),
+                (plain(Key::F(3)), Command::FindNext),
+                (key(Key::F(3), false, false, true), Command::FindPrev),
+                (ctrl('t'), Command::JumpToSymbol),
+                (ctrl('p'), Command::FindFile),
+                (ctrl('e'), Command::RunShell),
+                (ctrl('b'), Command::Build),
+                (plain(Key::F(8)), Command::NextError),
+                (key(Key::F(8), false, false, true), Command::PrevError),
+                (plain(Key::Escape), Command::HidePanel),
+                (plain(Key::F(1)), Command::ShowHelp),
+                (alt('x'), Command::RunCommandByName),
+                (alt('m'), Command::ShowMessages),
+                (alt('d'), Command::ShowDiagnostics),
+                (alt('D'), Command::DiagnosticsPanel),
+                (key(Key::F(8), true, false, false), Command::NextDiagnostic),
+                (key(Key::F(8), true, false, true), Command::PrevDiagnostic),
+                (plain(Key::F(12)), Command::GotoDefinition),
+                (alt('k'), Command::Hover),
+                (alt('z'), Command::ToggleSpellCheck),
+                (plain(Key::F(7)), Command::NextMisspelling),
+                (key(Key::F(7), false, false, true), Command::PrevMisspelling),
+                (alt('='), Command::SpellSuggest),
+                (key(Key::Left, false, true, false), Command::JumpBack),
+                (key(Key::Right, false, true, false), Command::JumpForward),
+                (key(Key::Left, false, true, true), Command::JumpBackInBuffer),
+                (
+                    key(Key::Right, false, true, true),
+                    Command::JumpForwardInBuffer,
+                ),
+                (alt('b'), Command::ToggleBlame),
+                (alt('c'), Command::CopyBlameHash),
+                (alt('n'), Command::NextHunk),
+                (alt('p'), Command::PrevHunk),
+                (alt('s'), Command::StageHunk),
+                (alt('r'), Command::RevertHunk),
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    /// Keys bound to `cmd`, in binding order.
+    pub fn keys_for(&self, cmd: Command) -> impl Iterator<Item = &KeyEvent> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == cmd)
+            .map(|(ke, _)| ke)
+    }
+
+    /// Find the command bound to `ke`. A Shift-modified key without its own
+    /// binding falls back to the unshifted one (Shift+arrows select).
+    pub fn lookup(&self, ke: &KeyEvent) -> Option<Command> {
+        let find = |ke: &KeyEvent| {
+            self.bindings
+                .iter()
+                .find(|(bound, _)| bound == ke)
+                .map(|(_, cmd)| *cmd)
+        };
+        find(ke).or_else(|| {
+            if ke.shift {
+                find(&key(ke.key.clone(), ke.ctrl, ke.alt, false))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Bind a key named like `Ctrl+A` or `Alt+Shift+Left` to the command
+    /// named `command`, replacing what the key did before.
+    pub fn bind(&mut self, key_name: &str, command: &str) -> Result<(), String> {
+        let cmd =
+            Command::from_name(command).ok_or_else(|| format!("Unknown command: {}", command))?;
+        self.bind_command(key_name, cmd)
+    }
+
+    /// Bind a key named like `Ctrl+A` to `cmd`, such as a user script.
+    pub fn bind_command(&mut self, key_name: &str, cmd: Command) -> Result<(), String> {
+        let ke: KeyEvent = key_name.parse()?;
+        self.bindings.retain(|(bound, _)| *bound != ke);
+        self.bindings.push((ke, cmd));
+        Ok(())
+    }
+}
+
+fn key(key: Key, ctrl: bool, alt: bool, shift: bool) -> KeyEvent {
+    KeyEvent {
+        key,
+        ctrl,
+        alt,
+        shift,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_names_unique() {
+        for (i, a) in COMMANDS.iter().enumerate() {
+            for b in &COMMANDS[i + 1..] {
+                assert_ne!(a.name, b.name);
+                assert_ne!(a.command, b.command);
+            }
+        }
+        assert_eq!(Command::from_name("next-hunk"), Some(Command::NextHunk));
+        assert_eq!(Command::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_keymap_lookup() {
+        let keymap = Keymap::default();
+        let ctrl_s = key(Key::Char('s'), true, false, false);
+        assert_eq!(keymap.lookup(&ctrl_s), Some(Command::Save));
+        let unbound = key(Key::Char('s'), false, false, false);
+        assert_eq!(keymap.lookup(&unbound), None);
+    }
+
+    #[test]
+    fn test_keymap_bind() {
+        let mut keymap = Keymap::default();
+        keymap.bind("Ctrl+A", "increment-number").unwrap();
+        let ctrl_a = key(Key::Char('a'), true, false, false);
+        assert_eq!(keymap.lookup(&ctrl_a), Some(Command::IncrementNumber));
+        assert_eq!(keymap.keys_for(Command::SelectAll).count(), 0);
+        assert!(keymap.bind("Ctrl+A", "bogus").is_err());
+        assert!(keymap.bind("Meta+A", "save").is_err());
+    }
+
+    #[test]
+    fn test_keymap_shift_fallback() {
+        let keymap = Keymap::default();
+        let shift_up = key(Key::Up, false, false, true);
+        assert_eq!(keymap.lookup(&shift_up), Some(Command::CursorUp));
+        // Shift+F3 has its own binding
+        let shift_f3 = key(Key::F(3), false, false, true);
+        assert_eq!(keymap.lookup(&shift_f3), Some(Command::FindPrev));
+        // So do the selecting Home and End keys
+        let ctrl_shift_end = key(Key::End, true, false, true);
+        assert_eq!(
+            keymap.lookup(&ctrl_shift_end),
+            Some(Command::SelectToFileEnd)
+        );
+    }
+
+    #[test]
+    fn test_help_text_lists_bindings() {
+        let mut keymap = Keymap::default();
+        keymap.bind_command("Alt+T", Command::Script(0)).unwrap();
+        let text = help_text(&keymap, &["insert-date"]);
+        let save = text.lines().find(|l| l.starts_with("save ")).unwrap();
+        assert!(save.contains("Ctrl+S"));
+        assert!(save.ends_with("Save the buffer"));
+        let back = text.lines().find(|l| l.starts_with("jump-back ")).unwrap();
+        assert!(back.contains("Alt+Left"));
+        let date = text.lines().last().unwrap();
+        assert!(date.starts_with("insert-date ") && date.contains("Alt+T"));
+        assert_eq!(text.lines().count(), COMMANDS.len() + 4);
+    }
+}
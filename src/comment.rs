@@ -0,0 +1,180 @@
+use crate::filetype::CommentSyntax;
+
+// ---------------------------------------------------------------------------
+// Comment toggling — computed as per-line edits so undo stays small
+// ---------------------------------------------------------------------------
+
+/// Replace `delete` bytes at byte `col` of line `line` with `insert`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEdit {
+    pub line: usize,
+    pub col: usize,
+    pub delete: usize,
+    pub insert: String,
+}
+
+impl LineEdit {
+    fn new(line: usize, col: usize, delete: usize, insert: &str) -> Self {
+        LineEdit {
+            line,
+            col,
+            delete,
+            insert: insert.to_string(),
+        }
+    }
+
+    /// Where column `col` of the edited line ends up. Text after the edit
+    /// shifts with it; column 0 stays at the line start.
+    pub fn shift(&self, col: usize) -> usize {
+        if self.col > col || (self.col == col && col == 0) {
+            return col;
+        }
+        self.col + self.insert.len() + (col - self.col).saturating_sub(self.delete)
+    }
+}
+
+/// Edits that comment or uncomment `lines`, in ascending order.
+///
+/// With a line comment: when every non-blank line is already commented the
+/// markers (and one following space) are removed; otherwise every non-blank
+/// line gets a marker at the smallest indentation, so a mixed region is
+/// commented as a whole. Blank lines are left alone. Filetypes with only
+/// block comments wrap the region in one block, or unwrap it.
+pub fn toggle<S: AsRef<str>>(lines: &[S], syntax: &CommentSyntax) -> Vec<LineEdit> {
+    let lines: Vec<&str> = lines
+        .iter()
+        .map(|l| l.as_ref().trim_end_matches('\r'))
+        .collect();
+    let filled: Vec<usize> = (0..lines.len())
+        .filter(|&i| !lines[i].trim().is_empty())
+        .collect();
+    if filled.is_empty() {
+        return Vec::new();
+    }
+    match (syntax.line, syntax.block) {
+        (Some(marker), _) => toggle_line(&lines, &filled, marker),
+        (None, Some((open, close))) => toggle_block(&lines, &filled, open, close),
+        (None, None) => Vec::new(),
+    }
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn toggle_line(lines: &[&str], filled: &[usize], marker: &str) -> Vec<LineEdit> {
+    let commented = filled
+        .iter()
+        .all(|&i| lines[i].trim_start().starts_with(marker));
+    if commented {
+        return filled
+            .iter()
+            .map(|&i| {
+                let col = indent(lines[i]);
+                let after = &lines[i][col + marker.len()..];
+                let len = marker.len() + usize::from(after.starts_with(' '));
+                LineEdit::new(i, col, len, "")
+            })
+            .collect();
+    }
+    let col = filled.iter().map(|&i| indent(lines[i])).min().unwrap_or(0);
+    let insert = format!("{} ", marker);
+    filled
+        .iter()
+        .map(|&i| LineEdit::new(i, col, 0, &insert))
+        .collect()
+}
+
+fn toggle_block(lines: &[&str], filled: &[usize], open: &str, close: &str) -> Vec<LineEdit> {
+    let (first, last) = (filled[0], filled[filled.len() - 1]);
+    let start = indent(lines[first]);
+    let end = lines[last].trim_end().len();
+    let head = &lines[first][start..];
+    let tail = &lines[last][..end];
+    let wrapped = head.starts_with(open)
+        && tail.ends_with(close)
+        && (first != last || head.len() >= open.len() + close.len());
+    if wrapped {
+        let open_len = open.len() + usize::from(head[open.len()..].starts_with(' '));
+        let close_start = end - close.len();
+        let close_start = if tail[..close_start].ends_with(' ')
+            && (first != last || close_start > start + open_len)
+        {
+            close_start - 1
+        } else {
+            close_start
+        };
+        return vec![
+            LineEdit::new(first, start, open_len, ""),
+            LineEdit::new(last, close_start, end - close_start, ""),
+        ];
+    }
+    vec![
+        LineEdit::new(first, start, 0, &format!("{} ", open)),
+        LineEdit::new(last, end, 0, &format!(" {}", close)),
+    ]
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filetype::comment_syntax;
+
+    /// Apply `edits` to `lines` the way the editor does: last first.
+    fn apply(lines: &[&str], edits: &[LineEdit]) -> Vec<String> {
+        let mut out: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        for e in edits.iter().rev() {
+            out[e.line].replace_range(e.col..e.col + e.delete, &e.insert);
+        }
+        out
+    }
+
+    fn toggled(lines: &[&str], filetype: &str) -> Vec<String> {
+        apply(lines, &toggle(lines, &comment_syntax(filetype).unwrap()))
+    }
+
+    #[test]
+    fn test_line_comments_align_and_skip_blank() {
+        let lines = ["    if x {", "", "        y();", "    }"];
+        let out = toggled(&lines, "rs");
+        assert_eq!(out, ["    // if x {", "", "    //     y();", "    // }"]);
+        let back: Vec<&str> = out.iter().map(String::as_str).collect();
+        assert_eq!(toggled(&back, "rs"), lines);
+    }
+
+    #[test]
+    fn test_mixed_region_is_commented() {
+        let lines = ["# done", "todo", "#tight"];
+        assert_eq!(toggled(&lines, "py"), ["# # done", "# todo", "# #tight"]);
+        assert_eq!(
+            toggled(&["#tight", "  # spaced"], "py"),
+            ["tight", "  spaced"]
+        );
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let lines = ["  a { color: red; }", "b {}"];
+        let out = toggled(&lines, "css");
+        assert_eq!(out, ["  /* a { color: red; }", "b {} */"]);
+        let back: Vec<&str> = out.iter().map(String::as_str).collect();
+        assert_eq!(toggled(&back, "css"), lines);
+        assert_eq!(toggled(&["<p>hi</p>"], "html"), ["<!-- <p>hi</p> -->"]);
+        assert_eq!(toggled(&["<!-- x -->"], "html"), ["x"]);
+    }
+
+    #[test]
+    fn test_shift() {
+        let insert = LineEdit::new(0, 4, 0, "// ");
+        assert_eq!(insert.shift(0), 0);
+        assert_eq!(insert.shift(2), 2);
+        assert_eq!(insert.shift(6), 9);
+        let remove = LineEdit::new(0, 4, 3, "");
+        assert_eq!(remove.shift(5), 4);
+        assert_eq!(remove.shift(9), 6);
+    }
+}
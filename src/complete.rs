@@ -0,0 +1,351 @@
+use std::fs;
+use std::path::Path;
+
+use crate::ui::ListState;
+use crate::unicode::is_word_char;
+
+// ---------------------------------------------------------------------------
+// Word completion — identifiers collected from open buffers
+// ---------------------------------------------------------------------------
+
+/// Most buffer words offered at once.
+pub const MAX_ITEMS: usize = 10;
+
+/// Rows of the popup; longer lists scroll.
+pub const VISIBLE_ITEMS: usize = 10;
+
+/// Byte offset where the word ending at `col` starts in `line`.
+pub fn word_start(line: &str, col: usize) -> usize {
+    line[..col]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map_or(col, |(i, _)| i)
+}
+
+/// Every identifier in `text` with its starting byte offset.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| match (start, is_word_char(c)) {
+            (None, true) => {
+                start = Some(i);
+                None
+            }
+            (Some(s), false) => {
+                start = None;
+                Some((s, &text[s..i]))
+            }
+            _ => None,
+        })
+}
+
+/// Completions for `prefix`, best first. Words matching the prefix exactly
+/// beat case-insensitive matches; within each, words from the active buffer
+/// rank by distance from `cursor`, ahead of words from `others`. The word
+/// being typed (the one touching `cursor`) is left out.
+pub fn candidates<'a>(
+    prefix: &str,
+    active: &'a str,
+    cursor: usize,
+    others: &[&'a str],
+) -> Vec<String> {
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    let lower_prefix = prefix.to_lowercase();
+    // (case rank, source, distance, word)
+    let mut scored: Vec<(u8, u8, usize, &'a str)> = Vec::new();
+    let mut consider = |word: &'a str, source: u8, distance: usize| {
+        if word.len() <= prefix.len() {
+            return;
+        }
+        let case = if word.starts_with(prefix) {
+            0
+        } else if word.to_lowercase().starts_with(&lower_prefix) {
+            1
+        } else {
+            return;
+        };
+        scored.push((case, source, distance, word));
+    };
+    for (start, word) in words(active) {
+        if start <= cursor && cursor <= start + word.len() {
+            continue;
+        }
+        consider(word, 0, start.abs_diff(cursor));
+    }
+    for text in others {
+        for (_, word) in words(text) {
+            consider(word, 1, 0);
+        }
+    }
+
+    scored.sort();
+    let mut result: Vec<String> = Vec::new();
+    for (_, _, _, word) in scored {
+        if !result.iter().any(|w| w == word) {
+            result.push(word.to_string());
+            if result.len() == MAX_ITEMS {
+                break;
+            }
+        }
+    }
+    result
+}
+
+// ---------------------------------------------------------------------------
+// Completion items — buffer words and language server results
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub insert: String, // replaces the typed prefix
+    pub filter: String, // matched against the typed prefix
+    pub snippet: bool,  // `insert` is a snippet template
+    pub kind: char,     // icon column, ' ' for buffer words
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+}
+
+impl CompletionItem {
+    pub fn word(word: &str) -> Self {
+        CompletionItem {
+            label: word.to_string(),
+            insert: word.to_string(),
+            filter: word.to_string(),
+            snippet: false,
+            kind: ' ',
+            detail: None,
+            documentation: None,
+        }
+    }
+}
+
+/// Server items matching `prefix` (exact-case prefix matches first, then
+/// case-insensitive ones, each in server order), followed by buffer `words`
+/// the server did not already offer. An empty prefix keeps every server item.
+pub fn merge(server: &[CompletionItem], prefix: &str, words: Vec<String>) -> Vec<CompletionItem> {
+    let lower_prefix = prefix.to_lowercase();
+    let mut exact = Vec::new();
+    let mut folded = Vec::new();
+    for item in server {
+        if item.filter.starts_with(prefix) {
+            exact.push(item.clone());
+        } else if item.filter.to_lowercase().starts_with(&lower_prefix) {
+            folded.push(item.clone());
+        }
+    }
+    exact.append(&mut folded);
+    for word in words {
+        if !exact.iter().any(|i| i.insert == word) {
+            exact.push(CompletionItem::word(&word));
+        }
+    }
+    exact
+}
+
+// ---------------------------------------------------------------------------
+// Path completion — file names in prompts
+// ---------------------------------------------------------------------------
+
+/// Split a typed path into its directory part (with the trailing `/`) and
+/// the file name being typed.
+pub fn split_path(input: &str) -> (&str, &str) {
+    match input.rfind('/') {
+        Some(i) => input.split_at(i + 1),
+        None => ("", input),
+    }
+}
+
+/// The (name, is_dir) entries of `dir`, or of the current directory when
+/// it is empty. Unreadable directories list nothing.
+pub fn dir_entries(dir: &str) -> Vec<(String, bool)> {
+    let dir = if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|e| {
+            let is_dir = e.path().is_dir();
+            (e.file_name().to_string_lossy().into_owned(), is_dir)
+        })
+        .collect()
+}
+
+/// Complete the file name at the end of `input` from `entries`, the
+/// listing of its directory. Returns the input extended by the longest
+/// common prefix of the matches (a lone directory also gets its `/`) and
+/// the matching names, sorted, with `/` after directories. Hidden files
+/// only match once a `.` is typed.
+pub fn complete_path(input: &str, entries: &[(String, bool)]) -> (String, Vec<String>) {
+    let (dir, typed) = split_path(input);
+    let mut matches: Vec<String> = entries
+        .iter()
+        .filter(|(name, _)| {
+            name.starts_with(typed) && (typed.starts_with('.') || !name.starts_with('.'))
+        })
+        .map(|(name, is_dir)| {
+            if *is_dir {
+                format!("{}/", name)
+            } else {
+                name.clone()
+            }
+        })
+        .collect();
+    matches.sort();
+    let completed = match matches.as_slice() {
+        [] => return (input.to_string(), matches),
+        [only] => format!("{}{}", dir, only),
+        [first, rest @ ..] => {
+            let mut common = first.as_str();
+            for name in rest {
+                let len = common
+                    .char_indices()
+                    .zip(name.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(name.len()), |((i, _), _)| i);
+                common = &common[..len];
+            }
+            format!("{}{}", dir, common)
+        }
+    };
+    (completed, matches)
+}
+
+// ---------------------------------------------------------------------------
+// Completion — popup state
+// ---------------------------------------------------------------------------
+
+pub struct Completion {
+    pub start: usize,                      // byte offset where the completed word begins
+    pub server_items: Vec<CompletionItem>, // unfiltered language server results
+    pub items: Vec<CompletionItem>,
+    pub list: ListState,
+}
+
+impl Completion {
+    pub fn new(start: usize) -> Self {
+        Completion {
+            start,
+            server_items: Vec::new(),
+            items: Vec::new(),
+            list: ListState::default(),
+        }
+    }
+
+    pub fn set_items(&mut self, items: Vec<CompletionItem>) {
+        self.items = items;
+        self.list = ListState::default();
+    }
+
+    pub fn current(&self) -> &CompletionItem {
+        &self.items[self.list.selected]
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_word_start() {
+        assert_eq!(word_start("let foo_b", 9), 4);
+        assert_eq!(word_start("x.", 2), 2);
+        assert_eq!(word_start("héllo", 6), 0);
+    }
+
+    #[test]
+    fn test_words() {
+        let found: Vec<(usize, &str)> = words("a(bc, d_1)").collect();
+        assert_eq!(found, vec![(0, "a"), (2, "bc"), (6, "d_1")]);
+    }
+
+    #[test]
+    fn test_candidates_rank_by_proximity() {
+        // Cursor after "pr" on the middle line
+        let text = "print_far\npr\nprint_near";
+        let items = candidates("pr", text, 12, &[]);
+        assert_eq!(items, vec!["print_near", "print_far"]);
+    }
+
+    #[test]
+    fn test_candidates_case_and_other_buffers() {
+        let text = "Preview pr";
+        let items = candidates("pr", text, 10, &["process preview"]);
+        // Exact-case matches (active first), then case-insensitive
+        assert_eq!(items, vec!["preview", "process", "Preview"]);
+    }
+
+    #[test]
+    fn test_candidates_dedup_and_skip_current_word() {
+        let text = "value value val";
+        let items = candidates("val", text, 15, &["value"]);
+        assert_eq!(items, vec!["value"]);
+        assert!(candidates("", text, 0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_merge_server_items_and_words() {
+        let mut upper = CompletionItem::word("Print");
+        upper.kind = 'S';
+        let server = vec![
+            upper,
+            CompletionItem::word("print"),
+            CompletionItem::word("len"),
+        ];
+        let items = merge(&server, "pr", vec!["print".into(), "process".into()]);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["print", "Print", "process"]);
+        assert_eq!(merge(&server, "", Vec::new()).len(), 3);
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let entries: Vec<(String, bool)> = [
+            ("src", true),
+            ("script.sh", false),
+            ("README.md", false),
+            (".git", true),
+        ]
+        .iter()
+        .map(|&(n, d)| (n.to_string(), d))
+        .collect();
+        let (input, matches) = complete_path("s", &entries);
+        assert_eq!(input, "s");
+        assert_eq!(matches, ["script.sh", "src/"]);
+        assert_eq!(complete_path("sr", &entries).0, "src/");
+        assert_eq!(complete_path("../R", &entries).0, "../README.md");
+        assert_eq!(complete_path(".", &entries).0, ".git/");
+        assert!(complete_path("x", &entries).1.is_empty());
+        assert_eq!(split_path("a/b/c"), ("a/b/", "c"));
+    }
+
+    #[test]
+    fn test_selection_wraps_and_scrolls() {
+        let mut c = Completion::new(0);
+        c.set_items(
+            (0..VISIBLE_ITEMS + 2)
+                .map(|i| CompletionItem::word(&format!("a{}", i)))
+                .collect(),
+        );
+        c.list.select_prev(c.items.len(), VISIBLE_ITEMS);
+        assert_eq!(c.current().label, format!("a{}", VISIBLE_ITEMS + 1));
+        assert_eq!(c.list.scroll, 2);
+        c.list.select_next(c.items.len(), VISIBLE_ITEMS);
+        assert_eq!(c.current().label, "a0");
+        assert_eq!(c.list.scroll, 0);
+    }
+}
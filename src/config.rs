@@ -0,0 +1,472 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::json::JsonValue;
+use crate::scrollbar::SideColumn;
+use crate::statusline::StatusLayout;
+use crate::theme::{Background, Theme};
+
+// ---------------------------------------------------------------------------
+// Config — runtime settings from ~/.config/zelux/config.json
+// ---------------------------------------------------------------------------
+
+pub struct Config {
+    /// Shell command run by the build key (Ctrl+B).
+    pub build_command: String,
+    /// Start in vi-style modal editing (normal/insert/visual).
+    pub vi_mode: bool,
+    /// Type the closing bracket/quote along with the opening one.
+    pub auto_pairs: bool,
+    /// Lines of context kept above and below the cursor.
+    pub scrolloff: usize,
+    /// Animate PageUp/PageDown over a few frames instead of jumping.
+    pub smooth_scroll: bool,
+    /// Frames drawn per second at most while input keeps arriving, so a
+    /// held key or a slow link redraws once per burst. 0 draws after every
+    /// read.
+    pub max_fps: usize,
+    /// Milliseconds to wait for input before doing background work, and
+    /// for the rest of a key sequence after Escape (rounded up to tenths of
+    /// a second there).
+    pub read_timeout: u64,
+    /// Above `read_timeout`, the wait for input grows towards this many
+    /// milliseconds while no keys come, for fewer wakeups when idle, and
+    /// drops back on the next key. 0 keeps the wait at `read_timeout`.
+    pub idle_timeout: u64,
+    /// Draw the cursor as a colored cell instead of using the terminal's,
+    /// for terminals and multiplexers that lose it while redrawing.
+    pub software_cursor: bool,
+    /// Milliseconds between blinks of the software cursor; 0 keeps it
+    /// steady.
+    pub cursor_blink: u64,
+    /// Underline search matches instead of coloring them, for terminals
+    /// with few colors; the current match keeps its background.
+    pub search_underline: bool,
+    /// Column limit for reflowing paragraphs.
+    pub text_width: usize,
+    /// Strip trailing whitespace and extra final newlines on save.
+    pub trim_trailing_whitespace: bool,
+    /// Only trim lines edited since the file was loaded or last saved.
+    pub trim_modified_lines_only: bool,
+    /// Open a symlinked file under its target's path, so the buffer names
+    /// the real file. Either way, saving writes the target and keeps the
+    /// link.
+    pub resolve_symlinks: bool,
+    /// Keep a `.NAME.zelux-lock` file beside each file open for editing,
+    /// so another zelux opening it warns and offers it read-only.
+    pub lock_files: bool,
+    /// One level of indentation: `"indent"` is a number of spaces or "tab".
+    pub indent: String,
+    /// Columns between tab stops, where tab characters are drawn to.
+    pub tab_width: usize,
+    /// Line up the tab-separated cells of adjacent lines (elastic
+    /// tabstops) instead of using fixed stops.
+    pub elastic_tabs: bool,
+    /// Language server commands by file extension, e.g. ("rs", "rust-analyzer").
+    pub language_servers: Vec<(String, String)>,
+    /// Formatters run on save by file extension, e.g. ("py", "black -q -").
+    /// They read the buffer on stdin and print the result.
+    pub formatters: Vec<(String, String)>,
+    /// Highlight misspelled words in prose, comments and strings.
+    pub spell_check: bool,
+    /// Hunspell `.dic` or plain word list; a system dictionary when unset.
+    pub spell_dictionary: Option<PathBuf>,
+    /// Give the cursor's line a subtle background.
+    pub highlight_current_line: bool,
+    /// Draw annotations such as diagnostic messages beside the text.
+    pub virtual_text: bool,
+    /// 1-based column to draw a vertical ruler at (e.g. 80).
+    pub color_column: Option<usize>,
+    /// Right-hand column: "off", "scrollbar" or "minimap".
+    pub side_column: SideColumn,
+    /// Status bar segments on each side.
+    pub status_bar: StatusLayout,
+    /// Terminal background: "auto" asks the terminal, "dark" or "light"
+    /// picks the theme variant outright.
+    pub background: Background,
+    /// Colors of the render layers on a dark background, and on a light
+    /// one. The `"theme"` object overrides colors in both.
+    pub theme: Theme,
+    pub light_theme: Theme,
+    /// Extra key bindings, e.g. ("Ctrl+A", "increment-number").
+    pub keys: Vec<(String, String)>,
+    /// User commands written in the script language, by name, e.g.
+    /// ("insert-date", "insert(date(\"%Y-%m-%d\"))"). In the JSON a
+    /// script is a string or an array of lines.
+    pub scripts: Vec<(String, String)>,
+    /// Digraphs added to or replacing the built-in ones, e.g. ("e=", '€').
+    pub digraphs: Vec<(String, char)>,
+    /// UI language: replaces the built-in strings with `lang/<language>.json`
+    /// from the config directory. Empty for English.
+    pub language: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            build_command: "cargo build".to_string(),
+            vi_mode: false,
+            auto_pairs: false,
+            scrolloff: 0,
+            smooth_scroll: false,
+            max_fps: 60,
+            read_timeout: 100,
+            idle_timeout: 0,
+            software_cursor: false,
+            cursor_blink: 500,
+            search_underline: false,
+            text_width: 80,
+            trim_trailing_whitespace: false,
+            trim_modified_lines_only: false,
+            resolve_symlinks: false,
+            lock_files: true,
+            indent: "    ".to_string(),
+            tab_width: 4,
+            elastic_tabs: false,
+            language_servers: Vec::new(),
+            formatters: Vec::new(),
+            spell_check: false,
+            spell_dictionary: None,
+            highlight_current_line: true,
+            virtual_text: true,
+            color_column: None,
+            side_column: SideColumn::Off,
+            status_bar: StatusLayout::default(),
+            background: Background::Auto,
+            theme: Theme::default(),
+            light_theme: Theme::light(),
+            keys: Vec::new(),
+            scripts: Vec::new(),
+            digraphs: Vec::new(),
+            language: String::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the user config. A missing file yields the defaults; a malformed
+    /// one is reported as an error so the caller can warn and fall back.
+    pub fn load() -> Result<Config, String> {
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.json"),
+            None => return Ok(Config::default()),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("Failed to read config: {}", e)),
+        };
+        let json = JsonValue::parse(&text).map_err(|e| format!("config.json: {}", e))?;
+        Ok(Config::from_json(&json))
+    }
+
+    /// Build a config from parsed JSON, keeping defaults for missing or
+    /// mistyped keys.
+    pub fn from_json(json: &JsonValue) -> Config {
+        let mut config = Config::default();
+        if let Some(cmd) = json.get("build_command").and_then(|v| v.as_str()) {
+            config.build_command = cmd.to_string();
+        }
+        if let Some(language) = json.get("language").and_then(|v| v.as_str()) {
+            config.language = language.to_string();
+        }
+        if let Some(vi) = json.get("vi_mode").and_then(|v| v.as_bool()) {
+            config.vi_mode = vi;
+        }
+        if let Some(pairs) = json.get("auto_pairs").and_then(|v| v.as_bool()) {
+            config.auto_pairs = pairs;
+        }
+        if let Some(lines) = json.get("scrolloff").and_then(|v| v.as_usize()) {
+            config.scrolloff = lines;
+        }
+        if let Some(smooth) = json.get("smooth_scroll").and_then(|v| v.as_bool()) {
+            config.smooth_scroll = smooth;
+        }
+        if let Some(fps) = json.get("max_fps").and_then(|v| v.as_usize()) {
+            config.max_fps = fps;
+        }
+        if let Some(ms) = json.get("read_timeout").and_then(|v| v.as_usize()) {
+            config.read_timeout = ms.max(1) as u64;
+        }
+        if let Some(ms) = json.get("idle_timeout").and_then(|v| v.as_usize()) {
+            config.idle_timeout = ms as u64;
+        }
+        if let Some(software) = json.get("software_cursor").and_then(|v| v.as_bool()) {
+            config.software_cursor = software;
+        }
+        if let Some(ms) = json.get("cursor_blink").and_then(|v| v.as_usize()) {
+            config.cursor_blink = ms as u64;
+        }
+        if let Some(underline) = json.get("search_underline").and_then(|v| v.as_bool()) {
+            config.search_underline = underline;
+        }
+        if let Some(width) = json.get("text_width").and_then(|v| v.as_usize()) {
+            config.text_width = width.max(10);
+        }
+        if let Some(trim) = json
+            .get("trim_trailing_whitespace")
+            .and_then(|v| v.as_bool())
+        {
+            config.trim_trailing_whitespace = trim;
+        }
+        if let Some(only) = json
+            .get("trim_modified_lines_only")
+            .and_then(|v| v.as_bool())
+        {
+            config.trim_modified_lines_only = only;
+        }
+        if let Some(resolve) = json.get("resolve_symlinks").and_then(|v| v.as_bool()) {
+            config.resolve_symlinks = resolve;
+        }
+        if let Some(lock) = json.get("lock_files").and_then(|v| v.as_bool()) {
+            config.lock_files = lock;
+        }
+        if let Some(width) = json.get("tab_width").and_then(|v| v.as_usize()) {
+            config.tab_width = width.clamp(1, 16);
+        }
+        if let Some(elastic) = json.get("elastic_tabs").and_then(|v| v.as_bool()) {
+            config.elastic_tabs = elastic;
+        }
+        if let Some(indent) = json.get("indent") {
+            if let Some(n) = indent.as_usize() {
+                config.indent = " ".repeat(n.clamp(1, 16));
+            } else if indent.as_str() == Some("tab") {
+                config.indent = "\t".to_string();
+            }
+        }
+        if let Some(servers) = json.get("language_servers").and_then(|v| v.as_object()) {
+            config.language_servers = servers
+                .iter()
+                .filter_map(|(ft, cmd)| Some((ft.clone(), cmd.as_str()?.to_string())))
+                .collect();
+        }
+        if let Some(formatters) = json.get("formatters").and_then(|v| v.as_object()) {
+            config.formatters = formatters
+                .iter()
+                .filter_map(|(ft, cmd)| Some((ft.clone(), cmd.as_str()?.to_string())))
+                .collect();
+        }
+        if let Some(spell) = json.get("spell_check").and_then(|v| v.as_bool()) {
+            config.spell_check = spell;
+        }
+        if let Some(dict) = json.get("spell_dictionary").and_then(|v| v.as_str()) {
+            config.spell_dictionary = Some(PathBuf::from(dict));
+        }
+        if let Some(highlight) = json.get("highlight_current_line").and_then(|v| v.as_bool()) {
+            config.highlight_current_line = highlight;
+        }
+        if let Some(shown) = json.get("virtual_text").and_then(|v| v.as_bool()) {
+            config.virtual_text = shown;
+        }
+        if let Some(col) = json.get("color_column").and_then(|v| v.as_usize()) {
+            config.color_column = (col > 0).then_some(col);
+        }
+        if let Some(side) = json
+            .get("side_column")
+            .and_then(|v| v.as_str())
+            .and_then(SideColumn::from_name)
+        {
+            config.side_column = side;
+        }
+        if let Some(status_bar) = json.get("status_bar") {
+            config.status_bar = StatusLayout::from_json(status_bar);
+        }
+        if let Some(background) = json
+            .get("background")
+            .and_then(|v| v.as_str())
+            .and_then(Background::from_name)
+        {
+            config.background = background;
+        }
+        if let Some(theme) = json.get("theme") {
+            config.theme = Theme::from_json(theme, Theme::default());
+            config.light_theme = Theme::from_json(theme, Theme::light());
+        }
+        if let Some(keys) = json.get("keys").and_then(|v| v.as_object()) {
+            config.keys = keys
+                .iter()
+                .filter_map(|(key, cmd)| Some((key.clone(), cmd.as_str()?.to_string())))
+                .collect();
+        }
+        if let Some(scripts) = json.get("scripts").and_then(|v| v.as_object()) {
+            config.scripts = scripts
+                .iter()
+                .filter_map(|(name, source)| {
+                    let source = match source.as_array() {
+                        Some(lines) => lines
+                            .iter()
+                            .map(|l| l.as_str())
+                            .collect::<Option<Vec<_>>>()?
+                            .join("\n"),
+                        None => source.as_str()?.to_string(),
+                    };
+                    Some((name.clone(), source))
+                })
+                .collect();
+        }
+        if let Some(digraphs) = json.get("digraphs").and_then(|v| v.as_object()) {
+            config.digraphs = digraphs
+                .iter()
+                .filter(|(name, _)| name.chars().count() == 2)
+                .filter_map(|(name, c)| {
+                    let mut chars = c.as_str()?.chars();
+                    let c = chars.next().filter(|_| chars.next().is_none())?;
+                    Some((name.clone(), c))
+                })
+                .collect();
+        }
+        config
+    }
+}
+
+/// The zelux config directory: `$XDG_CONFIG_HOME/zelux` or `~/.config/zelux`.
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME")
+        && !xdg.is_empty()
+    {
+        return Some(PathBuf::from(xdg).join("zelux"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("zelux"))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::default();
+        assert_eq!(config.build_command, "cargo build");
+    }
+
+    #[test]
+    fn test_from_json_overrides() {
+        let json = JsonValue::parse(
+            r#"{"build_command": "make -j4", "vi_mode": true, "auto_pairs": true,
+                "spell_check": true, "spell_dictionary": "/tmp/en.dic",
+                "trim_trailing_whitespace": true, "language": "es", "max_fps": 30,
+                "read_timeout": 50, "idle_timeout": 2000, "software_cursor": true,
+                "cursor_blink": 0, "resolve_symlinks": true, "lock_files": false}"#,
+        )
+        .unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(config.build_command, "make -j4");
+        assert_eq!(config.language, "es");
+        assert_eq!(config.max_fps, 30);
+        assert_eq!(config.read_timeout, 50);
+        assert_eq!(config.idle_timeout, 2000);
+        assert!(config.software_cursor);
+        assert_eq!(config.cursor_blink, 0);
+        assert!(config.vi_mode);
+        assert!(config.auto_pairs);
+        assert!(config.trim_trailing_whitespace);
+        assert!(!config.trim_modified_lines_only);
+        assert!(config.resolve_symlinks);
+        assert!(!config.lock_files);
+        assert!(config.spell_check);
+        assert_eq!(config.spell_dictionary, Some(PathBuf::from("/tmp/en.dic")));
+    }
+
+    #[test]
+    fn test_render_layers() {
+        let json = JsonValue::parse(
+            r#"{"highlight_current_line": false, "color_column": 80,
+                "side_column": "minimap", "theme": {"color_column": 52},
+                "virtual_text": false, "background": "light",
+                "search_underline": true}"#,
+        )
+        .unwrap();
+        let config = Config::from_json(&json);
+        assert!(!config.highlight_current_line);
+        assert!(!config.virtual_text);
+        assert!(config.search_underline);
+        assert_eq!(config.color_column, Some(80));
+        assert_eq!(config.side_column, SideColumn::Minimap);
+        assert_eq!(config.background, Background::Light);
+        assert_eq!(
+            config.theme.color_column,
+            crate::render::Color::Color256(52)
+        );
+        assert_eq!(
+            config.light_theme.color_column,
+            crate::render::Color::Color256(52)
+        );
+        assert_eq!(Config::default().background, Background::Auto);
+        assert!(Config::default().highlight_current_line);
+        assert!(Config::default().virtual_text);
+        assert_eq!(Config::default().color_column, None);
+    }
+
+    #[test]
+    fn test_from_json_ignores_wrong_types() {
+        let json = JsonValue::parse(r#"{"build_command": 42}"#).unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(config.build_command, "cargo build");
+        assert!(!config.vi_mode);
+    }
+
+    #[test]
+    fn test_indent_unit() {
+        let indent = |text: &str| Config::from_json(&JsonValue::parse(text).unwrap()).indent;
+        assert_eq!(indent(r#"{"indent": 2}"#), "  ");
+        assert_eq!(indent(r#"{"indent": "tab"}"#), "\t");
+        assert_eq!(indent(r#"{"indent": "wide"}"#), "    ");
+        let json = JsonValue::parse(r#"{"tab_width": 8, "elastic_tabs": true}"#).unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(config.tab_width, 8);
+        assert!(config.elastic_tabs);
+        assert_eq!(Config::default().tab_width, 4);
+    }
+
+    #[test]
+    fn test_language_servers() {
+        let json =
+            JsonValue::parse(r#"{"language_servers": {"rs": "rust-analyzer", "py": 1}}"#).unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(
+            config.language_servers,
+            vec![("rs".to_string(), "rust-analyzer".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_formatters() {
+        let json =
+            JsonValue::parse(r#"{"formatters": {"rs": "rustfmt", "py": ["black"]}}"#).unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(
+            config.formatters,
+            vec![("rs".to_string(), "rustfmt".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_scripts() {
+        let json = JsonValue::parse(
+            r#"{"scripts": {"hi": "insert(\"hi\")", "two": ["let a = 1", "insert(a)"],
+                "bad": 3}}"#,
+        )
+        .unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(
+            config.scripts,
+            vec![
+                ("hi".to_string(), "insert(\"hi\")".to_string()),
+                ("two".to_string(), "let a = 1\ninsert(a)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_digraphs() {
+        let json =
+            JsonValue::parse(r#"{"digraphs": {"e=": "€", "long": "x", "ab": "xy", "cd": 1}}"#)
+                .unwrap();
+        let config = Config::from_json(&json);
+        assert_eq!(config.digraphs, vec![("e=".to_string(), '€')]);
+    }
+}
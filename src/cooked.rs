@@ -0,0 +1,155 @@
+use std::io::{BufRead, Write};
+
+use crate::buffer::Buffer;
+use crate::strings;
+
+// ---------------------------------------------------------------------------
+// Cooked mode — a line-based viewer for when raw mode is not available
+// ---------------------------------------------------------------------------
+
+/// What a line typed at the viewer's prompt asks for.
+#[derive(Debug, PartialEq)]
+enum Request {
+    /// Enter: the next page.
+    Next,
+    Back,
+    /// A line number, 1-based.
+    Goto(usize),
+    /// `/TEXT`: the next line containing TEXT.
+    Find(String),
+    Quit,
+    Unknown,
+}
+
+fn parse(line: &str) -> Request {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let Some(pattern) = line.strip_prefix('/') {
+        return Request::Find(pattern.to_string());
+    }
+    match line.trim() {
+        "" => Request::Next,
+        "b" => Request::Back,
+        "q" => Request::Quit,
+        n => n.parse().map_or(Request::Unknown, Request::Goto),
+    }
+}
+
+/// Show `buffer` a page of `page` lines at a time on `output`, taking
+/// requests a line at a time from `input` in the terminal's cooked mode.
+/// When the input ends, the rest of the buffer is written out.
+pub fn view(
+    buffer: &Buffer,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    page: usize,
+) -> Result<(), String> {
+    let page = page.max(1);
+    let count = buffer.line_count();
+    let width = count.to_string().len();
+    let mut top = 0;
+    let io_error = |e: std::io::Error| e.to_string();
+    writeln!(output, "{}", strings::get("cooked_help")).map_err(io_error)?;
+    loop {
+        let end = (top + page).min(count);
+        write_lines(buffer, top..end, width, output).map_err(io_error)?;
+        if end == count {
+            return Ok(());
+        }
+        write!(output, ":").map_err(io_error)?;
+        output.flush().map_err(io_error)?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(io_error)? == 0 {
+            return write_lines(buffer, end..count, width, output).map_err(io_error);
+        }
+        top = match parse(&line) {
+            Request::Next => end,
+            Request::Back => top.saturating_sub(page),
+            Request::Goto(n) => n.saturating_sub(1).min(count - 1),
+            Request::Find(pattern) => match find_line(buffer, top + 1, &pattern) {
+                Some(found) => found,
+                None => {
+                    writeln!(output, "{}", strings::get("no_matches")).map_err(io_error)?;
+                    top
+                }
+            },
+            Request::Quit => return Ok(()),
+            Request::Unknown => {
+                writeln!(output, "{}", strings::get("cooked_help")).map_err(io_error)?;
+                top
+            }
+        };
+    }
+}
+
+fn write_lines(
+    buffer: &Buffer,
+    lines: std::ops::Range<usize>,
+    width: usize,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    for n in lines {
+        let text = buffer.get_line(n).unwrap_or_default();
+        writeln!(output, "{:>width$} {}", n + 1, text)?;
+    }
+    output.flush()
+}
+
+/// The first line at or after `from` containing `pattern`.
+fn find_line(buffer: &Buffer, from: usize, pattern: &str) -> Option<usize> {
+    (from..buffer.line_count()).find(|&n| {
+        buffer
+            .get_line(n)
+            .is_some_and(|text| text.contains(pattern))
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, input: &str, page: usize) -> String {
+        let mut buffer = Buffer::new();
+        buffer.insert(0, text);
+        let mut output = Vec::new();
+        view(&buffer, &mut input.as_bytes(), &mut output, page).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        // Drop the help line
+        output.split_once('\n').unwrap().1.to_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("\n"), Request::Next);
+        assert_eq!(parse("b\n"), Request::Back);
+        assert_eq!(parse(" 12 \n"), Request::Goto(12));
+        assert_eq!(parse("/a b\n"), Request::Find("a b".to_string()));
+        assert_eq!(parse("q\r\n"), Request::Quit);
+        assert_eq!(parse("x\n"), Request::Unknown);
+    }
+
+    #[test]
+    fn test_view_pages() {
+        let text = "a\nb\nc\nd\ne";
+        assert_eq!(run(text, "\nq\n", 2), "1 a\n2 b\n:3 c\n4 d\n:");
+        assert_eq!(run(text, "5\n", 2), "1 a\n2 b\n:5 e\n");
+        assert_eq!(run(text, "4\nb\nq\n", 2), "1 a\n2 b\n:4 d\n5 e\n");
+    }
+
+    #[test]
+    fn test_view_find() {
+        let text = "one\ntwo\nthree\nfour";
+        assert_eq!(run(text, "/f\nq\n", 1), "1 one\n:4 four\n");
+        assert_eq!(run(text, "/zz\nq\n", 1), "1 one\n:No matches\n1 one\n:");
+    }
+
+    #[test]
+    fn test_view_input_ends() {
+        // Piped input runs out: the rest is written without prompts
+        assert_eq!(run("a\nb\nc", "", 1), "1 a\n:2 b\n3 c\n");
+    }
+}
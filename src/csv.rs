@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Csv — aligned column view of comma- and tab-separated files
+// ---------------------------------------------------------------------------
+//
+// The view only changes how lines are drawn: each field is padded to the
+// width of its column and delimiters become a bar between columns. The
+// buffer keeps the delimited text, so edits apply to it as usual. Quoted
+// fields may contain the delimiter; one that spans lines is shown line by
+// line.
+
+/// Cells between two columns: a space, the bar and a space.
+const GAP: usize = 3;
+const BAR: char = '│';
+
+/// The field delimiter of `filetype`, or None when it is not a table.
+pub fn delimiter(filetype: &str) -> Option<char> {
+    match filetype {
+        "csv" => Some(','),
+        "tsv" | "tab" => Some('\t'),
+        _ => None,
+    }
+}
+
+/// Byte ranges of the fields of `line`, without their delimiters. A
+/// delimiter between double quotes is part of the field.
+pub fn fields(line: &str, delim: char) -> Vec<Range<usize>> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            // An escaped quote ("") toggles twice
+            quoted = !quoted;
+        } else if c == delim && !quoted {
+            fields.push(start..i);
+            start = i + c.len_utf8();
+        }
+    }
+    fields.push(start..line.len());
+    fields
+}
+
+/// Index of the field holding byte offset `col`. The delimiter after a
+/// field counts as part of it.
+pub fn field_at(fields: &[Range<usize>], col: usize) -> usize {
+    fields
+        .iter()
+        .position(|f| col <= f.end)
+        .unwrap_or(fields.len().saturating_sub(1))
+}
+
+/// The layout of a table: its delimiter and the width of each column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    delim: char,
+    widths: Vec<usize>,
+}
+
+impl Columns {
+    /// Size each column to the widest of its fields in `lines`.
+    pub fn measure<'a>(delim: char, lines: impl IntoIterator<Item = &'a str>) -> Columns {
+        let mut widths: Vec<usize> = Vec::new();
+        for line in lines {
+            let line = without_cr(line);
+            for (i, field) in fields(line, delim).into_iter().enumerate() {
+                let width = line[field].chars().map(unicode::text_width).sum();
+                match widths.get_mut(i) {
+                    Some(w) => *w = (*w).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+        Columns { delim, widths }
+    }
+
+    pub fn delimiter(&self) -> char {
+        self.delim
+    }
+
+    /// Where each character of `line` is drawn: its byte offset, the
+    /// character shown (delimiters become a bar, control characters their
+    /// placeholders) and its column.
+    pub fn layout(&self, line: &str) -> Vec<(usize, char, usize)> {
+        let line = without_cr(line);
+        let mut cells = Vec::new();
+        // Column where the current field starts
+        let mut start = 0;
+        for (i, field) in fields(line, self.delim).into_iter().enumerate() {
+            let mut col = start;
+            for (at, ch) in line[field.clone()].char_indices() {
+                match unicode::placeholder(ch) {
+                    Some(shown) => {
+                        let cols = col..col + shown.len();
+                        cells.extend(
+                            cols.zip(shown.chars())
+                                .map(|(c, s)| (field.start + at, s, c)),
+                        );
+                        col += shown.len();
+                    }
+                    None => {
+                        cells.push((field.start + at, ch, col));
+                        col += unicode::cell_width(ch);
+                    }
+                }
+            }
+            let width = self.widths.get(i).copied().unwrap_or(0).max(col - start);
+            if field.end < line.len() {
+                cells.push((field.end, BAR, start + width + 1));
+            }
+            start += width + GAP;
+        }
+        cells
+    }
+}
+
+/// `line` without the `\r` of a CRLF line ending, which is not drawn.
+fn without_cr(line: &str) -> &str {
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(fields("a,bb,", ','), [0..1, 2..4, 5..5]);
+        assert_eq!(fields("", ','), vec![0..0]);
+        // Quoted delimiters and escaped quotes stay in the field
+        assert_eq!(
+            fields(r#""x,y","say ""hi""",z"#, ','),
+            [0..5, 6..18, 19..20]
+        );
+        assert_eq!(fields("a\tb,c", '\t'), [0..1, 2..5]);
+        let f = fields("a,bb,c", ',');
+        assert_eq!(field_at(&f, 0), 0);
+        assert_eq!(field_at(&f, 1), 0);
+        assert_eq!(field_at(&f, 3), 1);
+        assert_eq!(field_at(&f, 6), 2);
+    }
+
+    #[test]
+    fn test_layout() {
+        let lines = ["name,age", "Bo,7", "Émilie,42"];
+        let columns = Columns::measure(',', lines);
+        assert_eq!(columns.widths, [6, 3]);
+        let shown = |line: &str| {
+            let mut row = vec![' '; 20];
+            for (_, ch, col) in columns.layout(line) {
+                row[col] = ch;
+            }
+            row.into_iter().collect::<String>().trim_end().to_string()
+        };
+        assert_eq!(shown("name,age"), "name   │ age");
+        assert_eq!(shown("Bo,7"), "Bo     │ 7");
+        assert_eq!(shown("Émilie,42"), "Émilie │ 42");
+        // Offsets still point into the delimited text
+        let cells = columns.layout("Bo,7");
+        assert_eq!(cells, [(0, 'B', 0), (1, 'o', 1), (2, '│', 7), (3, '7', 9)]);
+        // Control characters take their placeholders' width; the \r of a
+        // CRLF line ending is not drawn
+        let columns = Columns::measure(',', ["a\x1b,b\r", "c,d\r"]);
+        assert_eq!(columns.widths, [3, 1]);
+        let shown: String = columns.layout("a\x1b,b\r").iter().map(|c| c.1).collect();
+        assert_eq!(shown, "a^[│b");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::buffer::Buffer;
+use crate::unicode::is_word_char;
 
 pub struct Cursor {
     pub line: usize,
@@ -6,6 +7,12 @@ pub struct Cursor {
     pub desired_col: usize,
 }
 
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Cursor {
     pub fn new() -> Cursor {
         Cursor {
@@ -24,8 +31,8 @@ impl Cursor {
 
     pub fn move_left(&mut self, buf: &Buffer) {
         if self.col > 0 {
-            let line_text = buf.get_line(self.line).unwrap_or_default();
-            self.col = prev_char_boundary(&line_text, self.col);
+            let start = buf.line_start(self.line).unwrap_or(0);
+            self.col = buf.floor_char_boundary(start + self.col - 1) - start;
         } else if self.line > 0 {
             self.line -= 1;
             self.col = line_byte_len(buf, self.line);
@@ -36,8 +43,8 @@ impl Cursor {
     pub fn move_right(&mut self, buf: &Buffer) {
         let line_len = line_byte_len(buf, self.line);
         if self.col < line_len {
-            let line_text = buf.get_line(self.line).unwrap_or_default();
-            self.col = next_char_boundary(&line_text, self.col);
+            let start = buf.line_start(self.line).unwrap_or(0);
+            self.col = buf.ceil_char_boundary(start + self.col + 1) - start;
         } else if self.line + 1 < buf.line_count() {
             self.line += 1;
             self.col = 0;
@@ -73,19 +80,9 @@ impl Cursor {
         }
 
         let line_text = buf.get_line(self.line).unwrap_or_default();
-        let bytes = line_text.as_bytes();
-        let mut pos = self.col;
-
-        // Skip non-word chars backwards
-        while pos > 0 && !is_word_byte(bytes[pos - 1]) {
-            pos -= 1;
-        }
-        // Skip word chars backwards
-        while pos > 0 && is_word_byte(bytes[pos - 1]) {
-            pos -= 1;
-        }
-
-        self.col = pos;
+        // Skip non-word chars, then word chars, backwards
+        let pos = skip_back(&line_text, self.col, |c| !is_word_char(c));
+        self.col = skip_back(&line_text, pos, is_word_char);
         self.desired_col = self.col;
     }
 
@@ -103,23 +100,48 @@ impl Cursor {
         }
 
         let line_text = buf.get_line(self.line).unwrap_or_default();
-        let bytes = line_text.as_bytes();
-        let len = bytes.len();
-        let mut pos = self.col;
+        // Skip word chars, then non-word chars, forward
+        let pos = skip_forward(&line_text, self.col, is_word_char);
+        self.col = skip_forward(&line_text, pos, |c| !is_word_char(c));
+        self.desired_col = self.col;
+    }
 
-        // Skip word chars forward
-        while pos < len && is_word_byte(bytes[pos]) {
-            pos += 1;
-        }
-        // Skip non-word chars forward
-        while pos < len && !is_word_byte(bytes[pos]) {
-            pos += 1;
+    /// Move to the last character of the current or next word (vi `e`),
+    /// crossing line boundaries.
+    pub fn move_word_end(&mut self, buf: &Buffer) {
+        let mut line = self.line;
+        let mut pos = buf
+            .get_line(line)
+            .map_or(0, |text| next_char_boundary(&text, self.col));
+        loop {
+            let line_text = buf.get_line(line).unwrap_or_default();
+            let len = line_text.len();
+
+            // Skip non-word chars forward
+            pos = skip_forward(&line_text, pos, |c| !is_word_char(c));
+            if pos < len {
+                // Step back from the end of the word onto its last char
+                let end = skip_forward(&line_text, pos, is_word_char);
+                self.line = line;
+                self.col = prev_char_boundary(&line_text, end);
+                break;
+            }
+            if line + 1 >= buf.line_count() {
+                self.line = line;
+                self.col = len;
+                break;
+            }
+            line += 1;
+            pos = 0;
         }
-
-        self.col = pos;
         self.desired_col = self.col;
     }
 
+    pub fn move_line_start(&mut self) {
+        self.col = 0;
+        self.desired_col = 0;
+    }
+
     pub fn move_home(&mut self, buf: &Buffer) {
         let line_text = buf.get_line(self.line).unwrap_or_default();
         let first_non_ws = line_text
@@ -185,7 +207,10 @@ impl Cursor {
 }
 
 fn line_byte_len(buf: &Buffer, line: usize) -> usize {
-    buf.get_line(line).map_or(0, |s| s.len())
+    match (buf.line_start(line), buf.line_end(line)) {
+        (Some(start), Some(end)) => end - start,
+        _ => 0,
+    }
 }
 
 fn prev_char_boundary(line: &str, byte_col: usize) -> usize {
@@ -216,8 +241,22 @@ fn next_char_boundary(line: &str, byte_col: usize) -> usize {
     pos
 }
 
-fn is_word_byte(b: u8) -> bool {
-    b.is_ascii_alphanumeric() || b == b'_'
+/// Start of the run of chars matching `pred` that ends at byte `pos`.
+fn skip_back(line: &str, pos: usize, pred: impl Fn(char) -> bool) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| pred(c))
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+/// End of the run of chars matching `pred` that starts at byte `pos`.
+fn skip_forward(line: &str, pos: usize, pred: impl Fn(char) -> bool) -> usize {
+    line[pos..]
+        .char_indices()
+        .find(|&(_, c)| !pred(c))
+        .map_or(line.len(), |(i, _)| pos + i)
 }
 
 #[cfg(test)]
@@ -489,6 +528,36 @@ mod tests {
         assert_eq!(c.col, 5);
     }
 
+    #[test]
+    fn test_word_motion_unicode() {
+        // Accented letters, including a decomposed é, stay inside words
+        let buf = buf_with("café na\u{0308}ive über_1 日本語、テスト");
+        let mut c = Cursor::new();
+        let mut stops = Vec::new();
+        for _ in 0..5 {
+            c.move_word_right(&buf);
+            stops.push(c.col);
+        }
+        let text = buf.text();
+        let words: Vec<&str> = stops.iter().map(|&col| &text[col..]).collect();
+        assert!(words[0].starts_with("na\u{0308}ive"));
+        assert!(words[1].starts_with("über_1"));
+        assert!(words[2].starts_with("日本語"));
+        assert!(words[3].starts_with("テスト"));
+        assert_eq!(stops[4], text.len());
+
+        c.move_word_left(&buf);
+        assert!(text[c.col..].starts_with("テスト"));
+        c.move_word_left(&buf);
+        assert!(text[c.col..].starts_with("日本語"));
+
+        let mut c = Cursor::new();
+        c.move_word_end(&buf);
+        assert!(text[c.col..].starts_with("é "));
+        c.move_word_end(&buf);
+        assert!(text[c.col..].starts_with("e "));
+    }
+
     #[test]
     fn test_move_word_left_wraps_line() {
         let buf = buf_with("hello\nworld");
@@ -513,4 +582,19 @@ mod tests {
         assert_eq!(c.line, 1);
         assert_eq!(c.col, 0);
     }
+
+    #[test]
+    fn test_move_word_end() {
+        let buf = buf_with(
+            "foo bar
+  baz",
+        );
+        let mut c = Cursor::new();
+        c.move_word_end(&buf);
+        assert_eq!((c.line, c.col), (0, 2));
+        c.move_word_end(&buf);
+        assert_eq!((c.line, c.col), (0, 6));
+        c.move_word_end(&buf); // crosses into the next line
+        assert_eq!((c.line, c.col), (1, 4));
+    }
 }
@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+use crate::lsp::Severity;
+use crate::quickfix::QuickfixEntry;
+
+// ---------------------------------------------------------------------------
+// Diagnostics — everything the language servers and the build reported
+// ---------------------------------------------------------------------------
+
+/// One diagnostic, from either source. `line` and `col` are 0-based; `col`
+/// counts characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Item {
+    /// An item for a build output location. Its severity comes from the
+    /// message's leading word; unknown ones count as errors.
+    pub fn from_build(entry: &QuickfixEntry) -> Item {
+        let word = entry.message.trim_start().to_lowercase();
+        let severity = if word.starts_with("warning") {
+            Severity::Warning
+        } else if word.starts_with("note") || word.starts_with("help") {
+            Severity::Info
+        } else {
+            Severity::Error
+        };
+        Item {
+            path: entry.path.clone(),
+            line: entry.line - 1,
+            col: entry.col - 1,
+            severity,
+            message: entry.message.clone(),
+        }
+    }
+}
+
+/// Sort `items` by file and position and drop exact duplicates, such as a
+/// build error the language server reports too.
+pub fn sort(items: &mut Vec<Item>) {
+    items.sort_by(|a, b| {
+        (&a.path, a.line, a.col, a.severity).cmp(&(&b.path, b.line, b.col, b.severity))
+    });
+    items.dedup();
+}
+
+/// The item after (or before) position `here` of `path` in the sorted
+/// `items`, wrapping around the ends.
+pub fn step<'a>(
+    items: &'a [Item],
+    path: Option<&Path>,
+    here: (usize, usize),
+    forward: bool,
+) -> Option<&'a Item> {
+    let key = |item: &'a Item| (Some(item.path.as_path()), item.line, item.col);
+    let here = (path, here.0, here.1);
+    if forward {
+        items.iter().find(|item| key(item) > here).or(items.first())
+    } else {
+        items
+            .iter()
+            .rev()
+            .find(|item| key(item) < here)
+            .or(items.last())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Panel filters
+// ---------------------------------------------------------------------------
+
+/// Which items the panel lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// Only this file's items.
+    pub path: Option<PathBuf>,
+    /// Only items at least this severe.
+    pub severity: Severity,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            path: None,
+            severity: Severity::Hint,
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, item: &Item) -> bool {
+        item.severity <= self.severity && self.path.as_ref().is_none_or(|p| *p == item.path)
+    }
+
+    /// Step the severity threshold: all, then warnings and errors, then
+    /// errors only, then all again.
+    pub fn cycle_severity(&mut self) {
+        self.severity = match self.severity {
+            Severity::Error => Severity::Hint,
+            Severity::Warning => Severity::Error,
+            _ => Severity::Warning,
+        };
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(path: &str, line: usize, severity: Severity) -> Item {
+        Item {
+            path: PathBuf::from(path),
+            line,
+            col: 0,
+            severity,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_from_build() {
+        let entry = |message: &str| QuickfixEntry {
+            path: PathBuf::from("a.c"),
+            line: 3,
+            col: 5,
+            message: message.to_string(),
+        };
+        let warning = Item::from_build(&entry("warning: unused"));
+        assert_eq!((warning.line, warning.col), (2, 4));
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(
+            Item::from_build(&entry("note: here")).severity,
+            Severity::Info
+        );
+        assert_eq!(
+            Item::from_build(&entry("expected ';'")).severity,
+            Severity::Error
+        );
+    }
+
+    #[test]
+    fn test_sort_and_step() {
+        let mut items = vec![
+            item("b.rs", 1, Severity::Error),
+            item("a.rs", 7, Severity::Warning),
+            item("a.rs", 2, Severity::Error),
+            item("b.rs", 1, Severity::Error),
+        ];
+        sort(&mut items);
+        assert_eq!(items.len(), 3);
+        assert_eq!((items[0].line, items[1].line), (2, 7));
+
+        let a = Some(Path::new("a.rs"));
+        let b = Some(Path::new("b.rs"));
+        assert_eq!(step(&items, a, (2, 0), true), Some(&items[1]));
+        assert_eq!(step(&items, a, (2, 0), false), Some(&items[2]));
+        // Past the last item: wraps to the first
+        assert_eq!(step(&items, b, (5, 0), true), Some(&items[0]));
+        assert_eq!(step(&[], b, (5, 0), true), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut filter = Filter::default();
+        let hint = item("a.rs", 0, Severity::Hint);
+        let warning = item("b.rs", 0, Severity::Warning);
+        assert!(filter.matches(&hint) && filter.matches(&warning));
+        filter.cycle_severity();
+        assert!(!filter.matches(&hint) && filter.matches(&warning));
+        filter.cycle_severity();
+        assert!(!filter.matches(&warning));
+        filter.cycle_severity();
+        assert_eq!(filter.severity, Severity::Hint);
+        filter.path = Some(PathBuf::from("a.rs"));
+        assert!(filter.matches(&hint) && !filter.matches(&warning));
+    }
+}
@@ -0,0 +1,191 @@
+// ---------------------------------------------------------------------------
+// Diff — the fewest line changes that turn one text into another
+// ---------------------------------------------------------------------------
+//
+// Myers' O(ND) algorithm over the lines left after trimming the common
+// start and end, which is all of the work when a formatter touches a few
+// places in a large file.
+
+/// Old lines `old.0..old.0 + old.1` replaced by new lines
+/// `new.0..new.0 + new.1`. Either side may be empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub old: (usize, usize),
+    pub new: (usize, usize),
+}
+
+/// The changes that turn `old` into `new`, in order.
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // The changes are the gaps between matched lines, with the start and
+    // the end as matches too
+    let mut matches = vec![(0, 0)];
+    matches.extend(common_lines(a, b).into_iter().map(|(x, y)| (x + 1, y + 1)));
+    matches.push((a.len() + 1, b.len() + 1));
+    matches
+        .windows(2)
+        .filter(|w| w[1] != (w[0].0 + 1, w[0].1 + 1))
+        .map(|w| Change {
+            old: (prefix + w[0].0, w[1].0 - w[0].0 - 1),
+            new: (prefix + w[0].1, w[1].1 - w[0].1 - 1),
+        })
+        .collect()
+}
+
+/// Pairs of indexes of equal lines in `a` and `b` forming a longest common
+/// subsequence, in order.
+fn common_lines<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let offset = n + m + 1;
+    let index = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // The furthest x on each diagonal after each number of edits
+    let mut trace = Vec::new();
+    'search: for d in 0..=n + m {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from the end, collecting the diagonal (matching) steps
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let (d, k) = (d as isize, x - y);
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        if d == 0 {
+            break;
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// Where old line `line` ends up after `changes`: lines in a change keep
+/// their place within it as far as the new lines reach.
+pub fn map_line(changes: &[Change], line: usize) -> usize {
+    let mut shift = 0isize;
+    for change in changes {
+        let (start, len) = change.old;
+        if line < start {
+            break;
+        }
+        if line < start + len {
+            let within = (line - start).min(change.new.1.saturating_sub(1));
+            return change.new.0 + within;
+        }
+        shift += change.new.1 as isize - len as isize;
+    }
+    line.saturating_add_signed(shift)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(old: &[&str], new: &[&str], changes: &[Change]) -> Vec<String> {
+        let mut out: Vec<String> = old.iter().map(|s| s.to_string()).collect();
+        for c in changes.iter().rev() {
+            let lines = new[c.new.0..c.new.0 + c.new.1]
+                .iter()
+                .map(|s| s.to_string());
+            out.splice(c.old.0..c.old.0 + c.old.1, lines);
+        }
+        out
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let old = ["a", "b", "c", "d", "e", "f"];
+        let new = ["a", "x", "c", "d", "f", "g"];
+        let changes = diff_lines(&old, &new);
+        assert_eq!(
+            changes,
+            [
+                Change {
+                    old: (1, 1),
+                    new: (1, 1)
+                },
+                Change {
+                    old: (4, 1),
+                    new: (4, 0)
+                },
+                Change {
+                    old: (6, 0),
+                    new: (5, 1)
+                },
+            ]
+        );
+        assert_eq!(apply(&old, &new, &changes), new);
+        assert!(diff_lines(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_diff_lines_round_trip() {
+        let cases: [(&[&str], &[&str]); 5] = [
+            (&[], &["a"]),
+            (&["a"], &[]),
+            (&["a", "b", "a", "b"], &["b", "a", "b", "a"]),
+            (&["x", "y", "z"], &["1", "2"]),
+            (
+                &["fn f() {", "x", "}", "", "fn g() {}"],
+                &["fn f() {", "    x", "}", "fn g() {}"],
+            ),
+        ];
+        for (old, new) in cases {
+            assert_eq!(apply(old, new, &diff_lines(old, new)), new, "{:?}", old);
+        }
+    }
+
+    #[test]
+    fn test_map_line() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "y", "z", "d"];
+        let changes = diff_lines(&old, &new);
+        assert_eq!(map_line(&changes, 0), 0);
+        assert_eq!(map_line(&changes, 2), 2);
+        // After the changed lines: shifted by the growth
+        assert_eq!(map_line(&changes, 3), 4);
+        // A deleted line goes to the line that now follows the deletion
+        assert_eq!(map_line(&changes, 4), 5);
+    }
+}
@@ -0,0 +1,191 @@
+// ---------------------------------------------------------------------------
+// Digraphs — two typed characters that stand for one (a' → á)
+// ---------------------------------------------------------------------------
+//
+// The built-in table follows vim's RFC 1345 names for the common cases: a
+// letter then its accent (' acute, ! grave, > circumflex, ? tilde, :
+// diaeresis), plus currency, punctuation, arrows and math symbols. Users add
+// or override entries with "digraphs" in config.json.
+
+const DIGRAPHS: &[(&str, char)] = &[
+    // Acute
+    ("a'", 'á'),
+    ("e'", 'é'),
+    ("i'", 'í'),
+    ("o'", 'ó'),
+    ("u'", 'ú'),
+    ("y'", 'ý'),
+    ("c'", 'ć'),
+    ("n'", 'ń'),
+    ("s'", 'ś'),
+    ("z'", 'ź'),
+    ("A'", 'Á'),
+    ("E'", 'É'),
+    ("I'", 'Í'),
+    ("O'", 'Ó'),
+    ("U'", 'Ú'),
+    ("Y'", 'Ý'),
+    // Grave
+    ("a!", 'à'),
+    ("e!", 'è'),
+    ("i!", 'ì'),
+    ("o!", 'ò'),
+    ("u!", 'ù'),
+    ("A!", 'À'),
+    ("E!", 'È'),
+    ("I!", 'Ì'),
+    ("O!", 'Ò'),
+    ("U!", 'Ù'),
+    // Circumflex
+    ("a>", 'â'),
+    ("e>", 'ê'),
+    ("i>", 'î'),
+    ("o>", 'ô'),
+    ("u>", 'û'),
+    ("A>", 'Â'),
+    ("E>", 'Ê'),
+    ("I>", 'Î'),
+    ("O>", 'Ô'),
+    ("U>", 'Û'),
+    // Tilde
+    ("a?", 'ã'),
+    ("o?", 'õ'),
+    ("n?", 'ñ'),
+    ("A?", 'Ã'),
+    ("O?", 'Õ'),
+    ("N?", 'Ñ'),
+    // Diaeresis
+    ("a:", 'ä'),
+    ("e:", 'ë'),
+    ("i:", 'ï'),
+    ("o:", 'ö'),
+    ("u:", 'ü'),
+    ("y:", 'ÿ'),
+    ("A:", 'Ä'),
+    ("E:", 'Ë'),
+    ("I:", 'Ï'),
+    ("O:", 'Ö'),
+    ("U:", 'Ü'),
+    // Other letters
+    ("c,", 'ç'),
+    ("C,", 'Ç'),
+    ("aa", 'å'),
+    ("AA", 'Å'),
+    ("ae", 'æ'),
+    ("AE", 'Æ'),
+    ("o/", 'ø'),
+    ("O/", 'Ø'),
+    ("oe", 'œ'),
+    ("OE", 'Œ'),
+    ("ss", 'ß'),
+    ("c<", 'č'),
+    ("s<", 'š'),
+    ("z<", 'ž'),
+    ("C<", 'Č'),
+    ("S<", 'Š'),
+    ("Z<", 'Ž'),
+    // Currency
+    ("Eu", '€'),
+    ("Pd", '£'),
+    ("Ye", '¥'),
+    ("Ct", '¢'),
+    // Punctuation
+    ("!I", '¡'),
+    ("?I", '¿'),
+    ("<<", '«'),
+    (">>", '»'),
+    ("-N", '–'),
+    ("-M", '—'),
+    ("'6", '‘'),
+    ("'9", '’'),
+    ("\"6", '“'),
+    ("\"9", '”'),
+    (".:", '…'),
+    ("SE", '§'),
+    ("PI", '¶'),
+    ("Co", '©'),
+    ("Rg", '®'),
+    ("TM", '™'),
+    ("DG", '°'),
+    // Arrows
+    ("<-", '←'),
+    ("->", '→'),
+    ("-!", '↑'),
+    ("-v", '↓'),
+    ("<>", '↔'),
+    ("=>", '⇒'),
+    // Math
+    ("+-", '±'),
+    ("*X", '×'),
+    ("-:", '÷'),
+    ("!=", '≠'),
+    ("=<", '≤'),
+    (">=", '≥'),
+    ("?2", '≈'),
+    ("00", '∞'),
+    ("My", 'µ'),
+    ("1S", '¹'),
+    ("2S", '²'),
+    ("3S", '³'),
+    ("12", '½'),
+    ("14", '¼'),
+    ("34", '¾'),
+    // Greek
+    ("a*", 'α'),
+    ("b*", 'β'),
+    ("g*", 'γ'),
+    ("d*", 'δ'),
+    ("l*", 'λ'),
+    ("p*", 'π'),
+    ("s*", 'σ'),
+    ("D*", 'Δ'),
+    ("W*", 'Ω'),
+];
+
+/// The character for the digraph `first` `second`, looking in `user`
+/// entries before the built-in table. Either order of the two characters
+/// matches, so `'a` works as well as `a'`.
+pub fn lookup(user: &[(String, char)], first: char, second: char) -> Option<char> {
+    let find = |a: char, b: char| {
+        let is = |name: &str| name.chars().eq([a, b]);
+        user.iter()
+            .find(|(name, _)| is(name))
+            .map(|&(_, c)| c)
+            .or_else(|| DIGRAPHS.iter().find(|(name, _)| is(name)).map(|&(_, c)| c))
+    };
+    find(first, second).or_else(|| find(second, first))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digraphs_unique() {
+        for (i, (name, _)) in DIGRAPHS.iter().enumerate() {
+            assert_eq!(name.chars().count(), 2, "{}", name);
+            assert!(
+                DIGRAPHS[i + 1..].iter().all(|(n, _)| n != name),
+                "duplicate digraph {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(&[], 'a', '\''), Some('á'));
+        assert_eq!(lookup(&[], '\'', 'a'), Some('á'));
+        assert_eq!(lookup(&[], '-', '>'), Some('→'));
+        assert_eq!(lookup(&[], 'q', 'q'), None);
+        // User entries add to and override the table
+        let user = [("qq".to_string(), 'ǫ'), ("a'".to_string(), 'ą')];
+        assert_eq!(lookup(&user, 'q', 'q'), Some('ǫ'));
+        assert_eq!(lookup(&user, 'a', '\''), Some('ą'));
+        assert_eq!(lookup(&user, 'e', '\''), Some('é'));
+    }
+}
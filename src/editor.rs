@@ -1,11 +1,66 @@
-use std::path::Path;
-
-use crate::buffer::Buffer;
+use std::borrow::Cow;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::bidi;
+use crate::buffer::{self, Buffer, Edit};
+use crate::command::{self, Command, Keymap};
+use crate::comment;
+use crate::complete::{self, Completion, CompletionItem};
+use crate::config::Config;
+use crate::csv::{self, Columns};
 use crate::cursor::Cursor;
+use crate::diagnostics::{self, Item};
+use crate::diff;
+use crate::digraph;
+use crate::export;
+use crate::file_index::FileIndex;
+use crate::filetype;
+use crate::follow::{Follow, Growth};
+use crate::git::{self, BlameLine, GitGutter, LineChange};
 use crate::input::{self, Event, Key, KeyEvent, MouseButton};
-use crate::render::{Color, Screen};
-use crate::terminal::{self, ColorMode, Terminal};
+use crate::jobs::{JobId, Jobs};
+use crate::json::{self, JsonValue};
+use crate::jumplist::{Jump, JumpList};
+use crate::link::{self, Target};
+use crate::lockfile::{self, FileLock, Owner};
+use crate::lsp::{
+    self, Diagnostic, Location, LspClient, LspEvent, PositionEncoding, Severity, SignatureHelp,
+};
+use crate::pairs;
+use crate::patch::{self, LineKind};
+use crate::plugin::{self, Plugins, Request};
+use crate::profile::{Phase, Profile};
+use crate::prompt::Prompt;
+use crate::quickfix::{self, QuickfixEntry, QuickfixList};
+use crate::reflow;
+use crate::rename::{self, Hunk};
+use crate::render::{Cell, Color, Screen};
+use crate::script::{self, Script, Value};
+use crate::scrollbar::{self, SideColumn};
+use crate::session::{self, Session, SessionDocument};
+use crate::shell;
+use crate::snippet::{self, Snippet, SnippetSession};
+use crate::spell::{self, Dictionary};
+use crate::stats::{Counts, TextStats};
+use crate::statusline::{self, Segment, SelectionStats};
+use crate::strings::{self, Strings};
+use crate::symbols;
+use crate::tabs::TabStops;
+use crate::tags::{self, TagAddress};
+use crate::terminal::{self, ColorMode, Terminal, TerminalBackend};
+use crate::theme::{self, Background, Theme};
+use crate::transform::{self, Case, LineSwap, SortOptions};
+use crate::trash::{self, Trashed};
+use crate::ui::{self, Dialog, KeyResult, ListState, Menu, Rect, Style, TextPanel};
 use crate::undo::{CursorState, GroupContext, Operation, UndoStack};
+use crate::unicode;
+use crate::vi::{self, ViState};
+use crate::virtual_text::{self, Annotation, Placement, Source, VirtualText};
 
 // ---------------------------------------------------------------------------
 // Message types
@@ -24,9 +79,56 @@ enum MessageType {
 
 enum PromptAction {
     OpenFile,
+    InsertFile,
+    WriteSelection,
+    Export,
+    RunCommand,
+    ExCommand, // vi `:` command line
+    InsertChar,
     Find,
     Replace,
     ReplaceWith(String),
+    /// Replace in open buffers: asks the pattern, the replacement, then
+    /// which buffers.
+    ReplaceInBuffers,
+    ReplaceInBuffersWith(String),
+    ReplaceInBuffersIn(String, String),
+    /// Rename across the project: asks the text, then its new name.
+    RenameInProject,
+    RenameInProjectTo(String),
+    /// Replace one match at a time: the pattern, the replacement and how
+    /// many were replaced so far.
+    ConfirmReplace {
+        pattern: String,
+        replacement: String,
+        replaced: usize,
+    },
+    JumpToSymbol,
+    FindFile,
+    SetFiletype,
+    /// Write over an existing file, for a write prompt's action.
+    Overwrite(Box<PromptAction>, String),
+    /// Move a file to the trash.
+    ConfirmTrash(PathBuf),
+    /// Keep a file another instance is editing read-only.
+    OpenLocked(PathBuf, Owner),
+}
+
+/// Where the view-placing commands put the cursor line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ViewPlace {
+    Center,
+    Top,
+    Bottom,
+}
+
+/// A surround command waiting for the pair character(s) to be typed.
+#[derive(Clone, Copy)]
+enum PendingPair {
+    Surround,
+    Delete,
+    Change,
+    ChangeTo(char),
 }
 
 // ---------------------------------------------------------------------------
@@ -37,15 +139,49 @@ struct SearchState {
     pattern: String,
     matches: Vec<(usize, usize)>, // (byte_start, byte_end)
     current: Option<usize>,       // index into matches
+    version: u64,                 // buffer version the matches are for
+    scanned: Option<usize>,       // where an unfinished search goes on
+    capped: bool,                 // stopped at MAX_MATCHES
 }
 
-struct Prompt {
-    label: String,
-    input: String,
-    cursor_pos: usize, // byte offset within input
-    action: PromptAction,
+impl SearchState {
+    fn new(pattern: &str, matches: Vec<(usize, usize)>, version: u64) -> Self {
+        let capped = matches.len() > MAX_MATCHES;
+        let mut matches = matches;
+        matches.truncate(MAX_MATCHES);
+        SearchState {
+            pattern: pattern.to_string(),
+            matches,
+            current: None,
+            version,
+            scanned: None,
+            capped,
+        }
+    }
+
+    /// The match count for messages, `100000+` when capped.
+    fn total(&self) -> String {
+        let plus = if self.capped { "+" } else { "" };
+        format!("{}{}", self.matches.len(), plus)
+    }
 }
 
+/// Bytes searched per frame; a larger buffer is searched over several.
+const SEARCH_CHUNK: usize = 1 << 20;
+/// Matches kept at most. A search stops collecting at this many.
+const MAX_MATCHES: usize = 100_000;
+/// Lines longer than this many bytes are only read and laid out as far as
+/// needed: to the right edge of the view when drawn, to the cursor when
+/// placing it. The work then grows with how far along the line the view
+/// is rather than with its length.
+const LONG_LINE: usize = 64 * 1024;
+/// Bytes a character takes at most. Each takes a column or more, so the
+/// first `n` columns of a line are drawn from its first `n * 4` bytes.
+const CHAR_MAX_BYTES: usize = 4;
+/// Matches per text row in view, on average, above which all but the
+/// current one are drawn dimmed.
+const DIM_MATCHES_PER_ROW: usize = 2;
+
 // ---------------------------------------------------------------------------
 // Selection
 // ---------------------------------------------------------------------------
@@ -56,6 +192,253 @@ struct Selection {
     head: usize,   // byte offset at cursor end
 }
 
+// ---------------------------------------------------------------------------
+// Document — per-buffer state parked while another buffer is active
+// ---------------------------------------------------------------------------
+
+/// The editor keeps the active buffer's state in its own fields; every other
+/// open buffer lives here until it is swapped back in (see `swap_document`).
+/// The active slot itself only holds an empty placeholder.
+struct Document {
+    /// Stays with the slot while its fields are swapped in and out, and is
+    /// never reused, so a closed buffer's slot is not mistaken for it.
+    id: u64,
+    buffer: Buffer,
+    cursor: Cursor,
+    undo_stack: UndoStack,
+    selection: Option<Selection>,
+    scroll_row: usize,
+    scroll_col: usize,
+    git_gutter: Option<GitGutter>,
+    blame: Option<Vec<BlameLine>>,
+    follow: Option<Follow>,
+    column_view: Option<Columns>,
+    virtual_text: VirtualText,
+    text_stats: TextStats,
+}
+
+impl Document {
+    fn new(buffer: Buffer) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Document {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            buffer,
+            cursor: Cursor::new(),
+            undo_stack: UndoStack::new(),
+            selection: None,
+            scroll_row: 0,
+            scroll_col: 0,
+            git_gutter: None,
+            blame: None,
+            follow: None,
+            column_view: None,
+            virtual_text: VirtualText::default(),
+            text_stats: TextStats::default(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Symbol picker — fuzzy list shown above the prompt
+// ---------------------------------------------------------------------------
+
+enum SymbolTarget {
+    Line(usize),              // in the active buffer
+    Tag(PathBuf, TagAddress), // from a tags file
+    Char(char),               // inserted by the character picker
+    File(PathBuf),            // opened by the file finder
+}
+
+struct PickerEntry {
+    name: String,
+    detail: String, // kind and location, shown after the name
+    target: SymbolTarget,
+}
+
+struct SymbolPicker {
+    entries: Vec<PickerEntry>,
+    matches: Vec<usize>, // indices into entries, best first
+    list: ListState,     // selection within matches
+}
+
+impl SymbolPicker {
+    fn new(entries: Vec<PickerEntry>) -> Self {
+        let mut picker = SymbolPicker {
+            entries,
+            matches: Vec::new(),
+            list: ListState::default(),
+        };
+        picker.filter("");
+        picker
+    }
+
+    /// Keep the entries matching `query`, best score first.
+    fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(i32, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| symbols::fuzzy_score(query, &e.name).map(|s| (s, i)))
+            .collect();
+        if !query.is_empty() {
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        }
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.list = ListState::default();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Diagnostics panel — every diagnostic, listed below the text
+// ---------------------------------------------------------------------------
+
+struct DiagnosticsPanel {
+    filter: diagnostics::Filter,
+    items: Vec<Item>, // the ones the filter lets through, refreshed each frame
+    total: usize,     // before filtering
+    list: ListState,
+    focused: bool, // takes the keys
+}
+
+/// The preview of a project rename: every line it would change, each
+/// accepted or rejected before anything is written.
+struct RenamePanel {
+    pattern: String,
+    replacement: String,
+    hunks: Vec<Hunk>, // by file, then line
+    list: ListState,
+}
+
+/// A row of the buffer options panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BufferOption {
+    TextWidth,
+    TabWidth,
+    Filetype,
+    LineEnding,
+    Encoding,
+    ReadOnly,
+}
+
+const BUFFER_OPTIONS: [BufferOption; 6] = [
+    BufferOption::TextWidth,
+    BufferOption::TabWidth,
+    BufferOption::Filetype,
+    BufferOption::LineEnding,
+    BufferOption::Encoding,
+    BufferOption::ReadOnly,
+];
+
+/// The active buffer's options, changed in place. Values are read from
+/// the buffer each frame, so the panel always shows what is in effect.
+struct OptionsPanel {
+    list: ListState,
+    typed: Option<String>, // digits entered for the selected number
+}
+
+const TAB_WIDTHS: RangeInclusive<usize> = 1..=16;
+const TEXT_WIDTHS: RangeInclusive<usize> = 10..=999;
+
+const OUTPUT_BUFFER_NAME: &str = "[Output]";
+const MESSAGES_BUFFER_NAME: &str = "[Messages]";
+const HELP_BUFFER_NAME: &str = "[Help]";
+const PROFILE_BUFFER_NAME: &str = "[Profile]";
+
+/// Oldest messages are dropped from the log beyond this many.
+const MAX_LOGGED_MESSAGES: usize = 500;
+
+/// A mouse click at least this many lines away is recorded as a jump.
+const FAR_CLICK_LINES: usize = 10;
+
+/// Most entries the symbol picker lists at once.
+const PICKER_MAX_ROWS: usize = 10;
+/// How often idle upkeep looks for files come or gone, once the file
+/// finder has been used.
+const FILE_INDEX_POLL: Duration = Duration::from_secs(2);
+
+/// Entry rows shown in the quickfix panel until it is resized.
+const QUICKFIX_MAX_ROWS: usize = 6;
+
+/// Text rows kept above the quickfix panel when dragging it taller.
+const MIN_TEXT_ROWS: usize = 3;
+
+/// Intermediate frames of a smooth page scroll, and the pause after each.
+const SCROLL_STEPS: usize = 6;
+const SCROLL_FRAME: Duration = Duration::from_millis(10);
+
+/// Lines looked at above and below a line to line up its elastic tabs.
+const ELASTIC_TAB_REACH: usize = 100;
+
+/// Width of the blame column: "hash8 author___ age_ ", the age right
+/// aligned in four columns.
+const BLAME_WIDTH: usize = 24;
+const BLAME_AUTHOR_WIDTH: usize = 9;
+
+/// What a background job hands back to the main loop. Each result names
+/// the file it describes, which may no longer be the active one.
+enum JobResult {
+    Blame(PathBuf, Result<Vec<BlameLine>, String>),
+    GitGutter(PathBuf, Option<GitGutter>),
+}
+
+/// Everything that affects all text rows at once. When it differs from
+/// the last frame, every row is rebuilt.
+#[derive(Clone, PartialEq)]
+struct ViewState {
+    size: (usize, usize), // screen width, text rows
+    scroll_col: usize,
+    gutter_width: usize,
+    text_right: usize,
+    blame_width: usize,
+    sign_width: usize,
+    ruler: Option<usize>, // screen column of the color column
+    ruler_bg: Color,
+    line_bg: Color,
+    selection_bg: Color,
+    matches: MatchStyle,
+    columns: Option<Columns>, // column view layout
+    patch: bool,              // lines colored by what they do in a diff
+}
+
+/// How search matches are drawn: the text and backgrounds on them, the
+/// dimmed background that replaces `bg` when the view is full of matches,
+/// and whether all but the current one are only underlined.
+#[derive(Clone, PartialEq)]
+struct MatchStyle {
+    text: Color,
+    bg: Color,
+    current_bg: Color,
+    dim_bg: Option<Color>,
+    underline: bool,
+}
+
+/// What the message line shows: its row, the prompt label, input and
+/// scroll, and the message.
+type MessageLine = (
+    usize,
+    Option<(String, String, usize)>,
+    Option<String>,
+    MessageType,
+);
+
+/// What one text row shows. A row whose state equals the last frame's
+/// keeps its cells; byte offsets are relative to the line start. The text
+/// itself is only hashed, so keeping a row copies none of it.
+#[derive(Clone, Default, PartialEq)]
+struct RowState {
+    line: Option<usize>, // None past the end of the file
+    text_hash: u64,
+    fg: Option<Color>, // of the whole line, as for patch lines
+    cursor_line: bool,
+    sign: Option<(char, Color)>,
+    selection: Option<(usize, usize)>, // may end one past the text (newline)
+    matches: Vec<(usize, usize, bool)>, // search matches, current or not
+    underlines: Vec<(usize, usize)>,
+    misspelled: Vec<(usize, usize)>,
+    annotations: Vec<Annotation>,
+    tabs: TabStops,
+}
+
 // ---------------------------------------------------------------------------
 // Editor
 // ---------------------------------------------------------------------------
@@ -63,31 +446,69 @@ struct Selection {
 pub struct Editor {
     buffer: Buffer,
     cursor: Cursor,
-    terminal: Terminal,
+    // None when embedded in another program, which owns the terminal
+    terminal: Option<Terminal>,
     screen: Screen,
     color_mode: ColorMode,
+    config: Config,
+    // Whether the terminal's background is light, picking the theme variant
+    light_background: bool,
+    keymap: Keymap,
 
     // Viewport
     scroll_row: usize,
     scroll_col: usize,
+    // Last recenter (place, cursor line, scroll row), to cycle on repeats
+    last_recenter: Option<(ViewPlace, usize, usize)>,
+    // Screen column kept while moving up and down, with the cursor line and
+    // column it left; moving the cursor any other way starts afresh
+    vertical_goal: Option<(usize, usize, usize)>,
 
     // UI layout
     gutter_width: usize,
+    git_gutter: Option<GitGutter>,
     status_height: usize,
-
-    // Transient message
+    side_column_drag: bool,     // dragging in the scrollbar/minimap
+    gutter_drag: Option<usize>, // line where a gutter drag began
+    text_drag: Option<usize>,   // drop point while dragging the selection
+    panel_drag: bool,           // dragging the quickfix panel header
+
+    // Blame column (shared toggle, per-buffer annotations)
+    blame_visible: bool,
+    blame: Option<Vec<BlameLine>>,
+    blame_job: Option<JobId>,
+    gutter_job: Option<JobId>,
+    jobs: Jobs<JobResult>,
+    selection_stats: SelectionStats,
+    // What the last frame drew, to skip rows that did not change
+    drawn_view: Option<ViewState>,
+    drawn_rows: Vec<Option<RowState>>,
+    drawn_status: Option<(usize, String, String)>,
+    drawn_message: Option<MessageLine>,
+    overlay_drawn: bool,
+    // The software cursor: whether it is in the shown half of its blink
+    // and since when, and the cell it was drawn over
+    cursor_blink: (bool, Instant),
+    drawn_cursor: Option<(usize, usize, Cell)>,
+
+    // Transient message (errors stay until dismissed), and the log of all
     message: Option<String>,
     message_type: MessageType,
+    message_log: Vec<(SystemTime, MessageType, String)>,
 
-    // Quit state
-    quit_confirm: bool,
+    // Open buffers (the active one's slot is a placeholder)
+    documents: Vec<Document>,
+    active: usize,
+    /// The documents the last replace-in-buffers changed, by id, with the
+    /// buffer version it left, for undoing it in all of them.
+    buffers_replaced: Vec<(u64, u64)>,
 
     // Selection & clipboard
     selection: Option<Selection>,
     clipboard: String,
 
-    // Active prompt (mini-prompt for Open, Save As, etc.)
-    prompt: Option<Prompt>,
+    // Active prompt (mini-prompt for Open, Find, etc.) and what it is for
+    prompt: Option<(Prompt, PromptAction)>,
 
     // Undo/redo
     undo_stack: UndoStack,
@@ -95,107 +516,566 @@ pub struct Editor {
     // Search
     search: Option<SearchState>,
 
+    // Build diagnostics
+    quickfix: Option<QuickfixList>,
+    quickfix_visible: bool,
+    quickfix_rows: usize, // entry rows, set by dragging the panel header
+    // Shown instead of the quickfix panel while open
+    diagnostics_panel: Option<DiagnosticsPanel>,
+    rename_panel: Option<RenamePanel>,
+
+    // Snippets, loaded per filetype on first use
+    snippets: Vec<(String, Vec<Snippet>)>,
+    snippet_session: Option<SnippetSession>,
+
+    pending_pair: Option<PendingPair>,
+    // Characters typed so far after the digraph key
+    pending_digraph: Option<String>,
+
+    // Completion popup (buffer words and language server items)
+    completion: Option<Completion>,
+    lsp_completion_pending: bool, // request completion once typing pauses
+    completion_request: Option<usize>, // cursor offset the request was sent for
+    signature: Option<(SignatureHelp, usize)>, // (help, line it was requested on)
+
+    // Language servers by file extension (None once a server failed or exited)
+    lsp_clients: Vec<(String, Option<LspClient>)>,
+    diagnostics: Vec<(PathBuf, Vec<Diagnostic>)>,
+    // The buffer and version inlay hints were last asked for
+    inlay_hints: Option<(PathBuf, u64)>,
+    popup: Option<TextPanel>,
+    context_menu: Option<(Menu, Vec<Command>)>,
+    options_panel: Option<OptionsPanel>,
+    // Confirmation dialog and the command it confirms
+    dialog: Option<(Dialog, Command)>,
+    jumps: JumpList,
+    symbol_picker: Option<SymbolPicker>,
+    // Project files for the file finder, and when they were last checked
+    file_index: Option<(FileIndex, Instant)>,
+    // Files moved to the trash this session, newest last
+    trashed: Vec<Trashed>,
+    // Lock files of the files open for editing
+    locks: Vec<FileLock>,
+    // The session `--continue` restored, until nothing in it is unsaved
+    continued_session: Option<PathBuf>,
+
+    // Spell checking (dictionary loaded on first use)
+    spell_enabled: bool,
+    dictionary: Option<Dictionary>,
+    spell_errors: Vec<(usize, usize, usize)>, // (line, start, end) sorted, byte columns
+    spell_version: Option<u64>,               // buffer version spell_errors describe
+    // The user's and the project's word lists, read on first completion
+    listed_words: Option<String>,
+
+    // Vi modal editing (None when disabled)
+    vi: Option<ViState>,
+    vi_keys: Vec<KeyEvent>,        // keys of the change in progress
+    vi_last_change: Vec<KeyEvent>, // replayed by `.`
+    vi_replaying: bool,
+    vi_linewise: bool, // clipboard holds whole lines (yy/dd)
+    vi_visual_anchor: usize,
+
+    // Pager mode (`--pager`)
+    pager: bool,
+    // Tail -f of the active buffer's file (swapped with the document)
+    follow: Option<Follow>,
+    // Aligned columns of a CSV/TSV buffer (swapped with the document), and
+    // the buffer version they were measured at
+    column_view: Option<Columns>,
+    columns_version: Option<u64>,
+    // Annotations drawn with the text (swapped with the document)
+    virtual_text: VirtualText,
+    // Counts for document-stats, kept up once asked for (swapped with the
+    // document)
+    text_stats: TextStats,
+
+    // External plugin processes, and the buffer and version they last
+    // heard about
+    plugins: Plugins,
+    plugin_change: Option<(Option<PathBuf>, u64)>,
+    // User commands from the config's scripts, by name
+    scripts: Vec<(String, Script)>,
+
+    // Timings recorded with `--profile`, and the file the report is written
+    // to on exit
+    profile: Option<Profile>,
+    profile_output: Option<PathBuf>,
+    // When the last frame was sent, for the `max_fps` cap
+    last_frame: Option<Instant>,
+    // When input last came, for the `idle_timeout` wait
+    last_input: Instant,
+
     running: bool,
 }
 
 impl Editor {
     /// Create a new editor with an empty buffer.
     pub fn new() -> Result<Self, String> {
-        let color_mode = terminal::detect_color_mode();
-        let mut terminal = Terminal::new()?;
-        let (w, h) = terminal.size();
-
-        let buffer = Buffer::new();
-        let gutter_width = compute_gutter_width(buffer.line_count());
-
-        Ok(Editor {
-            buffer,
-            cursor: Cursor::new(),
-            screen: Screen::new(w as usize, h as usize),
-            terminal,
-            color_mode,
-            scroll_row: 0,
-            scroll_col: 0,
-            gutter_width,
-            status_height: 2,
-            message: None,
-            message_type: MessageType::Info,
-            quit_confirm: false,
-            selection: None,
-            clipboard: String::new(),
-            prompt: None,
-            undo_stack: UndoStack::new(),
-            search: None,
-            running: true,
-        })
+        Self::with_buffer(Buffer::new())
     }
 
     /// Create a new editor and load a file.
     pub fn open(path: &Path) -> Result<Self, String> {
-        let color_mode = terminal::detect_color_mode();
-        let mut terminal = Terminal::new()?;
-        let (w, h) = terminal.size();
+        Self::open_at(path, None)
+    }
+
+    /// Open a file with the cursor at a 1-based line and column, such as
+    /// `locate` finds in a `path:line:col` argument.
+    pub fn open_at(path: &Path, position: Option<(usize, usize)>) -> Result<Self, String> {
+        let mut editor = Self::with_buffer(Buffer::from_file(path)?)?;
+        if editor.config.resolve_symlinks {
+            editor.buffer.resolve_symlink();
+        }
+        editor.lock_active();
+        if let Some(position) = position {
+            editor.goto_position(position);
+        }
+        if editor.message.is_none() {
+            editor.offer_column_view();
+        }
+        Ok(editor)
+    }
+
+    /// View a file read-only with `less`-style keys (`zelux --pager`).
+    pub fn pager(path: &Path) -> Result<Self, String> {
+        let mut buffer = Buffer::from_file(path)?;
+        buffer.set_read_only(true);
+        let mut editor = Self::with_buffer(buffer)?;
+        editor.pager = true;
+        Ok(editor)
+    }
+
+    /// Reopen the session saved when the terminal was lost
+    /// (`zelux --continue`).
+    pub fn continue_session() -> Result<Self, String> {
+        let no_session = || strings::get("no_session").to_string();
+        let state = session::state_dir().ok_or_else(no_session)?;
+        let (dir, saved) = session::saved_sessions(&state)
+            .into_iter()
+            .find_map(|dir| Some((dir.clone(), Session::load(&dir).ok()?)))
+            .ok_or_else(no_session)?;
+        let mut editor = Self::with_buffer(Buffer::new())?;
+        editor.restore_session(&saved);
+        // Kept until the recovered text is saved, in case this one is lost
+        // too
+        editor.continued_session = Some(dir);
+        Ok(editor)
+    }
+
+    /// An editor for `buffer` that draws into another program's screen
+    /// instead of taking over the terminal. Feed it events with `feed` and
+    /// draw it with `render_into`; send `Event::Tick` while no input comes
+    /// for background work. Running shell commands is not available.
+    pub fn embedded(buffer: Buffer) -> Self {
+        Self::build(buffer, None)
+    }
+
+    fn with_buffer(buffer: Buffer) -> Result<Self, String> {
+        Ok(Self::build(buffer, Some(Terminal::new()?)))
+    }
+
+    fn build(buffer: Buffer, terminal: Option<Terminal>) -> Self {
+        let (config, mut config_error) = match Config::load() {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(e)),
+        };
+        match Strings::load(&config.language) {
+            Ok(loaded) => strings::init(loaded),
+            Err(e) => config_error = config_error.or(Some(e)),
+        }
+        let mut editor = Self::with_config(buffer, terminal, config, &mut config_error);
+        if let Some(dir) = crate::config::config_dir() {
+            let (plugins, errors) = Plugins::start_all(&dir.join("plugins"));
+            editor.plugins = plugins;
+            if let Some(e) = errors.first() {
+                config_error.get_or_insert(strings::format("plugin_failed", &[e]));
+            }
+        }
+        if let Some(e) = config_error {
+            editor.set_message(&e, MessageType::Warning);
+        }
+        editor
+    }
+
+    /// The editor for `config`, with its scripts and key bindings set up
+    /// (the first error goes in `config_error`) but no plugins started.
+    fn with_config(
+        buffer: Buffer,
+        mut terminal: Option<Terminal>,
+        config: Config,
+        config_error: &mut Option<String>,
+    ) -> Self {
+        let color_mode = terminal::capabilities().color_mode;
+        if let Some(ref mut terminal) = terminal {
+            terminal.set_read_timeout(Duration::from_millis(config.read_timeout));
+        }
+        // An embedded editor is sized by the first `render_into`
+        let (w, h) = terminal.as_mut().map_or((0, 0), Terminal::size);
+        if terminal.is_some()
+            && config.background == Background::Auto
+            && color_mode != ColorMode::Mono
+        {
+            terminal::query_background();
+        }
+        let light_background = config.background == Background::Light;
 
-        let buffer = Buffer::from_file(path)?;
         let gutter_width = compute_gutter_width(buffer.line_count());
+        let vi = config.vi_mode.then(ViState::new);
+        let spell_enabled = config.spell_check;
 
-        Ok(Editor {
+        let mut editor = Editor {
             buffer,
             cursor: Cursor::new(),
             screen: Screen::new(w as usize, h as usize),
             terminal,
             color_mode,
+            config,
+            light_background,
+            keymap: Keymap::default(),
             scroll_row: 0,
             scroll_col: 0,
+            last_recenter: None,
+            vertical_goal: None,
             gutter_width,
+            git_gutter: None,
             status_height: 2,
+            side_column_drag: false,
+            panel_drag: false,
+            gutter_drag: None,
+            text_drag: None,
+            blame_visible: false,
+            blame: None,
+            blame_job: None,
+            gutter_job: None,
+            jobs: Jobs::new(),
+            selection_stats: SelectionStats::default(),
+            drawn_view: None,
+            drawn_rows: Vec::new(),
+            drawn_status: None,
+            drawn_message: None,
+            overlay_drawn: false,
+            cursor_blink: (true, Instant::now()),
+            drawn_cursor: None,
             message: None,
             message_type: MessageType::Info,
-            quit_confirm: false,
+            message_log: Vec::new(),
+            documents: vec![Document::new(Buffer::new())],
+            active: 0,
+            buffers_replaced: Vec::new(),
             selection: None,
             clipboard: String::new(),
             prompt: None,
             undo_stack: UndoStack::new(),
             search: None,
+            quickfix: None,
+            quickfix_visible: false,
+            quickfix_rows: QUICKFIX_MAX_ROWS,
+            diagnostics_panel: None,
+            rename_panel: None,
+            snippets: Vec::new(),
+            snippet_session: None,
+            pending_pair: None,
+            pending_digraph: None,
+            completion: None,
+            lsp_completion_pending: false,
+            completion_request: None,
+            signature: None,
+            lsp_clients: Vec::new(),
+            diagnostics: Vec::new(),
+            inlay_hints: None,
+            popup: None,
+            context_menu: None,
+            options_panel: None,
+            dialog: None,
+            jumps: JumpList::default(),
+            symbol_picker: None,
+            file_index: None,
+            trashed: Vec::new(),
+            locks: Vec::new(),
+            continued_session: None,
+            spell_enabled,
+            dictionary: None,
+            spell_errors: Vec::new(),
+            spell_version: None,
+            listed_words: None,
+            vi,
+            vi_keys: Vec::new(),
+            vi_last_change: Vec::new(),
+            vi_replaying: false,
+            vi_linewise: false,
+            vi_visual_anchor: 0,
+            pager: false,
+            follow: None,
+            column_view: None,
+            columns_version: None,
+            virtual_text: VirtualText::default(),
+            text_stats: TextStats::default(),
+            plugins: Plugins::default(),
+            plugin_change: None,
+            scripts: Vec::new(),
+            profile: None,
+            profile_output: None,
+            last_frame: None,
+            last_input: Instant::now(),
             running: true,
-        })
+        };
+        editor.refresh_git_gutter();
+        for (name, source) in &editor.config.scripts {
+            match Script::parse(source) {
+                Ok(script) => editor.scripts.push((name.clone(), script)),
+                Err(e) => {
+                    config_error.get_or_insert(strings::format("script_error", &[name, &e]));
+                }
+            }
+        }
+        for (key, name) in &editor.config.keys {
+            let bound = match editor.command_named(name) {
+                Some(cmd @ Command::Script(_)) => editor.keymap.bind_command(key, cmd),
+                _ => editor.keymap.bind(key, name),
+            };
+            if let Err(e) = bound {
+                config_error.get_or_insert(format!("config.json keys: {}", e));
+            }
+        }
+        editor
+    }
+
+    /// An editor on `text` with the default configuration and no terminal
+    /// or plugins, drawing a `width` x `height` screen, for tests that drive
+    /// whole editing sessions.
+    #[cfg(test)]
+    pub(crate) fn headless(text: &str, width: usize, height: usize) -> Self {
+        let mut buffer = Buffer::new();
+        buffer.insert(0, text);
+        buffer.mark_saved();
+        let mut editor = Self::with_config(buffer, None, Config::default(), &mut None);
+        editor.screen.resize(width, height);
+        editor
+    }
+
+    /// Handle `event` as the main loop does, taking in finished jobs and
+    /// search progress afterwards.
+    #[cfg(test)]
+    pub(crate) fn handle_event_for_test(&mut self, event: Event) {
+        self.handle_event(event);
+        self.catch_up();
+    }
+
+    /// Handle the events `bytes` decode to, as if typed on the terminal.
+    #[cfg(test)]
+    pub(crate) fn type_bytes(&mut self, bytes: &[u8]) {
+        let source = terminal::MockTerminal::new(bytes);
+        while source.input_pending() {
+            for event in input::read_events(&source) {
+                self.handle_event_for_test(event);
+            }
+        }
+    }
+
+    /// Draw a frame and return its text, one line per screen row with
+    /// trailing blanks trimmed.
+    #[cfg(test)]
+    pub(crate) fn screen_snapshot(&mut self) -> String {
+        self.draw();
+        (0..self.screen.height())
+            .map(|row| self.screen.row_text(row).trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Record timings from now on (`zelux --profile`). `started` is when the
+    /// process began; the report also goes to `output` on exit, if given.
+    pub fn enable_profiling(&mut self, started: Instant, output: Option<PathBuf>) {
+        self.profile = Some(Profile::new(started));
+        self.profile_output = output;
     }
 
     /// Run the main editor loop.
     pub fn run(&mut self) -> Result<(), String> {
         while self.running {
+            let Some(terminal) = self.terminal.as_mut() else {
+                return Err(strings::get("needs_terminal").to_string());
+            };
+
+            // 0. The terminal is gone: keep the buffers for `--continue`
+            if terminal.hung_up() {
+                self.save_session();
+                break;
+            }
+
             // 1. Check for resize
-            if self.terminal.check_resize() {
-                let (w, h) = self.terminal.size();
-                self.screen.resize(w as usize, h as usize);
-                self.adjust_viewport();
+            if terminal.check_resize() {
+                self.handle_event(Event::Resize);
             }
 
-            // 2. Render
+            // 2. Take in finished background jobs and search another chunk
+            // of a huge buffer, then render
+            self.catch_up();
             self.render();
 
-            // 3. Read event (blocks until input or timeout)
-            let event = input::read_event(&self.terminal);
+            // 4. Read events (blocks until input or timeout), along with
+            // any that arrived in the same burst. An unfinished search
+            // only waits for input that is already there.
+            let mut events = if self.search_in_progress() {
+                self.read_events(Some(Duration::ZERO))
+            } else {
+                self.read_events(None)
+            };
 
-            // 4. Handle event
-            if event != Event::None {
-                self.handle_event(event);
+            // 5. Handle them, rendering once afterwards. Input arriving
+            // before the next frame is due joins the burst.
+            loop {
+                for event in events {
+                    let start = Instant::now();
+                    let kind = event_kind(&event);
+                    self.handle_event(event);
+                    if let Some(profile) = self.profile.as_mut() {
+                        profile.event(kind, start.elapsed());
+                    }
+                }
+                match self.until_next_frame() {
+                    Some(wait) if self.running => events = self.read_events(Some(wait)),
+                    _ => break,
+                }
+                if events.is_empty() {
+                    break;
+                }
             }
         }
 
+        if let (Some(profile), Some(path)) = (&self.profile, &self.profile_output) {
+            fs::write(path, profile.report())
+                .map_err(|e| strings::format("write_failed", &[&path.display(), &e]))?;
+        }
         Ok(())
     }
 
+    /// Read the waiting events, blocking until input or the read timeout
+    /// (a `Tick` then). With a `limit`, wait at most that long and return
+    /// none when no input came.
+    fn read_events(&mut self, limit: Option<Duration>) -> Vec<Event> {
+        let start = Instant::now();
+        let mut wait = idle_wait(
+            self.config.read_timeout,
+            self.config.idle_timeout,
+            self.last_input.elapsed(),
+        );
+        // Wake up in time for the next blink
+        if let Some(blink) = self.blink_interval() {
+            wait = wait.min(blink.saturating_sub(self.cursor_blink.1.elapsed()));
+        }
+        let Some(ref terminal) = self.terminal else {
+            return Vec::new();
+        };
+        let events = match limit {
+            Some(limit) if !terminal.wait_for_input(limit) => Vec::new(),
+            None if !terminal.wait_for_input(wait) => vec![Event::Tick],
+            _ => input::read_events(terminal),
+        };
+        if events.iter().any(|e| *e != Event::Tick) {
+            self.last_input = Instant::now();
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.phase(Phase::InputWait, start.elapsed());
+        }
+        events
+    }
+
+    /// How often the software cursor blinks; None when it does not.
+    fn blink_interval(&self) -> Option<Duration> {
+        (self.config.software_cursor && self.config.cursor_blink > 0)
+            .then(|| Duration::from_millis(self.config.cursor_blink))
+    }
+
+    /// Take in finished background jobs and search another chunk of a huge
+    /// buffer, before drawing a frame.
+    fn catch_up(&mut self) {
+        for (_, result) in self.jobs.poll() {
+            self.finish_job(result);
+        }
+        self.continue_search();
+    }
+
+    /// How long until `max_fps` allows the next frame; None when it is due.
+    fn until_next_frame(&self) -> Option<Duration> {
+        if self.config.max_fps == 0 {
+            return None;
+        }
+        let interval = Duration::from_secs(1) / self.config.max_fps as u32;
+        let drawn = self.last_frame?;
+        interval
+            .checked_sub(drawn.elapsed())
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Background upkeep on each `Event::Tick`, when no input is pending.
+    /// Work that must not hold up typing (polling, refreshing, indexing)
+    /// goes here and should return quickly.
+    fn on_idle(&mut self) {
+        if let Some(blink) = self.blink_interval()
+            && self.cursor_blink.1.elapsed() >= blink
+        {
+            self.cursor_blink = (!self.cursor_blink.0, Instant::now());
+        }
+        let refreshing = self.gutter_job.is_some_and(|id| self.jobs.is_running(id));
+        if !refreshing && self.git_gutter.as_ref().is_some_and(|g| g.is_stale()) {
+            self.refresh_git_gutter();
+        }
+        self.refresh_spelling();
+        self.follow_file();
+        self.lsp_sync();
+        self.request_inlay_hints();
+        self.request_lsp_completion();
+        self.poll_lsp();
+        self.notify_plugins_of_change();
+        self.poll_plugins();
+        self.refresh_file_index(false);
+        if self.text_stats.is_counted() {
+            self.text_stats.update(&self.buffer);
+        }
+        if self
+            .signature
+            .as_ref()
+            .is_some_and(|(_, line)| *line != self.cursor.line)
+        {
+            self.signature = None;
+        }
+    }
+
     // -----------------------------------------------------------------------
     // Viewport
     // -----------------------------------------------------------------------
 
     fn text_area_height(&self) -> usize {
-        self.screen.height().saturating_sub(self.status_height)
+        self.screen
+            .height()
+            .saturating_sub(self.status_height + self.panel_height())
+    }
+
+    /// Rows taken by the quickfix panel (header + entries), 0 when hidden.
+    fn panel_height(&self) -> usize {
+        if let Some(ref panel) = self.rename_panel {
+            // Two rows a line: before and after
+            return panel.hunks.len().min(self.rename_visible()) * 2 + 1;
+        }
+        if let Some(ref panel) = self.diagnostics_panel {
+            // A row for "No diagnostics" when there are none
+            return panel.items.len().clamp(1, self.quickfix_rows) + 1;
+        }
+        match self.quickfix {
+            Some(ref qf) if self.quickfix_visible => qf.entries.len().min(self.quickfix_rows) + 1,
+            _ => 0,
+        }
     }
 
     fn text_area_width(&self) -> usize {
-        self.screen.width().saturating_sub(self.gutter_width)
+        self.text_right().saturating_sub(self.gutter_width)
+    }
+
+    /// Screen column where the text area ends and the side column begins.
+    fn text_right(&self) -> usize {
+        let width = self.screen.width();
+        width.saturating_sub(self.config.side_column.width().min(width / 2))
     }
 
     fn adjust_viewport(&mut self) {
@@ -203,12 +1083,17 @@ impl Editor {
         let w = self.text_area_width();
 
         // Vertical scrolling
-        if h > 0 {
-            if self.cursor.line < self.scroll_row {
-                self.scroll_row = self.cursor.line;
-            } else if self.cursor.line >= self.scroll_row + h {
-                self.scroll_row = self.cursor.line - h + 1;
-            }
+        let last_line = self.buffer.line_count().saturating_sub(1);
+        self.scroll_row = scroll_to_cursor(
+            self.scroll_row,
+            self.cursor.line,
+            h,
+            self.config.scrolloff,
+            last_line,
+        );
+        // The pinned header covers the top line
+        if self.header_pinned() && self.cursor.line == self.scroll_row {
+            self.scroll_row -= 1;
         }
 
         // Horizontal scrolling
@@ -222,188 +1107,343 @@ impl Editor {
         }
     }
 
-    fn cursor_display_col(&self) -> usize {
-        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
-        byte_col_to_display_col(&line_text, self.cursor.col)
+    /// Whether the top text row shows the column view's header line instead
+    /// of the line scrolled there.
+    fn header_pinned(&self) -> bool {
+        self.column_view.is_some() && self.scroll_row > 0
     }
 
-    // -----------------------------------------------------------------------
-    // Rendering
-    // -----------------------------------------------------------------------
-
-    fn render(&mut self) {
-        self.gutter_width = compute_gutter_width(self.buffer.line_count());
-        self.adjust_viewport();
+    /// The buffer line shown on text row `screen_row`.
+    fn row_line(&self, screen_row: usize) -> usize {
+        if screen_row == 0 && self.header_pinned() {
+            0
+        } else {
+            self.scroll_row + screen_row
+        }
+    }
 
+    /// Scroll so the cursor line sits at `place` (within the scroll margin);
+    /// the cursor itself does not move.
+    fn place_cursor_line(&mut self, place: ViewPlace) {
         let h = self.text_area_height();
-        let screen_width = self.screen.width();
-
-        // -- Text area + gutter --
-        for screen_row in 0..h {
-            let file_line = self.scroll_row + screen_row;
-
-            if file_line < self.buffer.line_count() {
-                // Gutter: right-aligned line number
-                let num_str = format!("{}", file_line + 1);
-                let pad = self.gutter_width.saturating_sub(num_str.len() + 1);
-                let gutter_fg = Color::Color256(240); // dim gray
-                let gutter_bg = Color::Default;
+        self.scroll_row = scroll_for_place(place, self.cursor.line, h, self.config.scrolloff);
+        self.last_recenter = Some((place, self.cursor.line, self.scroll_row));
+    }
 
-                // Pad
-                for col in 0..pad {
-                    self.screen
-                        .put_char(screen_row, col, ' ', gutter_fg, gutter_bg, false);
-                }
-                // Number
-                self.screen
-                    .put_str(screen_row, pad, &num_str, gutter_fg, gutter_bg, false);
-                // Separator space
-                let sep_col = pad + num_str.len();
-                if sep_col < self.gutter_width {
-                    self.screen
-                        .put_char(screen_row, sep_col, ' ', gutter_fg, gutter_bg, false);
-                }
-
-                // Line content (with selection highlighting)
-                let line_text = self.buffer.get_line(file_line).unwrap_or_default();
-                let line_start_byte = self.buffer.line_start(file_line).unwrap_or(0);
-                let sel_range = self.selection_range();
-                let mut display_col: usize = 0;
-                let mut byte_offset_in_line: usize = 0;
-                for ch in line_text.chars() {
-                    if display_col >= self.scroll_col {
-                        let screen_col = display_col - self.scroll_col + self.gutter_width;
-                        if screen_col >= screen_width {
-                            break;
-                        }
-                        let char_byte = line_start_byte + byte_offset_in_line;
-                        let is_selected =
-                            sel_range.is_some_and(|(s, e)| char_byte >= s && char_byte < e);
-                        let (fg, bg, bold) = if is_selected {
-                            (Color::Ansi(0), Color::Ansi(7), true)
-                        } else if let Some(is_current) = self.match_at_byte(char_byte) {
-                            if is_current {
-                                (Color::Ansi(0), Color::Ansi(6), true) // cyan bg
-                            } else {
-                                (Color::Ansi(0), Color::Ansi(3), false) // yellow bg
-                            }
-                        } else {
-                            (Color::Default, Color::Default, false)
-                        };
-                        self.screen
-                            .put_char(screen_row, screen_col, ch, fg, bg, bold);
-                    }
-                    byte_offset_in_line += ch.len_utf8();
-                    display_col += 1;
-                }
-                // Fill remaining with spaces (selected if selection extends past EOL)
-                let start_fill = display_col
-                    .saturating_sub(self.scroll_col)
-                    .saturating_add(self.gutter_width);
-                let line_end_byte = line_start_byte + line_text.len();
-                for col in start_fill..screen_width {
-                    // Show selection highlight on trailing space if newline is selected
-                    let is_trailing_selected = sel_range
-                        .is_some_and(|(s, e)| line_end_byte >= s && line_end_byte < e)
-                        && col == start_fill; // only first trailing cell
-                    let (fg, bg, bold) = if is_trailing_selected {
-                        (Color::Ansi(0), Color::Ansi(7), true)
-                    } else {
-                        (Color::Default, Color::Default, false)
-                    };
-                    self.screen.put_char(screen_row, col, ' ', fg, bg, bold);
-                }
-            } else {
-                // Tilde line (past end of file)
-                self.screen.put_char(
-                    screen_row,
-                    0,
-                    '~',
-                    Color::Color256(240),
-                    Color::Default,
-                    false,
-                );
-                for col in 1..screen_width {
-                    self.screen.put_char(
-                        screen_row,
-                        col,
-                        ' ',
-                        Color::Default,
-                        Color::Default,
-                        false,
-                    );
+    /// Center the cursor line; pressed again without moving, go on to the
+    /// top and then the bottom.
+    fn recenter_top_bottom(&mut self) {
+        let place = match self.last_recenter {
+            Some((place, line, row)) if line == self.cursor.line && row == self.scroll_row => {
+                match place {
+                    ViewPlace::Center => ViewPlace::Top,
+                    ViewPlace::Top => ViewPlace::Bottom,
+                    ViewPlace::Bottom => ViewPlace::Center,
                 }
             }
+            _ => ViewPlace::Center,
+        };
+        self.place_cursor_line(place);
+    }
+
+    fn page(&mut self, down: bool) {
+        let h = self.text_area_height();
+        let (from_row, from_line) = (self.scroll_row, self.cursor.line);
+        if down {
+            let max_line = self.buffer.line_count().saturating_sub(1);
+            self.scroll_row = (self.scroll_row + h).min(max_line);
+            self.cursor.move_page_down(&self.buffer, h);
+        } else {
+            self.scroll_row = self.scroll_row.saturating_sub(h);
+            self.cursor.move_page_up(&self.buffer, h);
+        }
+        if self.config.smooth_scroll {
+            self.animate_scroll(from_row, from_line);
         }
+    }
 
-        // -- Status bar (inverted colors) --
-        let status_row = h;
-        if status_row < self.screen.height() {
-            let status_fg = Color::Ansi(0); // black
-            let status_bg = Color::Ansi(7); // white
+    /// Draw a few frames between the old view and the new one, moving the
+    /// cursor along so it keeps its place on screen.
+    fn animate_scroll(&mut self, from_row: usize, from_line: usize) {
+        let (to_row, to_line) = (self.scroll_row, self.cursor.line);
+        let (col, desired_col) = (self.cursor.col, self.cursor.desired_col);
+        let between = |from: usize, to: usize, step: usize| {
+            (from as isize + (to as isize - from as isize) * step as isize / SCROLL_STEPS as isize)
+                as usize
+        };
+        for step in 1..SCROLL_STEPS {
+            self.scroll_row = between(from_row, to_row, step);
+            let line = between(from_line, to_line, step);
+            self.cursor.set_position(line, desired_col, &self.buffer);
+            self.render();
+            std::thread::sleep(SCROLL_FRAME);
+        }
+        self.scroll_row = to_row;
+        self.cursor.line = to_line;
+        self.cursor.col = col;
+        self.cursor.desired_col = desired_col;
+    }
 
-            // Build status text
-            let filename = self
-                .buffer
-                .file_path()
-                .map(shorten_path)
-                .unwrap_or_else(|| "[No Name]".to_string());
-            let modified_marker = if self.buffer.is_modified() {
-                " [+]"
+    /// Screen row and column of the cursor, for anchoring popups.
+    fn cursor_screen_pos(&self) -> (usize, usize) {
+        let row = self.cursor.line.saturating_sub(self.scroll_row);
+        let col = self.cursor_display_col().saturating_sub(self.scroll_col) + self.gutter_width;
+        (row, col)
+    }
+
+    /// Character column of the cursor in its line.
+    fn cursor_char_col(&self) -> usize {
+        let line_text = self.cursor_line_head();
+        byte_col_to_display_col(&line_text, self.cursor.col)
+    }
+
+    /// Column the cursor is drawn at, from the line start. Differs from
+    /// `cursor_char_col` after wide characters and inside right-to-left text.
+    fn cursor_display_col(&self) -> usize {
+        let line_text = self.cursor_line_head();
+        byte_col_to_screen_col(
+            &self.line_cells(self.cursor.line, &line_text),
+            self.cursor.col,
+        )
+    }
+
+    /// Move up or down a line onto the character drawn in the same screen
+    /// column, so tabs and wide characters don't pull the cursor sideways.
+    /// Shorter lines on the way don't lose the column.
+    fn move_vertically(&mut self, down: bool) {
+        let goal = match self.vertical_goal {
+            Some((line, col, goal)) if line == self.cursor.line && col == self.cursor.col => goal,
+            _ => self.cursor_display_col(),
+        };
+        if down {
+            self.cursor.move_down(&self.buffer);
+        } else {
+            self.cursor.move_up(&self.buffer);
+        }
+        let text = self.line_head(self.cursor.line, (goal + 1) * CHAR_MAX_BYTES);
+        let cells = self.line_cells(self.cursor.line, &text);
+        self.cursor.col = screen_col_to_byte_col(&cells, text.len(), goal);
+        self.vertical_goal = Some((self.cursor.line, self.cursor.col, goal));
+    }
+
+    /// Where each character of line `line`, with text `text`, is drawn (see
+    /// `visual_cells`); in column view, laid out in its columns. Inline
+    /// annotations push the characters after them right.
+    fn line_cells(&self, line: usize, text: &str) -> Vec<(usize, char, usize)> {
+        let mut cells = match self.column_view {
+            Some(ref columns) => columns.layout(text),
+            None => visual_cells(text, &self.tab_stops(line, text)),
+        };
+        virtual_text::make_room(&mut cells, &self.annotations(line));
+        cells
+    }
+
+    /// The text of `line` up to `max_len` bytes in. Lines up to
+    /// `LONG_LINE` bytes are whole, so right-to-left runs and elastic tabs
+    /// are laid out over all of them; only longer ones are cut short.
+    fn line_head(&self, line: usize, max_len: usize) -> Cow<'_, str> {
+        let start = self.buffer.line_start(line).unwrap_or(0);
+        let len = self.buffer.line_end(line).unwrap_or(start) - start;
+        let max_len = if len > LONG_LINE { max_len } else { len };
+        self.buffer.get_line_head(line, max_len).unwrap_or_default()
+    }
+
+    /// The text of the cursor's line as far as the cursor's character.
+    fn cursor_line_head(&self) -> Cow<'_, str> {
+        self.line_head(self.cursor.line, self.cursor.col + 1)
+    }
+
+    /// Where the tabs of line `line`, with text `text`, end. Elastic stops
+    /// look at the lines with tabs around it, up to `ELASTIC_TAB_REACH`
+    /// each way.
+    fn tab_stops(&self, line: usize, text: &str) -> TabStops {
+        if !self.config.elastic_tabs || !text.contains('\t') {
+            return TabStops::fixed(self.tab_width());
+        }
+        let has_tabs = |l: usize| self.buffer.get_line(l).is_some_and(|t| t.contains('\t'));
+        let mut first = line;
+        while first > 0 && line - first < ELASTIC_TAB_REACH && has_tabs(first - 1) {
+            first -= 1;
+        }
+        let mut last = line;
+        while last - line < ELASTIC_TAB_REACH && has_tabs(last + 1) {
+            last += 1;
+        }
+        let texts: Vec<Cow<str>> = (first..=last)
+            .map(|l| self.buffer.get_line(l).unwrap_or_default())
+            .collect();
+        let lines: Vec<&str> = texts.iter().map(|t| t.as_ref()).collect();
+        TabStops::elastic(&lines, line - first, self.tab_width())
+    }
+
+    /// The annotations drawn on `line`, none when virtual text is off.
+    fn annotations(&self, line: usize) -> Vec<Annotation> {
+        if self.config.virtual_text {
+            self.virtual_text.line(line)
+        } else {
+            Vec::new()
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Rendering
+    // -----------------------------------------------------------------------
+
+    fn render(&mut self) {
+        let start = Instant::now();
+        self.draw();
+
+        // Encode the changed cells and send them
+        let built = Instant::now();
+        let output = self.screen.diff_output(&self.color_mode);
+        let encoded = Instant::now();
+        let (row, col) = self.cursor_position();
+        if let Some(ref terminal) = self.terminal {
+            if self.config.software_cursor {
+                // The frame draws the cursor; the terminal's stays hidden
+                if !output.is_empty() {
+                    terminal.write(b"\x1b[?25l");
+                    terminal.write(&output);
+                }
             } else {
-                ""
-            };
-            let color_str = match self.color_mode {
-                ColorMode::TrueColor => "TrueColor",
-                ColorMode::Color256 => "256color",
-                ColorMode::Color16 => "16color",
-            };
-            let position = format!(
-                "Ln {}, Col {}",
-                self.cursor.line + 1,
-                self.cursor_display_col() + 1,
-            );
+                Screen::send(terminal, &output);
+                terminal.write(format!("\x1b[{};{}H", row + 1, col + 1).as_bytes());
+            }
+            terminal.flush();
+        }
+        self.last_frame = Some(Instant::now());
+        if let Some(profile) = self.profile.as_mut() {
+            profile.phase(Phase::Render, built - start);
+            profile.phase(Phase::Emit, encoded - built);
+            profile.phase(Phase::Flush, encoded.elapsed());
+            profile.frame_done(output.len());
+        }
+    }
 
-            let left = format!(" {}{}", filename, modified_marker);
-            let right = format!("{} | {} ", position, color_str);
+    /// Build the frame in `self.screen`, redrawing only what changed.
+    fn draw(&mut self) {
+        // Put back the cell under last frame's software cursor
+        if let Some((row, col, cell)) = self.drawn_cursor.take() {
+            self.screen.put_cell(row, col, cell);
+        }
+        self.update_gutter_width();
+        self.measure_columns();
+        self.refresh_diagnostics_panel();
+        self.sync_search();
+        self.adjust_viewport();
 
-            // Fill status bar
-            for col in 0..screen_width {
+        let h = self.text_area_height();
+        let screen_width = self.screen.width();
+
+        // -- Text area + gutter --
+        // Rows are rebuilt only when what they show changed. Anything that
+        // affects every row, or an overlay drawn over them now or last
+        // frame, rebuilds them all.
+        let view = self.view_state(h);
+        let overlay = self.overlay_visible();
+        if self.drawn_view.as_ref() != Some(&view) || overlay || self.overlay_drawn {
+            self.invalidate_frame();
+        }
+        self.drawn_rows.resize(h, None);
+        for screen_row in 0..h {
+            let file_line = self.row_line(screen_row);
+            if view.blame_width > 0 && file_line < self.buffer.line_count() {
+                self.render_blame_cell(screen_row, file_line);
+            }
+            let (text, state) = self.row_state(screen_row, &view);
+            if self.drawn_rows[screen_row].as_ref() != Some(&state) {
+                // Copied out of the buffer only to draw a changed row
+                let text = text.into_owned();
+                self.draw_row(screen_row, &text, &state, &view);
+                self.drawn_rows[screen_row] = Some(state);
+            }
+        }
+        self.drawn_view = Some(view);
+        self.overlay_drawn = overlay;
+
+        self.render_side_column(h);
+        self.render_drop_point(h);
+
+        // -- Popups --
+        self.render_signature(h);
+        self.render_completion(h);
+        self.render_popup(h);
+        if let Some((ref mut menu, _)) = self.context_menu {
+            let area = Rect::new(0, 0, self.screen.width(), h);
+            menu.render(&mut self.screen, area);
+        }
+        self.render_options_panel(h);
+
+        self.render_symbol_picker(h);
+        if let Some((ref dialog, _)) = self.dialog {
+            let area = Rect::new(0, 0, self.screen.width(), h);
+            dialog.render(&mut self.screen, area);
+        }
+
+        // -- Quickfix, diagnostics or rename panel --
+        if self.rename_panel.is_some() {
+            self.render_rename_panel(h);
+        } else if self.diagnostics_panel.is_some() {
+            self.render_diagnostics_panel(h);
+        } else {
+            self.render_quickfix_panel(h);
+        }
+
+        // -- Status bar (inverted colors) --
+        let status_row = h + self.panel_height();
+        if status_row < self.screen.height() {
+            let status_fg = Color::Ansi(0); // black
+            let status_bg = Color::Ansi(7); // white
+
+            let selection = self.selection_summary();
+            let (left, right) = statusline::layout(
+                &self.status_segments(&self.config.status_bar.left, &selection),
+                &self.status_segments(&self.config.status_bar.right, &selection),
+                screen_width,
+            );
+
+            let drawn = (status_row, left, right);
+            if self.drawn_status.as_ref() != Some(&drawn) {
+                let (_, ref left, ref right) = drawn;
+                // Fill status bar
+                for col in 0..screen_width {
+                    self.screen
+                        .put_char(status_row, col, ' ', status_fg, status_bg, true);
+                }
+                // Left side
+                self.screen
+                    .put_str(status_row, 0, left, status_fg, status_bg, true);
+                // Right side
+                let right_start = screen_width.saturating_sub(unicode::display_width(right));
                 self.screen
-                    .put_char(status_row, col, ' ', status_fg, status_bg, true);
+                    .put_str(status_row, right_start, right, status_fg, status_bg, true);
+                self.drawn_status = Some(drawn);
             }
-            // Left side
-            self.screen
-                .put_str(status_row, 0, &left, status_fg, status_bg, true);
-            // Right side
-            let right_start = screen_width.saturating_sub(right.len());
-            self.screen
-                .put_str(status_row, right_start, &right, status_fg, status_bg, true);
         }
 
         // -- Message line --
-        let msg_row = h + 1;
-        if msg_row < self.screen.height() {
+        let msg_row = status_row + 1;
+        if let Some((prompt, _)) = self.prompt.as_mut() {
+            prompt.follow_cursor(screen_width);
+        }
+        let drawn = (
+            msg_row,
+            self.prompt
+                .as_ref()
+                .map(|(p, _)| (p.label.clone(), p.input.clone(), p.scroll())),
+            self.message.clone(),
+            self.message_type,
+        );
+        if msg_row < self.screen.height() && self.drawn_message.as_ref() != Some(&drawn) {
+            self.drawn_message = Some(drawn);
             // Fill with spaces first
             for col in 0..screen_width {
                 self.screen
                     .put_char(msg_row, col, ' ', Color::Default, Color::Default, false);
             }
 
-            if let Some(ref prompt) = self.prompt {
-                // Render prompt: label (yellow) + input (default)
-                let label_fg = Color::Ansi(3); // yellow
-                self.screen
-                    .put_str(msg_row, 1, &prompt.label, label_fg, Color::Default, false);
-                let input_start = 1 + prompt.label.chars().count();
-                self.screen.put_str(
-                    msg_row,
-                    input_start,
-                    &prompt.input,
-                    Color::Default,
-                    Color::Default,
-                    false,
-                );
+            if let Some((ref prompt, _)) = self.prompt {
+                let input_end = prompt.render(&mut self.screen, msg_row, screen_width);
 
                 // Show error message after the input if present
                 if let Some(ref msg) = self.message {
@@ -412,8 +1452,9 @@ impl Editor {
                         MessageType::Warning => Color::Ansi(3),
                         _ => Color::Ansi(2),
                     };
-                    let err_start = input_start + prompt.input.chars().count() + 2;
+                    let err_start = input_end + 2;
                     if err_start < screen_width {
+                        let msg = unicode::truncate_display(msg, screen_width - err_start);
                         self.screen
                             .put_str(msg_row, err_start, msg, msg_fg, Color::Default, false);
                     }
@@ -424,35 +1465,618 @@ impl Editor {
                     MessageType::Error => Color::Ansi(1),   // red
                     MessageType::Warning => Color::Ansi(3), // yellow
                 };
+                let msg = unicode::truncate_display(msg, screen_width.saturating_sub(1));
                 self.screen
                     .put_str(msg_row, 1, msg, msg_fg, Color::Default, false);
             }
         }
 
-        // Flush the screen
-        self.screen.flush(&self.color_mode);
+        // -- Software cursor --
+        if self.config.software_cursor && self.cursor_blink.0 {
+            let (row, col) = self.cursor_position();
+            if let Some(cell) = self.screen.cell(row, col) {
+                let (fg, bg) = (self.theme().cursor_text, self.theme().cursor);
+                self.screen.put_cell(row, col, Cell { fg, bg, ..cell });
+                self.drawn_cursor = Some((row, col, cell));
+            }
+        }
+    }
+
+    /// Where the cursor goes on screen, as (row, column): in the prompt's
+    /// input while one is open, else on the cursor's character.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        if let Some((ref prompt, _)) = self.prompt {
+            let msg_row = self.text_area_height() + self.panel_height() + 1;
+            return (msg_row, prompt.cursor_col());
+        }
+        let row = self.cursor.line.saturating_sub(self.scroll_row);
+        let col = self
+            .cursor_display_col()
+            .saturating_sub(self.scroll_col)
+            .saturating_add(self.gutter_width);
+        (row, col)
+    }
+
+    // -----------------------------------------------------------------------
+    // Embedding
+    // -----------------------------------------------------------------------
+
+    /// Handle one input event, as the main loop does.
+    pub fn feed(&mut self, event: Event) {
+        self.handle_event(event);
+    }
+
+    /// Draw the editor into `area` of the host's `screen`, sized to fit it.
+    /// The cursor goes at `cursor_position` within the area.
+    pub fn render_into(&mut self, screen: &mut Screen, area: Rect) {
+        if (self.screen.width(), self.screen.height()) != (area.width, area.height) {
+            self.screen.resize(area.width, area.height);
+            self.invalidate_frame();
+        }
+        self.catch_up();
+        self.draw();
+        screen.blit(&self.screen, area.top, area.left);
+        self.last_frame = Some(Instant::now());
+    }
+
+    /// False once the editor has been quit.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// The buffer being edited.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Forget what was drawn so the next frame rebuilds everything.
+    fn invalidate_frame(&mut self) {
+        self.screen.clear();
+        self.drawn_cursor = None;
+        self.drawn_rows.clear();
+        self.drawn_status = None;
+        self.drawn_message = None;
+    }
+
+    /// Whether anything is drawn over the text rows this frame.
+    fn overlay_visible(&self) -> bool {
+        self.signature.is_some()
+            || self.completion.is_some()
+            || self.popup.is_some()
+            || self.context_menu.is_some()
+            || self.options_panel.is_some()
+            || self.symbol_picker.is_some()
+            || self.dialog.is_some()
+            || self.text_drag.is_some()
+    }
 
-        // Position the hardware cursor
-        if let Some(ref prompt) = self.prompt {
-            // Cursor on message line within prompt input
-            let prompt_cursor_col = 1
-                + prompt.label.chars().count()
-                + prompt.input[..prompt.cursor_pos].chars().count();
-            let msg_row_1based = (h + 1 + 1) as u16; // h+1 is msg_row, +1 for 1-based
-            terminal::move_cursor(msg_row_1based, (prompt_cursor_col + 1) as u16);
+    /// The theme variant for the terminal's background.
+    fn theme(&self) -> &Theme {
+        if self.light_background {
+            &self.config.light_theme
         } else {
-            let cursor_screen_row = self.cursor.line.saturating_sub(self.scroll_row);
-            let cursor_display = self.cursor_display_col();
-            let cursor_screen_col = cursor_display
-                .saturating_sub(self.scroll_col)
-                .saturating_add(self.gutter_width);
-
-            terminal::move_cursor(
-                (cursor_screen_row + 1) as u16,
-                (cursor_screen_col + 1) as u16,
+            &self.config.theme
+        }
+    }
+
+    fn view_state(&self, text_height: usize) -> ViewState {
+        ViewState {
+            size: (self.screen.width(), text_height),
+            scroll_col: self.scroll_col,
+            gutter_width: self.gutter_width,
+            text_right: self.text_right(),
+            blame_width: self.blame_column_width(),
+            sign_width: self.sign_column_width(),
+            ruler: self
+                .config
+                .color_column
+                .and_then(|c| (c - 1).checked_sub(self.scroll_col))
+                .map(|c| c + self.gutter_width),
+            ruler_bg: self.theme().color_column,
+            line_bg: self.theme().current_line,
+            selection_bg: self.theme().selection,
+            matches: MatchStyle {
+                text: self.theme().search_text,
+                bg: self.theme().search_match,
+                current_bg: self.theme().search_current,
+                dim_bg: (self.visible_matches(text_height) > DIM_MATCHES_PER_ROW * text_height)
+                    .then_some(self.theme().search_dim),
+                underline: self.config.search_underline,
+            },
+            columns: self.column_view.clone(),
+            patch: patch::is_patch(&self.buffer.filetype()),
+        }
+    }
+
+    /// Search matches starting on the `rows` lines from the top of the view.
+    fn visible_matches(&self, rows: usize) -> usize {
+        let Some(search) = self.search.as_ref() else {
+            return 0;
+        };
+        let line_start = |line| self.buffer.line_start(line).unwrap_or(self.buffer.len());
+        let start = line_start(self.scroll_row);
+        let end = line_start(self.scroll_row + rows);
+        let first = search.matches.partition_point(|&(s, _)| s < start);
+        let last = search.matches.partition_point(|&(s, _)| s < end);
+        last - first
+    }
+
+    /// What screen row `screen_row` of the text area shows: the text of
+    /// its line, borrowed where the buffer allows, and the row's state.
+    fn row_state(&self, screen_row: usize, view: &ViewState) -> (Cow<'_, str>, RowState) {
+        let file_line = self.row_line(screen_row);
+        if file_line >= self.buffer.line_count() {
+            return (Cow::Borrowed(""), RowState::default());
+        }
+        let text = self.line_head(file_line, (view.scroll_col + view.size.0) * CHAR_MAX_BYTES);
+        let start = self.buffer.line_start(file_line).unwrap_or(0);
+        let end = start + text.len();
+
+        let sign = (view.sign_width > 0).then(|| {
+            let git_marker = self.git_gutter.as_ref().and_then(|g| g.marker(file_line));
+            match (self.line_severity(file_line), git_marker) {
+                (Some(Severity::Error), _) => ('E', Color::Ansi(1)),
+                (Some(Severity::Warning), _) => ('W', Color::Ansi(3)),
+                (Some(_), _) => ('I', Color::Ansi(4)),
+                (None, Some(LineChange::Added)) => ('+', Color::Ansi(2)),
+                (None, Some(LineChange::Modified)) => ('~', Color::Ansi(3)),
+                (None, Some(LineChange::Deleted)) => ('_', Color::Ansi(1)),
+                (None, None) => (' ', Color::Color256(240)),
+            }
+        });
+        // The selection may also cover the newline, one past the text
+        let selection = self
+            .selection_range()
+            .map(|(s, e)| (s.max(start), e.min(end + 1)))
+            .filter(|(s, e)| s < e)
+            .map(|(s, e)| (s - start, e - start));
+        let matches = self.search.as_ref().map_or(Vec::new(), |search| {
+            let first = search.matches.partition_point(|&(_, e)| e <= start);
+            search.matches[first..]
+                .iter()
+                .enumerate()
+                .take_while(|(_, (s, _))| *s < end)
+                .map(|(i, &(s, e))| {
+                    let current = search.current == Some(first + i);
+                    (s.saturating_sub(start), e - start, current)
+                })
+                .collect()
+        });
+        let state = RowState {
+            line: Some(file_line),
+            text_hash: text_hash(&text),
+            fg: view.patch.then(|| patch::line_color(&text)).flatten(),
+            cursor_line: self.config.highlight_current_line
+                && file_line == self.cursor.line
+                && self.selection.is_none(),
+            sign,
+            selection,
+            matches,
+            underlines: self.diagnostic_spans(file_line, &text),
+            misspelled: self.spelling_spans(file_line),
+            annotations: self.annotations(file_line),
+            tabs: self.tab_stops(file_line, &text),
+        };
+        (text, state)
+    }
+
+    /// Build the cells of one text row: gutter, text and highlights.
+    fn draw_row(&mut self, screen_row: usize, text: &str, row: &RowState, view: &ViewState) {
+        let layer_bg = |is_cursor_line: bool, screen_col: usize| {
+            if view.ruler == Some(screen_col) {
+                view.ruler_bg
+            } else if is_cursor_line {
+                view.line_bg
+            } else {
+                Color::Default
+            }
+        };
+        let Some(file_line) = row.line else {
+            // Tilde line (past end of file)
+            self.screen.put_char(
+                screen_row,
+                0,
+                '~',
+                Color::Color256(240),
+                Color::Default,
+                false,
             );
+            for col in 1..view.text_right {
+                self.screen.put_char(
+                    screen_row,
+                    col,
+                    ' ',
+                    Color::Default,
+                    layer_bg(false, col),
+                    false,
+                );
+            }
+            return;
+        };
+        let gutter_fg = Color::Color256(240); // dim gray
+        let gutter_bg = Color::Default;
+
+        // Diagnostic or git change marker
+        if let Some((ch, fg)) = row.sign {
+            self.screen
+                .put_char(screen_row, view.blame_width, ch, fg, gutter_bg, false);
+        }
+
+        // Gutter: right-aligned line number
+        let num_str = format!("{}", file_line + 1);
+        let pad = view.gutter_width.saturating_sub(num_str.len() + 1);
+
+        // Pad
+        for col in view.blame_width + view.sign_width..pad {
+            self.screen
+                .put_char(screen_row, col, ' ', gutter_fg, gutter_bg, false);
+        }
+        // Number
+        self.screen
+            .put_str(screen_row, pad, &num_str, gutter_fg, gutter_bg, false);
+        // Separator space
+        let sep_col = pad + num_str.len();
+        if sep_col < view.gutter_width {
+            self.screen
+                .put_char(screen_row, sep_col, ' ', gutter_fg, gutter_bg, false);
+        }
+
+        // Line content (with selection highlighting); the column view's
+        // header line is bold
+        let within =
+            |spans: &[(usize, usize)], at: usize| spans.iter().any(|&(s, e)| at >= s && at < e);
+        let mut cells = match view.columns {
+            Some(ref columns) => columns.layout(text),
+            None => visual_cells(text, &row.tabs),
+        };
+        let inline_starts = virtual_text::make_room(&mut cells, &row.annotations);
+        let text_end = cells
+            .iter()
+            .map(|&(_, ch, col)| col + unicode::cell_width(ch))
+            .chain(
+                row.annotations
+                    .iter()
+                    .zip(&inline_starts)
+                    .map(|(a, start)| start + a.width()),
+            )
+            .max()
+            .unwrap_or(0);
+        let header = view.columns.is_some() && file_line == 0;
+        // First column after the drawn text, and whether the line runs on
+        // past the right edge
+        let mut fill_from = view.gutter_width;
+        let mut clipped = false;
+        for (at, ch, col) in cells {
+            let width = unicode::cell_width(ch);
+            if col < view.scroll_col {
+                if col + width > view.scroll_col {
+                    // The right half of a wide character cut by the left edge
+                    let bg = layer_bg(row.cursor_line, view.gutter_width);
+                    self.screen.put_char(
+                        screen_row,
+                        view.gutter_width,
+                        ' ',
+                        Color::Default,
+                        bg,
+                        false,
+                    );
+                    fill_from = view.gutter_width + 1;
+                }
+                continue;
+            }
+            let screen_col = col - view.scroll_col + view.gutter_width;
+            if screen_col + width > view.text_right {
+                clipped = true;
+                break;
+            }
+            // Padding between columns, selected when the text on both
+            // sides is
+            let padding_selected = row.selection.is_some_and(|(s, e)| at > s && at < e);
+            for pad in fill_from..screen_col {
+                let (fg, bg) = if padding_selected {
+                    (Color::Default, view.selection_bg)
+                } else {
+                    (Color::Default, layer_bg(row.cursor_line, pad))
+                };
+                self.screen.put_char(screen_row, pad, ' ', fg, bg, false);
+            }
+            let is_selected = row.selection.is_some_and(|(s, e)| at >= s && at < e);
+            let search_match = row
+                .matches
+                .iter()
+                .find(|&&(s, e, _)| at >= s && at < e)
+                .map(|&(_, _, current)| current);
+            // The foreground layer, then the selection or search match
+            // backgrounds over it
+            let fg = if within(&row.misspelled, at) {
+                Color::Ansi(5)
+            } else {
+                row.fg.unwrap_or(Color::Default)
+            };
+            let style = &view.matches;
+            let (fg, bg, bold) = match search_match {
+                _ if is_selected => {
+                    let bg = view.selection_bg;
+                    (theme::readable(fg, bg), bg, false)
+                }
+                Some(true) => (style.text, style.current_bg, true),
+                Some(false) if !style.underline => match style.dim_bg {
+                    Some(bg) => (theme::readable(fg, bg), bg, false),
+                    None => (style.text, style.bg, false),
+                },
+                _ => (fg, layer_bg(row.cursor_line, screen_col), false),
+            };
+            self.screen
+                .put_char(screen_row, screen_col, ch, fg, bg, bold || header);
+            if text[at..]
+                .chars()
+                .next()
+                .is_some_and(unicode::has_placeholder)
+            {
+                self.screen.set_reverse(screen_row, screen_col);
+            }
+            let underlined_match = style.underline && search_match.is_some() && !is_selected;
+            if underlined_match || within(&row.underlines, at) || within(&row.misspelled, at) {
+                for col in screen_col..screen_col + width {
+                    self.screen.set_underline(screen_row, col);
+                }
+            }
+            fill_from = screen_col + width;
+        }
+        // Fill remaining with spaces (selected if selection extends past EOL)
+        let line_end = text.len();
+        for col in fill_from..view.text_right {
+            // Show selection highlight on trailing space if newline is selected
+            let is_trailing_selected = row
+                .selection
+                .is_some_and(|(s, e)| line_end >= s && line_end < e)
+                && !clipped
+                && col == fill_from; // only first trailing cell
+            let bg = if is_trailing_selected {
+                view.selection_bg
+            } else {
+                layer_bg(row.cursor_line, col)
+            };
+            self.screen
+                .put_char(screen_row, col, ' ', Color::Default, bg, false);
+        }
+
+        // Annotations: inline ones in the room left for them, line ends
+        // after the text, a space apart
+        let mut inline = inline_starts.iter();
+        let mut end_col = text_end + 1;
+        for annotation in &row.annotations {
+            let mut col = match annotation.placement {
+                Placement::Inline(_) => match inline.next() {
+                    Some(&col) => col,
+                    None => continue,
+                },
+                Placement::LineEnd => {
+                    let col = end_col;
+                    end_col += annotation.width() + 1;
+                    col
+                }
+            };
+            for ch in annotation.text.chars() {
+                let width = unicode::cell_width(ch);
+                if col >= view.scroll_col {
+                    let screen_col = col - view.scroll_col + view.gutter_width;
+                    if screen_col + width > view.text_right {
+                        break;
+                    }
+                    let bg = layer_bg(row.cursor_line, screen_col);
+                    self.screen
+                        .put_char(screen_row, screen_col, ch, annotation.fg, bg, false);
+                }
+                col += width;
+            }
+        }
+    }
+
+    /// Line, word and character counts of a non-empty selection, for the
+    /// status bar.
+    fn selection_summary(&mut self) -> String {
+        let Some((start, end)) = self.selection_range().filter(|(s, e)| s < e) else {
+            return String::new();
+        };
+        let (first, last) = self.selected_lines();
+        let (chars, words) = self.selection_stats.measure(&self.buffer, start, end);
+        let count = |n: usize, one: &str, many: &str| match n {
+            1 => strings::get(one).to_string(),
+            n => strings::format(many, &[&n]),
+        };
+        strings::format(
+            "status_selection",
+            &[
+                &count(last - first + 1, "one_line", "lines"),
+                &count(words, "one_word", "words"),
+                &count(chars, "one_char", "chars"),
+            ],
+        )
+    }
+
+    /// Render the text of each status bar segment in `segments`; segments
+    /// with nothing to show are empty.
+    fn status_segments(&self, segments: &[Segment], selection: &str) -> Vec<(Segment, String)> {
+        segments
+            .iter()
+            .map(|&segment| {
+                let text = match segment {
+                    Segment::Mode => match self.vi {
+                        Some(ref vi) => match vi.mode {
+                            vi::Mode::Normal => strings::get("mode_normal").to_string(),
+                            vi::Mode::Insert => strings::get("mode_insert").to_string(),
+                            vi::Mode::Visual => strings::get("mode_visual").to_string(),
+                        },
+                        None => String::new(),
+                    },
+                    Segment::Filename => {
+                        let modified = if self.buffer.is_modified() {
+                            " [+]"
+                        } else {
+                            ""
+                        };
+                        let follow = match self.follow {
+                            Some(_) if self.follow_pinned() => strings::get("status_follow"),
+                            Some(_) => strings::get("status_follow_paused"),
+                            None => "",
+                        };
+                        format!("{}{}{}", self.buffer_display_name(), modified, follow)
+                    }
+                    Segment::Buffers if self.documents.len() > 1 => {
+                        format!("[{}/{}]", self.active + 1, self.documents.len())
+                    }
+                    Segment::Buffers => String::new(),
+                    Segment::Position => strings::format(
+                        "status_position",
+                        &[
+                            &(self.cursor.line + 1),
+                            &self.buffer.line_count(),
+                            &(self.cursor_char_col() + 1),
+                        ],
+                    ),
+                    Segment::Percent => statusline::scroll_percent(
+                        self.scroll_row,
+                        self.text_area_height(),
+                        self.buffer.line_count(),
+                    ),
+                    Segment::Selection => selection.to_string(),
+                    Segment::LineEnding => line_ending_name(self.buffer.is_crlf()).to_string(),
+                    Segment::Encoding => "utf-8".to_string(),
+                    Segment::Filetype => self.buffer.filetype(),
+                    Segment::Branch => self
+                        .git_gutter
+                        .as_ref()
+                        .and_then(|g| g.branch())
+                        .map_or(String::new(), |b| format!("git:{}", b)),
+                    Segment::Colors => match self.color_mode {
+                        ColorMode::TrueColor => "TrueColor",
+                        ColorMode::Color256 => "256color",
+                        ColorMode::Color16 => "16color",
+                        ColorMode::Mono => "mono",
+                    }
+                    .to_string(),
+                };
+                (segment, text)
+            })
+            .collect()
+    }
+
+    /// Mark where a dragged selection would land.
+    fn render_drop_point(&mut self, text_height: usize) {
+        let drop = match (self.text_drag, self.selection_range()) {
+            (Some(drop), Some((start, end))) if !(start..=end).contains(&drop) => drop,
+            _ => return,
+        };
+        let line = self.buffer.byte_to_line(drop);
+        let text = self.buffer.get_line(line).unwrap_or_default();
+        let byte_col = drop - self.buffer.line_start(line).unwrap_or(0);
+        let Some(row) = line
+            .checked_sub(self.scroll_row)
+            .filter(|&r| r < text_height)
+        else {
+            return;
+        };
+        let Some(col) = byte_col_to_screen_col(&self.line_cells(line, &text), byte_col)
+            .checked_sub(self.scroll_col)
+            .map(|c| c + self.gutter_width)
+            .filter(|&c| c < self.text_right())
+        else {
+            return;
+        };
+        let ch = text[byte_col..]
+            .chars()
+            .next()
+            .filter(|c| !c.is_whitespace())
+            .unwrap_or(' ');
+        self.screen
+            .put_char(row, col, ch, Color::Ansi(0), Color::Ansi(3), false);
+    }
+
+    /// Draw the blame column for `file_line`: short hash, author and age,
+    /// colored from bright (recent) to dim (old).
+    fn render_blame_cell(&mut self, screen_row: usize, file_line: usize) {
+        let bg = Color::Default;
+        let (text, fg) = match self.blame.as_ref().map(|b| b.get(file_line)) {
+            None => ("loading blame...".to_string(), Color::Color256(240)),
+            Some(None) => (String::new(), Color::Color256(240)),
+            Some(Some(info)) if info.is_uncommitted() => {
+                ("Not committed yet".to_string(), Color::Ansi(3))
+            }
+            Some(Some(info)) => {
+                let age = unix_now() - info.time;
+                let author = unicode::truncate_display(&info.author, BLAME_AUTHOR_WIDTH);
+                let text = format!(
+                    "{} {:<aw$} {:>4}",
+                    info.short_hash(),
+                    author,
+                    git::format_age(age),
+                    aw = BLAME_AUTHOR_WIDTH
+                );
+                (text, blame_age_color(age))
+            }
+        };
+        for col in 0..BLAME_WIDTH {
+            self.screen.put_char(screen_row, col, ' ', fg, bg, false);
+        }
+        let text = unicode::truncate_display(&text, BLAME_WIDTH - 1);
+        self.screen.put_str(screen_row, 0, text, fg, bg, false);
+    }
+
+    fn render_quickfix_panel(&mut self, top: usize) {
+        let rows = self.panel_height();
+        let qf = match self.quickfix {
+            Some(ref qf) if rows > 0 => qf,
+            _ => return,
+        };
+        let screen_width = self.screen.width();
+
+        // Header row
+        let header = format!(
+            " Quickfix: {} locations  (F8 next, Shift+F8 prev, Esc close)",
+            qf.entries.len()
+        );
+        for col in 0..screen_width {
+            self.screen
+                .put_char(top, col, ' ', Color::Ansi(0), Color::Ansi(6), false);
+        }
+        self.screen
+            .put_str(top, 0, &header, Color::Ansi(0), Color::Ansi(6), false);
+
+        // Keep the current entry in view
+        let visible = rows - 1;
+        let current = qf.current.unwrap_or(0);
+        let first = current
+            .saturating_sub(visible - 1)
+            .min(qf.entries.len().saturating_sub(visible));
+
+        let lines: Vec<(String, bool)> = qf.entries[first..first + visible]
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let text = format!(
+                    " {}:{}:{}: {}",
+                    e.path.to_string_lossy(),
+                    e.line,
+                    e.col,
+                    e.message
+                );
+                (text, qf.current == Some(first + i))
+            })
+            .collect();
+
+        for (i, (text, is_current)) in lines.iter().enumerate() {
+            let row = top + 1 + i;
+            let (fg, bg) = if *is_current {
+                (Color::Ansi(0), Color::Ansi(7))
+            } else {
+                (Color::Default, Color::Default)
+            };
+            for col in 0..screen_width {
+                self.screen.put_char(row, col, ' ', fg, bg, false);
+            }
+            self.screen.put_str(row, 0, text, fg, bg, false);
         }
-        terminal::flush();
     }
 
     // -----------------------------------------------------------------------
@@ -460,10 +2084,21 @@ impl Editor {
     // -----------------------------------------------------------------------
 
     fn handle_event(&mut self, event: Event) {
-        // Clear message on any event (except resize), but only when no prompt is active
-        if self.prompt.is_none() {
+        // The software cursor shows steadily while input comes
+        if event != Event::Tick {
+            self.cursor_blink = (true, Instant::now());
+        }
+        // Clear message on any event (except resize), but only when no prompt
+        // is active. Errors stay until Escape acknowledges them.
+        if self.prompt.is_none() && self.message.is_some() {
             match &event {
-                Event::Resize => {}
+                Event::Resize | Event::Background(..) | Event::Tick | Event::None => {}
+                Event::Key(ke) if self.message_type == MessageType::Error => {
+                    if ke.key == Key::Escape && !ke.ctrl && !ke.alt {
+                        self.message = None;
+                        return;
+                    }
+                }
                 _ => {
                     self.message = None;
                 }
@@ -479,467 +2114,5371 @@ impl Editor {
                 }
             }
             Event::Mouse(me) => {
-                if self.prompt.is_none() && me.button == MouseButton::Left && me.pressed {
-                    self.handle_mouse_click(me.col, me.row);
+                if self.context_menu.is_some() {
+                    if me.pressed && !me.drag {
+                        self.handle_context_menu_click(me.col, me.row);
+                    }
+                } else if self.prompt.is_none() && me.button == MouseButton::Right {
+                    if me.pressed && !me.drag {
+                        self.open_context_menu(me.col, me.row);
+                    }
+                } else if self.prompt.is_none() && me.button == MouseButton::Left {
+                    if !me.pressed {
+                        self.side_column_drag = false;
+                        self.panel_drag = false;
+                        self.gutter_drag = None;
+                        if let Some(drop) = self.text_drag.take() {
+                            self.drop_selection(drop, me.ctrl);
+                        }
+                    } else if me.drag {
+                        // Drags act on what they began on
+                        if self.side_column_drag {
+                            self.scroll_to_side_row(me.row);
+                        } else if self.panel_drag {
+                            self.resize_panel_to(me.row as usize);
+                        } else if let Some(anchor) = self.gutter_drag {
+                            let line = self.scroll_row + me.row as usize;
+                            self.select_lines(anchor, line);
+                        } else if self.text_drag.is_some() {
+                            let h = self.text_area_height().max(1);
+                            let line = self.scroll_row + (me.row as usize).min(h - 1);
+                            let line = line.min(self.buffer.line_count().saturating_sub(1));
+                            self.text_drag = Some(self.line_offset_at(line, me.col as usize));
+                        }
+                    } else if me.ctrl && self.text_offset_at(me.col, me.row).is_some() {
+                        // Ctrl+Click follows the link clicked on
+                        self.selection = None;
+                        self.handle_mouse_click(me.col, me.row);
+                        self.open_under_cursor();
+                    } else {
+                        self.handle_mouse_click(me.col, me.row);
+                    }
                 }
             }
             Event::Paste(text) => {
                 if self.prompt.is_some() {
                     // Insert pasted text into prompt input
-                    if let Some(ref mut prompt) = self.prompt {
-                        prompt.input.insert_str(prompt.cursor_pos, &text);
-                        prompt.cursor_pos += text.len();
+                    if let Some((ref mut prompt, _)) = self.prompt {
+                        prompt.paste(&text);
                     }
-                } else {
+                } else if !self.reject_read_only() {
                     self.delete_selection();
                     self.handle_paste(&text);
                 }
             }
+            Event::Text(text) => {
+                // Each character is handled as a key press; typed into the
+                // buffer, they make an undo group of their own
+                let typing = self.prompt.is_none()
+                    && self
+                        .vi
+                        .as_ref()
+                        .is_none_or(|vi| vi.mode == vi::Mode::Insert);
+                if typing {
+                    self.undo_stack.finish_group(self.cursor_state());
+                }
+                for ch in text.chars() {
+                    self.handle_event(Event::Key(KeyEvent::plain(Key::Char(ch))));
+                }
+                if typing {
+                    self.undo_stack.finish_group(self.cursor_state());
+                }
+            }
             Event::Resize => {
-                let (w, h) = self.terminal.size();
-                self.screen.resize(w as usize, h as usize);
-                self.adjust_viewport();
+                // An embedded editor takes its size from `render_into`
+                if let Some(ref mut terminal) = self.terminal {
+                    let (w, h) = terminal.size();
+                    self.screen.resize(w as usize, h as usize);
+                    self.invalidate_frame();
+                    self.adjust_viewport();
+                }
+            }
+            Event::Background(r, g, b) => {
+                // Only the reply to our query at startup; later ones would
+                // come from something else writing to the terminal
+                if self.config.background == Background::Auto {
+                    self.light_background = theme::is_light(r, g, b);
+                    self.invalidate_frame();
+                }
             }
+            Event::Tick => self.on_idle(),
             Event::None => {}
         }
     }
 
     fn handle_key(&mut self, ke: KeyEvent) {
-        // Reset quit confirmation on any key that isn't Ctrl+Q
-        if !(ke.ctrl && ke.key == Key::Char('q')) {
-            self.quit_confirm = false;
+        self.plugins.send(&plugin::Event::Key(ke.to_string()));
+
+        if self.dialog.is_some() {
+            self.handle_dialog_key(&ke);
+            return;
         }
 
-        let is_nav = matches!(
-            &ke.key,
-            Key::Up
-                | Key::Down
-                | Key::Left
-                | Key::Right
-                | Key::Home
-                | Key::End
-                | Key::PageUp
-                | Key::PageDown
-        );
+        if self.popup.is_some() && self.handle_popup_key(&ke) {
+            return;
+        }
 
-        // Before navigation: start/continue selection if shift is held
-        if is_nav && ke.shift {
-            self.start_or_continue_selection();
+        if self.context_menu.is_some() && self.handle_context_menu_key(&ke) {
+            return;
         }
 
-        match (&ke.key, ke.ctrl, ke.alt) {
-            // -- Navigation (works with and without shift) --
-            (Key::Up, false, _) => self.cursor.move_up(&self.buffer),
-            (Key::Down, false, _) => self.cursor.move_down(&self.buffer),
-            (Key::Left, false, _) => self.cursor.move_left(&self.buffer),
-            (Key::Right, false, _) => self.cursor.move_right(&self.buffer),
+        if self.options_panel.is_some() {
+            self.handle_options_panel_key(&ke);
+            return;
+        }
 
-            (Key::Left, true, _) => self.cursor.move_word_left(&self.buffer),
-            (Key::Right, true, _) => self.cursor.move_word_right(&self.buffer),
+        if self.rename_panel.is_some() {
+            self.handle_rename_panel_key(&ke);
+            return;
+        }
 
-            (Key::Home, false, _) => self.cursor.move_home(&self.buffer),
-            (Key::End, false, _) => self.cursor.move_end(&self.buffer),
+        if self.diagnostics_panel.as_ref().is_some_and(|p| p.focused)
+            && self.handle_diagnostics_panel_key(&ke)
+        {
+            return;
+        }
 
-            (Key::Home, true, _) => self.cursor.move_to_start(),
-            (Key::End, true, _) => self.cursor.move_to_end(&self.buffer),
+        if let Some(pending) = self.pending_pair.take() {
+            self.handle_pending_pair(pending, &ke);
+            return;
+        }
+
+        if let Some(typed) = self.pending_digraph.take() {
+            self.handle_pending_digraph(typed, &ke);
+            return;
+        }
+
+        if self.pager && self.handle_pager_key(&ke) {
+            return;
+        }
+
+        if ke.key == Key::Escape && self.cancel_search() {
+            return;
+        }
+
+        if self.completion.is_some() && self.handle_completion_key(&ke) {
+            return;
+        }
+
+        if self.vi.is_some() && self.handle_vi_key(&ke) {
+            return;
+        }
+
+        // Edits bound to arrows (moving lines) handle the selection themselves
+        let cmd = self.keymap.lookup(&ke);
+        let is_nav = !cmd.is_some_and(Command::is_edit)
+            && matches!(
+                &ke.key,
+                Key::Up
+                    | Key::Down
+                    | Key::Left
+                    | Key::Right
+                    | Key::Home
+                    | Key::End
+                    | Key::PageUp
+                    | Key::PageDown
+            );
+
+        // Before navigation: start/continue selection if shift is held
+        if is_nav && ke.shift {
+            self.start_or_continue_selection();
+        }
+
+        match cmd {
+            Some(cmd) => self.execute_command(cmd),
+            None => {
+                // Unbound printable keys insert text
+                if let Key::Char(ch) = ke.key
+                    && !ke.ctrl
+                    && !ke.alt
+                    && !self.reject_read_only()
+                {
+                    self.type_char(ch);
+                    self.lsp_after_typing(ch);
+                }
+            }
+        }
+
+        // After navigation: extend or clear selection
+        if is_nav {
+            if ke.shift {
+                self.extend_selection();
+            } else {
+                self.selection = None;
+            }
+        }
+    }
+
+    /// Run a registered command, timing it when profiling.
+    fn execute_command(&mut self, cmd: Command) {
+        let start = Instant::now();
+        self.run_command(cmd);
+        if self.profile.is_some() {
+            let name = match cmd {
+                Command::Script(i) => self.scripts[i].0.clone(),
+                _ => cmd.name().to_string(),
+            };
+            if let Some(profile) = self.profile.as_mut() {
+                profile.operation(&name, start.elapsed());
+            }
+        }
+    }
 
-            (Key::PageUp, false, _) => {
-                let h = self.text_area_height();
-                self.scroll_row = self.scroll_row.saturating_sub(h);
-                self.cursor.move_page_up(&self.buffer, h);
+    fn run_command(&mut self, cmd: Command) {
+        if cmd.is_edit() && self.reject_read_only() {
+            return;
+        }
+        match cmd {
+            // -- Navigation --
+            Command::CursorUp if self.column_view.is_some() => self.move_cell_vertical(false),
+            Command::CursorDown if self.column_view.is_some() => self.move_cell_vertical(true),
+            Command::CursorUp => self.move_vertically(false),
+            Command::CursorDown => self.move_vertically(true),
+            Command::CursorLeft => self.cursor.move_left(&self.buffer),
+            Command::CursorRight => self.cursor.move_right(&self.buffer),
+            Command::CursorWordLeft => self.cursor.move_word_left(&self.buffer),
+            Command::CursorWordRight => self.cursor.move_word_right(&self.buffer),
+            Command::CursorWordEnd => self.cursor.move_word_end(&self.buffer),
+            Command::CursorHome => self.cursor.move_home(&self.buffer),
+            Command::CursorLineStart => self.cursor.move_line_start(),
+            Command::CursorEnd => self.cursor.move_end(&self.buffer),
+            Command::CursorFileStart => {
+                self.record_jump();
+                self.cursor.move_to_start();
             }
-            (Key::PageDown, false, _) => {
-                let h = self.text_area_height();
-                let max_line = self.buffer.line_count().saturating_sub(1);
-                self.scroll_row = (self.scroll_row + h).min(max_line);
-                self.cursor.move_page_down(&self.buffer, h);
+            Command::CursorFileEnd => {
+                self.record_jump();
+                self.cursor.move_to_end(&self.buffer);
             }
+            Command::PageUp => self.page(false),
+            Command::PageDown => self.page(true),
+            Command::RecenterTopBottom => self.recenter_top_bottom(),
+            Command::ScrollCursorCenter => self.place_cursor_line(ViewPlace::Center),
+            Command::ScrollCursorTop => self.place_cursor_line(ViewPlace::Top),
+            Command::ScrollCursorBottom => self.place_cursor_line(ViewPlace::Bottom),
 
             // -- Editing (delete selection first if active) --
-            (Key::Char(ch), false, false) => {
-                self.delete_selection();
-                self.insert_char(*ch);
-            }
-            (Key::Enter, false, false) => {
+            Command::InsertNewline => {
                 self.delete_selection();
                 self.insert_newline();
             }
-            (Key::Tab, false, false) => {
-                self.delete_selection();
-                self.insert_tab();
+            Command::InsertTab if self.column_view.is_some() => self.move_to_cell(true),
+            Command::PrevTabStop if self.column_view.is_some() => self.move_to_cell(false),
+            Command::InsertTab => {
+                let (first, last) = self.selected_lines();
+                if self.snippet_session.is_some() {
+                    self.snippet_jump(true);
+                } else if first < last {
+                    self.indent_lines(false);
+                } else if self.selection.is_some() || !self.expand_snippet() {
+                    self.delete_selection();
+                    self.insert_tab();
+                }
+            }
+            Command::PrevTabStop => {
+                if self.snippet_session.is_some() {
+                    self.snippet_jump(false);
+                } else if !self.reject_read_only() {
+                    self.indent_lines(true);
+                }
             }
-            (Key::Backspace, false, false) => {
-                if self.delete_selection().is_none() {
+            Command::DeleteBackward => {
+                let deleted = self.delete_selection();
+                if deleted.is_none() {
+                    if self.config.auto_pairs && self.between_empty_pair() {
+                        self.delete_at_cursor();
+                    }
                     self.backspace();
                 }
             }
-            (Key::Delete, false, false) => {
-                if self.delete_selection().is_none() {
+            Command::DeleteForward => {
+                let deleted = self.delete_selection();
+                if deleted.is_none() {
                     self.delete_at_cursor();
                 }
             }
+            Command::DeleteWordBackward => self.delete_to(Cursor::move_word_left),
+            Command::DeleteWordForward => self.delete_to(Cursor::move_word_right),
+            Command::DeleteToLineStart => self.delete_to(|cursor, buf| {
+                if cursor.col == 0 {
+                    cursor.move_left(buf);
+                } else {
+                    cursor.move_line_start();
+                }
+            }),
+            Command::DeleteToLineEnd => self.delete_to(|cursor, buf| {
+                let col = cursor.col;
+                cursor.move_end(buf);
+                if cursor.col == col {
+                    cursor.move_right(buf);
+                }
+            }),
 
             // -- Clipboard --
-            (Key::Char('c'), true, false) => self.copy_selection(),
-            (Key::Char('x'), true, false) => self.cut_selection(),
-            (Key::Char('v'), true, false) => self.paste_clipboard(),
-            (Key::Char('a'), true, false) => self.select_all(),
-
-            // -- Commands --
-            (Key::Char('s'), true, false) => self.save(),
-            (Key::Char('q'), true, false) => self.quit(),
+            Command::Copy => self.copy_selection(),
+            Command::Cut => self.cut_selection(),
+            Command::Paste => self.paste_clipboard(),
+            Command::PasteColumn => self.paste_column(),
+            Command::SelectAll => self.select_all(),
+            Command::SelectHome => self.select_to(Command::CursorHome),
+            Command::SelectEnd => self.select_to(Command::CursorEnd),
+            Command::SelectToFileStart => self.select_to(Command::CursorFileStart),
+            Command::SelectToFileEnd => self.select_to(Command::CursorFileEnd),
+
+            Command::Complete => self.start_completion(),
+
+            // -- Pairs --
+            Command::SurroundSelection => {
+                if self.selection_range().is_some_and(|(s, e)| s < e) {
+                    self.pending_pair = Some(PendingPair::Surround);
+                    self.set_message(strings::get("prompt_surround"), MessageType::Info);
+                } else {
+                    self.set_message(strings::get("no_selection"), MessageType::Warning);
+                }
+            }
+            Command::DeleteSurround => {
+                self.pending_pair = Some(PendingPair::Delete);
+                self.set_message(strings::get("prompt_delete_surrounding"), MessageType::Info);
+            }
+            Command::ChangeSurround => {
+                self.pending_pair = Some(PendingPair::Change);
+                self.set_message(strings::get("prompt_change_surrounding"), MessageType::Info);
+            }
 
             // -- Undo/Redo --
-            (Key::Char('z'), true, false) => {
-                self.selection = None;
-                let cs = self.cursor_state();
-                if let Some(restored) = self.undo_stack.undo(&mut self.buffer, cs) {
-                    self.restore_cursor(restored);
-                    self.set_message("Undo", MessageType::Info);
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+
+            // -- Line operations --
+            Command::MoveLineUp => self.move_lines(true),
+            Command::MoveLineDown => self.move_lines(false),
+            Command::DuplicateLine => self.duplicate_lines(),
+            Command::JoinLines => self.join_lines(),
+            Command::DeleteLine => self.delete_lines(),
+            Command::ToggleComment => self.toggle_comment(),
+
+            // -- Text transforms --
+            Command::UpperCase => self.change_case(Case::Upper),
+            Command::LowerCase => self.change_case(Case::Lower),
+            Command::TitleCase => self.change_case(Case::Title),
+            Command::SwapCase => self.change_case(Case::Swap),
+            Command::TransposeChars => self.transpose(transform::transpose_chars),
+            Command::TransposeWords => self.transpose(transform::transpose_words),
+            Command::SortLines => self.sort_lines(SortOptions::default()),
+            Command::SortLinesDescending => self.sort_lines(SortOptions {
+                descending: true,
+                ..SortOptions::default()
+            }),
+            Command::SortLinesNumeric => self.sort_lines(SortOptions {
+                numeric: true,
+                ..SortOptions::default()
+            }),
+            Command::SortLinesIgnoreCase => self.sort_lines(SortOptions {
+                ignore_case: true,
+                ..SortOptions::default()
+            }),
+            Command::UniqueLines => self.unique_lines(false),
+            Command::UniqueLinesAdjacent => self.unique_lines(true),
+            Command::IncrementNumber => self.add_to_number(1),
+            Command::DecrementNumber => self.add_to_number(-1),
+            Command::ReflowParagraph => self.reflow_paragraph(),
+            Command::FormatJson => self.format_json(false),
+            Command::MinifyJson => self.format_json(true),
+            Command::InsertChar => self.open_char_picker(),
+            Command::Digraph => {
+                self.pending_digraph = Some(String::new());
+                self.set_message(
+                    &strings::format("prompt_digraph", &[&""]),
+                    MessageType::Info,
+                );
+            }
+            Command::DescribeChar => self.describe_char(),
+
+            // -- Files & buffers --
+            Command::Save => self.save(),
+            Command::Quit => self.quit(),
+            Command::OpenFile => {
+                let prompt =
+                    Prompt::new(strings::get("prompt_open")).with_validation(not_directory);
+                self.open_prompt(prompt, PromptAction::OpenFile)
+            }
+            Command::OpenUnderCursor => self.open_under_cursor(),
+            Command::InsertFile => {
+                let prompt =
+                    Prompt::new(strings::get("prompt_insert_file")).with_validation(not_directory);
+                self.open_prompt(prompt, PromptAction::InsertFile)
+            }
+            Command::WriteSelection => {
+                if self
+                    .selection_range()
+                    .is_some_and(|(start, end)| start < end)
+                {
+                    let prompt = Prompt::new(strings::get("prompt_write_selection"))
+                        .with_validation(not_directory);
+                    self.open_prompt(prompt, PromptAction::WriteSelection);
                 } else {
-                    self.set_message("Nothing to undo", MessageType::Warning);
+                    self.set_message(strings::get("no_selection"), MessageType::Warning);
                 }
             }
-            (Key::Char('y'), true, false) => {
-                self.selection = None;
-                if let Some(restored) = self.undo_stack.redo(&mut self.buffer) {
-                    self.restore_cursor(restored);
-                    self.set_message("Redo", MessageType::Info);
-                } else {
-                    self.set_message("Nothing to redo", MessageType::Warning);
+            Command::Export => {
+                let mut prompt =
+                    Prompt::new(strings::get("prompt_export")).with_validation(not_directory);
+                // Default to an HTML page beside the file
+                if let Some(path) = self.buffer.file_path() {
+                    prompt = prompt.with_default(&format!("{}.html", path.display()));
                 }
+                self.open_prompt(prompt, PromptAction::Export)
+            }
+            Command::CloseBuffer => self.close_buffer(),
+            Command::DeleteFile => self.delete_file(),
+            Command::UndoDeleteFile => self.undo_delete_file(),
+            Command::PrevBuffer => {
+                self.record_jump();
+                self.prev_buffer();
+            }
+            Command::NextBuffer => {
+                self.record_jump();
+                self.next_buffer();
+            }
+            Command::ToggleFollow => self.toggle_follow(),
+            Command::ToggleColumnView => self.toggle_column_view(),
+            Command::SetFiletype => {
+                let prompt = Prompt::new(strings::get("prompt_filetype"))
+                    .with_default(&self.buffer.filetype());
+                self.open_prompt(prompt, PromptAction::SetFiletype)
+            }
+            Command::BufferOptions => {
+                self.options_panel = Some(OptionsPanel {
+                    list: ListState::default(),
+                    typed: None,
+                })
             }
 
             // -- Search --
-            (Key::Char('f'), true, false) => {
-                self.open_find_prompt(PromptAction::Find);
+            Command::Find => self.open_find_prompt(PromptAction::Find),
+            Command::Replace => self.open_find_prompt(PromptAction::Replace),
+            Command::ReplaceInBuffers => {
+                let prompt = Prompt::new(strings::get("prompt_replace_in_buffers"))
+                    .with_input(&self.prefill_search_text());
+                self.open_prompt(prompt, PromptAction::ReplaceInBuffers);
             }
-            (Key::Char('h'), true, false) => {
-                self.open_find_prompt(PromptAction::Replace);
+            Command::UndoReplaceInBuffers => self.undo_replace_in_buffers(),
+            Command::RenameInProject => {
+                let prompt = Prompt::new(strings::get("prompt_rename_in_project"))
+                    .with_input(&self.prefill_search_text());
+                self.open_prompt(prompt, PromptAction::RenameInProject);
             }
-            (Key::F(3), false, false) if !ke.shift => {
-                self.search_next();
+            Command::FindNext => self.search_next(),
+            Command::FindPrev => self.search_prev(),
+
+            // -- Tools --
+            Command::RunShell => {
+                self.start_prompt(strings::get("prompt_run"), PromptAction::RunCommand)
             }
-            (Key::F(3), false, false) if ke.shift => {
-                self.search_prev();
+            Command::Build => self.run_build(),
+            Command::NextError => self.next_error(),
+            Command::PrevError => self.prev_error(),
+            Command::HidePanel => {
+                self.quickfix_visible = false;
+                self.diagnostics_panel = None;
+                self.snippet_session = None;
+                self.signature = None;
             }
 
-            // -- File --
-            (Key::Char('o'), true, false) => {
-                self.start_prompt("Open: ", PromptAction::OpenFile);
+            // -- Language server --
+            Command::ShowDiagnostics => self.show_diagnostics(),
+            Command::DiagnosticsPanel => self.toggle_diagnostics_panel(),
+            Command::NextDiagnostic => self.step_diagnostic(true),
+            Command::PrevDiagnostic => self.step_diagnostic(false),
+            Command::ShowMessages => self.show_message_log(),
+            Command::DocumentStats => self.show_document_stats(),
+            Command::ProfileReport => match self.profile {
+                Some(ref profile) => {
+                    let report = profile.report();
+                    self.show_scratch(PROFILE_BUFFER_NAME, &report);
+                    self.buffer.set_read_only(true);
+                }
+                None => self.set_message(strings::get("profiling_off"), MessageType::Info),
+            },
+            Command::RunCommandByName => {
+                self.start_prompt(strings::get("prompt_command"), PromptAction::ExCommand)
             }
+            Command::ShowHelp => {
+                let scripts: Vec<&str> = self.scripts.iter().map(|(n, _)| n.as_str()).collect();
+                let text = command::help_text(&self.keymap, &scripts);
+                self.show_scratch(HELP_BUFFER_NAME, &text);
+                self.buffer.set_read_only(true);
+            }
+            Command::GotoDefinition => self.goto_definition(),
+            Command::JumpToSymbol => self.open_symbol_picker(),
+            Command::FindFile => self.open_file_finder(Path::new(".")),
+            Command::Hover => self.lsp_request_at("textDocument/hover"),
+
+            // -- Spelling --
+            Command::ToggleSpellCheck => self.toggle_spell_check(),
+            Command::NextMisspelling => self.jump_to_misspelling(true),
+            Command::PrevMisspelling => self.jump_to_misspelling(false),
+            Command::SpellSuggest => self.spell_suggest(),
+            Command::AddWordToDictionary => self.add_word_to_list(false),
+            Command::AddWordToProject => self.add_word_to_list(true),
+
+            // -- Jump list --
+            Command::JumpBack => self.navigate_jumps(false, false),
+            Command::JumpForward => self.navigate_jumps(true, false),
+            Command::JumpBackInBuffer => self.navigate_jumps(false, true),
+            Command::JumpForwardInBuffer => self.navigate_jumps(true, true),
+
+            // -- Git --
+            Command::ToggleBlame => self.toggle_blame(),
+            Command::CopyBlameHash => self.copy_blame_hash(),
+            Command::NextHunk => self.jump_to_hunk(true),
+            Command::PrevHunk => self.jump_to_hunk(false),
+            Command::GotoPatchSource => self.goto_patch_source(),
+            Command::StageHunk => self.stage_hunk(),
+            Command::RevertHunk => self.revert_hunk(),
+
+            Command::Script(i) => self.run_script(i),
+        }
+    }
 
-            _ => {}
+    /// The built-in command or user script called `name`.
+    fn command_named(&self, name: &str) -> Option<Command> {
+        Command::from_name(name).or_else(|| {
+            self.scripts
+                .iter()
+                .position(|(n, _)| n == name)
+                .map(Command::Script)
+        })
+    }
+
+    fn undo(&mut self) {
+        self.selection = None;
+        let cs = self.cursor_state();
+        if let Some(restored) = self.undo_stack.undo(&mut self.buffer, cs) {
+            self.restore_cursor(restored);
+            self.sync_saved_state();
+            self.set_message(strings::get("undo"), MessageType::Info);
+        } else {
+            self.set_message(strings::get("nothing_to_undo"), MessageType::Warning);
         }
+    }
 
-        // After navigation: extend or clear selection
-        if is_nav {
-            if ke.shift {
-                self.extend_selection();
-            } else {
-                self.selection = None;
-            }
+    fn redo(&mut self) {
+        self.selection = None;
+        if let Some(restored) = self.undo_stack.redo(&mut self.buffer) {
+            self.restore_cursor(restored);
+            self.sync_saved_state();
+            self.set_message(strings::get("redo"), MessageType::Info);
+        } else {
+            self.set_message(strings::get("nothing_to_redo"), MessageType::Warning);
         }
     }
 
     // -----------------------------------------------------------------------
-    // Selection helpers
+    // Pager mode
     // -----------------------------------------------------------------------
 
-    fn start_or_continue_selection(&mut self) {
-        if self.selection.is_none() {
-            let offset = self.cursor.byte_offset(&self.buffer);
-            self.selection = Some(Selection {
-                anchor: offset,
-                head: offset,
-            });
+    /// Keys in pager mode, after `less`: any key stops follow mode, and
+    /// letters page, scroll, search and quit. Returns false for keys the
+    /// regular keymap handles (arrows, PageUp/PageDown, Ctrl chords).
+    fn handle_pager_key(&mut self, ke: &KeyEvent) -> bool {
+        if self.follow.take().is_some() {
+            return true;
         }
-    }
-
-    fn extend_selection(&mut self) {
-        if let Some(ref mut sel) = self.selection {
-            sel.head = self.cursor.byte_offset(&self.buffer);
+        let ch = match ke.key {
+            Key::Char(ch) if !ke.ctrl && !ke.alt => ch,
+            Key::Enter => 'j',
+            _ => return false,
+        };
+        let half = (self.text_area_height() / 2).max(1) as isize;
+        match ch {
+            'q' | 'Q' => self.execute_command(Command::Quit),
+            ' ' | 'f' => self.page(true),
+            'b' => self.page(false),
+            'j' | 'e' => self.scroll_lines(1),
+            'k' | 'y' => self.scroll_lines(-1),
+            'd' => self.scroll_lines(half),
+            'u' => self.scroll_lines(-half),
+            'g' | '<' => self.execute_command(Command::CursorFileStart),
+            'G' | '>' => self.execute_command(Command::CursorFileEnd),
+            '/' | '?' => self.execute_command(Command::Find),
+            'n' => self.execute_command(Command::FindNext),
+            'N' => self.execute_command(Command::FindPrev),
+            'F' => {
+                self.toggle_follow();
+                let name = self.buffer_display_name();
+                self.set_message(
+                    &strings::format("following_any_key", &[&name]),
+                    MessageType::Info,
+                );
+            }
+            _ => {}
         }
+        true
     }
 
-    fn selection_range(&self) -> Option<(usize, usize)> {
-        self.selection.map(|sel| {
-            let start = sel.anchor.min(sel.head);
-            let end = sel.anchor.max(sel.head);
-            (start, end)
-        })
+    /// Scroll the view `delta` lines, taking the cursor along.
+    fn scroll_lines(&mut self, delta: isize) {
+        let lines = self.buffer.line_count();
+        let max_row = lines.saturating_sub(self.text_area_height());
+        let row = self.scroll_row.saturating_add_signed(delta).min(max_row);
+        let moved = row as isize - self.scroll_row as isize;
+        self.scroll_row = row;
+        let line = self.cursor.line.saturating_add_signed(moved);
+        self.cursor.set_position(line, 0, &self.buffer);
     }
 
-    /// Delete the selected text, reposition cursor to selection start, clear selection.
-    /// Returns the deleted text if there was a selection.
-    fn delete_selection(&mut self) -> Option<String> {
-        let (start, end) = self.selection_range()?;
-        if start == end {
-            self.selection = None;
-            return None;
+    // -----------------------------------------------------------------------
+    // Follow mode
+    // -----------------------------------------------------------------------
+
+    /// Start or stop following the active buffer's file. Following starts
+    /// from what the buffer holds, so anything written since it was loaded
+    /// comes in on the next poll.
+    fn toggle_follow(&mut self) {
+        if self.follow.take().is_some() {
+            self.set_message(strings::get("follow_off"), MessageType::Info);
+            return;
         }
-        let before = self.cursor_state();
-        let deleted = self.buffer.slice(start, end);
-        self.buffer.delete(start, end - start);
-        self.undo_stack.record(
-            Operation::Delete {
-                pos: start,
-                text: deleted.clone(),
-            },
-            before,
-            GroupContext::Other,
-        );
-        // Reposition cursor to selection start
-        let line = self.buffer.byte_to_line(start);
-        let line_start = self.buffer.line_start(line).unwrap_or(0);
-        let col = start - line_start;
-        self.cursor.set_position(line, col, &self.buffer);
+        let Some(path) = self.buffer.file_path() else {
+            self.set_message(strings::get("follow_needs_file"), MessageType::Warning);
+            return;
+        };
+        self.follow = Some(Follow::new(path, self.buffer.len() as u64));
+        self.cursor.move_to_end(&self.buffer);
         self.selection = None;
-        Some(deleted)
+        self.follow_file();
+        let name = self.buffer_display_name();
+        self.set_message(&strings::format("following", &[&name]), MessageType::Info);
     }
 
-    fn copy_selection(&mut self) {
-        if let Some((start, end)) = self.selection_range() {
-            if start == end {
-                // No selection: copy current line
-                self.copy_current_line();
+    /// Whether new data should scroll the view: the cursor is on the last
+    /// line. Moving off it pauses following; going back to the end resumes.
+    fn follow_pinned(&self) -> bool {
+        self.cursor.line + 1 >= self.buffer.line_count()
+    }
+
+    /// Take in what was written to the followed file since the last look.
+    /// Only the new bytes are read. Polling waits while the buffer has
+    /// unsaved edits, since they would no longer match the file.
+    fn follow_file(&mut self) {
+        if self.buffer.is_modified() {
+            return;
+        }
+        let pinned = self.follow_pinned();
+        let Some(ref mut follow) = self.follow else {
+            return;
+        };
+        match follow.poll() {
+            Ok(Growth::Unchanged) => return,
+            Ok(Growth::Appended(text)) => self.buffer.insert(self.buffer.len(), &text),
+            Ok(Growth::Replaced(text)) => {
+                self.buffer.delete(0, self.buffer.len());
+                self.buffer.insert(0, &text);
+                self.undo_stack = UndoStack::new();
+                self.search = None;
+                self.selection = None;
+                self.cursor
+                    .set_position(self.cursor.line, self.cursor.col, &self.buffer);
+            }
+            Err(e) => {
+                self.follow = None;
+                self.set_message(&e, MessageType::Error);
                 return;
             }
-            let text = self.buffer.slice(start, end);
-            let len = text.chars().count();
-            self.clipboard = text.clone();
-            terminal::set_clipboard_osc52(&text);
-            self.set_message(&format!("Copied {} chars", len), MessageType::Info);
-        } else {
-            // No selection: copy current line
-            self.copy_current_line();
+        }
+        self.buffer.mark_saved();
+        if pinned {
+            self.cursor.move_to_end(&self.buffer);
         }
     }
 
-    fn copy_current_line(&mut self) {
-        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
-        let text = format!("{}\n", line_text);
-        let len = line_text.chars().count();
-        self.clipboard = text.clone();
-        terminal::set_clipboard_osc52(&self.clipboard);
-        self.set_message(&format!("Copied line ({} chars)", len), MessageType::Info);
-    }
+    // -----------------------------------------------------------------------
+    // Column view
+    // -----------------------------------------------------------------------
 
-    fn cut_selection(&mut self) {
-        if let Some((start, end)) = self.selection_range() {
-            if start == end {
-                self.cut_current_line();
-                return;
-            }
-            let text = self.delete_selection().unwrap_or_default();
-            let len = text.chars().count();
-            self.clipboard = text.clone();
-            terminal::set_clipboard_osc52(&text);
-            self.set_message(&format!("Cut {} chars", len), MessageType::Info);
-        } else {
-            self.cut_current_line();
+    /// Show the active CSV or TSV buffer as aligned columns, or go back to
+    /// the delimited text.
+    fn toggle_column_view(&mut self) {
+        if self.column_view.take().is_some() {
+            return;
         }
+        let filetype = self.buffer.filetype();
+        let Some(delim) = csv::delimiter(&filetype) else {
+            self.set_message(
+                strings::get("column_view_needs_table"),
+                MessageType::Warning,
+            );
+            return;
+        };
+        self.column_view = Some(Columns::measure(delim, []));
+        self.columns_version = None;
+        self.measure_columns();
     }
 
-    fn cut_current_line(&mut self) {
-        let before = self.cursor_state();
-        let line = self.cursor.line;
-        let line_start = self.buffer.line_start(line).unwrap_or(0);
-        let line_end = self.buffer.line_end(line).unwrap_or(0);
-        // Include the newline if not the last line
-        let end = if line + 1 < self.buffer.line_count() {
-            line_end + 1
-        } else {
-            line_end
+    /// Point out the column view when a table is opened.
+    fn offer_column_view(&mut self) {
+        let filetype = self.buffer.filetype();
+        if self.column_view.is_some() || csv::delimiter(&filetype).is_none() {
+            return;
+        }
+        let key = match self.keymap.keys_for(Command::ToggleColumnView).next() {
+            Some(key) => key.to_string(),
+            None => "toggle-column-view".to_string(),
         };
-        let text = self.buffer.slice(line_start, end);
-        let len = text.chars().count();
-        self.buffer.delete(line_start, end - line_start);
-        self.undo_stack.record(
-            Operation::Delete {
-                pos: line_start,
-                text: text.clone(),
-            },
-            before,
-            GroupContext::Cut,
+        let name = self.buffer_display_name();
+        self.set_message(
+            &strings::format("column_view_offer", &[&name, &key]),
+            MessageType::Info,
         );
-        self.cursor.clamp(&self.buffer);
-        self.cursor.col = 0;
-        self.cursor.desired_col = 0;
-        self.clipboard = text.clone();
-        terminal::set_clipboard_osc52(&text);
-        self.set_message(&format!("Cut line ({} chars)", len), MessageType::Info);
     }
 
-    fn paste_clipboard(&mut self) {
-        if self.clipboard.is_empty() {
-            self.set_message("Clipboard is empty", MessageType::Warning);
+    /// Treat the active buffer as `name` ("auto" detects it from the path
+    /// again), for comment toggling, snippets, spelling and the rest.
+    fn set_filetype(&mut self, name: &str) {
+        let name = name.trim().trim_start_matches('.').to_lowercase();
+        if name.is_empty() {
             return;
         }
-        // Delete selection if active
-        self.delete_selection();
-        let text = self.clipboard.clone();
-        self.handle_paste(&text);
+        self.buffer.set_filetype((name != "auto").then_some(name));
+        let filetype = self.buffer.filetype();
+        self.spell_version = None;
+        if csv::delimiter(&filetype).is_none() {
+            self.column_view = None;
+        }
+        self.set_message(
+            &strings::format("filetype_set", &[&filetype]),
+            MessageType::Info,
+        );
+        self.offer_column_view();
     }
 
-    fn select_all(&mut self) {
-        let len = self.buffer.len();
-        self.selection = Some(Selection {
-            anchor: 0,
-            head: len,
-        });
-        self.cursor.move_to_end(&self.buffer);
+    /// Size the columns to the buffer again after it changed.
+    fn measure_columns(&mut self) {
+        let Some(ref mut columns) = self.column_view else {
+            return;
+        };
+        let version = self.buffer.version();
+        if self.columns_version != Some(version) {
+            let text = self.buffer.contents();
+            *columns = Columns::measure(columns.delimiter(), text.split('\n'));
+            self.columns_version = Some(version);
+        }
     }
 
-    // -----------------------------------------------------------------------
-    // Undo helpers
-    // -----------------------------------------------------------------------
-
-    fn cursor_state(&self) -> CursorState {
-        CursorState {
-            line: self.cursor.line,
-            col: self.cursor.col,
-            desired_col: self.cursor.desired_col,
-        }
+    /// Move to the start of the next cell, or the previous one; past the
+    /// last cell of a row, on to the next row.
+    fn move_to_cell(&mut self, forward: bool) {
+        let Some(delim) = self.column_view.as_ref().map(Columns::delimiter) else {
+            return;
+        };
+        let text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let fields = csv::fields(&text, delim);
+        let idx = csv::field_at(&fields, self.cursor.col);
+        let (line, col) = if forward {
+            match fields.get(idx + 1) {
+                Some(field) => (self.cursor.line, field.start),
+                None if self.cursor.line + 1 < self.buffer.line_count() => {
+                    (self.cursor.line + 1, 0)
+                }
+                None => return,
+            }
+        } else if idx > 0 {
+            (self.cursor.line, fields[idx - 1].start)
+        } else if self.cursor.line > 0 {
+            let line = self.cursor.line - 1;
+            let text = self.buffer.get_line(line).unwrap_or_default();
+            let last = csv::fields(&text, delim).pop().map_or(0, |f| f.start);
+            (line, last)
+        } else {
+            return;
+        };
+        self.cursor.set_position(line, col, &self.buffer);
     }
 
-    fn restore_cursor(&mut self, state: CursorState) {
-        self.cursor.line = state.line;
-        self.cursor.col = state.col;
-        self.cursor.desired_col = state.desired_col;
-        self.cursor.clamp(&self.buffer);
+    /// Move up or down a row, staying in the same column and at the same
+    /// place in the cell where the cell is long enough.
+    fn move_cell_vertical(&mut self, down: bool) {
+        let Some(delim) = self.column_view.as_ref().map(Columns::delimiter) else {
+            return;
+        };
+        let line = if down && self.cursor.line + 1 < self.buffer.line_count() {
+            self.cursor.line + 1
+        } else if !down && self.cursor.line > 0 {
+            self.cursor.line - 1
+        } else {
+            return;
+        };
+        let text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let fields = csv::fields(&text, delim);
+        let idx = csv::field_at(&fields, self.cursor.col);
+        let offset = self.cursor.col.saturating_sub(fields[idx].start);
+
+        let text = self.buffer.get_line(line).unwrap_or_default();
+        let fields = csv::fields(&text, delim);
+        let field = &fields[idx.min(fields.len() - 1)];
+        let mut col = (field.start + offset).min(field.end);
+        while !text.is_char_boundary(col) {
+            col -= 1;
+        }
+        self.cursor.set_position(line, col, &self.buffer);
     }
 
     // -----------------------------------------------------------------------
-    // Editing operations
+    // Vi mode
     // -----------------------------------------------------------------------
 
-    fn insert_char(&mut self, ch: char) {
+    /// Route a key through the vi layer. Returns false when the key should
+    /// fall through to the regular keymap (insert mode, Ctrl/Alt chords).
+    fn handle_vi_key(&mut self, ke: &KeyEvent) -> bool {
+        let mode = match self.vi {
+            Some(ref vi) => vi.mode,
+            None => return false,
+        };
+
+        // Plain keys typed in insert mode are part of the change being
+        // recorded; Ctrl/Alt commands are not.
+        if !self.vi_replaying && (mode != vi::Mode::Insert || !(ke.ctrl || ke.alt)) {
+            self.vi_keys.push(ke.clone());
+        }
+
+        if mode == vi::Mode::Insert {
+            if ke.key == Key::Escape && !ke.ctrl && !ke.alt {
+                if let Some(ref mut vi) = self.vi {
+                    vi.mode = vi::Mode::Normal;
+                }
+                if self.cursor.col > 0 {
+                    self.cursor.move_left(&self.buffer);
+                }
+                self.finish_vi_change();
+                return true;
+            }
+            return false;
+        }
+
+        let action = match self.vi {
+            Some(ref mut vi) => vi.feed(ke),
+            None => return false,
+        };
+        if action == vi::Action::Pending {
+            return true;
+        }
+        if action.is_change() && self.reject_read_only() {
+            self.vi_keys.clear();
+            return true;
+        }
+        if action.is_change() {
+            // Changes that enter insert mode keep recording until Escape
+            if self.vi_mode() != vi::Mode::Insert {
+                self.finish_vi_change();
+            }
+        } else if !self.vi_replaying {
+            self.vi_keys.clear();
+        }
+
+        match action {
+            vi::Action::Pending | vi::Action::None => {}
+            vi::Action::PassThrough => return false,
+            vi::Action::Move(motion, count) => {
+                self.vi_motions(motion, count);
+                self.update_visual_selection();
+            }
+            vi::Action::Operate(op, motion, count) => self.vi_operate(op, motion, count),
+            vi::Action::OperateLines(op, count) => self.vi_operate_lines(op, count),
+            vi::Action::VisualOperate(op) => {
+                if let Some((start, end)) = self.selection_range() {
+                    self.vi_apply(op, start, end);
+                }
+                self.selection = None;
+            }
+            vi::Action::Insert(at) => self.vi_insert(at),
+            vi::Action::EnterVisual => {
+                self.vi_visual_anchor = self.cursor.byte_offset(&self.buffer);
+                self.update_visual_selection();
+            }
+            vi::Action::ExitVisual => self.selection = None,
+            vi::Action::Paste { after, count } => self.vi_paste(after, count),
+            vi::Action::Undo => self.undo(),
+            vi::Action::Redo => self.redo(),
+            vi::Action::Repeat(count) => self.vi_repeat(count),
+            vi::Action::AddToNumber(delta) => self.add_to_number(delta),
+            vi::Action::CommandLine => {
+                self.start_prompt(strings::get("prompt_ex"), PromptAction::ExCommand)
+            }
+        }
+        true
+    }
+
+    /// Run a `:` command: a line number, `w`/`q`/`wq`, or any registered
+    /// command name.
+    fn execute_ex_command(&mut self, input: &str) {
+        if let Ok(line) = input.parse::<usize>() {
+            self.record_jump();
+            self.goto_line_col(line.saturating_sub(1), 0);
+            return;
+        }
+        if let Some(filetype) = ex_filetype(input) {
+            self.set_filetype(filetype);
+            return;
+        }
+        match input {
+            "" => {}
+            "w" => self.execute_command(Command::Save),
+            "q" => self.execute_command(Command::Quit),
+            "wq" | "x" => {
+                self.execute_command(Command::Save);
+                if !self.buffer.is_modified() {
+                    self.execute_command(Command::Quit);
+                }
+            }
+            name => match self.command_named(name) {
+                Some(cmd) => self.execute_command(cmd),
+                None => self.set_message(
+                    &strings::format("unknown_command", &[&name]),
+                    MessageType::Error,
+                ),
+            },
+        }
+    }
+
+    fn vi_mode(&self) -> vi::Mode {
+        self.vi.as_ref().map_or(vi::Mode::Insert, |vi| vi.mode)
+    }
+
+    /// The keys typed since the last completed change become the `.` macro.
+    fn finish_vi_change(&mut self) {
+        if !self.vi_replaying {
+            self.vi_last_change = std::mem::take(&mut self.vi_keys);
+        }
+    }
+
+    fn vi_repeat(&mut self, count: usize) {
+        if self.vi_last_change.is_empty() {
+            return;
+        }
+        let keys = self.vi_last_change.clone();
+        self.vi_replaying = true;
+        for _ in 0..count {
+            for ke in &keys {
+                self.handle_key(ke.clone());
+            }
+        }
+        self.vi_replaying = false;
+    }
+
+    /// Run `motion` `count` times, or until the cursor stops moving.
+    fn vi_motions(&mut self, motion: vi::Motion, count: usize) {
+        for _ in 0..count {
+            let before = (self.cursor.line, self.cursor.col);
+            self.vi_motion(motion);
+            if (self.cursor.line, self.cursor.col) == before {
+                break;
+            }
+        }
+    }
+
+    /// Run one motion through the command registry. Horizontal motions stay
+    /// within the line, as in vi.
+    fn vi_motion(&mut self, motion: vi::Motion) {
+        let line_len = self
+            .buffer
+            .get_line(self.cursor.line)
+            .map_or(0, |l| l.len());
+        let cmd = match motion {
+            vi::Motion::Left if self.cursor.col == 0 => return,
+            vi::Motion::Right if self.cursor.col >= line_len => return,
+            vi::Motion::Left => Command::CursorLeft,
+            vi::Motion::Right => Command::CursorRight,
+            vi::Motion::Up => Command::CursorUp,
+            vi::Motion::Down => Command::CursorDown,
+            vi::Motion::WordForward => Command::CursorWordRight,
+            vi::Motion::WordBackward => Command::CursorWordLeft,
+            vi::Motion::WordEnd => Command::CursorWordEnd,
+            vi::Motion::LineStart => Command::CursorLineStart,
+            vi::Motion::LineEnd => Command::CursorEnd,
+            vi::Motion::FileStart => Command::CursorFileStart,
+            vi::Motion::FileEnd => {
+                self.record_jump();
+                self.goto_line_col(self.buffer.line_count().saturating_sub(1), 0);
+                return;
+            }
+            vi::Motion::GotoLine(n) => {
+                self.record_jump();
+                self.goto_line_col(n.saturating_sub(1), 0);
+                return;
+            }
+        };
+        self.execute_command(cmd);
+    }
+
+    /// In visual mode the selection covers the anchor and cursor characters
+    /// inclusively.
+    fn update_visual_selection(&mut self) {
+        if self.vi_mode() != vi::Mode::Visual {
+            return;
+        }
+        let anchor = self.vi_visual_anchor;
+        let head = self.cursor.byte_offset(&self.buffer);
+        let char_end = |pos: usize| pos + self.buffer.char_at(pos).map_or(0, |c| c.len_utf8());
+        self.selection = Some(if head >= anchor {
+            Selection {
+                anchor,
+                head: char_end(head),
+            }
+        } else {
+            Selection {
+                anchor: char_end(anchor),
+                head,
+            }
+        });
+    }
+
+    fn vi_operate(&mut self, op: vi::Operator, motion: vi::Motion, count: usize) {
+        // `cw` changes to the end of the word, like vi
+        let motion = if op == vi::Operator::Change && motion == vi::Motion::WordForward {
+            vi::Motion::WordEnd
+        } else {
+            motion
+        };
+        let start_line = self.cursor.line;
+        let start = self.cursor.byte_offset(&self.buffer);
+        self.vi_motions(motion, count);
+        let end = self.cursor.byte_offset(&self.buffer);
+
+        if motion.is_linewise() {
+            let first = start_line.min(self.cursor.line);
+            let last = start_line.max(self.cursor.line);
+            self.vi_apply_lines(op, first, last);
+            return;
+        }
+
+        let (from, mut to) = (start.min(end), start.max(end));
+        if motion.is_inclusive() {
+            to += self.buffer.char_at(to).map_or(0, |c| c.len_utf8());
+        }
+        // Word motions never swallow the line break
+        if motion == vi::Motion::WordForward {
+            let line = self.buffer.byte_to_line(from);
+            let line_end = self.buffer.line_end(line).unwrap_or(to);
+            to = to.min(line_end.max(from));
+        }
+        self.vi_apply(op, from, to);
+    }
+
+    fn vi_operate_lines(&mut self, op: vi::Operator, count: usize) {
+        let first = self.cursor.line;
+        let last = (first + count - 1).min(self.buffer.line_count().saturating_sub(1));
+        self.vi_apply_lines(op, first, last);
+    }
+
+    /// Apply an operator to whole lines `first..=last`.
+    fn vi_apply_lines(&mut self, op: vi::Operator, first: usize, last: usize) {
+        let mut text = String::new();
+        for line in first..=last {
+            text.push_str(&self.buffer.get_line(line).unwrap_or_default());
+            text.push('\n');
+        }
+        self.vi_set_register(text, true);
+
+        let mut start = self.buffer.line_start(first).unwrap_or(0);
+        let mut end = self
+            .buffer
+            .line_start(last + 1)
+            .unwrap_or(self.buffer.len());
+        match op {
+            vi::Operator::Yank => end = start,
+            // Keep an empty line to type into
+            vi::Operator::Change => end = self.buffer.line_end(last).unwrap_or(end),
+            // Deleting through the last line takes the preceding newline
+            vi::Operator::Delete if last + 1 >= self.buffer.line_count() && first > 0 => {
+                start -= 1;
+            }
+            vi::Operator::Delete => {}
+        }
+        self.vi_delete_range(start, end);
+        if op != vi::Operator::Change {
+            let line = first.min(self.buffer.line_count().saturating_sub(1));
+            self.goto_line_col(line, 0);
+        }
+    }
+
+    /// Delete, change or yank the characters in `start..end`.
+    fn vi_apply(&mut self, op: vi::Operator, start: usize, end: usize) {
+        self.vi_set_register(self.buffer.slice(start, end), false);
+        if op == vi::Operator::Yank {
+            self.vi_delete_range(start, start);
+        } else {
+            self.vi_delete_range(start, end);
+        }
+    }
+
+    fn vi_set_register(&mut self, text: String, linewise: bool) {
+        terminal::set_clipboard_osc52(&text);
+        self.clipboard = text;
+        self.vi_linewise = linewise;
+    }
+
+    /// Delete `start..end` (possibly empty) and leave the cursor at `start`.
+    fn vi_delete_range(&mut self, start: usize, end: usize) {
+        self.selection = None;
+        if end > start {
+            let before = self.cursor_state();
+            let deleted = self.buffer.delete(start, end - start);
+            self.undo_stack.record(
+                Operation::Delete {
+                    pos: start,
+                    text: deleted,
+                },
+                before,
+                GroupContext::Cut,
+            );
+            self.update_gutter_width();
+        }
+        let line = self.buffer.byte_to_line(start);
+        let col = start - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+    }
+
+    fn vi_insert(&mut self, at: vi::InsertAt) {
+        match at {
+            vi::InsertAt::Cursor => {}
+            vi::InsertAt::After => self.vi_motion(vi::Motion::Right),
+            vi::InsertAt::LineStart => self.cursor.move_home(&self.buffer),
+            vi::InsertAt::LineEnd => self.cursor.move_end(&self.buffer),
+            vi::InsertAt::LineBelow => {
+                self.cursor.move_end(&self.buffer);
+                self.insert_newline();
+            }
+            vi::InsertAt::LineAbove => {
+                self.cursor.move_line_start();
+                self.insert_newline();
+                self.cursor.move_up(&self.buffer);
+            }
+        }
+    }
+
+    fn vi_paste(&mut self, after: bool, count: usize) {
+        if self.clipboard.is_empty() {
+            return;
+        }
+        let mut text = self.clipboard.repeat(count);
+        let pos = if self.vi_linewise {
+            let line = self.cursor.line + usize::from(after);
+            match self.buffer.line_start(line) {
+                Some(pos) => pos,
+                None => {
+                    // Below a last line without a trailing newline
+                    text.insert(0, '\n');
+                    text.pop();
+                    self.buffer.len()
+                }
+            }
+        } else {
+            let pos = self.cursor.byte_offset(&self.buffer);
+            match self.buffer.char_at(pos) {
+                Some(c) if after && c != '\n' => pos + c.len_utf8(),
+                _ => pos,
+            }
+        };
+
         let before = self.cursor_state();
-        let pos = self.cursor.byte_offset(&self.buffer);
-        let mut buf = [0u8; 4];
-        let s = ch.encode_utf8(&mut buf);
-        self.buffer.insert(pos, s);
+        self.buffer.insert(pos, &text);
         self.undo_stack.record(
             Operation::Insert {
                 pos,
-                text: s.to_string(),
+                text: text.clone(),
             },
             before,
-            GroupContext::Typing,
+            GroupContext::Paste,
         );
-        self.cursor.move_right(&self.buffer);
+        // Lines: cursor on the first pasted line; text: on its last char
+        let target = if self.vi_linewise {
+            pos + usize::from(text.starts_with('\n'))
+        } else {
+            pos + text.len() - text.chars().last().map_or(0, |c| c.len_utf8())
+        };
+        let line = self.buffer.byte_to_line(target);
+        let col = target - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.update_gutter_width();
     }
 
-    fn insert_newline(&mut self) {
+    // -----------------------------------------------------------------------
+    // Selection helpers
+    // -----------------------------------------------------------------------
+
+    fn start_or_continue_selection(&mut self) {
+        if self.selection.is_none() {
+            let offset = self.cursor.byte_offset(&self.buffer);
+            self.selection = Some(Selection {
+                anchor: offset,
+                head: offset,
+            });
+        }
+    }
+
+    fn extend_selection(&mut self) {
+        if let Some(ref mut sel) = self.selection {
+            sel.head = self.cursor.byte_offset(&self.buffer);
+        }
+    }
+
+    /// Run the movement `cmd`, selecting from the cursor (or extending the
+    /// selection) to where it lands. Smart Home toggles the same way as
+    /// without Shift: the first non-blank, then column 1.
+    fn select_to(&mut self, cmd: Command) {
+        self.start_or_continue_selection();
+        self.run_command(cmd);
+        self.extend_selection();
+    }
+
+    fn selection_range(&self) -> Option<(usize, usize)> {
+        self.selection.map(|sel| {
+            let start = sel.anchor.min(sel.head);
+            let end = sel.anchor.max(sel.head);
+            (start, end)
+        })
+    }
+
+    /// Delete the selected text, reposition cursor to selection start, clear selection.
+    /// Returns the deleted text if there was a selection.
+    fn delete_selection(&mut self) -> Option<String> {
+        let (start, end) = self.selection_range()?;
+        if start == end {
+            self.selection = None;
+            return None;
+        }
         let before = self.cursor_state();
-        let pos = self.cursor.byte_offset(&self.buffer);
-        self.buffer.insert(pos, "\n");
+        let deleted = self.buffer.slice(start, end);
+        self.buffer.delete(start, end - start);
         self.undo_stack.record(
-            Operation::Insert {
-                pos,
-                text: "\n".to_string(),
+            Operation::Delete {
+                pos: start,
+                text: deleted.clone(),
             },
             before,
             GroupContext::Other,
         );
-        self.cursor.move_right(&self.buffer);
+        // Reposition cursor to selection start
+        let line = self.buffer.byte_to_line(start);
+        let line_start = self.buffer.line_start(line).unwrap_or(0);
+        let col = start - line_start;
+        self.cursor.set_position(line, col, &self.buffer);
+        self.selection = None;
+        Some(deleted)
+    }
+
+    fn copy_selection(&mut self) {
+        if let Some((start, end)) = self.selection_range() {
+            if start == end {
+                // No selection: copy current line
+                self.copy_current_line();
+                return;
+            }
+            let text = self.buffer.slice(start, end);
+            let len = text.chars().count();
+            self.clipboard = text.clone();
+            terminal::set_clipboard_osc52(&text);
+            self.set_message(&strings::format("copied_chars", &[&len]), MessageType::Info);
+        } else {
+            // No selection: copy current line
+            self.copy_current_line();
+        }
+    }
+
+    fn copy_current_line(&mut self) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let text = format!("{}\n", line_text);
+        let len = line_text.chars().count();
+        self.clipboard = text.clone();
+        terminal::set_clipboard_osc52(&self.clipboard);
+        self.set_message(&strings::format("copied_line", &[&len]), MessageType::Info);
+    }
+
+    fn cut_selection(&mut self) {
+        if let Some((start, end)) = self.selection_range() {
+            if start == end {
+                self.cut_current_line();
+                return;
+            }
+            let text = self.delete_selection().unwrap_or_default();
+            let len = text.chars().count();
+            self.clipboard = text.clone();
+            terminal::set_clipboard_osc52(&text);
+            self.set_message(&strings::format("cut_chars", &[&len]), MessageType::Info);
+        } else {
+            self.cut_current_line();
+        }
+    }
+
+    fn cut_current_line(&mut self) {
+        let before = self.cursor_state();
+        let line = self.cursor.line;
+        let line_start = self.buffer.line_start(line).unwrap_or(0);
+        let line_end = self.buffer.line_end(line).unwrap_or(0);
+        // Include the newline if not the last line
+        let end = if line + 1 < self.buffer.line_count() {
+            line_end + 1
+        } else {
+            line_end
+        };
+        let text = self.buffer.slice(line_start, end);
+        let len = text.chars().count();
+        self.buffer.delete(line_start, end - line_start);
+        self.undo_stack.record(
+            Operation::Delete {
+                pos: line_start,
+                text: text.clone(),
+            },
+            before,
+            GroupContext::Cut,
+        );
+        self.cursor.clamp(&self.buffer);
+        self.cursor.col = 0;
+        self.cursor.desired_col = 0;
+        self.clipboard = text.clone();
+        terminal::set_clipboard_osc52(&text);
+        self.set_message(&strings::format("cut_line", &[&len]), MessageType::Info);
+    }
+
+    fn paste_clipboard(&mut self) {
+        if self.clipboard.is_empty() {
+            self.set_message(strings::get("clipboard_empty"), MessageType::Warning);
+            return;
+        }
+        // Delete selection if active
+        self.delete_selection();
+        let text = self.clipboard.clone();
+        self.handle_paste(&text);
+    }
+
+    /// Paste the clipboard's lines one below the other, each at the
+    /// cursor's screen column, keeping a copied column of text a column:
+    /// short lines are padded with spaces to reach it and lines run on past
+    /// the end of the buffer. A single-line clip pastes as usual.
+    fn paste_column(&mut self) {
+        let clip = self.clipboard.strip_suffix('\n').unwrap_or(&self.clipboard);
+        if !clip.contains('\n') {
+            return self.paste_clipboard();
+        }
+        let rows: Vec<String> = clip
+            .split('\n')
+            .map(|row| row.strip_suffix('\r').unwrap_or(row).to_string())
+            .collect();
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_selection();
+        let first = self.cursor.line;
+        let col = self.cursor_display_col();
+        let mut end = 0;
+        for (i, row) in rows.iter().enumerate() {
+            let line = first + i;
+            if line == self.buffer.line_count() {
+                self.insert_at(self.buffer.len(), "\n");
+            }
+            let start = self.buffer.line_start(line).unwrap_or(0);
+            let mut text = self.buffer.get_line(line).unwrap_or_default().into_owned();
+            if text.ends_with('\r') {
+                text.pop();
+            }
+            let cells = self.line_cells(line, &text);
+            let width = byte_col_to_screen_col(&cells, text.len());
+            let at = if width < col {
+                // No padding for nothing to paste
+                if row.is_empty() {
+                    continue;
+                }
+                let pad = " ".repeat(col - width);
+                self.insert_at(start + text.len(), &pad);
+                start + text.len() + pad.len()
+            } else {
+                start + screen_col_to_byte_col(&cells, text.len(), col)
+            };
+            self.insert_at(at, row);
+            end = at + row.len();
+        }
+        // After the last row pasted
+        let line = self.buffer.byte_to_line(end);
+        let line_start = self.buffer.line_start(line).unwrap_or(0);
+        self.cursor
+            .set_position(line, end - line_start, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    fn select_all(&mut self) {
+        let len = self.buffer.len();
+        self.selection = Some(Selection {
+            anchor: 0,
+            head: len,
+        });
+        self.cursor.move_to_end(&self.buffer);
+    }
+
+    // -----------------------------------------------------------------------
+    // Undo helpers
+    // -----------------------------------------------------------------------
+
+    fn cursor_state(&self) -> CursorState {
+        CursorState {
+            line: self.cursor.line,
+            col: self.cursor.col,
+            desired_col: self.cursor.desired_col,
+        }
+    }
+
+    fn restore_cursor(&mut self, state: CursorState) {
+        self.cursor.line = state.line;
+        self.cursor.col = state.col;
+        self.cursor.desired_col = state.desired_col;
+        self.cursor.clamp(&self.buffer);
+    }
+
+    /// Clear the modified flag when undo/redo lands back on the saved state.
+    fn sync_saved_state(&mut self) {
+        if self.undo_stack.is_at_saved() {
+            self.buffer.mark_saved();
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Editing operations
+    // -----------------------------------------------------------------------
+
+    fn insert_char(&mut self, ch: char) {
+        let before = self.cursor_state();
+        let pos = self.cursor.byte_offset(&self.buffer);
+        let mut buf = [0u8; 4];
+        let s = ch.encode_utf8(&mut buf);
+        self.buffer.insert(pos, s);
+        self.undo_stack.record(
+            Operation::Insert {
+                pos,
+                text: s.to_string(),
+            },
+            before,
+            GroupContext::Typing,
+        );
+        self.cursor.move_right(&self.buffer);
+    }
+
+    fn insert_newline(&mut self) {
+        let before = self.cursor_state();
+        let pos = self.cursor.byte_offset(&self.buffer);
+        self.buffer.insert(pos, "\n");
+        self.undo_stack.record(
+            Operation::Insert {
+                pos,
+                text: "\n".to_string(),
+            },
+            before,
+            GroupContext::Other,
+        );
+        self.cursor.move_right(&self.buffer);
+    }
+
+    fn insert_tab(&mut self) {
+        let before = self.cursor_state();
+        let pos = self.cursor.byte_offset(&self.buffer);
+        let unit = self.config.indent.clone();
+        self.buffer.insert(pos, &unit);
+        self.undo_stack.record(
+            Operation::Insert {
+                pos,
+                text: unit.clone(),
+            },
+            before,
+            GroupContext::Other,
+        );
+        self.cursor.col += unit.len();
+        self.cursor.desired_col = self.cursor.col;
+    }
+
+    fn backspace(&mut self) {
+        let pos = self.cursor.byte_offset(&self.buffer);
+        if pos == 0 {
+            return;
+        }
+        let before = self.cursor_state();
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        let indent = self.buffer.slice(line_start, pos);
+        if let Some(len) = indent_backspace_len(&indent, &self.config.indent) {
+            self.cursor.col -= len;
+            self.cursor.desired_col = self.cursor.col;
+        } else {
+            // Move cursor left first (handles UTF-8 boundaries)
+            self.cursor.move_left(&self.buffer);
+        }
+        let new_pos = self.cursor.byte_offset(&self.buffer);
+        let delete_len = pos - new_pos;
+        let deleted = self.buffer.slice(new_pos, pos);
+        self.buffer.delete(new_pos, delete_len);
+        self.undo_stack.record(
+            Operation::Delete {
+                pos: new_pos,
+                text: deleted,
+            },
+            before,
+            GroupContext::Deleting,
+        );
+    }
+
+    fn delete_at_cursor(&mut self) {
+        let pos = self.cursor.byte_offset(&self.buffer);
+        if pos >= self.buffer.len() {
+            return;
+        }
+        // Find the length of the character at cursor position
+        if let Some(ch) = self.buffer.char_at(pos) {
+            let before = self.cursor_state();
+            let char_len = ch.len_utf8();
+            let deleted = self.buffer.slice(pos, pos + char_len);
+            self.buffer.delete(pos, char_len);
+            self.undo_stack.record(
+                Operation::Delete { pos, text: deleted },
+                before,
+                GroupContext::Deleting,
+            );
+            self.cursor.clamp(&self.buffer);
+        }
+    }
+
+    /// Delete between the cursor and where `motion` would move it, as one
+    /// undo step, or the selection when there is one. At the start or end
+    /// of a line the motions cross the line break, which goes too.
+    fn delete_to(&mut self, motion: fn(&mut Cursor, &Buffer)) {
+        if self.delete_selection().is_some() {
+            return;
+        }
+        let before = self.cursor_state();
+        let from = self.cursor.byte_offset(&self.buffer);
+        motion(&mut self.cursor, &self.buffer);
+        let to = self.cursor.byte_offset(&self.buffer);
+        if to > from {
+            self.restore_cursor(before);
+        }
+        let (start, end) = (from.min(to), from.max(to));
+        if start == end {
+            return;
+        }
+        let deleted = self.buffer.delete(start, end - start);
+        self.undo_stack.record(
+            Operation::Delete {
+                pos: start,
+                text: deleted,
+            },
+            before,
+            GroupContext::Other,
+        );
+        self.cursor.desired_col = self.cursor.col;
+    }
+
+    // -----------------------------------------------------------------------
+    // Line operations
+    // -----------------------------------------------------------------------
+
+    /// First and last line touched by the selection, or the cursor line. A
+    /// selection ending at the start of a line leaves that line out.
+    fn selected_lines(&self) -> (usize, usize) {
+        match self.selection_range() {
+            Some((start, end)) if start < end => {
+                let first = self.buffer.byte_to_line(start);
+                let mut last = self.buffer.byte_to_line(end);
+                if last > first && self.buffer.line_start(last) == Some(end) {
+                    last -= 1;
+                }
+                (first, last)
+            }
+            _ => (self.cursor.line, self.cursor.line),
+        }
+    }
+
+    /// Byte range of lines `first..=last`, without the final newline.
+    fn lines_span(&self, first: usize, last: usize) -> (usize, usize) {
+        let start = self.buffer.line_start(first).unwrap_or(0);
+        let end = self.buffer.line_end(last).unwrap_or(self.buffer.len());
+        (start, end)
+    }
+
+    /// Move the selection's anchor and head by `delta` bytes.
+    fn shift_selection(&mut self, delta: isize) {
+        if let Some(ref mut sel) = self.selection {
+            sel.anchor = sel.anchor.saturating_add_signed(delta);
+            sel.head = sel.head.saturating_add_signed(delta);
+        }
+    }
+
+    /// Comment or uncomment the current lines with the filetype's syntax.
+    /// The cursor and selection stay on the same text.
+    fn toggle_comment(&mut self) {
+        let filetype = self.buffer.filetype();
+        let syntax = match filetype::comment_syntax(&filetype) {
+            Some(syntax) => syntax,
+            None => {
+                let msg = strings::format("no_comment_syntax", &[&filetype]);
+                self.set_message(&msg, MessageType::Info);
+                return;
+            }
+        };
+        let (first, last) = self.selected_lines();
+        let lines: Vec<_> = (first..=last)
+            .map(|line| self.buffer.get_line(line).unwrap_or_default())
+            .collect();
+        let edits = comment::toggle(&lines, &syntax);
+        if edits.is_empty() {
+            return;
+        }
+        // Positions as (line, col), moved by the edits on their line
+        let shift = |buffer: &Buffer, pos: usize| {
+            let line = buffer.byte_to_line(pos);
+            let col = pos - buffer.line_start(line).unwrap_or(0);
+            let col = edits
+                .iter()
+                .filter(|e| first + e.line == line)
+                .rev()
+                .fold(col, |col, e| e.shift(col));
+            (line, col)
+        };
+        let selection = self.selection.map(|sel| {
+            (
+                shift(&self.buffer, sel.anchor),
+                shift(&self.buffer, sel.head),
+            )
+        });
+        let cursor = shift(&self.buffer, self.cursor.byte_offset(&self.buffer));
+
+        self.undo_stack.begin_compound(self.cursor_state());
+        for e in edits.iter().rev() {
+            let pos = self.buffer.line_start(first + e.line).unwrap_or(0) + e.col;
+            if e.delete > 0 {
+                self.delete_at(pos, e.delete);
+            }
+            self.insert_at(pos, &e.insert);
+        }
+        let offset = |buffer: &Buffer, (line, col): (usize, usize)| {
+            buffer.line_start(line).unwrap_or(0) + col
+        };
+        if let Some((anchor, head)) = selection {
+            self.selection = Some(Selection {
+                anchor: offset(&self.buffer, anchor),
+                head: offset(&self.buffer, head),
+            });
+        }
+        self.cursor.set_position(cursor.0, cursor.1, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Indent the current lines by one unit, or outdent them by up to one.
+    /// Blank lines are not indented. The cursor and selection stay on the
+    /// same text, and the whole change undoes at once.
+    fn indent_lines(&mut self, outdent: bool) {
+        let (first, last) = self.selected_lines();
+        let unit = self.config.indent.clone();
+        let line_col = |buffer: &Buffer, pos: usize| {
+            let line = buffer.byte_to_line(pos);
+            (line, pos - buffer.line_start(line).unwrap_or(0))
+        };
+        let selection = self.selection.map(|sel| {
+            (
+                line_col(&self.buffer, sel.anchor),
+                line_col(&self.buffer, sel.head),
+            )
+        });
+
+        self.undo_stack.begin_compound(self.cursor_state());
+        let mut deltas = Vec::new();
+        for line in first..=last {
+            let text = self.buffer.get_line(line).unwrap_or_default();
+            let start = self.buffer.line_start(line).unwrap_or(0);
+            if outdent {
+                let len = outdent_len(&text, &unit, self.tab_width());
+                if len > 0 {
+                    self.delete_at(start, len);
+                }
+                deltas.push(-(len as isize));
+            } else if text.trim().is_empty() {
+                deltas.push(0);
+            } else {
+                self.insert_at(start, &unit);
+                deltas.push(unit.len() as isize);
+            }
+        }
+
+        // Columns move with their line's indentation; column 0 stays put
+        let shift = |(line, col): (usize, usize)| -> (usize, usize) {
+            match line.checked_sub(first).and_then(|i| deltas.get(i)) {
+                Some(&delta) if delta > 0 && col > 0 => (line, col + delta as usize),
+                Some(&delta) if delta < 0 => (line, col.saturating_sub(delta.unsigned_abs())),
+                _ => (line, col),
+            }
+        };
+        let offset = |buffer: &Buffer, (line, col): (usize, usize)| {
+            buffer.line_start(line).unwrap_or(0) + col
+        };
+        if let Some((anchor, head)) = selection {
+            self.selection = Some(Selection {
+                anchor: offset(&self.buffer, shift(anchor)),
+                head: offset(&self.buffer, shift(head)),
+            });
+        }
+        let (line, col) = shift((self.cursor.line, self.cursor.col));
+        self.cursor.set_position(line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Swap the current lines with the line above or below. The cursor and
+    /// selection travel with the moved lines.
+    fn move_lines(&mut self, up: bool) {
+        let (first, last) = self.selected_lines();
+        if (up && first == 0) || (!up && last + 1 >= self.buffer.line_count()) {
+            return;
+        }
+        let (block_start, block_end) = self.lines_span(first, last);
+        let block = self.buffer.slice(block_start, block_end);
+        let (other, span) = if up {
+            (first - 1, self.lines_span(first - 1, last))
+        } else {
+            (last + 1, self.lines_span(first, last + 1))
+        };
+        let other_text = self.buffer.get_line(other).unwrap_or_default().into_owned();
+        let text = if up {
+            format!("{}\n{}", block, other_text)
+        } else {
+            format!("{}\n{}", other_text, block)
+        };
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(span.0, span.1 - span.0);
+        self.insert_at(span.0, &text);
+        let shift = other_text.len() as isize + 1;
+        if up {
+            self.cursor.line -= 1;
+            self.shift_selection(-shift);
+        } else {
+            self.cursor.line += 1;
+            self.shift_selection(shift);
+        }
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Copy the current lines below themselves and move onto the copy.
+    fn duplicate_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        let (start, end) = self.lines_span(first, last);
+        let block = self.buffer.slice(start, end);
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.insert_at(end, &format!("\n{}", block));
+        self.cursor.line += last - first + 1;
+        self.shift_selection(block.len() as isize + 1);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    /// Join the selected lines, or the cursor line with the next, replacing
+    /// each line break and the whitespace around it with one space. The
+    /// cursor lands on the last join.
+    fn join_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        let last = last.max(first + 1);
+        if last >= self.buffer.line_count() {
+            return;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        let mut col = 0;
+        for _ in first..last {
+            let line = self.buffer.get_line(first).unwrap_or_default().into_owned();
+            let next = self.buffer.get_line(first + 1).unwrap_or_default();
+            let (trailing, leading, separator) = join_span(&line, &next);
+            let at = self.buffer.line_end(first).unwrap_or(0) - trailing;
+            self.delete_at(at, trailing + 1 + leading);
+            self.insert_at(at, separator);
+            col = line.len() - trailing;
+        }
+        self.selection = None;
+        self.cursor.set_position(first, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    /// Delete the current lines without touching the clipboard. The cursor
+    /// moves to the first non-blank of the line that takes their place.
+    fn delete_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        let mut start = self.buffer.line_start(first).unwrap_or(0);
+        let end = self
+            .buffer
+            .line_start(last + 1)
+            .unwrap_or(self.buffer.len());
+        // Deleting through the last line takes the preceding newline
+        if last + 1 >= self.buffer.line_count() && first > 0 {
+            start -= 1;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(start, end - start);
+        self.selection = None;
+        let line = first.min(self.buffer.line_count().saturating_sub(1));
+        let text = self.buffer.get_line(line).unwrap_or_default();
+        let indent = text.len() - text.trim_start().len();
+        self.cursor.set_position(line, indent, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    // -----------------------------------------------------------------------
+    // Text transforms
+    // -----------------------------------------------------------------------
+
+    /// Change the case of the selection, or of the word under the cursor.
+    /// The selection is kept around the changed text.
+    fn change_case(&mut self, case: Case) {
+        let (start, end) = match self.selection_range() {
+            Some((start, end)) if start < end => (start, end),
+            _ => {
+                let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+                let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+                let col = self.cursor.col;
+                let word_end = line_text[col..]
+                    .find(|c| !unicode::is_word_char(c))
+                    .map_or(line_text.len(), |i| col + i);
+                let word_start = complete::word_start(&line_text, col);
+                (line_start + word_start, line_start + word_end)
+            }
+        };
+        let text = self.buffer.slice(start, end);
+        let changed = case.apply(&text);
+        if changed == text {
+            return;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(start, end - start);
+        self.insert_at(start, &changed);
+        // Case changes can change the length (ß becomes SS)
+        let new_end = start + changed.len();
+        if let Some(ref mut sel) = self.selection {
+            let forward = sel.anchor <= sel.head;
+            (sel.anchor, sel.head) = if forward {
+                (start, new_end)
+            } else {
+                (new_end, start)
+            };
+        }
+        let cursor = self.cursor.byte_offset(&self.buffer).min(new_end);
+        let line = self.buffer.byte_to_line(cursor);
+        let col = cursor - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Apply a transposition to the cursor line and move past the result.
+    fn transpose(&mut self, swap: fn(&str, usize) -> Option<LineSwap>) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let (start, end, text) = match swap(&line_text, self.cursor.col) {
+            Some(edit) => edit,
+            None => return,
+        };
+        self.replace_in_line(start, end, &text, start + text.len());
+    }
+
+    /// Add `delta` to the number at or after the cursor, leaving the cursor
+    /// on its last digit so repeating keeps hitting the same number.
+    fn add_to_number(&mut self, delta: i64) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        match transform::add_to_number(&line_text, self.cursor.col, delta) {
+            Some((start, end, text)) => {
+                self.replace_in_line(start, end, &text, start + text.len() - 1)
+            }
+            None => self.set_message(strings::get("no_number"), MessageType::Info),
+        }
+    }
+
+    /// Replace bytes `start..end` of the cursor line with `text` as one undo
+    /// group and move the cursor to column `col`.
+    fn replace_in_line(&mut self, start: usize, end: usize, text: &str, col: usize) {
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.selection = None;
+        self.delete_at(line_start + start, end - start);
+        self.insert_at(line_start + start, text);
+        self.cursor
+            .set_position(self.cursor.line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// The lines sort and unique work on: the selected lines, or the whole
+    /// buffer without a selection.
+    fn region_lines(&self) -> (usize, usize) {
+        match self.selection_range() {
+            Some((start, end)) if start < end => self.selected_lines(),
+            _ => {
+                // Leave out the empty line after a final newline
+                let mut last = self.buffer.line_count().saturating_sub(1);
+                if last > 0 && self.buffer.get_line(last).is_some_and(|l| l.is_empty()) {
+                    last -= 1;
+                }
+                (0, last)
+            }
+        }
+    }
+
+    /// Replace lines `first..=last` with `lines` as one undo group,
+    /// selecting the result when there was a selection.
+    fn replace_lines(&mut self, first: usize, last: usize, lines: &[String]) {
+        let (start, end) = self.lines_span(first, last);
+        let text = lines.join("\n");
+        if self.buffer.slice(start, end) == text {
+            return;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(start, end - start);
+        self.insert_at(start, &text);
+        if self.selection.is_some() {
+            self.selection = Some(Selection {
+                anchor: start,
+                head: start + text.len(),
+            });
+        }
+        self.cursor.clamp(&self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    /// Rewrap the selected lines, or the paragraph at the cursor, to the
+    /// configured text width.
+    fn reflow_paragraph(&mut self) {
+        let filetype = self.buffer.filetype();
+        let marker = filetype::comment_syntax(&filetype).and_then(|s| s.line);
+        let (first, last) = match self.selection_range() {
+            Some((start, end)) if start < end => self.selected_lines(),
+            _ => {
+                let lines: Vec<_> = (0..self.buffer.line_count())
+                    .map(|line| self.buffer.get_line(line).unwrap_or_default())
+                    .collect();
+                match reflow::paragraph_at(&lines, self.cursor.line, marker) {
+                    Some(range) => range,
+                    None => return,
+                }
+            }
+        };
+        let lines: Vec<_> = (first..=last)
+            .map(|line| self.buffer.get_line(line).unwrap_or_default())
+            .collect();
+        let wrapped = reflow::reflow(&lines, self.text_width(), marker);
+        let new_last = first + wrapped.len() - 1;
+        self.replace_lines(first, last, &wrapped);
+        if self.selection.is_none() {
+            let end = self.buffer.get_line(new_last).map_or(0, |l| l.len());
+            self.cursor.set_position(new_last, end, &self.buffer);
+        }
+    }
+
+    fn sort_lines(&mut self, options: SortOptions) {
+        let (first, last) = self.region_lines();
+        let mut lines: Vec<String> = (first..=last)
+            .map(|line| self.buffer.get_line(line).unwrap_or_default().into_owned())
+            .collect();
+        transform::sort_lines(&mut lines, options);
+        self.replace_lines(first, last, &lines);
+        let msg = strings::format("sorted_lines", &[&lines.len()]);
+        self.set_message(&msg, MessageType::Info);
+    }
+
+    fn unique_lines(&mut self, adjacent_only: bool) {
+        let (first, last) = self.region_lines();
+        let mut lines: Vec<String> = (first..=last)
+            .map(|line| self.buffer.get_line(line).unwrap_or_default().into_owned())
+            .collect();
+        let removed = transform::unique_lines(&mut lines, adjacent_only);
+        self.replace_lines(first, last, &lines);
+        let msg = strings::format("removed_duplicates", &[&removed]);
+        self.set_message(&msg, MessageType::Info);
+    }
+
+    /// Pretty-print or minify the JSON in the selection, or the whole
+    /// buffer, as one undo group. Pretty-printed lines keep the indentation
+    /// of the line the JSON starts on. On a parse error, the cursor jumps to
+    /// it.
+    fn format_json(&mut self, minify: bool) {
+        let (start, end) = match self.selection_range() {
+            Some((start, end)) if start < end => (start, end),
+            _ => (0, self.buffer.len()),
+        };
+        let text = self.buffer.slice(start, end);
+        let start = start + text.len() - text.trim_start().len();
+        let text = text.trim();
+        let line = self.buffer.byte_to_line(start);
+        let line_text = self.buffer.get_line(line).unwrap_or_default();
+        let margin = &line_text[..line_text.len() - line_text.trim_start().len()];
+        let result = if minify {
+            json::minify(text)
+        } else {
+            json::pretty(text, &self.config.indent)
+                .map(|out| out.replace('\n', &format!("\n{}", margin)))
+        };
+        let formatted = match result {
+            Ok(formatted) => formatted,
+            Err((msg, offset)) => {
+                self.record_jump();
+                self.selection = None;
+                let pos = start + offset;
+                let line = self.buffer.byte_to_line(pos);
+                let col = pos - self.buffer.line_start(line).unwrap_or(0);
+                self.cursor.set_position(line, col, &self.buffer);
+                self.set_message(&strings::format("json_error", &[&msg]), MessageType::Error);
+                return;
+            }
+        };
+        if formatted != text {
+            self.undo_stack.begin_compound(self.cursor_state());
+            self.delete_at(start, text.len());
+            self.insert_at(start, &formatted);
+            if self.selection.is_some() {
+                self.selection = Some(Selection {
+                    anchor: start,
+                    head: start + formatted.len(),
+                });
+            }
+            self.cursor.clamp(&self.buffer);
+            self.undo_stack.end_compound(self.cursor_state());
+            self.update_gutter_width();
+        }
+        let done = if minify {
+            "json_minified"
+        } else {
+            "json_formatted"
+        };
+        self.set_message(strings::get(done), MessageType::Info);
+    }
+
+    // -----------------------------------------------------------------------
+    // Commands
+    // -----------------------------------------------------------------------
+
+    fn save(&mut self) {
+        if self.buffer.file_path().is_none() {
+            self.set_message(strings::get("no_file_name"), MessageType::Error);
+            return;
+        }
+        let mut format_error = None;
+        if !self.buffer.is_read_only() {
+            format_error = self.run_formatter().err();
+        }
+        if self.config.trim_trailing_whitespace && !self.buffer.is_read_only() {
+            self.trim_whitespace();
+        }
+        // Saving through a link writes its target, which may not be meant
+        // to change
+        let read_only_target = self.buffer.file_path().and_then(|path| {
+            let target = buffer::read_only_target(path)?;
+            Some(strings::format(
+                "link_to_read_only",
+                &[&shorten_path(path), &shorten_path(&target)],
+            ))
+        });
+        match self.buffer.save() {
+            Ok(()) => {
+                self.buffer.mark_saved();
+                self.undo_stack.mark_saved(self.cursor_state());
+                if !self.any_modified() {
+                    self.finish_continued_session();
+                }
+                self.refresh_git_gutter();
+                self.blame = None;
+                self.request_blame();
+                self.lsp_sync();
+                if let Some(path) = self.buffer.file_path().map(Path::to_path_buf)
+                    && let Some(client) = self.lsp_client_for(&path)
+                {
+                    client.did_save(&path);
+                }
+                if let Some(path) = self.buffer.file_path() {
+                    self.plugins.send(&plugin::Event::Save(path.to_path_buf()));
+                }
+                // Another instance editing the file may write over this
+                let other = match self.buffer.file_path() {
+                    Some(path) if self.config.lock_files => lockfile::holder(path),
+                    _ => None,
+                };
+                match (format_error, read_only_target, other) {
+                    (Some(e), _, _) => self.set_message(
+                        &strings::format("saved_format_failed", &[&e]),
+                        MessageType::Warning,
+                    ),
+                    (None, Some(link), _) => self.set_message(
+                        &strings::format("saved_through_link", &[&link]),
+                        MessageType::Warning,
+                    ),
+                    (None, None, Some(owner)) => self.set_message(
+                        &strings::format("saved_while_locked", &[&owner.pid, &owner.host]),
+                        MessageType::Warning,
+                    ),
+                    (None, None, None) => {
+                        self.set_message(strings::get("saved"), MessageType::Info)
+                    }
+                }
+            }
+            Err(e) => {
+                let e = match read_only_target {
+                    Some(link) => format!("{} ({})", e, link),
+                    None => e,
+                };
+                self.set_message(&strings::format("save_failed", &[&e]), MessageType::Error);
+            }
+        }
+    }
+
+    /// The save hook: pipe the buffer through the formatter configured for
+    /// its filetype and apply only the lines it changed, as one undo group,
+    /// so the cursor and the undo history elsewhere stay put.
+    fn run_formatter(&mut self) -> Result<(), String> {
+        let filetype = self.buffer.filetype();
+        let Some((_, cmd)) = self
+            .config
+            .formatters
+            .iter()
+            .find(|(ft, _)| *ft == filetype)
+        else {
+            return Ok(());
+        };
+        let text = self.buffer.text();
+        let formatted = shell::filter(cmd, &text)?;
+        let old: Vec<&str> = text.split_inclusive('\n').collect();
+        let new: Vec<&str> = formatted.split_inclusive('\n').collect();
+        let changes = diff::diff_lines(&old, &new);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut starts = vec![0];
+        starts.extend(old.iter().scan(0, |pos, line| {
+            *pos += line.len();
+            Some(*pos)
+        }));
+        let (line, col) = (self.cursor.line, self.cursor.col);
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.selection = None;
+        for change in changes.iter().rev() {
+            let (first, count) = change.old;
+            let (start, end) = (starts[first], starts[first + count]);
+            self.delete_at(start, end - start);
+            self.insert_at(
+                start,
+                &new[change.new.0..change.new.0 + change.new.1].concat(),
+            );
+        }
+        let line = diff::map_line(&changes, line);
+        self.cursor.set_position(line, col, &self.buffer);
+        let pos = self.cursor.byte_offset(&self.buffer);
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        let col = self.buffer.floor_char_boundary(pos) - line_start;
+        self.cursor
+            .set_position(self.cursor.line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+        Ok(())
+    }
+
+    /// The save hook: strip trailing whitespace (from every line, or only
+    /// those edited since the last save) and extra blank lines at the end,
+    /// as one undo group.
+    fn trim_whitespace(&mut self) {
+        let lines: Vec<String> = (0..self.buffer.line_count())
+            .filter_map(|l| self.buffer.get_line(l).map(Cow::into_owned))
+            .collect();
+        let only_changed = self.config.trim_modified_lines_only;
+        // Cut from the first surplus blank line to the end, so the text
+        // ends with the newline of the last line kept
+        let mut kept = lines.len();
+        let mut edits: Vec<(usize, usize)> = Vec::new();
+        let excess = transform::excess_final_lines(&lines);
+        if excess > 0 {
+            kept -= excess + 1;
+            let start = self.buffer.line_start(kept).unwrap_or(0);
+            edits.push((start, self.buffer.len() - start));
+        }
+        for (line, text) in lines[..kept].iter().enumerate().rev() {
+            if only_changed && !self.buffer.is_line_changed(line) {
+                continue;
+            }
+            if let Some((start, end)) = transform::trailing_whitespace(text) {
+                let line_start = self.buffer.line_start(line).unwrap_or(0);
+                edits.push((line_start + start, end - start));
+            }
+        }
+        if edits.is_empty() {
+            return;
+        }
+        // Back to front, so earlier offsets stay valid
+        self.undo_stack.begin_compound(self.cursor_state());
+        for (pos, len) in edits {
+            self.delete_at(pos, len);
+        }
+        self.cursor
+            .set_position(self.cursor.line, self.cursor.col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Insert the contents of `path` at the cursor as one undo step, leaving
+    /// the cursor after them.
+    fn insert_file(&mut self, path: &Path) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                let msg = strings::format("read_failed", &[&shorten_path(path), &e]);
+                self.set_message(&msg, MessageType::Error);
+                return;
+            }
+        };
+        let pos = self.cursor.byte_offset(&self.buffer);
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.selection = None;
+        self.insert_at(pos, &text);
+        let end = pos + text.len();
+        let line = self.buffer.byte_to_line(end);
+        let col = end - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+        let msg = strings::format(
+            "inserted_lines",
+            &[&text.lines().count(), &shorten_path(path)],
+        );
+        self.set_message(&msg, MessageType::Info);
+    }
+
+    /// Write the selected text to a new file; existing files are left alone.
+    fn write_selection(&mut self, path: &Path) {
+        let Some((start, end)) = self.selection_range() else {
+            return;
+        };
+        let text = self.buffer.slice(start, end);
+        match fs::write(path, &text) {
+            Ok(()) => {
+                let msg = strings::format("wrote_bytes", &[&text.len(), &shorten_path(path)]);
+                self.set_message(&msg, MessageType::Info);
+            }
+            Err(e) => {
+                let msg = strings::format("write_failed", &[&shorten_path(path), &e]);
+                self.set_message(&msg, MessageType::Error);
+            }
+        }
+    }
+
+    /// Write the buffer with its syntax colors to `target`: an HTML page
+    /// for a `.html` or `.htm` name, ANSI text for anything else. The
+    /// buffer's own file is never overwritten.
+    fn export(&mut self, target: &str) {
+        let path = Path::new(target);
+        if let Some(own) = self.buffer.file_path()
+            && same_file(own, path)
+        {
+            let msg = strings::format("export_own_file", &[&shorten_path(path)]);
+            self.set_message(&msg, MessageType::Error);
+            return;
+        }
+        let filetype = self.buffer.filetype();
+        let text = self.buffer.contents();
+        let out = if export::is_html_path(target) {
+            let title = self.buffer_display_name();
+            export::html(&text, &filetype, &title, self.theme())
+        } else {
+            export::ansi(&text, &filetype, self.theme(), &self.color_mode)
+        };
+        match fs::write(path, &out) {
+            Ok(()) => {
+                let msg = strings::format("wrote_bytes", &[&out.len(), &shorten_path(path)]);
+                self.set_message(&msg, MessageType::Info);
+            }
+            Err(e) => {
+                let msg = strings::format("write_failed", &[&shorten_path(path), &e]);
+                self.set_message(&msg, MessageType::Error);
+            }
+        }
+    }
+
+    fn quit(&mut self) {
+        if self.any_modified() {
+            self.confirm_discard(strings::get("quit_without_saving"), Command::Quit);
+            return;
+        }
+        self.finish_continued_session();
+        self.running = false;
+    }
+
+    /// Ask before `cmd` throws away unsaved changes.
+    fn confirm_discard(&mut self, question: &str, cmd: Command) {
+        let dialog = Dialog::new(
+            strings::get("unsaved_changes"),
+            question,
+            &[('y', strings::get("yes")), ('n', strings::get("no"))],
+        );
+        self.dialog = Some((dialog, cmd));
+    }
+
+    /// Keys while a dialog is open: `y` (or the confirmed command's own key
+    /// again) goes ahead, `n` and Escape back out, anything else is ignored.
+    fn handle_dialog_key(&mut self, ke: &KeyEvent) {
+        let (dialog, cmd) = match self.dialog {
+            Some((ref d, cmd)) => (d, cmd),
+            None => return,
+        };
+        let again = self.keymap.lookup(ke) == Some(cmd);
+        match dialog.handle_key(ke) {
+            KeyResult::Choice('y') => {}
+            _ if again => {}
+            KeyResult::Choice(_) | KeyResult::Cancel => {
+                self.dialog = None;
+                return;
+            }
+            _ => return,
+        }
+        self.dialog = None;
+        match cmd {
+            Command::Quit => {
+                self.finish_continued_session();
+                self.running = false;
+            }
+            Command::CloseBuffer => self.discard_buffer(),
+            _ => {}
+        }
+    }
+
+    fn any_modified(&self) -> bool {
+        self.buffer.is_modified() || self.documents.iter().any(|d| d.buffer.is_modified())
+    }
+
+    // -----------------------------------------------------------------------
+    // Buffers
+    // -----------------------------------------------------------------------
+
+    fn buffer_display_name(&self) -> String {
+        display_name(&self.buffer)
+    }
+
+    /// Warn and return true when the active buffer must not be edited.
+    fn reject_read_only(&mut self) -> bool {
+        if self.buffer.is_read_only() {
+            self.set_message(strings::get("read_only"), MessageType::Warning);
+        }
+        self.buffer.is_read_only()
+    }
+
+    /// Exchange the editor's active-buffer fields with document slot `idx`.
+    fn swap_document(&mut self, idx: usize) {
+        let doc = &mut self.documents[idx];
+        std::mem::swap(&mut self.buffer, &mut doc.buffer);
+        std::mem::swap(&mut self.cursor, &mut doc.cursor);
+        std::mem::swap(&mut self.undo_stack, &mut doc.undo_stack);
+        std::mem::swap(&mut self.selection, &mut doc.selection);
+        std::mem::swap(&mut self.scroll_row, &mut doc.scroll_row);
+        std::mem::swap(&mut self.scroll_col, &mut doc.scroll_col);
+        std::mem::swap(&mut self.git_gutter, &mut doc.git_gutter);
+        std::mem::swap(&mut self.blame, &mut doc.blame);
+        std::mem::swap(&mut self.follow, &mut doc.follow);
+        std::mem::swap(&mut self.column_view, &mut doc.column_view);
+        std::mem::swap(&mut self.virtual_text, &mut doc.virtual_text);
+        std::mem::swap(&mut self.text_stats, &mut doc.text_stats);
+        self.spell_errors.clear();
+        self.spell_version = None;
+        self.columns_version = None;
+    }
+
+    fn switch_to_document(&mut self, idx: usize) {
+        if idx == self.active || idx >= self.documents.len() {
+            return;
+        }
+        self.swap_document(self.active);
+        self.swap_document(idx);
+        self.active = idx;
+        // Match offsets belong to the previous buffer
+        self.search = None;
+        self.snippet_session = None;
+        self.update_gutter_width();
+        if self.blame.is_none() {
+            self.request_blame();
+        }
+    }
+
+    /// Add `buffer` as a new document and make it active.
+    fn add_document(&mut self, buffer: Buffer) {
+        self.documents.push(Document::new(buffer));
+        let idx = self.documents.len() - 1;
+        self.switch_to_document(idx);
+    }
+
+    fn next_buffer(&mut self) {
+        let count = self.documents.len();
+        if count > 1 {
+            self.switch_to_document((self.active + 1) % count);
+        }
+    }
+
+    fn prev_buffer(&mut self) {
+        let count = self.documents.len();
+        if count > 1 {
+            self.switch_to_document((self.active + count - 1) % count);
+        }
+    }
+
+    /// True when the active buffer is an untouched, unnamed empty buffer that
+    /// can be replaced instead of opening a new one next to it.
+    fn active_is_pristine(&self) -> bool {
+        self.buffer.file_path().is_none()
+            && self.buffer.name().is_none()
+            && !self.buffer.is_modified()
+            && self.buffer.is_empty()
+    }
+
+    /// Open `path` in a buffer (reusing one that already shows it) and make it
+    /// active.
+    fn open_path(&mut self, path: &Path) -> Result<(), String> {
+        let existing = (0..self.documents.len()).find(|&i| {
+            let buf = if i == self.active {
+                &self.buffer
+            } else {
+                &self.documents[i].buffer
+            };
+            buf.file_path().is_some_and(|p| same_file(p, path))
+        });
+        if let Some(idx) = existing {
+            self.switch_to_document(idx);
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let mut buf = Buffer::from_file(path)?;
+        if self.config.resolve_symlinks {
+            buf.resolve_symlink();
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.operation("load-file", start.elapsed());
+        }
+        if self.active_is_pristine() {
+            self.replace_active_buffer(buf);
+        } else {
+            self.add_document(buf);
+            self.refresh_git_gutter();
+        }
+        self.lock_active();
+        self.offer_column_view();
+        Ok(())
+    }
+
+    /// Open `path` at a 1-based line and column, if given, remembering
+    /// where the cursor was on the jump list.
+    fn open_located(
+        &mut self,
+        path: &Path,
+        position: Option<(usize, usize)>,
+    ) -> Result<(), String> {
+        let here = self.current_jump();
+        self.open_path(path)?;
+        if let Some(here) = here {
+            self.jumps.push(here);
+        }
+        if let Some(position) = position {
+            self.goto_position(position);
+        }
+        let msg = strings::format("opened", &[&shorten_path(path)]);
+        self.set_message(&msg, MessageType::Info);
+        Ok(())
+    }
+
+    /// Open what the text under the cursor names: a URL in the system
+    /// browser, or a file, with an optional `:line[:col]`, in a buffer. A
+    /// relative path is looked for from the working directory, then from
+    /// the buffer's own.
+    fn open_under_cursor(&mut self) {
+        let line = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let text = match link::target_at(&line, self.cursor.col) {
+            Some(Target::Url(url)) => {
+                match link::open_url(&url) {
+                    Ok(()) => self
+                        .set_message(&strings::format("opening_url", &[&url]), MessageType::Info),
+                    Err(e) => {
+                        self.set_message(&strings::format("error", &[&e]), MessageType::Error)
+                    }
+                }
+                return;
+            }
+            Some(Target::Path(text)) => text,
+            None => {
+                self.set_message(strings::get("nothing_under_cursor"), MessageType::Warning);
+                return;
+            }
+        };
+        let (name, position) = locate(&text);
+        let buffer_dir = self.buffer.file_path().and_then(Path::parent);
+        let found = [Some(Path::new("")), buffer_dir]
+            .into_iter()
+            .flatten()
+            .find_map(|dir| {
+                // Where the whole text is a file name, it has no position
+                let whole = dir.join(&text);
+                if whole.is_file() {
+                    return Some((whole, None));
+                }
+                let path = dir.join(name);
+                path.is_file().then_some((path, position))
+            });
+        let Some((path, position)) = found else {
+            let msg = strings::format("no_file_under_cursor", &[&text]);
+            self.set_message(&msg, MessageType::Warning);
+            return;
+        };
+        if let Err(e) = self.open_located(&path, position) {
+            self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+        }
+    }
+
+    /// Replace the active buffer's contents and reset its per-buffer state.
+    fn replace_active_buffer(&mut self, buf: Buffer) {
+        self.lsp_close_active();
+        self.buffer = buf;
+        self.cursor = Cursor::new();
+        self.scroll_row = 0;
+        self.scroll_col = 0;
+        self.selection = None;
+        self.search = None;
+        self.undo_stack.clear();
+        self.blame = None;
+        self.follow = None;
+        self.column_view = None;
+        self.virtual_text = VirtualText::default();
+        self.refresh_git_gutter();
+        self.request_blame();
+        self.release_locks();
+    }
+
+    /// Ask before moving the buffer's file to the trash. The buffer stays
+    /// open, so saving writes the file again.
+    fn delete_file(&mut self) {
+        let Some(path) = self.buffer.file_path().map(Path::to_path_buf) else {
+            self.set_message(strings::get("no_file_to_delete"), MessageType::Warning);
+            return;
+        };
+        let label = strings::format("prompt_trash", &[&shorten_path(&path)]);
+        let choices = [('y', strings::get("yes")), ('n', strings::get("no"))];
+        self.open_prompt(
+            Prompt::choice(&label, &choices),
+            PromptAction::ConfirmTrash(path),
+        );
+    }
+
+    /// Move `path` to the desktop trash, or beside it into `.zelux-trash`,
+    /// remembering it for undo-delete-file.
+    fn trash_file(&mut self, path: &Path) {
+        match trash::trash(path, trash::trash_dir().as_deref()) {
+            Ok(trashed) => {
+                let name = shorten_path(path);
+                let place = shorten_path(trashed.trashed.parent().unwrap_or(Path::new("")));
+                self.set_message(
+                    &strings::format("trashed", &[&name, &place]),
+                    MessageType::Info,
+                );
+                self.trashed.push(trashed);
+                self.locks.retain(|lock| lock.file() != path);
+            }
+            Err(e) => self.set_message(&strings::format("error", &[&e]), MessageType::Error),
+        }
+    }
+
+    /// Put back the file most recently moved to the trash.
+    fn undo_delete_file(&mut self) {
+        let Some(trashed) = self.trashed.pop() else {
+            self.set_message(strings::get("nothing_trashed"), MessageType::Info);
+            return;
+        };
+        match trash::restore(&trashed) {
+            Ok(()) => {
+                let name = shorten_path(&trashed.original);
+                self.set_message(&strings::format("restored", &[&name]), MessageType::Info);
+            }
+            Err(e) => {
+                self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+                self.trashed.push(trashed);
+            }
+        }
+    }
+
+    fn close_buffer(&mut self) {
+        if self.buffer.is_modified() {
+            self.confirm_discard(strings::get("close_without_saving"), Command::CloseBuffer);
+            return;
+        }
+        self.discard_buffer();
+    }
+
+    /// Close the active buffer, unsaved changes and all.
+    fn discard_buffer(&mut self) {
+        if self.documents.len() == 1 {
+            self.replace_active_buffer(Buffer::new());
+            return;
+        }
+
+        self.lsp_close_active();
+
+        // Park the closing buffer in its own slot, load a neighbour, then drop
+        // the slot.
+        let idx = self.active;
+        let next = if idx + 1 < self.documents.len() {
+            idx + 1
+        } else {
+            idx - 1
+        };
+        self.swap_document(idx);
+        self.swap_document(next);
+        self.documents.remove(idx);
+        self.active = if next > idx { next - 1 } else { next };
+        self.search = None;
+        self.update_gutter_width();
+        if self.blame.is_none() {
+            self.request_blame();
+        }
+        self.release_locks();
+    }
+
+    /// Lock the active buffer's file against other instances or, when one
+    /// holds it already, say which and offer to keep the buffer read-only.
+    fn lock_active(&mut self) {
+        if !self.config.lock_files || self.buffer.is_read_only() {
+            return;
+        }
+        let Some(path) = self.buffer.file_path().map(Path::to_path_buf) else {
+            return;
+        };
+        if self.locks.iter().any(|lock| same_file(lock.file(), &path)) {
+            return;
+        }
+        match lockfile::acquire(&path) {
+            Ok(lock) => self.locks.extend(lock),
+            Err(owner) => {
+                let label = strings::format(
+                    "prompt_locked",
+                    &[&shorten_path(&path), &owner.pid, &owner.host],
+                );
+                let choices = [('y', strings::get("yes")), ('n', strings::get("no"))];
+                self.open_prompt(
+                    Prompt::choice(&label, &choices),
+                    PromptAction::OpenLocked(path, owner),
+                );
+            }
+        }
+    }
+
+    /// Make the buffer of `path` read-only, or go on editing it alongside
+    /// `owner`.
+    fn answer_locked(&mut self, path: &Path, owner: &Owner, choice: char) {
+        if choice != 'y' {
+            let msg = strings::format(
+                "editing_locked",
+                &[&shorten_path(path), &owner.pid, &owner.host],
+            );
+            self.set_message(&msg, MessageType::Warning);
+            return;
+        }
+        for i in 0..self.documents.len() {
+            let buf = if i == self.active {
+                &mut self.buffer
+            } else {
+                &mut self.documents[i].buffer
+            };
+            if buf.file_path().is_some_and(|p| p == path) {
+                buf.set_read_only(true);
+            }
+        }
+    }
+
+    /// Let go of the locks of files no longer open.
+    fn release_locks(&mut self) {
+        let open: Vec<PathBuf> = (0..self.documents.len())
+            .filter_map(|i| {
+                let buf = if i == self.active {
+                    &self.buffer
+                } else {
+                    &self.documents[i].buffer
+                };
+                buf.file_path().map(Path::to_path_buf)
+            })
+            .collect();
+        self.locks
+            .retain(|lock| open.iter().any(|path| same_file(path, lock.file())));
+    }
+
+    /// Show `text` in the named scratch buffer, creating it if necessary.
+    fn show_scratch(&mut self, name: &str, text: &str) {
+        let existing = (0..self.documents.len()).find(|&i| {
+            let buf = if i == self.active {
+                &self.buffer
+            } else {
+                &self.documents[i].buffer
+            };
+            buf.name() == Some(name)
+        });
+        match existing {
+            Some(idx) => {
+                self.switch_to_document(idx);
+                self.replace_active_buffer(Buffer::scratch(name, text));
+            }
+            None => self.add_document(Buffer::scratch(name, text)),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Sessions
+    // -----------------------------------------------------------------------
+
+    /// The buffers worth bringing back: files, and unnamed buffers with
+    /// text. Scratch buffers are left out. The panel keeps its size.
+    fn session(&self) -> Session {
+        let mut session = Session {
+            panel_rows: Some(self.quickfix_rows),
+            ..Session::default()
+        };
+        for i in 0..self.documents.len() {
+            let (buf, cursor) = if i == self.active {
+                (&self.buffer, &self.cursor)
+            } else {
+                (&self.documents[i].buffer, &self.documents[i].cursor)
+            };
+            if buf.file_path().is_none() && !buf.is_modified() {
+                continue;
+            }
+            if i == self.active {
+                session.active = session.documents.len();
+            }
+            session.documents.push(SessionDocument {
+                path: buf
+                    .file_path()
+                    .map(|p| std::path::absolute(p).unwrap_or(p.to_path_buf())),
+                line: cursor.line,
+                col: cursor.col,
+                unsaved: buf.is_modified().then(|| buf.text()),
+            });
+        }
+        session
+    }
+
+    /// Write the session for `zelux --continue`. Called when the terminal
+    /// hangs up, so there is no one to tell about a failure.
+    fn save_session(&mut self) {
+        if let Some(state) = session::state_dir()
+            && self
+                .session()
+                .save(&session::new_session_dir(&state))
+                .is_ok()
+        {
+            self.finish_continued_session();
+        }
+    }
+
+    /// Delete the session continued from: its text was saved, replaced by
+    /// a newer session or given up on quitting.
+    fn finish_continued_session(&mut self) {
+        if let Some(dir) = self.continued_session.take() {
+            Session::remove(&dir);
+        }
+    }
+
+    fn restore_session(&mut self, saved: &Session) {
+        let mut failed = None;
+        let mut active = None;
+        for (i, doc) in saved.documents.iter().enumerate() {
+            let buf = match (&doc.unsaved, &doc.path) {
+                (Some(text), path) => Buffer::recovered(path.clone(), text),
+                (None, Some(path)) => match Buffer::from_file(path) {
+                    Ok(buf) => buf,
+                    Err(e) => {
+                        failed.get_or_insert(strings::format(
+                            "session_reopen_failed",
+                            &[&path.display(), &e],
+                        ));
+                        continue;
+                    }
+                },
+                (None, None) => continue,
+            };
+            if self.active_is_pristine() {
+                self.replace_active_buffer(buf);
+            } else {
+                self.add_document(buf);
+                self.refresh_git_gutter();
+            }
+            self.lock_active();
+            self.cursor.set_position(doc.line, doc.col, &self.buffer);
+            if i == saved.active {
+                active = Some(self.active);
+            }
+        }
+        if let Some(idx) = active {
+            self.switch_to_document(idx);
+        }
+        if let Some(rows) = saved.panel_rows {
+            self.set_panel_rows(rows);
+        }
+        self.adjust_viewport();
+        match failed {
+            Some(e) => self.set_message(&e, MessageType::Warning),
+            None => {
+                let unsaved = saved.documents.iter().filter(|d| d.unsaved.is_some());
+                self.set_message(
+                    &strings::format(
+                        "session_continued",
+                        &[&saved.documents.len(), &unsaved.count()],
+                    ),
+                    MessageType::Info,
+                );
+            }
+        }
+    }
+
+    /// Move the cursor to a 1-based line and column, as `path:line:col`
+    /// references count them.
+    fn goto_position(&mut self, (line, col): (usize, usize)) {
+        self.goto_line_col(line.saturating_sub(1), col.saturating_sub(1));
+    }
+
+    /// Move the cursor to a 0-based line and display column, e.g. from a
+    /// `file:line:col` reference.
+    fn goto_line_col(&mut self, line: usize, display_col: usize) {
+        let line = line.min(self.buffer.line_count().saturating_sub(1));
+        let line_text = self.buffer.get_line(line).unwrap_or_default();
+        let col = display_col_to_byte_col(&line_text, display_col);
+        self.selection = None;
+        self.cursor.set_position(line, col, &self.buffer);
+    }
+
+    // -----------------------------------------------------------------------
+    // Snippets
+    // -----------------------------------------------------------------------
+
+    /// Snippets for the active buffer's filetype, loading them on first use.
+    fn snippets_for_buffer(&mut self) -> &[Snippet] {
+        let filetype = self.buffer.filetype();
+        let idx = match self.snippets.iter().position(|(ft, _)| *ft == filetype) {
+            Some(idx) => idx,
+            None => {
+                let loaded = match snippet::load(&filetype) {
+                    Ok(list) => list,
+                    Err(e) => {
+                        self.set_message(&e, MessageType::Warning);
+                        Vec::new()
+                    }
+                };
+                self.snippets.push((filetype, loaded));
+                self.snippets.len() - 1
+            }
+        };
+        &self.snippets[idx].1
+    }
+
+    /// Expand the snippet whose trigger ends at the cursor. Returns false
+    /// when there is none.
+    fn expand_snippet(&mut self) -> bool {
+        let line_text = self
+            .buffer
+            .get_line(self.cursor.line)
+            .unwrap_or_default()
+            .into_owned();
+        let before_cursor = &line_text[..self.cursor.col];
+        let word_start = before_cursor
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+            .last()
+            .map_or(before_cursor.len(), |(i, _)| i);
+        let word = &before_cursor[word_start..];
+        if word.is_empty() {
+            return false;
+        }
+        let template = match self
+            .snippets_for_buffer()
+            .iter()
+            .find(|s| s.trigger == word)
+        {
+            Some(s) => s.template.clone(),
+            None => return false,
+        };
+
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        self.insert_snippet(
+            line_start + word_start,
+            self.cursor.col - word_start,
+            &template,
+        );
+        true
+    }
+
+    /// Replace `len` bytes at `start` with an expanded snippet template and
+    /// select its first tab stop.
+    fn insert_snippet(&mut self, start: usize, len: usize, template: &str) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let indent: String = line_text
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let expansion = snippet::expand(template, &indent);
+
+        self.undo_stack.begin_compound(self.cursor_state());
+        if len > 0 {
+            self.delete_at(start, len);
+        }
+        self.insert_at(start, &expansion.text);
+
+        let session = SnippetSession::new(start, &expansion, self.buffer.len());
+        let first = session.current();
+        let finished = session.is_finished();
+        self.snippet_session = if finished { None } else { Some(session) };
+        self.select_tab_stop(first);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+    }
+
+    fn snippet_jump(&mut self, forward: bool) {
+        let len = self.buffer.len();
+        let (stop, finished) = match self.snippet_session {
+            Some(ref mut session) => (session.advance(forward, len), session.is_finished()),
+            None => return,
+        };
+        if finished {
+            self.snippet_session = None;
+        }
+        self.select_tab_stop(stop);
+    }
+
+    /// Put the cursor at the end of a tab stop, selecting its placeholder.
+    fn select_tab_stop(&mut self, (start, end): (usize, usize)) {
+        let end = end.min(self.buffer.len());
+        let start = start.min(end);
+        let line = self.buffer.byte_to_line(end);
+        let col = end - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.selection = if end > start {
+            Some(Selection {
+                anchor: start,
+                head: end,
+            })
+        } else {
+            None
+        };
+    }
+
+    // -----------------------------------------------------------------------
+    // Completion
+    // -----------------------------------------------------------------------
+
+    /// Complete the word before the cursor. A single match is inserted
+    /// directly; several open the popup.
+    fn start_completion(&mut self) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let start = complete::word_start(&line_text, self.cursor.col);
+        if start == self.cursor.col {
+            self.set_message(strings::get("no_word_to_complete"), MessageType::Warning);
+            return;
+        }
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        self.completion = Some(Completion::new(line_start + start));
+        self.update_completion();
+        match self.completion {
+            None => self.set_message(strings::get("no_completions"), MessageType::Info),
+            Some(ref c) if c.items.len() == 1 => self.accept_completion(),
+            Some(_) => {}
+        }
+    }
+
+    /// Recompute the candidates for the text between the completion start
+    /// and the cursor, closing the popup when nothing matches. Server items
+    /// may complete an empty prefix (after `.` or `::`); buffer words may not.
+    fn update_completion(&mut self) {
+        let (start, has_server_items) = match self.completion {
+            Some(ref c) => (c.start, !c.server_items.is_empty()),
+            None => return,
+        };
+        let cursor = self.cursor.byte_offset(&self.buffer);
+        let empty_ok = has_server_items && cursor == start;
+        if (cursor <= start && !empty_ok) || self.buffer.byte_to_line(start) != self.cursor.line {
+            self.completion = None;
+            return;
+        }
+        let prefix = self.buffer.slice(start, cursor);
+        if prefix.chars().any(|c| !(c.is_alphanumeric() || c == '_')) {
+            self.completion = None;
+            return;
+        }
+        self.buffer.make_contiguous();
+        for doc in &mut self.documents {
+            doc.buffer.make_contiguous();
+        }
+        if self.listed_words.is_none() {
+            let lists = self.word_lists();
+            let text = lists.iter().filter_map(|p| fs::read_to_string(p).ok());
+            self.listed_words = Some(text.collect::<Vec<_>>().join("\n"));
+        }
+        let active = self.buffer.contents();
+        let others: Vec<Cow<str>> = self
+            .documents
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != self.active)
+            .map(|(_, doc)| doc.buffer.contents())
+            .chain(self.listed_words.as_deref().map(Cow::Borrowed))
+            .collect();
+        let others: Vec<&str> = others.iter().map(AsRef::as_ref).collect();
+        let words = complete::candidates(&prefix, &active, cursor, &others);
+        if let Some(ref mut c) = self.completion {
+            let items = complete::merge(&c.server_items, &prefix, words);
+            if items.is_empty() {
+                self.completion = None;
+            } else {
+                c.set_items(items);
+            }
+        }
+    }
+
+    /// Replace the typed prefix with the selected candidate. Snippet items
+    /// from the language server expand with tab stops.
+    fn accept_completion(&mut self) {
+        let completion = match self.completion.take() {
+            Some(c) => c,
+            None => return,
+        };
+        let cursor = self.cursor.byte_offset(&self.buffer);
+        let item = completion.current().clone();
+        if item.snippet {
+            self.insert_snippet(completion.start, cursor - completion.start, &item.insert);
+            return;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(completion.start, cursor - completion.start);
+        self.insert_at(completion.start, &item.insert);
+        let end = completion.start + item.insert.len();
+        let line = self.buffer.byte_to_line(end);
+        let col = end - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Keys while the popup is open. Returns true when the key was consumed;
+    /// typing and backspace pass through and refine the list.
+    fn handle_completion_key(&mut self, ke: &KeyEvent) -> bool {
+        let completion = match self.completion {
+            Some(ref mut c) => c,
+            None => return false,
+        };
+        let len = completion.items.len();
+        match (
+            completion.list.handle_key(ke, len, complete::VISIBLE_ITEMS),
+            &ke.key,
+        ) {
+            (KeyResult::Consumed, _) => {}
+            (KeyResult::Accept, _) | (_, Key::Tab) => self.accept_completion(),
+            (KeyResult::Cancel, _) => self.completion = None,
+            (_, Key::Char(_) | Key::Backspace) if !ke.ctrl && !ke.alt => {
+                // Let the key edit the buffer, then refilter
+                let completion = self.completion.take();
+                self.handle_key(ke.clone());
+                self.completion = completion;
+                self.update_completion();
+            }
+            _ => {
+                self.completion = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Draw the list below the cursor (or above when there is no room), with
+    /// the selected item's details in a panel beside it.
+    fn render_completion(&mut self, text_height: usize) {
+        let completion = match self.completion {
+            Some(ref c) => c,
+            None => return,
+        };
+        let has_icons = completion.items.iter().any(|i| i.kind != ' ');
+        let label_width = completion
+            .items
+            .iter()
+            .map(|i| i.label.chars().count())
+            .max()
+            .unwrap_or(0);
+        let width = label_width + if has_icons { 4 } else { 2 };
+        let rows = completion.items.len().min(complete::VISIBLE_ITEMS);
+        let cursor = self.cursor.byte_offset(&self.buffer);
+        let prefix_cols = self.buffer.slice(completion.start, cursor).chars().count();
+        let screen_width = self.screen.width();
+        let (cursor_row, cursor_col) = self.cursor_screen_pos();
+        let left = cursor_col.saturating_sub(prefix_cols + if has_icons { 2 } else { 0 });
+        let area = Rect::new(0, 0, screen_width, text_height);
+        let rect = Rect::near(cursor_row, left, width, rows, area);
+        let texts: Vec<String> = completion
+            .items
+            .iter()
+            .map(|item| {
+                if has_icons {
+                    format!(" {} {}", item.kind, item.label)
+                } else {
+                    format!(" {}", item.label)
+                }
+            })
+            .collect();
+        let style = Style::plain(Color::Ansi(7), ui::PANEL_BG);
+        ui::draw_list(&mut self.screen, rect, &texts, &completion.list, style);
+
+        // Documentation preview to the right of the list
+        let item = completion.current();
+        let mut lines: Vec<(String, Color)> = Vec::new();
+        if let Some(ref detail) = item.detail {
+            lines.push((detail.clone(), Color::Ansi(6)));
+        }
+        if let Some(ref doc) = item.documentation {
+            lines.extend(doc.lines().map(|l| (l.to_string(), Color::Default)));
+        }
+        let doc_left = rect.left + rect.width;
+        let doc_width = screen_width.saturating_sub(doc_left).min(60);
+        if lines.is_empty() || doc_width < 20 {
+            return;
+        }
+        let wrapped: Vec<(String, Color)> = lines
+            .iter()
+            .flat_map(|(text, fg)| {
+                ui::wrap_line(text, doc_width - 2)
+                    .into_iter()
+                    .map(|r| (r, *fg))
+            })
+            .take(complete::VISIBLE_ITEMS)
+            .collect();
+        let height = wrapped.len().min(text_height.saturating_sub(rect.top));
+        let bg = Color::Color256(238);
+        let doc = Rect::new(rect.top, doc_left, doc_width, height);
+        let inner = ui::draw_frame(
+            &mut self.screen,
+            doc,
+            Style::plain(Color::Default, bg),
+            None,
+        );
+        for (row, (text, fg)) in (inner.top..inner.bottom()).zip(&wrapped) {
+            self.screen
+                .put_str(row, inner.left + 1, text, *fg, bg, false);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Language server completion and signature help
+    // -----------------------------------------------------------------------
+
+    /// React to a typed character when the buffer has a running language
+    /// server: words and member accessors queue a completion request,
+    /// call parentheses and commas ask for signature help.
+    fn lsp_after_typing(&mut self, ch: char) {
+        let path = match self.buffer.file_path() {
+            Some(p) => p.to_path_buf(),
+            None => return,
+        };
+        if self.lsp_client_for(&path).is_none() {
+            return;
+        }
+        match ch {
+            '(' | ',' => self.lsp_request_at("textDocument/signatureHelp"),
+            ')' => self.signature = None,
+            _ => {}
+        }
+        if ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == ':' {
+            self.lsp_completion_pending = true;
+        }
+    }
+
+    /// Send the completion request queued by typing, once input pauses.
+    fn request_lsp_completion(&mut self) {
+        if !std::mem::take(&mut self.lsp_completion_pending) {
+            return;
+        }
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let before = &line_text[..self.cursor.col];
+        // Only after part of a word or a member accessor
+        if !before.ends_with(|c: char| c.is_alphanumeric() || c == '_')
+            && !before.ends_with('.')
+            && !before.ends_with("::")
+        {
+            return;
+        }
+        self.lsp_request_at("textDocument/completion");
+        self.completion_request = Some(self.cursor.byte_offset(&self.buffer));
+    }
+
+    /// Merge a completion response into the popup, unless the cursor moved
+    /// since the request.
+    fn show_lsp_completion(&mut self, result: &JsonValue) {
+        let cursor = self.cursor.byte_offset(&self.buffer);
+        if self.completion_request.take() != Some(cursor) || self.popup.is_some() {
+            return;
+        }
+        let items = lsp::parse_completion(result);
+        if items.is_empty() {
+            return;
+        }
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        let start = line_start + complete::word_start(&line_text, self.cursor.col);
+        let mut completion = match self.completion.take() {
+            Some(c) if c.start == start => c,
+            _ => Completion::new(start),
+        };
+        completion.server_items = items;
+        self.completion = Some(completion);
+        self.update_completion();
+    }
+
+    /// Show the signature being called one row above the cursor, with the
+    /// active parameter highlighted.
+    fn render_signature(&mut self, text_height: usize) {
+        let help = match self.signature {
+            Some((ref help, _)) => help,
+            None => return,
+        };
+        let cursor_row = self.cursor.line.saturating_sub(self.scroll_row);
+        let row = match cursor_row.checked_sub(1) {
+            Some(row) => row,
+            None if cursor_row + 1 < text_height => cursor_row + 1,
+            None => return,
+        };
+        let screen_width = self.screen.width();
+        let width = (help.label.chars().count() + 2).min(screen_width);
+        let left = (self.cursor_display_col().saturating_sub(self.scroll_col) + self.gutter_width)
+            .min(screen_width.saturating_sub(width));
+        let bg = Color::Color256(236);
+        self.screen
+            .put_char(row, left, ' ', Color::Default, bg, false);
+        let mut col = left + 1;
+        for (i, ch) in help.label.char_indices() {
+            if col + 1 >= left + width {
+                break;
+            }
+            let active = help.active.is_some_and(|(s, e)| s <= i && i < e);
+            let fg = if active {
+                Color::Ansi(3)
+            } else {
+                Color::Ansi(7)
+            };
+            self.screen.put_char(row, col, ch, fg, bg, active);
+            col += 1;
+        }
+        self.screen
+            .put_char(row, col, ' ', Color::Default, bg, false);
+    }
+
+    // -----------------------------------------------------------------------
+    // Pairs
+    // -----------------------------------------------------------------------
+
+    /// Insert a typed character. With auto-pairs on, an opener gets its
+    /// closer (or wraps the selection) and a closer already under the
+    /// cursor is stepped over.
+    fn type_char(&mut self, ch: char) {
+        if !self.config.auto_pairs {
+            self.delete_selection();
+            self.insert_char(ch);
+            return;
+        }
+        if let Some(close) = pairs::closer_for(ch)
+            && self.selection_range().is_some_and(|(s, e)| s < e)
+        {
+            self.surround_selection(ch, close);
+            return;
+        }
+        self.delete_selection();
+
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let prev = line_text[..self.cursor.col].chars().next_back();
+        let next = line_text[self.cursor.col..].chars().next();
+        if pairs::is_closer(ch) && next == Some(ch) {
+            self.cursor.move_right(&self.buffer);
+            return;
+        }
+        match pairs::closer_for(ch) {
+            Some(close) if pairs::should_autoclose(ch, prev, next) => {
+                let before = self.cursor_state();
+                let pos = self.cursor.byte_offset(&self.buffer);
+                let text = format!("{}{}", ch, close);
+                self.buffer.insert(pos, &text);
+                self.undo_stack.record(
+                    Operation::Insert { pos, text },
+                    before,
+                    GroupContext::Typing,
+                );
+                self.cursor.move_right(&self.buffer);
+            }
+            _ => self.insert_char(ch),
+        }
+    }
+
+    /// True when the cursor sits between an empty pair like `(|)`.
+    fn between_empty_pair(&self) -> bool {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let prev = line_text[..self.cursor.col].chars().next_back();
+        let next = line_text[self.cursor.col..].chars().next();
+        prev.and_then(pairs::closer_for)
+            .is_some_and(|c| Some(c) == next)
+    }
+
+    fn handle_pending_pair(&mut self, pending: PendingPair, ke: &KeyEvent) {
+        let ch = match ke.key {
+            Key::Char(ch) if !ke.ctrl && !ke.alt => ch,
+            _ => return, // any other key cancels
+        };
+        let (open, close) = match pairs::pair_of(ch) {
+            Some(pair) => pair,
+            None => {
+                self.set_message(&strings::format("not_a_pair", &[&ch]), MessageType::Warning);
+                return;
+            }
+        };
+        match pending {
+            PendingPair::Surround => self.surround_selection(open, close),
+            PendingPair::Delete => self.replace_surrounding(open, close, ""),
+            PendingPair::Change => {
+                self.pending_pair = Some(PendingPair::ChangeTo(open));
+                self.set_message(
+                    &strings::format("prompt_change_pair", &[&open]),
+                    MessageType::Info,
+                );
+            }
+            PendingPair::ChangeTo(old) => {
+                if let Some((old_open, old_close)) = pairs::pair_of(old) {
+                    let mut replacement = String::new();
+                    replacement.push(open);
+                    replacement.push(close);
+                    self.replace_surrounding(old_open, old_close, &replacement);
+                }
+            }
+        }
+    }
+
+    /// Wrap the selection in `open`..`close`, keeping the inner text selected.
+    fn surround_selection(&mut self, open: char, close: char) {
+        let (start, end) = match self.selection_range() {
+            Some((s, e)) if s < e => (s, e),
+            _ => return,
+        };
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.insert_at(end, &close.to_string());
+        self.insert_at(start, &open.to_string());
+        let inner_start = start + open.len_utf8();
+        let inner_end = end + open.len_utf8();
+        self.select_tab_stop((inner_start, inner_end));
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Replace the `open`..`close` pair around the cursor with the two
+    /// characters of `replacement` (or delete it when empty).
+    fn replace_surrounding(&mut self, open: char, close: char, replacement: &str) {
+        let pos = self.cursor.byte_offset(&self.buffer);
+        self.buffer.make_contiguous();
+        let found = pairs::find_surrounding(&self.buffer.contents(), pos, open, close);
+        let (a, b) = match found {
+            Some(found) => found,
+            None => {
+                self.set_message(
+                    &strings::format("no_surrounding", &[&open]),
+                    MessageType::Warning,
+                );
+                return;
+            }
+        };
+        let mut chars = replacement.chars();
+        let new_open = chars.next().map(String::from).unwrap_or_default();
+        let new_close = chars.next().map(String::from).unwrap_or_default();
+
+        self.undo_stack.begin_compound(self.cursor_state());
+        self.delete_at(b, close.len_utf8());
+        self.insert_at(b, &new_close);
+        self.delete_at(a, open.len_utf8());
+        self.insert_at(a, &new_open);
+
+        // Keep the cursor on the same character of the inner text
+        let mut pos = pos;
+        if pos > b {
+            pos = pos + new_close.len() - close.len_utf8();
+        }
+        if pos > a {
+            pos = pos + new_open.len() - open.len_utf8();
+        }
+        let pos = pos.min(self.buffer.len());
+        let line = self.buffer.byte_to_line(pos);
+        let col = pos - self.buffer.line_start(line).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.selection = None;
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    fn insert_at(&mut self, pos: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let before = self.cursor_state();
+        self.buffer.insert(pos, text);
+        self.undo_stack.record(
+            Operation::Insert {
+                pos,
+                text: text.to_string(),
+            },
+            before,
+            GroupContext::Other,
+        );
+    }
+
+    fn delete_at(&mut self, pos: usize, len: usize) {
+        let before = self.cursor_state();
+        let deleted = self.buffer.delete(pos, len);
+        self.undo_stack.record(
+            Operation::Delete { pos, text: deleted },
+            before,
+            GroupContext::Other,
+        );
+    }
+
+    // -----------------------------------------------------------------------
+    // Language servers
+    // -----------------------------------------------------------------------
+
+    /// Index of the language server for `filetype`, starting it on first
+    /// use. None when no server is configured or it failed.
+    fn lsp_index(&mut self, filetype: &str) -> Option<usize> {
+        if let Some(i) = self.lsp_clients.iter().position(|(ft, _)| ft == filetype) {
+            return self.lsp_clients[i].1.is_some().then_some(i);
+        }
+        let command = self
+            .config
+            .language_servers
+            .iter()
+            .find(|(ft, _)| ft == filetype)?
+            .1
+            .clone();
+        let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let client = match LspClient::start(&command, &root) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                self.set_message(&e, MessageType::Warning);
+                None
+            }
+        };
+        let started = client.is_some();
+        self.lsp_clients.push((filetype.to_string(), client));
+        started.then_some(self.lsp_clients.len() - 1)
+    }
+
+    /// The running server for `path`, without starting one.
+    fn lsp_client_for(&mut self, path: &Path) -> Option<&mut LspClient> {
+        let filetype = filetype::detect(Some(path));
+        self.lsp_clients
+            .iter_mut()
+            .find(|(ft, _)| *ft == filetype)?
+            .1
+            .as_mut()
+    }
+
+    /// Send the active buffer's latest contents to its language server.
+    fn lsp_sync(&mut self) {
+        let path = match self.buffer.file_path() {
+            Some(p) => p.to_path_buf(),
+            None => return,
+        };
+        let filetype = filetype::detect(Some(&path));
+        let idx = match self.lsp_index(&filetype) {
+            Some(i) => i,
+            None => return,
+        };
+        let buffer = &self.buffer;
+        if let Some(client) = self.lsp_clients[idx].1.as_mut() {
+            client.sync(&path, &filetype, buffer.version(), || buffer.text());
+        }
+    }
+
+    fn lsp_close_active(&mut self) {
+        if let Some(path) = self.buffer.file_path().map(Path::to_path_buf)
+            && let Some(client) = self.lsp_client_for(&path)
+        {
+            client.did_close(&path);
+        }
+    }
+
+    fn poll_lsp(&mut self) {
+        let mut events = Vec::new();
+        for (_, slot) in self.lsp_clients.iter_mut() {
+            if let Some(client) = slot {
+                let polled = client.poll();
+                if polled.iter().any(|e| matches!(e, LspEvent::Exited(_))) {
+                    *slot = None;
+                }
+                events.extend(polled);
+            }
+        }
+        for event in events {
+            match event {
+                LspEvent::Diagnostics(path, diags) => {
+                    let annotations = diagnostic_annotations(&diags);
+                    if self.buffer.file_path() == Some(path.as_path()) {
+                        self.virtual_text.set(Source::Diagnostics, annotations);
+                        // The server has caught up: ask again for hints it
+                        // may have been too busy to give
+                        self.inlay_hints = None;
+                    } else if let Some(doc) = self.document_for(&path) {
+                        doc.virtual_text.set(Source::Diagnostics, annotations);
+                    }
+                    self.diagnostics.retain(|(p, _)| *p != path);
+                    if !diags.is_empty() {
+                        self.diagnostics.push((path, diags));
+                    }
+                }
+                LspEvent::Response {
+                    method,
+                    result,
+                    encoding,
+                } if method == "textDocument/inlayHint" => {
+                    self.show_inlay_hints(result.ok().as_ref(), encoding)
+                }
+                LspEvent::Response {
+                    method,
+                    result,
+                    encoding,
+                } => match result {
+                    Err(e) => self.set_message(&e, MessageType::Warning),
+                    Ok(value) => self.handle_lsp_response(&method, &value, encoding),
+                },
+                LspEvent::Message(text) => self.set_message(&text, MessageType::Info),
+                LspEvent::Exited(e) => self.set_message(&e, MessageType::Warning),
+            }
+        }
+    }
+
+    fn active_diagnostics(&self) -> &[Diagnostic] {
+        let path = match self.buffer.file_path() {
+            Some(p) => p,
+            None => return &[],
+        };
+        self.diagnostics
+            .iter()
+            .find(|(p, _)| p == path)
+            .map_or(&[], |(_, d)| d.as_slice())
+    }
+
+    /// The most severe diagnostic touching `line`.
+    fn line_severity(&self, line: usize) -> Option<Severity> {
+        self.active_diagnostics()
+            .iter()
+            .filter(|d| d.contains_line(line))
+            .map(|d| d.severity)
+            .min()
+    }
+
+    /// Byte ranges of `line` to underline. Empty ranges widen to one
+    /// character so they stay visible.
+    fn diagnostic_spans(&self, line: usize, text: &str) -> Vec<(usize, usize)> {
+        self.active_diagnostics()
+            .iter()
+            .filter(|d| d.contains_line(line))
+            .filter_map(|d| {
+                let start = if d.start.0 == line { d.start.1 } else { 0 };
+                let end = if d.end.0 == line { d.end.1 } else { text.len() };
+                // Columns from before later edits may fall inside a character
+                let start = text.floor_char_boundary(start);
+                let end = text.floor_char_boundary(end);
+                if end > start {
+                    Some((start, end))
+                } else {
+                    let ch = text[start..].chars().next()?;
+                    Some((start, start + ch.len_utf8()))
+                }
+            })
+            .collect()
+    }
+
+    /// Open a popup with the messages of the diagnostics on the cursor line.
+    fn show_diagnostics(&mut self) {
+        let line = self.cursor.line;
+        let mut lines = Vec::new();
+        for d in self
+            .active_diagnostics()
+            .iter()
+            .filter(|d| d.contains_line(line))
+        {
+            let fg = severity_color(d.severity);
+            for (i, text) in d.message.lines().enumerate() {
+                let text = if i == 0 {
+                    format!("{}: {}", d.severity.label(), text)
+                } else {
+                    format!("  {}", text)
+                };
+                lines.push((text, fg));
+            }
+        }
+        if lines.is_empty() {
+            self.set_message(strings::get("no_diagnostics"), MessageType::Info);
+        } else {
+            self.popup = Some(TextPanel::new(lines));
+        }
+    }
+
+    /// Ask the active buffer's server about the cursor position.
+    fn lsp_request_at(&mut self, method: &str) {
+        self.lsp_sync();
+        let line = self.cursor.line;
+        let line_text = self.buffer.get_line(line).unwrap_or_default().into_owned();
+        let col = self.cursor.col;
+        let client = match self.buffer.file_path().map(Path::to_path_buf) {
+            Some(path) => self.lsp_client_for(&path).map(|c| (c, path)),
+            None => None,
+        };
+        match client {
+            Some((client, path)) => {
+                let character = lsp::col_to_lsp(&line_text, col, client.encoding());
+                client.request_at(method, &path, line, character);
+            }
+            None => self.set_message(strings::get("no_language_server"), MessageType::Warning),
+        }
+    }
+
+    fn handle_lsp_response(
+        &mut self,
+        method: &str,
+        result: &JsonValue,
+        encoding: PositionEncoding,
+    ) {
+        match method {
+            "textDocument/definition" => {
+                let locations = lsp::parse_locations(result);
+                match locations.first() {
+                    Some(location) => self.jump_to_location(location, encoding),
+                    None => {
+                        if !self.goto_tag() {
+                            self.set_message(strings::get("no_definition"), MessageType::Info);
+                        }
+                    }
+                }
+            }
+            "textDocument/hover" => match lsp::hover_text(result) {
+                Some(text) => {
+                    let lines = text
+                        .lines()
+                        .map(|l| (l.to_string(), Color::Default))
+                        .collect();
+                    self.popup = Some(TextPanel::new(lines));
+                }
+                None => self.set_message(strings::get("no_hover"), MessageType::Info),
+            },
+            "textDocument/completion" => self.show_lsp_completion(result),
+            "textDocument/signatureHelp" => {
+                self.signature = lsp::parse_signature_help(result).map(|h| (h, self.cursor.line));
+            }
+            _ => {}
+        }
+    }
+
+    /// Ask the active buffer's server for its inlay hints once per buffer
+    /// version.
+    fn request_inlay_hints(&mut self) {
+        if !self.config.virtual_text {
+            return;
+        }
+        let Some(path) = self.buffer.file_path().map(Path::to_path_buf) else {
+            return;
+        };
+        let asked = (path, self.buffer.version());
+        if self.inlay_hints.as_ref() == Some(&asked) {
+            return;
+        }
+        let line_count = self.buffer.line_count();
+        if let Some(client) = self.lsp_client_for(&asked.0) {
+            client.request_inlay_hints(&asked.0, line_count);
+            self.inlay_hints = Some(asked);
+        }
+    }
+
+    /// Show the hints in `result` inline, if they are for the buffer as it
+    /// is now. A failed request (None) clears them.
+    fn show_inlay_hints(&mut self, result: Option<&JsonValue>, encoding: PositionEncoding) {
+        let current = self
+            .buffer
+            .file_path()
+            .map(|path| (path.to_path_buf(), self.buffer.version()));
+        if current.is_none() || current != self.inlay_hints {
+            return;
+        }
+        let Some(result) = result else {
+            self.virtual_text.clear(Source::InlayHints);
+            return;
+        };
+        let annotations = lsp::parse_inlay_hints(result)
+            .into_iter()
+            .filter_map(|hint| {
+                let text = self.buffer.get_line(hint.line)?;
+                let col = lsp::col_from_lsp(&text, hint.character, encoding);
+                Some(Annotation {
+                    line: hint.line,
+                    placement: Placement::Inline(col),
+                    text: hint.label,
+                    fg: Color::Color256(243),
+                })
+            })
+            .collect();
+        self.virtual_text.set(Source::InlayHints, annotations);
+    }
+
+    /// Open the location's file (if needed) and move there, remembering the
+    /// current position in the jump list.
+    fn jump_to_location(&mut self, location: &Location, encoding: PositionEncoding) {
+        let here = self.current_jump();
+        if let Err(e) = self.open_path(&location.path) {
+            self.set_message(&e, MessageType::Error);
+            return;
+        }
+        if let Some(here) = here {
+            self.jumps.push(here);
+        }
+        let line = location
+            .line
+            .min(self.buffer.line_count().saturating_sub(1));
+        let line_text = self.buffer.get_line(line).unwrap_or_default();
+        let col = lsp::col_from_lsp(&line_text, location.character, encoding);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.selection = None;
+    }
+
+    /// Keys while a popup is shown: arrows and paging scroll an overflowing
+    /// popup, Escape closes it, anything else closes it and acts as usual.
+    fn handle_popup_key(&mut self, ke: &KeyEvent) -> bool {
+        let popup = match self.popup {
+            Some(ref mut p) => p,
+            None => return false,
+        };
+        match popup.handle_key(ke) {
+            KeyResult::Consumed => true,
+            KeyResult::Cancel => {
+                self.popup = None;
+                true
+            }
+            _ => {
+                self.popup = None;
+                false
+            }
+        }
+    }
+
+    /// Draw the popup next to the cursor, word-wrapped and scrolled.
+    fn render_popup(&mut self, text_height: usize) {
+        let (row, col) = self.cursor_screen_pos();
+        let area = Rect::new(0, 0, self.screen.width(), text_height);
+        if let Some(ref mut popup) = self.popup {
+            popup.render(&mut self.screen, row, col, area);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Buffer options
+    // -----------------------------------------------------------------------
+
+    /// Columns between tab stops in the active buffer: its own width, else
+    /// the configured one.
+    fn tab_width(&self) -> usize {
+        self.buffer.tab_width().unwrap_or(self.config.tab_width)
+    }
+
+    /// The column the active buffer's paragraphs are reflowed to.
+    fn text_width(&self) -> usize {
+        self.buffer.text_width().unwrap_or(self.config.text_width)
+    }
+
+    /// The label of `option` and its value in the active buffer.
+    fn option_row(&self, option: BufferOption) -> (&'static str, String) {
+        let check = |on: bool| if on { "[x]" } else { "[ ]" }.to_string();
+        match option {
+            BufferOption::TextWidth => (
+                strings::get("option_text_width"),
+                self.text_width().to_string(),
+            ),
+            BufferOption::TabWidth => (
+                strings::get("option_tab_width"),
+                self.tab_width().to_string(),
+            ),
+            BufferOption::Filetype => (strings::get("option_filetype"), self.buffer.filetype()),
+            BufferOption::LineEnding => (
+                strings::get("option_line_ending"),
+                line_ending_name(self.buffer.is_crlf()).to_string(),
+            ),
+            BufferOption::Encoding => (strings::get("option_encoding"), "utf-8".to_string()),
+            BufferOption::ReadOnly => (
+                strings::get("option_read_only"),
+                check(self.buffer.is_read_only()),
+            ),
+        }
+    }
+
+    /// Keys while the options panel is open: arrows move between the rows,
+    /// Left and Right (or - and +) step a number and digits type one, and
+    /// Space or Enter flips a toggle or asks for the filetype. Escape, or
+    /// Enter on a number, closes the panel. Changes apply at once.
+    fn handle_options_panel_key(&mut self, ke: &KeyEvent) {
+        let Some(ref mut panel) = self.options_panel else {
+            return;
+        };
+        let option = BUFFER_OPTIONS[panel.list.selected];
+        let number = matches!(option, BufferOption::TextWidth | BufferOption::TabWidth);
+        let plain = !ke.ctrl && !ke.alt;
+        match ke.key {
+            Key::Char(c) if plain && number && c.is_ascii_digit() => {
+                let typed = panel.typed.get_or_insert_default();
+                typed.push(c);
+                let max = *width_range(option).end();
+                match typed.parse() {
+                    Ok(width) if width <= max => self.set_width(option, width),
+                    _ => {
+                        typed.pop();
+                    }
+                }
+            }
+            Key::Backspace if panel.typed.is_some() => {
+                let typed = panel.typed.get_or_insert_default();
+                typed.pop();
+                if let Ok(width) = typed.parse() {
+                    self.set_width(option, width);
+                }
+            }
+            Key::Left | Key::Right | Key::Char('-' | '+') if plain && number => {
+                panel.typed = None;
+                let step = if matches!(ke.key, Key::Left | Key::Char('-')) {
+                    -1
+                } else {
+                    1
+                };
+                let width = match option {
+                    BufferOption::TabWidth => self.tab_width(),
+                    _ => self.text_width(),
+                };
+                self.set_width(option, width.saturating_add_signed(step));
+            }
+            Key::Char(' ') | Key::Enter if !number => self.change_option(option),
+            _ => {
+                let len = BUFFER_OPTIONS.len();
+                match panel.list.handle_key(ke, len, len) {
+                    KeyResult::Consumed => panel.typed = None,
+                    KeyResult::Accept | KeyResult::Cancel => self.options_panel = None,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Give the active buffer its own tab or reflow width, when `width`
+    /// is one the option allows.
+    fn set_width(&mut self, option: BufferOption, width: usize) {
+        if !width_range(option).contains(&width) {
+            return;
+        }
+        match option {
+            BufferOption::TabWidth => self.buffer.set_tab_width(Some(width)),
+            BufferOption::TextWidth => self.buffer.set_text_width(Some(width)),
+            _ => {}
+        }
+    }
+
+    /// Flip a toggle of the panel, or leave it to ask for the filetype.
+    fn change_option(&mut self, option: BufferOption) {
+        match option {
+            BufferOption::Filetype => {
+                self.options_panel = None;
+                self.run_command(Command::SetFiletype);
+            }
+            BufferOption::LineEnding => self.set_line_ending(!self.buffer.is_crlf()),
+            BufferOption::Encoding => {
+                self.set_message(strings::get("utf8_only"), MessageType::Info)
+            }
+            BufferOption::ReadOnly => {
+                let read_only = self.buffer.is_read_only();
+                self.buffer.set_read_only(!read_only);
+            }
+            BufferOption::TextWidth | BufferOption::TabWidth => {}
+        }
+    }
+
+    /// End every line of the active buffer in CRLF, or in LF alone, as one
+    /// undoable change.
+    fn set_line_ending(&mut self, crlf: bool) {
+        if self.reject_read_only() {
+            return;
+        }
+        let count = self.buffer.line_count();
+        let lines: Vec<String> = (0..count)
+            .map(|line| {
+                let text = self.buffer.get_line(line).unwrap_or_default();
+                if line + 1 == count {
+                    return text.into_owned();
+                }
+                let text = text.strip_suffix('\r').unwrap_or(&text);
+                if crlf {
+                    format!("{}\r", text)
+                } else {
+                    text.to_string()
+                }
+            })
+            .collect();
+        self.replace_lines(0, count - 1, &lines);
+    }
+
+    /// Draw the options panel boxed in the middle of the text, a label
+    /// and a value on each row and the keys on the last.
+    fn render_options_panel(&mut self, text_height: usize) {
+        let Some(ref panel) = self.options_panel else {
+            return;
+        };
+        let rows: Vec<(&str, String)> = BUFFER_OPTIONS
+            .iter()
+            .enumerate()
+            .map(|(i, &option)| match panel.typed {
+                Some(ref typed) if i == panel.list.selected => {
+                    (self.option_row(option).0, format!("{}_", typed))
+                }
+                _ => self.option_row(option),
+            })
+            .collect();
+        let label_width = rows.iter().map(|(l, _)| l.chars().count()).max();
+        let label_width = label_width.unwrap_or(0);
+        let texts: Vec<String> = rows
+            .iter()
+            .map(|(label, value)| format!(" {:<w$}  {} ", label, value, w = label_width))
+            .collect();
+        let hint = strings::get("options_hint");
+        let width = texts
+            .iter()
+            .map(|t| t.chars().count())
+            .chain([hint.chars().count() + 2])
+            .max()
+            .unwrap_or(0);
+        let area = Rect::new(0, 0, self.screen.width(), text_height);
+        let rect = Rect::centered(width + 2, texts.len() + 3, area);
+        let title = strings::get("options_title");
+        let inner = ui::draw_frame(&mut self.screen, rect, Style::boxed(), Some(title));
+        if inner.height == 0 {
+            return;
+        }
+        let list = Rect::new(inner.top, inner.left, inner.width, inner.height - 1);
+        ui::draw_list(&mut self.screen, list, &texts, &panel.list, Style::boxed());
+        let hint_row = inner.bottom() - 1;
+        self.screen.put_str(
+            hint_row,
+            inner.left + 1,
+            hint,
+            Color::Color256(244),
+            ui::PANEL_BG,
+            false,
+        );
+    }
+
+    // -----------------------------------------------------------------------
+    // Symbols and tags
+    // -----------------------------------------------------------------------
+
+    /// Go to the definition of the symbol at the cursor: ask the language
+    /// server when one runs for the buffer, otherwise look it up in tags.
+    fn goto_definition(&mut self) {
+        let has_server = self
+            .buffer
+            .file_path()
+            .map(Path::to_path_buf)
+            .is_some_and(|path| self.lsp_client_for(&path).is_some());
+        if has_server {
+            self.lsp_request_at("textDocument/definition");
+        } else if !self.goto_tag() {
+            self.set_message(strings::get("no_definition"), MessageType::Info);
+        }
+    }
+
+    /// The identifier under (or just before) the cursor.
+    fn word_under_cursor(&self) -> Option<String> {
+        let line_text = self.buffer.get_line(self.cursor.line)?;
+        let start = complete::word_start(&line_text, self.cursor.col);
+        let end = line_text[self.cursor.col..]
+            .find(|c: char| !unicode::is_word_char(c))
+            .map_or(line_text.len(), |i| self.cursor.col + i);
+        (start < end).then(|| line_text[start..end].to_string())
+    }
+
+    /// Tags from the nearest tags file above the buffer (or the working
+    /// directory). Missing files give an empty list.
+    fn load_tags(&mut self) -> Vec<tags::Tag> {
+        let dir = self
+            .buffer
+            .file_path()
+            .and_then(|p| std::fs::canonicalize(p).ok())
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+            .or_else(|| std::env::current_dir().ok());
+        let file = match dir.as_deref().and_then(tags::find_tags_file) {
+            Some(f) => f,
+            None => return Vec::new(),
+        };
+        let mut tags = tags::load(&file).unwrap_or_else(|e| {
+            self.set_message(&e, MessageType::Error);
+            Vec::new()
+        });
+        // Show and open paths relative to the working directory
+        if let Ok(cwd) = std::env::current_dir() {
+            for tag in &mut tags {
+                if let Ok(relative) = tag.path.strip_prefix(&cwd) {
+                    tag.path = relative.to_path_buf();
+                }
+            }
+        }
+        tags
+    }
+
+    /// Jump to the tag named by the word under the cursor. Several matches
+    /// open the symbol picker on them. Returns false when there is none.
+    fn goto_tag(&mut self) -> bool {
+        let word = match self.word_under_cursor() {
+            Some(w) => w,
+            None => return false,
+        };
+        let mut entries: Vec<PickerEntry> = self
+            .load_tags()
+            .into_iter()
+            .filter(|t| t.name == word)
+            .map(tag_entry)
+            .collect();
+        match entries.len() {
+            0 => false,
+            1 => {
+                let entry = entries.remove(0);
+                self.jump_to_symbol(&entry);
+                true
+            }
+            _ => {
+                self.symbol_picker = Some(SymbolPicker::new(entries));
+                self.start_prompt(
+                    &strings::format("prompt_definition", &[&word]),
+                    PromptAction::JumpToSymbol,
+                );
+                true
+            }
+        }
+    }
+
+    /// List the buffer's outline and the project's tags in a fuzzy picker.
+    fn open_symbol_picker(&mut self) {
+        let filetype = self.buffer.filetype();
+        self.buffer.make_contiguous();
+        let mut entries: Vec<PickerEntry> = symbols::outline(&self.buffer.contents(), &filetype)
+            .into_iter()
+            .map(|s| PickerEntry {
+                detail: format!("{}  :{}", s.kind, s.line + 1),
+                name: s.name,
+                target: SymbolTarget::Line(s.line),
+            })
+            .collect();
+        entries.extend(self.load_tags().into_iter().map(tag_entry));
+        if entries.is_empty() {
+            self.set_message(strings::get("no_symbols"), MessageType::Info);
+            return;
+        }
+        self.symbol_picker = Some(SymbolPicker::new(entries));
+        self.start_prompt(strings::get("prompt_symbol"), PromptAction::JumpToSymbol);
+    }
+
+    /// Pick a file under `root` by fuzzy name. The index is built on first
+    /// use and brought up to date each time, so new files are listed.
+    fn open_file_finder(&mut self, root: &Path) {
+        match self.file_index {
+            Some((ref index, _)) if index.root() == root => self.refresh_file_index(true),
+            _ => self.file_index = Some((FileIndex::scan(root), Instant::now())),
+        }
+        let entries = self.file_entries();
+        if entries.is_empty() {
+            self.set_message(strings::get("no_files"), MessageType::Info);
+            return;
+        }
+        self.symbol_picker = Some(SymbolPicker::new(entries));
+        self.start_prompt(strings::get("prompt_file"), PromptAction::FindFile);
+    }
+
+    /// The file finder's entries: the indexed files by their path under
+    /// the root.
+    fn file_entries(&self) -> Vec<PickerEntry> {
+        let Some((ref index, _)) = self.file_index else {
+            return Vec::new();
+        };
+        index
+            .files()
+            .map(|path| PickerEntry {
+                name: path
+                    .strip_prefix(index.root())
+                    .unwrap_or(path)
+                    .display()
+                    .to_string(),
+                detail: String::new(),
+                target: SymbolTarget::File(path.to_path_buf()),
+            })
+            .collect()
+    }
+
+    /// Bring the file index up to date, at most every `FILE_INDEX_POLL`
+    /// unless `now`; an open finder gets the new list, filtered as typed.
+    fn refresh_file_index(&mut self, now: bool) {
+        let Some((ref mut index, ref mut checked)) = self.file_index else {
+            return;
+        };
+        if !now && checked.elapsed() < FILE_INDEX_POLL {
+            return;
+        }
+        *checked = Instant::now();
+        if !index.refresh() {
+            return;
+        }
+        let entries = self.file_entries();
+        if let Some((ref prompt, PromptAction::FindFile)) = self.prompt
+            && let Some(ref mut picker) = self.symbol_picker
+        {
+            picker.entries = entries;
+            picker.filter(&prompt.input);
+        }
+    }
+
+    /// Pick a character to insert from the bundled name table, or type its
+    /// code point (`U+2192`).
+    fn open_char_picker(&mut self) {
+        let entries = unicode::NAMES
+            .iter()
+            .map(|&(c, name)| PickerEntry {
+                name: name.to_string(),
+                detail: format!("{}  U+{:04X}", c, c as u32),
+                target: SymbolTarget::Char(c),
+            })
+            .collect();
+        self.symbol_picker = Some(SymbolPicker::new(entries));
+        self.start_prompt(strings::get("prompt_character"), PromptAction::InsertChar);
+    }
+
+    /// Collect the two characters of a digraph and insert the character
+    /// they name. Any other key cancels.
+    fn handle_pending_digraph(&mut self, mut typed: String, ke: &KeyEvent) {
+        let ch = match ke.key {
+            Key::Char(ch) if !ke.ctrl && !ke.alt => ch,
+            _ => {
+                self.message = None;
+                return;
+            }
+        };
+        typed.push(ch);
+        let mut chars = typed.chars();
+        let (Some(first), Some(second)) = (chars.next(), chars.next()) else {
+            self.set_message(
+                &strings::format("prompt_digraph", &[&typed]),
+                MessageType::Info,
+            );
+            self.pending_digraph = Some(typed);
+            return;
+        };
+        match digraph::lookup(&self.config.digraphs, first, second) {
+            Some(c) => {
+                self.message = None;
+                self.insert_char_at_cursor(c);
+            }
+            None => self.set_message(
+                &strings::format("no_digraph", &[&typed]),
+                MessageType::Warning,
+            ),
+        }
+    }
+
+    /// Insert `c` at the cursor, replacing any selection.
+    fn insert_char_at_cursor(&mut self, c: char) {
+        if self.reject_read_only() {
+            return;
+        }
+        self.undo_stack.begin_compound(self.cursor_state());
+        if let Some((start, end)) = self.selection_range() {
+            self.delete_at(start, end - start);
+            self.selection = None;
+            let line = self.buffer.byte_to_line(start);
+            let col = start - self.buffer.line_start(line).unwrap_or(0);
+            self.cursor.set_position(line, col, &self.buffer);
+        }
+        let pos = self.cursor.byte_offset(&self.buffer);
+        self.insert_at(pos, c.encode_utf8(&mut [0u8; 4]));
+        self.cursor.move_right(&self.buffer);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    fn describe_char(&mut self) {
+        let pos = self.cursor.byte_offset(&self.buffer);
+        match self.buffer.char_at(pos) {
+            Some(c) => self.set_message(&unicode::describe(c), MessageType::Info),
+            None => self.set_message(strings::get("end_of_buffer"), MessageType::Info),
+        }
+    }
+
+    /// Jump to the picker's selected entry and close it.
+    fn pick_symbol(&mut self) {
+        let picker = match self.symbol_picker.take() {
+            Some(p) => p,
+            None => return,
+        };
+        match picker.matches.get(picker.list.selected) {
+            Some(&i) => match picker.entries[i].target {
+                SymbolTarget::Char(c) => self.insert_char_at_cursor(c),
+                SymbolTarget::File(ref path) => {
+                    if let Err(e) = self.open_located(path, None) {
+                        self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+                    }
+                }
+                _ => self.jump_to_symbol(&picker.entries[i]),
+            },
+            None => self.set_message(strings::get("no_matching_symbol"), MessageType::Warning),
+        }
+    }
+
+    fn jump_to_symbol(&mut self, entry: &PickerEntry) {
+        let here = self.current_jump();
+        let line = match entry.target {
+            SymbolTarget::Line(line) => Some(line),
+            SymbolTarget::Tag(ref path, ref address) => {
+                if let Err(e) = self.open_path(path) {
+                    self.set_message(&e, MessageType::Error);
+                    return;
+                }
+                self.buffer.make_contiguous();
+                address.resolve(&self.buffer.contents())
+            }
+            SymbolTarget::Char(_) | SymbolTarget::File(_) => return,
+        };
+        let line = match line {
+            Some(l) => l.min(self.buffer.line_count().saturating_sub(1)),
+            None => {
+                self.set_message(
+                    &strings::format("tag_not_found", &[&entry.name]),
+                    MessageType::Warning,
+                );
+                return;
+            }
+        };
+        if let Some(here) = here {
+            self.jumps.push(here);
+        }
+        let line_text = self.buffer.get_line(line).unwrap_or_default();
+        let col = line_text.find(entry.name.as_str()).unwrap_or(0);
+        self.cursor.set_position(line, col, &self.buffer);
+        self.selection = None;
+    }
+
+    /// Draw the picker's matches above the prompt, selection highlighted.
+    fn render_symbol_picker(&mut self, text_height: usize) {
+        let picker = match self.symbol_picker {
+            Some(ref p) => p,
+            None => return,
+        };
+        let rows = picker.matches.len().min(PICKER_MAX_ROWS).min(text_height);
+        let screen_width = self.screen.width();
+        let name_width = picker
+            .matches
+            .iter()
+            .map(|&i| picker.entries[i].name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(screen_width / 2);
+        let texts: Vec<String> = picker
+            .matches
+            .iter()
+            .map(|&i| {
+                let entry = &picker.entries[i];
+                format!(" {:<w$}  {}", entry.name, entry.detail, w = name_width)
+            })
+            .collect();
+        let mut list = picker.list;
+        list.follow(rows);
+        let area = Rect::new(text_height - rows, 0, screen_width, rows);
+        let style = Style::plain(Color::Default, ui::PANEL_BG);
+        ui::draw_list(&mut self.screen, area, &texts, &list, style);
+    }
+
+    // -----------------------------------------------------------------------
+    // Spell checking
+    // -----------------------------------------------------------------------
+
+    fn toggle_spell_check(&mut self) {
+        self.spell_enabled = !self.spell_enabled;
+        self.spell_errors.clear();
+        self.spell_version = None;
+        if self.spell_enabled {
+            self.set_message(strings::get("spell_check_on"), MessageType::Info);
+            self.refresh_spelling();
+        } else {
+            self.set_message(strings::get("spell_check_off"), MessageType::Info);
+        }
+    }
+
+    /// Re-check the active buffer if it changed since the last check. A
+    /// missing dictionary turns spell checking off with a warning.
+    fn refresh_spelling(&mut self) {
+        if !self.spell_enabled || self.spell_version == Some(self.buffer.version()) {
+            return;
+        }
+        if self.dictionary.is_none() {
+            match Dictionary::load(self.config.spell_dictionary.as_deref(), &self.word_lists()) {
+                Ok(dict) => self.dictionary = Some(dict),
+                Err(e) => {
+                    self.spell_enabled = false;
+                    self.set_message(&e, MessageType::Warning);
+                    return;
+                }
+            }
+        }
+        let dict = match self.dictionary {
+            Some(ref d) => d,
+            None => return,
+        };
+        let filetype = self.buffer.filetype();
+        let mut in_block = false;
+        self.spell_errors.clear();
+        self.buffer.make_contiguous();
+        for (line, text) in self.buffer.contents().lines().enumerate() {
+            let ranges = spell::prose_ranges(text, &filetype, &mut in_block);
+            for (start, end) in spell::misspellings(text, &ranges, dict) {
+                self.spell_errors.push((line, start, end));
+            }
+        }
+        self.spell_version = Some(self.buffer.version());
+    }
+
+    fn spelling_spans(&self, line: usize) -> Vec<(usize, usize)> {
+        let first = self.spell_errors.partition_point(|&(l, _, _)| l < line);
+        self.spell_errors[first..]
+            .iter()
+            .take_while(|&&(l, _, _)| l == line)
+            .map(|&(_, s, e)| (s, e))
+            .collect()
+    }
+
+    /// The word lists spell checking and completion read, the project's
+    /// from the working directory.
+    fn word_lists(&self) -> Vec<PathBuf> {
+        spell::word_lists(crate::config::config_dir().as_deref(), Path::new("."))
+    }
+
+    /// Add the word under the cursor, the misspelled one if the cursor is
+    /// on one, to the user's dictionary or to the project's word list.
+    fn add_word_to_list(&mut self, project: bool) {
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let col = self.cursor.col;
+        let word = match self
+            .spelling_spans(self.cursor.line)
+            .into_iter()
+            .find(|&(s, e)| s <= col && col <= e)
+        {
+            Some((start, end)) => Some(line_text[start..end].to_string()),
+            None => self.word_under_cursor(),
+        };
+        let Some(word) = word else {
+            self.set_message(strings::get("no_word_under_cursor"), MessageType::Warning);
+            return;
+        };
+        let path = if project {
+            Path::new(".").join(spell::PROJECT_WORDS)
+        } else {
+            match crate::config::config_dir() {
+                Some(dir) => dir.join(spell::USER_DICTIONARY),
+                None => {
+                    self.set_message(strings::get("no_config_dir"), MessageType::Error);
+                    return;
+                }
+            }
+        };
+        let name = shorten_path(&path);
+        match spell::add_word(&path, &word) {
+            Ok(added) => {
+                if let Some(ref mut dict) = self.dictionary {
+                    dict.insert(&word);
+                }
+                self.listed_words = None;
+                self.spell_version = None;
+                let key = if added { "word_added" } else { "word_listed" };
+                self.set_message(&strings::format(key, &[&word, &name]), MessageType::Info);
+            }
+            Err(e) => self.set_message(&strings::format("error", &[&e]), MessageType::Error),
+        }
+    }
+
+    /// Move to the next (or previous) misspelled word, wrapping around.
+    fn jump_to_misspelling(&mut self, forward: bool) {
+        if !self.spell_enabled {
+            self.toggle_spell_check();
+        }
+        self.refresh_spelling();
+        let here = (self.cursor.line, self.cursor.col);
+        let target = if forward {
+            self.spell_errors
+                .iter()
+                .find(|&&(l, s, _)| (l, s) > here)
+                .or(self.spell_errors.first())
+        } else {
+            self.spell_errors
+                .iter()
+                .rev()
+                .find(|&&(l, s, _)| (l, s) < here)
+                .or(self.spell_errors.last())
+        };
+        match target.copied() {
+            Some((line, start, _)) => {
+                self.cursor.set_position(line, start, &self.buffer);
+                self.selection = None;
+                let total = self.spell_errors.len();
+                self.set_message(
+                    &strings::format("misspelled_words", &[&total]),
+                    MessageType::Info,
+                );
+            }
+            None if self.spell_enabled => {
+                self.set_message(strings::get("no_misspelled_words"), MessageType::Info)
+            }
+            None => {}
+        }
+    }
+
+    /// Offer corrections for the misspelled word at the cursor in the
+    /// completion popup; accepting one replaces the word.
+    fn spell_suggest(&mut self) {
+        if !self.spell_enabled {
+            self.toggle_spell_check();
+        }
+        self.refresh_spelling();
+        let col = self.cursor.col;
+        let (start, end) = match self
+            .spelling_spans(self.cursor.line)
+            .into_iter()
+            .find(|&(s, e)| s <= col && col <= e)
+        {
+            Some(span) => span,
+            None => {
+                if self.spell_enabled {
+                    self.set_message(strings::get("no_misspelled_word"), MessageType::Info);
+                }
+                return;
+            }
+        };
+        let line_text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let suggestions = match self.dictionary {
+            Some(ref dict) => dict.suggest(&line_text[start..end]),
+            None => return,
+        };
+        if suggestions.is_empty() {
+            self.set_message(strings::get("no_suggestions"), MessageType::Info);
+            return;
+        }
+        self.cursor
+            .set_position(self.cursor.line, end, &self.buffer);
+        self.selection = None;
+        let line_start = self.buffer.line_start(self.cursor.line).unwrap_or(0);
+        let mut completion = Completion::new(line_start + start);
+        completion.set_items(
+            suggestions
+                .iter()
+                .map(|w| CompletionItem::word(w))
+                .collect(),
+        );
+        self.completion = Some(completion);
+    }
+
+    // -----------------------------------------------------------------------
+    // Jump list
+    // -----------------------------------------------------------------------
+
+    /// The cursor position as a jump list entry (None for unnamed buffers).
+    fn current_jump(&self) -> Option<Jump> {
+        self.buffer.file_path().map(|p| Jump {
+            path: p.to_path_buf(),
+            line: self.cursor.line,
+            col: self.cursor.col,
+        })
+    }
+
+    /// Remember the cursor position before a significant jump.
+    fn record_jump(&mut self) {
+        if let Some(here) = self.current_jump() {
+            self.jumps.push(here);
+        }
+    }
+
+    /// Step through the jump list, across all buffers or within the active
+    /// one only.
+    fn navigate_jumps(&mut self, forward: bool, in_buffer: bool) {
+        let here = match self.current_jump() {
+            Some(j) => j,
+            None => {
+                self.set_message(strings::get("jump_list_needs_name"), MessageType::Warning);
+                return;
+            }
+        };
+        let file = in_buffer.then(|| here.path.clone());
+        let target = if forward {
+            self.jumps.forward(file.as_deref())
+        } else {
+            self.jumps.back(here, file.as_deref())
+        };
+        let target = match target {
+            Some(t) => t,
+            None if forward => {
+                self.set_message(strings::get("no_later_position"), MessageType::Info);
+                return;
+            }
+            None => {
+                self.set_message(strings::get("no_earlier_position"), MessageType::Info);
+                return;
+            }
+        };
+        if let Err(e) = self.open_path(&target.path) {
+            self.set_message(&e, MessageType::Error);
+            return;
+        }
+        let line = target.line.min(self.buffer.line_count().saturating_sub(1));
+        self.cursor.set_position(line, target.col, &self.buffer);
+        self.selection = None;
+    }
+
+    // -----------------------------------------------------------------------
+    // Git
+    // -----------------------------------------------------------------------
+
+    /// Re-diff the active file against the index and update change markers.
+    /// Reload the change markers for the active file in the background.
+    fn refresh_git_gutter(&mut self) {
+        if let Some(id) = self.gutter_job.take() {
+            self.jobs.cancel(id);
+        }
+        let Some(path) = self.buffer.file_path().map(Path::to_path_buf) else {
+            self.git_gutter = None;
+            self.update_gutter_width();
+            return;
+        };
+        let id = self.jobs.spawn(move || {
+            let gutter = GitGutter::load(&path);
+            JobResult::GitGutter(path, gutter)
+        });
+        self.gutter_job = Some(id);
+    }
+
+    /// Width of the change-marker column (only shown for files in a git repo).
+    fn sign_column_width(&self) -> usize {
+        if self.git_gutter.is_some() || !self.active_diagnostics().is_empty() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn update_gutter_width(&mut self) {
+        self.gutter_width = compute_gutter_width(self.buffer.line_count())
+            + self.sign_column_width()
+            + self.blame_column_width();
+    }
+
+    /// Move the cursor to the start of the next (or previous) changed hunk.
+    fn jump_to_hunk(&mut self, forward: bool) {
+        if patch::is_patch(&self.buffer.filetype()) {
+            return self.jump_to_patch_hunk(forward);
+        }
+        let line = self.cursor.line;
+        let target = self.git_gutter.as_ref().and_then(|g| {
+            let hunk = if forward {
+                g.next_hunk(line)
+            } else {
+                g.prev_hunk(line)
+            };
+            hunk.map(|h| h.first_line())
+        });
+        match target {
+            Some(line) => {
+                self.record_jump();
+                self.goto_line_col(line, 0);
+            }
+            None => self.set_message(strings::get("no_changes"), MessageType::Info),
+        }
+    }
+
+    /// In a diff or patch buffer, move to the next or previous `@@` line.
+    fn jump_to_patch_hunk(&mut self, forward: bool) {
+        let line = self.cursor.line;
+        let is_hunk = |n: &usize| {
+            self.buffer
+                .get_line(*n)
+                .is_some_and(|l| patch::line_kind(&l) == LineKind::Hunk)
+        };
+        let target = if forward {
+            (line + 1..self.buffer.line_count()).find(is_hunk)
+        } else {
+            (0..line).rev().find(is_hunk)
+        };
+        match target {
+            Some(line) => {
+                self.record_jump();
+                self.goto_line_col(line, 0);
+            }
+            None => self.set_message(strings::get("no_more_hunks"), MessageType::Info),
+        }
+    }
+
+    /// Open the file and line the patch line under the cursor applies to.
+    /// The path is looked for as written and without its first component,
+    /// the `a/` or `b/` of git, from the working directory and then the
+    /// patch's own.
+    fn goto_patch_source(&mut self) {
+        let Some((name, line)) = patch::source_at(|n| self.buffer.get_line(n), self.cursor.line)
+        else {
+            self.set_message(strings::get("not_in_hunk"), MessageType::Warning);
+            return;
+        };
+        // The cursor's column, less the line's `+` or ` ` prefix
+        let text = self.buffer.get_line(self.cursor.line).unwrap_or_default();
+        let col = match patch::line_kind(&text) {
+            LineKind::Added | LineKind::Context if self.cursor.col > 0 => {
+                byte_col_to_display_col(&text[1..], self.cursor.col - 1)
+            }
+            _ => 0,
+        };
+        let written = PathBuf::from(&name);
+        let stripped: PathBuf = written.components().skip(1).collect();
+        let patch_dir = self.buffer.file_path().and_then(Path::parent);
+        let found = [Some(Path::new("")), patch_dir]
+            .into_iter()
+            .flatten()
+            .flat_map(|dir| [dir.join(&written), dir.join(&stripped)])
+            .find(|path| path.is_file());
+        let Some(path) = found else {
+            let msg = strings::format("no_file_under_cursor", &[&name]);
+            self.set_message(&msg, MessageType::Warning);
+            return;
+        };
+        if let Err(e) = self.open_located(&path, Some((line, col + 1))) {
+            self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+        }
+    }
+
+    /// Hunks are computed from the saved file, so they only line up with
+    /// the buffer while it is unmodified.
+    fn hunk_under_cursor(&mut self) -> Option<git::DiffHunk> {
+        if self.buffer.is_modified() {
+            self.set_message(strings::get("save_first"), MessageType::Warning);
+            return None;
+        }
+        let hunk = self
+            .git_gutter
+            .as_ref()
+            .and_then(|g| g.hunk_at(self.cursor.line).cloned());
+        if hunk.is_none() {
+            self.set_message(strings::get("no_change_under_cursor"), MessageType::Warning);
+        }
+        hunk
+    }
+
+    fn stage_hunk(&mut self) {
+        let hunk = match self.hunk_under_cursor() {
+            Some(h) => h,
+            None => return,
+        };
+        let result = match self.git_gutter {
+            Some(ref g) => g.stage(&hunk),
+            None => return,
+        };
+        match result {
+            Ok(()) => {
+                self.refresh_git_gutter();
+                self.set_message(strings::get("hunk_staged"), MessageType::Info);
+            }
+            Err(e) => self.set_message(&e, MessageType::Error),
+        }
+    }
+
+    /// Replace the hunk under the cursor with the index version, as a single
+    /// undoable edit.
+    fn revert_hunk(&mut self) {
+        let hunk = match self.hunk_under_cursor() {
+            Some(h) => h,
+            None => return,
+        };
+        let h = &hunk.hunk;
+        let mut old_text = hunk.old_text();
+
+        let (start, end) = if h.new_count == 0 {
+            // Pure deletion: re-insert below line `new_start` (1-based)
+            let pos = self
+                .buffer
+                .line_start(h.new_start)
+                .unwrap_or(self.buffer.len());
+            if pos == self.buffer.len() && pos > 0 && self.buffer.char_at(pos - 1) != Some('\n') {
+                // Appending after a last line without a newline
+                old_text.insert(0, '\n');
+                if old_text.ends_with('\n') {
+                    old_text.pop();
+                }
+            }
+            (pos, pos)
+        } else {
+            let first = h.new_start - 1;
+            let start = self.buffer.line_start(first).unwrap_or(0);
+            let end = self
+                .buffer
+                .line_start(first + h.new_count)
+                .unwrap_or(self.buffer.len());
+            (start, end)
+        };
+
+        self.selection = None;
+        self.undo_stack.begin_compound(self.cursor_state());
+        if end > start {
+            let before = self.cursor_state();
+            let deleted = self.buffer.delete(start, end - start);
+            self.undo_stack.record(
+                Operation::Delete {
+                    pos: start,
+                    text: deleted,
+                },
+                before,
+                GroupContext::Other,
+            );
+        }
+        if !old_text.is_empty() {
+            let before = self.cursor_state();
+            self.buffer.insert(start, &old_text);
+            self.undo_stack.record(
+                Operation::Insert {
+                    pos: start,
+                    text: old_text,
+                },
+                before,
+                GroupContext::Other,
+            );
+        }
+        let line = self.buffer.byte_to_line(start);
+        self.goto_line_col(line, 0);
+        self.undo_stack.end_compound(self.cursor_state());
+        self.update_gutter_width();
+        self.set_message(strings::get("hunk_reverted"), MessageType::Info);
+    }
+
+    /// Width of the blame column (0 when hidden or the buffer has no file).
+    fn blame_column_width(&self) -> usize {
+        if self.blame_visible && self.buffer.file_path().is_some() {
+            BLAME_WIDTH
+        } else {
+            0
+        }
+    }
+
+    fn toggle_blame(&mut self) {
+        self.blame_visible = !self.blame_visible;
+        if self.blame_visible {
+            if self.buffer.file_path().is_none() {
+                self.set_message(strings::get("blame_needs_file"), MessageType::Warning);
+            }
+            self.request_blame();
+        }
+        self.update_gutter_width();
+    }
+
+    /// Start `git blame` for the active file on a background thread. The
+    /// result is picked up by `finish_job`.
+    fn request_blame(&mut self) {
+        if !self.blame_visible {
+            return;
+        }
+        let path = match self.buffer.file_path() {
+            Some(p) => p.to_path_buf(),
+            None => return,
+        };
+        if let Some(id) = self.blame_job.take() {
+            self.jobs.cancel(id);
+        }
+        let id = self.jobs.spawn(move || {
+            let result = git::blame(&path);
+            JobResult::Blame(path, result)
+        });
+        self.blame_job = Some(id);
+    }
+
+    /// Store a finished job's result with the document it belongs to.
+    fn finish_job(&mut self, result: JobResult) {
+        match result {
+            JobResult::Blame(path, result) => {
+                let lines = match result {
+                    Ok(lines) => lines,
+                    Err(e) => {
+                        self.set_message(&e, MessageType::Warning);
+                        Vec::new()
+                    }
+                };
+                if self.buffer.file_path() == Some(path.as_path()) {
+                    self.blame = Some(lines);
+                } else if let Some(doc) = self.document_for(&path) {
+                    doc.blame = Some(lines);
+                }
+            }
+            JobResult::GitGutter(path, gutter) => {
+                if self.buffer.file_path() == Some(path.as_path()) {
+                    self.git_gutter = gutter;
+                    self.update_gutter_width();
+                } else if let Some(doc) = self.document_for(&path) {
+                    doc.git_gutter = gutter;
+                }
+            }
+        }
+    }
+
+    /// The inactive document showing `path`.
+    fn document_for(&mut self, path: &Path) -> Option<&mut Document> {
+        self.documents
+            .iter_mut()
+            .find(|d| d.buffer.file_path() == Some(path))
+    }
+
+    /// Copy the full commit hash that last touched the cursor line.
+    fn copy_blame_hash(&mut self) {
+        let info = match self.blame.as_ref().and_then(|b| b.get(self.cursor.line)) {
+            Some(info) if !info.is_uncommitted() => info,
+            Some(_) => {
+                self.set_message(strings::get("not_committed"), MessageType::Warning);
+                return;
+            }
+            None => {
+                self.set_message(strings::get("no_blame"), MessageType::Warning);
+                return;
+            }
+        };
+        let hash = info.hash.clone();
+        let msg = strings::format("copied_commit", &[&info.short_hash(), &info.author]);
+        terminal::set_clipboard_osc52(&hash);
+        self.clipboard = hash;
+        self.set_message(&msg, MessageType::Info);
+    }
+
+    // -----------------------------------------------------------------------
+    // External commands
+    // -----------------------------------------------------------------------
+
+    /// Run a shell command on the real terminal, then show its captured output
+    /// in the `[Output]` scratch buffer.
+    fn run_shell_command(&mut self, cmd: &str) {
+        let Some(ref mut terminal) = self.terminal else {
+            self.set_message(strings::get("needs_terminal"), MessageType::Error);
+            return;
+        };
+        terminal.suspend();
+        terminal.write(format!("$ {}\n", cmd).as_bytes());
+        terminal.flush();
+
+        let result = shell::run_interactive(cmd);
+
+        terminal.write(b"\n[Press Enter to return to zelux]");
+        terminal.flush();
+        let mut line = String::new();
+        let _ = std::io::stdin().read_line(&mut line);
+
+        terminal.resume();
+        let (w, h) = terminal.size();
+        self.screen.resize(w as usize, h as usize);
+        self.invalidate_frame();
+
+        match result {
+            Ok(output) => {
+                let text = format!("$ {}\n{}", cmd, output.text);
+                self.show_scratch(OUTPUT_BUFFER_NAME, &text);
+                let msg_type = if output.success() {
+                    MessageType::Info
+                } else {
+                    MessageType::Warning
+                };
+                self.set_message(
+                    &strings::format("command_finished", &[&output.status_label()]),
+                    msg_type,
+                );
+            }
+            Err(e) => self.set_message(&e, MessageType::Error),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // Build / quickfix
+    // -----------------------------------------------------------------------
+
+    /// Run the configured build command and collect its diagnostics into the
+    /// quickfix panel.
+    fn run_build(&mut self) {
+        let cmd = self.config.build_command.clone();
+        self.set_message(&strings::format("running", &[&cmd]), MessageType::Info);
+        self.render();
+
+        let output = match shell::run_captured(&cmd) {
+            Ok(output) => output,
+            Err(e) => {
+                self.set_message(&e, MessageType::Error);
+                return;
+            }
+        };
+
+        let entries = quickfix::parse_diagnostics(&output.text);
+        if entries.is_empty() {
+            self.quickfix = None;
+            self.quickfix_visible = false;
+            if output.success() {
+                self.set_message(strings::get("build_succeeded"), MessageType::Info);
+            } else {
+                let text = format!("$ {}\n{}", cmd, output.text);
+                self.show_scratch(OUTPUT_BUFFER_NAME, &text);
+                self.set_message(
+                    &strings::format("build_failed", &[&output.status_label()]),
+                    MessageType::Error,
+                );
+            }
+            return;
+        }
+
+        let count = entries.len();
+        self.quickfix = Some(QuickfixList::new(entries));
+        self.quickfix_visible = true;
+        let msg_type = if output.success() {
+            MessageType::Warning
+        } else {
+            MessageType::Error
+        };
+        self.set_message(
+            &strings::format("build_finished", &[&output.status_label(), &count]),
+            msg_type,
+        );
+    }
+
+    fn next_error(&mut self) {
+        let entry = self.quickfix.as_mut().and_then(|qf| qf.next().cloned());
+        self.jump_to_quickfix(entry);
+    }
+
+    fn prev_error(&mut self) {
+        let entry = self.quickfix.as_mut().and_then(|qf| qf.prev().cloned());
+        self.jump_to_quickfix(entry);
+    }
+
+    fn jump_to_quickfix(&mut self, entry: Option<QuickfixEntry>) {
+        let entry = match entry {
+            Some(e) => e,
+            None => {
+                self.set_message(strings::get("no_build_errors"), MessageType::Warning);
+                return;
+            }
+        };
+        self.record_jump();
+        if let Err(e) = self.open_path(&entry.path) {
+            self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+            return;
+        }
+        self.goto_line_col(entry.line - 1, entry.col - 1);
+        self.quickfix_visible = true;
+        let (current, total) = self.quickfix.as_ref().map_or((0, 0), |qf| {
+            (qf.current.map_or(0, |i| i + 1), qf.entries.len())
+        });
+        self.set_message(
+            &strings::format("quickfix_entry", &[&current, &total, &entry.message]),
+            MessageType::Info,
+        );
+    }
+
+    // -----------------------------------------------------------------------
+    // Diagnostics panel
+    // -----------------------------------------------------------------------
+
+    /// Every diagnostic from the language servers and the last build, by
+    /// file and position.
+    fn all_diagnostics(&self) -> Vec<Item> {
+        let mut items: Vec<Item> = self
+            .quickfix
+            .iter()
+            .flat_map(|qf| qf.entries.iter().map(Item::from_build))
+            .collect();
+        for (path, diags) in &self.diagnostics {
+            // Byte columns become character columns where the file is open
+            let buffer = if self.buffer.file_path() == Some(path.as_path()) {
+                Some(&self.buffer)
+            } else {
+                self.documents
+                    .iter()
+                    .map(|doc| &doc.buffer)
+                    .find(|b| b.file_path() == Some(path.as_path()))
+            };
+            for d in diags {
+                let (line, byte_col) = d.start;
+                let col = buffer
+                    .and_then(|b| b.get_line(line))
+                    .map_or(byte_col, |text| byte_col_to_display_col(&text, byte_col));
+                items.push(Item {
+                    path: path.clone(),
+                    line,
+                    col,
+                    severity: d.severity,
+                    message: d.message.lines().next().unwrap_or_default().to_string(),
+                });
+            }
+        }
+        diagnostics::sort(&mut items);
+        items
+    }
+
+    /// Open the panel with the keys, give it the keys, or close it.
+    fn toggle_diagnostics_panel(&mut self) {
+        match self.diagnostics_panel {
+            Some(ref mut panel) if !panel.focused => panel.focused = true,
+            Some(_) => self.diagnostics_panel = None,
+            None => {
+                self.diagnostics_panel = Some(DiagnosticsPanel {
+                    filter: diagnostics::Filter::default(),
+                    items: Vec::new(),
+                    total: 0,
+                    list: ListState::default(),
+                    focused: true,
+                });
+            }
+        }
+    }
+
+    /// Bring the panel's list up to date with the diagnostics and filter.
+    fn refresh_diagnostics_panel(&mut self) {
+        if self.diagnostics_panel.is_none() {
+            return;
+        }
+        let all = self.all_diagnostics();
+        let visible = self.quickfix_rows;
+        let Some(ref mut panel) = self.diagnostics_panel else {
+            return;
+        };
+        panel.total = all.len();
+        panel.items = all
+            .into_iter()
+            .filter(|i| panel.filter.matches(i))
+            .collect();
+        panel.list.selected = panel.list.selected.min(panel.items.len().saturating_sub(1));
+        panel.list.follow(visible);
+    }
+
+    /// Keys while the panel has focus: arrows and paging move, Enter jumps
+    /// and gives the keys back to the text, Tab steps the severity filter,
+    /// F toggles showing only the active file, Escape closes. Typing is
+    /// swallowed rather than editing the buffer.
+    fn handle_diagnostics_panel_key(&mut self, ke: &KeyEvent) -> bool {
+        let active_path = self.buffer.file_path().map(Path::to_path_buf);
+        let visible = self.quickfix_rows;
+        let Some(ref mut panel) = self.diagnostics_panel else {
+            return false;
+        };
+        let plain = !ke.ctrl && !ke.alt;
+        match ke.key {
+            Key::Tab if plain && !ke.shift => {
+                panel.filter.cycle_severity();
+                panel.list = ListState::default();
+            }
+            Key::Char('f' | 'F') if plain => {
+                panel.filter.path = match panel.filter.path {
+                    Some(_) => None,
+                    None => active_path,
+                };
+                panel.list = ListState::default();
+            }
+            _ => match panel.list.handle_key(ke, panel.items.len(), visible) {
+                KeyResult::Accept => {
+                    panel.focused = false;
+                    if let Some(item) = panel.items.get(panel.list.selected).cloned() {
+                        self.jump_to_diagnostic(&item);
+                    }
+                }
+                KeyResult::Cancel => self.diagnostics_panel = None,
+                KeyResult::Consumed | KeyResult::Choice(_) => {}
+                KeyResult::Ignored => return plain && matches!(ke.key, Key::Char(_)),
+            },
+        }
+        true
     }
 
-    fn insert_tab(&mut self) {
-        let before = self.cursor_state();
-        let pos = self.cursor.byte_offset(&self.buffer);
-        self.buffer.insert(pos, "    ");
-        self.undo_stack.record(
-            Operation::Insert {
-                pos,
-                text: "    ".to_string(),
-            },
-            before,
-            GroupContext::Other,
+    /// Jump to the diagnostic after (or before) the cursor, moving on to
+    /// the next file with any at the end of this one. Works with the panel
+    /// closed; an open panel selects it.
+    fn step_diagnostic(&mut self, forward: bool) {
+        let items = self.all_diagnostics();
+        let here = (self.cursor.line, self.cursor_char_col());
+        let Some(item) = diagnostics::step(&items, self.buffer.file_path(), here, forward).cloned()
+        else {
+            self.set_message(strings::get("no_diagnostics_anywhere"), MessageType::Info);
+            return;
+        };
+        if !self.jump_to_diagnostic(&item) {
+            return;
+        }
+        let index = items.iter().position(|i| *i == item).unwrap_or(0);
+        let msg = strings::format(
+            "diagnostic_entry",
+            &[&(index + 1), &items.len(), &item.message],
         );
-        // Move right 4 times for 4 spaces
-        for _ in 0..4 {
-            self.cursor.move_right(&self.buffer);
+        self.set_message(&msg, MessageType::Info);
+        let visible = self.quickfix_rows;
+        if let Some(ref mut panel) = self.diagnostics_panel
+            && let Some(i) = panel.items.iter().position(|i| *i == item)
+        {
+            panel.list.selected = i;
+            panel.list.follow(visible);
         }
     }
 
-    fn backspace(&mut self) {
-        let pos = self.cursor.byte_offset(&self.buffer);
-        if pos == 0 {
-            return;
+    /// Open the diagnostic's file and move to it. False when the file
+    /// could not be opened.
+    fn jump_to_diagnostic(&mut self, item: &Item) -> bool {
+        self.record_jump();
+        if let Err(e) = self.open_path(&item.path) {
+            self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+            return false;
         }
-        let before = self.cursor_state();
-        // Move cursor left first (handles UTF-8 boundaries)
-        self.cursor.move_left(&self.buffer);
-        let new_pos = self.cursor.byte_offset(&self.buffer);
-        let delete_len = pos - new_pos;
-        let deleted = self.buffer.slice(new_pos, pos);
-        self.buffer.delete(new_pos, delete_len);
-        self.undo_stack.record(
-            Operation::Delete {
-                pos: new_pos,
-                text: deleted,
-            },
-            before,
-            GroupContext::Deleting,
+        self.goto_line_col(item.line, item.col);
+        true
+    }
+
+    fn render_diagnostics_panel(&mut self, top: usize) {
+        let rows = self.panel_height();
+        let Some(ref panel) = self.diagnostics_panel else {
+            return;
+        };
+        let screen_width = self.screen.width();
+
+        // Header row: counts and filters
+        let severity = match panel.filter.severity {
+            Severity::Error => "diagnostics_filter_errors",
+            Severity::Warning => "diagnostics_filter_warnings",
+            _ => "diagnostics_filter_all",
+        };
+        let file = match panel.filter.path {
+            Some(ref path) => shorten_path(path),
+            None => strings::get("diagnostics_filter_any_file").to_string(),
+        };
+        let header = strings::format(
+            "diagnostics_panel_header",
+            &[
+                &panel.items.len(),
+                &panel.total,
+                &strings::get(severity),
+                &file,
+            ],
         );
+        for col in 0..screen_width {
+            self.screen
+                .put_char(top, col, ' ', Color::Ansi(0), Color::Ansi(6), false);
+        }
+        self.screen
+            .put_str(top, 0, &header, Color::Ansi(0), Color::Ansi(6), false);
+
+        let lines: Vec<(String, Color, bool)> = if panel.items.is_empty() {
+            vec![(
+                format!(" {}", strings::get("no_diagnostics_anywhere")),
+                Color::Default,
+                false,
+            )]
+        } else {
+            panel
+                .items
+                .iter()
+                .enumerate()
+                .skip(panel.list.scroll)
+                .take(rows - 1)
+                .map(|(i, item)| {
+                    let text = format!(
+                        " {} {}:{}:{}: {}",
+                        item.severity.label()[..1].to_uppercase(),
+                        shorten_path(&item.path),
+                        item.line + 1,
+                        item.col + 1,
+                        item.message
+                    );
+                    (
+                        text,
+                        severity_color(item.severity),
+                        i == panel.list.selected,
+                    )
+                })
+                .collect()
+        };
+        let focused = panel.focused;
+        for (i, (text, fg, selected)) in lines.iter().enumerate() {
+            let row = top + 1 + i;
+            let (fg, bg) = if *selected && focused {
+                (Color::Ansi(0), Color::Ansi(7))
+            } else {
+                (*fg, Color::Default)
+            };
+            for col in 0..screen_width {
+                self.screen.put_char(row, col, ' ', fg, bg, false);
+            }
+            self.screen.put_str(row, 0, text, fg, bg, *selected);
+        }
     }
 
-    fn delete_at_cursor(&mut self) {
-        let pos = self.cursor.byte_offset(&self.buffer);
-        if pos >= self.buffer.len() {
+    /// The rename preview: a header, then each line as a removed and an
+    /// added row, diff style. Rejected lines are drawn plain.
+    fn render_rename_panel(&mut self, top: usize) {
+        let visible = self.rename_visible();
+        let Some(ref panel) = self.rename_panel else {
             return;
+        };
+        let screen_width = self.screen.width();
+
+        let accepted = panel.hunks.iter().filter(|h| h.accepted).count();
+        let mut files: Vec<&Path> = panel.hunks.iter().map(|h| h.path.as_path()).collect();
+        files.dedup();
+        let header = strings::format(
+            "rename_panel_header",
+            &[
+                &panel.pattern,
+                &panel.replacement,
+                &accepted,
+                &panel.hunks.len(),
+                &files.len(),
+            ],
+        );
+        for col in 0..screen_width {
+            self.screen
+                .put_char(top, col, ' ', Color::Ansi(0), Color::Ansi(6), false);
         }
-        // Find the length of the character at cursor position
-        if let Some(ch) = self.buffer.char_at(pos) {
-            let before = self.cursor_state();
-            let char_len = ch.len_utf8();
-            let deleted = self.buffer.slice(pos, pos + char_len);
-            self.buffer.delete(pos, char_len);
-            self.undo_stack.record(
-                Operation::Delete { pos, text: deleted },
-                before,
-                GroupContext::Deleting,
+        self.screen
+            .put_str(top, 0, &header, Color::Ansi(0), Color::Ansi(6), false);
+
+        let mut rows: Vec<(String, Color, bool)> = Vec::new();
+        for (i, hunk) in panel
+            .hunks
+            .iter()
+            .enumerate()
+            .skip(panel.list.scroll)
+            .take(visible)
+        {
+            let place = format!(
+                " [{}] {}:{} ",
+                if hunk.accepted { 'x' } else { ' ' },
+                shorten_path(&hunk.path),
+                hunk.line + 1
             );
-            self.cursor.clamp(&self.buffer);
+            let indent = " ".repeat(unicode::display_width(&place));
+            let (removed, added) = if hunk.accepted {
+                (Color::Ansi(1), Color::Ansi(2))
+            } else {
+                (Color::Default, Color::Default)
+            };
+            let selected = i == panel.list.selected;
+            let old = hunk.old.trim_end_matches('\r').trim_start();
+            let new = hunk.new.trim_end_matches('\r').trim_start();
+            rows.push((format!("{}-{}", place, old), removed, selected));
+            rows.push((format!("{}+{}", indent, new), added, selected));
+        }
+        for (i, (text, fg, selected)) in rows.iter().enumerate() {
+            let row = top + 1 + i;
+            let (fg, bg) = if *selected {
+                (Color::Ansi(0), Color::Ansi(7))
+            } else {
+                (*fg, Color::Default)
+            };
+            for col in 0..screen_width {
+                self.screen.put_char(row, col, ' ', fg, bg, false);
+            }
+            self.screen.put_str(row, 0, text, fg, bg, false);
         }
     }
 
     // -----------------------------------------------------------------------
-    // Commands
+    // Side column (scrollbar / minimap)
     // -----------------------------------------------------------------------
 
-    fn save(&mut self) {
-        if self.buffer.file_path().is_none() {
-            self.set_message(
-                "No file name — use save_to (not yet implemented)",
-                MessageType::Error,
-            );
+    fn render_side_column(&mut self, h: usize) {
+        let side = self.config.side_column;
+        let left = self.text_right();
+        let width = self.screen.width() - left;
+        if side == SideColumn::Off || h == 0 || width == 0 {
             return;
         }
-        match self.buffer.save() {
-            Ok(()) => {
-                self.buffer.mark_saved();
-                self.undo_stack.mark_saved(self.cursor_state());
-                self.set_message("Saved!", MessageType::Info);
-            }
-            Err(e) => {
-                self.set_message(&format!("Save failed: {}", e), MessageType::Error);
+        let total = self.buffer.line_count();
+
+        // Mark color per row: diagnostics by severity, then search matches
+        let match_lines: Vec<usize> = self.search.as_ref().map_or(Vec::new(), |search| {
+            search
+                .matches
+                .iter()
+                .map(|&(start, _)| self.buffer.byte_to_line(start))
+                .collect()
+        });
+        let marks: Vec<Option<Color>> = (0..h)
+            .map(|row| {
+                let lines = scrollbar::row_lines(row, h, total);
+                let severity = self
+                    .active_diagnostics()
+                    .iter()
+                    .filter(|d| d.start.0 < lines.end && d.end.0 >= lines.start)
+                    .map(|d| d.severity)
+                    .min();
+                match severity {
+                    Some(Severity::Error) => Some(Color::Ansi(1)),
+                    Some(Severity::Warning) => Some(Color::Ansi(3)),
+                    Some(_) => Some(Color::Ansi(4)),
+                    None if match_lines.iter().any(|l| lines.contains(l)) => Some(Color::Ansi(6)),
+                    None => None,
+                }
+            })
+            .collect();
+
+        let (thumb_start, thumb_len) = scrollbar::thumb(self.scroll_row, h, total, h);
+        for (row, mark) in marks.into_iter().enumerate() {
+            match side {
+                SideColumn::Scrollbar => {
+                    let in_thumb = row >= thumb_start && row < thumb_start + thumb_len;
+                    let bg = if in_thumb {
+                        Color::Color256(245)
+                    } else {
+                        Color::Color256(237)
+                    };
+                    let (ch, fg) = mark.map_or((' ', Color::Default), |fg| ('━', fg));
+                    self.screen.put_char(row, left, ch, fg, bg, false);
+                }
+                SideColumn::Minimap => {
+                    // Rows summarizing the visible lines stand out like a thumb
+                    let lines = scrollbar::row_lines(row, h, total);
+                    let in_view = lines.start < self.scroll_row + h && lines.end > self.scroll_row;
+                    let bg = if in_view {
+                        Color::Color256(238)
+                    } else {
+                        Color::Default
+                    };
+                    let step = lines.len().div_ceil(scrollbar::MAX_SAMPLED_LINES).max(1);
+                    let sample: Vec<_> = lines
+                        .step_by(step)
+                        .filter_map(|l| self.buffer.get_line(l))
+                        .collect();
+                    let cells = scrollbar::minimap_cells(&sample);
+                    for (i, ch) in cells.into_iter().take(width - 1).enumerate() {
+                        self.screen
+                            .put_char(row, left + i, ch, Color::Color256(244), bg, false);
+                    }
+                    let (ch, fg) = mark.map_or((' ', Color::Default), |fg| ('▐', fg));
+                    self.screen
+                        .put_char(row, left + width - 1, ch, fg, bg, false);
+                }
+                SideColumn::Off => {}
             }
         }
     }
 
-    fn quit(&mut self) {
-        if self.buffer.is_modified() && !self.quit_confirm {
-            self.quit_confirm = true;
-            self.set_message(
-                "Unsaved changes! Press Ctrl+Q again to quit without saving.",
-                MessageType::Warning,
-            );
+    /// Scroll so the line under `row` of the side column is centered. The
+    /// cursor is pulled into view, or the next render would scroll back to it.
+    fn scroll_to_side_row(&mut self, row: u16) {
+        let h = self.text_area_height();
+        if h == 0 {
             return;
         }
-        self.running = false;
+        let total = self.buffer.line_count();
+        self.scroll_row = scrollbar::scroll_for_row(row as usize, h, total, h);
+        if self.cursor.line < self.scroll_row {
+            let distance = self.scroll_row - self.cursor.line;
+            self.cursor.move_page_down(&self.buffer, distance);
+        } else if self.cursor.line >= self.scroll_row + h {
+            let distance = self.cursor.line + 1 - (self.scroll_row + h);
+            self.cursor.move_page_up(&self.buffer, distance);
+        }
     }
 
     // -----------------------------------------------------------------------
@@ -947,14 +7486,32 @@ impl Editor {
     // -----------------------------------------------------------------------
 
     fn handle_mouse_click(&mut self, col: u16, row: u16) {
+        // A press inside the selection starts dragging it
+        if let Some(pos) = self.text_offset_at(col, row)
+            && self
+                .selection_range()
+                .is_some_and(|(s, e)| s <= pos && pos < e)
+        {
+            self.text_drag = Some(pos);
+            return;
+        }
         self.selection = None;
 
         let screen_row = row as usize;
         let screen_col = col as usize;
 
         let h = self.text_area_height();
+        if screen_row == h && self.panel_height() > 0 {
+            self.panel_drag = true; // Grabbed the quickfix panel header
+            return;
+        }
         if screen_row >= h {
-            return; // Click on status bar or message line
+            return; // Click on panel, status bar or message line
+        }
+        if screen_col >= self.text_right() {
+            self.side_column_drag = true;
+            self.scroll_to_side_row(row);
+            return;
         }
 
         let file_line = self.scroll_row + screen_row;
@@ -962,19 +7519,184 @@ impl Editor {
             return; // Click past end of file
         }
 
-        // Convert screen column to byte column
+        // A click on the gutter selects the line; dragging extends it
         if screen_col < self.gutter_width {
-            return; // Click on gutter
+            self.gutter_drag = Some(file_line);
+            self.select_lines(file_line, file_line);
+            return;
         }
         let display_col = screen_col - self.gutter_width + self.scroll_col;
 
         // Convert display column to byte column
-        let line_text = self.buffer.get_line(file_line).unwrap_or_default();
-        let byte_col = display_col_to_byte_col(&line_text, display_col);
+        let line_text = self.line_head(file_line, (display_col + 1) * CHAR_MAX_BYTES);
+        let cells = self.line_cells(file_line, &line_text);
+        let byte_col = screen_col_to_byte_col(&cells, line_text.len(), display_col);
 
+        if file_line.abs_diff(self.cursor.line) >= FAR_CLICK_LINES {
+            self.record_jump();
+        }
         self.cursor.set_position(file_line, byte_col, &self.buffer);
     }
 
+    /// Byte offset of the text cell at (`col`, `row`), or None off the text.
+    fn text_offset_at(&self, col: u16, row: u16) -> Option<usize> {
+        let (col, row) = (col as usize, row as usize);
+        let line = self.scroll_row + row;
+        let on_text = row < self.text_area_height()
+            && (self.gutter_width..self.text_right()).contains(&col)
+            && line < self.buffer.line_count();
+        on_text.then(|| self.line_offset_at(line, col))
+    }
+
+    /// Byte offset in `line` under screen column `col`; columns left of the
+    /// text give the line start.
+    fn line_offset_at(&self, line: usize, col: usize) -> usize {
+        let display_col = col.saturating_sub(self.gutter_width) + self.scroll_col;
+        let text = self.line_head(line, (display_col + 1) * CHAR_MAX_BYTES);
+        let cells = self.line_cells(line, &text);
+        self.buffer.line_start(line).unwrap_or(0)
+            + screen_col_to_byte_col(&cells, text.len(), display_col)
+    }
+
+    /// Finish dragging the selection: move it to `drop`, or copy it there
+    /// when `copy`, as one undo step. Dropping it onto itself is a click.
+    fn drop_selection(&mut self, drop: usize, copy: bool) {
+        let Some((start, end)) = self.selection_range() else {
+            return;
+        };
+        if (start..=end).contains(&drop) {
+            self.selection = None;
+            self.jump_to_byte(drop);
+            return;
+        }
+        if self.reject_read_only() {
+            return;
+        }
+        let text = self.buffer.slice(start, end);
+        let len = end - start;
+        self.undo_stack.begin_compound(self.cursor_state());
+        let at = if copy {
+            self.insert_at(drop, &text);
+            drop
+        } else if drop > end {
+            self.insert_at(drop, &text);
+            self.delete_at(start, len);
+            drop - len
+        } else {
+            self.delete_at(start, len);
+            self.insert_at(drop, &text);
+            drop
+        };
+        self.selection = Some(Selection {
+            anchor: at,
+            head: at + len,
+        });
+        self.jump_to_byte(at + len);
+        self.undo_stack.end_compound(self.cursor_state());
+    }
+
+    /// Move the quickfix panel header to screen row `row`.
+    fn resize_panel_to(&mut self, row: usize) {
+        let bottom = self.screen.height().saturating_sub(self.status_height);
+        self.set_panel_rows(bottom.saturating_sub(row + 1));
+    }
+
+    /// Give the quickfix panel `rows` entry rows, keeping at least one and
+    /// `MIN_TEXT_ROWS` of text.
+    fn set_panel_rows(&mut self, rows: usize) {
+        let bottom = self.screen.height().saturating_sub(self.status_height);
+        let most = bottom.saturating_sub(MIN_TEXT_ROWS + 1).max(1);
+        self.quickfix_rows = rows.clamp(1, most);
+    }
+
+    /// Select whole lines from `anchor` through `line`, with the cursor at
+    /// the end that follows `line`.
+    fn select_lines(&mut self, anchor: usize, line: usize) {
+        let last = self.buffer.line_count().saturating_sub(1);
+        let line = line.min(last);
+        let start_of = |l: usize| self.buffer.line_start(l).unwrap_or(self.buffer.len());
+        let (anchor, head) = if line >= anchor {
+            (start_of(anchor), start_of(line + 1))
+        } else {
+            (start_of(anchor + 1), start_of(line))
+        };
+        self.selection = Some(Selection { anchor, head });
+        self.jump_to_byte(head);
+    }
+
+    /// Open the context menu at a right-clicked cell. A click outside the
+    /// selection first moves the cursor there.
+    fn open_context_menu(&mut self, col: u16, row: u16) {
+        if row as usize >= self.text_area_height() || col as usize >= self.text_right() {
+            return;
+        }
+        let clicked = self.text_offset_at(col, row);
+        let in_selection = clicked.is_some_and(|pos| {
+            self.selection_range()
+                .is_some_and(|(start, end)| (start..=end).contains(&pos))
+        });
+        if clicked.is_some() && !in_selection {
+            self.handle_mouse_click(col, row);
+        }
+
+        let commands = vec![
+            (Command::Cut, "menu_cut"),
+            (Command::Copy, "menu_copy"),
+            (Command::Paste, "menu_paste"),
+            (Command::SelectAll, "menu_select_all"),
+            (Command::GotoDefinition, "menu_go_to_definition"),
+        ];
+        let items = commands
+            .iter()
+            .map(|&(cmd, label)| {
+                let key = self.keymap.keys_for(cmd).next();
+                (
+                    strings::get(label).to_string(),
+                    key.map(|k| k.to_string()).unwrap_or_default(),
+                )
+            })
+            .collect();
+        let menu = Menu::new(row as usize, col as usize, items);
+        self.context_menu = Some((menu, commands.into_iter().map(|(c, _)| c).collect()));
+    }
+
+    /// Keys while the context menu is open: Enter runs the selected item and
+    /// Escape closes it. Any other key closes it and acts as usual.
+    fn handle_context_menu_key(&mut self, ke: &KeyEvent) -> bool {
+        let (menu, commands) = match self.context_menu {
+            Some((ref mut menu, ref commands)) => (menu, commands),
+            None => return false,
+        };
+        match menu.handle_key(ke) {
+            KeyResult::Consumed => true,
+            KeyResult::Accept => {
+                let cmd = commands[menu.list.selected];
+                self.context_menu = None;
+                self.execute_command(cmd);
+                true
+            }
+            KeyResult::Cancel => {
+                self.context_menu = None;
+                true
+            }
+            _ => {
+                self.context_menu = None;
+                false
+            }
+        }
+    }
+
+    /// A click while the context menu is open runs the clicked item; a
+    /// click anywhere else just closes the menu.
+    fn handle_context_menu_click(&mut self, col: u16, row: u16) {
+        let Some((menu, commands)) = self.context_menu.take() else {
+            return;
+        };
+        if let Some(item) = menu.item_at(row as usize, col as usize) {
+            self.execute_command(commands[item]);
+        }
+    }
+
     // -----------------------------------------------------------------------
     // Paste
     // -----------------------------------------------------------------------
@@ -997,6 +7719,82 @@ impl Editor {
         }
     }
 
+    // -----------------------------------------------------------------------
+    // Plugins
+    // -----------------------------------------------------------------------
+
+    /// Send the active buffer's text to plugins that want changes, once per
+    /// buffer version.
+    fn notify_plugins_of_change(&mut self) {
+        let current = (
+            self.buffer.file_path().map(Path::to_path_buf),
+            self.buffer.version(),
+        );
+        if self.plugin_change.as_ref() == Some(&current) || !self.plugins.wants("change") {
+            return;
+        }
+        let (path, version) = current.clone();
+        self.plugins.send(&plugin::Event::Change {
+            path,
+            version,
+            text: self.buffer.text(),
+        });
+        self.plugin_change = Some(current);
+    }
+
+    fn poll_plugins(&mut self) {
+        for (name, request) in self.plugins.poll() {
+            match request {
+                Err(e) => self.set_message(
+                    &strings::format("plugin_error", &[&name, &e]),
+                    MessageType::Warning,
+                ),
+                Ok(Request::Subscribe(_)) => {}
+                Ok(Request::Insert(text)) => {
+                    if !self.reject_read_only() {
+                        self.delete_selection();
+                        self.handle_paste(&text);
+                    }
+                }
+                Ok(Request::Status(text)) => self.set_message(&text, MessageType::Info),
+                Ok(Request::Open(path)) => {
+                    let here = self.current_jump();
+                    match self.open_path(&path) {
+                        Ok(()) => {
+                            if let Some(here) = here {
+                                self.jumps.push(here);
+                            }
+                        }
+                        Err(e) => self.set_message(&e, MessageType::Error),
+                    }
+                }
+                Ok(Request::Run(command)) => match self.command_named(&command) {
+                    Some(cmd) => self.execute_command(cmd),
+                    None => self.set_message(
+                        &strings::format("unknown_command", &[&command]),
+                        MessageType::Error,
+                    ),
+                },
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+    // User scripts
+    // -----------------------------------------------------------------------
+
+    fn run_script(&mut self, i: usize) {
+        let Some((name, script)) = self.scripts.get(i).cloned() else {
+            return;
+        };
+        if let Err(e) = script.run(self) {
+            self.set_message(
+                &strings::format("script_error", &[&name, &e]),
+                MessageType::Error,
+            );
+        }
+    }
+
     // -----------------------------------------------------------------------
     // Messages
     // -----------------------------------------------------------------------
@@ -1004,6 +7802,90 @@ impl Editor {
     fn set_message(&mut self, msg: &str, msg_type: MessageType) {
         self.message = Some(msg.to_string());
         self.message_type = msg_type;
+        self.message_log
+            .push((SystemTime::now(), msg_type, msg.to_string()));
+        if self.message_log.len() > MAX_LOGGED_MESSAGES {
+            self.message_log.remove(0);
+        }
+    }
+
+    /// Open a popup counting the lines, words, characters and bytes of the
+    /// buffer and of the selection.
+    fn show_document_stats(&mut self) {
+        const ROWS: [&str; 5] = [
+            "stats_lines",
+            "stats_words",
+            "stats_chars",
+            "stats_graphemes",
+            "stats_bytes",
+        ];
+        let cells = |c: Counts, lines: usize| [lines, c.words, c.chars, c.graphemes, c.bytes];
+        let total = self.text_stats.total(&self.buffer);
+        // A final newline ends the last line rather than starting one
+        let last = self.buffer.line_count() - 1;
+        let trailing = self.buffer.get_line(last).is_some_and(|l| l.is_empty());
+        let mut columns = vec![(
+            strings::get("stats_document"),
+            cells(total, last + 1 - usize::from(trailing)),
+        )];
+        if let Some((start, end)) = self.selection_range().filter(|(s, e)| s < e) {
+            let (first, last) = self.selected_lines();
+            let counts = self.text_stats.range(&self.buffer, start, end);
+            columns.push((
+                strings::get("stats_selection"),
+                cells(counts, last - first + 1),
+            ));
+        }
+        let label_width = ROWS
+            .iter()
+            .map(|key| unicode::display_width(strings::get(key)))
+            .max()
+            .unwrap_or(0);
+        let mut header = " ".repeat(label_width);
+        for (name, _) in &columns {
+            header.push_str(&format!("{:>12}", name));
+        }
+        let mut lines = vec![(header, Color::Ansi(6))];
+        for (i, key) in ROWS.iter().enumerate() {
+            let label = strings::get(key);
+            let mut text = format!(
+                "{}{}",
+                label,
+                " ".repeat(label_width - unicode::display_width(label))
+            );
+            for (_, values) in &columns {
+                text.push_str(&format!("{:>12}", values[i]));
+            }
+            lines.push((text, Color::Default));
+        }
+        self.popup = Some(TextPanel::new(lines));
+    }
+
+    /// List every message so far, newest last, in the messages buffer.
+    fn show_message_log(&mut self) {
+        if self.message_log.is_empty() {
+            self.set_message(strings::get("no_messages"), MessageType::Info);
+            return;
+        }
+        let now = SystemTime::now();
+        let mut text = String::new();
+        for (time, msg_type, msg) in &self.message_log {
+            let age = now.duration_since(*time).map_or(0, |d| d.as_secs());
+            let level = match msg_type {
+                MessageType::Info => "info",
+                MessageType::Warning => "warning",
+                MessageType::Error => "error",
+            };
+            text.push_str(&format!(
+                "{:>4} ago  {:<7}  {}\n",
+                git::format_age(age as i64),
+                level,
+                msg
+            ));
+        }
+        self.show_scratch(MESSAGES_BUFFER_NAME, &text);
+        self.buffer.set_read_only(true);
+        self.cursor.move_to_end(&self.buffer);
     }
 
     // -----------------------------------------------------------------------
@@ -1011,77 +7893,225 @@ impl Editor {
     // -----------------------------------------------------------------------
 
     fn open_find_prompt(&mut self, action: PromptAction) {
+        // Incremental search moves the cursor; remember where it started
+        self.record_jump();
         // Pre-fill with selection text (if short, single-line) or last search pattern
         let prefill = self.prefill_search_text();
-        let label = match action {
-            PromptAction::Replace | PromptAction::ReplaceWith(_) => "Find: ",
-            _ => "Find: ",
-        };
-        self.prompt = Some(Prompt {
-            label: label.to_string(),
-            input: prefill.clone(),
-            cursor_pos: prefill.len(),
-            action,
-        });
-        self.message = None;
+        let label = strings::get("prompt_find");
+        self.open_prompt(Prompt::new(label).with_input(&prefill), action);
         // Trigger incremental search if prefill is non-empty
         if !prefill.is_empty() {
             self.update_search(&prefill);
         }
     }
 
-    fn prefill_search_text(&self) -> String {
-        // Use selection if it's short and single-line
-        if let Some((start, end)) = self.selection_range()
-            && start != end
-        {
-            let text = self.buffer.slice(start, end);
-            if !text.contains('\n') && text.len() <= 100 {
-                return text;
-            }
-        }
-        // Fall back to last search pattern
-        if let Some(ref search) = self.search {
-            return search.pattern.clone();
+    fn prefill_search_text(&self) -> String {
+        // Use selection if it's short and single-line
+        if let Some((start, end)) = self.selection_range()
+            && start != end
+        {
+            let text = self.buffer.slice(start, end);
+            if !text.contains('\n') && text.len() <= 100 {
+                return text;
+            }
+        }
+        // Fall back to last search pattern
+        if let Some(ref search) = self.search {
+            return search.pattern.clone();
+        }
+        String::new()
+    }
+
+    /// Start searching for `pattern`. A buffer larger than SEARCH_CHUNK
+    /// is searched a chunk per frame (see `continue_search`); the cursor
+    /// jumps to the first match at or after it once one is found.
+    fn update_search(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            self.search = None;
+            return;
+        }
+        let mut search = SearchState::new(pattern, Vec::new(), self.buffer.version());
+        search.scanned = Some(0);
+        self.search = Some(search);
+        self.continue_search();
+    }
+
+    /// Search the next chunk for an unfinished search, showing progress
+    /// and reporting the matches when a search of several chunks ends.
+    fn continue_search(&mut self) {
+        let Some(ref mut search) = self.search else {
+            return;
+        };
+        let Some(from) = search.scanned else {
+            return;
+        };
+        self.buffer.make_contiguous();
+        let text = self.buffer.contents();
+        // Chunks end at a line end, and patterns are single lines
+        let mut end = (from + SEARCH_CHUNK).min(text.len());
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        let end = text[end..].find('\n').map_or(text.len(), |i| end + i + 1);
+        let found = find_all_matches(&text[from..end], &search.pattern);
+        search
+            .matches
+            .extend(found.into_iter().map(|(s, e)| (from + s, from + e)));
+        search.scanned = (end < text.len()).then_some(end);
+        if search.matches.len() > MAX_MATCHES {
+            search.matches.truncate(MAX_MATCHES);
+            search.capped = true;
+            search.scanned = None;
+        }
+        let len = text.len();
+        drop(text);
+
+        // Find nearest match at or after cursor, wrapping once the whole
+        // buffer is searched
+        let mut jump = None;
+        if search.current.is_none() {
+            let cursor_byte = self.cursor.byte_offset(&self.buffer);
+            let at = search.matches.iter().position(|&(s, _)| s >= cursor_byte);
+            let at = at.or((search.scanned.is_none() && !search.matches.is_empty()).then_some(0));
+            if let Some(idx) = at {
+                search.current = Some(idx);
+                jump = Some(search.matches[idx].0);
+            }
+        }
+        let message = match search.scanned {
+            Some(at) => Some((
+                strings::format("searching", &[&(at * 100 / len.max(1))]),
+                MessageType::Info,
+            )),
+            None if search.capped => Some((
+                strings::format("matches_capped", &[&MAX_MATCHES]),
+                MessageType::Warning,
+            )),
+            None if from > 0 => Some(match search.current {
+                Some(i) => (
+                    strings::format("match_of", &[&(i + 1), &search.total()]),
+                    MessageType::Info,
+                ),
+                None => (strings::get("no_matches").to_string(), MessageType::Warning),
+            }),
+            None => None,
+        };
+        if let Some(pos) = jump {
+            self.jump_to_byte(pos);
+        }
+        if let Some((message, kind)) = message {
+            self.set_message(&message, kind);
+        }
+    }
+
+    fn search_in_progress(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.scanned.is_some())
+    }
+
+    /// Stop an unfinished search, keeping the matches found so far. True
+    /// when there was one to stop.
+    fn cancel_search(&mut self) -> bool {
+        let Some(search) = self.search.as_mut().filter(|s| s.scanned.is_some()) else {
+            return false;
+        };
+        search.scanned = None;
+        let message = strings::format("search_cancelled", &[&search.total()]);
+        self.set_message(&message, MessageType::Warning);
+        true
+    }
+
+    /// Run an unfinished search to its end, for commands that need every
+    /// match.
+    fn finish_search(&mut self) {
+        while self.search_in_progress() {
+            self.continue_search();
         }
-        String::new()
     }
 
-    fn update_search(&mut self, pattern: &str) {
-        if pattern.is_empty() {
-            self.search = None;
+    /// Bring the matches up to date with the buffer's edits since they
+    /// were found, searching only the edited lines again.
+    fn sync_search(&mut self) {
+        let version = self.buffer.version();
+        let Some(ref mut search) = self.search else {
+            return;
+        };
+        if search.version == version {
             return;
         }
-        let text = self.buffer.text();
-        let matches = find_all_matches(&text, pattern);
-        let cursor_byte = self.cursor.byte_offset(&self.buffer);
-
-        // Find nearest match at or after cursor
-        let current = if matches.is_empty() {
-            None
-        } else {
-            let idx = matches
+        let Some(edits) = self.buffer.edits_since(search.version) else {
+            // Too many edits to follow: search the whole buffer again
+            let pattern = search.pattern.clone();
+            let current = search.current;
+            self.buffer.make_contiguous();
+            let matches = find_all_matches(&self.buffer.contents(), &pattern);
+            let mut search = SearchState::new(&pattern, matches, version);
+            let cursor = self.cursor.byte_offset(&self.buffer);
+            let nearest = search.matches.iter().position(|&(s, _)| s >= cursor);
+            search.current = current.and(nearest.or((!search.matches.is_empty()).then_some(0)));
+            self.search = Some(search);
+            return;
+        };
+        let current = search
+            .current
+            .and_then(|i| search.matches.get(i))
+            .map(|&(start, _)| {
+                edits
+                    .iter()
+                    .fold(start, |at, edit| shift_offset(at, edit, false))
+            });
+        let scanned = search.scanned.map(|at| {
+            edits
                 .iter()
-                .position(|(start, _)| *start >= cursor_byte)
-                .unwrap_or(0);
-            // Jump cursor to this match
-            self.jump_to_byte(matches[idx].0);
-            Some(idx)
+                .fold(at, |at, edit| shift_offset(at, edit, false))
+        });
+        let Some((lo, hi)) = shift_matches(&mut search.matches, edits) else {
+            search.version = version;
+            return;
         };
-
-        self.search = Some(SearchState {
-            pattern: pattern.to_string(),
-            matches,
-            current,
+        // Patterns are single lines: search the edited lines whole
+        let first = self.buffer.byte_to_line(lo);
+        let last = self.buffer.byte_to_line(hi);
+        let start = self.buffer.line_start(first).unwrap_or(0);
+        let end = self.buffer.line_end(last).unwrap_or(self.buffer.len());
+        let found = find_all_matches(&self.buffer.slice(start, end), &search.pattern);
+        let from = search.matches.partition_point(|&(_, e)| e <= start);
+        let to = search.matches.partition_point(|&(s, _)| s < end);
+        search.matches.splice(
+            from..to,
+            found.into_iter().map(|(s, e)| (start + s, start + e)),
+        );
+        // An unfinished search goes on from the start of the line it
+        // stopped in; what lies after is found then
+        if let Some(at) = scanned {
+            let at = self
+                .buffer
+                .line_start(self.buffer.byte_to_line(at))
+                .unwrap_or(0);
+            search.matches.retain(|&(s, _)| s < at);
+            search.scanned = Some(at);
+        }
+        if search.matches.len() > MAX_MATCHES {
+            search.matches.truncate(MAX_MATCHES);
+            search.capped = true;
+            search.scanned = None;
+        }
+        search.current = current.and_then(|at| {
+            let i = search.matches.partition_point(|&(s, _)| s < at);
+            match search.matches.len() {
+                0 => None,
+                len => Some(i % len),
+            }
         });
+        search.version = version;
     }
 
     fn search_next(&mut self) {
-        let (total, next_idx, byte_pos) = {
+        self.sync_search();
+        let (count, next_idx, byte_pos) = {
             let search = match self.search {
                 Some(ref s) if !s.matches.is_empty() => s,
                 _ => {
-                    self.set_message("No search pattern", MessageType::Warning);
+                    self.set_message(strings::get("no_search_pattern"), MessageType::Warning);
                     return;
                 }
             };
@@ -1090,22 +8120,24 @@ impl Editor {
                 Some(i) => (i + 1) % total,
                 None => 0,
             };
-            (total, next, search.matches[next].0)
+            (search.total(), next, search.matches[next].0)
         };
+        self.record_jump();
         self.jump_to_byte(byte_pos);
         self.search.as_mut().unwrap().current = Some(next_idx);
         self.set_message(
-            &format!("Match {} of {}", next_idx + 1, total),
+            &strings::format("match_of", &[&(next_idx + 1), &count]),
             MessageType::Info,
         );
     }
 
     fn search_prev(&mut self) {
-        let (total, prev_idx, byte_pos) = {
+        self.sync_search();
+        let (count, prev_idx, byte_pos) = {
             let search = match self.search {
                 Some(ref s) if !s.matches.is_empty() => s,
                 _ => {
-                    self.set_message("No search pattern", MessageType::Warning);
+                    self.set_message(strings::get("no_search_pattern"), MessageType::Warning);
                     return;
                 }
             };
@@ -1120,12 +8152,13 @@ impl Editor {
                 }
                 None => total - 1,
             };
-            (total, prev, search.matches[prev].0)
+            (search.total(), prev, search.matches[prev].0)
         };
+        self.record_jump();
         self.jump_to_byte(byte_pos);
         self.search.as_mut().unwrap().current = Some(prev_idx);
         self.set_message(
-            &format!("Match {} of {}", prev_idx + 1, total),
+            &strings::format("match_of", &[&(prev_idx + 1), &count]),
             MessageType::Info,
         );
     }
@@ -1137,15 +8170,80 @@ impl Editor {
         self.cursor.set_position(line, col, &self.buffer);
     }
 
-    fn execute_replace_all(&mut self, find_pattern: &str, replacement: &str) {
-        let text = self.buffer.text();
-        let matches = find_all_matches(&text, find_pattern);
-        if matches.is_empty() {
-            self.set_message("No matches to replace", MessageType::Warning);
+    /// Step to the first match of `pattern` at or after byte `from` and ask
+    /// whether to replace it; `replaced` counts the replacements so far.
+    fn confirm_replace(
+        &mut self,
+        pattern: String,
+        replacement: String,
+        replaced: usize,
+        from: usize,
+    ) {
+        self.buffer.make_contiguous();
+        let matches = find_all_matches(&self.buffer.contents(), &pattern);
+        let Some(idx) = matches.iter().position(|&(start, _)| start >= from) else {
+            self.finish_replace(replaced);
+            return;
+        };
+        self.jump_to_byte(matches[idx].0);
+        // Every match, uncapped, so `a` replaces them all
+        self.search = Some(SearchState {
+            pattern: pattern.clone(),
+            matches,
+            current: Some(idx),
+            version: self.buffer.version(),
+            scanned: None,
+            capped: false,
+        });
+        let choices = [
+            ('y', strings::get("yes")),
+            ('n', strings::get("no")),
+            ('a', strings::get("all")),
+            ('q', strings::get("quit")),
+        ];
+        let prompt = Prompt::choice(strings::get("prompt_replace_match"), &choices);
+        let action = PromptAction::ConfirmReplace {
+            pattern,
+            replacement,
+            replaced,
+        };
+        self.open_prompt(prompt, action);
+    }
+
+    /// `y` replaces the current match, `n` skips it, `a` replaces it and
+    /// every later one, and `q` stops.
+    fn answer_replace(
+        &mut self,
+        pattern: String,
+        replacement: String,
+        replaced: usize,
+        choice: char,
+    ) {
+        let Some((matches, current)) = self
+            .search
+            .as_ref()
+            .and_then(|s| Some((s.matches.clone(), s.current?)))
+        else {
             return;
+        };
+        let (start, end) = matches[current];
+        match choice {
+            'y' => {
+                self.replace_matches(&[(start, end)], &replacement);
+                let from = start + replacement.len();
+                self.confirm_replace(pattern, replacement, replaced + 1, from);
+            }
+            'n' => self.confirm_replace(pattern, replacement, replaced, start + 1),
+            'a' => {
+                let rest = &matches[current..];
+                self.replace_matches(rest, &replacement);
+                self.finish_replace(replaced + rest.len());
+            }
+            _ => self.finish_replace(replaced),
         }
-        let count = matches.len();
+    }
 
+    fn replace_matches(&mut self, matches: &[(usize, usize)], replacement: &str) {
         // Replace in reverse order to preserve byte offsets
         for &(start, end) in matches.iter().rev() {
             let before = self.cursor_state();
@@ -1170,180 +8268,470 @@ impl Editor {
                 GroupContext::Other,
             );
         }
+    }
 
+    fn finish_replace(&mut self, replaced: usize) {
         // Clear search state after replace
         self.search = None;
         self.cursor.clamp(&self.buffer);
         self.set_message(
-            &format!("Replaced {} occurrences", count),
+            &strings::format("replaced", &[&replaced]),
             MessageType::Info,
         );
     }
 
-    /// Check if a byte position falls within any search match.
-    /// Returns Some(is_current_match) if in a match, None otherwise.
-    fn match_at_byte(&self, byte_pos: usize) -> Option<bool> {
-        let search = self.search.as_ref()?;
-        for (i, &(start, end)) in search.matches.iter().enumerate() {
-            if byte_pos >= start && byte_pos < end {
-                let is_current = search.current == Some(i);
-                return Some(is_current);
+    /// Replace every match of `pattern` in the open buffers at indexes
+    /// `chosen`, each as one undo step, and sum up the counts per buffer.
+    /// Read-only buffers are left alone.
+    fn replace_in_buffers(&mut self, pattern: &str, replacement: &str, chosen: &[usize]) {
+        let mut counts = Vec::new();
+        let mut replaced = Vec::new();
+        for &idx in chosen {
+            // Work on another buffer by swapping it in for a moment
+            let other = idx != self.active;
+            if other {
+                self.swap_document(idx);
+            }
+            self.buffer.make_contiguous();
+            let matches = find_all_matches(&self.buffer.contents(), pattern);
+            if !matches.is_empty() && !self.buffer.is_read_only() {
+                self.undo_stack.begin_compound(self.cursor_state());
+                self.replace_matches(&matches, replacement);
+                self.cursor.clamp(&self.buffer);
+                self.selection = None;
+                self.undo_stack.end_compound(self.cursor_state());
+                counts.push(format!("{} {}", display_name(&self.buffer), matches.len()));
+                replaced.push((self.documents[idx].id, self.buffer.version(), matches.len()));
+            }
+            if other {
+                self.swap_document(idx);
+            }
+        }
+        self.search = None;
+        if replaced.is_empty() {
+            self.set_message(strings::get("no_matches_to_replace"), MessageType::Warning);
+            return;
+        }
+        let total: usize = replaced.iter().map(|&(_, _, n)| n).sum();
+        let msg = strings::format(
+            "replaced_in_buffers",
+            &[&total, &replaced.len(), &counts.join(", ")],
+        );
+        self.set_message(&msg, MessageType::Info);
+        self.buffers_replaced = replaced
+            .iter()
+            .map(|&(id, version, _)| (id, version))
+            .collect();
+    }
+
+    /// Undo the last replace-in-buffers in each buffer it changed, except
+    /// those edited or closed since.
+    fn undo_replace_in_buffers(&mut self) {
+        if self.buffers_replaced.is_empty() {
+            self.set_message(strings::get("no_buffers_replace"), MessageType::Warning);
+            return;
+        }
+        let (mut undone, mut changed) = (0, 0);
+        for (id, version) in std::mem::take(&mut self.buffers_replaced) {
+            let Some(idx) = self.documents.iter().position(|d| d.id == id) else {
+                changed += 1;
+                continue;
+            };
+            let other = idx != self.active;
+            if other {
+                self.swap_document(idx);
+            }
+            let cs = self.cursor_state();
+            if self.buffer.version() == version
+                && let Some(restored) = self.undo_stack.undo(&mut self.buffer, cs)
+            {
+                self.restore_cursor(restored);
+                self.sync_saved_state();
+                undone += 1;
+            } else {
+                changed += 1;
             }
-            if start > byte_pos {
-                break; // matches are sorted, no need to continue
+            if other {
+                self.swap_document(idx);
             }
         }
-        None
+        self.search = None;
+        let msg = match changed {
+            0 => strings::format("buffers_replace_undone", &[&undone]),
+            _ => strings::format("buffers_replace_undone_partly", &[&undone, &changed]),
+        };
+        self.set_message(&msg, MessageType::Info);
     }
 
     // -----------------------------------------------------------------------
-    // Prompt
+    // Project rename
     // -----------------------------------------------------------------------
 
-    fn start_prompt(&mut self, label: &str, action: PromptAction) {
-        self.prompt = Some(Prompt {
-            label: label.to_string(),
-            input: String::new(),
-            cursor_pos: 0,
-            action,
+    /// Find every line under `root` that renaming `pattern` would change
+    /// and open the preview. Open buffers are searched as they are, unsaved
+    /// edits included, wherever their file is; other files as they are on
+    /// disk.
+    fn preview_rename(&mut self, root: &Path, pattern: String, replacement: String) {
+        let mut hunks = Vec::new();
+        let mut open = Vec::new();
+        for idx in 0..self.documents.len() {
+            let buf = if idx == self.active {
+                &self.buffer
+            } else {
+                &self.documents[idx].buffer
+            };
+            if let Some(path) = buf.file_path() {
+                hunks.extend(rename::hunks(path, &buf.text(), &pattern, &replacement));
+                open.push(path.to_path_buf());
+            }
+        }
+        for path in rename::project_files(root) {
+            let path = path
+                .strip_prefix(".")
+                .map_or(path.clone(), Path::to_path_buf);
+            if open.iter().any(|p| same_file(p, &path)) {
+                continue;
+            }
+            if let Some(text) = rename::read_text(&path) {
+                hunks.extend(rename::hunks(&path, &text, &pattern, &replacement));
+            }
+        }
+        if hunks.is_empty() {
+            let msg = strings::format("rename_nothing", &[&pattern]);
+            self.set_message(&msg, MessageType::Warning);
+            return;
+        }
+        hunks.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        self.diagnostics_panel = None;
+        self.rename_panel = Some(RenamePanel {
+            pattern,
+            replacement,
+            hunks,
+            list: ListState::default(),
         });
-        self.message = None;
     }
 
-    fn handle_prompt_key(&mut self, ke: KeyEvent) {
-        let mut input_changed = false;
+    /// How many lines the rename preview shows at once.
+    fn rename_visible(&self) -> usize {
+        (self.quickfix_rows / 2).max(1)
+    }
 
-        match (&ke.key, ke.ctrl, ke.alt) {
-            (Key::Enter, false, false) => {
-                // Take the prompt out to avoid borrow issues
-                let prompt = self.prompt.take().unwrap();
-                if prompt.input.is_empty() {
-                    // Empty input — cancel
-                    return;
-                }
-                self.execute_prompt(prompt);
-                return;
-            }
-            (Key::Escape, _, _) => {
-                // Keep search state so F3 still works
-                self.prompt = None;
-                return;
+    /// Keys while the preview is open: arrows and paging move, Space
+    /// toggles the line (Y accepts and N rejects it, moving on), A accepts
+    /// or rejects them all, Enter applies the accepted ones and Escape
+    /// drops the rename.
+    fn handle_rename_panel_key(&mut self, ke: &KeyEvent) {
+        let visible = self.rename_visible();
+        let Some(ref mut panel) = self.rename_panel else {
+            return;
+        };
+        let plain = !ke.ctrl && !ke.alt;
+        let len = panel.hunks.len();
+        let selected = panel.list.selected;
+        match ke.key {
+            Key::Char(' ') if plain => {
+                let hunk = &mut panel.hunks[selected];
+                hunk.accepted = !hunk.accepted;
             }
-            (Key::Backspace, false, false) => {
-                if let Some(ref mut prompt) = self.prompt
-                    && prompt.cursor_pos > 0
-                {
-                    let before = &prompt.input[..prompt.cursor_pos];
-                    if let Some(ch) = before.chars().next_back() {
-                        let len = ch.len_utf8();
-                        let new_pos = prompt.cursor_pos - len;
-                        prompt.input.drain(new_pos..prompt.cursor_pos);
-                        prompt.cursor_pos = new_pos;
-                        input_changed = true;
-                    }
+            Key::Char(c @ ('y' | 'Y' | 'n' | 'N')) if plain => {
+                panel.hunks[selected].accepted = c.eq_ignore_ascii_case(&'y');
+                if selected + 1 < len {
+                    panel.list.select_next(len, visible);
                 }
             }
-            (Key::Delete, false, false) => {
-                if let Some(ref mut prompt) = self.prompt
-                    && prompt.cursor_pos < prompt.input.len()
-                {
-                    let after = &prompt.input[prompt.cursor_pos..];
-                    if let Some(ch) = after.chars().next() {
-                        let len = ch.len_utf8();
-                        prompt
-                            .input
-                            .drain(prompt.cursor_pos..prompt.cursor_pos + len);
-                        input_changed = true;
-                    }
+            Key::Char('a' | 'A') if plain => {
+                let all = panel.hunks.iter().all(|h| h.accepted);
+                for hunk in &mut panel.hunks {
+                    hunk.accepted = !all;
                 }
             }
-            (Key::Left, false, false) => {
-                if let Some(ref mut prompt) = self.prompt
-                    && prompt.cursor_pos > 0
-                {
-                    let before = &prompt.input[..prompt.cursor_pos];
-                    if let Some(ch) = before.chars().next_back() {
-                        prompt.cursor_pos -= ch.len_utf8();
+            _ => match panel.list.handle_key(ke, len, visible) {
+                KeyResult::Accept => {
+                    if let Some(panel) = self.rename_panel.take() {
+                        self.apply_rename(&panel);
                     }
                 }
+                KeyResult::Cancel => self.rename_panel = None,
+                _ => {}
+            },
+        }
+    }
+
+    /// Apply the preview's accepted lines: files open in a buffer are edited there,
+    /// as one undoable change each; the rest are edited on disk after their
+    /// original is copied to a backup. Lines changed since the preview are
+    /// skipped.
+    fn apply_rename(&mut self, panel: &RenamePanel) {
+        let accepted: Vec<&Hunk> = panel.hunks.iter().filter(|h| h.accepted).collect();
+        if accepted.is_empty() {
+            self.set_message(strings::get("rename_none_accepted"), MessageType::Warning);
+            return;
+        }
+        let (mut applied, mut stale, mut files, mut on_disk) = (0, 0, 0, 0);
+        let mut errors = Vec::new();
+        for group in accepted.chunk_by(|a, b| a.path == b.path) {
+            let path = &group[0].path;
+            let open = (0..self.documents.len()).find(|&i| {
+                let buf = if i == self.active {
+                    &self.buffer
+                } else {
+                    &self.documents[i].buffer
+                };
+                buf.file_path().is_some_and(|p| same_file(p, path))
+            });
+            let (done, skipped) = match open {
+                Some(idx) => {
+                    self.rename_in_document(idx, group, &panel.pattern, &panel.replacement)
+                }
+                None => match rename::apply_to_file(path, group) {
+                    Ok(counts) => {
+                        on_disk += usize::from(counts.0 > 0);
+                        counts
+                    }
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                },
+            };
+            applied += done;
+            stale += skipped;
+            files += usize::from(done > 0);
+        }
+        self.search = None;
+        if let Some(e) = errors.first() {
+            self.set_message(&strings::format("error", &[e]), MessageType::Error);
+            return;
+        }
+        let mut msg = strings::format("renamed", &[&applied, &files, &on_disk]);
+        if stale > 0 {
+            msg = strings::format("renamed_stale", &[&msg, &stale]);
+        }
+        self.set_message(&msg, MessageType::Info);
+    }
+
+    /// Rename `pattern` on the lines of `hunks` in the open document `idx`,
+    /// as one undoable change. Returns the hunks applied and the ones
+    /// skipped, as stale or because the buffer is read-only.
+    fn rename_in_document(
+        &mut self,
+        idx: usize,
+        hunks: &[&Hunk],
+        pattern: &str,
+        replacement: &str,
+    ) -> (usize, usize) {
+        let other = idx != self.active;
+        if other {
+            self.swap_document(idx);
+        }
+        let mut matches = Vec::new();
+        let mut applied = 0;
+        for hunk in hunks {
+            let current = self.buffer.get_line(hunk.line);
+            if self.buffer.is_read_only() || current.as_deref() != Some(hunk.old.as_str()) {
+                continue;
             }
-            (Key::Right, false, false) => {
-                if let Some(ref mut prompt) = self.prompt
-                    && prompt.cursor_pos < prompt.input.len()
-                {
-                    let after = &prompt.input[prompt.cursor_pos..];
-                    if let Some(ch) = after.chars().next() {
-                        prompt.cursor_pos += ch.len_utf8();
+            let start = self.buffer.line_start(hunk.line).unwrap_or(0);
+            matches.extend(
+                rename::occurrences(&hunk.old, pattern)
+                    .into_iter()
+                    .map(|(s, e)| (start + s, start + e)),
+            );
+            applied += 1;
+        }
+        if !matches.is_empty() {
+            matches.sort_unstable();
+            self.undo_stack.begin_compound(self.cursor_state());
+            self.replace_matches(&matches, replacement);
+            self.cursor.clamp(&self.buffer);
+            self.selection = None;
+            self.undo_stack.end_compound(self.cursor_state());
+        }
+        if other {
+            self.swap_document(idx);
+        }
+        (applied, hunks.len() - applied)
+    }
+
+    // -----------------------------------------------------------------------
+    // Prompt
+    // -----------------------------------------------------------------------
+
+    fn start_prompt(&mut self, label: &str, action: PromptAction) {
+        self.open_prompt(Prompt::new(label), action);
+    }
+
+    fn open_prompt(&mut self, prompt: Prompt, action: PromptAction) {
+        self.prompt = Some((prompt, action));
+        self.message = None;
+    }
+
+    fn handle_prompt_key(&mut self, ke: KeyEvent) {
+        // The first Escape stops an unfinished search, the next one closes
+        // the prompt
+        if ke.key == Key::Escape && self.cancel_search() {
+            return;
+        }
+        let (result, input_changed) = match self.prompt {
+            Some((ref mut prompt, _)) => {
+                let before = prompt.input.clone();
+                let result = prompt.handle_key(&ke);
+                (result, prompt.input != before)
+            }
+            None => return,
+        };
+
+        match result {
+            KeyResult::Accept => {
+                // Take the prompt out to avoid borrow issues
+                let (prompt, action) = self.prompt.take().unwrap();
+                if matches!(action, PromptAction::JumpToSymbol | PromptAction::FindFile) {
+                    self.pick_symbol();
+                    return;
+                }
+                if matches!(action, PromptAction::InsertChar) {
+                    match unicode::parse_codepoint(&prompt.input) {
+                        Some(c) => {
+                            self.symbol_picker = None;
+                            self.insert_char_at_cursor(c);
+                        }
+                        None => self.pick_symbol(),
                     }
+                    return;
                 }
-            }
-            (Key::Home, false, false) => {
-                if let Some(ref mut prompt) = self.prompt {
-                    prompt.cursor_pos = 0;
+                if prompt.answer().is_empty() {
+                    // Empty input — cancel
+                    return;
                 }
-            }
-            (Key::End, false, false) => {
-                if let Some(ref mut prompt) = self.prompt {
-                    prompt.cursor_pos = prompt.input.len();
+                if let Err(e) = prompt.check() {
+                    self.prompt = Some((prompt, action));
+                    self.set_message(&e, MessageType::Error);
+                    return;
                 }
+                self.execute_prompt(prompt, action);
+                return;
             }
-            (Key::Char(ch), false, false) => {
-                if let Some(ref mut prompt) = self.prompt {
-                    let mut buf = [0u8; 4];
-                    let s = ch.encode_utf8(&mut buf);
-                    prompt.input.insert_str(prompt.cursor_pos, s);
-                    prompt.cursor_pos += s.len();
-                    input_changed = true;
+            KeyResult::Choice(c) => {
+                let (_, action) = self.prompt.take().unwrap();
+                self.answer_prompt(action, c);
+                return;
+            }
+            KeyResult::Cancel => {
+                // Keep search state so F3 still works
+                let (_, action) = self.prompt.take().unwrap();
+                self.symbol_picker = None;
+                if let PromptAction::ConfirmReplace { replaced, .. } = action {
+                    self.finish_replace(replaced);
                 }
+                return;
             }
-            _ => {}
+            KeyResult::Ignored => match (&ke.key, ke.ctrl, ke.alt) {
+                (Key::Tab, false, false) => self.complete_prompt_path(),
+                (Key::Up | Key::Down | Key::PageUp | Key::PageDown, false, false) => {
+                    if let Some(ref mut picker) = self.symbol_picker {
+                        let len = picker.matches.len();
+                        picker.list.handle_key(&ke, len, PICKER_MAX_ROWS);
+                    }
+                }
+                _ => {}
+            },
+            KeyResult::Consumed => {}
         }
 
         // Incremental search: update matches when input changes in Find/Replace prompts
-        if input_changed && let Some(ref prompt) = self.prompt {
-            let is_search_prompt =
-                matches!(prompt.action, PromptAction::Find | PromptAction::Replace);
+        if input_changed && let Some((ref prompt, ref action)) = self.prompt {
+            let is_search_prompt = matches!(action, PromptAction::Find | PromptAction::Replace);
             if is_search_prompt {
                 let pattern = prompt.input.clone();
                 self.update_search(&pattern);
+            } else if let Some(ref mut picker) = self.symbol_picker {
+                picker.filter(&prompt.input);
             }
         }
     }
 
-    fn execute_prompt(&mut self, prompt: Prompt) {
-        match prompt.action {
+    /// Tab in a file name prompt: complete the name being typed, listing
+    /// the choices when it is ambiguous.
+    fn complete_prompt_path(&mut self) {
+        let Some((ref mut prompt, ref action)) = self.prompt else {
+            return;
+        };
+        if !matches!(
+            action,
+            PromptAction::OpenFile
+                | PromptAction::InsertFile
+                | PromptAction::WriteSelection
+                | PromptAction::Export
+        ) {
+            return;
+        }
+        let (dir, _) = complete::split_path(&prompt.input);
+        let entries = complete::dir_entries(dir);
+        let (completed, matches) = complete::complete_path(&prompt.input, &entries);
+        prompt.cursor_pos = completed.len();
+        prompt.input = completed;
+        match matches.len() {
+            0 => self.set_message(strings::get("no_match"), MessageType::Warning),
+            1 => self.message = None,
+            _ => self.set_message(&matches.join("  "), MessageType::Info),
+        }
+    }
+
+    /// Ask before writing over an existing file at `target`; true when the
+    /// question was asked.
+    fn confirm_overwrite(&mut self, target: &str, action: PromptAction) -> bool {
+        if !Path::new(target).exists() {
+            return false;
+        }
+        let label = strings::format("prompt_overwrite", &[&shorten_path(Path::new(target))]);
+        let choices = [('y', strings::get("yes")), ('n', strings::get("no"))];
+        let overwrite = PromptAction::Overwrite(Box::new(action), target.to_string());
+        self.open_prompt(Prompt::choice(&label, &choices), overwrite);
+        true
+    }
+
+    fn execute_prompt(&mut self, prompt: Prompt, action: PromptAction) {
+        let answer = prompt.answer().to_string();
+        match action {
             PromptAction::OpenFile => {
-                let path = Path::new(&prompt.input);
-                match Buffer::from_file(path) {
-                    Ok(buf) => {
-                        let display_name = shorten_path(path);
-                        self.buffer = buf;
-                        self.cursor = Cursor::new();
-                        self.scroll_row = 0;
-                        self.scroll_col = 0;
-                        self.selection = None;
-                        self.undo_stack.clear();
-                        self.gutter_width = compute_gutter_width(self.buffer.line_count());
-                        self.set_message(&format!("Opened: {}", display_name), MessageType::Info);
-                    }
-                    Err(e) => {
-                        // Keep prompt open so user can fix the path
-                        self.prompt = Some(prompt);
-                        self.set_message(&format!("Error: {}", e), MessageType::Error);
-                    }
+                let (path, position) = locate(&answer);
+                if let Err(e) = self.open_located(path, position) {
+                    // Keep prompt open so user can fix the path
+                    self.prompt = Some((prompt, PromptAction::OpenFile));
+                    self.set_message(&strings::format("error", &[&e]), MessageType::Error);
+                }
+            }
+            PromptAction::InsertFile => self.insert_file(Path::new(&answer)),
+            PromptAction::WriteSelection => {
+                if !self.confirm_overwrite(&answer, PromptAction::WriteSelection) {
+                    self.write_selection(Path::new(&answer));
+                }
+            }
+            PromptAction::Export => {
+                if !self.confirm_overwrite(&answer, PromptAction::Export) {
+                    self.export(&answer);
                 }
             }
+            PromptAction::RunCommand => {
+                self.run_shell_command(&answer);
+            }
+            PromptAction::ExCommand => self.execute_ex_command(answer.trim()),
+            PromptAction::SetFiletype => self.set_filetype(&answer),
             PromptAction::Find => {
-                // Finalize search, jump to current match
-                self.update_search(&prompt.input.clone());
-                if let Some(ref search) = self.search {
+                // Finalize search, jump to current match. An unfinished
+                // search goes on, and reports when it ends.
+                if self.search.as_ref().is_none_or(|s| s.pattern != answer) {
+                    self.update_search(&answer);
+                }
+                if let Some(ref search) = self.search
+                    && search.scanned.is_none()
+                {
                     if search.matches.is_empty() {
-                        self.set_message("No matches", MessageType::Warning);
+                        self.set_message(strings::get("no_matches"), MessageType::Warning);
                     } else {
-                        let total = search.matches.len();
                         let current = search.current.map_or(0, |i| i + 1);
                         self.set_message(
-                            &format!("Match {} of {}", current, total),
+                            &strings::format("match_of", &[&current, &search.total()]),
                             MessageType::Info,
                         );
                     }
@@ -1351,43 +8739,314 @@ impl Editor {
             }
             PromptAction::Replace => {
                 // Save pattern, open "Replace with:" prompt
-                let pattern = prompt.input;
+                let pattern = answer;
                 self.update_search(&pattern);
+                self.finish_search();
                 if let Some(ref search) = self.search
                     && search.matches.is_empty()
                 {
-                    self.set_message("No matches", MessageType::Warning);
+                    self.set_message(strings::get("no_matches"), MessageType::Warning);
                     return;
                 }
-                self.start_prompt("Replace with: ", PromptAction::ReplaceWith(pattern));
+                self.start_prompt(
+                    strings::get("prompt_replace_with"),
+                    PromptAction::ReplaceWith(pattern),
+                );
             }
-            PromptAction::ReplaceWith(ref find_pattern) => {
-                let replacement = prompt.input;
-                let find_pattern = find_pattern.clone();
-                self.execute_replace_all(&find_pattern, &replacement);
+            PromptAction::ReplaceWith(pattern) => {
+                let from = self.cursor.byte_offset(&self.buffer);
+                self.confirm_replace(pattern, answer, 0, from);
             }
+            PromptAction::ReplaceInBuffers if !answer.is_empty() => self.start_prompt(
+                strings::get("prompt_replace_with"),
+                PromptAction::ReplaceInBuffersWith(answer),
+            ),
+            PromptAction::ReplaceInBuffers => {}
+            PromptAction::ReplaceInBuffersWith(pattern) => {
+                let prompt = Prompt::new(strings::get("prompt_in_buffers")).with_default("all");
+                self.open_prompt(prompt, PromptAction::ReplaceInBuffersIn(pattern, answer));
+            }
+            PromptAction::ReplaceInBuffersIn(pattern, replacement) => {
+                let names: Vec<String> = (0..self.documents.len())
+                    .map(|i| {
+                        if i == self.active {
+                            display_name(&self.buffer)
+                        } else {
+                            display_name(&self.documents[i].buffer)
+                        }
+                    })
+                    .collect();
+                let chosen = chosen_buffers(&answer, &names);
+                self.replace_in_buffers(&pattern, &replacement, &chosen);
+            }
+            PromptAction::RenameInProject if !answer.is_empty() => {
+                let prompt = Prompt::new(strings::get("prompt_rename_to")).with_input(&answer);
+                self.open_prompt(prompt, PromptAction::RenameInProjectTo(answer));
+            }
+            PromptAction::RenameInProject => {}
+            PromptAction::RenameInProjectTo(pattern) => {
+                self.preview_rename(Path::new("."), pattern, answer);
+            }
+            // Handled on Enter, with the picker; choice prompts have no
+            // input to execute
+            PromptAction::JumpToSymbol
+            | PromptAction::FindFile
+            | PromptAction::InsertChar
+            | PromptAction::Overwrite(..)
+            | PromptAction::ConfirmTrash(_)
+            | PromptAction::OpenLocked(..)
+            | PromptAction::ConfirmReplace { .. } => {}
+        }
+    }
+
+    /// Act on the key that answered a choice prompt.
+    fn answer_prompt(&mut self, action: PromptAction, choice: char) {
+        match action {
+            PromptAction::Overwrite(action, target) => {
+                if choice != 'y' {
+                    return;
+                }
+                match *action {
+                    PromptAction::WriteSelection => self.write_selection(Path::new(&target)),
+                    PromptAction::Export => self.export(&target),
+                    _ => {}
+                }
+            }
+            PromptAction::ConfirmReplace {
+                pattern,
+                replacement,
+                replaced,
+            } => self.answer_replace(pattern, replacement, replaced, choice),
+            PromptAction::ConfirmTrash(path) if choice == 'y' => self.trash_file(&path),
+            PromptAction::OpenLocked(path, owner) => self.answer_locked(&path, &owner, choice),
+            _ => {}
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Helper functions
+// ---------------------------------------------------------------------------
+
+/// The range of widths `option` may be set to.
+fn width_range(option: BufferOption) -> RangeInclusive<usize> {
+    match option {
+        BufferOption::TabWidth => TAB_WIDTHS,
+        _ => TEXT_WIDTHS,
+    }
+}
+
+/// How the status bar and options panel name a line ending.
+fn line_ending_name(crlf: bool) -> &'static str {
+    if crlf { "CRLF" } else { "LF" }
+}
+
+/// The name a buffer goes by in the status bar: its shortened path, its
+/// scratch name or `[No Name]`.
+fn display_name(buffer: &Buffer) -> String {
+    if let Some(path) = buffer.file_path() {
+        shorten_path(path)
+    } else if let Some(name) = buffer.name() {
+        name.to_string()
+    } else {
+        "[No Name]".to_string()
+    }
+}
+
+/// The indexes of the buffers named by `input`, given their `names`: 1-based
+/// numbers as in the status bar, or parts of names, separated by spaces.
+/// Nothing, `all` or `*` names them all.
+fn chosen_buffers(input: &str, names: &[String]) -> Vec<usize> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if words.is_empty() || words == ["all"] || words == ["*"] {
+        return (0..names.len()).collect();
+    }
+    (0..names.len())
+        .filter(|&i| {
+            words.iter().any(|w| match w.parse::<usize>() {
+                Ok(n) => n == i + 1,
+                Err(_) => names[i].contains(w),
+            })
+        })
+        .collect()
+}
+
+fn tag_entry(tag: tags::Tag) -> PickerEntry {
+    let detail = format!(
+        "{}  {}",
+        tag.kind.as_deref().unwrap_or(""),
+        shorten_path(&tag.path)
+    );
+    PickerEntry {
+        name: tag.name,
+        detail,
+        target: SymbolTarget::Tag(tag.path, tag.address),
+    }
+}
+
+/// Where byte offset `at` moves to after `edit`. `end` marks the end of a
+/// range: text inserted right at it goes after the start of a range but
+/// not after its end. An offset inside the replaced text goes to the start
+/// of the new text, or to its end for `end`.
+fn shift_offset(at: usize, edit: &Edit, end: bool) -> usize {
+    if at < edit.pos || (end && at == edit.pos) {
+        at
+    } else if at >= edit.pos + edit.deleted {
+        at - edit.deleted + edit.inserted
+    } else if end {
+        edit.pos + edit.inserted
+    } else {
+        edit.pos
+    }
+}
+
+/// Move search `matches` past `edits`, dropping the matches an edit
+/// changed (text inserted inside one, or any of it deleted). Returns the
+/// byte range that covers the edited text, where matches may have been
+/// lost or made, or None when nothing was edited.
+fn shift_matches(matches: &mut Vec<(usize, usize)>, edits: &[Edit]) -> Option<(usize, usize)> {
+    let mut edited: Option<(usize, usize)> = None;
+    for edit in edits {
+        matches.retain(|&(s, e)| !(s < edit.pos + edit.deleted && edit.pos < e));
+        for m in matches.iter_mut() {
+            *m = (
+                shift_offset(m.0, edit, false),
+                shift_offset(m.1, edit, true),
+            );
         }
+        let (lo, hi) = (edit.pos, edit.pos + edit.inserted);
+        edited = Some(match edited {
+            None => (lo, hi),
+            Some((a, b)) => (
+                shift_offset(a, edit, false).min(lo),
+                shift_offset(b, edit, true).max(hi),
+            ),
+        });
+    }
+    edited
+}
+
+/// Validation for file name prompts: the name must not be a directory.
+fn not_directory(answer: &str) -> Result<(), String> {
+    if Path::new(answer).is_dir() {
+        Err(strings::format("is_directory", &[&answer]))
+    } else {
+        Ok(())
+    }
+}
+
+/// How long to wait for input `idle` after the last of it came: the
+/// `read_timeout`, growing towards `idle_timeout` (both in milliseconds)
+/// as the editor stays idle when that is longer.
+fn idle_wait(read_timeout: u64, idle_timeout: u64, idle: Duration) -> Duration {
+    let base = Duration::from_millis(read_timeout);
+    let max = Duration::from_millis(idle_timeout);
+    if max <= base {
+        return base;
+    }
+    (idle / 2).clamp(base, max)
+}
+
+/// The filetype named by a `set ft=rs`, `set filetype=rs` or `setf rs`
+/// command line.
+fn ex_filetype(input: &str) -> Option<&str> {
+    if let Some(name) = input.strip_prefix("setf ") {
+        return Some(name.trim());
+    }
+    let setting = input.strip_prefix("set ")?.trim_start();
+    setting
+        .strip_prefix("ft=")
+        .or_else(|| setting.strip_prefix("filetype="))
+        .map(str::trim)
+}
+
+/// True when two paths name the same file, even if one is relative.
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (std::fs::canonicalize(a), std::fs::canonicalize(b)),
+            (Ok(x), Ok(y)) if x == y
+        )
+}
+
+/// Bytes of leading whitespace removed by outdenting `line` one `unit`: a
+/// tab, or up to the unit's width in spaces.
+fn outdent_len(line: &str, unit: &str, tab_width: usize) -> usize {
+    if line.starts_with('\t') {
+        return 1;
+    }
+    let width = if unit == "\t" { tab_width } else { unit.len() };
+    line.bytes().take(width).take_while(|&b| b == b' ').count()
+}
+
+/// Bytes Backspace deletes when the text `before` the cursor on its line is
+/// all spaces and indentation is `unit` spaces: back to the previous
+/// indent level. None elsewhere, and when indenting with tabs.
+fn indent_backspace_len(before: &str, unit: &str) -> Option<usize> {
+    if unit == "\t" || before.is_empty() || !before.bytes().all(|b| b == b' ') {
+        return None;
+    }
+    match before.len() % unit.len() {
+        0 => Some(unit.len()),
+        n => Some(n),
     }
 }
 
-// ---------------------------------------------------------------------------
-// Helper functions
-// ---------------------------------------------------------------------------
+/// How to join `line` with `next`: bytes of trailing whitespace to drop
+/// from `line`, bytes of leading whitespace to drop from `next`, and the
+/// separator put between them (none when either side is blank).
+fn join_span(line: &str, next: &str) -> (usize, usize, &'static str) {
+    let left = line.trim_end();
+    let right = next.trim_start();
+    let separator = if left.is_empty() || right.is_empty() {
+        ""
+    } else {
+        " "
+    };
+    (line.len() - left.len(), next.len() - right.len(), separator)
+}
 
-/// Case-insensitive substring search. Returns non-overlapping byte ranges.
+/// Case-insensitive substring search. Returns non-overlapping byte ranges,
+/// always on character boundaries of `text`.
 fn find_all_matches(text: &str, pattern: &str) -> Vec<(usize, usize)> {
     if pattern.is_empty() {
         return Vec::new();
     }
+    // Lowercasing can change a character's length (`İ`, `ẞ`). When it does,
+    // `origin` maps each byte of the lowered text to the start of the
+    // character it came from.
     let text_lower = text.to_lowercase();
+    let lower_len = |c: char| c.to_lowercase().map(char::len_utf8).sum::<usize>();
+    let shifted = !text.is_ascii()
+        && text
+            .chars()
+            .any(|c| !c.is_ascii() && lower_len(c) != c.len_utf8());
+    let origin = shifted.then(|| {
+        text.char_indices()
+            .flat_map(|(i, c)| std::iter::repeat_n(i, lower_len(c)))
+            .collect::<Vec<usize>>()
+    });
+    let to_text = |start: usize, end: usize| match origin {
+        None => (start, end),
+        Some(ref origin) => {
+            let last = origin[end - 1];
+            let last_len = text[last..].chars().next().map_or(0, char::len_utf8);
+            (origin[start], last + last_len)
+        }
+    };
+
     let pattern_lower = pattern.to_lowercase();
     let pat_len = pattern_lower.len();
-    let mut results = Vec::new();
+    let mut results: Vec<(usize, usize)> = Vec::new();
     let mut start = 0;
     while start + pat_len <= text_lower.len() {
         if let Some(pos) = text_lower[start..].find(&pattern_lower) {
             let abs_pos = start + pos;
-            results.push((abs_pos, abs_pos + pat_len));
+            let (s, e) = to_text(abs_pos, abs_pos + pat_len);
+            // Two matches inside one expanded character map to the same range
+            if results.last().is_none_or(|&(_, prev_end)| s >= prev_end) {
+                results.push((s, e));
+            }
             start = abs_pos + pat_len; // non-overlapping
         } else {
             break;
@@ -1396,6 +9055,151 @@ fn find_all_matches(text: &str, pattern: &str) -> Vec<(usize, usize)> {
     results
 }
 
+/// The editor functions scripts can call.
+impl script::Host for Editor {
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+        let text = |i| script::arg(name, args, i);
+        let result = match name {
+            "insert" => text(0).map(str::to_string).and_then(|text| {
+                if self.reject_read_only() {
+                    return Err(strings::get("read_only").to_string());
+                }
+                self.delete_selection();
+                self.handle_paste(&text);
+                Ok(Value::Int(0))
+            }),
+            "selection" => {
+                let (start, end) = self.selection_range().unwrap_or((0, 0));
+                Ok(self.buffer.slice(start, end).into())
+            }
+            "clipboard" => Ok(self.clipboard.as_str().into()),
+            "line" => Ok(self
+                .buffer
+                .get_line(self.cursor.line)
+                .map_or_else(String::new, |l| l.trim_end_matches('\n').to_string())
+                .into()),
+            "path" => Ok(self
+                .buffer
+                .file_path()
+                .map_or_else(String::new, |p| p.display().to_string())
+                .into()),
+            "status" => text(0).map(|text| {
+                self.set_message(text, MessageType::Info);
+                Value::Int(0)
+            }),
+            // Built-in commands only, so scripts cannot call themselves
+            "run" => text(0).and_then(|command| match Command::from_name(command) {
+                Some(cmd) => {
+                    self.execute_command(cmd);
+                    Ok(Value::Int(0))
+                }
+                None => Err(strings::format("unknown_command", &[&command])),
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+/// How an event is listed in the profile.
+fn event_kind(event: &Event) -> &'static str {
+    match event {
+        Event::Key(_) => "key",
+        Event::Mouse(_) => "mouse",
+        Event::Paste(_) => "paste",
+        Event::Text(_) => "text",
+        Event::Resize => "resize",
+        Event::Background(..) => "background reply",
+        Event::Tick => "idle upkeep",
+        Event::None => "other input",
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Blame text color by commit age: recent changes stand out, old ones fade.
+fn blame_age_color(age_secs: i64) -> Color {
+    const DAY: i64 = 86_400;
+    let shade = match age_secs {
+        a if a < DAY => 255,
+        a if a < 7 * DAY => 252,
+        a if a < 30 * DAY => 249,
+        a if a < 365 * DAY => 246,
+        _ => 243,
+    };
+    Color::Color256(shade)
+}
+
+fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Error => Color::Ansi(1),
+        Severity::Warning => Color::Ansi(3),
+        _ => Color::Ansi(4),
+    }
+}
+
+/// Line-end annotations for `diagnostics`: on each line where one starts,
+/// the first line of the most severe one's message.
+fn diagnostic_annotations(diagnostics: &[Diagnostic]) -> Vec<Annotation> {
+    let mut annotations: Vec<(Severity, Annotation)> = Vec::new();
+    for d in diagnostics {
+        let line = d.start.0;
+        let annotation = Annotation {
+            line,
+            placement: Placement::LineEnd,
+            text: d.message.lines().next().unwrap_or_default().to_string(),
+            fg: severity_color(d.severity),
+        };
+        match annotations.iter_mut().find(|(_, a)| a.line == line) {
+            Some(slot) if d.severity < slot.0 => *slot = (d.severity, annotation),
+            Some(_) => {}
+            None => annotations.push((d.severity, annotation)),
+        }
+    }
+    annotations.into_iter().map(|(_, a)| a).collect()
+}
+
+/// The first visible line after scrolling just enough to show `cursor` with
+/// `margin` lines of context on either side (fewer at the buffer's ends, and
+/// at most half the `height`).
+fn scroll_to_cursor(
+    scroll_row: usize,
+    cursor: usize,
+    height: usize,
+    margin: usize,
+    last_line: usize,
+) -> usize {
+    if height == 0 {
+        return scroll_row;
+    }
+    let margin = margin.min((height - 1) / 2);
+    let top = cursor.saturating_sub(margin);
+    let bottom = (cursor + margin).min(last_line.max(cursor));
+    if top < scroll_row {
+        top
+    } else if bottom >= scroll_row + height {
+        bottom + 1 - height
+    } else {
+        scroll_row
+    }
+}
+
+/// The first visible line that shows line `cursor` at `place` in a view
+/// `height` lines tall, keeping `margin` lines of context at the top or
+/// bottom.
+fn scroll_for_place(place: ViewPlace, cursor: usize, height: usize, margin: usize) -> usize {
+    let margin = margin.min(height.saturating_sub(1) / 2);
+    match place {
+        ViewPlace::Center => cursor.saturating_sub(height / 2),
+        ViewPlace::Top => cursor.saturating_sub(margin),
+        ViewPlace::Bottom => (cursor + margin + 1).saturating_sub(height),
+    }
+}
+
 fn compute_gutter_width(line_count: usize) -> usize {
     let digits = if line_count == 0 {
         1
@@ -1412,6 +9216,17 @@ fn compute_gutter_width(line_count: usize) -> usize {
     (digits + 2).max(4)
 }
 
+/// The file an Open prompt answer or command-line argument names, and
+/// the 1-based line and column from a `path:line:col` suffix, which is
+/// left out of the path. A file really called that is taken as it is.
+pub fn locate(arg: &str) -> (&Path, Option<(usize, usize)>) {
+    if Path::new(arg).exists() {
+        return (Path::new(arg), None);
+    }
+    let (path, position) = quickfix::split_position(arg);
+    (Path::new(path), position)
+}
+
 /// Shorten a file path for display: replace $HOME prefix with `~`.
 fn shorten_path(path: &Path) -> String {
     let full = path.to_string_lossy();
@@ -1447,6 +9262,65 @@ fn display_col_to_byte_col(line: &str, display_col: usize) -> usize {
     byte_offset
 }
 
+/// A hash of a row's text, to tell whether it changed since the last frame.
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Where each character of `line` is drawn: its byte offset, the character
+/// and its screen column from the line start. Wide characters take two
+/// columns and right-to-left runs are in display order. A tab becomes
+/// blank cells up to its stop, all with the tab's offset; a control
+/// character the cells of its placeholder, and the `\r` of a CRLF line
+/// ending none.
+fn visual_cells(line: &str, tabs: &TabStops) -> Vec<(usize, char, usize)> {
+    let mut cells = Vec::with_capacity(line.len());
+    let mut col = 0;
+    let mut tab = 0;
+    for (at, ch) in bidi::visual_chars(line) {
+        if ch == '\t' {
+            let end = tabs.end(tab, col);
+            cells.extend((col..end).map(|c| (at, ' ', c)));
+            col = end;
+            tab += 1;
+        } else if ch == '\r' && at + 1 == line.len() {
+            continue;
+        } else if let Some(shown) = unicode::placeholder(ch) {
+            cells.extend(shown.chars().enumerate().map(|(i, c)| (at, c, col + i)));
+            col += shown.len();
+        } else {
+            cells.push((at, ch, col));
+            col += unicode::cell_width(ch);
+        }
+    }
+    cells
+}
+
+/// The screen column where the character at `byte_col` is drawn, given the
+/// `cells` of its line. The end of the line follows the last cell.
+fn byte_col_to_screen_col(cells: &[(usize, char, usize)], byte_col: usize) -> usize {
+    match cells.iter().find(|&&(at, _, _)| at == byte_col) {
+        Some(&(_, _, col)) => col,
+        None => cells
+            .iter()
+            .map(|&(_, ch, col)| col + unicode::cell_width(ch))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// Byte offset of the character drawn at screen column `col`, including
+/// the right half of a wide character; between cells, of the next one.
+/// Columns past the text give `len`, the end of the line.
+fn screen_col_to_byte_col(cells: &[(usize, char, usize)], len: usize, col: usize) -> usize {
+    cells
+        .iter()
+        .find(|&&(_, ch, start)| col < start + unicode::cell_width(ch))
+        .map_or(len, |&(at, _, _)| at)
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -1466,6 +9340,69 @@ mod tests {
         assert_eq!(compute_gutter_width(1000), 6); // 4 digits + 2 = 6
     }
 
+    #[test]
+    fn test_blame_age_color_fades() {
+        assert_eq!(blame_age_color(60), Color::Color256(255));
+        assert_eq!(blame_age_color(3 * 86_400), Color::Color256(252));
+        assert_eq!(blame_age_color(3000 * 86_400), Color::Color256(243));
+    }
+
+    #[test]
+    fn test_indent_backspace_len() {
+        assert_eq!(indent_backspace_len("        ", "    "), Some(4));
+        assert_eq!(indent_backspace_len("      ", "    "), Some(2));
+        assert_eq!(indent_backspace_len("  ", "  "), Some(2));
+        assert_eq!(indent_backspace_len("    x ", "    "), None);
+        assert_eq!(indent_backspace_len("        ", "\t"), None);
+        assert_eq!(indent_backspace_len("", "    "), None);
+    }
+
+    #[test]
+    fn test_outdent_len() {
+        assert_eq!(outdent_len("        x", "    ", 4), 4);
+        assert_eq!(outdent_len("  x", "    ", 4), 2);
+        assert_eq!(outdent_len("\t\tx", "    ", 4), 1);
+        assert_eq!(outdent_len("      x", "\t", 4), 4);
+        assert_eq!(outdent_len("x", "  ", 4), 0);
+    }
+
+    #[test]
+    fn test_scroll_to_cursor() {
+        // No margin: scroll only when the cursor leaves the view
+        assert_eq!(scroll_to_cursor(0, 9, 10, 0, 100), 0);
+        assert_eq!(scroll_to_cursor(0, 10, 10, 0, 100), 1);
+        assert_eq!(scroll_to_cursor(5, 3, 10, 0, 100), 3);
+        // A margin of 3 keeps context below and above
+        assert_eq!(scroll_to_cursor(0, 7, 10, 3, 100), 1);
+        assert_eq!(scroll_to_cursor(10, 12, 10, 3, 100), 9);
+        // ...but not past the buffer's ends
+        assert_eq!(scroll_to_cursor(91, 100, 10, 3, 100), 91);
+        assert_eq!(scroll_to_cursor(0, 1, 10, 3, 100), 0);
+        // A margin too big for the view centers the cursor
+        assert_eq!(scroll_to_cursor(0, 50, 11, 99, 100), 45);
+    }
+
+    #[test]
+    fn test_scroll_for_place() {
+        assert_eq!(scroll_for_place(ViewPlace::Center, 50, 20, 0), 40);
+        assert_eq!(scroll_for_place(ViewPlace::Top, 50, 20, 0), 50);
+        assert_eq!(scroll_for_place(ViewPlace::Bottom, 50, 20, 0), 31);
+        assert_eq!(scroll_for_place(ViewPlace::Top, 50, 20, 3), 47);
+        assert_eq!(scroll_for_place(ViewPlace::Bottom, 50, 20, 3), 34);
+        // Near the start there is nothing to scroll
+        assert_eq!(scroll_for_place(ViewPlace::Center, 3, 20, 0), 0);
+        assert_eq!(scroll_for_place(ViewPlace::Bottom, 3, 20, 0), 0);
+    }
+
+    #[test]
+    fn test_join_span() {
+        assert_eq!(join_span("let x =", "    1;"), (0, 4, " "));
+        assert_eq!(join_span("foo(  ", "\tbar)"), (2, 1, " "));
+        assert_eq!(join_span("    ", "  next"), (4, 2, ""));
+        assert_eq!(join_span("end", ""), (0, 0, ""));
+        assert_eq!(join_span("crlf\r", "next"), (1, 0, " "));
+    }
+
     #[test]
     fn test_shorten_path() {
         // Path outside home stays as-is
@@ -1505,6 +9442,58 @@ mod tests {
         assert_eq!(display_col_to_byte_col("café", 4), 5); // after 'é'
     }
 
+    #[test]
+    fn test_screen_cols() {
+        // Wide characters take two columns
+        let line = "a日本b";
+        let cells = visual_cells(line, &TabStops::fixed(4));
+        assert_eq!(byte_col_to_screen_col(&cells, 1), 1);
+        assert_eq!(byte_col_to_screen_col(&cells, 4), 3);
+        assert_eq!(byte_col_to_screen_col(&cells, 7), 5);
+        assert_eq!(byte_col_to_screen_col(&cells, 8), 6);
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 2), 1); // right half of 日
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 3), 4);
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 9), 8);
+
+        // The Hebrew letters at bytes 3, 5, 7 are drawn at columns 5, 4, 3
+        let line = "ab אבג cd";
+        let cells = visual_cells(line, &TabStops::fixed(4));
+        assert_eq!(byte_col_to_screen_col(&cells, 3), 5);
+        assert_eq!(byte_col_to_screen_col(&cells, 7), 3);
+        assert_eq!(byte_col_to_screen_col(&cells, 1), 1);
+        assert_eq!(byte_col_to_screen_col(&cells, line.len()), 9);
+        for (at, _) in line.char_indices() {
+            let col = byte_col_to_screen_col(&cells, at);
+            assert_eq!(screen_col_to_byte_col(&cells, line.len(), col), at);
+        }
+
+        // A tab is one character drawn up to its stop
+        let line = "a\tb";
+        let cells = visual_cells(line, &TabStops::fixed(4));
+        assert_eq!(byte_col_to_screen_col(&cells, 1), 1);
+        assert_eq!(byte_col_to_screen_col(&cells, 2), 4);
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 3), 1);
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 4), 2);
+
+        // Control characters take the cells of their placeholders; a CRLF
+        // line's `\r` takes none
+        let line = "a\x1bb\r";
+        let cells = visual_cells(line, &TabStops::fixed(4));
+        let shown: String = cells.iter().map(|&(_, ch, _)| ch).collect();
+        assert_eq!(shown, "a^[b");
+        assert_eq!(byte_col_to_screen_col(&cells, 2), 3);
+        assert_eq!(byte_col_to_screen_col(&cells, 3), 4);
+        assert_eq!(screen_col_to_byte_col(&cells, line.len(), 2), 1);
+
+        // Padding between columns maps to the delimiter after the field
+        let columns = Columns::measure(',', ["name,age", "Bo,7"]);
+        let cells = columns.layout("Bo,7");
+        assert_eq!(byte_col_to_screen_col(&cells, 3), 7);
+        assert_eq!(byte_col_to_screen_col(&cells, 4), 8);
+        assert_eq!(screen_col_to_byte_col(&cells, 4, 3), 2);
+        assert_eq!(screen_col_to_byte_col(&cells, 4, 6), 3);
+    }
+
     // -- Selection tests --
 
     #[test]
@@ -1562,147 +9551,87 @@ mod tests {
         assert_eq!(cursor.col, 5);
     }
 
-    // -- Prompt tests --
-
-    #[test]
-    fn test_prompt_insert_char() {
-        let mut prompt = Prompt {
-            label: "Open: ".to_string(),
-            input: String::new(),
-            cursor_pos: 0,
-            action: PromptAction::OpenFile,
-        };
-
-        // Insert 'a'
-        prompt.input.insert_str(prompt.cursor_pos, "a");
-        prompt.cursor_pos += 1;
-        assert_eq!(prompt.input, "a");
-        assert_eq!(prompt.cursor_pos, 1);
-
-        // Insert 'b'
-        prompt.input.insert_str(prompt.cursor_pos, "b");
-        prompt.cursor_pos += 1;
-        assert_eq!(prompt.input, "ab");
-        assert_eq!(prompt.cursor_pos, 2);
+    // -- Search tests --
 
-        // Move cursor left, insert 'x' in the middle
-        let before = &prompt.input[..prompt.cursor_pos];
-        if let Some(ch) = before.chars().next_back() {
-            prompt.cursor_pos -= ch.len_utf8();
+    fn edit(pos: usize, deleted: usize, inserted: usize) -> Edit {
+        Edit {
+            pos,
+            deleted,
+            inserted,
         }
-        prompt.input.insert_str(prompt.cursor_pos, "x");
-        prompt.cursor_pos += 1;
-        assert_eq!(prompt.input, "axb");
-        assert_eq!(prompt.cursor_pos, 2);
     }
 
     #[test]
-    fn test_prompt_backspace() {
-        let mut prompt = Prompt {
-            label: "Open: ".to_string(),
-            input: "hello".to_string(),
-            cursor_pos: 5,
-            action: PromptAction::OpenFile,
-        };
-
-        // Backspace at end
-        let before = &prompt.input[..prompt.cursor_pos];
-        if let Some(ch) = before.chars().next_back() {
-            let len = ch.len_utf8();
-            let new_pos = prompt.cursor_pos - len;
-            prompt.input.drain(new_pos..prompt.cursor_pos);
-            prompt.cursor_pos = new_pos;
-        }
-        assert_eq!(prompt.input, "hell");
-        assert_eq!(prompt.cursor_pos, 4);
+    fn test_search_state_cap() {
+        let matches: Vec<(usize, usize)> = (0..MAX_MATCHES + 5).map(|i| (i, i + 1)).collect();
+        let search = SearchState::new("x", matches, 0);
+        assert_eq!(search.matches.len(), MAX_MATCHES);
+        assert_eq!(search.total(), format!("{}+", MAX_MATCHES));
+        assert_eq!(SearchState::new("x", vec![(0, 1)], 0).total(), "1");
     }
 
     #[test]
-    fn test_prompt_delete() {
-        let mut prompt = Prompt {
-            label: "Open: ".to_string(),
-            input: "hello".to_string(),
-            cursor_pos: 0,
-            action: PromptAction::OpenFile,
-        };
-
-        // Delete at start
-        let after = &prompt.input[prompt.cursor_pos..];
-        if let Some(ch) = after.chars().next() {
-            let len = ch.len_utf8();
-            prompt
-                .input
-                .drain(prompt.cursor_pos..prompt.cursor_pos + len);
-        }
-        assert_eq!(prompt.input, "ello");
-        assert_eq!(prompt.cursor_pos, 0);
+    fn test_shift_matches_insertions() {
+        // "foo bar foo": typing "xy" before the first match moves both
+        let mut matches = vec![(0, 3), (8, 11)];
+        assert_eq!(shift_matches(&mut matches, &[edit(0, 0, 2)]), Some((0, 2)));
+        assert_eq!(matches, vec![(2, 5), (10, 13)]);
+
+        // Inside a match drops it; right after one leaves it alone
+        let mut matches = vec![(0, 3), (8, 11)];
+        assert_eq!(shift_matches(&mut matches, &[edit(1, 0, 1)]), Some((1, 2)));
+        assert_eq!(matches, vec![(9, 12)]);
+        let mut matches = vec![(0, 3), (8, 11)];
+        shift_matches(&mut matches, &[edit(3, 0, 1)]);
+        assert_eq!(matches, vec![(0, 3), (9, 12)]);
+
+        // No edits, no range to search again
+        assert_eq!(shift_matches(&mut matches, &[]), None);
     }
 
     #[test]
-    fn test_prompt_cursor_movement() {
-        let mut prompt = Prompt {
-            label: "Open: ".to_string(),
-            input: "abc".to_string(),
-            cursor_pos: 0,
-            action: PromptAction::OpenFile,
-        };
-
-        // Right
-        let after = &prompt.input[prompt.cursor_pos..];
-        if let Some(ch) = after.chars().next() {
-            prompt.cursor_pos += ch.len_utf8();
-        }
-        assert_eq!(prompt.cursor_pos, 1);
-
-        // End
-        prompt.cursor_pos = prompt.input.len();
-        assert_eq!(prompt.cursor_pos, 3);
-
-        // Home
-        prompt.cursor_pos = 0;
-        assert_eq!(prompt.cursor_pos, 0);
-
-        // Left at start — should stay at 0
-        if prompt.cursor_pos > 0 {
-            let before = &prompt.input[..prompt.cursor_pos];
-            if let Some(ch) = before.chars().next_back() {
-                prompt.cursor_pos -= ch.len_utf8();
-            }
-        }
-        assert_eq!(prompt.cursor_pos, 0);
+    fn test_shift_matches_deletions() {
+        // Deleting part of a match drops it; the range shrinks to a point
+        let mut matches = vec![(0, 3), (8, 11)];
+        assert_eq!(shift_matches(&mut matches, &[edit(2, 3, 0)]), Some((2, 2)));
+        assert_eq!(matches, vec![(5, 8)]);
+        // Deleting the text between matches keeps both
+        let mut matches = vec![(0, 3), (8, 11)];
+        shift_matches(&mut matches, &[edit(3, 5, 0)]);
+        assert_eq!(matches, vec![(0, 3), (3, 6)]);
     }
 
     #[test]
-    fn test_prompt_utf8_navigation() {
-        let mut prompt = Prompt {
-            label: "Open: ".to_string(),
-            input: "café".to_string(), // c(1) a(1) f(1) é(2) = 5 bytes
-            cursor_pos: 5,             // at end
-            action: PromptAction::OpenFile,
-        };
-
-        // Left from end — should move back over 'é' (2 bytes)
-        let before = &prompt.input[..prompt.cursor_pos];
-        if let Some(ch) = before.chars().next_back() {
-            prompt.cursor_pos -= ch.len_utf8();
-        }
-        assert_eq!(prompt.cursor_pos, 3);
+    fn test_shift_matches_several_edits() {
+        // Typing "ab" one character at a time, then a far edit: the range
+        // covers all of them in the final text
+        let mut matches = vec![(0, 3), (20, 23)];
+        let edits = [edit(10, 0, 1), edit(11, 0, 1), edit(4, 2, 0)];
+        assert_eq!(shift_matches(&mut matches, &edits), Some((4, 10)));
+        assert_eq!(matches, vec![(0, 3), (20, 23)]);
+    }
 
-        // Backspace 'é' — should remove 2 bytes
-        // Move cursor back to end to test backspace over 'é'
-        prompt.cursor_pos = 5;
-        let before3 = &prompt.input[..prompt.cursor_pos];
-        if let Some(ch) = before3.chars().next_back() {
-            let len = ch.len_utf8();
-            let new_pos = prompt.cursor_pos - len;
-            prompt.input.drain(new_pos..prompt.cursor_pos);
-            prompt.cursor_pos = new_pos;
-        }
-        assert_eq!(prompt.input, "caf");
-        assert_eq!(prompt.cursor_pos, 3);
+    #[test]
+    fn test_idle_wait() {
+        let ms = Duration::from_millis;
+        // Fixed without an idle timeout above the read timeout
+        assert_eq!(idle_wait(100, 0, Duration::from_secs(60)), ms(100));
+        assert_eq!(idle_wait(100, 50, Duration::from_secs(60)), ms(100));
+        // Short while typing, longer the longer it is idle
+        assert_eq!(idle_wait(20, 1000, ms(10)), ms(20));
+        assert_eq!(idle_wait(20, 1000, ms(600)), ms(300));
+        assert_eq!(idle_wait(20, 1000, Duration::from_secs(60)), ms(1000));
     }
 
-    // -- Search tests --
+    #[test]
+    fn test_ex_filetype() {
+        assert_eq!(ex_filetype("set ft=rs"), Some("rs"));
+        assert_eq!(ex_filetype("set filetype=py"), Some("py"));
+        assert_eq!(ex_filetype("setf sh"), Some("sh"));
+        assert_eq!(ex_filetype("set ft="), Some(""));
+        assert_eq!(ex_filetype("set number"), None);
+        assert_eq!(ex_filetype("wq"), None);
+    }
 
     #[test]
     fn test_find_all_matches_basic() {
@@ -1728,6 +9657,38 @@ mod tests {
         assert_eq!(matches, vec![(0, 2)]);
     }
 
+    #[test]
+    fn test_find_all_matches_length_changing_case() {
+        // 'İ' lowers to three bytes and 'ẞ' to two: ranges stay on the
+        // original text's characters
+        let text = "aİb ẞx İ";
+        assert_eq!(find_all_matches(text, "i"), [(1, 3), (10, 12)]);
+        assert_eq!(find_all_matches(text, "b"), [(3, 4)]);
+        assert_eq!(find_all_matches(text, "ßx"), [(5, 9)]);
+        assert_eq!(find_all_matches(text, "x"), [(8, 9)]);
+        for (s, e) in find_all_matches(text, "İ") {
+            assert!(text.is_char_boundary(s) && text.is_char_boundary(e));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_find_all_matches() {
+        let text = crate::bench::large_text(100_000);
+        crate::bench::within(
+            "search: 100k lines, many matches",
+            20,
+            Duration::from_millis(100),
+            || find_all_matches(&text, "value_9"),
+        );
+        crate::bench::within(
+            "search: 100k lines, no match",
+            20,
+            Duration::from_millis(100),
+            || find_all_matches(&text, "missing"),
+        );
+    }
+
     #[test]
     fn test_find_all_matches_utf8() {
         let matches = find_all_matches("café café", "café");
@@ -1735,4 +9696,872 @@ mod tests {
         assert_eq!(matches[0], (0, 5)); // "café" = 5 bytes
         assert_eq!(matches[1], (6, 11)); // after space
     }
+
+    // -- Whole sessions, driven through the headless editor --
+
+    #[test]
+    fn test_headless_type_and_undo() {
+        let mut editor = Editor::headless("hello\n", 40, 6);
+        editor.type_bytes(b"abc");
+        assert_eq!(editor.buffer().text(), "abchello\n");
+        let screen = editor.screen_snapshot();
+        assert!(screen.starts_with("  1 abchello\n  2\n"), "{}", screen);
+        assert!(screen.contains("[No Name] [+]"), "{}", screen);
+        assert_eq!(editor.cursor_position(), (0, 7));
+
+        editor.type_bytes(b"\x1a"); // Ctrl+Z
+        assert_eq!(editor.buffer().text(), "hello\n");
+        assert!(editor.screen_snapshot().starts_with("  1 hello\n"));
+    }
+
+    #[test]
+    fn test_headless_search() {
+        let mut editor = Editor::headless("one two\nthree two\n", 40, 6);
+        editor.type_bytes(b"\x06two\r"); // Ctrl+F
+        let screen = editor.screen_snapshot();
+        assert!(screen.ends_with("Match 1 of 2"), "{}", screen);
+        assert_eq!(editor.cursor_position(), (0, 8));
+
+        editor.handle_event_for_test(Event::Key(KeyEvent::plain(Key::F(3))));
+        assert_eq!(editor.cursor_position(), (1, 10));
+    }
+
+    /// Mark the first cell of every text row, send the frame after that to
+    /// a terminal and return the text rows it wrote: the rows drawn again.
+    /// A row that is kept keeps its mark and sends nothing.
+    fn redrawn_rows(editor: &mut Editor) -> Vec<usize> {
+        let h = editor.text_area_height();
+        for row in 0..h {
+            editor
+                .screen
+                .put_char(row, 0, '#', Color::Default, Color::Default, false);
+        }
+        editor.screen.diff_output(&ColorMode::TrueColor);
+        editor.draw();
+        let term = terminal::MockTerminal::new(b"");
+        Screen::send(&term, &editor.screen.diff_output(&ColorMode::TrueColor));
+        let mut rows: Vec<usize> = term
+            .output()
+            .split("\x1b[")
+            .filter_map(|seq| {
+                let (row, rest) = seq.split_once(';')?;
+                let (col, _) = rest.split_once('H')?;
+                col.parse::<usize>().ok()?;
+                row.parse::<usize>().ok().map(|r| r - 1)
+            })
+            .filter(|&row| row < h)
+            .collect();
+        rows.dedup();
+        rows
+    }
+
+    #[test]
+    fn test_headless_redraws_changed_rows() {
+        let mut editor = Editor::headless("one\ntwo\nthree\n", 40, 8);
+        editor.cursor.set_position(1, 3, &editor.buffer);
+        editor.screen_snapshot();
+        assert_eq!(redrawn_rows(&mut editor), []);
+
+        // An edit redraws its row only
+        editor.type_bytes(b"s");
+        assert_eq!(redrawn_rows(&mut editor), [1]);
+        assert_eq!(redrawn_rows(&mut editor), []);
+        // A new line redraws the rows below it too
+        editor.type_bytes(b"\r");
+        assert_eq!(redrawn_rows(&mut editor), [1, 2, 3, 4]);
+
+        editor.invalidate_frame();
+        assert_eq!(redrawn_rows(&mut editor), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_headless_long_line() {
+        let text = format!("start{}end\nnext\n", "ab".repeat(LONG_LINE / 2));
+        let mut editor = Editor::headless(&text, 20, 5);
+        let screen = editor.screen_snapshot();
+        assert!(screen.starts_with("  1 startabababababa\n"), "{}", screen);
+        // Only the start of the line is read for the row
+        let view = editor.view_state(editor.text_area_height());
+        let (text, _) = editor.row_state(0, &view);
+        assert!(text.len() <= 20 * CHAR_MAX_BYTES);
+
+        editor.type_bytes(b"\x1b[F"); // End
+        let screen = editor.screen_snapshot();
+        assert!(screen.starts_with("  1 ababababababend\n"), "{}", screen);
+        assert_eq!(editor.cursor_position(), (0, 19));
+        editor.type_bytes(b"\x1b[B\x1b[A\x1b[H"); // Down, Up, Home
+        assert_eq!(editor.cursor.col, 0);
+        assert!(editor.screen_snapshot().starts_with("  1 start"));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_long_line_frame() {
+        // A 10 MB line, like a minified bundle
+        let text = "var a=[1,2,3];".repeat(10 << 20 >> 4);
+        let mut editor = Editor::headless(&text, 200, 60);
+        editor.draw();
+        crate::bench::within(
+            "render: 10 MB line, moving",
+            50,
+            Duration::from_millis(2),
+            || {
+                editor.handle_event_for_test(Event::Key(KeyEvent::plain(Key::Right)));
+                editor.draw();
+            },
+        );
+        crate::bench::within(
+            "render: 10 MB line, full frame",
+            50,
+            Duration::from_millis(2),
+            || {
+                editor.invalidate_frame();
+                editor.draw();
+            },
+        );
+    }
+
+    #[test]
+    fn test_headless_control_chars() {
+        let mut editor = Editor::headless("a\x1bb\x0c\r\n", 20, 4);
+        assert!(editor.screen_snapshot().starts_with("  1 a^[b^L\n"));
+        assert!(editor.screen.cell(0, 5).unwrap().reverse);
+        assert!(!editor.screen.cell(0, 7).unwrap().reverse);
+        editor.type_bytes(b"\x1b[C\x1b[C"); // Right, Right
+        assert_eq!(editor.cursor_position(), (0, 7));
+    }
+
+    #[test]
+    fn test_headless_match_styles() {
+        let theme = Theme::default();
+        let mut editor = Editor::headless("x. x. x\n", 40, 6);
+        editor.type_bytes(b"\x06x\r"); // Ctrl+F
+        editor.screen_snapshot();
+        let current = editor.screen.cell(0, 4).unwrap();
+        assert_eq!((current.bg, current.bold), (theme.search_current, true));
+        let other = editor.screen.cell(0, 7).unwrap();
+        assert_eq!(
+            (other.fg, other.bg),
+            (theme.search_text, theme.search_match)
+        );
+
+        // Underlined instead of colored
+        editor.config.search_underline = true;
+        editor.screen_snapshot();
+        let other = editor.screen.cell(0, 7).unwrap();
+        assert!(other.underline);
+        assert_ne!(other.bg, theme.search_match);
+        assert_eq!(editor.screen.cell(0, 4).unwrap().bg, theme.search_current);
+
+        // A view full of matches has the others dimmed
+        let mut editor = Editor::headless(&"x ".repeat(16), 40, 6);
+        editor.type_bytes(b"\x06x\r");
+        editor.screen_snapshot();
+        let other = editor.screen.cell(0, 6).unwrap();
+        assert_eq!((other.fg, other.bg), (Color::Default, theme.search_dim));
+    }
+
+    #[test]
+    fn test_headless_software_cursor() {
+        let mut editor = Editor::headless("ab\n", 20, 4);
+        editor.config.software_cursor = true;
+        let cursor_bg = editor.theme().cursor;
+        let cell_at = |editor: &Editor, (row, col)| editor.screen.cell(row, col).unwrap();
+
+        editor.screen_snapshot();
+        let first = editor.cursor_position();
+        assert_eq!(cell_at(&editor, first).ch, 'a');
+        assert_eq!(cell_at(&editor, first).bg, cursor_bg);
+
+        // Moving on puts back the cell it covered
+        editor.type_bytes(b"\x1b[C"); // Right
+        editor.screen_snapshot();
+        assert_eq!(cell_at(&editor, editor.cursor_position()).bg, cursor_bg);
+        assert_eq!(cell_at(&editor, first).ch, 'a');
+        assert_ne!(cell_at(&editor, first).bg, cursor_bg);
+
+        // The hidden half of a blink
+        editor.cursor_blink.0 = false;
+        editor.screen_snapshot();
+        assert_ne!(cell_at(&editor, editor.cursor_position()).bg, cursor_bg);
+    }
+
+    #[test]
+    fn test_headless_frame_rate_cap() {
+        let mut editor = Editor::headless("one\n", 20, 4);
+        editor.config.max_fps = 10;
+        // The first frame is due at once
+        assert_eq!(editor.until_next_frame(), None);
+        editor.render();
+        let wait = editor.until_next_frame().unwrap();
+        assert!(wait <= Duration::from_millis(100), "{:?}", wait);
+        // A frame drawn long enough ago lets the next one through
+        editor.last_frame = Some(Instant::now() - Duration::from_millis(100));
+        assert_eq!(editor.until_next_frame(), None);
+        // No cap draws after every read
+        editor.render();
+        editor.config.max_fps = 0;
+        assert_eq!(editor.until_next_frame(), None);
+    }
+
+    #[test]
+    fn test_headless_tick_runs_idle_work() {
+        let mut editor = Editor::headless("one\n", 20, 4);
+        editor.config.software_cursor = true;
+        editor.config.cursor_blink = 1;
+        editor.set_message("Saved", MessageType::Info);
+        let overdue = Instant::now() - Duration::from_millis(10);
+
+        // A tick blinks the cursor and leaves the message
+        editor.cursor_blink.1 = overdue;
+        editor.handle_event_for_test(Event::Tick);
+        assert!(!editor.cursor_blink.0);
+        assert!(editor.screen_snapshot().ends_with("Saved"));
+        editor.cursor_blink.1 = overdue;
+        editor.handle_event_for_test(Event::Tick);
+        assert!(editor.cursor_blink.0);
+
+        // Input shows the cursor again and takes the message
+        editor.cursor_blink = (false, overdue);
+        editor.type_bytes(b"x");
+        assert!(editor.cursor_blink.0);
+        assert!(!editor.screen_snapshot().contains("Saved"));
+    }
+
+    #[test]
+    fn test_headless_selection_layer() {
+        let mut editor = Editor::headless("ab\n", 20, 4);
+        editor.type_bytes(b"\x01"); // Ctrl+A
+        editor.screen_snapshot();
+        // The selection is a background; the text keeps its own color
+        let selected = editor.screen.cell(0, 4).unwrap();
+        assert_eq!(selected.ch, 'a');
+        assert_eq!(selected.bg, editor.theme().selection);
+        assert_eq!(selected.fg, Color::Default);
+        assert!(!selected.bold);
+    }
+
+    #[test]
+    fn test_headless_open_file() {
+        let path = std::env::temp_dir().join("zelux_test_headless_open.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+        let mut editor = Editor::headless("", 60, 6);
+        editor.type_bytes(b"\x0f"); // Ctrl+O
+        assert!(editor.screen_snapshot().ends_with("Open:"));
+        editor.type_bytes(path.to_string_lossy().as_bytes());
+        editor.type_bytes(b"\r");
+        assert_eq!(editor.buffer().text(), "fn main() {}\n");
+        let screen = editor.screen_snapshot();
+        assert!(screen.starts_with("  1 fn main() {}\n"), "{}", screen);
+        assert!(screen.contains("zelux_test_headless_open.rs"), "{}", screen);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_headless_open_at_position() {
+        let path = std::env::temp_dir().join("zelux_test_headless_open_at.rs");
+        let text: String = (1..=40).map(|n| format!("line {}\n", n)).collect();
+        fs::write(&path, &text).unwrap();
+        let mut editor = Editor::headless("", 60, 6);
+        editor.type_bytes(b"\x0f"); // Ctrl+O
+        editor.type_bytes(format!("{}:30:6:", path.display()).as_bytes());
+        editor.type_bytes(b"\r");
+        assert_eq!(editor.buffer().text(), text);
+        assert_eq!((editor.cursor.line, editor.cursor.col), (29, 5));
+        let screen = editor.screen_snapshot();
+        assert!(screen.contains(" 30 line 30"), "{}", screen);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_headless_lock_files() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_locks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        fs::write(&a, "a\n").unwrap();
+        fs::write(&b, "b\n").unwrap();
+        let mut editor = Editor::headless("", 160, 6);
+        editor.open_path(&a).unwrap();
+        assert!(lockfile::lock_path(&a).exists());
+
+        // Held by another instance: offered read-only
+        let other = format!("{} elsewhere\n", std::process::id());
+        fs::write(lockfile::lock_path(&b), &other).unwrap();
+        editor.open_path(&b).unwrap();
+        let screen = editor.screen_snapshot();
+        assert!(
+            screen.contains("on elsewhere. Open it read-only?"),
+            "{}",
+            screen
+        );
+        editor.type_bytes(b"y");
+        assert!(editor.buffer().is_read_only());
+        editor.run_command(Command::Save);
+        assert!(editor.screen_snapshot().contains("has it open too"));
+
+        // Closing a buffer lets go of its lock, and only that one
+        editor.run_command(Command::PrevBuffer);
+        editor.run_command(Command::CloseBuffer);
+        assert!(!lockfile::lock_path(&a).exists());
+        assert_eq!(fs::read_to_string(lockfile::lock_path(&b)).unwrap(), other);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_headless_patch_buffer() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_patch_{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/a.rs"), "one\ntwo\nthree\n").unwrap();
+        let patch = "diff --git a/src/a.rs b/src/a.rs\n--- a/src/a.rs\n+++ b/src/a.rs\n\
+                     @@ -1,3 +1,3 @@\n one\n-two\n+TWO\n three\n";
+        fs::write(dir.join("fix.patch"), patch).unwrap();
+        let mut editor = Editor::headless("", 60, 12);
+        editor.open_path(&dir.join("fix.patch")).unwrap();
+        let screen = editor.screen_snapshot();
+        let fg_of = |row: usize, text: &str| {
+            let line = screen.lines().nth(row).unwrap();
+            let col = line[..line.find(text).unwrap()].chars().count();
+            editor.screen.cell(row, col).unwrap().fg
+        };
+        assert_eq!(fg_of(3, "@@"), Color::Ansi(6));
+        assert_eq!(fg_of(5, "-two"), Color::Ansi(1));
+        assert_eq!(fg_of(6, "+TWO"), Color::Ansi(2));
+        assert_eq!(fg_of(7, "three"), Color::Default);
+
+        editor.run_command(Command::NextHunk);
+        assert_eq!(editor.cursor.line, 3);
+        // From the W of +TWO to the w of two in the file
+        editor.goto_line_col(6, 2);
+        editor.run_command(Command::GotoPatchSource);
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\n");
+        assert_eq!((editor.cursor.line, editor.cursor.col), (1, 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_headless_open_under_cursor() {
+        let path = std::env::temp_dir().join("zelux_test_open_under_cursor.txt");
+        fs::write(&path, "one\ntwo\nthree\n").unwrap();
+        let text = format!("see ({}:2:3).\n", path.display());
+        let mut editor = Editor::headless(&text, 80, 6);
+        editor.type_bytes(b"\x1bo"); // Alt+O on "see"
+        assert!(editor.screen_snapshot().ends_with("No file named see"));
+
+        // Ctrl+Click on the path, at screen column 10
+        editor.type_bytes(b"\x1b[<16;11;1M\x1b[<16;11;1m");
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\n");
+        assert_eq!((editor.cursor.line, editor.cursor.col), (1, 2));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_headless_file_finder() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_finder_{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        fs::write(dir.join("src/b.rs"), "b\n").unwrap();
+        let mut editor = Editor::headless("", 60, 8);
+        editor.open_file_finder(&dir);
+        let screen = editor.screen_snapshot();
+        assert!(
+            screen.contains(" a.txt") && screen.contains(" src/b.rs"),
+            "{}",
+            screen
+        );
+
+        // A file created while the finder is open shows up on refresh
+        fs::write(dir.join("src/new.rs"), "new\n").unwrap();
+        editor.refresh_file_index(true);
+        assert!(editor.screen_snapshot().contains(" src/new.rs"));
+        editor.type_bytes(b"nw\r");
+        assert_eq!(editor.buffer().text(), "new\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_headless_complete_from_word_lists() {
+        let mut editor = Editor::headless("Zel", 40, 8);
+        editor.cursor.set_position(0, 3, &editor.buffer);
+        // As read from the user's and the project's lists
+        editor.listed_words = Some("zeluxian\nother".to_string());
+        editor.run_command(Command::Complete);
+        assert_eq!(editor.buffer().text(), "zeluxian");
+    }
+
+    #[test]
+    fn test_headless_delete_words_and_line_parts() {
+        let mut editor = Editor::headless("one two\nthree\n", 40, 8);
+        let at = |editor: &Editor| (editor.cursor.line, editor.cursor.col);
+        // Nothing before the start or after the end of the buffer
+        editor.run_command(Command::DeleteWordBackward);
+        editor.run_command(Command::DeleteToLineStart);
+        assert_eq!(editor.buffer().text(), "one two\nthree\n");
+        editor.run_command(Command::CursorFileEnd);
+        editor.run_command(Command::DeleteWordForward);
+        editor.run_command(Command::DeleteToLineEnd);
+        assert_eq!(editor.buffer().text(), "one two\nthree\n");
+
+        editor.cursor.set_position(0, 7, &editor.buffer);
+        editor.type_bytes(b"\x1b\x7f"); // Alt+Backspace
+        assert_eq!(editor.buffer().text(), "one \nthree\n");
+        assert_eq!(at(&editor), (0, 4));
+        editor.run_command(Command::Undo);
+        assert_eq!(editor.buffer().text(), "one two\nthree\n");
+        assert_eq!(at(&editor), (0, 7));
+
+        editor.cursor.set_position(0, 0, &editor.buffer);
+        editor.type_bytes(b"\x1b[3;5~"); // Ctrl+Delete
+        assert_eq!(editor.buffer().text(), "two\nthree\n");
+        assert_eq!(at(&editor), (0, 0));
+
+        // Ctrl+K to the line end, then the line break
+        editor.cursor.set_position(0, 1, &editor.buffer);
+        editor.type_bytes(b"\x0b");
+        assert_eq!(editor.buffer().text(), "t\nthree\n");
+        editor.type_bytes(b"\x0b");
+        assert_eq!(editor.buffer().text(), "tthree\n");
+        assert_eq!(at(&editor), (0, 1));
+
+        // Ctrl+U to the line start, then the line break before it
+        editor.cursor.set_position(0, 3, &editor.buffer);
+        editor.type_bytes(b"\x15");
+        assert_eq!(editor.buffer().text(), "ree\n");
+        assert_eq!(at(&editor), (0, 0));
+        editor.cursor.set_position(1, 0, &editor.buffer);
+        editor.type_bytes(b"\x15");
+        assert_eq!(editor.buffer().text(), "ree");
+        assert_eq!(at(&editor), (0, 3));
+
+        // A selection is deleted instead
+        editor.selection = Some(Selection { anchor: 0, head: 1 });
+        editor.run_command(Command::DeleteWordForward);
+        assert_eq!(editor.buffer().text(), "ee");
+    }
+
+    #[test]
+    fn test_headless_select_home_end_and_file_edges() {
+        let mut editor = Editor::headless("  one\ntwo\n", 40, 8);
+        editor.cursor.set_position(0, 4, &editor.buffer);
+        // Shift+Home stops at the first non-blank, then column 1
+        editor.type_bytes(b"\x1b[1;2H");
+        assert_eq!(editor.selection_range(), Some((2, 4)));
+        editor.type_bytes(b"\x1b[1;2H");
+        assert_eq!(editor.selection_range(), Some((0, 4)));
+        editor.type_bytes(b"\x1b[1;2F"); // Shift+End
+        assert_eq!(editor.selection_range(), Some((4, 5)));
+
+        // Ctrl+Shift+End from the anchor to the end of the file
+        editor.type_bytes(b"\x1b[1;6F");
+        assert_eq!(editor.selection_range(), Some((4, 10)));
+        assert_eq!((editor.cursor.line, editor.cursor.col), (2, 0));
+        // ...and Ctrl+Shift+Home back past it to the start
+        editor.type_bytes(b"\x1b[1;6H");
+        assert_eq!(editor.selection_range(), Some((0, 4)));
+        assert_eq!((editor.cursor.line, editor.cursor.col), (0, 0));
+        editor.type_bytes(b"\x1b[1;6H");
+        assert_eq!(editor.selection_range(), Some((0, 4)));
+
+        editor.type_bytes(b"\x1b[1;6F");
+        editor.run_command(Command::Cut);
+        assert_eq!(editor.buffer().text(), "  on");
+    }
+
+    #[test]
+    fn test_headless_message_log() {
+        let mut editor = Editor::headless("", 60, 8);
+        editor.run_command(Command::ShowMessages);
+        assert!(editor.screen_snapshot().ends_with("No messages"));
+
+        // An error stays through typing until Escape
+        editor.set_message("Disk full", MessageType::Error);
+        editor.type_bytes(b"a");
+        editor.type_bytes(b"b");
+        assert_eq!(editor.buffer().text(), "ab");
+        let screen = editor.screen_snapshot();
+        assert!(screen.ends_with("Disk full"), "{}", screen);
+        editor.type_bytes(b"\x1b");
+        assert!(!editor.screen_snapshot().contains("Disk full"));
+        assert_eq!(editor.buffer().text(), "ab");
+        // Other messages go with the next key
+        editor.set_message("Saved", MessageType::Info);
+        editor.type_bytes(b"c");
+        assert!(!editor.screen_snapshot().contains("Saved"));
+
+        editor.type_bytes(b"\x1bm"); // Alt+M
+        assert_eq!(editor.buffer().name(), Some(MESSAGES_BUFFER_NAME));
+        assert!(editor.buffer().is_read_only());
+        let log = editor.buffer().text();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", log);
+        assert!(lines[0].ends_with("info     No messages"), "{}", log);
+        assert!(lines[1].ends_with("error    Disk full"), "{}", log);
+        assert!(lines[2].starts_with("  0m ago  info"), "{}", log);
+
+        // Only the newest are kept
+        for i in 0..MAX_LOGGED_MESSAGES {
+            editor.set_message(&i.to_string(), MessageType::Info);
+        }
+        assert_eq!(editor.message_log.len(), MAX_LOGGED_MESSAGES);
+        assert_eq!(editor.message_log[0].2, "0");
+    }
+
+    #[test]
+    fn test_headless_follow_file() {
+        let path =
+            std::env::temp_dir().join(format!("zelux_test_follow_{}.log", std::process::id()));
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let append = |text: &str| {
+            let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            std::io::Write::write_all(&mut file, text.as_bytes()).unwrap();
+        };
+        let mut editor = Editor::headless("", 60, 8);
+        editor.run_command(Command::ToggleFollow);
+        assert!(
+            editor
+                .screen_snapshot()
+                .ends_with("Follow needs a file on disk")
+        );
+
+        editor.buffer = Buffer::from_file(&path).unwrap();
+        editor.type_bytes(b"\x1bf"); // Alt+F
+        assert_eq!((editor.cursor.line, editor.cursor.col), (2, 0));
+        append("three\n");
+        editor.handle_event_for_test(Event::Tick);
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\n");
+        assert!(!editor.buffer().is_modified());
+        assert_eq!(editor.cursor.line, 3);
+        assert!(editor.screen_snapshot().contains("[follow]"));
+
+        // Moving up pauses the view, not the reading
+        editor.type_bytes(b"\x1b[A");
+        append("four\n");
+        editor.handle_event_for_test(Event::Tick);
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\nfour\n");
+        assert_eq!(editor.cursor.line, 2);
+        assert!(editor.screen_snapshot().contains("[follow: paused]"));
+
+        editor.type_bytes(b"\x1bf");
+        append("five\n");
+        editor.handle_event_for_test(Event::Tick);
+        assert_eq!(editor.buffer().text(), "one\ntwo\nthree\nfour\n");
+        assert!(editor.screen_snapshot().ends_with("Stopped following"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_headless_paste_column() {
+        let mut editor = Editor::headless("ab\ncd\n\nx", 40, 8);
+        editor.cursor.set_position(0, 1, &editor.buffer);
+        editor.clipboard = "1\n2\n3\n4\n5\n".to_string();
+        editor.type_bytes(b"\x1by"); // Alt+Y
+        assert_eq!(editor.buffer().text(), "a1b\nc2d\n 3\nx4\n 5");
+        assert_eq!((editor.cursor.line, editor.cursor.col), (4, 2));
+        editor.run_command(Command::Undo);
+        assert_eq!(editor.buffer().text(), "ab\ncd\n\nx");
+
+        // One line pastes as usual
+        editor.clipboard = "12".to_string();
+        editor.run_command(Command::PasteColumn);
+        assert_eq!(editor.buffer().text(), "a12b\ncd\n\nx");
+
+        // Columns are where lines are drawn, past tabs
+        let mut editor = Editor::headless("\tab\ncd\nefghi", 40, 8);
+        editor.cursor.set_position(0, 1, &editor.buffer);
+        editor.clipboard = "1\n2\n3".to_string();
+        editor.run_command(Command::PasteColumn);
+        assert_eq!(editor.buffer().text(), "\t1ab\ncd  2\nefgh3i");
+    }
+
+    #[test]
+    fn test_headless_document_stats() {
+        let row = |screen: &str, label: &str| {
+            let line = screen.lines().find_map(|l| l.split_once(label)).unwrap().1;
+            let numbers = line
+                .split_whitespace()
+                .filter(|w| w.parse::<usize>().is_ok());
+            numbers.collect::<Vec<_>>().join(" ")
+        };
+        let mut editor = Editor::headless("one two\nthree e\u{301}\n", 60, 12);
+        editor.run_command(Command::DocumentStats);
+        let screen = editor.screen_snapshot();
+        assert_eq!(row(&screen, "Lines"), "2");
+        assert_eq!(row(&screen, "Words"), "4");
+        assert_eq!(row(&screen, "Characters"), "17");
+        assert_eq!(row(&screen, "Graphemes"), "16");
+        assert_eq!(row(&screen, "Bytes"), "18");
+
+        // After an edit, with the first line selected
+        editor.type_bytes(b"\x1b");
+        editor.type_bytes(b"zero ");
+        editor.run_command(Command::SelectHome);
+        editor.run_command(Command::DocumentStats);
+        let screen = editor.screen_snapshot();
+        assert!(screen.contains("Selection"), "{}", screen);
+        assert_eq!(row(&screen, "Lines"), "2 1");
+        assert_eq!(row(&screen, "Words"), "5 1");
+        assert_eq!(row(&screen, "Characters"), "22 5");
+    }
+
+    #[test]
+    fn test_stale_diagnostic_inside_character() {
+        let mut editor = Editor::headless("", 60, 6);
+        editor.buffer = Buffer::recovered(Some(PathBuf::from("a.rs")), "é x\n");
+        let diagnostic = |start, end| Diagnostic {
+            start: (0, start),
+            end: (0, end),
+            severity: Severity::Error,
+            message: "stale".to_string(),
+        };
+        editor.diagnostics = vec![(
+            PathBuf::from("a.rs"),
+            vec![diagnostic(1, 1), diagnostic(1, 3), diagnostic(9, 9)],
+        )];
+        // Past the end of the line there is nothing to underline
+        assert_eq!(editor.diagnostic_spans(0, "é x"), [(0, 2), (0, 3)]);
+        assert!(editor.screen_snapshot().contains("é x"));
+    }
+
+    #[test]
+    fn test_headless_blame_shows_whole_age() {
+        let mut editor = Editor::headless("", 60, 6);
+        editor.buffer = Buffer::recovered(Some(PathBuf::from("a.rs")), "one\ntwo\n");
+        let line = |author: &str, days: i64| BlameLine {
+            hash: "0123456789abcdef".to_string(),
+            author: author.to_string(),
+            time: unix_now() - days * 86_400,
+        };
+        editor.blame = Some(vec![line("Alexandra Smith", 3), line("Bo", 60)]);
+        editor.blame_visible = true;
+        let screen = editor.screen_snapshot();
+        let rows: Vec<&str> = screen.lines().collect();
+        assert!(
+            rows[0].starts_with("01234567 Alexandra   3d "),
+            "{}",
+            screen
+        );
+        assert!(
+            rows[1].starts_with("01234567 Bo         2mo "),
+            "{}",
+            screen
+        );
+    }
+
+    #[test]
+    fn test_headless_gutter_selects_lines() {
+        let mut editor = Editor::headless("a\nbb\nccc\n", 40, 8);
+        // A click on the line number of the second line
+        editor.type_bytes(b"\x1b[<0;1;2M");
+        assert_eq!(editor.selection_range(), Some((2, 5)));
+        assert_eq!((editor.cursor.line, editor.cursor.col), (2, 0));
+        // Dragging down takes whole lines, dragging up keeps the first
+        editor.type_bytes(b"\x1b[<32;2;3M");
+        assert_eq!(editor.selection_range(), Some((2, 9)));
+        editor.type_bytes(b"\x1b[<32;2;1M");
+        assert_eq!(editor.selection_range(), Some((0, 5)));
+        assert_eq!((editor.cursor.line, editor.cursor.col), (0, 0));
+        // Past the last line stops at the end of the file
+        editor.type_bytes(b"\x1b[<32;2;6M\x1b[<0;2;6m");
+        assert_eq!(editor.selection_range(), Some((2, 9)));
+        assert_eq!((editor.cursor.line, editor.cursor.col), (3, 0));
+
+        // A click past the end of the file selects nothing
+        editor.type_bytes(b"\x1b[<0;1;6M\x1b[<0;1;6m");
+        assert_eq!(editor.selection_range(), None);
+    }
+
+    #[test]
+    fn test_headless_drag_selection() {
+        let mut editor = Editor::headless("one two three", 40, 6);
+        editor.selection = Some(Selection { anchor: 0, head: 3 });
+        // Grab "one" and drag it before "three": the drop point is marked
+        editor.type_bytes(b"\x1b[<0;6;1M\x1b[<32;13;1M");
+        editor.draw();
+        let cell = editor.screen.cell(0, 12).unwrap();
+        assert_eq!((cell.ch, cell.bg), ('t', Color::Ansi(3)));
+        editor.type_bytes(b"\x1b[<0;13;1m");
+        assert_eq!(editor.buffer().text(), " two onethree");
+        assert_eq!(editor.selection_range(), Some((5, 8)));
+        editor.run_command(Command::Undo);
+        assert_eq!(editor.buffer().text(), "one two three");
+
+        // Ctrl copies instead, here past the end of the buffer
+        editor.selection = Some(Selection { anchor: 0, head: 3 });
+        editor.type_bytes(b"\x1b[<0;6;1M\x1b[<32;30;1M\x1b[<16;30;1m");
+        assert_eq!(editor.buffer().text(), "one two threeone");
+        assert_eq!(editor.selection_range(), Some((13, 16)));
+
+        // Dropped onto itself it is a click
+        editor.type_bytes(b"\x1b[<0;19;1M\x1b[<0;19;1m");
+        assert_eq!(editor.selection_range(), None);
+        assert_eq!(editor.buffer().text(), "one two threeone");
+        assert_eq!((editor.cursor.line, editor.cursor.col), (0, 14));
+    }
+
+    #[test]
+    fn test_headless_drag_panel_header() {
+        let mut editor = Editor::headless("one\n", 60, 14);
+        let entry = |line| QuickfixEntry {
+            path: PathBuf::from("a.rs"),
+            line,
+            col: 1,
+            message: String::new(),
+        };
+        editor.quickfix = Some(QuickfixList::new((1..=10).map(entry).collect()));
+        editor.quickfix_visible = true;
+        // Six entry rows under the header at row 5, above the status rows
+        assert_eq!(editor.text_area_height(), 5);
+        editor.type_bytes(b"\x1b[<0;1;6M");
+        // Up to the top keeps three text rows
+        editor.type_bytes(b"\x1b[<32;1;1M");
+        assert_eq!((editor.quickfix_rows, editor.text_area_height()), (8, 3));
+        // Down past the bottom keeps one entry row
+        editor.type_bytes(b"\x1b[<32;1;14M");
+        assert_eq!(editor.quickfix_rows, 1);
+        editor.type_bytes(b"\x1b[<32;1;9M\x1b[<0;1;9m");
+        assert_eq!(editor.quickfix_rows, 3);
+
+        // The size comes back with the session, fitted to the screen
+        let session = editor.session();
+        assert_eq!(session.panel_rows, Some(3));
+        let mut restored = Editor::headless("", 60, 14);
+        restored.restore_session(&session);
+        assert_eq!(restored.quickfix_rows, 3);
+        let mut small = Editor::headless("", 60, 8);
+        small.restore_session(&Session {
+            panel_rows: Some(20),
+            ..Session::default()
+        });
+        assert_eq!(small.quickfix_rows, 2);
+    }
+
+    #[test]
+    fn test_headless_vi_huge_counts() {
+        let mut editor = Editor::headless("one two\nthree\n", 60, 8);
+        editor.vi = Some(ViState::new());
+        editor.type_bytes(b"99999999999j");
+        assert_eq!(editor.cursor.line, 2);
+        editor.type_bytes(b"gg9999999999d9999999999w");
+        assert_eq!(editor.buffer().text(), "\nthree\n");
+        editor.type_bytes(b"yy99999999999p");
+        assert_eq!(editor.buffer().line_count(), vi::MAX_COUNT + 3);
+    }
+
+    #[test]
+    fn test_headless_buffer_options() {
+        let mut editor = Editor::headless("a\tb\nc\n", 60, 14);
+        editor.run_command(Command::BufferOptions);
+        let screen = editor.screen_snapshot();
+        assert!(screen.contains("Tab width    4"), "{}", screen);
+        assert!(screen.contains("Line ending  LF"), "{}", screen);
+
+        // Typed digits apply as they come; out of range ones are refused
+        editor.type_bytes(b"\x1b[B"); // Down, to the tab width
+        editor.type_bytes(b"2");
+        assert_eq!(editor.tab_width(), 2);
+        editor.type_bytes(b"9");
+        assert_eq!(editor.tab_width(), 2);
+        editor.type_bytes(b"\x1b[C"); // Right
+        assert_eq!(editor.tab_width(), 3);
+        let first = editor.screen_snapshot().lines().next().map(str::to_string);
+        assert_eq!(first.as_deref().map(str::trim), Some("1 a  b"));
+
+        // Toggles change the buffer at once, undoably
+        editor.type_bytes(b"\x1b[B\x1b[B "); // to the line ending
+        assert_eq!(editor.buffer().text(), "a\tb\r\nc\r\n");
+        editor.type_bytes(b"\x1b[B\x1b[B "); // to read-only
+        assert!(editor.buffer().is_read_only());
+        editor.type_bytes(b" \x1b");
+        assert!(editor.options_panel.is_none());
+        editor.run_command(Command::Undo);
+        assert_eq!(editor.buffer().text(), "a\tb\nc\n");
+        assert_eq!(editor.config.tab_width, 4);
+    }
+
+    #[test]
+    fn test_chosen_buffers() {
+        let names = ["src/a.rs", "src/b.rs", "notes.md"].map(String::from);
+        assert_eq!(chosen_buffers("", &names), [0, 1, 2]);
+        assert_eq!(chosen_buffers(" all ", &names), [0, 1, 2]);
+        assert_eq!(chosen_buffers(".rs", &names), [0, 1]);
+        assert_eq!(chosen_buffers("3 a.rs", &names), [0, 2]);
+        assert_eq!(chosen_buffers("9 zz", &names), [] as [usize; 0]);
+    }
+
+    #[test]
+    fn test_headless_replace_in_buffers() {
+        let mut editor = Editor::headless("foo one\n", 80, 6);
+        editor.add_document(Buffer::scratch("second", "foo two foo\n"));
+        editor.add_document(Buffer::scratch("third", "foo three\n"));
+        editor.run_command(Command::ReplaceInBuffers);
+        // Every buffer but the first
+        editor.type_bytes(b"foo\rbar\r2 3\r");
+        assert_eq!(editor.buffer().text(), "bar three\n");
+        assert_eq!(editor.documents[1].buffer.text(), "bar two bar\n");
+        assert_eq!(editor.documents[0].buffer.text(), "foo one\n");
+        let screen = editor.screen_snapshot();
+        assert!(
+            screen.ends_with("Replaced 3 occurrences in 2 buffers: second 2, third 1"),
+            "{}",
+            screen
+        );
+
+        // Undone everywhere at once, unless edited since
+        editor.run_command(Command::UndoReplaceInBuffers);
+        assert_eq!(editor.buffer().text(), "foo three\n");
+        assert_eq!(editor.documents[1].buffer.text(), "foo two foo\n");
+        editor.run_command(Command::ReplaceInBuffers);
+        editor.type_bytes(b"foo\rbar\r\r");
+        editor.type_bytes(b"x");
+        editor.run_command(Command::UndoReplaceInBuffers);
+        assert_eq!(editor.buffer().text(), "xbar three\n");
+        assert_eq!(editor.documents[0].buffer.text(), "foo one\n");
+        assert!(editor.screen_snapshot().ends_with("changed since"));
+    }
+
+    #[test]
+    fn test_headless_rename_in_project() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_rename_ed_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (open, closed) = (dir.join("a.rs"), dir.join("b.rs"));
+        fs::write(&open, "fn foo() {}\n").unwrap();
+        fs::write(&closed, "use foo;\nlet foobar = foo();\n").unwrap();
+
+        // The open file is searched with its unsaved edits
+        let mut editor = Editor::headless("", 80, 12);
+        editor.open_path(&open).unwrap();
+        editor.type_bytes(b"foo();\r");
+        editor.preview_rename(&dir, "foo".to_string(), "bar".to_string());
+        let screen = editor.screen_snapshot();
+        assert!(
+            screen.contains("Rename foo → bar: 4 of 4 lines in 2 files"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("a.rs:1 -foo();"), "{}", screen);
+        assert!(screen.contains("+bar();"), "{}", screen);
+
+        // Reject the second line, then apply
+        editor.type_bytes(b"yn\r");
+        assert_eq!(editor.buffer().text(), "bar();\nfn foo() {}\n");
+        assert_eq!(
+            fs::read_to_string(&closed).unwrap(),
+            "use bar;\nlet foobar = bar();\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("b.rs.bak")).unwrap(),
+            "use foo;\nlet foobar = foo();\n"
+        );
+        assert_eq!(fs::read_to_string(&open).unwrap(), "fn foo() {}\n");
+        let screen = editor.screen_snapshot();
+        assert!(
+            screen.ends_with("Renamed 3 lines in 2 files (1 on disk, originals kept as .bak)"),
+            "{}",
+            screen
+        );
+
+        // One undo takes back the buffer's share
+        editor.run_command(Command::Undo);
+        assert_eq!(editor.buffer().text(), "foo();\nfn foo() {}\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,130 @@
+use crate::highlight;
+use crate::render::{self, Color};
+use crate::terminal::ColorMode;
+use crate::theme::Theme;
+
+// ---------------------------------------------------------------------------
+// Export — the buffer as HTML or ANSI text, with syntax colors
+// ---------------------------------------------------------------------------
+
+/// Split `text` into runs of one color. Runs do not cross line ends.
+fn colored_runs<'a>(text: &'a str, filetype: &str, theme: &Theme) -> Vec<(&'a str, Color)> {
+    let mut runs = Vec::new();
+    let mut in_block = false;
+    for line in text.split_inclusive('\n') {
+        let body = line.strip_suffix('\n').unwrap_or(line);
+        let mut at = 0;
+        for (start, end, token) in highlight::line_tokens(body, filetype, &mut in_block) {
+            if start > at {
+                runs.push((&line[at..start], Color::Default));
+            }
+            runs.push((&line[start..end], theme.token_color(token)));
+            at = end;
+        }
+        if at < line.len() {
+            runs.push((&line[at..], Color::Default));
+        }
+    }
+    runs
+}
+
+/// A standalone HTML page showing `text` in a `<pre>` block, with the
+/// colors inlined as styles.
+pub fn html(text: &str, filetype: &str, title: &str, theme: &Theme) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+    out.push_str("</head>\n<body>\n<pre>");
+    for (run, color) in colored_runs(text, filetype, theme) {
+        match css_color(color) {
+            Some(css) => out.push_str(&format!(
+                "<span style=\"color: {}\">{}</span>",
+                css,
+                escape_html(run)
+            )),
+            None => out.push_str(&escape_html(run)),
+        }
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
+
+/// `text` with terminal color codes, for `less -R`, `cat` and the like.
+/// In `ColorMode::Mono` it is left plain.
+pub fn ansi(text: &str, filetype: &str, theme: &Theme, mode: &ColorMode) -> String {
+    let mut out = Vec::new();
+    for (run, color) in colored_runs(text, filetype, theme) {
+        if color == Color::Default || *mode == ColorMode::Mono {
+            out.extend_from_slice(run.as_bytes());
+            continue;
+        }
+        render::write_fg_color(&mut out, color, mode);
+        out.extend_from_slice(run.as_bytes());
+        render::write_fg_color(&mut out, Color::Default, mode);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn css_color(color: Color) -> Option<String> {
+    let (r, g, b) = match color {
+        Color::Default => return None,
+        Color::Ansi(n) | Color::Color256(n) => render::ansi256_to_rgb(n),
+        Color::Rgb(r, g, b) => (r, g, b),
+    };
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether a file at `path` should get HTML rather than ANSI text.
+pub fn is_html_path(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.ends_with(".html") || lower.ends_with(".htm")
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html() {
+        let page = html("let a = \"<b>\";\n", "rs", "a.rs", &Theme::default());
+        assert!(page.contains("<title>a.rs</title>"));
+        assert!(page.contains(
+            "<pre><span style=\"color: #800080\">let</span> a = \
+             <span style=\"color: #008000\">&quot;&lt;b&gt;&quot;</span>;\n</pre>"
+        ));
+    }
+
+    #[test]
+    fn test_ansi() {
+        let text = "/* a\nb */ x\n";
+        let out = ansi(text, "c", &Theme::default(), &ColorMode::TrueColor);
+        assert_eq!(
+            out,
+            "\x1b[38;5;244m/* a\x1b[39m\n\x1b[38;5;244mb */\x1b[39m x\n"
+        );
+        // Plain text passes through
+        assert_eq!(
+            ansi("hi\n", "txt", &Theme::default(), &ColorMode::TrueColor),
+            "hi\n"
+        );
+        assert_eq!(ansi(text, "c", &Theme::default(), &ColorMode::Mono), text);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// ---------------------------------------------------------------------------
+// File index — the project's files, kept fresh by polling directories
+// ---------------------------------------------------------------------------
+
+/// Directories never listed besides hidden ones: build output.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// Files listed at most, so a huge tree cannot stall the editor.
+const MAX_FILES: usize = 100_000;
+
+/// A directory changed this recently may change again within the same
+/// timestamp tick, unseen; it is listed again on the next refresh.
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// The files under a root, sorted. Creating, deleting or renaming an entry
+/// touches its directory's modification time, so a refresh costs a stat
+/// per directory and lists only the directories that changed, which works
+/// anywhere without inotify.
+#[derive(Debug)]
+pub struct FileIndex {
+    root: PathBuf,
+    /// Each directory's modification time when it was listed; None while
+    /// it has not settled.
+    dirs: HashMap<PathBuf, Option<SystemTime>>,
+    files: BTreeSet<PathBuf>,
+}
+
+impl FileIndex {
+    /// List the files under `root`: hidden entries and build output
+    /// directories are skipped, and so are symlinks, so a link cycle
+    /// cannot trap the walk.
+    pub fn scan(root: &Path) -> FileIndex {
+        let mut index = FileIndex {
+            root: root.to_path_buf(),
+            dirs: HashMap::new(),
+            files: BTreeSet::new(),
+        };
+        index.add_tree(root);
+        index
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(PathBuf::as_path)
+    }
+
+    /// List again the directories that changed since they were last
+    /// listed. True when files came or went.
+    pub fn refresh(&mut self) -> bool {
+        let changed: Vec<PathBuf> = self
+            .dirs
+            .iter()
+            .filter(|&(dir, &seen)| seen.is_none() || modified(dir) != seen)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        let mut any = false;
+        for dir in changed {
+            // Gone with a parent listed before it
+            if self.dirs.contains_key(&dir) {
+                any |= self.relist(&dir);
+            }
+        }
+        any
+    }
+
+    /// Walk the tree at `dir`, adding its directories and files.
+    fn add_tree(&mut self, dir: &Path) -> bool {
+        let mut any = false;
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let (files, subdirs) = self.list(&dir);
+            for file in files {
+                any |= self.files.insert(file);
+            }
+            pending.extend(subdirs);
+        }
+        any
+    }
+
+    /// List `dir` again: its files are replaced, new subdirectories walked
+    /// and vanished ones forgotten with everything under them.
+    fn relist(&mut self, dir: &Path) -> bool {
+        if !dir.is_dir() {
+            return self.remove_tree(dir);
+        }
+        let (files, subdirs) = self.list(dir);
+        let before = self.files.len();
+        self.files
+            .retain(|f| f.parent() != Some(dir) || files.contains(f));
+        let mut any = self.files.len() != before;
+        for file in files {
+            any |= self.files.insert(file);
+        }
+        let gone: Vec<PathBuf> = self
+            .dirs
+            .keys()
+            .filter(|d| d.parent() == Some(dir) && !subdirs.contains(d))
+            .cloned()
+            .collect();
+        for sub in gone {
+            any |= self.remove_tree(&sub);
+        }
+        for sub in subdirs {
+            if !self.dirs.contains_key(&sub) {
+                any |= self.add_tree(&sub);
+            }
+        }
+        any
+    }
+
+    fn remove_tree(&mut self, dir: &Path) -> bool {
+        self.dirs.retain(|d, _| !d.starts_with(dir));
+        let before = self.files.len();
+        self.files.retain(|f| !f.starts_with(dir));
+        self.files.len() != before
+    }
+
+    /// Record `dir`'s time and return its listed files and subdirectories.
+    fn list(&mut self, dir: &Path) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        // Taken before reading, so a change during it is seen next time
+        let time = modified(dir).filter(|t| t.elapsed().is_ok_and(|age| age >= SETTLE));
+        self.dirs.insert(dir.to_path_buf(), time);
+        let (mut files, mut subdirs) = (Vec::new(), Vec::new());
+        let Ok(entries) = fs::read_dir(dir) else {
+            return (files, subdirs);
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if name.starts_with('.') || kind.is_symlink() {
+                continue;
+            }
+            if kind.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    subdirs.push(entry.path());
+                }
+            } else if kind.is_file() && self.files.len() + files.len() < MAX_FILES {
+                files.push(entry.path());
+            }
+        }
+        (files, subdirs)
+    }
+}
+
+fn modified(dir: &Path) -> Option<SystemTime> {
+    fs::metadata(dir).and_then(|m| m.modified()).ok()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(index: &FileIndex) -> Vec<String> {
+        index
+            .files()
+            .map(|f| f.strip_prefix(index.root()).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_refresh() {
+        let root = std::env::temp_dir().join(format!("zelux_test_index_{}", std::process::id()));
+        fs::create_dir_all(root.join("src/old")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join("src/old/b.rs"), "").unwrap();
+        fs::write(root.join(".git/HEAD"), "").unwrap();
+        let mut index = FileIndex::scan(&root);
+        assert_eq!(names(&index), ["a.txt", "src/old/b.rs"]);
+        // Fresh directories are unsettled: listing them again finds no change
+        assert!(!index.refresh());
+
+        fs::write(root.join("src/c.rs"), "").unwrap();
+        fs::create_dir_all(root.join("src/new")).unwrap();
+        fs::write(root.join("src/new/d.rs"), "").unwrap();
+        fs::remove_dir_all(root.join("src/old")).unwrap();
+        fs::remove_file(root.join("a.txt")).unwrap();
+        assert!(index.refresh());
+        assert_eq!(names(&index), ["src/c.rs", "src/new/d.rs"]);
+        assert!(!index.dirs.contains_key(&root.join("src/old")));
+
+        // A settled directory whose time has not moved is not listed
+        fs::write(root.join("unseen"), "").unwrap();
+        let dirs: Vec<PathBuf> = index.dirs.keys().cloned().collect();
+        for dir in dirs {
+            index.dirs.insert(dir.clone(), modified(&dir));
+        }
+        assert!(!index.refresh());
+        assert_eq!(names(&index), ["src/c.rs", "src/new/d.rs"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::Path;
+
+// ---------------------------------------------------------------------------
+// Filetype — detection and per-language syntax facts
+// ---------------------------------------------------------------------------
+
+/// Lines at either end of a file searched for a mode line.
+const MODELINE_LINES: usize = 5;
+
+/// The filetype of a buffer: its lowercased extension, or "text".
+pub fn detect(path: Option<&Path>) -> String {
+    extension(path).unwrap_or_else(|| "text".to_string())
+}
+
+/// The filetype of a buffer whose text starts with `head` and ends with
+/// `tail`: the one a mode line names, else the extension, else the
+/// shebang's interpreter, else a guess from the contents, else "text".
+pub fn detect_in(path: Option<&Path>, head: &str, tail: &str) -> String {
+    modeline(head, tail)
+        .or_else(|| extension(path))
+        .or_else(|| shebang(head))
+        .or_else(|| guess(head))
+        .unwrap_or_else(|| "text".to_string())
+}
+
+fn extension(path: Option<&Path>) -> Option<String> {
+    path.and_then(|p| p.extension())
+        .map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/// Filetypes by language, mode or interpreter name, for names that are not
+/// the filetype themselves.
+#[rustfmt::skip]
+const NAMES: &[(&str, &str)] = &[
+    ("python", "py"), ("ruby", "rb"), ("perl", "pl"), ("node", "js"), ("nodejs", "js"),
+    ("javascript", "js"), ("typescript", "ts"), ("rust", "rs"), ("dash", "sh"),
+    ("ksh", "sh"), ("shell-script", "sh"), ("c++", "cpp"), ("emacs-lisp", "el"),
+    ("scheme", "scm"), ("haskell", "hs"), ("runhaskell", "hs"), ("markdown", "md"),
+    ("latex", "tex"), ("kotlin", "kt"), ("csharp", "cs"),
+];
+
+/// The filetype for a language `name` like "Python" or "rust".
+fn from_name(name: &str) -> Option<String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    Some(match NAMES.iter().find(|(n, _)| *n == name) {
+        Some((_, filetype)) => filetype.to_string(),
+        None => name,
+    })
+}
+
+/// The filetype from the interpreter on a `#!` first line, looking past
+/// `env` and its options: `#!/usr/bin/env -S python3 -u` is "py".
+fn shebang(head: &str) -> Option<String> {
+    let line = head.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    // python3.12 and lua5.4 are python and lua
+    from_name(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+/// The filetype an Emacs `-*- mode: python -*-` line or a Vim
+/// `vim: set ft=python:` line names, looked for in the first and last few
+/// lines.
+fn modeline(head: &str, tail: &str) -> Option<String> {
+    let mut first = head.lines();
+    let emacs = match first.next() {
+        Some(line) if line.starts_with("#!") => first.next(),
+        line => line,
+    };
+    if let Some(filetype) = emacs.and_then(emacs_mode) {
+        return Some(filetype);
+    }
+    head.lines()
+        .take(MODELINE_LINES)
+        .chain(tail.lines().rev().take(MODELINE_LINES))
+        .find_map(vim_filetype)
+}
+
+fn emacs_mode(line: &str) -> Option<String> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    if !vars.contains(':') {
+        return from_name(vars);
+    }
+    vars.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| from_name(value))?
+    })
+}
+
+fn vim_filetype(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:", "Vim:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|&(i, _)| {
+                line[..i]
+                    .chars()
+                    .next_back()
+                    .is_none_or(char::is_whitespace)
+            })
+            .map(|(i, m)| i + m.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| {
+            option
+                .strip_prefix("ft=")
+                .or_else(|| option.strip_prefix("filetype="))
+        })
+        .and_then(from_name)
+}
+
+/// A guess from how the text starts: an XML declaration, an HTML page, a
+/// JSON object or array, or a diff as `git diff` prints it.
+fn guess(head: &str) -> Option<String> {
+    let line = head
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .lines()
+        .next()?;
+    let lower = line.to_ascii_lowercase();
+    let filetype = if line.starts_with("<?xml") {
+        "xml"
+    } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        "html"
+    } else if line.starts_with("diff --git ") {
+        "diff"
+    } else if let Some(rest) = line.strip_prefix('{') {
+        let rest = rest.trim_start();
+        if !(rest.is_empty() || rest.starts_with(['"', '}'])) {
+            return None;
+        }
+        "json"
+    } else if let Some(rest) = line.strip_prefix('[') {
+        let rest = rest.trim_start();
+        if !(rest.is_empty() || rest.starts_with(['"', '{', '[', ']'])) {
+            return None;
+        }
+        "json"
+    } else {
+        return None;
+    };
+    Some(filetype.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommentSyntax {
+    /// Marker that comments out the rest of a line, e.g. `//`.
+    pub line: Option<&'static str>,
+    /// Block comment delimiters, e.g. (`/*`, `*/`).
+    pub block: Option<(&'static str, &'static str)>,
+}
+
+const fn line(marker: &'static str) -> CommentSyntax {
+    CommentSyntax {
+        line: Some(marker),
+        block: None,
+    }
+}
+
+const fn block(open: &'static str, close: &'static str) -> CommentSyntax {
+    CommentSyntax {
+        line: None,
+        block: Some((open, close)),
+    }
+}
+
+const C_LIKE: CommentSyntax = CommentSyntax {
+    line: Some("//"),
+    block: Some(("/*", "*/")),
+};
+
+/// Comment syntax by filetype.
+#[rustfmt::skip]
+const COMMENTS: &[(&[&str], CommentSyntax)] = &[
+    (&["rs", "c", "h", "cc", "cpp", "hpp", "js", "jsx", "ts", "tsx", "mjs", "go", "java",
+       "swift", "kt", "cs", "scala", "dart", "zig"], C_LIKE),
+    (&["py", "sh", "bash", "zsh", "rb", "toml", "yaml", "yml", "pl", "r", "conf"], line("#")),
+    (&["lua", "sql", "hs"], line("--")),
+    (&["lisp", "el", "clj", "scm"], line(";")),
+    (&["tex"], line("%")),
+    (&["vim"], line("\"")),
+    (&["css"], block("/*", "*/")),
+    (&["html", "htm", "xml", "svg"], block("<!--", "-->")),
+];
+
+/// How `filetype` writes comments; None for prose and unknown types.
+pub fn comment_syntax(filetype: &str) -> Option<CommentSyntax> {
+    COMMENTS
+        .iter()
+        .find(|(types, _)| types.contains(&filetype))
+        .map(|&(_, syntax)| syntax)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(Some(Path::new("src/main.RS"))), "rs");
+        assert_eq!(detect(Some(Path::new("Makefile"))), "text");
+        assert_eq!(detect(None), "text");
+    }
+
+    #[test]
+    fn test_detect_shebang() {
+        let script = Some(Path::new("bin/run"));
+        assert_eq!(detect_in(script, "#!/usr/bin/env python3\n", ""), "py");
+        assert_eq!(
+            detect_in(script, "#!/usr/bin/env -S node --harmony\n", ""),
+            "js"
+        );
+        assert_eq!(detect_in(script, "#!/bin/bash -e\n", ""), "bash");
+        assert_eq!(detect_in(script, "#!/usr/bin/lua5.4\n", ""), "lua");
+        // The extension wins over the shebang
+        assert_eq!(
+            detect_in(Some(Path::new("a.sh")), "#!/usr/bin/python\n", ""),
+            "sh"
+        );
+        assert_eq!(detect_in(script, "echo hi\n", ""), "text");
+    }
+
+    #[test]
+    fn test_detect_modelines() {
+        assert_eq!(
+            detect_in(None, "# -*- mode: Ruby; coding: utf-8 -*-\n", ""),
+            "rb"
+        );
+        assert_eq!(detect_in(None, "#!/bin/sh\n# -*- perl -*-\n", ""), "pl");
+        assert_eq!(detect_in(None, "", "x\n# vim: set ft=python ts=4:\n"), "py");
+        assert_eq!(detect_in(None, "// vim: filetype=rust\n", ""), "rs");
+        // A mode line wins over the extension
+        assert_eq!(detect_in(Some(Path::new("a.txt")), "", "vi: ft=sh\n"), "sh");
+        assert_eq!(detect_in(None, "the envim: ft=c\n", ""), "text");
+    }
+
+    #[test]
+    fn test_detect_contents() {
+        assert_eq!(detect_in(None, "<?xml version=\"1.0\"?>\n<a/>", ""), "xml");
+        assert_eq!(detect_in(None, "\n<!DOCTYPE html>\n", ""), "html");
+        assert_eq!(detect_in(None, "{\n  \"a\": 1\n}\n", ""), "json");
+        assert_eq!(detect_in(None, "[{\"a\": 1}]", ""), "json");
+        assert_eq!(detect_in(None, "[section]\nkey = 1\n", ""), "text");
+        assert_eq!(detect_in(None, "{ a b }", ""), "text");
+        assert_eq!(detect_in(None, "diff --git a/x b/x\n", ""), "diff");
+    }
+
+    #[test]
+    fn test_comment_syntax() {
+        let rs = comment_syntax("rs").unwrap();
+        assert_eq!(rs.line, Some("//"));
+        assert_eq!(rs.block, Some(("/*", "*/")));
+        assert_eq!(comment_syntax("html").unwrap().line, None);
+        assert_eq!(comment_syntax("md"), None);
+    }
+}
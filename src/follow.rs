@@ -0,0 +1,107 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// ---------------------------------------------------------------------------
+// Follow — tail -f for a buffer's file
+// ---------------------------------------------------------------------------
+
+/// What happened to the followed file since it was last read.
+#[derive(Debug, PartialEq)]
+pub enum Growth {
+    Unchanged,
+    /// Text added at the end.
+    Appended(String),
+    /// The file shrank (truncated or rotated); its whole new contents.
+    Replaced(String),
+}
+
+/// Watches a file for data written after the part already loaded.
+pub struct Follow {
+    path: PathBuf,
+    /// Bytes of the file already loaded.
+    len: u64,
+}
+
+impl Follow {
+    /// Follow `path`, whose first `len` bytes are already loaded.
+    pub fn new(path: &Path, len: u64) -> Self {
+        Follow {
+            path: path.to_path_buf(),
+            len,
+        }
+    }
+
+    /// Look for changes to the file. A character split across writes is
+    /// left for the next call.
+    pub fn poll(&mut self) -> Result<Growth, String> {
+        let len = fs::metadata(&self.path)
+            .map_err(|e| format!("Failed to read file: {}", e))?
+            .len();
+        if len == self.len {
+            return Ok(Growth::Unchanged);
+        }
+        if len < self.len {
+            let bytes = fs::read(&self.path).map_err(|e| format!("Failed to read file: {}", e))?;
+            self.len = bytes.len() as u64;
+            return Ok(Growth::Replaced(
+                String::from_utf8_lossy(&bytes).into_owned(),
+            ));
+        }
+        let mut bytes = Vec::new();
+        File::open(&self.path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(self.len))?;
+                file.read_to_end(&mut bytes)
+            })
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        if let Err(e) = std::str::from_utf8(&bytes)
+            && e.error_len().is_none()
+        {
+            bytes.truncate(e.valid_up_to());
+        }
+        self.len += bytes.len() as u64;
+        if bytes.is_empty() {
+            return Ok(Growth::Unchanged);
+        }
+        Ok(Growth::Appended(
+            String::from_utf8_lossy(&bytes).into_owned(),
+        ))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_poll_follows_growth() {
+        let path = std::env::temp_dir().join("zelux_test_follow.log");
+        fs::write(&path, "one\n").unwrap();
+        let mut follow = Follow::new(&path, 4);
+        assert_eq!(follow.poll(), Ok(Growth::Unchanged));
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"two\n").unwrap();
+        assert_eq!(follow.poll(), Ok(Growth::Appended("two\n".to_string())));
+
+        // Half of "é" waits for the rest
+        file.write_all(b"caf\xc3").unwrap();
+        assert_eq!(follow.poll(), Ok(Growth::Appended("caf".to_string())));
+        file.write_all(b"\xa9\n").unwrap();
+        assert_eq!(follow.poll(), Ok(Growth::Appended("é\n".to_string())));
+
+        // Truncated: start over
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(follow.poll(), Ok(Growth::Replaced("new\n".to_string())));
+        assert_eq!(follow.poll(), Ok(Growth::Unchanged));
+
+        fs::remove_file(&path).unwrap();
+        assert!(follow.poll().is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::bench::Rng;
+
+// ---------------------------------------------------------------------------
+// Fuzz — random-input harness for the `fuzz_` tests
+// ---------------------------------------------------------------------------
+//
+// Modules that take untrusted input fuzz it in tests named `fuzz_*`, which
+// run a short round with every `cargo test`. For a longer run, or to replay a
+// failure, set the number of cases or the failing seed:
+//
+//     ZELUX_FUZZ_CASES=1000000 cargo test --release fuzz_
+//     ZELUX_FUZZ_SEED=12345 cargo test fuzz_
+
+/// Cases per fuzz test when `ZELUX_FUZZ_CASES` is unset.
+const DEFAULT_CASES: u64 = 500;
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Run `case` once per seed, each with its own generator. A panicking case
+/// names its seed before the test fails, so it can be replayed alone.
+pub fn run(case: impl Fn(&mut Rng)) {
+    let seeds = match env_number("ZELUX_FUZZ_SEED") {
+        Some(seed) => seed..seed + 1,
+        None => 1..1 + env_number("ZELUX_FUZZ_CASES").unwrap_or(DEFAULT_CASES),
+    };
+    for seed in seeds {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| case(&mut Rng::new(seed))));
+        if let Err(e) = result {
+            eprintln!("fuzz case failed; replay with ZELUX_FUZZ_SEED={}", seed);
+            panic::resume_unwind(e);
+        }
+    }
+}
+
+/// Up to `max_len` bytes, most drawn from `alphabet` so the input hits the
+/// interesting cases more often than uniform noise would.
+pub fn bytes(rng: &mut Rng, max_len: usize, alphabet: &[u8]) -> Vec<u8> {
+    let len = rng.below(max_len + 1);
+    (0..len)
+        .map(|_| match rng.below(4) {
+            0 => rng.below(256) as u8,
+            _ => alphabet[rng.below(alphabet.len())],
+        })
+        .collect()
+}
+
+/// Up to `max_len` characters of text mixing ASCII, newlines, CRs and
+/// multi-byte characters.
+pub fn text(rng: &mut Rng, max_len: usize) -> String {
+    const CHARS: &[char] = &['a', 'b', ' ', '\n', '\r', '\t', 'é', 'ñ', '日', '本', '😀'];
+    let len = rng.below(max_len + 1);
+    (0..len).map(|_| CHARS[rng.below(CHARS.len())]).collect()
+}
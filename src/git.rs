@@ -0,0 +1,584 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+// ---------------------------------------------------------------------------
+// Hunks from `git diff -U0`
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Added,
+    Modified,
+    Deleted, // lines were removed just below this one
+}
+
+/// A hunk together with its body lines (each still carrying its `+`, `-` or
+/// `\` prefix), as needed to stage or revert it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub hunk: Hunk,
+    pub lines: Vec<String>,
+}
+
+impl DiffHunk {
+    /// First 0-based buffer line the hunk is shown on (for a pure deletion,
+    /// the line its marker is attached to).
+    pub fn first_line(&self) -> usize {
+        self.hunk.new_start.saturating_sub(1)
+    }
+
+    /// True when 0-based `line` is covered by this hunk's markers.
+    pub fn contains_line(&self, line: usize) -> bool {
+        let first = self.first_line();
+        line >= first && line < first + self.hunk.new_count.max(1)
+    }
+
+    /// The removed (index-side) text, with newlines restored. A trailing
+    /// `\ No newline at end of file` marker drops the last newline.
+    pub fn old_text(&self) -> String {
+        let mut text = String::new();
+        let mut last_was_old = false;
+        for line in &self.lines {
+            if let Some(old) = line.strip_prefix('-') {
+                text.push_str(old);
+                text.push('\n');
+                last_was_old = true;
+            } else if line.starts_with('\\') {
+                if last_was_old {
+                    text.pop();
+                }
+            } else {
+                last_was_old = false;
+            }
+        }
+        text
+    }
+}
+
+/// Split a unified diff of one file into its header (everything before the
+/// first `@@`) and hunks.
+pub fn parse_diff(diff: &str) -> (String, Vec<DiffHunk>) {
+    let mut header = String::new();
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    for line in diff.lines() {
+        if let Some(hunk) = parse_hunk_header(line) {
+            hunks.push(DiffHunk {
+                hunk,
+                lines: Vec::new(),
+            });
+        } else if let Some(current) = hunks.last_mut() {
+            current.lines.push(line.to_string());
+        } else {
+            header.push_str(line);
+            header.push('\n');
+        }
+    }
+    (header, hunks)
+}
+
+/// Parse the `@@ -a,b +c,d @@` headers of a unified diff.
+pub fn parse_hunks(diff: &str) -> Vec<Hunk> {
+    diff.lines().filter_map(parse_hunk_header).collect()
+}
+
+/// Build a patch that applies exactly one hunk, for `git apply --unidiff-zero`.
+pub fn hunk_patch(header: &str, hunk: &DiffHunk) -> String {
+    let h = &hunk.hunk;
+    let mut patch = format!(
+        "{}@@ -{},{} +{},{} @@\n",
+        header, h.old_start, h.old_count, h.new_start, h.new_count
+    );
+    for line in &hunk.lines {
+        patch.push_str(line);
+        patch.push('\n');
+    }
+    patch
+}
+
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (old, rest) = rest.split_once(" +")?;
+    let (new, _) = rest.split_once(" @@")?;
+    let (old_start, old_count) = parse_range(old)?;
+    let (new_start, new_count) = parse_range(new)?;
+    Some(Hunk {
+        old_start,
+        old_count,
+        new_start,
+        new_count,
+    })
+}
+
+/// Parse "start,count" or "start" (count defaults to 1).
+fn parse_range(s: &str) -> Option<(usize, usize)> {
+    match s.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((s.parse().ok()?, 1)),
+    }
+}
+
+/// Expand hunks into a per-line (0-based) change marker table.
+pub fn line_markers(hunks: &[Hunk]) -> Vec<Option<LineChange>> {
+    let mut markers: Vec<Option<LineChange>> = Vec::new();
+    let mut mark = |line: usize, change: LineChange| {
+        if markers.len() <= line {
+            markers.resize(line + 1, None);
+        }
+        markers[line] = Some(change);
+    };
+
+    for h in hunks {
+        if h.new_count == 0 {
+            // Pure deletion: `+c,0` means "after line c" (1-based)
+            mark(h.new_start.saturating_sub(1), LineChange::Deleted);
+        } else {
+            let change = if h.old_count == 0 {
+                LineChange::Added
+            } else {
+                LineChange::Modified
+            };
+            for line in h.new_start..h.new_start + h.new_count {
+                mark(line - 1, change);
+            }
+        }
+    }
+    markers
+}
+
+// ---------------------------------------------------------------------------
+// GitGutter — change markers for one file, plus staleness tracking
+// ---------------------------------------------------------------------------
+
+pub struct GitGutter {
+    markers: Vec<Option<LineChange>>,
+    header: String,
+    hunks: Vec<DiffHunk>,
+    toplevel: PathBuf,
+    branch: Option<String>,
+    // Files whose modification would invalidate the markers (the file itself
+    // and the repository index), with the mtime seen at load time.
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl GitGutter {
+    /// Diff `path` against the index. Returns `None` when the file is not
+    /// inside a git work tree (or git is unavailable).
+    pub fn load(path: &Path) -> Option<GitGutter> {
+        let dir = parent_dir(path);
+        let file_name = path.file_name()?;
+
+        let info = git_output(&dir, &["rev-parse", "--show-toplevel", "--git-dir"])?;
+        let mut info = info.lines();
+        let toplevel = PathBuf::from(info.next()?);
+        let git_dir = dir.join(info.next()?.trim());
+        let index = git_dir.join("index");
+        let branch = fs::read_to_string(git_dir.join("HEAD"))
+            .ok()
+            .and_then(|head| parse_head(&head));
+
+        let mut args = vec!["diff", "--no-color", "-U0", "--"];
+        let name = file_name.to_string_lossy();
+        args.push(&name);
+        let diff = git_output(&dir, &args)?;
+
+        let watched = [path.to_path_buf(), index]
+            .into_iter()
+            .map(|p| {
+                let mtime = mtime(&p);
+                (p, mtime)
+            })
+            .collect();
+
+        let (header, hunks) = parse_diff(&diff);
+        Some(GitGutter {
+            markers: line_markers(&parse_hunks(&diff)),
+            header,
+            hunks,
+            toplevel,
+            branch,
+            watched,
+        })
+    }
+
+    /// The checked-out branch, or the short commit hash when detached.
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
+    pub fn marker(&self, line: usize) -> Option<LineChange> {
+        self.markers.get(line).copied().flatten()
+    }
+
+    /// True when the file or the index changed on disk since `load`.
+    pub fn is_stale(&self) -> bool {
+        self.watched.iter().any(|(p, seen)| mtime(p) != *seen)
+    }
+
+    pub fn hunk_at(&self, line: usize) -> Option<&DiffHunk> {
+        self.hunks.iter().find(|h| h.contains_line(line))
+    }
+
+    /// The first hunk starting below `line`, wrapping to the top.
+    pub fn next_hunk(&self, line: usize) -> Option<&DiffHunk> {
+        self.hunks
+            .iter()
+            .find(|h| h.first_line() > line)
+            .or_else(|| self.hunks.first())
+    }
+
+    /// The last hunk starting above `line`, wrapping to the bottom.
+    pub fn prev_hunk(&self, line: usize) -> Option<&DiffHunk> {
+        self.hunks
+            .iter()
+            .rev()
+            .find(|h| h.first_line() < line)
+            .or_else(|| self.hunks.last())
+    }
+
+    /// Add one hunk's changes to the index.
+    pub fn stage(&self, hunk: &DiffHunk) -> Result<(), String> {
+        let patch = hunk_patch(&self.header, hunk);
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(&self.toplevel)
+            .args(["apply", "--cached", "--unidiff-zero", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run git: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(patch.as_bytes())
+                .map_err(|e| format!("Failed to write patch: {}", e))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("Failed to run git: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let err = String::from_utf8_lossy(&output.stderr);
+            Err(format!("git apply failed: {}", err.trim()))
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Blame
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub hash: String,
+    pub author: String,
+    pub time: i64, // author time, seconds since the Unix epoch
+}
+
+impl BlameLine {
+    /// Lines that are only in the working tree get an all-zero hash.
+    pub fn is_uncommitted(&self) -> bool {
+        self.hash.bytes().all(|b| b == b'0')
+    }
+
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(8)]
+    }
+}
+
+/// Run `git blame --porcelain` on the saved file. Entry `i` describes line `i`.
+pub fn blame(path: &Path) -> Result<Vec<BlameLine>, String> {
+    let dir = parent_dir(path);
+    let name = path
+        .file_name()
+        .ok_or_else(|| "No file name".to_string())?
+        .to_string_lossy()
+        .into_owned();
+    let text = git_output(&dir, &["blame", "--porcelain", "--", &name])
+        .ok_or_else(|| "git blame failed (file not tracked?)".to_string())?;
+    Ok(parse_blame_porcelain(&text))
+}
+
+/// Parse `git blame --porcelain` output. Commit details are only printed the
+/// first time a commit appears, so they are remembered per hash.
+pub fn parse_blame_porcelain(text: &str) -> Vec<BlameLine> {
+    let mut commits: Vec<(String, String, i64)> = Vec::new(); // (hash, author, time)
+    let mut lines: Vec<BlameLine> = Vec::new();
+    let mut current: Option<(String, usize)> = None; // (hash, final line, 1-based)
+
+    for line in text.lines() {
+        if line.starts_with('\t') {
+            if let Some((hash, final_line)) = current.take() {
+                let (author, time) = commits
+                    .iter()
+                    .find(|(h, _, _)| *h == hash)
+                    .map(|(_, a, t)| (a.clone(), *t))
+                    .unwrap_or_default();
+                let idx = final_line.saturating_sub(1);
+                if lines.len() <= idx {
+                    lines.resize(
+                        idx + 1,
+                        BlameLine {
+                            hash: String::new(),
+                            author: String::new(),
+                            time: 0,
+                        },
+                    );
+                }
+                lines[idx] = BlameLine { hash, author, time };
+            }
+            continue;
+        }
+
+        let mut parts = line.splitn(2, ' ');
+        let key = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("");
+
+        if key.len() == 40 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            let final_line = value
+                .split(' ')
+                .nth(1)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+            if !commits.iter().any(|(h, _, _)| h == key) {
+                commits.push((key.to_string(), String::new(), 0));
+            }
+            current = Some((key.to_string(), final_line));
+            continue;
+        }
+
+        let hash = match current {
+            Some((ref h, _)) => h,
+            None => continue,
+        };
+        if let Some(entry) = commits.iter_mut().find(|(h, _, _)| h == hash) {
+            match key {
+                "author" => entry.1 = value.to_string(),
+                "author-time" => entry.2 = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+    }
+    lines
+}
+
+/// Compact age like "5m", "3h", "2d", "4mo", "1y".
+pub fn format_age(seconds: i64) -> String {
+    const MINUTE: i64 = 60;
+    const HOUR: i64 = 60 * MINUTE;
+    const DAY: i64 = 24 * HOUR;
+    let s = seconds.max(0);
+    if s < HOUR {
+        format!("{}m", s / MINUTE)
+    } else if s < DAY {
+        format!("{}h", s / HOUR)
+    } else if s < 30 * DAY {
+        format!("{}d", s / DAY)
+    } else if s < 365 * DAY {
+        format!("{}mo", s / (30 * DAY))
+    } else {
+        format!("{}y", s / (365 * DAY))
+    }
+}
+
+/// Branch name from the contents of `.git/HEAD` (`ref: refs/heads/main`),
+/// or the abbreviated hash of a detached HEAD.
+fn parse_head(head: &str) -> Option<String> {
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        ),
+        None => head.get(..7).map(str::to_string),
+    }
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Run `git -C dir <args>` and return stdout on success.
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hunk_headers() {
+        let diff = "diff --git a/f b/f\n\
+                    --- a/f\n\
+                    +++ b/f\n\
+                    @@ -3 +3 @@ fn main() {\n\
+                    -old\n\
+                    +new\n\
+                    @@ -10,0 +11,2 @@\n\
+                    +a\n\
+                    +b\n\
+                    @@ -20,3 +22,0 @@\n";
+        let hunks = parse_hunks(diff);
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    old_start: 3,
+                    old_count: 1,
+                    new_start: 3,
+                    new_count: 1
+                },
+                Hunk {
+                    old_start: 10,
+                    old_count: 0,
+                    new_start: 11,
+                    new_count: 2
+                },
+                Hunk {
+                    old_start: 20,
+                    old_count: 3,
+                    new_start: 22,
+                    new_count: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_markers() {
+        let hunks = parse_hunks("@@ -3 +3 @@\n@@ -10,0 +11,2 @@\n@@ -20,3 +22,0 @@\n");
+        let markers = line_markers(&hunks);
+        assert_eq!(markers[2], Some(LineChange::Modified));
+        assert_eq!(markers[10], Some(LineChange::Added));
+        assert_eq!(markers[11], Some(LineChange::Added));
+        assert_eq!(markers[21], Some(LineChange::Deleted));
+        assert_eq!(markers[0], None);
+    }
+
+    #[test]
+    fn test_parse_diff_keeps_bodies() {
+        let diff = "diff --git a/f b/f\n\
+                    --- a/f\n\
+                    +++ b/f\n\
+                    @@ -2 +2 @@\n\
+                    -old\n\
+                    +new\n\
+                    @@ -5,2 +4,0 @@\n\
+                    -gone1\n\
+                    -gone2\n";
+        let (header, hunks) = parse_diff(diff);
+        assert_eq!(header, "diff --git a/f b/f\n--- a/f\n+++ b/f\n");
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].lines, vec!["-old", "+new"]);
+        assert_eq!(hunks[0].old_text(), "old\n");
+        assert_eq!(hunks[1].old_text(), "gone1\ngone2\n");
+        assert!(hunks[0].contains_line(1));
+        assert!(!hunks[0].contains_line(2));
+        assert!(hunks[1].contains_line(3));
+
+        let patch = hunk_patch(&header, &hunks[1]);
+        assert!(patch.ends_with("@@ -5,2 +4,0 @@\n-gone1\n-gone2\n"));
+    }
+
+    #[test]
+    fn test_old_text_without_final_newline() {
+        let (_, hunks) = parse_diff("@@ -3 +3 @@\n-last\n\\ No newline at end of file\n+last!\n");
+        assert_eq!(hunks[0].old_text(), "last");
+    }
+
+    #[test]
+    fn test_parse_blame_porcelain() {
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let text = format!(
+            "{a} 1 1 2\n\
+             author Alice\n\
+             author-time 1000\n\
+             summary first\n\
+             filename f.rs\n\
+             \tline one\n\
+             {a} 2 2\n\
+             \tline two\n\
+             {b} 3 3 1\n\
+             author Bob\n\
+             author-time 2000\n\
+             filename f.rs\n\
+             \tline three\n"
+        );
+        let lines = parse_blame_porcelain(&text);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].author, "Alice");
+        assert_eq!(lines[1].author, "Alice");
+        assert_eq!(lines[1].time, 1000);
+        assert_eq!(lines[2].author, "Bob");
+        assert_eq!(lines[2].short_hash(), "bbbbbbbb");
+        assert!(!lines[2].is_uncommitted());
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(30), "0m");
+        assert_eq!(format_age(90 * 60), "1h");
+        assert_eq!(format_age(3 * 86400), "3d");
+        assert_eq!(format_age(65 * 86400), "2mo");
+        assert_eq!(format_age(800 * 86400), "2y");
+    }
+
+    #[test]
+    fn test_deletion_at_top_of_file() {
+        let markers = line_markers(&parse_hunks("@@ -1,2 +0,0 @@\n"));
+        assert_eq!(markers[0], Some(LineChange::Deleted));
+    }
+
+    #[test]
+    fn test_parse_head() {
+        assert_eq!(
+            parse_head("ref: refs/heads/main\n"),
+            Some("main".to_string())
+        );
+        assert_eq!(
+            parse_head("ref: refs/heads/feature/x"),
+            Some("feature/x".to_string())
+        );
+        assert_eq!(
+            parse_head("b5cf153c0ffee0000000000000000000000000000\n"),
+            Some("b5cf153".to_string())
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::filetype::{self, CommentSyntax};
+
+// ---------------------------------------------------------------------------
+// Highlight — comments, strings, numbers and keywords, per line
+// ---------------------------------------------------------------------------
+//
+// A lexical pass good enough for exporting colored code, not a parser: it
+// knows each filetype's comment markers, double-quoted strings, numbers and
+// a list of keywords.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Comment,
+    String,
+    Number,
+    Keyword,
+}
+
+/// Keywords of `filetype`, separated by spaces.
+fn keywords(filetype: &str) -> &'static str {
+    match filetype {
+        "rs" => concat!(
+            "as async await break const continue crate dyn else enum extern false fn for if ",
+            "impl in let loop match mod move mut pub ref return self Self static struct super ",
+            "trait true type unsafe use where while",
+        ),
+        "c" | "h" | "cc" | "cpp" | "hpp" => concat!(
+            "auto break case char class const continue default do double else enum extern ",
+            "float for goto if int long namespace return short signed sizeof static struct ",
+            "switch template typedef union unsigned void volatile while",
+        ),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" => concat!(
+            "async await break case catch class const continue default delete do else export ",
+            "extends false finally for function if import in instanceof interface let new ",
+            "null return switch this throw true try type typeof undefined var while yield",
+        ),
+        "go" => concat!(
+            "break case chan const continue default defer else false for func go if import ",
+            "interface map nil package range return select struct switch true type var",
+        ),
+        "py" => concat!(
+            "and as assert async await break class continue def del elif else except False ",
+            "finally for from global if import in is lambda None nonlocal not or pass raise ",
+            "return True try while with yield",
+        ),
+        "sh" | "bash" | "zsh" => concat!(
+            "case do done elif else esac export fi for function if in local return then until ",
+            "while",
+        ),
+        "rb" => concat!(
+            "begin class def do else elsif end ensure false if module nil rescue return self ",
+            "true unless until when while yield",
+        ),
+        _ => "",
+    }
+}
+
+/// A comment or string literal on a line: `start..end` is all of it,
+/// markers and quotes too, and `body` the text inside them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub body: (usize, usize),
+    /// `Comment` or `String`.
+    pub token: Token,
+}
+
+/// The comments and string literals of `line`, in order. `in_block`
+/// carries an open block comment from one line to the next.
+pub fn comments_and_strings(line: &str, syntax: CommentSyntax, in_block: &mut bool) -> Vec<Span> {
+    let (open, close) = syntax.block.unwrap_or(("", ""));
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        let rest = &line[i..];
+        if *in_block || (!open.is_empty() && rest.starts_with(open)) {
+            // A block comment, from its start or the line start to its end
+            // or the line end
+            let from = if *in_block { i } else { i + open.len() };
+            *in_block = true;
+            let (to, end) = match line[from..].find(close) {
+                Some(to) => {
+                    *in_block = false;
+                    (from + to, from + to + close.len())
+                }
+                None => (line.len(), line.len()),
+            };
+            spans.push(Span {
+                start: i,
+                end,
+                body: (from, to),
+                token: Token::Comment,
+            });
+            i = end;
+        } else if let Some(marker) = syntax.line
+            && rest.starts_with(marker)
+        {
+            spans.push(Span {
+                start: i,
+                end: line.len(),
+                body: (i + marker.len(), line.len()),
+                token: Token::Comment,
+            });
+            break;
+        } else if rest.starts_with('"') {
+            // Up to the closing quote, escapes skipped
+            let mut to = line.len();
+            let mut escaped = false;
+            for (j, c) in rest.char_indices().skip(1) {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == '"' {
+                    to = i + j;
+                    break;
+                }
+            }
+            let end = (to + 1).min(line.len());
+            spans.push(Span {
+                start: i,
+                end,
+                body: (i + 1, to),
+                token: Token::String,
+            });
+            i = end;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    spans
+}
+
+/// The highlighted byte ranges of `line`, in order. Filetypes without
+/// comment syntax are not highlighted; `in_block` is as for
+/// `comments_and_strings`.
+pub fn line_tokens(line: &str, filetype: &str, in_block: &mut bool) -> Vec<(usize, usize, Token)> {
+    let Some(syntax) = filetype::comment_syntax(filetype) else {
+        return Vec::new();
+    };
+    let keywords = keywords(filetype);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    for span in comments_and_strings(line, syntax, in_block) {
+        word_tokens(line, i, span.start, keywords, &mut tokens);
+        tokens.push((span.start, span.end, span.token));
+        i = span.end;
+    }
+    word_tokens(line, i, line.len(), keywords, &mut tokens);
+    tokens
+}
+
+/// Push the numbers and keywords of `line[from..to]`.
+fn word_tokens(
+    line: &str,
+    from: usize,
+    to: usize,
+    keywords: &str,
+    tokens: &mut Vec<(usize, usize, Token)>,
+) {
+    let mut i = from;
+    while i < to {
+        let rest = &line[i..to];
+        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push((i, i + len, Token::Number));
+            } else if keywords.split(' ').any(|k| k == word) {
+                tokens.push((i, i + len, Token::Keyword));
+            }
+            i += len;
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds<'a>(line: &'a str, filetype: &str, in_block: &mut bool) -> Vec<(&'a str, Token)> {
+        line_tokens(line, filetype, in_block)
+            .into_iter()
+            .map(|(s, e, t)| (&line[s..e], t))
+            .collect()
+    }
+
+    #[test]
+    fn test_line_tokens() {
+        let mut in_block = false;
+        assert_eq!(
+            kinds(r#"let s = "a \" b"; // done"#, "rs", &mut in_block),
+            [
+                ("let", Token::Keyword),
+                (r#""a \" b""#, Token::String),
+                ("// done", Token::Comment),
+            ]
+        );
+        assert_eq!(
+            kinds("x = 0x1F + lets", "rs", &mut in_block),
+            [("0x1F", Token::Number)]
+        );
+        assert!(kinds("fn main", "txt", &mut in_block).is_empty());
+    }
+
+    #[test]
+    fn test_block_comments_span_lines() {
+        let mut in_block = false;
+        assert_eq!(
+            kinds("int x; /* one", "c", &mut in_block),
+            [("int", Token::Keyword), ("/* one", Token::Comment)]
+        );
+        assert!(in_block);
+        assert_eq!(
+            kinds("two */ return", "c", &mut in_block),
+            [("two */", Token::Comment), ("return", Token::Keyword)]
+        );
+        assert!(!in_block);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::terminal::Terminal;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::terminal::TerminalBackend;
 
 // ---------------------------------------------------------------------------
 // Event types
@@ -31,8 +34,80 @@ pub struct KeyEvent {
     pub shift: bool,
 }
 
+/// Key names as shown to the user: `Ctrl+S`, `Alt+Shift+Left`, `F12`.
+impl fmt::Display for KeyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        match self.key {
+            Key::Char(' ') => write!(f, "Space"),
+            Key::Char(c) => write!(f, "{}", c.to_uppercase()),
+            Key::F(n) => write!(f, "F{}", n),
+            ref key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+/// Parse the names `Display` produces, case-insensitively: `ctrl+s`,
+/// `Alt++`, `Shift+Tab`, `F5`.
+impl FromStr for KeyEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Unknown key: {}", s);
+        // The key name follows the last `+` that is not itself the key
+        let (mods, name) = match s.strip_suffix('+') {
+            Some(mods) if mods.is_empty() || mods.ends_with('+') => (mods, "+"),
+            _ => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+        let mut ke = KeyEvent::plain(Key::Char(' '));
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            match m.to_ascii_lowercase().as_str() {
+                "ctrl" => ke.ctrl = true,
+                "alt" => ke.alt = true,
+                "shift" => ke.shift = true,
+                _ => return Err(invalid()),
+            }
+        }
+        let mut chars = name.chars();
+        ke.key = match (chars.next(), chars.next()) {
+            (Some(c), None) => Key::Char(c.to_ascii_lowercase()),
+            _ => match name.to_ascii_lowercase().as_str() {
+                "space" => Key::Char(' '),
+                "enter" => Key::Enter,
+                "tab" => Key::Tab,
+                "backspace" => Key::Backspace,
+                "delete" => Key::Delete,
+                "escape" | "esc" => Key::Escape,
+                "up" => Key::Up,
+                "down" => Key::Down,
+                "left" => Key::Left,
+                "right" => Key::Right,
+                "home" => Key::Home,
+                "end" => Key::End,
+                "pageup" => Key::PageUp,
+                "pagedown" => Key::PageDown,
+                f => f
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=12).contains(n))
+                    .map(Key::F)
+                    .ok_or_else(invalid)?,
+            },
+        };
+        Ok(ke)
+    }
+}
+
 impl KeyEvent {
-    fn plain(key: Key) -> Self {
+    pub fn plain(key: Key) -> Self {
         KeyEvent {
             key,
             ctrl: false,
@@ -75,6 +150,9 @@ pub struct MouseEvent {
     pub col: u16,
     pub row: u16,
     pub pressed: bool,
+    /// Motion with the button held (needs button-event tracking).
+    pub drag: bool,
+    pub ctrl: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -82,7 +160,16 @@ pub enum Event {
     Key(KeyEvent),
     Mouse(MouseEvent),
     Paste(String),
+    /// Characters that arrived together, like the composed text an input
+    /// method commits. Inserted and undone as one.
+    Text(String),
+    /// The terminal changed size.
     Resize,
+    /// No input arrived before the read timed out: time for background work.
+    Tick,
+    /// The terminal's default background color, answering an OSC 11 query.
+    Background(u8, u8, u8),
+    /// Input that decoded to nothing the editor handles.
     None,
 }
 
@@ -90,13 +177,50 @@ pub enum Event {
 // Main entry point
 // ---------------------------------------------------------------------------
 
+/// Read the next event and every event already waiting behind it. Runs of
+/// plain characters in the burst, such as text committed by an input method
+/// (CJK IMEs send it as one write), come back as a single `Event::Text`.
+pub fn read_events(term: &impl TerminalBackend) -> Vec<Event> {
+    let mut events = vec![read_event(term)];
+    while term.input_pending() {
+        events.push(read_event(term));
+    }
+    let mut merged: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        let Some(ch) = plain_char(&event) else {
+            merged.push(event);
+            continue;
+        };
+        let prev = merged.last().and_then(plain_char);
+        match (merged.last_mut(), prev) {
+            (Some(Event::Text(text)), _) => text.push(ch),
+            (Some(last), Some(prev)) => *last = Event::Text([prev, ch].into_iter().collect()),
+            _ => merged.push(event),
+        }
+    }
+    merged
+}
+
+/// The character of an unmodified key press.
+fn plain_char(event: &Event) -> Option<char> {
+    match event {
+        Event::Key(KeyEvent {
+            key: Key::Char(ch),
+            ctrl: false,
+            alt: false,
+            ..
+        }) => Some(*ch),
+        _ => None,
+    }
+}
+
 /// Read and decode one input event from the terminal.
 ///
-/// Returns `Event::None` when no data is available (timeout).
-pub fn read_event(term: &Terminal) -> Event {
+/// Returns `Event::Tick` when no data is available (timeout).
+pub fn read_event(term: &impl TerminalBackend) -> Event {
     let byte = match term.read_byte() {
         Some(b) => b,
-        None => return Event::None,
+        None => return Event::Tick,
     };
 
     match byte {
@@ -114,8 +238,11 @@ pub fn read_event(term: &Terminal) -> Event {
             Event::Key(KeyEvent::ctrl(Key::Char(ch)))
         }
 
+        // Ctrl+/ (also sent for Ctrl+_)
+        0x1f => Event::Key(KeyEvent::ctrl(Key::Char('/'))),
+
         // Other control chars we don't map
-        0x1c..=0x1f => Event::None,
+        0x1c..=0x1e => Event::None,
 
         // Backspace
         0x7f => Event::Key(KeyEvent::plain(Key::Backspace)),
@@ -134,7 +261,7 @@ pub fn read_event(term: &Terminal) -> Event {
 // ESC sequence handling
 // ---------------------------------------------------------------------------
 
-fn parse_escape(term: &Terminal) -> Event {
+fn parse_escape(term: &impl TerminalBackend) -> Event {
     // Try to read the next byte. If nothing comes, it's a lone Escape.
     let next = match term.read_byte() {
         Some(b) => b,
@@ -144,17 +271,68 @@ fn parse_escape(term: &Terminal) -> Event {
     match next {
         b'[' => parse_csi(term),
         b'O' => parse_ss3(term),
+        b']' => parse_osc(term),
         // Alt + printable character
         0x20..=0x7e => Event::Key(KeyEvent::alt(Key::Char(next as char))),
+        0x7f => Event::Key(KeyEvent::alt(Key::Backspace)),
         _ => Event::Key(KeyEvent::plain(Key::Escape)),
     }
 }
 
+// ---------------------------------------------------------------------------
+// OSC replies (\x1b] ... BEL or ST)
+// ---------------------------------------------------------------------------
+
+/// Longest OSC reply read before giving up on it.
+const MAX_OSC_LEN: usize = 256;
+
+fn parse_osc(term: &impl TerminalBackend) -> Event {
+    let mut body = Vec::new();
+    loop {
+        match term.read_byte() {
+            None => return Event::None,
+            Some(0x07) => break,
+            Some(0x1b) => {
+                // ST is ESC \
+                if term.read_byte() != Some(b'\\') {
+                    return Event::None;
+                }
+                break;
+            }
+            Some(b) if body.len() < MAX_OSC_LEN => body.push(b),
+            Some(_) => {}
+        }
+    }
+    let body = String::from_utf8_lossy(&body);
+    match parse_background_reply(&body) {
+        Some((r, g, b)) => Event::Background(r, g, b),
+        None => Event::None,
+    }
+}
+
+/// Parse the body of an OSC 11 reply, `11;rgb:RRRR/GGGG/BBBB`, into 8-bit
+/// channels. Terminals send 1 to 4 hex digits per channel.
+fn parse_background_reply(body: &str) -> Option<(u8, u8, u8)> {
+    let spec = body.strip_prefix("11;")?;
+    let channels = spec
+        .strip_prefix("rgb:")
+        .or_else(|| spec.strip_prefix("rgba:"))?;
+    let mut parts = channels.split('/').map(|hex| {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
 // ---------------------------------------------------------------------------
 // CSI sequence parser (\x1b[ ...)
 // ---------------------------------------------------------------------------
 
-fn parse_csi(term: &Terminal) -> Event {
+fn parse_csi(term: &impl TerminalBackend) -> Event {
     let mut params = [0u16; 8];
     let mut param_count: usize = 0;
     let mut current: u16 = 0;
@@ -202,6 +380,18 @@ fn parse_csi(term: &Terminal) -> Event {
                     return parse_sgr_mouse(params[0], params[1], params[2], b == b'M');
                 }
 
+                // urxvt mouse: \x1b[btn;col;rowM, for terminals without SGR
+                if b == b'M' && param_count == 3 {
+                    return parse_legacy_mouse(params[0], params[1], params[2]);
+                }
+
+                // X10 mouse: \x1b[M followed by three raw bytes
+                if b == b'M' && param_count == 0 {
+                    let next = || term.read_byte().unwrap_or(32) as u16;
+                    let (btn, col, row) = (next(), next(), next());
+                    return parse_legacy_mouse(btn, col.saturating_sub(32), row.saturating_sub(32));
+                }
+
                 // Bracketed paste: \x1b[200~
                 if b == b'~' && param_count == 1 && params[0] == 200 {
                     return read_bracketed_paste(term);
@@ -237,6 +427,14 @@ fn decode_csi_final(final_byte: u8, params: &[u16]) -> Event {
         b'H' => key_with_mod(Key::Home, modifier(1)),
         b'F' => key_with_mod(Key::End, modifier(1)),
 
+        // Back-tab: \x1b[Z = Shift+Tab
+        b'Z' => key_with_mod(Key::Tab, (false, false, true)),
+
+        // Keys the legacy encoding can't tell apart (Ctrl+Shift+letter):
+        // \x1b[code;mod u and xterm's modifyOtherKeys \x1b[27;mod;code~
+        b'u' if !params.is_empty() => char_with_mod(params[0], modifier(1)),
+        b'~' if params.len() == 3 && params[0] == 27 => char_with_mod(params[2], modifier(1)),
+
         // Tilde sequences: \x1b[N~ or \x1b[N;mod~
         b'~' if !params.is_empty() => {
             let mod_idx = if params.len() >= 2 { 1 } else { 99 };
@@ -285,6 +483,23 @@ fn key_with_mod(key: Key, (ctrl, alt, shift): (bool, bool, bool)) -> Event {
     })
 }
 
+/// A key given by its code point. Letters with Ctrl or Alt come out
+/// lowercase, matching how bindings are written.
+fn char_with_mod(code: u16, (ctrl, alt, shift): (bool, bool, bool)) -> Event {
+    let key = match code {
+        9 => Key::Tab,
+        13 => Key::Enter,
+        27 => Key::Escape,
+        127 => Key::Backspace,
+        _ => match char::from_u32(code as u32) {
+            Some(ch) if ctrl || alt => Key::Char(ch.to_ascii_lowercase()),
+            Some(ch) => Key::Char(ch),
+            None => return Event::None,
+        },
+    };
+    key_with_mod(key, (ctrl, alt, shift))
+}
+
 // ---------------------------------------------------------------------------
 // SGR mouse: \x1b[<btn;col;rowM/m
 // ---------------------------------------------------------------------------
@@ -304,14 +519,27 @@ fn parse_sgr_mouse(btn_bits: u16, col: u16, row: u16, pressed: bool) -> Event {
         col: col.saturating_sub(1), // 1-based to 0-based
         row: row.saturating_sub(1),
         pressed,
+        drag: btn_bits & 32 != 0,
+        ctrl: btn_bits & 16 != 0,
     })
 }
 
+/// urxvt and X10 reports: the button is offset by 32 and a release says
+/// which button only as `3`; it is reported as a Left release, the only
+/// one the editor acts on.
+fn parse_legacy_mouse(btn: u16, col: u16, row: u16) -> Event {
+    let btn = btn.saturating_sub(32);
+    if btn & 0x43 == 3 {
+        return parse_sgr_mouse(btn & !3, col, row, false);
+    }
+    parse_sgr_mouse(btn, col, row, true)
+}
+
 // ---------------------------------------------------------------------------
 // Bracketed paste: read until \x1b[201~
 // ---------------------------------------------------------------------------
 
-fn read_bracketed_paste(term: &Terminal) -> Event {
+fn read_bracketed_paste(term: &impl TerminalBackend) -> Event {
     let mut buf = Vec::with_capacity(256);
 
     // We need to detect the ending sequence \x1b[201~
@@ -339,7 +567,7 @@ fn read_bracketed_paste(term: &Terminal) -> Event {
 // SS3 sequences: \x1bO ...
 // ---------------------------------------------------------------------------
 
-fn parse_ss3(term: &Terminal) -> Event {
+fn parse_ss3(term: &impl TerminalBackend) -> Event {
     let b = match term.read_byte() {
         Some(b) => b,
         None => return Event::None,
@@ -362,7 +590,7 @@ fn parse_ss3(term: &Terminal) -> Event {
 // UTF-8 decoder
 // ---------------------------------------------------------------------------
 
-fn decode_utf8(lead: u8, term: &Terminal) -> Event {
+fn decode_utf8(lead: u8, term: &impl TerminalBackend) -> Event {
     let (expected, mut codepoint) = if lead & 0xE0 == 0xC0 {
         (1, (lead & 0x1F) as u32)
     } else if lead & 0xF0 == 0xE0 {
@@ -395,6 +623,7 @@ fn decode_utf8(lead: u8, term: &Terminal) -> Event {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::terminal::MockTerminal;
 
     #[test]
     fn test_decode_modifier() {
@@ -467,6 +696,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decode_modified_chars() {
+        let ctrl_shift_d = Event::Key(KeyEvent {
+            key: Key::Char('d'),
+            ctrl: true,
+            alt: false,
+            shift: true,
+        });
+        // \x1b[27;6;68~ (modifyOtherKeys) and \x1b[100;6u
+        assert_eq!(decode_csi_final(b'~', &[27, 6, 68]), ctrl_shift_d);
+        assert_eq!(decode_csi_final(b'u', &[100, 6]), ctrl_shift_d);
+        assert_eq!(
+            decode_csi_final(b'u', &[13, 5]),
+            Event::Key(KeyEvent::ctrl(Key::Enter))
+        );
+    }
+
+    #[test]
+    fn test_decode_csi_back_tab() {
+        assert_eq!(
+            decode_csi_final(b'Z', &[]),
+            Event::Key(KeyEvent {
+                key: Key::Tab,
+                ctrl: false,
+                alt: false,
+                shift: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_key_display() {
+        let key = |key, ctrl, alt, shift| KeyEvent {
+            key,
+            ctrl,
+            alt,
+            shift,
+        };
+        assert_eq!(
+            key(Key::Char('s'), true, false, false).to_string(),
+            "Ctrl+S"
+        );
+        assert_eq!(
+            key(Key::Char(' '), true, false, false).to_string(),
+            "Ctrl+Space"
+        );
+        assert_eq!(
+            key(Key::Left, false, true, true).to_string(),
+            "Alt+Shift+Left"
+        );
+        assert_eq!(key(Key::F(12), false, false, false).to_string(), "F12");
+        assert_eq!(key(Key::Char('='), false, true, false).to_string(), "Alt+=");
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let parse = |s: &str| s.parse::<KeyEvent>();
+        assert_eq!(parse("Ctrl+S"), Ok(KeyEvent::ctrl(Key::Char('s'))));
+        assert_eq!(parse("alt++"), Ok(KeyEvent::alt(Key::Char('+'))));
+        assert_eq!(parse("+"), Ok(KeyEvent::plain(Key::Char('+'))));
+        assert_eq!(
+            parse("Alt+Shift+Left"),
+            Ok(KeyEvent {
+                key: Key::Left,
+                ctrl: false,
+                alt: true,
+                shift: true,
+            })
+        );
+        assert_eq!(parse("ctrl+space"), Ok(KeyEvent::ctrl(Key::Char(' '))));
+        assert_eq!(parse("F12"), Ok(KeyEvent::plain(Key::F(12))));
+        assert!(parse("Hyper+A").is_err());
+        assert!(parse("F13").is_err());
+        // Every displayed name parses back
+        let shown = KeyEvent::alt(Key::PageDown);
+        assert_eq!(parse(&shown.to_string()), Ok(shown));
+    }
+
     #[test]
     fn test_sgr_mouse() {
         assert_eq!(
@@ -476,6 +783,8 @@ mod tests {
                 col: 9,
                 row: 4,
                 pressed: true,
+                drag: false,
+                ctrl: false,
             })
         );
         assert_eq!(
@@ -485,7 +794,145 @@ mod tests {
                 col: 0,
                 row: 0,
                 pressed: true,
+                drag: false,
+                ctrl: false,
+            })
+        );
+        assert_eq!(
+            parse_sgr_mouse(32, 3, 7, true),
+            Event::Mouse(MouseEvent {
+                button: MouseButton::Left,
+                col: 2,
+                row: 6,
+                pressed: true,
+                drag: true,
+                ctrl: false,
             })
         );
+        // urxvt / X10 press and release
+        assert_eq!(
+            parse_legacy_mouse(32, 10, 5),
+            parse_sgr_mouse(0, 10, 5, true)
+        );
+        assert_eq!(
+            parse_legacy_mouse(35, 10, 5),
+            parse_sgr_mouse(0, 10, 5, false)
+        );
+        assert_eq!(
+            parse_legacy_mouse(96, 1, 1),
+            parse_sgr_mouse(64, 1, 1, true)
+        );
+        // Ctrl+drag
+        assert!(matches!(
+            parse_sgr_mouse(48, 3, 7, true),
+            Event::Mouse(MouseEvent {
+                button: MouseButton::Left,
+                drag: true,
+                ctrl: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_event_sequences() {
+        let input = MockTerminal::new(b"a\x1b[1;5C\xc3\xa9\x1b[200~hi\x1b[201~\x1b");
+        let key = |key, ctrl| {
+            Event::Key(KeyEvent {
+                key,
+                ctrl,
+                alt: false,
+                shift: false,
+            })
+        };
+        assert_eq!(read_event(&input), key(Key::Char('a'), false));
+        assert_eq!(read_event(&input), key(Key::Right, true));
+        assert_eq!(read_event(&input), key(Key::Char('é'), false));
+        assert_eq!(read_event(&input), Event::Paste("hi".to_string()));
+        assert_eq!(read_event(&input), key(Key::Escape, false));
+        assert_eq!(read_event(&input), Event::Tick);
+        // Alt+Backspace, and Ctrl+Backspace under modifyOtherKeys
+        let input = MockTerminal::new(b"\x1b\x7f\x1b[27;5;127~");
+        assert_eq!(
+            read_event(&input),
+            Event::Key(KeyEvent::alt(Key::Backspace))
+        );
+        assert_eq!(read_event(&input), key(Key::Backspace, true));
+    }
+
+    #[test]
+    fn test_background_reply() {
+        assert_eq!(
+            parse_background_reply("11;rgb:ffff/ffff/ffff"),
+            Some((255, 255, 255))
+        );
+        assert_eq!(
+            parse_background_reply("11;rgb:1e1e/2020/3030"),
+            Some((0x1e, 0x20, 0x30))
+        );
+        assert_eq!(parse_background_reply("11;rgb:f/8/0"), Some((255, 136, 0)));
+        assert_eq!(parse_background_reply("11;rgb:ff/ff"), None);
+        assert_eq!(parse_background_reply("10;rgb:0/0/0"), None);
+        assert_eq!(parse_background_reply("11;?"), None);
+
+        // BEL and ST terminators, then input after the reply
+        let input =
+            MockTerminal::new(b"\x1b]11;rgb:0000/0000/0000\x07\x1b]11;rgb:fdfd/f6f6/e3e3\x1b\\a");
+        assert_eq!(read_event(&input), Event::Background(0, 0, 0));
+        assert_eq!(read_event(&input), Event::Background(0xfd, 0xf6, 0xe3));
+        assert_eq!(
+            read_event(&input),
+            Event::Key(KeyEvent::plain(Key::Char('a')))
+        );
+    }
+
+    #[test]
+    fn test_read_events_coalesce_text() {
+        let text = |s: &str| Event::Text(s.to_string());
+        let plain = |key| Event::Key(KeyEvent::plain(key));
+        // A Japanese IME committing 日本語 in one write
+        let input = MockTerminal::new(b"\xe6\x97\xa5\xe6\x9c\xac\xe8\xaa\x9e");
+        assert_eq!(read_events(&input), [text("日本語")]);
+        // Hangul syllables composed by a Korean IME, then Enter and an arrow
+        let input = MockTerminal::new(b"\xed\x95\x9c\xea\xb8\x80\r\x1b[A");
+        assert_eq!(
+            read_events(&input),
+            [text("한글"), plain(Key::Enter), plain(Key::Up)]
+        );
+        // A single typed character stays a key press, and modified keys
+        // split the text
+        let input = MockTerminal::new(b"a");
+        assert_eq!(read_events(&input), [plain(Key::Char('a'))]);
+        let input = MockTerminal::new(b"ab\x01c\xc3\xa9");
+        assert_eq!(
+            read_events(&input),
+            [
+                text("ab"),
+                Event::Key(KeyEvent::ctrl(Key::Char('a'))),
+                text("cé")
+            ]
+        );
+        // Nothing waiting
+        assert_eq!(read_events(&MockTerminal::new(b"")), [Event::Tick]);
+    }
+
+    #[test]
+    fn fuzz_read_event() {
+        // Weighted towards the bytes that start and shape escape sequences
+        const ALPHABET: &[u8] = b"\x1b\x1b[[O<;;0123456789~MmABCDHFPZu\xc3\xe6\xf0\x80\xbf";
+        crate::fuzz::run(|rng| {
+            let data = crate::fuzz::bytes(rng, 64, ALPHABET);
+            let input = MockTerminal::new(&data);
+            // Every event consumes input until only the timeout is left
+            loop {
+                let before = input.consumed();
+                let event = read_event(&input);
+                if event == Event::Tick {
+                    assert_eq!(before, data.len());
+                    break;
+                }
+                assert!(input.consumed() > before, "no progress on {:?}", data);
+            }
+        });
     }
 }
@@ -0,0 +1,131 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// ---------------------------------------------------------------------------
+// Jobs — background work whose results come back to the main loop
+// ---------------------------------------------------------------------------
+
+/// Identifies a job started by `Jobs::spawn`.
+pub type JobId = u64;
+
+/// Runs work on worker threads so slow calls (git and other external
+/// processes, file scans) never hold up input. Each job's result is sent
+/// back over a channel and handed out by `poll`, which the main loop calls
+/// between events.
+pub struct Jobs<T> {
+    next_id: JobId,
+    sender: Sender<(JobId, Option<T>)>,
+    receiver: Receiver<(JobId, Option<T>)>,
+    running: Vec<JobId>,
+}
+
+impl<T: Send + 'static> Jobs<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Jobs {
+            next_id: 0,
+            sender,
+            receiver,
+            running: Vec::new(),
+        }
+    }
+
+    /// Run `work` on a new thread.
+    pub fn spawn(&mut self, work: impl FnOnce() -> T + Send + 'static) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.running.push(id);
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            // A panicking job still reports back, so it stops counting as running
+            let result = panic::catch_unwind(AssertUnwindSafe(work)).ok();
+            let _ = sender.send((id, result));
+        });
+        id
+    }
+
+    /// Forget job `id`: its result is dropped when it arrives.
+    pub fn cancel(&mut self, id: JobId) {
+        self.running.retain(|&running| running != id);
+    }
+
+    pub fn is_running(&self, id: JobId) -> bool {
+        self.running.contains(&id)
+    }
+
+    /// Results of the jobs that finished since the last call, in the order
+    /// they finished. Cancelled and panicked jobs yield nothing.
+    pub fn poll(&mut self) -> Vec<(JobId, T)> {
+        let mut done = Vec::new();
+        while let Ok((id, result)) = self.receiver.try_recv() {
+            if !self.is_running(id) {
+                continue;
+            }
+            self.cancel(id);
+            if let Some(result) = result {
+                done.push((id, result));
+            }
+        }
+        done
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Poll until the jobs `ids` have finished, or give up after a few
+    /// seconds.
+    fn wait<T: Send + 'static>(jobs: &mut Jobs<T>, ids: &[JobId]) -> Vec<(JobId, T)> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut done = Vec::new();
+        while ids.iter().any(|&id| jobs.is_running(id)) && Instant::now() < deadline {
+            done.extend(jobs.poll());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        done
+    }
+
+    #[test]
+    fn test_jobs_deliver_results() {
+        let mut jobs = Jobs::new();
+        let a = jobs.spawn(|| 1);
+        let b = jobs.spawn(|| 2);
+        assert!(jobs.is_running(a) && jobs.is_running(b));
+        let mut done = wait(&mut jobs, &[a, b]);
+        done.sort();
+        assert_eq!(done, [(a, 1), (b, 2)]);
+        assert!(!jobs.is_running(a));
+    }
+
+    #[test]
+    fn test_jobs_cancel_and_panic() {
+        let mut jobs = Jobs::new();
+        let cancelled = jobs.spawn(|| {
+            std::thread::sleep(Duration::from_millis(20));
+            1
+        });
+        let broken = jobs.spawn(|| panic!("job failed"));
+        let kept = jobs.spawn(|| 3);
+        jobs.cancel(cancelled);
+        assert_eq!(wait(&mut jobs, &[broken, kept]), [(kept, 3)]);
+        assert!(!jobs.is_running(broken));
+    }
+
+    #[test]
+    fn test_jobs_run_processes() {
+        let mut jobs = Jobs::new();
+        let id = jobs.spawn(|| {
+            let output = std::process::Command::new("sh")
+                .args(["-c", "echo hello"])
+                .output();
+            String::from_utf8_lossy(&output.unwrap().stdout).into_owned()
+        });
+        assert_eq!(wait(&mut jobs, &[id]), [(id, "hello\n".to_string())]);
+    }
+}
@@ -0,0 +1,564 @@
+use std::fmt;
+
+// ---------------------------------------------------------------------------
+// Minimal JSON parser and serializer (zero dependencies)
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>), // preserves order
+}
+
+impl JsonValue {
+    pub fn parse(input: &str) -> Result<JsonValue, String> {
+        Self::parse_with_offset(input).map_err(|(msg, _)| msg)
+    }
+
+    /// Like `parse`, but an error also gives the byte offset where it was
+    /// found, for jumping to it.
+    pub fn parse_with_offset(input: &str) -> Result<JsonValue, (String, usize)> {
+        let mut parser = Parser {
+            bytes: input.as_bytes(),
+            pos: 0,
+        };
+        let mut parse = || {
+            parser.skip_ws();
+            let value = parser.parse_value()?;
+            parser.skip_ws();
+            if parser.pos < parser.bytes.len() {
+                return Err(parser.error("Trailing characters after JSON value"));
+            }
+            Ok(value)
+        };
+        parse().map_err(|msg| (msg, parser.pos.min(input.len())))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative integer (positions, ids, lengths).
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            JsonValue::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Look up a key in an object. Returns `None` for non-objects.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        self.as_object()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Build an object from borrowed keys.
+    pub fn object(fields: Vec<(&str, JsonValue)>) -> JsonValue {
+        JsonValue::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(s: &str) -> Self {
+        JsonValue::String(s.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(s: String) -> Self {
+        JsonValue::String(s)
+    }
+}
+
+impl From<usize> for JsonValue {
+    fn from(n: usize) -> Self {
+        JsonValue::Number(n as f64)
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(b: bool) -> Self {
+        JsonValue::Bool(b)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Serializer — compact output, integers without a fraction
+// ---------------------------------------------------------------------------
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            JsonValue::Number(n) if !n.is_finite() => f.write_str("null"),
+            JsonValue::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+                write!(f, "{}", *n as i64)
+            }
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write_string(f, s),
+            JsonValue::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            JsonValue::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+// ---------------------------------------------------------------------------
+// Formatter — re-indent or compact JSON text, keeping tokens as written
+// ---------------------------------------------------------------------------
+
+/// Lay out `input` with one value or field per line, indented by `indent`
+/// per level. Strings and numbers are kept exactly as written. Invalid
+/// JSON gives the parse error and its byte offset.
+pub fn pretty(input: &str, indent: &str) -> Result<String, (String, usize)> {
+    layout(input, Some(indent))
+}
+
+/// `input` with no whitespace between tokens.
+pub fn minify(input: &str) -> Result<String, (String, usize)> {
+    layout(input, None)
+}
+
+fn layout(input: &str, indent: Option<&str>) -> Result<String, (String, usize)> {
+    JsonValue::parse_with_offset(input)?;
+    let newline = |out: &mut String, depth: usize| {
+        if let Some(unit) = indent {
+            out.push('\n');
+            for _ in 0..depth {
+                out.push_str(unit);
+            }
+        }
+    };
+    let mut out = String::with_capacity(input.len());
+    let mut depth = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                let mut escaped = false;
+                for c in chars.by_ref() {
+                    out.push(c);
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            ' ' | '\t' | '\n' | '\r' => {}
+            '{' | '[' => {
+                out.push(c);
+                // Empty containers stay on one line
+                while chars
+                    .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+                    .is_some()
+                {}
+                if let Some(close) =
+                    chars.next_if(|&next| matches!((c, next), ('{', '}') | ('[', ']')))
+                {
+                    out.push(close);
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => {
+                out.push(c);
+                if indent.is_some() {
+                    out.push(' ');
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Parser
+// ---------------------------------------------------------------------------
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Build an error message with a 1-based line/column for `self.pos`.
+    fn error(&self, msg: &str) -> String {
+        let consumed = &self.bytes[..self.pos.min(self.bytes.len())];
+        let line = consumed.iter().filter(|&&b| b == b'\n').count() + 1;
+        let line_start = consumed
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let col = String::from_utf8_lossy(&consumed[line_start..])
+            .chars()
+            .count()
+            + 1;
+        format!("{} at line {}, column {}", msg, line, col)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b) = self.peek() {
+            if b == b' ' || b == b'\t' || b == b'\n' || b == b'\r' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), String> {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", b as char)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        match self.peek() {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("Invalid literal"))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.pos;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        while let Some(b) = self.peek() {
+            if b.is_ascii_digit() || b == b'.' || b == b'e' || b == b'E' || b == b'+' || b == b'-' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        text.parse::<f64>().map(JsonValue::Number).map_err(|_| {
+            self.pos = start;
+            self.error("Invalid number")
+        })
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out: Vec<u8> = Vec::new();
+        loop {
+            let b = match self.peek() {
+                Some(b) => b,
+                None => return Err(self.error("Unterminated string")),
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let esc = match self.peek() {
+                        Some(e) => e,
+                        None => return Err(self.error("Unterminated string")),
+                    };
+                    self.pos += 1;
+                    match esc {
+                        b'"' => out.push(b'"'),
+                        b'\\' => out.push(b'\\'),
+                        b'/' => out.push(b'/'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'u' => {
+                            let ch = self.parse_unicode_escape()?;
+                            let mut tmp = [0u8; 4];
+                            out.extend_from_slice(ch.encode_utf8(&mut tmp).as_bytes());
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        if self.pos + 4 > self.bytes.len() {
+            return Err(self.error("Truncated \\u escape"));
+        }
+        let hex = std::str::from_utf8(&self.bytes[self.pos..self.pos + 4]).unwrap_or("");
+        let v = u32::from_str_radix(hex, 16).map_err(|_| self.error("Invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(v)
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let hi = self.parse_hex4()?;
+        // Surrogate pair, e.g. \uD83D\uDE00
+        if (0xD800..0xDC00).contains(&hi) && self.bytes[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let lo = self.parse_hex4()?;
+            let cp = 0x10000 + ((hi - 0xD800) << 10) + (lo.wrapping_sub(0xDC00) & 0x3FF);
+            return Ok(char::from_u32(cp).unwrap_or('\u{FFFD}'));
+        }
+        Ok(char::from_u32(hi).unwrap_or('\u{FFFD}'))
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            self.skip_ws();
+            items.push(self.parse_value()?);
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_ws();
+            let key = self.parse_string()?;
+            self.skip_ws();
+            self.expect(b':')?;
+            self.skip_ws();
+            let value = self.parse_value()?;
+            fields.push((key, value));
+            self.skip_ws();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scalars() {
+        assert_eq!(JsonValue::parse("null"), Ok(JsonValue::Null));
+        assert_eq!(JsonValue::parse("true"), Ok(JsonValue::Bool(true)));
+        assert_eq!(JsonValue::parse(" false "), Ok(JsonValue::Bool(false)));
+        assert_eq!(JsonValue::parse("-12.5e1"), Ok(JsonValue::Number(-125.0)));
+        assert_eq!(
+            JsonValue::parse("\"hi\""),
+            Ok(JsonValue::String("hi".into()))
+        );
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let v = JsonValue::parse(r#""a\n\"b\" é 😀""#).unwrap();
+        assert_eq!(v.as_str(), Some("a\n\"b\" é 😀"));
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        let v = JsonValue::parse(r#"{"a": [1, 2, {"b": "c"}], "d": {}}"#).unwrap();
+        let arr = match v.get("a") {
+            Some(JsonValue::Array(items)) => items,
+            other => panic!("expected array, got {:?}", other),
+        };
+        assert_eq!(arr.len(), 3);
+        assert_eq!(arr[2].get("b").and_then(|b| b.as_str()), Some("c"));
+        assert_eq!(
+            v.get("d").and_then(|d| d.as_object()).map(|o| o.len()),
+            Some(0)
+        );
+        assert_eq!(v.get("missing"), None);
+    }
+
+    #[test]
+    fn test_object_preserves_order() {
+        let v = JsonValue::parse(r#"{"z": 1, "a": 2}"#).unwrap();
+        let keys: Vec<&str> = v
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect();
+        assert_eq!(keys, vec!["z", "a"]);
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let text = r#"{"id":3,"s":"a\"b\\\n\u0001é","x":[1.5,-2,true,null],"o":{}}"#;
+        let v = JsonValue::parse(text).unwrap();
+        assert_eq!(v.to_string(), text);
+        assert_eq!(JsonValue::parse(&v.to_string()), Ok(v));
+    }
+
+    #[test]
+    fn test_build_object() {
+        let v = JsonValue::object(vec![("line", 4usize.into()), ("ok", true.into())]);
+        assert_eq!(v.to_string(), r#"{"line":4,"ok":true}"#);
+        assert_eq!(v.get("line").and_then(|l| l.as_usize()), Some(4));
+        assert_eq!(JsonValue::Number(-1.0).as_usize(), None);
+    }
+
+    #[test]
+    fn test_pretty_and_minify() {
+        let text = r#"{"a": [1, 2.50, {}], "b": {"c": "x, \"y\": [z]"}, "d": [ ]}"#;
+        assert_eq!(
+            pretty(text, "  ").unwrap(),
+            "{\n  \"a\": [\n    1,\n    2.50,\n    {}\n  ],\n  \"b\": {\n    \"c\": \"x, \\\"y\\\": [z]\"\n  },\n  \"d\": []\n}"
+        );
+        assert_eq!(
+            minify(&pretty(text, "\t").unwrap()).unwrap(),
+            r#"{"a":[1,2.50,{}],"b":{"c":"x, \"y\": [z]"},"d":[]}"#
+        );
+        assert_eq!(minify(" 1e3 ").unwrap(), "1e3");
+        // Errors point at the offending byte
+        let (msg, offset) = minify("{\n  \"a\": tru\n}").unwrap_err();
+        assert!(msg.contains("line 2, column 8"), "{}", msg);
+        assert_eq!(offset, 9);
+    }
+
+    #[test]
+    fn test_parse_errors_report_position() {
+        let err = JsonValue::parse("{\n  \"a\": tru\n}").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+        assert!(JsonValue::parse("[1, 2").is_err());
+        assert!(JsonValue::parse("{} x").is_err());
+        assert!(JsonValue::parse("").is_err());
+    }
+}
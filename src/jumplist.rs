@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+// ---------------------------------------------------------------------------
+// Jump list — positions left by significant cursor jumps
+// ---------------------------------------------------------------------------
+
+/// Oldest entries are dropped beyond this many.
+const MAX_JUMPS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jump {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Jump {
+    fn same_line(&self, other: &Jump) -> bool {
+        self.line == other.line && self.path == other.path
+    }
+}
+
+/// Browser-style history: `back` walks to older positions, `forward`
+/// returns, and a new jump from the middle drops the forward entries.
+#[derive(Default)]
+pub struct JumpList {
+    entries: Vec<Jump>,
+    index: usize, // == entries.len() when not navigating the history
+}
+
+impl JumpList {
+    /// Record `from`, the position a jump is leaving. An older entry on the
+    /// same line is dropped so repeated jumps don't fill the list.
+    pub fn push(&mut self, from: Jump) {
+        self.entries.truncate(self.index);
+        self.entries.retain(|j| !j.same_line(&from));
+        self.entries.push(from);
+        if self.entries.len() > MAX_JUMPS {
+            self.entries.remove(0);
+        }
+        self.index = self.entries.len();
+    }
+
+    /// Step back from `here` to the previous entry, only considering
+    /// entries in `file` when given. `here` is kept so `forward` can return.
+    pub fn back(&mut self, here: Jump, file: Option<&Path>) -> Option<Jump> {
+        if self.index == self.entries.len() {
+            if self.entries.last().is_some_and(|j| j.same_line(&here)) {
+                self.entries.pop();
+            }
+            self.entries.push(here.clone());
+            self.index = self.entries.len() - 1;
+        }
+        let found = (0..self.index)
+            .rev()
+            .find(|&i| self.matches(i, file) && !self.entries[i].same_line(&here))?;
+        self.index = found;
+        Some(self.entries[found].clone())
+    }
+
+    /// Step forward again after `back`.
+    pub fn forward(&mut self, file: Option<&Path>) -> Option<Jump> {
+        let found = (self.index + 1..self.entries.len()).find(|&i| self.matches(i, file))?;
+        self.index = found;
+        Some(self.entries[found].clone())
+    }
+
+    fn matches(&self, i: usize, file: Option<&Path>) -> bool {
+        file.is_none_or(|f| self.entries[i].path == f)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jump(path: &str, line: usize) -> Jump {
+        Jump {
+            path: PathBuf::from(path),
+            line,
+            col: 0,
+        }
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut list = JumpList::default();
+        list.push(jump("a", 1));
+        list.push(jump("a", 20));
+        // Now at line 40
+        assert_eq!(list.back(jump("a", 40), None), Some(jump("a", 20)));
+        assert_eq!(list.back(jump("a", 20), None), Some(jump("a", 1)));
+        assert_eq!(list.back(jump("a", 1), None), None);
+        assert_eq!(list.forward(None), Some(jump("a", 20)));
+        assert_eq!(list.forward(None), Some(jump("a", 40)));
+        assert_eq!(list.forward(None), None);
+    }
+
+    #[test]
+    fn test_new_jump_drops_forward_history() {
+        let mut list = JumpList::default();
+        list.push(jump("a", 1));
+        list.push(jump("a", 2));
+        list.back(jump("a", 3), None);
+        list.back(jump("a", 2), None);
+        // Jump away from line 1: entries 2 and 3 are forgotten
+        list.push(jump("a", 1));
+        assert_eq!(list.forward(None), None);
+        assert_eq!(list.back(jump("a", 9), None), Some(jump("a", 1)));
+        assert_eq!(list.back(jump("a", 1), None), None);
+    }
+
+    #[test]
+    fn test_per_file_navigation() {
+        let mut list = JumpList::default();
+        list.push(jump("a", 1));
+        list.push(jump("b", 5));
+        list.push(jump("a", 7));
+        let here = jump("b", 9);
+        assert_eq!(
+            list.back(here.clone(), Some(Path::new("b"))),
+            Some(jump("b", 5))
+        );
+        assert_eq!(list.forward(Some(Path::new("b"))), Some(here));
+        assert_eq!(list.back(jump("b", 9), None), Some(jump("a", 7)));
+    }
+
+    #[test]
+    fn test_same_line_is_deduplicated() {
+        let mut list = JumpList::default();
+        list.push(jump("a", 1));
+        list.push(jump("a", 5));
+        list.push(jump("a", 1));
+        assert_eq!(list.back(jump("a", 9), None), Some(jump("a", 1)));
+        assert_eq!(list.back(jump("a", 1), None), Some(jump("a", 5)));
+        assert_eq!(list.back(jump("a", 5), None), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! The zelux editing core as a library: the gap buffer, cursor, screen
+//! and input parsing, and an `Editor` that other terminal programs can
+//! embed. Feed it `input::Event`s with `Editor::feed` and draw it into a
+//! region of your own `render::Screen` with `Editor::render_into`; the
+//! `zelux` binary is a thin wrapper that runs it on the whole terminal.
+
+#[cfg(test)]
+mod bench;
+mod bidi;
+pub mod buffer;
+mod command;
+mod comment;
+mod complete;
+mod config;
+pub mod cooked;
+mod csv;
+pub mod cursor;
+mod diagnostics;
+mod diff;
+mod digraph;
+pub mod editor;
+mod export;
+mod file_index;
+mod filetype;
+mod follow;
+#[cfg(test)]
+mod fuzz;
+mod git;
+mod highlight;
+pub mod input;
+mod jobs;
+mod json;
+mod jumplist;
+mod link;
+mod lockfile;
+mod lsp;
+mod pairs;
+mod patch;
+mod plugin;
+mod profile;
+mod prompt;
+mod quickfix;
+mod reflow;
+mod rename;
+pub mod render;
+mod script;
+mod scrollbar;
+mod session;
+mod shell;
+mod snippet;
+mod spell;
+mod stats;
+mod statusline;
+mod strings;
+mod symbols;
+mod tabs;
+mod tags;
+pub mod terminal;
+mod theme;
+mod transform;
+mod trash;
+mod ui;
+mod undo;
+mod unicode;
+mod vi;
+mod virtual_text;
+
+pub use ui::Rect;
@@ -0,0 +1,115 @@
+use std::process::{Command, Stdio};
+
+// ---------------------------------------------------------------------------
+// Links — the URL or file path under the cursor
+// ---------------------------------------------------------------------------
+
+/// Characters that end a link on either side besides whitespace: quotes
+/// and brackets around it, as in `"path"`, `<url>` or `[text](url)`.
+const DELIMITERS: &[char] = &['"', '\'', '`', '<', '>', '(', ')', '[', ']', '{', '}'];
+
+/// Punctuation that ends a sentence rather than the link it follows.
+const TRAILING: &[char] = &['.', ',', ';', '!', '?'];
+
+/// What the text under the cursor points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Url(String),
+    /// A path, possibly with a `:line[:col]` suffix; whether the file
+    /// exists is for the caller to find out.
+    Path(String),
+}
+
+/// The link around byte `at` of `line`, if there is text there.
+pub fn target_at(line: &str, at: usize) -> Option<Target> {
+    let is_delimiter = |c: char| c.is_whitespace() || DELIMITERS.contains(&c);
+    let at = line.floor_char_boundary(at);
+    let start = line[..at]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| is_delimiter(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = line[at..].find(is_delimiter).map_or(line.len(), |i| at + i);
+    let token = line[start..end].trim_end_matches(TRAILING);
+    if token.is_empty() {
+        return None;
+    }
+    if is_url(token) {
+        Some(Target::Url(token.to_string()))
+    } else if let Some(host) = token.strip_prefix("www.") {
+        Some(Target::Url(format!("https://www.{}", host)))
+    } else {
+        Some(Target::Path(token.to_string()))
+    }
+}
+
+/// `scheme://...` with a letter-led scheme, or `mailto:`.
+fn is_url(token: &str) -> bool {
+    if token.starts_with("mailto:") {
+        return true;
+    }
+    token.split_once("://").is_some_and(|(scheme, rest)| {
+        !rest.is_empty()
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+    })
+}
+
+/// The program that hands a URL to the desktop's browser.
+fn opener() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    }
+}
+
+/// Open `url` in the system browser without waiting for it. The opener is
+/// reaped on a thread of its own.
+pub fn open_url(url: &str) -> Result<(), String> {
+    let mut child = Command::new(opener())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", opener(), e))?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Option<Target> {
+        Some(Target::Url(s.to_string()))
+    }
+
+    fn path(s: &str) -> Option<Target> {
+        Some(Target::Path(s.to_string()))
+    }
+
+    #[test]
+    fn test_target_at() {
+        let line = "see https://example.com/a?b=1. or (src/main.rs:12:3), www.rust-lang.org";
+        assert_eq!(target_at(line, 0), path("see"));
+        assert_eq!(target_at(line, 10), url("https://example.com/a?b=1"));
+        // At the end of the link counts too
+        assert_eq!(target_at(line, 29), url("https://example.com/a?b=1"));
+        assert_eq!(target_at(line, 40), path("src/main.rs:12:3"));
+        assert_eq!(target_at(line, 60), url("https://www.rust-lang.org"));
+        assert_eq!(target_at(line, 3), path("see"));
+        assert_eq!(target_at("a  b", 2), None);
+        assert_eq!(target_at("[docs](ftp://host/x)", 9), url("ftp://host/x"));
+        assert_eq!(target_at("mailto:me@host", 0), url("mailto:me@host"));
+        assert_eq!(target_at("a::b", 0), path("a::b"));
+        assert_eq!(target_at("é.txt", 1), path("é.txt"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+// ---------------------------------------------------------------------------
+// Lock files — telling other zelux instances a file is being edited
+// ---------------------------------------------------------------------------
+
+/// Added to a file's name, after a dot, for its lock file beside it.
+const SUFFIX: &str = "zelux-lock";
+
+/// `kill(2)`'s error for a process that exists but is not ours.
+const EPERM: i32 = 1;
+
+unsafe extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+    fn gethostname(name: *mut u8, len: usize) -> i32;
+}
+
+/// Who holds a lock: a process on a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub pid: u32,
+    pub host: String,
+}
+
+impl Owner {
+    /// This process.
+    pub fn current() -> Owner {
+        Owner {
+            pid: std::process::id(),
+            host: hostname(),
+        }
+    }
+
+    /// Read back what `to_string` wrote: `PID HOST`.
+    fn parse(text: &str) -> Option<Owner> {
+        let (pid, host) = text.trim_end().split_once(' ')?;
+        Some(Owner {
+            pid: pid.parse().ok()?,
+            host: host.to_string(),
+        })
+    }
+
+    /// Whether the process still runs. One on another host cannot be
+    /// asked, so it is taken to.
+    fn is_alive(&self) -> bool {
+        if self.host != hostname() {
+            return true;
+        }
+        let Ok(pid) = i32::try_from(self.pid) else {
+            return false;
+        };
+        // Signal 0 only checks that the process could be signalled
+        let signalled = unsafe { kill(pid, 0) } == 0;
+        signalled || std::io::Error::last_os_error().raw_os_error() == Some(EPERM)
+    }
+}
+
+impl std::fmt::Display for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.pid, self.host)
+    }
+}
+
+/// A lock held on a file by this process. Dropping it removes the lock
+/// file, unless another instance has taken it over since.
+#[derive(Debug)]
+pub struct FileLock {
+    file: PathBuf,
+    lock: PathBuf,
+}
+
+impl FileLock {
+    /// The file locked, as it was named to `acquire`.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if read_owner(&self.lock).is_some_and(|o| o == Owner::current()) {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+/// The lock file of `path`: `.NAME.zelux-lock` beside it, hidden.
+pub fn lock_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, SUFFIX))
+}
+
+/// Lock `path` for this process. Err names the live instance that holds
+/// it already; a lock left by one that is gone is taken over. Ok(None)
+/// when no lock can be written there, as in a read-only directory.
+pub fn acquire(path: &Path) -> Result<Option<FileLock>, Owner> {
+    let lock = lock_path(path);
+    let me = Owner::current();
+    // A lock file appears with its contents, so it is never read half
+    // written: it is written under a name of its own and then linked.
+    let temp = lock.with_file_name(format!(
+        "{}.{}",
+        lock.file_name().unwrap_or_default().to_string_lossy(),
+        me.pid
+    ));
+    if fs::write(&temp, format!("{}\n", me)).is_err() {
+        return Ok(None);
+    }
+    let mut result = Ok(None);
+    for _ in 0..2 {
+        match fs::hard_link(&temp, &lock) {
+            Ok(()) => {
+                result = Ok(Some(FileLock {
+                    file: path.to_path_buf(),
+                    lock: lock.clone(),
+                }));
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match read_owner(&lock) {
+                Some(owner) if owner == me => {
+                    result = Ok(Some(FileLock {
+                        file: path.to_path_buf(),
+                        lock: lock.clone(),
+                    }));
+                    break;
+                }
+                Some(owner) if owner.is_alive() => {
+                    result = Err(owner);
+                    break;
+                }
+                // Left by an instance that is gone: try again without it
+                _ => {
+                    let _ = fs::remove_file(&lock);
+                }
+            },
+            Err(_) => break,
+        }
+    }
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+/// The live instance other than this one that holds the lock on `path`,
+/// if any.
+pub fn holder(path: &Path) -> Option<Owner> {
+    read_owner(&lock_path(path)).filter(|o| *o != Owner::current() && o.is_alive())
+}
+
+fn read_owner(lock: &Path) -> Option<Owner> {
+    Owner::parse(&fs::read_to_string(lock).ok()?)
+}
+
+/// The name of this machine, or "localhost" when it cannot be had.
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return "localhost".to_string();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_and_release() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_lock_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        let lock = lock_path(&path);
+        assert_eq!(lock, dir.join(".a.txt.zelux-lock"));
+
+        let held = acquire(&path).unwrap().unwrap();
+        assert_eq!(read_owner(&lock), Some(Owner::current()));
+        assert_eq!(holder(&path), None);
+        assert_eq!(held.file(), path);
+        drop(held);
+        assert!(!lock.exists());
+
+        // An instance on another host is taken to be alive
+        let other = Owner {
+            pid: std::process::id(),
+            host: "elsewhere".to_string(),
+        };
+        fs::write(&lock, format!("{}\n", other)).unwrap();
+        assert_eq!(acquire(&path).unwrap_err(), other);
+        assert_eq!(holder(&path), Some(other));
+
+        // One that is gone leaves a lock that is taken over
+        let gone = Owner {
+            pid: i32::MAX as u32,
+            host: hostname(),
+        };
+        fs::write(&lock, format!("{}\n", gone)).unwrap();
+        assert_eq!(holder(&path), None);
+        let held = acquire(&path).unwrap().unwrap();
+        assert_eq!(read_owner(&lock), Some(Owner::current()));
+        drop(held);
+        assert!(!lock.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1059 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::complete::CompletionItem;
+use crate::json::JsonValue;
+
+// ---------------------------------------------------------------------------
+// Wire format — JSON-RPC with Content-Length framing
+// ---------------------------------------------------------------------------
+
+pub fn encode_message(msg: &JsonValue) -> Vec<u8> {
+    let body = msg.to_string();
+    let mut out = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    out.extend_from_slice(body.as_bytes());
+    out
+}
+
+/// Read one framed message. `Ok(None)` means the stream ended cleanly.
+pub fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<JsonValue>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        let n = reader
+            .read_line(&mut header)
+            .map_err(|e| format!("LSP read failed: {}", e))?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or("LSP message without Content-Length")?;
+    let mut body = vec![0u8; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| format!("LSP read failed: {}", e))?;
+    let text = String::from_utf8_lossy(&body);
+    JsonValue::parse(&text).map(Some)
+}
+
+// ---------------------------------------------------------------------------
+// URIs and positions
+// ---------------------------------------------------------------------------
+
+pub fn path_to_uri(path: &Path) -> String {
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|d| d.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+    let mut uri = String::from("file://");
+    for b in absolute.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?;
+    let bytes = encoded.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = encoded.get(i + 1..i + 3)
+            && let Ok(b) = u8::from_str_radix(hex, 16)
+        {
+            out.push(b);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&out).into_owned()))
+}
+
+/// How `character` offsets in positions are counted. UTF-16 is the protocol
+/// default; UTF-8 (bytes) is used when the server agrees to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    Utf16,
+}
+
+/// Convert a byte column in `line` to a protocol column.
+pub fn col_to_lsp(line: &str, byte_col: usize, encoding: PositionEncoding) -> usize {
+    let byte_col = byte_col.min(line.len());
+    match encoding {
+        PositionEncoding::Utf8 => byte_col,
+        PositionEncoding::Utf16 => line[..byte_col].chars().map(char::len_utf16).sum(),
+    }
+}
+
+/// Convert a protocol column in `line` to a byte column, clamped to the line.
+pub fn col_from_lsp(line: &str, col: usize, encoding: PositionEncoding) -> usize {
+    match encoding {
+        PositionEncoding::Utf8 => {
+            let mut col = col.min(line.len());
+            while !line.is_char_boundary(col) {
+                col -= 1;
+            }
+            col
+        }
+        PositionEncoding::Utf16 => {
+            let mut units = 0;
+            for (i, c) in line.char_indices() {
+                if units >= col {
+                    return i;
+                }
+                units += c.len_utf16();
+            }
+            line.len()
+        }
+    }
+}
+
+/// The LSP `languageId` for a file extension.
+pub fn language_id(filetype: &str) -> &str {
+    match filetype {
+        "rs" => "rust",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "go" => "go",
+        "sh" => "shellscript",
+        "md" => "markdown",
+        other => other,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Diagnostics
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+/// A diagnostic with positions already converted to (line, byte column).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn contains_line(&self, line: usize) -> bool {
+        self.start.0 <= line && line <= self.end.0
+    }
+}
+
+/// Parse one `range` object against `text` (the document as the server
+/// sees it).
+fn parse_range(
+    range: &JsonValue,
+    text: &str,
+    encoding: PositionEncoding,
+) -> Option<((usize, usize), (usize, usize))> {
+    let pos = |key: &str| -> Option<(usize, usize)> {
+        let p = range.get(key)?;
+        let line = p.get("line")?.as_usize()?;
+        let character = p.get("character")?.as_usize()?;
+        let line_text = text.split('\n').nth(line).unwrap_or("");
+        Some((line, col_from_lsp(line_text, character, encoding)))
+    };
+    Some((pos("start")?, pos("end")?))
+}
+
+/// Parse `textDocument/publishDiagnostics` params, converting positions with
+/// `text`.
+pub fn parse_diagnostics(
+    params: &JsonValue,
+    text: &str,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let items = match params.get("diagnostics").and_then(|d| d.as_array()) {
+        Some(items) => items,
+        None => return Vec::new(),
+    };
+    items
+        .iter()
+        .filter_map(|d| {
+            let (start, end) = parse_range(d.get("range")?, text, encoding)?;
+            let severity = match d.get("severity").and_then(|s| s.as_usize()) {
+                Some(2) => Severity::Warning,
+                Some(3) => Severity::Info,
+                Some(4) => Severity::Hint,
+                _ => Severity::Error,
+            };
+            let message = d.get("message")?.as_str()?.to_string();
+            Some(Diagnostic {
+                start,
+                end,
+                severity,
+                message,
+            })
+        })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Definition and hover results
+// ---------------------------------------------------------------------------
+
+/// A target from a definition response: file, line and protocol column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub character: usize,
+}
+
+/// Parse a `Location`, `Location[]` or `LocationLink[]` result.
+pub fn parse_locations(result: &JsonValue) -> Vec<Location> {
+    let one = |loc: &JsonValue| -> Option<Location> {
+        let uri = loc.get("uri").or_else(|| loc.get("targetUri"))?.as_str()?;
+        let range = loc
+            .get("range")
+            .or_else(|| loc.get("targetSelectionRange"))?;
+        let start = range.get("start")?;
+        Some(Location {
+            path: uri_to_path(uri)?,
+            line: start.get("line")?.as_usize()?,
+            character: start.get("character")?.as_usize()?,
+        })
+    };
+    match result {
+        JsonValue::Array(items) => items.iter().filter_map(one).collect(),
+        JsonValue::Null => Vec::new(),
+        single => one(single).into_iter().collect(),
+    }
+}
+
+/// A label the server wants shown before a position, such as a type or
+/// a parameter name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlayHint {
+    pub line: usize,
+    pub character: usize,
+    pub label: String,
+}
+
+/// Parse an `InlayHint[]` result. Padding becomes spaces in the label.
+pub fn parse_inlay_hints(result: &JsonValue) -> Vec<InlayHint> {
+    let one = |hint: &JsonValue| -> Option<InlayHint> {
+        let position = hint.get("position")?;
+        let mut label = match hint.get("label")? {
+            JsonValue::String(s) => s.clone(),
+            JsonValue::Array(parts) => parts
+                .iter()
+                .filter_map(|p| p.get("value")?.as_str())
+                .collect(),
+            _ => return None,
+        };
+        let padded = |key: &str| hint.get(key).and_then(|v| v.as_bool()) == Some(true);
+        if padded("paddingLeft") {
+            label.insert(0, ' ');
+        }
+        if padded("paddingRight") {
+            label.push(' ');
+        }
+        Some(InlayHint {
+            line: position.get("line")?.as_usize()?,
+            character: position.get("character")?.as_usize()?,
+            label,
+        })
+    };
+    result
+        .as_array()
+        .map_or(Vec::new(), |hints| hints.iter().filter_map(one).collect())
+}
+
+/// Plain text of a `MarkupContent`, `MarkedString` or an array of them.
+/// Markdown code fences are dropped; the code inside them is kept.
+fn markup_text(value: &JsonValue) -> Option<String> {
+    let text = match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Array(items) => {
+            let parts: Vec<String> = items.iter().filter_map(markup_text).collect();
+            parts.join("\n\n")
+        }
+        other => other.get("value")?.as_str()?.to_string(),
+    };
+    let text: Vec<&str> = text
+        .lines()
+        .filter(|l| !l.trim_start().starts_with("```"))
+        .collect();
+    let text = text.join("\n").trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+/// Extract the text of a hover result.
+pub fn hover_text(result: &JsonValue) -> Option<String> {
+    markup_text(result.get("contents")?)
+}
+
+// ---------------------------------------------------------------------------
+// Completion and signature help results
+// ---------------------------------------------------------------------------
+
+/// One-character icon for a `CompletionItemKind`.
+fn kind_icon(kind: usize) -> char {
+    match kind {
+        2 => 'm',       // Method
+        3 | 4 => 'f',   // Function, Constructor
+        5 | 10 => '.',  // Field, Property
+        6 | 12 => 'v',  // Variable, Value
+        7 | 22 => 'S',  // Class, Struct
+        8 => 'I',       // Interface
+        9 => 'M',       // Module
+        13 | 20 => 'E', // Enum, EnumMember
+        14 => 'k',      // Keyword
+        15 => '~',      // Snippet
+        21 => 'c',      // Constant
+        25 => 'T',      // TypeParameter
+        _ => ' ',
+    }
+}
+
+/// Parse a `CompletionItem[]` or `CompletionList` result, in the server's
+/// `sortText` order.
+pub fn parse_completion(result: &JsonValue) -> Vec<CompletionItem> {
+    let items = match result {
+        JsonValue::Array(items) => items.as_slice(),
+        other => match other.get("items").and_then(JsonValue::as_array) {
+            Some(items) => items,
+            None => return Vec::new(),
+        },
+    };
+    let mut parsed: Vec<(String, CompletionItem)> = items
+        .iter()
+        .filter_map(|item| {
+            let label = item.get("label")?.as_str()?.to_string();
+            let text = |key: &str| {
+                item.get(key)
+                    .and_then(JsonValue::as_str)
+                    .map(str::to_string)
+            };
+            let insert = item
+                .get("textEdit")
+                .and_then(|e| e.get("newText"))
+                .and_then(JsonValue::as_str)
+                .map(str::to_string)
+                .or_else(|| text("insertText"))
+                .unwrap_or_else(|| label.clone());
+            let sort = text("sortText").unwrap_or_else(|| label.clone());
+            Some((
+                sort,
+                CompletionItem {
+                    filter: text("filterText").unwrap_or_else(|| label.clone()),
+                    snippet: item.get("insertTextFormat").and_then(JsonValue::as_usize) == Some(2),
+                    kind: item
+                        .get("kind")
+                        .and_then(JsonValue::as_usize)
+                        .map_or(' ', kind_icon),
+                    detail: text("detail").filter(|d| !d.is_empty()),
+                    documentation: item.get("documentation").and_then(markup_text),
+                    label,
+                    insert,
+                },
+            ))
+        })
+        .collect();
+    parsed.sort_by(|a, b| a.0.cmp(&b.0));
+    parsed.into_iter().map(|(_, item)| item).collect()
+}
+
+/// The active signature of a signature help result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHelp {
+    pub label: String,
+    /// Byte range of the active parameter within `label`.
+    pub active: Option<(usize, usize)>,
+}
+
+pub fn parse_signature_help(result: &JsonValue) -> Option<SignatureHelp> {
+    let signatures = result.get("signatures")?.as_array()?;
+    let index = result
+        .get("activeSignature")
+        .and_then(JsonValue::as_usize)
+        .unwrap_or(0);
+    let signature = signatures.get(index).or(signatures.first())?;
+    let label = signature.get("label")?.as_str()?.to_string();
+    let param = signature
+        .get("activeParameter")
+        .or_else(|| result.get("activeParameter"))
+        .and_then(JsonValue::as_usize)
+        .unwrap_or(0);
+    // Parameter labels are either a substring of the label or a pair of
+    // UTF-16 offsets into it
+    let active = signature
+        .get("parameters")
+        .and_then(JsonValue::as_array)
+        .and_then(|params| params.get(param))
+        .and_then(|p| match p.get("label")? {
+            JsonValue::String(s) => label.find(s.as_str()).map(|i| (i, i + s.len())),
+            JsonValue::Array(range) if range.len() == 2 => {
+                let col = |v: &JsonValue| {
+                    v.as_usize()
+                        .map(|c| col_from_lsp(&label, c, PositionEncoding::Utf16))
+                };
+                Some((col(&range[0])?, col(&range[1])?))
+            }
+            _ => None,
+        });
+    Some(SignatureHelp { label, active })
+}
+
+// ---------------------------------------------------------------------------
+// LspClient — one language server process
+// ---------------------------------------------------------------------------
+
+pub enum LspEvent {
+    Diagnostics(PathBuf, Vec<Diagnostic>),
+    /// The answer to a request made with `request_at` or
+    /// `request_inlay_hints`.
+    Response {
+        method: String,
+        result: Result<JsonValue, String>,
+        encoding: PositionEncoding,
+    },
+    Message(String),
+    Exited(String),
+}
+
+/// A document the server has been told about, with the text it last saw.
+struct SyncedDoc {
+    path: PathBuf,
+    uri: String,
+    buffer_version: u64,
+    lsp_version: usize,
+    text: String,
+}
+
+pub struct LspClient {
+    child: Child,
+    stdin: ChildStdin,
+    incoming: Receiver<Result<JsonValue, String>>,
+    next_id: usize,
+    init_id: usize,
+    initialized: bool,
+    queued: Vec<JsonValue>, // sent once `initialize` is answered
+    encoding: PositionEncoding,
+    docs: Vec<SyncedDoc>,
+    pending: Vec<(usize, String)>, // request ids awaiting an answer, with their method
+}
+
+impl LspClient {
+    /// Spawn `command` through `sh -c` and send `initialize` for `root`.
+    pub fn start(command: &str, root: &Path) -> Result<LspClient, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start language server: {}", e))?;
+        let stdin = child.stdin.take().ok_or("Language server has no stdin")?;
+        let stdout = child.stdout.take().ok_or("Language server has no stdout")?;
+
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                match read_message(&mut reader) {
+                    Ok(Some(msg)) => {
+                        if tx.send(Ok(msg)).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        let _ = tx.send(Err("Language server exited".to_string()));
+                        break;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut client = LspClient {
+            child,
+            stdin,
+            incoming: rx,
+            next_id: 1,
+            init_id: 0,
+            initialized: false,
+            queued: Vec::new(),
+            encoding: PositionEncoding::Utf16,
+            docs: Vec::new(),
+            pending: Vec::new(),
+        };
+        let params = JsonValue::object(vec![
+            ("processId", (std::process::id() as usize).into()),
+            ("rootUri", path_to_uri(root).into()),
+            (
+                "capabilities",
+                JsonValue::object(vec![
+                    (
+                        "general",
+                        JsonValue::object(vec![(
+                            "positionEncodings",
+                            JsonValue::Array(vec!["utf-8".into(), "utf-16".into()]),
+                        )]),
+                    ),
+                    (
+                        "textDocument",
+                        JsonValue::object(vec![
+                            ("publishDiagnostics", JsonValue::object(vec![])),
+                            ("definition", JsonValue::object(vec![])),
+                            ("inlayHint", JsonValue::object(vec![])),
+                            (
+                                "completion",
+                                JsonValue::object(vec![(
+                                    "completionItem",
+                                    JsonValue::object(vec![
+                                        ("snippetSupport", true.into()),
+                                        (
+                                            "documentationFormat",
+                                            JsonValue::Array(vec![
+                                                "plaintext".into(),
+                                                "markdown".into(),
+                                            ]),
+                                        ),
+                                    ]),
+                                )]),
+                            ),
+                            (
+                                "signatureHelp",
+                                JsonValue::object(vec![(
+                                    "signatureInformation",
+                                    JsonValue::object(vec![(
+                                        "parameterInformation",
+                                        JsonValue::object(vec![(
+                                            "labelOffsetSupport",
+                                            true.into(),
+                                        )]),
+                                    )]),
+                                )]),
+                            ),
+                            (
+                                "hover",
+                                JsonValue::object(vec![(
+                                    "contentFormat",
+                                    JsonValue::Array(vec!["plaintext".into(), "markdown".into()]),
+                                )]),
+                            ),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]);
+        client.init_id = client.request("initialize", params);
+        Ok(client)
+    }
+
+    /// Send a request and return its id.
+    pub fn request(&mut self, method: &str, params: JsonValue) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(JsonValue::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+        id
+    }
+
+    pub fn encoding(&self) -> PositionEncoding {
+        self.encoding
+    }
+
+    /// Send a `textDocument/position` request (definition, hover, ...) for
+    /// `path` at `line`/`character`. The answer arrives as an
+    /// `LspEvent::Response`.
+    pub fn request_at(&mut self, method: &str, path: &Path, line: usize, character: usize) {
+        let params = JsonValue::object(vec![
+            (
+                "textDocument",
+                JsonValue::object(vec![("uri", path_to_uri(path).into())]),
+            ),
+            (
+                "position",
+                JsonValue::object(vec![("line", line.into()), ("character", character.into())]),
+            ),
+        ]);
+        let id = self.request(method, params);
+        self.pending.push((id, method.to_string()));
+    }
+
+    /// Ask for the inlay hints of the first `line_count` lines of `path`.
+    /// The answer arrives as an `LspEvent::Response`.
+    pub fn request_inlay_hints(&mut self, path: &Path, line_count: usize) {
+        let position = |line: usize| {
+            JsonValue::object(vec![("line", line.into()), ("character", 0usize.into())])
+        };
+        let params = JsonValue::object(vec![
+            (
+                "textDocument",
+                JsonValue::object(vec![("uri", path_to_uri(path).into())]),
+            ),
+            (
+                "range",
+                JsonValue::object(vec![("start", position(0)), ("end", position(line_count))]),
+            ),
+        ]);
+        let method = "textDocument/inlayHint";
+        let id = self.request(method, params);
+        self.pending.push((id, method.to_string()));
+    }
+
+    pub fn notify(&mut self, method: &str, params: JsonValue) {
+        self.send(JsonValue::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]));
+    }
+
+    fn send(&mut self, msg: JsonValue) {
+        let is_init = msg.get("method").and_then(|m| m.as_str()) == Some("initialize");
+        if !self.initialized && !is_init {
+            self.queued.push(msg);
+            return;
+        }
+        // A dead server shows up as an exit event from the reader thread
+        let _ = self.stdin.write_all(&encode_message(&msg));
+        let _ = self.stdin.flush();
+    }
+
+    fn respond(&mut self, id: JsonValue, result: JsonValue) {
+        self.send(JsonValue::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id),
+            ("result", result),
+        ]));
+    }
+
+    // -- Document sync ------------------------------------------------------
+
+    /// Bring the server's copy of `path` up to date: `didOpen` the first
+    /// time, full-text `didChange` whenever the buffer version moved.
+    pub fn sync(
+        &mut self,
+        path: &Path,
+        filetype: &str,
+        buffer_version: u64,
+        text: impl FnOnce() -> String,
+    ) {
+        if let Some(i) = self.docs.iter().position(|d| d.path == path) {
+            if self.docs[i].buffer_version == buffer_version {
+                return;
+            }
+            let doc = &mut self.docs[i];
+            doc.buffer_version = buffer_version;
+            doc.lsp_version += 1;
+            doc.text = text();
+            let params = JsonValue::object(vec![
+                (
+                    "textDocument",
+                    JsonValue::object(vec![
+                        ("uri", doc.uri.clone().into()),
+                        ("version", doc.lsp_version.into()),
+                    ]),
+                ),
+                (
+                    "contentChanges",
+                    JsonValue::Array(vec![JsonValue::object(vec![(
+                        "text",
+                        doc.text.clone().into(),
+                    )])]),
+                ),
+            ]);
+            self.notify("textDocument/didChange", params);
+            return;
+        }
+
+        let doc = SyncedDoc {
+            path: path.to_path_buf(),
+            uri: path_to_uri(path),
+            buffer_version,
+            lsp_version: 1,
+            text: text(),
+        };
+        let params = JsonValue::object(vec![(
+            "textDocument",
+            JsonValue::object(vec![
+                ("uri", doc.uri.clone().into()),
+                ("languageId", language_id(filetype).into()),
+                ("version", doc.lsp_version.into()),
+                ("text", doc.text.clone().into()),
+            ]),
+        )]);
+        self.docs.push(doc);
+        self.notify("textDocument/didOpen", params);
+    }
+
+    pub fn did_save(&mut self, path: &Path) {
+        if let Some(doc) = self.docs.iter().find(|d| d.path == path) {
+            let params = JsonValue::object(vec![(
+                "textDocument",
+                JsonValue::object(vec![("uri", doc.uri.clone().into())]),
+            )]);
+            self.notify("textDocument/didSave", params);
+        }
+    }
+
+    pub fn did_close(&mut self, path: &Path) {
+        if let Some(i) = self.docs.iter().position(|d| d.path == path) {
+            let doc = self.docs.remove(i);
+            let params = JsonValue::object(vec![(
+                "textDocument",
+                JsonValue::object(vec![("uri", doc.uri.into())]),
+            )]);
+            self.notify("textDocument/didClose", params);
+        }
+    }
+
+    // -- Incoming messages --------------------------------------------------
+
+    /// Drain messages from the server without blocking.
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = Vec::new();
+        loop {
+            let msg = match self.incoming.try_recv() {
+                Ok(Ok(msg)) => msg,
+                Ok(Err(e)) => {
+                    events.push(LspEvent::Exited(e));
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(LspEvent::Exited("Language server exited".to_string()));
+                    break;
+                }
+            };
+            self.handle_message(msg, &mut events);
+        }
+        events
+    }
+
+    fn handle_message(&mut self, msg: JsonValue, events: &mut Vec<LspEvent>) {
+        let method = msg
+            .get("method")
+            .and_then(|m| m.as_str())
+            .map(str::to_string);
+        let id = msg.get("id").cloned();
+        match (method, id) {
+            // Response to one of our requests
+            (None, Some(id)) => {
+                let id = id.as_usize();
+                if id == Some(self.init_id) {
+                    self.finish_initialize(&msg);
+                } else if let Some(i) = self.pending.iter().position(|(p, _)| Some(*p) == id) {
+                    let (_, method) = self.pending.remove(i);
+                    let result = match msg.get("error") {
+                        Some(err) => Err(err
+                            .get("message")
+                            .and_then(|m| m.as_str())
+                            .unwrap_or("Language server error")
+                            .to_string()),
+                        None => Ok(msg.get("result").cloned().unwrap_or(JsonValue::Null)),
+                    };
+                    events.push(LspEvent::Response {
+                        method,
+                        result,
+                        encoding: self.encoding,
+                    });
+                }
+            }
+            // Request from the server: answer so it doesn't wait on us
+            (Some(method), Some(id)) => {
+                let result = if method == "workspace/configuration" {
+                    let count = msg
+                        .get("params")
+                        .and_then(|p| p.get("items"))
+                        .and_then(|i| i.as_array())
+                        .map_or(0, |i| i.len());
+                    JsonValue::Array(vec![JsonValue::Null; count])
+                } else {
+                    JsonValue::Null
+                };
+                self.respond(id, result);
+            }
+            (Some(method), None) => {
+                let params = msg.get("params").cloned().unwrap_or(JsonValue::Null);
+                match method.as_str() {
+                    "textDocument/publishDiagnostics" => {
+                        let uri = params.get("uri").and_then(|u| u.as_str()).unwrap_or("");
+                        // Report synced documents under the path the editor
+                        // used for them, which may be relative
+                        let (path, text) = match self.docs.iter().find(|d| d.uri == uri) {
+                            Some(doc) => (Some(doc.path.clone()), doc.text.as_str()),
+                            None => (uri_to_path(uri), ""),
+                        };
+                        if let Some(path) = path {
+                            let diags = parse_diagnostics(&params, text, self.encoding);
+                            events.push(LspEvent::Diagnostics(path, diags));
+                        }
+                    }
+                    "window/showMessage" => {
+                        if let Some(text) = params.get("message").and_then(|m| m.as_str()) {
+                            events.push(LspEvent::Message(text.to_string()));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn finish_initialize(&mut self, response: &JsonValue) {
+        let chosen = response
+            .get("result")
+            .and_then(|r| r.get("capabilities"))
+            .and_then(|c| c.get("positionEncoding"))
+            .and_then(|e| e.as_str());
+        self.encoding = if chosen == Some("utf-8") {
+            PositionEncoding::Utf8
+        } else {
+            PositionEncoding::Utf16
+        };
+        self.initialized = true;
+        self.notify("initialized", JsonValue::object(vec![]));
+        for msg in std::mem::take(&mut self.queued) {
+            self.send(msg);
+        }
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        if self.initialized {
+            self.request("shutdown", JsonValue::Null);
+            self.notify("exit", JsonValue::Null);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_framing_round_trip() {
+        let msg = JsonValue::object(vec![("id", 1usize.into()), ("method", "é".into())]);
+        let mut bytes = encode_message(&msg);
+        assert!(bytes.starts_with(b"Content-Length: 22\r\n\r\n"));
+        bytes.extend_from_slice(&encode_message(&JsonValue::Null));
+        let mut reader = std::io::Cursor::new(bytes);
+        assert_eq!(read_message(&mut reader), Ok(Some(msg)));
+        assert_eq!(read_message(&mut reader), Ok(Some(JsonValue::Null)));
+        assert_eq!(read_message(&mut reader), Ok(None));
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let path = Path::new("/tmp/my dir/a#b.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/my%20dir/a%23b.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("http://x"), None);
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let line = "a😀b";
+        assert_eq!(col_to_lsp(line, 5, PositionEncoding::Utf16), 3);
+        assert_eq!(col_from_lsp(line, 3, PositionEncoding::Utf16), 5);
+        assert_eq!(col_from_lsp(line, 99, PositionEncoding::Utf16), 6);
+        assert_eq!(col_from_lsp(line, 2, PositionEncoding::Utf8), 1);
+        assert_eq!(col_to_lsp(line, 5, PositionEncoding::Utf8), 5);
+    }
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let params = JsonValue::parse(
+            r#"{"uri": "file:///x.rs", "diagnostics": [
+                {"range": {"start": {"line": 1, "character": 2},
+                           "end": {"line": 1, "character": 4}},
+                 "severity": 2, "message": "unused"},
+                {"range": {"start": {"line": 0, "character": 0},
+                           "end": {"line": 0, "character": 1}},
+                 "message": "no severity"}
+            ]}"#,
+        )
+        .unwrap();
+        let diags = parse_diagnostics(&params, "x\né = 1", PositionEncoding::Utf16);
+        assert_eq!(diags.len(), 2);
+        assert_eq!(diags[0].start, (1, 3));
+        assert_eq!(diags[0].end, (1, 5));
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(diags[1].severity, Severity::Error);
+        assert!(diags[0].contains_line(1) && !diags[0].contains_line(0));
+    }
+
+    #[test]
+    fn test_parse_locations() {
+        let single = JsonValue::parse(
+            r#"{"uri": "file:///a.rs", "range": {"start": {"line": 3, "character": 7},
+                "end": {"line": 3, "character": 9}}}"#,
+        )
+        .unwrap();
+        let expected = Location {
+            path: PathBuf::from("/a.rs"),
+            line: 3,
+            character: 7,
+        };
+        assert_eq!(parse_locations(&single), vec![expected.clone()]);
+
+        let links = JsonValue::parse(
+            r#"[{"targetUri": "file:///a.rs",
+                 "targetRange": {"start": {"line": 0, "character": 0}, "end": {"line": 9, "character": 0}},
+                 "targetSelectionRange": {"start": {"line": 3, "character": 7},
+                                          "end": {"line": 3, "character": 9}}}]"#,
+        )
+        .unwrap();
+        assert_eq!(parse_locations(&links), vec![expected]);
+        assert!(parse_locations(&JsonValue::Null).is_empty());
+    }
+
+    #[test]
+    fn test_parse_inlay_hints() {
+        let result = JsonValue::parse(
+            r#"[{"position": {"line": 2, "character": 9}, "label": ": i32", "kind": 1},
+                {"position": {"line": 4, "character": 7},
+                 "label": [{"value": "count"}, {"value": ":"}], "paddingRight": true},
+                {"label": "no position"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_inlay_hints(&result),
+            [
+                InlayHint {
+                    line: 2,
+                    character: 9,
+                    label: ": i32".to_string()
+                },
+                InlayHint {
+                    line: 4,
+                    character: 7,
+                    label: "count: ".to_string()
+                },
+            ]
+        );
+        assert!(parse_inlay_hints(&JsonValue::Null).is_empty());
+    }
+
+    #[test]
+    fn test_hover_text() {
+        let markup = JsonValue::parse(
+            r#"{"contents": {"kind": "markdown", "value": "```rust\nfn f()\n```\nDocs."}}"#,
+        )
+        .unwrap();
+        assert_eq!(hover_text(&markup).as_deref(), Some("fn f()\nDocs."));
+        let marked =
+            JsonValue::parse(r#"{"contents": ["a", {"language": "c", "value": "b"}]}"#).unwrap();
+        assert_eq!(hover_text(&marked).as_deref(), Some("a\n\nb"));
+        let empty = JsonValue::parse(r#"{"contents": ""}"#).unwrap();
+        assert_eq!(hover_text(&empty), None);
+    }
+
+    #[test]
+    fn test_parse_completion() {
+        let list = JsonValue::parse(
+            r#"{"isIncomplete": false, "items": [
+                {"label": "push", "kind": 2, "sortText": "b", "detail": "fn(&mut self, T)",
+                 "documentation": {"kind": "markdown", "value": "Appends."}},
+                {"label": "len()", "kind": 2, "sortText": "a", "filterText": "len",
+                 "insertText": "len()"},
+                {"label": "for", "kind": 15, "insertTextFormat": 2,
+                 "textEdit": {"range": {}, "newText": "for ${1:x} in ${2:xs} {}"}}
+            ]}"#,
+        )
+        .unwrap();
+        let items = parse_completion(&list);
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["len()", "push", "for"]);
+        assert_eq!(items[0].filter, "len");
+        assert_eq!(items[1].kind, 'm');
+        assert_eq!(items[1].detail.as_deref(), Some("fn(&mut self, T)"));
+        assert_eq!(items[1].documentation.as_deref(), Some("Appends."));
+        assert!(items[2].snippet);
+        assert_eq!(items[2].insert, "for ${1:x} in ${2:xs} {}");
+        assert!(parse_completion(&JsonValue::Null).is_empty());
+    }
+
+    #[test]
+    fn test_parse_signature_help() {
+        let by_name = JsonValue::parse(
+            r#"{"signatures": [{"label": "fn add(a: i32, b: i32)",
+                "parameters": [{"label": "a: i32"}, {"label": "b: i32"}]}],
+                "activeParameter": 1}"#,
+        )
+        .unwrap();
+        let help = parse_signature_help(&by_name).unwrap();
+        assert_eq!(
+            &help.label[help.active.unwrap().0..help.active.unwrap().1],
+            "b: i32"
+        );
+        let by_offset = JsonValue::parse(
+            r#"{"signatures": [{"label": "é(x)", "activeParameter": 0,
+                "parameters": [{"label": [2, 3]}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_signature_help(&by_offset).unwrap().active,
+            Some((3, 4))
+        );
+        assert_eq!(parse_signature_help(&JsonValue::Null), None);
+    }
+}
@@ -1,29 +1,88 @@
-mod buffer;
-mod cursor;
-mod editor;
-mod input;
-mod render;
-mod terminal;
-mod undo;
-
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use zelux::buffer::Buffer;
+use zelux::editor::{self, Editor};
+use zelux::{cooked, terminal};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let started = Instant::now();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let profile = take_flag(&mut args, "--profile");
+    if take_flag(&mut args, "--no-color").is_some() {
+        terminal::disable_color();
+    }
 
-    let mut editor = if args.len() > 1 {
-        editor::Editor::open(Path::new(&args[1]))
-    } else {
-        editor::Editor::new()
+    // Without raw mode, fall back to viewing the file line by line
+    if let Err(e) = terminal::check_raw_mode() {
+        let path = match args.first().map(String::as_str) {
+            Some("--pager") => args.get(1).map(Path::new),
+            Some("--continue") | None => None,
+            Some(arg) => Some(editor::locate(arg).0),
+        };
+        let Some(path) = path else {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        };
+        eprintln!("{}: viewing {} line by line", e, path.display());
+        if let Err(e) = view_cooked(path) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut editor = match args.first().map(String::as_str) {
+        Some("--pager") => match args.get(1) {
+            Some(path) => Editor::pager(Path::new(path)),
+            None => Err("Usage: zelux --pager FILE".to_string()),
+        },
+        Some("--continue") => Editor::continue_session(),
+        Some(arg) => {
+            let (path, position) = editor::locate(arg);
+            Editor::open_at(path, position)
+        }
+        None => Editor::new(),
     }
     .unwrap_or_else(|e| {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     });
 
+    if let Some(flag) = profile {
+        let output = flag.strip_prefix("--profile=").map(PathBuf::from);
+        editor.enable_profiling(started, output);
+    }
+
     if let Err(e) = editor.run() {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
 }
+
+/// Remove `flag` (or `flag=VALUE`) from anywhere in `args` and return it.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args
+        .iter()
+        .position(|a| a == flag || a.strip_prefix(flag).is_some_and(|v| v.starts_with('=')))?;
+    Some(args.remove(i))
+}
+
+/// Page through `path` in the terminal's cooked mode, a screenful (the
+/// `LINES` variable, or 24) at a time.
+fn view_cooked(path: &Path) -> Result<(), String> {
+    let buffer = Buffer::from_file(path)?;
+    let rows = env::var("LINES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(24);
+    // A row is left for the prompt
+    let page = rows.saturating_sub(1);
+    cooked::view(
+        &buffer,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        page,
+    )
+}
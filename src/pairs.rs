@@ -0,0 +1,153 @@
+// ---------------------------------------------------------------------------
+// Bracket and quote pairs
+// ---------------------------------------------------------------------------
+
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// The (open, close) pair that `ch` belongs to, as either side.
+pub fn pair_of(ch: char) -> Option<(char, char)> {
+    PAIRS
+        .iter()
+        .copied()
+        .find(|&(open, close)| ch == open || ch == close)
+}
+
+pub fn closer_for(open: char) -> Option<char> {
+    PAIRS.iter().find(|&&(o, _)| o == open).map(|&(_, c)| c)
+}
+
+pub fn is_closer(ch: char) -> bool {
+    PAIRS.iter().any(|&(_, c)| c == ch)
+}
+
+/// Whether typing `open` should also insert its closer, given the characters
+/// around the cursor. Avoids pairing inside words (`don't`, `foo(`) so that
+/// only "fresh" openers are closed.
+pub fn should_autoclose(open: char, prev: Option<char>, next: Option<char>) -> bool {
+    let close = match closer_for(open) {
+        Some(c) => c,
+        None => return false,
+    };
+    let next_ok = next.is_none_or(|c| c.is_whitespace() || is_closer(c));
+    if open == close {
+        // Quotes: not right after a word character (apostrophes, suffixes)
+        let prev_ok = prev.is_none_or(|c| !(c.is_alphanumeric() || c == '_'));
+        next_ok && prev_ok
+    } else {
+        next_ok
+    }
+}
+
+/// Find the innermost `open`..`close` pair enclosing byte `pos` in `text`.
+/// Returns the byte offsets of the opening and closing characters.
+///
+/// Brackets nest; quotes are matched on the cursor's line only (nearest quote
+/// before and after). A quote under the cursor opens the pair when an even
+/// number of quotes come before it on the line, and closes it otherwise.
+pub fn find_surrounding(text: &str, pos: usize, open: char, close: char) -> Option<(usize, usize)> {
+    let pos = pos.min(text.len());
+    if open == close {
+        let line_start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i);
+        let quotes_before = text[line_start..pos].matches(open).count();
+        if text[pos..].starts_with(open) && quotes_before.is_multiple_of(2) {
+            let after = text[pos + open.len_utf8()..line_end].find(close)?;
+            return Some((pos, pos + open.len_utf8() + after));
+        }
+        let before = text[line_start..pos].rfind(open)? + line_start;
+        let after = text[pos..line_end].find(close)? + pos;
+        return Some((before, after));
+    }
+
+    // Walk backwards for an unmatched opener. A closer under the cursor
+    // belongs to the pair being searched for.
+    let mut depth = 0usize;
+    let mut start = None;
+    let back_from = match text[pos..].chars().next() {
+        Some(c) if c == close => pos,
+        Some(c) if c == open => pos + c.len_utf8(),
+        _ => pos,
+    };
+    for (i, c) in text[..back_from].char_indices().rev() {
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                start = Some(i);
+                break;
+            }
+            depth -= 1;
+        }
+    }
+    let start = start?;
+
+    // Walk forwards from the opener to its matching closer
+    let mut depth = 0usize;
+    for (i, c) in text[start..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some((start, start + i));
+            }
+        }
+    }
+    None
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pair_lookup() {
+        assert_eq!(pair_of(')'), Some(('(', ')')));
+        assert_eq!(pair_of('"'), Some(('"', '"')));
+        assert_eq!(pair_of('x'), None);
+        assert_eq!(closer_for('{'), Some('}'));
+        assert!(is_closer(']'));
+        assert!(!is_closer('['));
+    }
+
+    #[test]
+    fn test_should_autoclose() {
+        assert!(should_autoclose('(', Some('f'), None));
+        assert!(should_autoclose('(', None, Some(' ')));
+        assert!(should_autoclose('[', None, Some(')')));
+        assert!(!should_autoclose('(', None, Some('x')));
+        assert!(should_autoclose('"', Some(' '), None));
+        assert!(!should_autoclose('\'', Some('n'), Some('t')));
+        assert!(!should_autoclose('\'', Some('n'), None));
+        assert!(!should_autoclose('x', None, None));
+    }
+
+    #[test]
+    fn test_find_surrounding_brackets_nest() {
+        let text = "f(a, (b), c)";
+        // Inside the inner parens
+        assert_eq!(find_surrounding(text, 6, '(', ')'), Some((5, 7)));
+        // Between the inner pair and `c`
+        assert_eq!(find_surrounding(text, 9, '(', ')'), Some((1, 11)));
+        // On the outer opener / closer
+        assert_eq!(find_surrounding(text, 1, '(', ')'), Some((1, 11)));
+        assert_eq!(find_surrounding(text, 11, '(', ')'), Some((1, 11)));
+        assert_eq!(find_surrounding(text, 0, '(', ')'), None);
+    }
+
+    #[test]
+    fn test_find_surrounding_quotes_same_line() {
+        let text = "say \"hi there\" now\n\"x\"";
+        assert_eq!(find_surrounding(text, 7, '"', '"'), Some((4, 13)));
+        assert_eq!(find_surrounding(text, 16, '"', '"'), None);
+        // On the opening / closing quote
+        assert_eq!(find_surrounding(text, 4, '"', '"'), Some((4, 13)));
+        assert_eq!(find_surrounding(text, 13, '"', '"'), Some((4, 13)));
+        assert_eq!(find_surrounding(text, 19, '"', '"'), Some((19, 21)));
+        assert_eq!(find_surrounding(text, 21, '"', '"'), Some((19, 21)));
+    }
+}
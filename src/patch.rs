@@ -0,0 +1,193 @@
+use crate::render::Color;
+
+// ---------------------------------------------------------------------------
+// Patch — reading unified diffs: line kinds, hunks and where they apply
+// ---------------------------------------------------------------------------
+
+/// Whether `filetype` is a unified diff, as `.diff` and `.patch` files are.
+pub fn is_patch(filetype: &str) -> bool {
+    matches!(filetype, "diff" | "patch")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// `diff`, `index`, `---` and `+++` lines and the like, naming files.
+    Header,
+    /// `@@ -old +new @@`.
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+/// Header lines git writes besides `---` and `+++`.
+const HEADERS: &[&str] = &[
+    "diff ",
+    "index ",
+    "new file",
+    "deleted file",
+    "old mode",
+    "new mode",
+    "similarity",
+    "dissimilarity",
+    "rename ",
+    "copy ",
+    "Binary files",
+];
+
+/// The kind of a line judged by itself. A removed line reading `-- x`
+/// looks like a `---` header; that is rare enough to live with.
+pub fn line_kind(line: &str) -> LineKind {
+    if line.starts_with("@@") {
+        LineKind::Hunk
+    } else if line.starts_with("+++ ")
+        || line.starts_with("--- ")
+        || HEADERS.iter().any(|h| line.starts_with(h))
+    {
+        LineKind::Header
+    } else if line.starts_with('+') {
+        LineKind::Added
+    } else if line.starts_with('-') {
+        LineKind::Removed
+    } else {
+        LineKind::Context
+    }
+}
+
+/// The color a patch line is drawn in, if any.
+pub fn line_color(line: &str) -> Option<Color> {
+    match line_kind(line) {
+        LineKind::Header => Some(Color::Ansi(3)),
+        LineKind::Hunk => Some(Color::Ansi(6)),
+        LineKind::Added => Some(Color::Ansi(2)),
+        LineKind::Removed => Some(Color::Ansi(1)),
+        LineKind::Context => None,
+    }
+}
+
+/// The first line of the new side from a hunk header: 14 from
+/// `@@ -12,5 +14,7 @@`, combined diffs' `@@@` included.
+pub fn hunk_new_start(header: &str) -> Option<usize> {
+    let ranges = header.trim_start_matches('@');
+    let new = ranges
+        .split_whitespace()
+        .find_map(|r| r.strip_prefix('+'))?;
+    new.split(',').next()?.parse().ok()
+}
+
+/// The file named by a `+++` or `---` line, without the timestamp `diff
+/// -u` puts after a tab. None for `/dev/null`.
+fn header_path(line: &str) -> Option<&str> {
+    let name = line.get(4..)?.split('\t').next()?.trim_end();
+    (!name.is_empty() && name != "/dev/null").then_some(name)
+}
+
+/// The file and 1-based line of the new side that line `at` of a patch
+/// stands for, reading back through `line(n)` to its hunk header and the
+/// `+++` line before that. A removed line stands for where it was taken
+/// out; a `---` or `+++` line for the start of the file. The path is as
+/// written, with any `b/` prefix.
+pub fn source_at<S: AsRef<str>>(
+    line: impl Fn(usize) -> Option<S>,
+    at: usize,
+) -> Option<(String, usize)> {
+    let text = line(at)?;
+    if text.as_ref().starts_with("--- ") {
+        return Some((file_named(&line, at)?, 1));
+    } else if text.as_ref().starts_with("+++ ") {
+        return Some((file_named(&line, at.checked_sub(1)?)?, 1));
+    }
+    let mut target = None;
+    let mut offset = 0;
+    for n in (0..=at).rev() {
+        let text = line(n)?;
+        let text = text.as_ref();
+        match target {
+            // Past the hunk: the nearest file header names the file
+            Some(target) if text.starts_with("+++ ") => {
+                return Some((file_named(&line, n.checked_sub(1)?)?, target));
+            }
+            Some(_) => {}
+            None if text.starts_with("@@") => {
+                target = Some(hunk_new_start(text)?.max(1) + offset);
+            }
+            None if n == at || text.starts_with(['-', '\\']) => {}
+            None if text.is_empty() || text.starts_with([' ', '+']) => offset += 1,
+            None => return None, // not inside a hunk
+        }
+    }
+    None
+}
+
+/// The file of the `---` line `minus` and the `+++` line after it: the new
+/// name, or the old one for a deleted file.
+fn file_named<S: AsRef<str>>(line: &impl Fn(usize) -> Option<S>, minus: usize) -> Option<String> {
+    let plus = line(minus + 1)?;
+    if let Some(path) = header_path(plus.as_ref()) {
+        return Some(path.to_string());
+    }
+    header_path(line(minus)?.as_ref()).map(str::to_string)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1234567..89abcde 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -10,4 +12,5 @@ mod a;
+ mod b;
+-mod c;
++mod d;
++mod e;
+
+ mod f;
+--- old.txt\t2024-01-01 10:00:00
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+
+    fn at(n: usize) -> Option<(String, usize)> {
+        let lines: Vec<&str> = PATCH.lines().collect();
+        source_at(|i| lines.get(i).copied(), n)
+    }
+
+    #[test]
+    fn test_line_kind() {
+        let kinds: Vec<LineKind> = PATCH.lines().take(9).map(line_kind).collect();
+        use LineKind::*;
+        assert_eq!(
+            kinds,
+            [
+                Header, Header, Header, Header, Hunk, Context, Removed, Added, Added
+            ]
+        );
+        assert_eq!(hunk_new_start("@@ -10,4 +12,5 @@ mod a;"), Some(12));
+        assert_eq!(hunk_new_start("@@@ -1,2 -1,2 +1,3 @@@"), Some(1));
+        assert_eq!(hunk_new_start("@@ broken"), None);
+    }
+
+    #[test]
+    fn test_source_at() {
+        let lib = |line| Some(("b/src/lib.rs".to_string(), line));
+        assert_eq!(at(0), None);
+        assert_eq!(at(3), lib(1));
+        assert_eq!(at(4), lib(12));
+        assert_eq!(at(5), lib(12));
+        // Removed lines point where they were, between their neighbors
+        assert_eq!(at(6), lib(13));
+        assert_eq!(at(7), lib(13));
+        assert_eq!(at(8), lib(14));
+        assert_eq!(at(10), lib(16));
+        // A deleted file is found by its old name
+        assert_eq!(at(14), Some(("old.txt".to_string(), 1)));
+    }
+}
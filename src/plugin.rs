@@ -0,0 +1,295 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::json::JsonValue;
+
+// ---------------------------------------------------------------------------
+// Plugins — external processes speaking line-delimited JSON
+// ---------------------------------------------------------------------------
+//
+// Every file in `<config dir>/plugins` is started with the editor, with
+// piped stdin and stdout. Each line is one JSON object.
+//
+// The editor sends the events a plugin subscribed to:
+//   {"event":"key","key":"Ctrl+S"}
+//   {"event":"change","path":"src/a.rs","version":7,"text":"..."}
+//   {"event":"save","path":"src/a.rs"}
+// (`path` is null for an unnamed buffer.)
+//
+// A plugin sends requests:
+//   {"command":"subscribe","events":["key","save"]}
+//   {"command":"insert","text":"..."}    at the cursor of the active buffer
+//   {"command":"status","text":"..."}    shown in the message line
+//   {"command":"open","path":"..."}
+//   {"command":"run","name":"save"}      any command by name
+
+/// What the editor tells plugins about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Key(String),
+    Change {
+        path: Option<PathBuf>,
+        version: u64,
+        text: String,
+    },
+    Save(PathBuf),
+}
+
+impl Event {
+    /// The name plugins subscribe to.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Key(_) => "key",
+            Event::Change { .. } => "change",
+            Event::Save(_) => "save",
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        let path_value = |path: Option<&Path>| {
+            path.map_or(JsonValue::Null, |p| p.to_string_lossy().as_ref().into())
+        };
+        let mut fields = vec![("event", self.name().into())];
+        match self {
+            Event::Key(key) => fields.push(("key", key.as_str().into())),
+            Event::Change {
+                path,
+                version,
+                text,
+            } => {
+                fields.push(("path", path_value(path.as_deref())));
+                fields.push(("version", (*version as usize).into()));
+                fields.push(("text", text.as_str().into()));
+            }
+            Event::Save(path) => fields.push(("path", path_value(Some(path)))),
+        }
+        JsonValue::object(fields)
+    }
+}
+
+/// What a plugin asks the editor to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Subscribe(Vec<String>),
+    Insert(String),
+    Status(String),
+    Open(PathBuf),
+    Run(String),
+}
+
+/// Parse one line from a plugin.
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    let msg = JsonValue::parse(line)?;
+    let string = |key: &str| {
+        msg.get(key)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| format!("missing \"{}\"", key))
+    };
+    match msg.get("command").and_then(|c| c.as_str()) {
+        Some("subscribe") => {
+            let events = msg
+                .get("events")
+                .and_then(|e| e.as_array())
+                .ok_or("missing \"events\"")?;
+            Ok(Request::Subscribe(
+                events
+                    .iter()
+                    .filter_map(|e| e.as_str().map(str::to_string))
+                    .collect(),
+            ))
+        }
+        Some("insert") => Ok(Request::Insert(string("text")?)),
+        Some("status") => Ok(Request::Status(string("text")?)),
+        Some("open") => Ok(Request::Open(PathBuf::from(string("path")?))),
+        Some("run") => Ok(Request::Run(string("name")?)),
+        Some(other) => Err(format!("unknown command \"{}\"", other)),
+        None => Err("missing \"command\"".to_string()),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Plugin processes
+// ---------------------------------------------------------------------------
+
+struct Plugin {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    incoming: Receiver<String>,
+    subscriptions: Vec<String>,
+}
+
+impl Plugin {
+    fn start(path: &Path) -> Result<Plugin, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+
+        // The channel disconnects when the plugin closes its stdout
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Plugin {
+            name: path
+                .file_name()
+                .map_or_else(String::new, |n| n.to_string_lossy().into_owned()),
+            child,
+            stdin,
+            incoming: rx,
+            subscriptions: Vec::new(),
+        })
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The running plugins. A plugin that exits or stops reading is dropped.
+#[derive(Default)]
+pub struct Plugins {
+    running: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Start every file in `dir` (hidden files skipped), in name order.
+    /// Returns the plugins that started and an error for each that didn't.
+    pub fn start_all(dir: &Path) -> (Plugins, Vec<String>) {
+        let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .filter(|p| {
+                    p.file_name()
+                        .is_some_and(|n| !n.to_string_lossy().starts_with('.'))
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        let mut plugins = Plugins::default();
+        let mut errors = Vec::new();
+        for path in paths {
+            match Plugin::start(&path) {
+                Ok(plugin) => plugins.running.push(plugin),
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        (plugins, errors)
+    }
+
+    /// Whether any plugin subscribed to events named `name`.
+    pub fn wants(&self, name: &str) -> bool {
+        self.running
+            .iter()
+            .any(|p| p.subscriptions.iter().any(|s| s == name))
+    }
+
+    /// Send `event` to the plugins subscribed to it.
+    pub fn send(&mut self, event: &Event) {
+        let line = format!("{}\n", event.to_json());
+        self.running.retain_mut(|plugin| {
+            !plugin.subscriptions.iter().any(|s| s == event.name())
+                || plugin
+                    .stdin
+                    .write_all(line.as_bytes())
+                    .and_then(|_| plugin.stdin.flush())
+                    .is_ok()
+        });
+    }
+
+    /// Drain the plugins' requests without blocking, with the name of the
+    /// plugin each came from. Subscriptions are handled here; malformed
+    /// lines and exits come back as errors.
+    pub fn poll(&mut self) -> Vec<(String, Result<Request, String>)> {
+        let mut requests = Vec::new();
+        self.running.retain_mut(|plugin| {
+            loop {
+                match plugin.incoming.try_recv() {
+                    Ok(line) if line.trim().is_empty() => {}
+                    Ok(line) => match parse_request(&line) {
+                        Ok(Request::Subscribe(events)) => plugin.subscriptions = events,
+                        request => requests.push((plugin.name.clone(), request)),
+                    },
+                    Err(TryRecvError::Empty) => return true,
+                    Err(TryRecvError::Disconnected) => {
+                        requests.push((plugin.name.clone(), Err("exited".to_string())));
+                        return false;
+                    }
+                }
+            }
+        });
+        requests
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            parse_request(r#"{"command":"subscribe","events":["key","save"]}"#),
+            Ok(Request::Subscribe(vec!["key".into(), "save".into()]))
+        );
+        assert_eq!(
+            parse_request(r#"{"command":"insert","text":"hi\n"}"#),
+            Ok(Request::Insert("hi\n".into()))
+        );
+        assert_eq!(
+            parse_request(r#"{"command":"open","path":"a.rs"}"#),
+            Ok(Request::Open(PathBuf::from("a.rs")))
+        );
+        assert_eq!(
+            parse_request(r#"{"command":"run","name":"save"}"#),
+            Ok(Request::Run("save".into()))
+        );
+        assert!(parse_request(r#"{"command":"status"}"#).is_err());
+        assert!(parse_request(r#"{"command":"fly"}"#).is_err());
+        assert!(parse_request("not json").is_err());
+    }
+
+    #[test]
+    fn test_event_json() {
+        assert_eq!(
+            Event::Key("Ctrl+S".into()).to_json().to_string(),
+            r#"{"event":"key","key":"Ctrl+S"}"#
+        );
+        let change = Event::Change {
+            path: None,
+            version: 3,
+            text: "a\n".into(),
+        };
+        assert_eq!(
+            change.to_json().to_string(),
+            r#"{"event":"change","path":null,"version":3,"text":"a\n"}"#
+        );
+        assert_eq!(
+            Event::Save(PathBuf::from("a.rs")).to_json().to_string(),
+            r#"{"event":"save","path":"a.rs"}"#
+        );
+    }
+}
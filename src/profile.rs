@@ -0,0 +1,222 @@
+use std::time::{Duration, Instant};
+
+// ---------------------------------------------------------------------------
+// Profile — timings recorded with `--profile`
+// ---------------------------------------------------------------------------
+//
+// Each pass through the main loop is split into phases: waiting for input,
+// building the frame, encoding the changed cells and writing them to the
+// terminal. Handling each event, and editor operations (commands, file
+// loads), are timed by name. The report lists count, mean, 95th percentile,
+// worst and total for each.
+
+/// The parts of one pass through the main loop, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    InputWait,
+    Render,
+    Emit,
+    Flush,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::InputWait, Phase::Render, Phase::Emit, Phase::Flush];
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::InputWait => "input wait",
+            Phase::Render => "render build",
+            Phase::Emit => "diff emit",
+            Phase::Flush => "flush",
+        }
+    }
+}
+
+/// Samples kept per timing for the percentile; older ones are dropped.
+const MAX_SAMPLES: usize = 4096;
+
+#[derive(Debug, Default, Clone)]
+struct Stats {
+    count: usize,
+    total: Duration,
+    max: Duration,
+    samples: Vec<Duration>,
+}
+
+impl Stats {
+    fn add(&mut self, d: Duration) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.remove(0);
+        }
+        self.samples.push(d);
+        self.count += 1;
+        self.total += d;
+        self.max = self.max.max(d);
+    }
+
+    fn mean(&self) -> Duration {
+        self.total / self.count.max(1) as u32
+    }
+
+    fn p95(&self) -> Duration {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let i = (sorted.len() * 95).div_ceil(100).saturating_sub(1);
+        sorted.get(i).copied().unwrap_or_default()
+    }
+}
+
+pub struct Profile {
+    started: Instant,
+    startup: Option<Duration>,
+    bytes: usize,
+    phases: [Stats; 4],
+    events: Vec<(&'static str, Stats)>,
+    operations: Vec<(String, Stats)>,
+}
+
+impl Profile {
+    /// Start profiling; `started` is when the process began, for the
+    /// startup time.
+    pub fn new(started: Instant) -> Self {
+        Profile {
+            started,
+            startup: None,
+            bytes: 0,
+            phases: Default::default(),
+            events: Vec::new(),
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn phase(&mut self, phase: Phase, d: Duration) {
+        self.phases[phase as usize].add(d);
+    }
+
+    /// Count a frame's output. The first frame ends startup.
+    pub fn frame_done(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        self.startup.get_or_insert_with(|| self.started.elapsed());
+    }
+
+    pub fn event(&mut self, kind: &'static str, d: Duration) {
+        match self.events.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, stats)) => stats.add(d),
+            None => {
+                let mut stats = Stats::default();
+                stats.add(d);
+                self.events.push((kind, stats));
+            }
+        }
+    }
+
+    pub fn operation(&mut self, name: &str, d: Duration) {
+        match self.operations.iter_mut().find(|(n, _)| n == name) {
+            Some((_, stats)) => stats.add(d),
+            None => {
+                let mut stats = Stats::default();
+                stats.add(d);
+                self.operations.push((name.to_string(), stats));
+            }
+        }
+    }
+
+    /// The timings as a plain-text table.
+    pub fn report(&self) -> String {
+        let frames = self.phases[Phase::Render as usize].count;
+        let mut out = format!(
+            "zelux profile: {:.1?} running, {} frames, {} bytes written\n",
+            self.started.elapsed(),
+            frames,
+            self.bytes
+        );
+        if let Some(startup) = self.startup {
+            out.push_str(&format!("startup (to the first frame): {:.2?}\n", startup));
+        }
+        let phases: Vec<(&str, &Stats)> = Phase::ALL
+            .iter()
+            .map(|&p| (p.label(), &self.phases[p as usize]))
+            .collect();
+        table(&mut out, "Frame phase", &phases);
+        let mut events: Vec<(&str, &Stats)> = self.events.iter().map(|(k, s)| (*k, s)).collect();
+        events.sort_by_key(|(_, s)| std::cmp::Reverse(s.total));
+        table(&mut out, "Event", &events);
+        let mut operations: Vec<(&str, &Stats)> = self
+            .operations
+            .iter()
+            .map(|(n, s)| (n.as_str(), s))
+            .collect();
+        operations.sort_by_key(|(_, s)| std::cmp::Reverse(s.total));
+        table(&mut out, "Operation", &operations);
+        out
+    }
+}
+
+fn table(out: &mut String, heading: &str, rows: &[(&str, &Stats)]) {
+    let width = rows
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain([heading.len()])
+        .max()
+        .unwrap_or(0);
+    out.push_str(&format!(
+        "\n{:width$}  {:>7}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+        heading, "count", "mean", "p95", "max", "total"
+    ));
+    for (name, stats) in rows {
+        out.push_str(&format!(
+            "{:width$}  {:>7}  {:>10}  {:>10}  {:>10}  {:>10}\n",
+            name,
+            stats.count,
+            format!("{:.2?}", stats.mean()),
+            format!("{:.2?}", stats.p95()),
+            format!("{:.2?}", stats.max),
+            format!("{:.2?}", stats.total),
+        ));
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        for ms in 1..=100 {
+            stats.add(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.p95(), Duration::from_millis(95));
+        assert_eq!(stats.mean(), Duration::from_micros(50_500));
+        assert_eq!(Stats::default().p95(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut profile = Profile::new(Instant::now());
+        profile.phase(Phase::Render, Duration::from_millis(2));
+        profile.frame_done(120);
+        profile.event("key", Duration::from_millis(1));
+        profile.operation("save", Duration::from_millis(5));
+        profile.operation("undo", Duration::from_millis(9));
+        let report = profile.report();
+        assert!(report.contains("1 frames, 120 bytes written"));
+        assert!(report.contains("startup (to the first frame)"));
+        let lines: Vec<&str> = report.lines().collect();
+        let render = lines
+            .iter()
+            .find(|l| l.starts_with("render build"))
+            .unwrap();
+        assert!(render.contains("2.00ms"));
+        // Costliest operations first
+        let undo = lines.iter().position(|l| l.starts_with("undo")).unwrap();
+        let save = lines.iter().position(|l| l.starts_with("save")).unwrap();
+        assert!(undo < save);
+    }
+}
@@ -0,0 +1,452 @@
+use std::borrow::Cow;
+
+use crate::input::{Key, KeyEvent};
+use crate::render::{Color, Screen};
+use crate::ui::KeyResult;
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Prompt — a question asked on the message line
+// ---------------------------------------------------------------------------
+//
+// A text prompt edits a line of input and is answered with Enter; a choice
+// prompt is answered with one key, like a dialog. What the answer is for
+// is up to the caller, which keeps it next to the prompt.
+
+/// Checks an answer before it is accepted; the error is shown and the
+/// prompt stays open.
+pub type Validate = fn(&str) -> Result<(), String>;
+
+const LABEL_FG: Color = Color::Ansi(3); // yellow
+const DIM_FG: Color = Color::Color256(240); // dim gray
+
+pub struct Prompt {
+    pub label: String,
+    pub input: String,
+    pub cursor_pos: usize, // byte offset within input
+    scroll: usize,         // first display column of input shown
+    /// Keys and labels of a choice prompt; empty for a text prompt.
+    choices: Vec<(char, &'static str)>,
+    /// The answer when Enter confirms empty input, shown dim until typing.
+    default: Option<String>,
+    /// Input drawn as `*`.
+    masked: bool,
+    validate: Option<Validate>,
+}
+
+impl Prompt {
+    pub fn new(label: &str) -> Self {
+        Prompt {
+            label: label.to_string(),
+            input: String::new(),
+            cursor_pos: 0,
+            scroll: 0,
+            choices: Vec::new(),
+            default: None,
+            masked: false,
+            validate: None,
+        }
+    }
+
+    /// A question answered by one of `choices`' keys.
+    pub fn choice(label: &str, choices: &[(char, &'static str)]) -> Self {
+        Prompt {
+            choices: choices.to_vec(),
+            ..Prompt::new(label)
+        }
+    }
+
+    /// Start with `input` typed, the cursor after it.
+    pub fn with_input(mut self, input: &str) -> Self {
+        self.input = input.to_string();
+        self.cursor_pos = input.len();
+        self
+    }
+
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    pub fn with_validation(mut self, validate: Validate) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn masked(mut self) -> Self {
+        self.masked = true;
+        self
+    }
+
+    /// The input, or the default when nothing was typed.
+    pub fn answer(&self) -> &str {
+        match self.default {
+            Some(ref default) if self.input.is_empty() => default,
+            _ => &self.input,
+        }
+    }
+
+    /// Run the validation on the answer.
+    pub fn check(&self) -> Result<(), String> {
+        self.validate
+            .map_or(Ok(()), |validate| validate(self.answer()))
+    }
+
+    /// Enter accepts, Escape cancels and a choice's key picks it. A text
+    /// prompt edits its input with the other keys it knows and ignores the
+    /// rest; a choice prompt swallows them.
+    pub fn handle_key(&mut self, ke: &KeyEvent) -> KeyResult {
+        if ke.key == Key::Escape {
+            return KeyResult::Cancel;
+        }
+        if !self.choices.is_empty() {
+            return match ke.key {
+                Key::Char(c) if !ke.ctrl && !ke.alt => {
+                    let c = c.to_ascii_lowercase();
+                    if self.choices.iter().any(|(key, _)| *key == c) {
+                        KeyResult::Choice(c)
+                    } else {
+                        KeyResult::Consumed
+                    }
+                }
+                _ => KeyResult::Consumed,
+            };
+        }
+        if ke.ctrl || ke.alt {
+            return KeyResult::Ignored;
+        }
+        let before = &self.input[..self.cursor_pos];
+        let after = &self.input[self.cursor_pos..];
+        match ke.key {
+            Key::Enter => return KeyResult::Accept,
+            Key::Backspace => {
+                if let Some(ch) = before.chars().next_back() {
+                    let start = self.cursor_pos - ch.len_utf8();
+                    self.input.drain(start..self.cursor_pos);
+                    self.cursor_pos = start;
+                }
+            }
+            Key::Delete => {
+                if let Some(ch) = after.chars().next() {
+                    let end = self.cursor_pos + ch.len_utf8();
+                    self.input.drain(self.cursor_pos..end);
+                }
+            }
+            Key::Left => {
+                if let Some(ch) = before.chars().next_back() {
+                    self.cursor_pos -= ch.len_utf8();
+                }
+            }
+            Key::Right => {
+                if let Some(ch) = after.chars().next() {
+                    self.cursor_pos += ch.len_utf8();
+                }
+            }
+            Key::Home => self.cursor_pos = 0,
+            Key::End => self.cursor_pos = self.input.len(),
+            Key::Char(ch) => {
+                self.input.insert(self.cursor_pos, ch);
+                self.cursor_pos += ch.len_utf8();
+            }
+            _ => return KeyResult::Ignored,
+        }
+        KeyResult::Consumed
+    }
+
+    /// Insert pasted text at the cursor. A choice prompt has no input.
+    pub fn paste(&mut self, text: &str) {
+        if self.choices.is_empty() {
+            self.input.insert_str(self.cursor_pos, text);
+            self.cursor_pos += text.len();
+        }
+    }
+
+    /// What follows the label: the input, masked, or the choices.
+    fn shown(&self) -> Cow<'_, str> {
+        if !self.choices.is_empty() {
+            let choices: Vec<String> = self
+                .choices
+                .iter()
+                .map(|(key, label)| format!("[{}] {}", key, label))
+                .collect();
+            Cow::Owned(choices.join("  "))
+        } else if self.masked {
+            Cow::Owned("*".repeat(self.input.chars().count()))
+        } else {
+            Cow::Borrowed(&self.input)
+        }
+    }
+
+    /// The cursor's display column within `shown`.
+    fn shown_cursor(&self) -> usize {
+        let before = &self.input[..self.cursor_pos];
+        if !self.choices.is_empty() {
+            unicode::display_width(&self.shown())
+        } else if self.masked {
+            before.chars().count()
+        } else {
+            unicode::display_width(before)
+        }
+    }
+
+    fn input_start(&self) -> usize {
+        1 + unicode::display_width(&self.label)
+    }
+
+    /// Scroll the input so the cursor shows on a line `width` columns wide.
+    /// The scroll only changes here, so a frame that skips drawing an
+    /// unchanged prompt still sees where its cursor is.
+    pub fn follow_cursor(&mut self, width: usize) {
+        let shown = unicode::display_width(&self.shown());
+        let available = width.saturating_sub(self.input_start());
+        self.scroll = scroll_to_cursor(self.scroll, self.shown_cursor(), shown, available);
+    }
+
+    /// The first shown column, which changes what `render` draws.
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Draw the prompt on `row` of a `width` column line, with an ellipsis
+    /// on each edge of the input that hides some of it. Returns the column
+    /// after the input.
+    pub fn render(&self, screen: &mut Screen, row: usize, width: usize) -> usize {
+        screen.put_str(row, 1, &self.label, LABEL_FG, Color::Default, false);
+        let start = self.input_start();
+        let available = width.saturating_sub(start).max(3);
+        let shown = self.shown();
+        let len = unicode::display_width(&shown);
+        if len == 0 {
+            if let Some(ref default) = self.default {
+                let default = unicode::truncate_display(default, available - 1);
+                screen.put_str(row, start, default, DIM_FG, Color::Default, false);
+            }
+            return start;
+        }
+        let fg = if self.choices.is_empty() {
+            Color::Default
+        } else {
+            LABEL_FG
+        };
+        let clipped_left = self.scroll > 0;
+        let clipped_right = self.scroll + available <= len;
+        let first = self.scroll + usize::from(clipped_left);
+        let end = self.scroll + available - usize::from(clipped_right);
+        let mut col = 0;
+        let mut input_end = start;
+        for c in shown.chars() {
+            let w = unicode::char_width(c);
+            if col >= first && col + w <= end {
+                let at = start + col - self.scroll;
+                screen.put_char(row, at, c, fg, Color::Default, false);
+                input_end = at + w;
+            }
+            col += w;
+        }
+        if clipped_left {
+            screen.put_char(row, start, '…', DIM_FG, Color::Default, false);
+        }
+        if clipped_right {
+            input_end = start + available;
+            screen.put_char(row, input_end - 1, '…', DIM_FG, Color::Default, false);
+        }
+        input_end
+    }
+
+    /// The screen column of the cursor, as last drawn.
+    pub fn cursor_col(&self) -> usize {
+        self.input_start() + self.shown_cursor() - self.scroll
+    }
+}
+
+/// The first visible column of input `width` columns wide after scrolling
+/// just enough to show the cursor, at display column `cursor` of an input
+/// `len` columns wide. An edge with hidden input shows an ellipsis, so the
+/// cursor stays off those columns; the column after the input is kept for
+/// the cursor at the end.
+fn scroll_to_cursor(scroll: usize, cursor: usize, len: usize, width: usize) -> usize {
+    let width = width.max(3);
+    if len < width {
+        return 0;
+    }
+    let scroll = if cursor <= scroll {
+        cursor.saturating_sub(1)
+    } else if cursor + 2 > scroll + width {
+        cursor + 2 - width
+    } else {
+        scroll
+    };
+    scroll.min(len + 1 - width)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: Key) -> KeyEvent {
+        KeyEvent {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    fn type_keys(prompt: &mut Prompt, keys: &[Key]) {
+        for k in keys {
+            prompt.handle_key(&key(k.clone()));
+        }
+    }
+
+    #[test]
+    fn test_prompt_insert_char() {
+        let mut prompt = Prompt::new("Open: ");
+        type_keys(&mut prompt, &[Key::Char('a')]);
+        assert_eq!(prompt.input, "a");
+        assert_eq!(prompt.cursor_pos, 1);
+        type_keys(&mut prompt, &[Key::Char('b')]);
+        assert_eq!(prompt.input, "ab");
+        assert_eq!(prompt.cursor_pos, 2);
+
+        // Move cursor left, insert 'x' in the middle
+        type_keys(&mut prompt, &[Key::Left, Key::Char('x')]);
+        assert_eq!(prompt.input, "axb");
+        assert_eq!(prompt.cursor_pos, 2);
+    }
+
+    #[test]
+    fn test_prompt_backspace() {
+        let mut prompt = Prompt::new("Open: ").with_input("hello");
+        type_keys(&mut prompt, &[Key::Backspace]);
+        assert_eq!(prompt.input, "hell");
+        assert_eq!(prompt.cursor_pos, 4);
+    }
+
+    #[test]
+    fn test_prompt_delete() {
+        let mut prompt = Prompt::new("Open: ").with_input("hello");
+        type_keys(&mut prompt, &[Key::Home, Key::Delete]);
+        assert_eq!(prompt.input, "ello");
+        assert_eq!(prompt.cursor_pos, 0);
+    }
+
+    #[test]
+    fn test_prompt_cursor_movement() {
+        let mut prompt = Prompt::new("Open: ").with_input("abc");
+        type_keys(&mut prompt, &[Key::Home, Key::Right]);
+        assert_eq!(prompt.cursor_pos, 1);
+        type_keys(&mut prompt, &[Key::End]);
+        assert_eq!(prompt.cursor_pos, 3);
+        type_keys(&mut prompt, &[Key::Home]);
+        assert_eq!(prompt.cursor_pos, 0);
+        // Left at start — should stay at 0
+        type_keys(&mut prompt, &[Key::Left]);
+        assert_eq!(prompt.cursor_pos, 0);
+    }
+
+    #[test]
+    fn test_prompt_utf8_navigation() {
+        // c(1) a(1) f(1) é(2) = 5 bytes
+        let mut prompt = Prompt::new("Open: ").with_input("café");
+        // Left from end — should move back over 'é' (2 bytes)
+        type_keys(&mut prompt, &[Key::Left]);
+        assert_eq!(prompt.cursor_pos, 3);
+        // Backspace 'é' — should remove 2 bytes
+        type_keys(&mut prompt, &[Key::End, Key::Backspace]);
+        assert_eq!(prompt.input, "caf");
+        assert_eq!(prompt.cursor_pos, 3);
+    }
+
+    #[test]
+    fn test_prompt_keys() {
+        let mut prompt = Prompt::new("Find: ");
+        assert_eq!(prompt.handle_key(&key(Key::Enter)), KeyResult::Accept);
+        assert_eq!(prompt.handle_key(&key(Key::Escape)), KeyResult::Cancel);
+        assert_eq!(prompt.handle_key(&key(Key::Tab)), KeyResult::Ignored);
+        let ctrl_a = KeyEvent {
+            ctrl: true,
+            ..key(Key::Char('a'))
+        };
+        assert_eq!(prompt.handle_key(&ctrl_a), KeyResult::Ignored);
+        assert_eq!(prompt.input, "");
+
+        // A choice prompt answers with a key and swallows the others
+        let mut choice = Prompt::choice("Replace? ", &[('y', "Yes"), ('a', "All")]);
+        assert_eq!(
+            choice.handle_key(&key(Key::Char('A'))),
+            KeyResult::Choice('a')
+        );
+        assert_eq!(choice.handle_key(&key(Key::Char('x'))), KeyResult::Consumed);
+        assert_eq!(choice.handle_key(&key(Key::Enter)), KeyResult::Consumed);
+        assert_eq!(choice.handle_key(&key(Key::Escape)), KeyResult::Cancel);
+        assert_eq!(choice.input, "");
+    }
+
+    #[test]
+    fn test_prompt_default_and_validation() {
+        fn not_empty(answer: &str) -> Result<(), String> {
+            if answer.is_empty() {
+                Err("empty".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        let prompt = Prompt::new("Name: ").with_validation(not_empty);
+        assert_eq!(prompt.check(), Err("empty".to_string()));
+        let mut prompt = prompt.with_default("out.html");
+        assert_eq!(prompt.answer(), "out.html");
+        assert_eq!(prompt.check(), Ok(()));
+        type_keys(&mut prompt, &[Key::Char('x')]);
+        assert_eq!(prompt.answer(), "x");
+    }
+
+    #[test]
+    fn test_prompt_render() {
+        let mut screen = Screen::new(20, 1);
+        let mut prompt = Prompt::new("Key: ").masked().with_input("sécret");
+        assert_eq!(prompt.shown(), "******");
+        prompt.follow_cursor(20);
+        assert_eq!(prompt.render(&mut screen, 0, 20), 12);
+        assert_eq!(prompt.cursor_col(), 12);
+
+        // Long input scrolls to the cursor; the ellipsis takes the last
+        // column
+        let mut prompt = Prompt::new("Open: ").with_input("abcdefghijklmnopqrstuvwxyz");
+        prompt.handle_key(&key(Key::Home));
+        prompt.follow_cursor(20);
+        assert_eq!(prompt.render(&mut screen, 0, 20), 20);
+        assert_eq!(prompt.cursor_col(), 7);
+        prompt.handle_key(&key(Key::End));
+        prompt.follow_cursor(20);
+        assert_eq!(prompt.scroll(), 14);
+        assert_eq!(prompt.cursor_col(), 19);
+
+        let choice = Prompt::choice("Overwrite? ", &[('y', "Yes"), ('n', "No")]);
+        assert_eq!(choice.shown(), "[y] Yes  [n] No");
+    }
+
+    #[test]
+    fn test_scroll_to_cursor() {
+        // Input that fits, with room for the cursor after it, never scrolls
+        assert_eq!(scroll_to_cursor(0, 9, 9, 10), 0);
+        assert_eq!(scroll_to_cursor(4, 2, 5, 10), 0);
+        // The cursor at the end sits in the last column
+        assert_eq!(scroll_to_cursor(0, 10, 10, 10), 1);
+        assert_eq!(scroll_to_cursor(0, 30, 30, 10), 21);
+        // Moving right stops short of the ellipsis on the right edge
+        assert_eq!(scroll_to_cursor(0, 7, 30, 10), 0);
+        assert_eq!(scroll_to_cursor(0, 9, 30, 10), 1);
+        assert_eq!(scroll_to_cursor(0, 15, 30, 10), 7);
+        // Moving left stops short of the ellipsis on the left edge
+        assert_eq!(scroll_to_cursor(21, 22, 30, 10), 21);
+        assert_eq!(scroll_to_cursor(21, 21, 30, 10), 20);
+        assert_eq!(scroll_to_cursor(21, 1, 30, 10), 0);
+        // Deleting input pulls the view back to its end
+        assert_eq!(scroll_to_cursor(21, 12, 15, 10), 6);
+    }
+}
@@ -0,0 +1,245 @@
+use std::path::PathBuf;
+
+// ---------------------------------------------------------------------------
+// QuickfixEntry — one `file:line:col` location from tool output
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickfixEntry {
+    pub path: PathBuf,
+    pub line: usize, // 1-based
+    pub col: usize,  // 1-based, in characters
+    pub message: String,
+}
+
+// ---------------------------------------------------------------------------
+// QuickfixList — entries plus the current position
+// ---------------------------------------------------------------------------
+
+pub struct QuickfixList {
+    pub entries: Vec<QuickfixEntry>,
+    pub current: Option<usize>,
+}
+
+impl QuickfixList {
+    pub fn new(entries: Vec<QuickfixEntry>) -> Self {
+        QuickfixList {
+            entries,
+            current: None,
+        }
+    }
+
+    /// Advance to the next entry (wrapping) and return it.
+    pub fn next(&mut self) -> Option<&QuickfixEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let idx = match self.current {
+            Some(i) => (i + 1) % self.entries.len(),
+            None => 0,
+        };
+        self.current = Some(idx);
+        self.entries.get(idx)
+    }
+
+    /// Step back to the previous entry (wrapping) and return it.
+    pub fn prev(&mut self) -> Option<&QuickfixEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let total = self.entries.len();
+        let idx = match self.current {
+            Some(0) | None => total - 1,
+            Some(i) => i - 1,
+        };
+        self.current = Some(idx);
+        self.entries.get(idx)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Extract `file:line[:col]` locations from compiler/grep style output.
+///
+/// Understands the common `path:line:col: message` form (gcc, clang, grep -n,
+/// many linters) and rustc's two-line form where the message precedes a
+/// `--> path:line:col` pointer.
+pub fn parse_diagnostics(output: &str) -> Vec<QuickfixEntry> {
+    let mut entries = Vec::new();
+    let mut last_headline = String::new();
+
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+
+        if let Some(rest) = trimmed.strip_prefix("--> ") {
+            if let Some((path, ln, col, _)) = parse_location(rest.trim()) {
+                entries.push(QuickfixEntry {
+                    path: PathBuf::from(path),
+                    line: ln,
+                    col,
+                    message: last_headline.clone(),
+                });
+            }
+            continue;
+        }
+
+        if is_headline(trimmed) {
+            last_headline = trimmed.to_string();
+            continue;
+        }
+
+        // Generic form must start at column 0 to avoid matching prose.
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        if let Some((path, ln, col, msg)) = parse_location(line) {
+            entries.push(QuickfixEntry {
+                path: PathBuf::from(path),
+                line: ln,
+                col,
+                message: msg.to_string(),
+            });
+        }
+    }
+
+    entries
+}
+
+/// rustc/cargo style "error[E0308]: ..." or "warning: ..." headline.
+fn is_headline(line: &str) -> bool {
+    ["error", "warning"].iter().any(|kind| {
+        line.strip_prefix(kind)
+            .is_some_and(|rest| rest.starts_with(':') || rest.starts_with('['))
+    })
+}
+
+/// Parse `path:line[:col][: message]`. Returns (path, line, col, message);
+/// col defaults to 1 when absent.
+fn parse_location(s: &str) -> Option<(&str, usize, usize, &str)> {
+    let (path, rest) = s.split_once(':')?;
+    if path.is_empty() || path.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let (line, rest) = take_number(rest)?;
+    let (col, rest) = match rest.strip_prefix(':').and_then(take_number) {
+        Some((col, after)) => (col, after),
+        None => (1, rest),
+    };
+    let message = rest.strip_prefix(':').unwrap_or(rest).trim();
+    if line == 0 {
+        return None;
+    }
+    Some((path, line, col.max(1), message))
+}
+
+/// Split a `path:line[:col]` reference, as compilers and grep print them
+/// (a trailing `:` included), into the path and its 1-based line and
+/// column; the column is 1 when absent. Anything else is all path.
+pub fn split_position(s: &str) -> (&str, Option<(usize, usize)>) {
+    let number = |s: &str| match take_number(s) {
+        Some((n, "")) => Some(n),
+        _ => None,
+    };
+    let trimmed = s.strip_suffix(':').unwrap_or(s);
+    let Some((rest, last)) = trimmed.rsplit_once(':') else {
+        return (s, None);
+    };
+    let Some(last) = number(last) else {
+        return (s, None);
+    };
+    let (path, line, col) = match rest
+        .rsplit_once(':')
+        .and_then(|(path, line)| Some((path, number(line)?)))
+    {
+        Some((path, line)) => (path, line, last),
+        None => (rest, last, 1),
+    };
+    if path.is_empty() || line == 0 {
+        return (s, None);
+    }
+    (path, Some((line, col.max(1))))
+}
+
+/// Split a leading decimal number off `s`.
+fn take_number(s: &str) -> Option<(usize, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    if end == 0 {
+        return None;
+    }
+    let n = s[..end].parse().ok()?;
+    Some((n, &s[end..]))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gcc_style() {
+        let out = "main.c:12:5: error: expected ';'\nmain.c:20: warning: unused\n";
+        let entries = parse_diagnostics(out);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, PathBuf::from("main.c"));
+        assert_eq!((entries[0].line, entries[0].col), (12, 5));
+        assert_eq!(entries[0].message, "error: expected ';'");
+        assert_eq!((entries[1].line, entries[1].col), (20, 1));
+    }
+
+    #[test]
+    fn test_parse_rustc_style() {
+        let out = "error[E0425]: cannot find value `x`\n  --> src/main.rs:4:13\n   |\n";
+        let entries = parse_diagnostics(out);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, PathBuf::from("src/main.rs"));
+        assert_eq!((entries[0].line, entries[0].col), (4, 13));
+        assert_eq!(entries[0].message, "error[E0425]: cannot find value `x`");
+    }
+
+    #[test]
+    fn test_split_position() {
+        assert_eq!(split_position("src/a.rs:12:5"), ("src/a.rs", Some((12, 5))));
+        assert_eq!(split_position("src/a.rs:12"), ("src/a.rs", Some((12, 1))));
+        assert_eq!(
+            split_position("src/a.rs:12:5:"),
+            ("src/a.rs", Some((12, 5)))
+        );
+        assert_eq!(split_position("src/a.rs"), ("src/a.rs", None));
+        assert_eq!(split_position("a:b.rs:3"), ("a:b.rs", Some((3, 1))));
+        assert_eq!(split_position("a.rs:x"), ("a.rs:x", None));
+        assert_eq!(split_position("a.rs:0"), ("a.rs:0", None));
+        assert_eq!(split_position(":4"), (":4", None));
+    }
+
+    #[test]
+    fn test_ignores_non_locations() {
+        let out =
+            "   Compiling zelux v0.1.0\nhttp://example.com\nnote: see here\n  12:3 indented\n";
+        assert!(parse_diagnostics(out).is_empty());
+    }
+
+    #[test]
+    fn test_next_prev_wrap() {
+        let entry = |line| QuickfixEntry {
+            path: PathBuf::from("a.rs"),
+            line,
+            col: 1,
+            message: String::new(),
+        };
+        let mut list = QuickfixList::new(vec![entry(1), entry(2)]);
+        assert_eq!(list.next().map(|e| e.line), Some(1));
+        assert_eq!(list.next().map(|e| e.line), Some(2));
+        assert_eq!(list.next().map(|e| e.line), Some(1));
+        assert_eq!(list.prev().map(|e| e.line), Some(2));
+
+        let mut empty = QuickfixList::new(Vec::new());
+        assert!(empty.next().is_none());
+        assert!(empty.prev().is_none());
+    }
+}
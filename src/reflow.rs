@@ -0,0 +1,257 @@
+// ---------------------------------------------------------------------------
+// Reflow — rewrap paragraphs of prose and comments to a text width
+// ---------------------------------------------------------------------------
+
+/// The part of `line` that reflow keeps in place: its indentation and,
+/// when it starts with the comment `marker`, the marker (doubled or with a
+/// `!`, as in `///` and `//!`) and the spaces after it.
+pub fn line_prefix<'a>(line: &'a str, marker: Option<&str>) -> &'a str {
+    let indent = line.len() - line.trim_start().len();
+    let mut end = indent;
+    if let Some(marker) = marker
+        && line[indent..].starts_with(marker)
+    {
+        end += marker.len();
+        let repeat = marker.chars().last();
+        end += line[end..]
+            .chars()
+            .take_while(|&c| Some(c) == repeat || c == '!')
+            .map(char::len_utf8)
+            .sum::<usize>();
+        end += line[end..].len() - line[end..].trim_start().len();
+    }
+    &line[..end]
+}
+
+/// Length of a list bullet at the start of `text` with the spaces after it:
+/// `- `, `* `, `+ `, `1. ` or `2) `.
+fn bullet_len(text: &str) -> Option<usize> {
+    let rest = match text.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            text[digits..].strip_prefix(['.', ')'])?
+        }
+    };
+    if !rest.starts_with(' ') {
+        return None;
+    }
+    Some(text.len() - rest.trim_start().len())
+}
+
+struct Lines<'a, S> {
+    lines: &'a [S],
+    marker: Option<&'a str>,
+}
+
+impl<S: AsRef<str>> Lines<'_, S> {
+    fn get(&self, i: usize) -> &str {
+        self.lines[i].as_ref().trim_end_matches('\r')
+    }
+
+    fn prefix(&self, i: usize) -> &str {
+        line_prefix(self.get(i), self.marker)
+    }
+
+    fn body(&self, i: usize) -> &str {
+        &self.get(i)[self.prefix(i).len()..]
+    }
+
+    fn is_blank(&self, i: usize) -> bool {
+        self.body(i).trim().is_empty()
+    }
+
+    /// Whether line `i` has text and the same kind of comment as line `of`.
+    fn is_like(&self, i: usize, of: usize) -> bool {
+        !self.is_blank(i) && self.prefix(i).trim() == self.prefix(of).trim()
+    }
+
+    fn is_bullet(&self, i: usize) -> bool {
+        bullet_len(self.body(i)).is_some()
+    }
+
+    /// Whether line `i` continues the paragraph of line `of` rather than
+    /// starting a list item.
+    fn continues(&self, i: usize, of: usize) -> bool {
+        self.is_like(i, of) && !self.is_bullet(i)
+    }
+}
+
+/// The lines of the paragraph around line `at`, or None on a blank line.
+/// Paragraphs end at blank lines, a change of comment marker and list items.
+pub fn paragraph_at<S: AsRef<str>>(
+    lines: &[S],
+    at: usize,
+    marker: Option<&str>,
+) -> Option<(usize, usize)> {
+    let lines = Lines { lines, marker };
+    if at >= lines.lines.len() || lines.is_blank(at) {
+        return None;
+    }
+    let mut first = at;
+    while first > 0 && !lines.is_bullet(first) && lines.is_like(first - 1, at) {
+        first -= 1;
+    }
+    let mut last = at;
+    while last + 1 < lines.lines.len() && lines.continues(last + 1, at) {
+        last += 1;
+    }
+    Some((first, last))
+}
+
+/// Rewrap every paragraph of `lines` to `width` columns. Each paragraph
+/// keeps its first line's prefix and bullet; later lines take the second
+/// line's prefix, or align under the bullet text. Blank lines stay put and
+/// words longer than the width get a line of their own.
+pub fn reflow<S: AsRef<str>>(lines: &[S], width: usize, marker: Option<&str>) -> Vec<String> {
+    let lines = Lines { lines, marker };
+    let crlf = lines
+        .lines
+        .first()
+        .is_some_and(|l| l.as_ref().ends_with('\r'));
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lines.lines.len() {
+        if lines.is_blank(i) {
+            out.push(lines.get(i).trim_end().to_string());
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < lines.lines.len() && lines.continues(end, i) {
+            end += 1;
+        }
+        fill(&lines, i, end, width, &mut out);
+        i = end;
+    }
+    if crlf {
+        out.iter_mut().for_each(|l| l.push('\r'));
+    }
+    out
+}
+
+fn fill<S: AsRef<str>>(
+    lines: &Lines<S>,
+    first: usize,
+    end: usize,
+    width: usize,
+    out: &mut Vec<String>,
+) {
+    let prefix = lines.prefix(first);
+    let body = lines.body(first);
+    let bullet = bullet_len(body).unwrap_or(0);
+    let first_prefix = format!("{}{}", prefix, &body[..bullet]);
+    let rest_prefix = if bullet > 0 {
+        format!("{}{}", prefix, " ".repeat(bullet))
+    } else if end - first > 1 {
+        lines.prefix(first + 1).to_string()
+    } else {
+        prefix.to_string()
+    };
+    let words = std::iter::once(&body[bullet..])
+        .chain((first + 1..end).map(|i| lines.body(i)))
+        .flat_map(str::split_whitespace);
+
+    let mut line = first_prefix;
+    let mut empty = true;
+    for word in words {
+        let len = line.chars().count() + 1 + word.chars().count();
+        if !empty && len > width {
+            out.push(line);
+            line = rest_prefix.clone();
+            empty = true;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(word);
+        empty = false;
+    }
+    out.push(line);
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_prefix() {
+        assert_eq!(line_prefix("    /// Docs", Some("//")), "    /// ");
+        assert_eq!(line_prefix("//! Crate", Some("//")), "//! ");
+        assert_eq!(line_prefix("  # note", Some("#")), "  # ");
+        assert_eq!(line_prefix("  code()", Some("//")), "  ");
+        assert_eq!(line_prefix("prose", None), "");
+    }
+
+    #[test]
+    fn test_reflow_prose() {
+        let lines = [
+            "The quick brown",
+            "fox jumps over the",
+            "lazy dog.",
+            "",
+            "Next.",
+        ];
+        assert_eq!(
+            reflow(&lines, 20, None),
+            [
+                "The quick brown fox",
+                "jumps over the lazy",
+                "dog.",
+                "",
+                "Next."
+            ]
+        );
+        // A word longer than the width stands alone
+        assert_eq!(
+            reflow(&["a https://example.com/long b"], 10, None),
+            ["a", "https://example.com/long", "b"]
+        );
+    }
+
+    #[test]
+    fn test_reflow_keeps_comment_markers() {
+        let lines = ["    // One two three", "    // four five six seven"];
+        assert_eq!(
+            reflow(&lines, 25, Some("//")),
+            ["    // One two three four", "    // five six seven"]
+        );
+    }
+
+    #[test]
+    fn test_reflow_list_items() {
+        let lines = [
+            "- first item that is",
+            "  long",
+            "- second",
+            "10. numbered item here",
+        ];
+        assert_eq!(
+            reflow(&lines, 16, None),
+            [
+                "- first item",
+                "  that is long",
+                "- second",
+                "10. numbered",
+                "    item here",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_at() {
+        let lines = ["// a", "// b", "code", "", "- x", "  y", "- z"];
+        assert_eq!(paragraph_at(&lines, 1, Some("//")), Some((0, 1)));
+        assert_eq!(paragraph_at(&lines, 2, Some("//")), Some((2, 2)));
+        assert_eq!(paragraph_at(&lines, 3, Some("//")), None);
+        assert_eq!(paragraph_at(&lines, 5, Some("//")), Some((4, 5)));
+        assert_eq!(paragraph_at(&lines, 6, Some("//")), Some((6, 6)));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::file_index::FileIndex;
+
+// ---------------------------------------------------------------------------
+// Project rename — every line a literal rename would change, file by file
+// ---------------------------------------------------------------------------
+
+/// Files larger than this are not searched.
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// One changed line of the preview. `line` is 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub path: PathBuf,
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+    pub accepted: bool,
+}
+
+/// The files under `root` to search, sorted, as the file finder lists
+/// them.
+pub fn project_files(root: &Path) -> Vec<PathBuf> {
+    FileIndex::scan(root)
+        .files()
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// The text of `path` if it looks like a text file worth searching: not
+/// too big, valid UTF-8 and free of NUL bytes.
+pub fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let text = String::from_utf8(fs::read(path).ok()?).ok()?;
+    (!text.contains('\0')).then_some(text)
+}
+
+/// Byte ranges of `pattern` in `line`, matched literally and with case.
+/// When the pattern starts or ends like an identifier, so must the match:
+/// renaming `foo` leaves `foobar` and `my_foo` alone.
+pub fn occurrences(line: &str, pattern: &str) -> Vec<(usize, usize)> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    let word_start = pattern.starts_with(word);
+    let word_end = pattern.ends_with(word);
+    line.match_indices(pattern)
+        .map(|(start, m)| (start, start + m.len()))
+        .filter(|&(start, end)| {
+            let joined_before = word_start && line[..start].ends_with(word);
+            let joined_after = word_end && line[end..].starts_with(word);
+            !joined_before && !joined_after
+        })
+        .collect()
+}
+
+/// `line` with every occurrence of `pattern` replaced.
+pub fn rename_line(line: &str, pattern: &str, replacement: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut last = 0;
+    for (start, end) in occurrences(line, pattern) {
+        out.push_str(&line[last..start]);
+        out.push_str(replacement);
+        last = end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+/// A hunk, accepted, for each line of `text` the rename changes.
+pub fn hunks(path: &Path, text: &str, pattern: &str, replacement: &str) -> Vec<Hunk> {
+    text.split('\n')
+        .enumerate()
+        .filter(|(_, line)| !occurrences(line, pattern).is_empty())
+        .map(|(n, line)| Hunk {
+            path: path.to_path_buf(),
+            line: n,
+            old: line.to_string(),
+            new: rename_line(line, pattern, replacement),
+            accepted: true,
+        })
+        .collect()
+}
+
+/// `text` with the accepted `hunks` applied, and how many of them were
+/// skipped because their line no longer reads as it did in the preview.
+pub fn apply(text: &str, hunks: &[&Hunk]) -> (String, usize) {
+    let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+    let mut stale = 0;
+    for hunk in hunks.iter().filter(|h| h.accepted) {
+        match lines.get_mut(hunk.line) {
+            Some(line) if *line == hunk.old => *line = hunk.new.clone(),
+            _ => stale += 1,
+        }
+    }
+    (lines.join("\n"), stale)
+}
+
+/// Where the original of a file edited on disk is kept: `name.bak`
+/// beside it.
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Apply the accepted `hunks` to the file at `path`, copying the original
+/// to its backup first. Returns the hunks applied and the ones skipped as
+/// stale; a file where every hunk is stale is left untouched.
+pub fn apply_to_file(path: &Path, hunks: &[&Hunk]) -> Result<(usize, usize), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let (renamed, stale) = apply(&text, hunks);
+    let applied = hunks.iter().filter(|h| h.accepted).count() - stale;
+    if applied > 0 {
+        let backup = backup_path(path);
+        fs::write(&backup, &text).map_err(|e| format!("{}: {}", backup.display(), e))?;
+        fs::write(path, renamed).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok((applied, stale))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_occurrences() {
+        assert_eq!(occurrences("foo(foo_bar, foo)", "foo"), [(0, 3), (13, 16)]);
+        assert_eq!(occurrences("my_foo foobar", "foo"), []);
+        assert_eq!(occurrences("Foo foo", "foo"), [(4, 7)]);
+        // Not identifier-like at the edges: matched anywhere
+        assert_eq!(occurrences("a::b::c", "::"), [(1, 3), (4, 6)]);
+        assert_eq!(occurrences("x.len()", ".len"), [(1, 5)]);
+        assert_eq!(occurrences("abc", ""), []);
+    }
+
+    #[test]
+    fn test_hunks_and_apply() {
+        let path = Path::new("a.rs");
+        let text = "fn foo() {}\nlet x = 1;\r\nfoo(); foo();\r\n";
+        let mut found = hunks(path, text, "foo", "bar");
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].line, found[0].new.as_str()), (0, "fn bar() {}"));
+        assert_eq!(
+            (found[1].line, found[1].new.as_str()),
+            (2, "bar(); bar();\r")
+        );
+
+        let all: Vec<&Hunk> = found.iter().collect();
+        assert_eq!(
+            apply(text, &all),
+            (
+                "fn bar() {}\nlet x = 1;\r\nbar(); bar();\r\n".to_string(),
+                0
+            )
+        );
+
+        // Rejected hunks are left alone; changed lines are stale
+        found[0].accepted = false;
+        let some: Vec<&Hunk> = found.iter().collect();
+        assert_eq!(
+            apply(text, &some).0,
+            "fn foo() {}\nlet x = 1;\r\nbar(); bar();\r\n"
+        );
+        let edited = "fn foo() {}\nlet x = 1;\r\nfoo();\r\n";
+        assert_eq!(apply(edited, &some), (edited.to_string(), 1));
+    }
+
+    #[test]
+    fn test_apply_to_file() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_rename_{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        let path = dir.join("src/a.rs");
+        fs::write(&path, "use foo;\nfoo::run();\n").unwrap();
+        fs::write(dir.join("b.txt"), "foo\n").unwrap();
+        fs::write(dir.join(".git/config"), "foo\n").unwrap();
+        fs::write(dir.join("target/out"), "foo\n").unwrap();
+        fs::write(dir.join("blob"), b"foo\0").unwrap();
+
+        let files = project_files(&dir);
+        assert_eq!(files, [dir.join("b.txt"), dir.join("blob"), path.clone()]);
+        assert_eq!(read_text(&dir.join("blob")), None);
+
+        let text = read_text(&path).unwrap();
+        let found = hunks(&path, &text, "foo", "bar");
+        let refs: Vec<&Hunk> = found.iter().collect();
+        assert_eq!(apply_to_file(&path, &refs), Ok((2, 0)));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "use bar;\nbar::run();\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("src/a.rs.bak")).unwrap(),
+            "use foo;\nfoo::run();\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
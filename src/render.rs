@@ -1,4 +1,5 @@
-use crate::terminal::{self, ColorMode};
+use crate::terminal::{ColorMode, TerminalBackend};
+use crate::unicode;
 
 // ---------------------------------------------------------------------------
 // Color
@@ -16,12 +17,16 @@ pub enum Color {
 // Cell
 // ---------------------------------------------------------------------------
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Cell {
     pub ch: char,
     pub fg: Color,
     pub bg: Color,
     pub bold: bool,
+    pub underline: bool,
+    /// Reverse video, for placeholders such as `^[` that stand for a
+    /// character which is not drawn as itself.
+    pub reverse: bool,
 }
 
 impl Default for Cell {
@@ -31,24 +36,33 @@ impl Default for Cell {
             fg: Color::Default,
             bg: Color::Default,
             bold: false,
+            underline: false,
+            reverse: false,
         }
     }
 }
 
+/// The `ch` of the cell under the right half of a wide character. The
+/// terminal draws both halves when it prints the character on the left.
+const WIDE_TAIL: char = '\0';
+
 // ---------------------------------------------------------------------------
 // Screen
 // ---------------------------------------------------------------------------
 
+/// The frame being built and the one the terminal shows, each a flat
+/// row-major grid of `width * height` cells. A wide character takes its
+/// cell and a `WIDE_TAIL` cell after it.
 pub struct Screen {
     width: usize,
     height: usize,
-    cells: Vec<Vec<Cell>>,
-    prev_cells: Vec<Vec<Cell>>,
+    cells: Vec<Cell>,
+    prev_cells: Vec<Cell>,
 }
 
 impl Screen {
     pub fn new(width: usize, height: usize) -> Self {
-        let cells = make_grid(width, height);
+        let cells = vec![Cell::default(); width * height];
         let prev_cells = Vec::new(); // empty → forces full draw on first flush
         Self {
             width,
@@ -69,21 +83,52 @@ impl Screen {
     // -- Building frames ---------------------------------------------------
 
     pub fn clear(&mut self) {
-        for row in &mut self.cells {
-            for cell in row.iter_mut() {
-                *cell = Cell::default();
-            }
-        }
+        self.cells.fill(Cell::default());
     }
 
     pub fn put_cell(&mut self, row: usize, col: usize, cell: Cell) {
-        if row < self.height && col < self.width {
-            self.cells[row][col] = cell;
+        if row >= self.height || col >= self.width {
+            return;
+        }
+        let i = row * self.width + col;
+        let wide = !cell.ch.is_ascii() && unicode::char_width(cell.ch) == 2;
+        let cells = if wide && col + 1 < self.width { 2 } else { 1 };
+        // A wide character cut by the right edge shows as a blank
+        let cell = if wide && cells == 1 {
+            Cell { ch: ' ', ..cell }
+        } else {
+            cell
+        };
+        // Half of a wide character that is being drawn over is left blank
+        if self.cells[i].ch == WIDE_TAIL {
+            self.cells[i - 1].ch = ' ';
+        }
+        let after = i + cells;
+        if col + cells < self.width && self.cells[after].ch == WIDE_TAIL {
+            self.cells[after].ch = ' ';
+        }
+        self.cells[i] = cell;
+        if cells == 2 {
+            self.cells[i + 1] = Cell {
+                ch: WIDE_TAIL,
+                ..cell
+            };
         }
     }
 
     pub fn put_char(&mut self, row: usize, col: usize, ch: char, fg: Color, bg: Color, bold: bool) {
-        self.put_cell(row, col, Cell { ch, fg, bg, bold });
+        self.put_cell(
+            row,
+            col,
+            Cell {
+                ch,
+                fg,
+                bg,
+                bold,
+                underline: false,
+                reverse: false,
+            },
+        );
     }
 
     pub fn put_str(
@@ -95,63 +140,100 @@ impl Screen {
         bg: Color,
         bold: bool,
     ) {
-        if row >= self.height {
-            return;
-        }
-        let mut c = col;
+        let mut col = col;
         for ch in text.chars() {
-            if c >= self.width {
+            if col >= self.width {
                 break;
             }
-            self.cells[row][c] = Cell { ch, fg, bg, bold };
-            c += 1;
+            self.put_char(row, col, ch, fg, bg, bold);
+            col += unicode::cell_width(ch);
         }
     }
 
-    // -- Rendering ---------------------------------------------------------
+    /// The cell at `row`, `col`; None outside the screen.
+    pub fn cell(&self, row: usize, col: usize) -> Option<Cell> {
+        (row < self.height && col < self.width).then(|| self.cells[row * self.width + col])
+    }
 
-    pub fn flush(&mut self, color_mode: &ColorMode) {
-        let buf = self.build_diff_output(color_mode);
-        if !buf.is_empty() {
-            terminal::hide_cursor();
-            terminal::write_all(&buf);
-            terminal::show_cursor();
-            terminal::flush();
+    /// The characters on `row`, each wide one once.
+    #[cfg(test)]
+    pub(crate) fn row_text(&self, row: usize) -> String {
+        let cells = &self.cells[row * self.width..(row + 1) * self.width];
+        cells
+            .iter()
+            .map(|c| c.ch)
+            .filter(|&ch| ch != WIDE_TAIL)
+            .collect()
+    }
+
+    /// Draw the cells of `src` with its top left corner at `top`, `left`,
+    /// clipped to this screen.
+    pub fn blit(&mut self, src: &Screen, top: usize, left: usize) {
+        for row in 0..src.height {
+            for col in 0..src.width {
+                let cell = src.cells[row * src.width + col];
+                // put_cell draws both halves of a wide character
+                if cell.ch != WIDE_TAIL {
+                    self.put_cell(top + row, left + col, cell);
+                }
+            }
         }
-        // Swap: prev = current, then clear current for next frame
-        self.prev_cells = self.cells.clone();
-        self.clear();
     }
 
-    // -- Resize ------------------------------------------------------------
+    /// Underline a cell that has already been drawn.
+    pub fn set_underline(&mut self, row: usize, col: usize) {
+        if row < self.height && col < self.width {
+            self.cells[row * self.width + col].underline = true;
+        }
+    }
 
-    pub fn resize(&mut self, width: usize, height: usize) {
-        self.width = width;
-        self.height = height;
-        self.cells = make_grid(width, height);
-        self.prev_cells = Vec::new(); // force full redraw
+    /// Show a cell that has already been drawn in reverse video.
+    pub fn set_reverse(&mut self, row: usize, col: usize) {
+        if row < self.height && col < self.width {
+            self.cells[row * self.width + col].reverse = true;
+        }
     }
 
-    // -- Internal ----------------------------------------------------------
+    // -- Rendering ---------------------------------------------------------
+
+    /// Write output from `diff_output` to the terminal, with the cursor
+    /// hidden while it draws.
+    pub fn send(term: &impl TerminalBackend, buf: &[u8]) {
+        if !buf.is_empty() {
+            term.write(b"\x1b[?25l");
+            term.write(buf);
+            term.write(b"\x1b[?25h");
+            term.flush();
+        }
+    }
 
-    fn build_diff_output(&self, color_mode: &ColorMode) -> Vec<u8> {
+    /// Escape output for the cells that changed since the last call, for
+    /// `send`. The frame stays in place, so the next one only redraws what
+    /// changed: `prev_cells` is brought up to date as it goes instead of
+    /// copied afterwards.
+    pub fn diff_output(&mut self, color_mode: &ColorMode) -> Vec<u8> {
         let mut buf = Vec::with_capacity(4096);
         let mut cur_fg = Color::Default;
         let mut cur_bg = Color::Default;
         let mut cur_bold = false;
-        let full_redraw = self.prev_cells.is_empty()
-            || self.prev_cells.len() != self.height
-            || (self.height > 0 && self.prev_cells[0].len() != self.width);
+        let mut cur_underline = false;
+        let mut cur_reverse = false;
+        let mono = *color_mode == ColorMode::Mono;
+        // Nothing on the terminal to compare with after a resize: draw everything
+        let full_redraw = self.prev_cells.len() != self.cells.len();
+        if full_redraw {
+            self.prev_cells = vec![Cell::default(); self.cells.len()];
+        }
 
         for row in 0..self.height {
             for col in 0..self.width {
-                let cell = &self.cells[row][col];
-                let changed = if full_redraw {
-                    true
-                } else {
-                    &self.prev_cells[row][col] != cell
-                };
-                if !changed {
+                let i = row * self.width + col;
+                let cell = self.cells[i];
+                if !full_redraw && self.prev_cells[i] == cell {
+                    continue;
+                }
+                self.prev_cells[i] = cell;
+                if cell.ch == WIDE_TAIL {
                     continue;
                 }
 
@@ -167,13 +249,34 @@ impl Screen {
                     }
                     cur_bold = cell.bold;
                 }
-                if cell.fg != cur_fg {
-                    write_fg_color(&mut buf, cell.fg, color_mode);
-                    cur_fg = cell.fg;
+                if cell.underline != cur_underline {
+                    if cell.underline {
+                        buf.extend_from_slice(b"\x1b[4m");
+                    } else {
+                        buf.extend_from_slice(b"\x1b[24m");
+                    }
+                    cur_underline = cell.underline;
                 }
-                if cell.bg != cur_bg {
-                    write_bg_color(&mut buf, cell.bg, color_mode);
-                    cur_bg = cell.bg;
+                // Without colors, backgrounds that mark something (selection,
+                // status bar, matches) become reverse video too
+                let reverse = cell.reverse != (mono && stands_out(cell.bg));
+                if reverse != cur_reverse {
+                    if reverse {
+                        buf.extend_from_slice(b"\x1b[7m");
+                    } else {
+                        buf.extend_from_slice(b"\x1b[27m");
+                    }
+                    cur_reverse = reverse;
+                }
+                if !mono {
+                    if cell.fg != cur_fg {
+                        write_fg_color(&mut buf, cell.fg, color_mode);
+                        cur_fg = cell.fg;
+                    }
+                    if cell.bg != cur_bg {
+                        write_bg_color(&mut buf, cell.bg, color_mode);
+                        cur_bg = cell.bg;
+                    }
                 }
 
                 // Write character
@@ -188,16 +291,15 @@ impl Screen {
 
         buf
     }
-}
 
-// ---------------------------------------------------------------------------
-// Grid helper
-// ---------------------------------------------------------------------------
+    // -- Resize ------------------------------------------------------------
 
-fn make_grid(width: usize, height: usize) -> Vec<Vec<Cell>> {
-    (0..height)
-        .map(|_| (0..width).map(|_| Cell::default()).collect())
-        .collect()
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.cells = vec![Cell::default(); width * height];
+        self.prev_cells = Vec::new(); // force full redraw
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -227,12 +329,16 @@ fn write_usize(buf: &mut Vec<u8>, n: usize) {
     buf[start..].reverse();
 }
 
+/// Write a cell's character. A control character that reached the screen
+/// would move the terminal's cursor or start a sequence, so it is sent as
+/// `?`.
 fn write_char(buf: &mut Vec<u8>, ch: char) {
+    let ch = if ch.is_control() { '?' } else { ch };
     let mut tmp = [0u8; 4];
     buf.extend_from_slice(ch.encode_utf8(&mut tmp).as_bytes());
 }
 
-fn write_fg_color(buf: &mut Vec<u8>, color: Color, mode: &ColorMode) {
+pub fn write_fg_color(buf: &mut Vec<u8>, color: Color, mode: &ColorMode) {
     match effective_color(color, mode) {
         Color::Default => buf.extend_from_slice(b"\x1b[39m"),
         Color::Ansi(n) => {
@@ -290,6 +396,7 @@ fn write_bg_color(buf: &mut Vec<u8>, color: Color, mode: &ColorMode) {
 
 fn effective_color(color: Color, mode: &ColorMode) -> Color {
     match (color, mode) {
+        (_, ColorMode::Mono) => Color::Default,
         (Color::Rgb(r, g, b), ColorMode::Color256) => Color::Color256(rgb_to_ansi256(r, g, b)),
         (Color::Rgb(r, g, b), ColorMode::Color16) => {
             Color::Ansi(ansi256_to_ansi16(rgb_to_ansi256(r, g, b)))
@@ -370,7 +477,18 @@ pub fn ansi256_to_ansi16(n: u8) -> u8 {
     }
 }
 
-fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
+/// Whether a background is light enough to mark text rather than tint it.
+/// Dark shades (panels, the current line) are dropped in `ColorMode::Mono`.
+fn stands_out(bg: Color) -> bool {
+    let (r, g, b) = match bg {
+        Color::Default => return false,
+        Color::Ansi(n) | Color::Color256(n) => ansi256_to_rgb(n),
+        Color::Rgb(r, g, b) => (r, g, b),
+    };
+    r.max(g).max(b) >= 96
+}
+
+pub fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
     static ANSI_BASIC: [(u8, u8, u8); 16] = [
         (0, 0, 0),
         (128, 0, 0),
@@ -416,32 +534,32 @@ fn ansi256_to_rgb(n: u8) -> (u8, u8, u8) {
 mod tests {
     use super::*;
 
+    fn cell(s: &Screen, row: usize, col: usize) -> Cell {
+        s.cells[row * s.width + col]
+    }
+
     #[test]
     fn new_screen_dimensions() {
         let s = Screen::new(80, 24);
         assert_eq!(s.width(), 80);
         assert_eq!(s.height(), 24);
-        assert_eq!(s.cells.len(), 24);
-        assert_eq!(s.cells[0].len(), 80);
+        assert_eq!(s.cells.len(), 80 * 24);
     }
 
     #[test]
     fn new_screen_default_cells() {
         let s = Screen::new(3, 2);
-        for row in &s.cells {
-            for cell in row {
-                assert_eq!(*cell, Cell::default());
-            }
-        }
+        assert!(s.cells.iter().all(|c| *c == Cell::default()));
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn put_char_populates_cell() {
         let mut s = Screen::new(10, 5);
         s.put_char(2, 3, 'A', Color::Rgb(255, 0, 0), Color::Default, true);
-        assert_eq!(s.cells[2][3].ch, 'A');
-        assert_eq!(s.cells[2][3].fg, Color::Rgb(255, 0, 0));
-        assert_eq!(s.cells[2][3].bold, true);
+        assert_eq!(cell(&s, 2, 3).ch, 'A');
+        assert_eq!(cell(&s, 2, 3).fg, Color::Rgb(255, 0, 0));
+        assert_eq!(cell(&s, 2, 3).bold, true);
     }
 
     #[test]
@@ -455,18 +573,18 @@ mod tests {
     fn put_str_populates_cells() {
         let mut s = Screen::new(10, 5);
         s.put_str(0, 0, "Hi!", Color::Default, Color::Default, false);
-        assert_eq!(s.cells[0][0].ch, 'H');
-        assert_eq!(s.cells[0][1].ch, 'i');
-        assert_eq!(s.cells[0][2].ch, '!');
-        assert_eq!(s.cells[0][3].ch, ' '); // untouched
+        assert_eq!(cell(&s, 0, 0).ch, 'H');
+        assert_eq!(cell(&s, 0, 1).ch, 'i');
+        assert_eq!(cell(&s, 0, 2).ch, '!');
+        assert_eq!(cell(&s, 0, 3).ch, ' '); // untouched
     }
 
     #[test]
     fn put_str_truncates_at_edge() {
         let mut s = Screen::new(5, 1);
         s.put_str(0, 3, "Hello", Color::Default, Color::Default, false);
-        assert_eq!(s.cells[0][3].ch, 'H');
-        assert_eq!(s.cells[0][4].ch, 'e');
+        assert_eq!(cell(&s, 0, 3).ch, 'H');
+        assert_eq!(cell(&s, 0, 4).ch, 'e');
         // "llo" should be truncated
     }
 
@@ -482,7 +600,7 @@ mod tests {
         let mut s = Screen::new(5, 3);
         s.put_char(1, 2, 'Z', Color::Ansi(1), Color::Ansi(2), true);
         s.clear();
-        assert_eq!(s.cells[1][2], Cell::default());
+        assert_eq!(cell(&s, 1, 2), Cell::default());
     }
 
     #[test]
@@ -491,24 +609,104 @@ mod tests {
         s.resize(20, 10);
         assert_eq!(s.width(), 20);
         assert_eq!(s.height(), 10);
-        assert_eq!(s.cells.len(), 10);
-        assert_eq!(s.cells[0].len(), 20);
+        assert_eq!(s.cells.len(), 200);
     }
 
     #[test]
     fn unchanged_screen_empty_diff() {
         let mut s = Screen::new(5, 3);
         // First flush: full draw (prev_cells empty)
-        let first = s.build_diff_output(&ColorMode::TrueColor);
+        let first = s.diff_output(&ColorMode::TrueColor);
         assert!(!first.is_empty());
 
-        // Copy current into prev (simulate flush without actual terminal I/O)
-        s.prev_cells = s.cells.clone();
-        s.clear();
-
         // Second flush with identical content: no diff
-        let second = s.build_diff_output(&ColorMode::TrueColor);
+        let second = s.diff_output(&ColorMode::TrueColor);
         assert!(second.is_empty());
+
+        // Only the changed cell is redrawn
+        s.put_char(1, 2, 'Z', Color::Default, Color::Default, false);
+        let third = s.diff_output(&ColorMode::TrueColor);
+        assert_eq!(third, b"\x1b[2;3HZ\x1b[0m");
+    }
+
+    #[test]
+    fn frame_kept_after_diff_and_resent_after_resize() {
+        let mut s = Screen::new(3, 2);
+        s.put_char(0, 2, 'a', Color::Default, Color::Default, false);
+        s.put_char(1, 0, 'b', Color::Default, Color::Default, false);
+        s.diff_output(&ColorMode::TrueColor);
+        // The frame stays for the next one to draw over
+        assert_eq!(cell(&s, 0, 2).ch, 'a');
+        assert_eq!(cell(&s, 1, 0).ch, 'b');
+
+        // Cells either side of a row boundary are told apart
+        s.put_char(1, 0, 'c', Color::Default, Color::Default, false);
+        assert_eq!(s.diff_output(&ColorMode::TrueColor), b"\x1b[2;1Hc\x1b[0m");
+
+        // After a resize every cell is sent again
+        s.resize(2, 1);
+        let out = String::from_utf8(s.diff_output(&ColorMode::TrueColor)).unwrap();
+        assert_eq!(out, "\x1b[1;1H \x1b[1;2H \x1b[0m");
+    }
+
+    #[test]
+    fn mono_uses_reverse_video_for_backgrounds() {
+        let mut s = Screen::new(4, 1);
+        s.put_str(0, 0, "ab", Color::Ansi(1), Color::Ansi(7), true);
+        s.put_char(0, 2, 'c', Color::Default, Color::Color256(236), false);
+        let out = String::from_utf8(s.diff_output(&ColorMode::Mono)).unwrap();
+        // Bold and reverse for the light background, neither for the dark
+        // shade, and no colors at all
+        assert!(out.starts_with("\x1b[1;1H\x1b[1m\x1b[7ma"));
+        assert!(out.contains("\x1b[1;3H\x1b[22m\x1b[27mc"));
+        assert!(!out.contains("\x1b[3") && !out.contains("\x1b[4"));
+    }
+
+    #[test]
+    fn wide_chars_take_two_cells() {
+        let mut s = Screen::new(6, 1);
+        s.put_str(0, 0, "日本x", Color::Default, Color::Default, false);
+        assert_eq!(cell(&s, 0, 0).ch, '日');
+        assert_eq!(cell(&s, 0, 1).ch, WIDE_TAIL);
+        assert_eq!(cell(&s, 0, 2).ch, '本');
+        assert_eq!(cell(&s, 0, 4).ch, 'x');
+        // Only the characters are sent; the terminal fills their right halves
+        let out = String::from_utf8(s.diff_output(&ColorMode::TrueColor)).unwrap();
+        assert!(!out.contains(WIDE_TAIL));
+        assert!(out.contains("\x1b[1;3H本"));
+
+        // Drawing over either half blanks the other
+        s.put_char(0, 1, 'a', Color::Default, Color::Default, false);
+        s.put_char(0, 2, 'b', Color::Default, Color::Default, false);
+        let row: String = (0..6).map(|c| cell(&s, 0, c).ch).collect();
+        assert_eq!(row, " ab x ");
+        // No room for the right half at the edge
+        s.put_char(0, 5, '日', Color::Default, Color::Default, false);
+        assert_eq!(cell(&s, 0, 5).ch, ' ');
+    }
+
+    #[test]
+    fn send_hides_cursor_while_drawing() {
+        let term = crate::terminal::MockTerminal::new(b"");
+        Screen::send(&term, b"");
+        assert_eq!(term.output(), "");
+        Screen::send(&term, b"\x1b[1;1Hab");
+        assert_eq!(term.output(), "\x1b[?25l\x1b[1;1Hab\x1b[?25h");
+    }
+
+    #[test]
+    fn blit_places_and_clips() {
+        let mut src = Screen::new(3, 2);
+        src.put_str(0, 0, "ab日", Color::Ansi(1), Color::Default, false);
+        src.put_str(1, 0, "cde", Color::Default, Color::Default, false);
+        let mut s = Screen::new(5, 2);
+        s.blit(&src, 1, 2);
+        let row: String = (0..5).map(|c| s.cell(1, c).unwrap().ch).collect();
+        // The wide character has no room for its right half
+        assert_eq!(row, "  ab ");
+        assert_eq!(s.cell(1, 2).unwrap().fg, Color::Ansi(1));
+        assert_eq!(s.cell(0, 2).unwrap(), Cell::default());
+        assert_eq!(s.cell(2, 0), None);
     }
 
     #[test]
@@ -557,10 +755,35 @@ mod tests {
             fg: Color::Default,
             bg: Color::Default,
             bold: false,
+            underline: false,
+            reverse: false,
         };
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn underline_is_emitted_and_reset() {
+        let mut screen = Screen::new(2, 1);
+        screen.put_str(0, 0, "ab", Color::Default, Color::Default, false);
+        screen.set_underline(0, 0);
+        let out = String::from_utf8(screen.diff_output(&ColorMode::TrueColor)).unwrap();
+        let on = out.find("\x1b[4ma").unwrap();
+        assert!(out[on..].contains("\x1b[24m"));
+    }
+
+    #[test]
+    fn reverse_is_emitted_and_control_chars_are_not() {
+        let mut screen = Screen::new(3, 1);
+        screen.put_str(0, 0, "^[", Color::Default, Color::Default, false);
+        screen.set_reverse(0, 0);
+        screen.set_reverse(0, 1);
+        screen.put_char(0, 2, '\x1b', Color::Default, Color::Default, false);
+        let out = String::from_utf8(screen.diff_output(&ColorMode::TrueColor)).unwrap();
+        assert!(out.contains("\x1b[7m^"));
+        assert!(out.contains("\x1b[27m?"));
+        assert_eq!(out.matches('\x1b').count(), out.matches("\x1b[").count());
+    }
+
     #[test]
     fn color_downgrade_rgb_to_256() {
         let c = effective_color(Color::Rgb(255, 0, 0), &ColorMode::Color256);
@@ -596,4 +819,34 @@ mod tests {
         write_usize(&mut buf, 123);
         assert_eq!(buf, b"123");
     }
+
+    #[test]
+    #[ignore]
+    fn bench_full_frame_diff() {
+        let (width, height) = (240, 70);
+        let mut s = Screen::new(width, height);
+        let mut frame = 0;
+        crate::bench::within(
+            "render: full-frame diff",
+            200,
+            std::time::Duration::from_millis(5),
+            || {
+                frame += 1;
+                for row in 0..height {
+                    let ch = (b'a' + ((row + frame) % 26) as u8) as char;
+                    let fg = Color::Color256(((row + frame) % 256) as u8);
+                    for col in 0..width {
+                        s.put_char(row, col, ch, fg, Color::Default, col % 7 == 0);
+                    }
+                }
+                s.diff_output(&ColorMode::TrueColor)
+            },
+        );
+        crate::bench::within(
+            "render: unchanged-frame diff",
+            200,
+            std::time::Duration::from_millis(1),
+            || s.diff_output(&ColorMode::TrueColor),
+        );
+    }
 }
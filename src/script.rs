@@ -0,0 +1,595 @@
+use std::fmt;
+
+// ---------------------------------------------------------------------------
+// Script — a small language for user-defined commands
+// ---------------------------------------------------------------------------
+//
+// Scripts come from the "scripts" section of config.json and run as commands
+// of their own, bindable to keys like any other:
+//
+//   let text = selection()
+//   if text == "" { status("Nothing selected") }
+//   else { insert("[" + text + "](" + clipboard() + ")") }
+//
+// Values are strings and integers. `+` adds two integers and joins anything
+// else as text; `-` subtracts; `==` and `!=` give 1 or 0. In a condition the
+// empty string and 0 are false. Statements are separated by newlines or `;`.
+//
+// Functions are built in (`len`, `upper`, `lower`, `trim`, `replace`,
+// `date`) or supplied by the editor through `Host`.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+}
+
+impl Value {
+    fn is_true(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::Int(n) => *n != 0,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => f.write_str(s),
+            Value::Int(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+/// The functions the editor provides to scripts.
+pub trait Host {
+    /// Call `name`, or return None when there is no such function.
+    fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>>;
+}
+
+// ---------------------------------------------------------------------------
+// Tokens
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Sym(&'static str),
+    Newline,
+}
+
+const SYMBOLS: &[&str] = &["==", "!=", "=", "+", "-", "(", ")", "{", "}", ",", ";"];
+
+/// Split `source` into tokens, each with its 1-based line.
+fn tokenize(source: &str) -> Result<Vec<(Tok, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c == '\n' {
+            tokens.push((Tok::Newline, line));
+            line += 1;
+            chars.next();
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, c)) => text.push(c),
+                        None => return Err(format!("line {}: unterminated string", line)),
+                    },
+                    Some((_, '\n')) | None => {
+                        return Err(format!("line {}: unterminated string", line));
+                    }
+                    Some((_, c)) => text.push(c),
+                }
+            }
+            tokens.push((Tok::Str(text), line));
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some((_, d)) = chars.next_if(|(_, d)| d.is_ascii_digit()) {
+                digits.push(d);
+            }
+            let n = digits
+                .parse()
+                .map_err(|_| format!("line {}: number too large", line))?;
+            tokens.push((Tok::Int(n), line));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some((_, d)) = chars.next_if(|(_, d)| d.is_alphanumeric() || *d == '_') {
+                word.push(d);
+            }
+            tokens.push((Tok::Ident(word), line));
+        } else if let Some(sym) = SYMBOLS.iter().find(|s| source[at..].starts_with(**s)) {
+            for _ in 0..sym.len() {
+                chars.next();
+            }
+            tokens.push((Tok::Sym(sym), line));
+        } else {
+            return Err(format!("line {}: unexpected '{}'", line, c));
+        }
+    }
+    Ok(tokens)
+}
+
+// ---------------------------------------------------------------------------
+// Parser
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Lit(Value),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stmt {
+    Let(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Expr(Expr),
+}
+
+/// A parsed script, ready to run any number of times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    body: Vec<Stmt>,
+}
+
+struct Parser {
+    tokens: Vec<(Tok, usize)>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.at).map(|(t, _)| t)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.at)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, what: &str) -> String {
+        match self.peek() {
+            Some(_) => format!("line {}: {}", self.line(), what),
+            None => format!("line {}: {} at end of script", self.line(), what),
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Sym(s)) if *s == sym) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), String> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", sym)))
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(Tok::Newline | Tok::Sym(";"))) {
+            self.at += 1;
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Tok::Newline) {
+            self.at += 1;
+        }
+    }
+
+    /// Statements up to the end of the script, or a `}` when `in_block`.
+    fn statements(&mut self, in_block: bool) -> Result<Vec<Stmt>, String> {
+        let mut body = Vec::new();
+        loop {
+            self.skip_separators();
+            match self.peek() {
+                None if in_block => return Err(self.error("expected '}'")),
+                None => return Ok(body),
+                Some(Tok::Sym("}")) if in_block => {
+                    self.at += 1;
+                    return Ok(body);
+                }
+                _ => body.push(self.statement()?),
+            }
+            if !matches!(
+                self.peek(),
+                None | Some(Tok::Newline | Tok::Sym(";") | Tok::Sym("}"))
+            ) {
+                return Err(self.error("expected the end of the statement"));
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        match self.peek() {
+            Some(Tok::Ident(word)) if word == "let" => {
+                self.at += 1;
+                let Some(Tok::Ident(name)) = self.peek().cloned() else {
+                    return Err(self.error("expected a variable name"));
+                };
+                self.at += 1;
+                self.expect("=")?;
+                Ok(Stmt::Let(name, self.expr()?))
+            }
+            Some(Tok::Ident(word)) if word == "if" => {
+                self.at += 1;
+                let cond = self.expr()?;
+                self.skip_newlines();
+                self.expect("{")?;
+                let then = self.statements(true)?;
+                // `else` may start the next line
+                let mark = self.at;
+                self.skip_newlines();
+                let otherwise = match self.peek() {
+                    Some(Tok::Ident(word)) if word == "else" => {
+                        self.at += 1;
+                        self.skip_newlines();
+                        if matches!(self.peek(), Some(Tok::Ident(w)) if w == "if") {
+                            vec![self.statement()?]
+                        } else {
+                            self.expect("{")?;
+                            self.statements(true)?
+                        }
+                    }
+                    _ => {
+                        self.at = mark;
+                        Vec::new()
+                    }
+                };
+                Ok(Stmt::If(cond, then, otherwise))
+            }
+            _ => Ok(Stmt::Expr(self.expr()?)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        for op in ["==", "!="] {
+            if self.eat(op) {
+                let right = self.sum()?;
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.primary()?;
+        loop {
+            let op = if self.eat("+") {
+                "+"
+            } else if self.eat("-") {
+                "-"
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.primary()?));
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some(tok) = self.peek().cloned() else {
+            return Err(self.error("expected a value"));
+        };
+        self.at += 1;
+        match tok {
+            Tok::Str(s) => Ok(Expr::Lit(Value::Str(s))),
+            Tok::Int(n) => Ok(Expr::Lit(Value::Int(n))),
+            Tok::Ident(name) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return Err(self.error("expected ',' or ')'"));
+                        }
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Tok::Ident(name) => Ok(Expr::Var(name)),
+            Tok::Sym("(") => {
+                let inner = self.expr()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            _ => {
+                self.at -= 1;
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            at: 0,
+        };
+        Ok(Script {
+            body: parser.statements(false)?,
+        })
+    }
+
+    /// Run the script, calling on `host` for the editor's functions.
+    pub fn run(&self, host: &mut dyn Host) -> Result<(), String> {
+        let mut vars = Vec::new();
+        run_block(&self.body, &mut vars, host)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Interpreter
+// ---------------------------------------------------------------------------
+
+type Vars = Vec<(String, Value)>;
+
+fn run_block(body: &[Stmt], vars: &mut Vars, host: &mut dyn Host) -> Result<(), String> {
+    for stmt in body {
+        match stmt {
+            Stmt::Let(name, expr) => {
+                let value = eval(expr, vars, host)?;
+                match vars.iter_mut().find(|(n, _)| n == name) {
+                    Some((_, slot)) => *slot = value,
+                    None => vars.push((name.clone(), value)),
+                }
+            }
+            Stmt::If(cond, then, otherwise) => {
+                if eval(cond, vars, host)?.is_true() {
+                    run_block(then, vars, host)?;
+                } else {
+                    run_block(otherwise, vars, host)?;
+                }
+            }
+            Stmt::Expr(expr) => {
+                eval(expr, vars, host)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn eval(expr: &Expr, vars: &Vars, host: &mut dyn Host) -> Result<Value, String> {
+    match expr {
+        Expr::Lit(value) => Ok(value.clone()),
+        Expr::Var(name) => vars
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("unknown variable {}", name)),
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, vars, host)?;
+            let right = eval(right, vars, host)?;
+            match (*op, left, right) {
+                ("==", l, r) => Ok(Value::Int((l.to_string() == r.to_string()) as i64)),
+                ("!=", l, r) => Ok(Value::Int((l.to_string() != r.to_string()) as i64)),
+                ("+", Value::Int(l), Value::Int(r)) => Ok(Value::Int(l.wrapping_add(r))),
+                ("+", l, r) => Ok(Value::Str(format!("{}{}", l, r))),
+                ("-", Value::Int(l), Value::Int(r)) => Ok(Value::Int(l.wrapping_sub(r))),
+                (op, _, _) => Err(format!("'{}' needs two numbers", op)),
+            }
+        }
+        Expr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|a| eval(a, vars, host))
+                .collect::<Result<Vec<_>, _>>()?;
+            match builtin(name, &args) {
+                Some(result) => result,
+                None => host
+                    .call(name, &args)
+                    .unwrap_or_else(|| Err(format!("unknown function {}", name))),
+            }
+        }
+    }
+}
+
+/// The string argument `i` of a call to `name`.
+pub fn arg<'a>(name: &str, args: &'a [Value], i: usize) -> Result<&'a str, String> {
+    match args.get(i) {
+        Some(Value::Str(s)) => Ok(s),
+        Some(Value::Int(_)) => Err(format!("{}: argument {} must be text", name, i + 1)),
+        None => Err(format!("{}: missing argument {}", name, i + 1)),
+    }
+}
+
+fn builtin(name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+    let text = |i| arg(name, args, i);
+    let result = match name {
+        "len" => text(0).map(|s| Value::Int(s.chars().count() as i64)),
+        "upper" => text(0).map(|s| s.to_uppercase().into()),
+        "lower" => text(0).map(|s| s.to_lowercase().into()),
+        "trim" => text(0).map(|s| s.trim().into()),
+        "replace" => text(0).and_then(|s| Ok(s.replace(text(1)?, text(2)?).into())),
+        "date" => {
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            text(0).map(|f| format_date(f, secs).into())
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// The year, month and day `days` after 1970-01-01 (Howard Hinnant's
+/// algorithm).
+pub fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format the UTC time `secs` after the epoch: `%Y`, `%m`, `%d`, `%H`, `%M`,
+/// `%S` and `%%` are replaced, the rest is copied.
+fn format_date(format: &str, secs: i64) -> String {
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_date(days);
+
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", rem / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", rem / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", rem % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A host with a selection and an output log.
+    struct Fake {
+        selection: String,
+        output: Vec<String>,
+    }
+
+    impl Host for Fake {
+        fn call(&mut self, name: &str, args: &[Value]) -> Option<Result<Value, String>> {
+            match name {
+                "selection" => Some(Ok(self.selection.as_str().into())),
+                "insert" | "status" => {
+                    self.output.push(format!("{}: {}", name, args[0]));
+                    Some(Ok(Value::Int(0)))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    fn run(source: &str, selection: &str) -> Result<Vec<String>, String> {
+        let mut host = Fake {
+            selection: selection.to_string(),
+            output: Vec::new(),
+        };
+        Script::parse(source)?.run(&mut host)?;
+        Ok(host.output)
+    }
+
+    #[test]
+    fn test_markdown_link() {
+        let source = "let text = selection()\n\
+                      if text == \"\" { status(\"Nothing selected\") }\n\
+                      else { insert(\"[\" + text + \"](url)\") }";
+        assert_eq!(
+            run(source, "zelux"),
+            Ok(vec!["insert: [zelux](url)".into()])
+        );
+        assert_eq!(run(source, ""), Ok(vec!["status: Nothing selected".into()]));
+    }
+
+    #[test]
+    fn test_values_and_builtins() {
+        let source = r#"let n = len("héllo") + 2; insert(n - 1)
+            insert(upper("a") + 1 + 2) # joined as text
+            insert(replace(trim("  a-b "), "-", "+"))
+            if 0 { insert("no") } else if "x" != "x" { insert("no") } else { insert("yes") }"#;
+        assert_eq!(
+            run(source, ""),
+            Ok(vec![
+                "insert: 6".into(),
+                "insert: A12".into(),
+                "insert: a+b".into(),
+                "insert: yes".into(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Script::parse("insert(\"a\"\nx").unwrap_err(),
+            "line 1: expected ',' or ')'"
+        );
+        assert_eq!(
+            Script::parse("let = 1").unwrap_err(),
+            "line 1: expected a variable name"
+        );
+        assert!(Script::parse("if 1 { insert(\"a\")").is_err());
+        assert!(Script::parse("\"open").is_err());
+        assert_eq!(run("nope()", ""), Err("unknown function nope".into()));
+        assert_eq!(run("insert(x)", ""), Err("unknown variable x".into()));
+        assert_eq!(
+            run("upper(1)", ""),
+            Err("upper: argument 1 must be text".into())
+        );
+    }
+
+    #[test]
+    fn test_format_date() {
+        // 2024-02-29 13:05:09 UTC
+        assert_eq!(
+            format_date("%Y-%m-%d %H:%M:%S %%", 1_709_211_909),
+            "2024-02-29 13:05:09 %"
+        );
+        assert_eq!(format_date("%d.%m.%Y", 0), "01.01.1970");
+    }
+}
@@ -0,0 +1,142 @@
+use std::ops::Range;
+
+// ---------------------------------------------------------------------------
+// Side column — scrollbar or minimap at the right edge of the text area
+// ---------------------------------------------------------------------------
+
+/// Cells of condensed text in the minimap; one more column holds marks.
+const MINIMAP_CELLS: usize = 8;
+/// Buffer columns folded into one minimap cell.
+const CHARS_PER_CELL: usize = 4;
+/// Lines sampled per minimap row, so huge files stay cheap to draw.
+pub const MAX_SAMPLED_LINES: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SideColumn {
+    Off,
+    Scrollbar,
+    Minimap,
+}
+
+impl SideColumn {
+    pub fn from_name(name: &str) -> Option<SideColumn> {
+        match name {
+            "off" | "none" => Some(SideColumn::Off),
+            "scrollbar" => Some(SideColumn::Scrollbar),
+            "minimap" => Some(SideColumn::Minimap),
+            _ => None,
+        }
+    }
+
+    /// Screen columns taken from the text area.
+    pub fn width(self) -> usize {
+        match self {
+            SideColumn::Off => 0,
+            SideColumn::Scrollbar => 1,
+            SideColumn::Minimap => MINIMAP_CELLS + 1,
+        }
+    }
+}
+
+/// First buffer line represented by `row` of a column `height` rows tall.
+/// Buffers shorter than the column get one line per row.
+pub fn line_at_row(row: usize, height: usize, total: usize) -> usize {
+    if total <= height {
+        row
+    } else {
+        row * total / height
+    }
+}
+
+/// Lines summarized by `row`; empty past the end of a short buffer.
+pub fn row_lines(row: usize, height: usize, total: usize) -> Range<usize> {
+    let start = line_at_row(row, height, total).min(total);
+    let end = line_at_row(row + 1, height, total).clamp(start, total);
+    start..end.max((start + 1).min(total))
+}
+
+/// The scrollbar thumb as (first row, row count). It fills the column when
+/// the whole buffer is visible and touches the bottom when scrolled to the end.
+pub fn thumb(scroll: usize, visible: usize, total: usize, height: usize) -> (usize, usize) {
+    if height == 0 || total <= visible {
+        return (0, height);
+    }
+    let len = (height * visible / total).clamp(1, height);
+    let max_scroll = total - visible;
+    let start = (scroll.min(max_scroll) * (height - len) + max_scroll / 2) / max_scroll;
+    (start, len)
+}
+
+/// Scroll row that centers the viewport on the line under `row`, for
+/// clicking and dragging in the column.
+pub fn scroll_for_row(row: usize, height: usize, total: usize, visible: usize) -> usize {
+    let line = line_at_row(row.min(height.saturating_sub(1)), height, total);
+    line.saturating_sub(visible / 2)
+        .min(total.saturating_sub(visible))
+}
+
+/// Condensed picture of `lines`: one shade per `CHARS_PER_CELL` columns,
+/// darker where more of the cell holds non-blank text.
+pub fn minimap_cells<S: AsRef<str>>(lines: &[S]) -> [char; MINIMAP_CELLS] {
+    let mut filled = [0usize; MINIMAP_CELLS];
+    for line in lines {
+        for (i, c) in line.as_ref().chars().enumerate() {
+            let cell = i / CHARS_PER_CELL;
+            if cell >= MINIMAP_CELLS {
+                break;
+            }
+            if !c.is_whitespace() {
+                filled[cell] += 1;
+            }
+        }
+    }
+    let capacity = (lines.len() * CHARS_PER_CELL).max(1);
+    filled.map(|n| match n {
+        0 => ' ',
+        n if n * 3 <= capacity => '░',
+        n if n * 3 <= capacity * 2 => '▒',
+        _ => '▓',
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumb_geometry() {
+        // Everything visible: the thumb fills the track
+        assert_eq!(thumb(0, 20, 10, 20), (0, 20));
+        // 100 lines, 20 visible, 20 rows: a 4-row thumb moving over 16 rows
+        assert_eq!(thumb(0, 20, 100, 20), (0, 4));
+        assert_eq!(thumb(40, 20, 100, 20), (8, 4));
+        assert_eq!(thumb(80, 20, 100, 20), (16, 4));
+        assert_eq!(thumb(500, 20, 100, 20), (16, 4));
+        // Very long buffers keep at least one row
+        assert_eq!(thumb(0, 20, 100_000, 20).1, 1);
+    }
+
+    #[test]
+    fn test_rows_and_scrolling() {
+        assert_eq!(row_lines(3, 20, 10), 3..4);
+        assert_eq!(row_lines(12, 20, 10), 10..10);
+        assert_eq!(row_lines(0, 20, 100), 0..5);
+        assert_eq!(row_lines(19, 20, 100), 95..100);
+        assert_eq!(scroll_for_row(0, 20, 100, 20), 0);
+        assert_eq!(scroll_for_row(10, 20, 100, 20), 40);
+        assert_eq!(scroll_for_row(19, 20, 100, 20), 80);
+        assert_eq!(scroll_for_row(5, 20, 10, 20), 0);
+    }
+
+    #[test]
+    fn test_minimap_cells() {
+        let cells = minimap_cells(&["fn main() {", "    x;", "}"]);
+        assert_eq!(&cells[..4], &['░', '▒', '░', ' ']);
+        assert_eq!(minimap_cells(&["abcdefg"])[..2], ['▓', '▓']);
+        assert_eq!(minimap_cells::<&str>(&[]), [' '; MINIMAP_CELLS]);
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json::JsonValue;
+
+// ---------------------------------------------------------------------------
+// Session — what `zelux --continue` brings back after the terminal is lost
+// ---------------------------------------------------------------------------
+//
+// When the terminal hangs up (SIGHUP from an SSH drop or a closed window)
+// the editor writes the open buffers, cursor positions and panel size to
+// `<state dir>/sessions/<time>-<pid>/session.json`, and the unsaved text of
+// each modified buffer to `recovery/<n>` beside it, then exits. Each
+// instance has a directory of its own, so several losing the terminal
+// together keep their text apart. `zelux --continue` reopens the newest
+// with the recovered text as unsaved changes; the files go once that
+// text is saved or the editor quits.
+
+const SESSIONS_DIR: &str = "sessions";
+const SESSION_FILE: &str = "session.json";
+const RECOVERY_DIR: &str = "recovery";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDocument {
+    /// Absolute, so the session can be continued from another directory.
+    /// None for an unnamed buffer.
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub col: usize,
+    /// The buffer's text when it had unsaved changes.
+    pub unsaved: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Session {
+    pub active: usize,
+    pub documents: Vec<SessionDocument>,
+    /// Entry rows of the quickfix panel, as its header was dragged.
+    pub panel_rows: Option<usize>,
+}
+
+impl Session {
+    /// Write the session and its recovery files to `dir` (see
+    /// `new_session_dir`), replacing any earlier ones.
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        let recovery = dir.join(RECOVERY_DIR);
+        let _ = fs::remove_dir_all(&recovery);
+        fs::create_dir_all(&recovery).map_err(|e| e.to_string())?;
+        let mut documents = Vec::new();
+        for (i, doc) in self.documents.iter().enumerate() {
+            let recovery_name = match &doc.unsaved {
+                Some(text) => {
+                    let name = i.to_string();
+                    fs::write(recovery.join(&name), text).map_err(|e| e.to_string())?;
+                    JsonValue::from(name)
+                }
+                None => JsonValue::Null,
+            };
+            documents.push(JsonValue::object(vec![
+                (
+                    "path",
+                    doc.path
+                        .as_ref()
+                        .map_or(JsonValue::Null, |p| p.to_string_lossy().as_ref().into()),
+                ),
+                ("line", doc.line.into()),
+                ("col", doc.col.into()),
+                ("recovery", recovery_name),
+            ]));
+        }
+        let json = JsonValue::object(vec![
+            ("active", self.active.into()),
+            ("documents", JsonValue::Array(documents)),
+            (
+                "panel_rows",
+                self.panel_rows.map_or(JsonValue::Null, JsonValue::from),
+            ),
+        ]);
+        // Written aside and renamed, so a session file is always whole
+        let temp = dir.join(format!("{}.tmp", SESSION_FILE));
+        fs::write(&temp, json.to_string()).map_err(|e| e.to_string())?;
+        fs::rename(&temp, dir.join(SESSION_FILE)).map_err(|e| e.to_string())
+    }
+
+    /// Read the session saved in `dir`, with the recovered text.
+    pub fn load(dir: &Path) -> Result<Session, String> {
+        let text = fs::read_to_string(dir.join(SESSION_FILE)).map_err(|e| e.to_string())?;
+        let json = JsonValue::parse(&text).map_err(|e| format!("{}: {}", SESSION_FILE, e))?;
+        let mut session = Session {
+            active: json.get("active").and_then(|v| v.as_usize()).unwrap_or(0),
+            documents: Vec::new(),
+            panel_rows: json.get("panel_rows").and_then(|v| v.as_usize()),
+        };
+        for doc in json
+            .get("documents")
+            .and_then(|v| v.as_array())
+            .unwrap_or_default()
+        {
+            let number = |key: &str| doc.get(key).and_then(|v| v.as_usize()).unwrap_or(0);
+            let unsaved = match doc.get("recovery").and_then(|v| v.as_str()) {
+                Some(name) => Some(
+                    fs::read_to_string(dir.join(RECOVERY_DIR).join(name))
+                        .map_err(|e| format!("{}/{}: {}", RECOVERY_DIR, name, e))?,
+                ),
+                None => None,
+            };
+            session.documents.push(SessionDocument {
+                path: doc.get("path").and_then(|v| v.as_str()).map(PathBuf::from),
+                line: number("line"),
+                col: number("col"),
+                unsaved,
+            });
+        }
+        Ok(session)
+    }
+
+    /// Delete a saved session, once nothing in it is needed.
+    pub fn remove(dir: &Path) {
+        let _ = fs::remove_file(dir.join(SESSION_FILE));
+        let _ = fs::remove_dir_all(dir.join(RECOVERY_DIR));
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+/// A directory under `state` for this process's session, named by the
+/// time and process so that names sort oldest first.
+pub fn new_session_dir(state: &Path) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let name = format!("{:016}-{}", millis, std::process::id());
+    state.join(SESSIONS_DIR).join(name)
+}
+
+/// The directories of the sessions saved under `state`, newest first.
+pub fn saved_sessions(state: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(state.join(SESSIONS_DIR)) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|dir| dir.join(SESSION_FILE).is_file())
+        .collect();
+    dirs.sort_by(|a, b| b.cmp(a));
+    dirs
+}
+
+/// `$XDG_STATE_HOME/zelux`, falling back to `~/.local/state/zelux`.
+pub fn state_dir() -> Option<PathBuf> {
+    if let Some(xdg) = std::env::var_os("XDG_STATE_HOME")
+        && !xdg.is_empty()
+    {
+        return Some(PathBuf::from(xdg).join("zelux"));
+    }
+    std::env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join(".local")
+            .join("state")
+            .join("zelux")
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_session_{}", std::process::id()));
+        let session = Session {
+            active: 1,
+            documents: vec![
+                SessionDocument {
+                    path: Some(PathBuf::from("/tmp/a.rs")),
+                    line: 4,
+                    col: 2,
+                    unsaved: None,
+                },
+                SessionDocument {
+                    path: None,
+                    line: 0,
+                    col: 5,
+                    unsaved: Some("draft\n".to_string()),
+                },
+            ],
+            panel_rows: Some(4),
+        };
+        session.save(&dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("recovery/1")).unwrap(),
+            "draft\n"
+        );
+        assert_eq!(Session::load(&dir), Ok(session));
+
+        // A missing recovery file fails the load rather than dropping text
+        fs::remove_file(dir.join("recovery/1")).unwrap();
+        assert!(Session::load(&dir).is_err());
+
+        Session::remove(&dir);
+        assert!(Session::load(&dir).is_err());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_sessions_kept_apart() {
+        let state =
+            std::env::temp_dir().join(format!("zelux_test_sessions_{}", std::process::id()));
+        let draft = |text: &str| Session {
+            active: 0,
+            documents: vec![SessionDocument {
+                path: None,
+                line: 0,
+                col: 0,
+                unsaved: Some(text.to_string()),
+            }],
+            panel_rows: None,
+        };
+        let older = state.join(SESSIONS_DIR).join("0000000000000001-10");
+        let newer = state.join(SESSIONS_DIR).join("0000000000000002-20");
+        draft("older").save(&older).unwrap();
+        draft("newer").save(&newer).unwrap();
+        // Saving one session leaves the other's text alone
+        assert_eq!(Session::load(&older), Ok(draft("older")));
+        assert_eq!(saved_sessions(&state), [newer.clone(), older.clone()]);
+
+        Session::remove(&newer);
+        assert_eq!(saved_sessions(&state), [older]);
+        assert!(new_session_dir(&state).starts_with(state.join(SESSIONS_DIR)));
+        fs::remove_dir_all(&state).unwrap();
+    }
+}
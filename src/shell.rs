@@ -0,0 +1,180 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+// ---------------------------------------------------------------------------
+// CommandOutput
+// ---------------------------------------------------------------------------
+
+pub struct CommandOutput {
+    pub text: String,
+    pub status: Option<i32>,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+
+    /// Short human-readable exit description, e.g. "exit 0" or "killed".
+    pub fn status_label(&self) -> String {
+        match self.status {
+            Some(code) => format!("exit {}", code),
+            None => "killed by signal".to_string(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Running commands
+// ---------------------------------------------------------------------------
+
+/// Run `cmd` through `sh -c` with stderr merged into stdout, echoing the
+/// output to the real terminal while capturing it.
+///
+/// The caller must have suspended raw mode first (see `Terminal::suspend`);
+/// stdin is inherited so interactive commands still work.
+pub fn run_interactive(cmd: &str) -> Result<CommandOutput, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(merged_script(cmd))
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run command: {}", e))?;
+
+    let mut captured = Vec::new();
+    if let Some(mut out) = child.stdout.take() {
+        let mut stdout = std::io::stdout();
+        let mut chunk = [0u8; 4096];
+        loop {
+            match out.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let _ = stdout.write_all(&chunk[..n]);
+                    let _ = stdout.flush();
+                    captured.extend_from_slice(&chunk[..n]);
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to wait for command: {}", e))?;
+
+    Ok(CommandOutput {
+        text: normalize_output(&captured),
+        status: status.code(),
+    })
+}
+
+/// Run `cmd` through `sh -c` without a terminal (stdin closed), capturing
+/// stdout and stderr interleaved.
+pub fn run_captured(cmd: &str) -> Result<CommandOutput, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(merged_script(cmd))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run command: {}", e))?;
+    Ok(CommandOutput {
+        text: normalize_output(&output.stdout),
+        status: output.status.code(),
+    })
+}
+
+/// Pipe `input` through `cmd` (run by `sh -c`) and return its output
+/// unchanged. A failing command gives the first line of its stderr.
+pub fn filter(cmd: &str, input: &str) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", cmd, e))?;
+    // Feed stdin from another thread so a large output can't block it
+    let mut stdin = child.stdin.take();
+    let input = input.to_string();
+    let writer = std::thread::spawn(move || {
+        if let Some(ref mut stdin) = stdin {
+            let _ = stdin.write_all(input.as_bytes());
+        }
+    });
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for {}: {}", cmd, e))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().find(|l| !l.trim().is_empty()) {
+            Some(line) => line.trim().to_string(),
+            None => format!("{} failed", cmd),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("{} printed invalid UTF-8", cmd))
+}
+
+/// Wrap a command so that stderr is interleaved with stdout in one stream.
+fn merged_script(cmd: &str) -> String {
+    format!("exec 2>&1\n{}", cmd)
+}
+
+/// Decode command output as UTF-8 (lossy) and normalize CRLF line endings.
+fn normalize_output(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).replace("\r\n", "\n")
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_output_crlf() {
+        assert_eq!(normalize_output(b"a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(normalize_output(b"plain"), "plain");
+    }
+
+    #[test]
+    fn test_merged_script() {
+        assert_eq!(merged_script("ls -l"), "exec 2>&1\nls -l");
+    }
+
+    #[test]
+    fn test_run_captured_merges_stderr() {
+        let out = run_captured("echo out; echo err 1>&2; exit 3").unwrap();
+        assert_eq!(out.text, "out\nerr\n");
+        assert_eq!(out.status, Some(3));
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(filter("tr a-z A-Z", "ab\r\nc").unwrap(), "AB\r\nC");
+        let err = filter("cat >/dev/null; echo bad input >&2; exit 1", "x").unwrap_err();
+        assert_eq!(err, "bad input");
+    }
+
+    #[test]
+    fn test_status_label() {
+        let ok = CommandOutput {
+            text: String::new(),
+            status: Some(0),
+        };
+        assert!(ok.success());
+        assert_eq!(ok.status_label(), "exit 0");
+
+        let killed = CommandOutput {
+            text: String::new(),
+            status: None,
+        };
+        assert!(!killed.success());
+        assert_eq!(killed.status_label(), "killed by signal");
+    }
+}
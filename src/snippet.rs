@@ -0,0 +1,243 @@
+use std::fs;
+
+use crate::config;
+use crate::json::JsonValue;
+
+// ---------------------------------------------------------------------------
+// Snippet — a trigger word and the template it expands to
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub trigger: String,
+    pub template: String,
+}
+
+/// Load the snippets for `filetype` from `<config>/snippets/<filetype>.json`.
+///
+/// The file maps triggers to templates, either as one string or as an array
+/// of lines: `{"fn": ["fn ${1:name}() {", "    $0", "}"]}`. A missing file
+/// means no snippets.
+pub fn load(filetype: &str) -> Result<Vec<Snippet>, String> {
+    let path = match config::config_dir() {
+        Some(dir) => dir.join("snippets").join(format!("{}.json", filetype)),
+        None => return Ok(Vec::new()),
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read snippets: {}", e)),
+    };
+    let json = JsonValue::parse(&text).map_err(|e| format!("snippets/{}.json: {}", filetype, e))?;
+    Ok(from_json(&json))
+}
+
+/// Build snippets from parsed JSON, skipping entries of the wrong type.
+pub fn from_json(json: &JsonValue) -> Vec<Snippet> {
+    let fields = match json.as_object() {
+        Some(fields) => fields,
+        None => return Vec::new(),
+    };
+    fields
+        .iter()
+        .filter_map(|(trigger, value)| {
+            let template = match value {
+                JsonValue::String(s) => s.clone(),
+                JsonValue::Array(lines) => lines
+                    .iter()
+                    .map(|l| l.as_str())
+                    .collect::<Option<Vec<&str>>>()?
+                    .join("\n"),
+                _ => return None,
+            };
+            Some(Snippet {
+                trigger: trigger.clone(),
+                template,
+            })
+        })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Template expansion
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// Tab-stop byte ranges within `text`, in visiting order ($1, $2, ...,
+    /// then $0).
+    pub stops: Vec<(usize, usize)>,
+}
+
+/// Expand a template: `$N` and `${N:placeholder}` mark tab stops, `$0` the
+/// final cursor position, `\$` a literal dollar. Lines after the first get
+/// `indent` prepended.
+pub fn expand(template: &str, indent: &str) -> Expansion {
+    let mut text = String::new();
+    let mut numbered: Vec<(usize, usize, usize)> = Vec::new(); // (n, start, end)
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('$') | Some('\\')) => {
+                text.push(chars.next().unwrap_or(c));
+            }
+            '\n' => {
+                text.push('\n');
+                text.push_str(indent);
+            }
+            '$' if chars.peek().is_some_and(|d| d.is_ascii_digit()) => {
+                let mut n = 0;
+                while let Some(d) = chars.peek().and_then(|d| d.to_digit(10)) {
+                    n = n * 10 + d as usize;
+                    chars.next();
+                }
+                numbered.push((n, text.len(), text.len()));
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let mut n = 0;
+                while let Some(d) = chars.peek().and_then(|d| d.to_digit(10)) {
+                    n = n * 10 + d as usize;
+                    chars.next();
+                }
+                if chars.peek() == Some(&':') {
+                    chars.next();
+                }
+                let start = text.len();
+                for p in chars.by_ref() {
+                    if p == '}' {
+                        break;
+                    }
+                    text.push(p);
+                }
+                numbered.push((n, start, text.len()));
+            }
+            _ => text.push(c),
+        }
+    }
+
+    // Visit $1..$n in order, $0 (or the end of the text) last. Only the
+    // first occurrence of a number is a stop.
+    numbered.sort_by_key(|&(n, start, _)| (n == 0, n, start));
+    numbered.dedup_by_key(|&mut (n, _, _)| n);
+    let mut stops: Vec<(usize, usize)> = numbered.iter().map(|&(_, s, e)| (s, e)).collect();
+    if !numbered.iter().any(|&(n, _, _)| n == 0) {
+        stops.push((text.len(), text.len()));
+    }
+    Expansion { text, stops }
+}
+
+// ---------------------------------------------------------------------------
+// SnippetSession — tab-stop navigation after an expansion
+// ---------------------------------------------------------------------------
+
+pub struct SnippetSession {
+    stops: Vec<(usize, usize)>, // absolute byte ranges in the buffer
+    current: usize,
+    len_at_stop: usize, // buffer length when the current stop was entered
+}
+
+impl SnippetSession {
+    /// Start a session for an expansion inserted at byte `base`.
+    pub fn new(base: usize, expansion: &Expansion, buffer_len: usize) -> Self {
+        SnippetSession {
+            stops: expansion
+                .stops
+                .iter()
+                .map(|&(s, e)| (base + s, base + e))
+                .collect(),
+            current: 0,
+            len_at_stop: buffer_len,
+        }
+    }
+
+    pub fn current(&self) -> (usize, usize) {
+        self.stops[self.current]
+    }
+
+    /// True when the session sits on its final stop.
+    pub fn is_finished(&self) -> bool {
+        self.current + 1 >= self.stops.len()
+    }
+
+    /// Move to the next or previous stop. Edits made since entering the
+    /// current stop are assumed to be inside it: its end and every later
+    /// stop shift by the change in buffer length.
+    pub fn advance(&mut self, forward: bool, buffer_len: usize) -> (usize, usize) {
+        let delta = buffer_len as isize - self.len_at_stop as isize;
+        let shift = |pos: usize| (pos as isize + delta).max(0) as usize;
+        let cur = self.current;
+        let cur_start = self.stops[cur].0;
+        self.stops[cur].1 = shift(self.stops[cur].1).max(cur_start);
+        for stop in self.stops.iter_mut().skip(cur + 1) {
+            *stop = (shift(stop.0), shift(stop.1));
+        }
+        self.len_at_stop = buffer_len;
+
+        if forward {
+            self.current = (cur + 1).min(self.stops.len() - 1);
+        } else {
+            self.current = cur.saturating_sub(1);
+        }
+        self.current()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_stops_in_order() {
+        let exp = expand("fn ${1:name}($2) {\n    $0\n}", "  ");
+        assert_eq!(exp.text, "fn name() {\n      \n  }");
+        assert_eq!(exp.stops, vec![(3, 7), (8, 8), (18, 18)]);
+    }
+
+    #[test]
+    fn test_expand_without_final_stop() {
+        let exp = expand("a$1b", "");
+        assert_eq!(exp.text, "ab");
+        assert_eq!(exp.stops, vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_expand_escapes() {
+        let exp = expand("cost: \\$5", "");
+        assert_eq!(exp.text, "cost: $5");
+        assert_eq!(exp.stops, vec![(8, 8)]);
+    }
+
+    #[test]
+    fn test_session_shifts_later_stops() {
+        // "f(x, y)" inserted at 10 with stops on x and y
+        let exp = Expansion {
+            text: "f(x, y)".to_string(),
+            stops: vec![(2, 3), (5, 6), (7, 7)],
+        };
+        let mut session = SnippetSession::new(10, &exp, 100);
+        assert_eq!(session.current(), (12, 13));
+        // Replace "x" with "xyz": buffer grows by 2
+        assert_eq!(session.advance(true, 102), (17, 18));
+        assert!(!session.is_finished());
+        assert_eq!(session.advance(false, 102), (12, 15));
+        assert_eq!(session.advance(true, 102), (17, 18));
+        assert_eq!(session.advance(true, 102), (19, 19));
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let json =
+            JsonValue::parse(r#"{"p": "print($1)", "if": ["if $1 {", "}"], "bad": 3}"#).unwrap();
+        let snippets = from_json(&json);
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[1].template, "if $1 {\n}");
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::filetype;
+use crate::highlight;
+
+// ---------------------------------------------------------------------------
+// Dictionary — hunspell .dic files or plain word lists
+// ---------------------------------------------------------------------------
+
+/// Dictionaries tried when no `spell_dictionary` is configured.
+const SYSTEM_DICTIONARIES: &[&str] = &[
+    "/usr/share/hunspell/en_US.dic",
+    "/usr/share/myspell/en_US.dic",
+    "/usr/share/myspell/dicts/en_US.dic",
+    "/usr/share/dict/words",
+];
+
+/// Most suggestions offered for a word.
+const MAX_SUGGESTIONS: usize = 8;
+
+pub struct Dictionary {
+    words: HashSet<String>, // lowercase
+}
+
+impl Dictionary {
+    /// Parse a hunspell `.dic` file (word count on the first line, affix
+    /// flags after `/`) or a plain list with one word per line.
+    pub fn parse(text: &str) -> Dictionary {
+        let mut lines = text.lines().peekable();
+        // Hunspell files start with the approximate word count
+        if lines
+            .peek()
+            .is_some_and(|l| l.trim().parse::<usize>().is_ok())
+        {
+            lines.next();
+        }
+        let words = lines
+            .filter_map(|line| {
+                let word = line.split(['/', '\t']).next()?.trim();
+                (!word.is_empty() && !word.starts_with('#')).then(|| word.to_lowercase())
+            })
+            .collect();
+        Dictionary { words }
+    }
+
+    /// Load `path`, or the first system dictionary found when None, with
+    /// the words of the `lists` that exist added (see `word_lists`).
+    pub fn load(path: Option<&Path>, lists: &[PathBuf]) -> Result<Dictionary, String> {
+        let path: PathBuf = match path {
+            Some(p) => p.to_path_buf(),
+            None => SYSTEM_DICTIONARIES
+                .iter()
+                .map(PathBuf::from)
+                .find(|p| p.is_file())
+                .ok_or("No dictionary found; set spell_dictionary in config.json")?,
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut dict = Dictionary::parse(&text);
+        for list in lists {
+            if let Ok(text) = fs::read_to_string(list) {
+                dict.words.extend(Dictionary::parse(&text).words);
+            }
+        }
+        Ok(dict)
+    }
+
+    /// Accept `word` from now on.
+    pub fn insert(&mut self, word: &str) {
+        self.words.insert(word.to_lowercase());
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+            || word
+                .strip_suffix("'s")
+                .is_some_and(|stem| self.words.contains(&stem.to_lowercase()))
+    }
+
+    /// Dictionary words within edit distance 2 of `word`, closest first
+    /// (ties broken by a longer shared prefix, then alphabetically).
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let lower = word.to_lowercase();
+        let len = lower.chars().count();
+        let mut scored: Vec<(usize, usize, &String)> = self
+            .words
+            .iter()
+            .filter(|w| w.chars().count().abs_diff(len) <= 2)
+            .filter_map(|w| {
+                let distance = edit_distance(&lower, w);
+                let shared = lower
+                    .chars()
+                    .zip(w.chars())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                (distance <= 2).then_some((distance, usize::MAX - shared, w))
+            })
+            .collect();
+        scored.sort();
+        scored
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, _, w)| match_case(word, w))
+            .collect()
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and swaps of adjacent characters each cost 1.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Give `suggestion` the capitalization pattern of `word` (Title, UPPER).
+fn match_case(word: &str, suggestion: &str) -> String {
+    let mut chars = word.chars();
+    let first_upper = chars.next().is_some_and(char::is_uppercase);
+    if first_upper && word.chars().count() > 1 && chars.all(char::is_uppercase) {
+        suggestion.to_uppercase()
+    } else if first_upper {
+        let mut out: String = suggestion
+            .chars()
+            .take(1)
+            .flat_map(char::to_uppercase)
+            .collect();
+        out.extend(suggestion.chars().skip(1));
+        out
+    } else {
+        suggestion.to_string()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Checking — prose, comments and strings
+// ---------------------------------------------------------------------------
+
+/// Byte ranges of `line` that hold prose: everything for text files,
+/// comments and string literals for code. `in_block` is as for
+/// `highlight::comments_and_strings`.
+pub fn prose_ranges(line: &str, filetype: &str, in_block: &mut bool) -> Vec<(usize, usize)> {
+    // Filetypes without comment syntax are all prose
+    let Some(syntax) = filetype::comment_syntax(filetype) else {
+        return vec![(0, line.len())];
+    };
+    highlight::comments_and_strings(line, syntax, in_block)
+        .into_iter()
+        .map(|span| span.body)
+        .collect()
+}
+
+/// Misspelled words inside `ranges` of `line`, as byte ranges. Words with
+/// digits, underscores or inner capitals (identifiers) and all-caps words
+/// (acronyms) are not checked.
+pub fn misspellings(
+    line: &str,
+    ranges: &[(usize, usize)],
+    dict: &Dictionary,
+) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    for &(start, end) in ranges {
+        let text = &line[start..end];
+        let mut word_start = None;
+        let mut prev = None;
+        let chars = text
+            .char_indices()
+            .chain(std::iter::once((text.len(), ' ')));
+        for (i, c) in chars {
+            // An escape like `\n` ends a word rather than starting one
+            let escaped = prev == Some('\\');
+            prev = Some(c);
+            let part_of_word = !escaped && (c.is_alphanumeric() || c == '_' || c == '\'');
+            match (word_start, part_of_word) {
+                (None, true) => word_start = Some(i),
+                (Some(s), false) => {
+                    word_start = None;
+                    let word = text[s..i].trim_matches('\'');
+                    let offset = s + text[s..i].find(word).unwrap_or(0);
+                    if should_check(word) && !dict.contains(word) {
+                        found.push((start + offset, start + offset + word.len()));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    found
+}
+
+fn should_check(word: &str) -> bool {
+    word.chars().count() > 1
+        && word.chars().all(|c| c.is_alphabetic() || c == '\'')
+        && !word.chars().skip(1).any(char::is_uppercase)
+}
+
+// ---------------------------------------------------------------------------
+// Word lists — the user's and the project's own words
+// ---------------------------------------------------------------------------
+
+/// The user's dictionary, in the config directory.
+pub const USER_DICTIONARY: &str = "dictionary.txt";
+
+/// The list read before `USER_DICTIONARY`, still honored.
+const OLD_USER_WORDS: &str = "words.txt";
+
+/// A project's words, in the directory zelux runs in.
+pub const PROJECT_WORDS: &str = ".zelux-words";
+
+/// The word lists spell checking accepts and completion offers, one word
+/// per line: the user's in `config_dir`, then the project's in `project`.
+pub fn word_lists(config_dir: Option<&Path>, project: &Path) -> Vec<PathBuf> {
+    let user = config_dir
+        .into_iter()
+        .flat_map(|dir| [dir.join(OLD_USER_WORDS), dir.join(USER_DICTIONARY)]);
+    user.chain([project.join(PROJECT_WORDS)]).collect()
+}
+
+/// Append `word` to the list at `path`, creating the file and its
+/// directory. False when the list already had it.
+pub fn add_word(path: &Path, word: &str) -> Result<bool, String> {
+    let err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let text = fs::read_to_string(path).unwrap_or_default();
+    if Dictionary::parse(&text).contains(word) {
+        return Ok(false);
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(err)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(err)?;
+    let separator = if text.is_empty() || text.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    writeln!(file, "{}{}", separator, word).map_err(err)?;
+    Ok(true)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict() -> Dictionary {
+        Dictionary::parse("5\nhello/MS\nworld\nthe\nword\nwords\n")
+    }
+
+    #[test]
+    fn test_parse_hunspell_and_plain() {
+        let d = dict();
+        assert!(d.contains("hello"));
+        assert!(d.contains("Hello"));
+        assert!(d.contains("world's"));
+        assert!(!d.contains("5"));
+        let plain = Dictionary::parse("apple\n# comment\nBanana\n");
+        assert!(plain.contains("banana"));
+        assert!(!plain.contains("# comment"));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("word", "word"), 0);
+        assert_eq!(edit_distance("wrod", "word"), 1);
+        assert_eq!(edit_distance("wor", "word"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_suggest_ranks_and_keeps_case() {
+        let d = dict();
+        assert_eq!(d.suggest("wrod"), vec!["word", "words", "world"]);
+        assert_eq!(d.suggest("Helo"), vec!["Hello"]);
+        assert_eq!(d.suggest("WORLDD"), vec!["WORLD", "WORD", "WORDS"]);
+        assert!(d.suggest("zzzzzz").is_empty());
+    }
+
+    #[test]
+    fn test_prose_ranges_code() {
+        let mut in_block = false;
+        let line = r#"let s = "a \"b\" c"; // note"#;
+        let ranges = prose_ranges(line, "rs", &mut in_block);
+        assert_eq!(&line[ranges[0].0..ranges[0].1], r#"a \"b\" c"#);
+        assert_eq!(&line[ranges[1].0..ranges[1].1], " note");
+        // Block comments continue across lines
+        assert_eq!(prose_ranges("x /* one", "rs", &mut in_block), vec![(4, 8)]);
+        assert!(in_block);
+        assert_eq!(prose_ranges("two */ y", "rs", &mut in_block), vec![(0, 4)]);
+        assert!(!in_block);
+        assert_eq!(prose_ranges("plain", "md", &mut in_block), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn test_misspellings_skip_identifiers() {
+        let d = dict();
+        let line = "hello wrold, the fooBar x_y HTTP 'word' abc1 \\nthe";
+        let found = misspellings(line, &[(0, line.len())], &d);
+        let words: Vec<&str> = found.iter().map(|&(s, e)| &line[s..e]).collect();
+        assert_eq!(words, vec!["wrold"]);
+    }
+
+    #[test]
+    fn test_word_lists() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_words_{}", std::process::id()));
+        let lists = word_lists(Some(&dir.join("config")), &dir);
+        assert_eq!(lists[1], dir.join("config/dictionary.txt"));
+        assert_eq!(lists[2], dir.join(".zelux-words"));
+
+        assert_eq!(add_word(&lists[1], "zelux"), Ok(true));
+        assert_eq!(add_word(&lists[1], "Zelux"), Ok(false));
+        fs::write(&lists[2], "gapbuf").unwrap();
+        assert_eq!(add_word(&lists[2], "rustc"), Ok(true));
+        assert_eq!(fs::read_to_string(&lists[2]).unwrap(), "gapbuf\nrustc\n");
+
+        let system = dir.join("system.dic");
+        fs::write(&system, "hello\n").unwrap();
+        let mut d = Dictionary::load(Some(&system), &lists).unwrap();
+        assert!(d.contains("zelux") && d.contains("gapbuf") && d.contains("rustc"));
+        assert!(!d.contains("wrold"));
+        d.insert("Wrold");
+        assert!(d.contains("wrold"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+use std::ops::AddAssign;
+
+use crate::buffer::Buffer;
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Text statistics — counts kept up to date edit by edit
+// ---------------------------------------------------------------------------
+
+/// Bytes of whole lines counted together; an edit recounts only the
+/// chunks it touched.
+const CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub newlines: usize,
+    /// Text after whitespace or the start.
+    pub words: usize,
+    /// Code points.
+    pub chars: usize,
+    /// What reads as one character, such as a letter with its accent.
+    pub graphemes: usize,
+    pub bytes: usize,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        self.newlines += other.newlines;
+        self.words += other.words;
+        self.chars += other.chars;
+        self.graphemes += other.graphemes;
+        self.bytes += other.bytes;
+    }
+}
+
+/// Count `text` as if it started the buffer or a line, so the counts of
+/// whole lines add up.
+pub fn count(text: &str) -> Counts {
+    let mut counts = Counts {
+        bytes: text.len(),
+        ..Counts::default()
+    };
+    let (mut prev, mut joined) = (None, false);
+    for c in text.chars() {
+        counts.chars += 1;
+        counts.newlines += usize::from(c == '\n');
+        let after_space = prev.is_none_or(char::is_whitespace);
+        counts.words += usize::from(after_space && !c.is_whitespace());
+        joined = prev.is_some_and(|p| unicode::joins_previous(p, c, joined));
+        counts.graphemes += usize::from(!joined);
+        prev = Some(c);
+    }
+    counts
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    len: usize,
+    counts: Option<Counts>, // None once an edit touched it
+}
+
+/// Counts of a buffer, kept as runs of whole lines so that after edits
+/// only the runs they touched are counted again.
+#[derive(Debug, Default)]
+pub struct TextStats {
+    version: Option<u64>, // of the buffer last counted
+    chunks: Vec<Chunk>,
+}
+
+impl TextStats {
+    /// Whether the buffer was counted before, so keeping up is worth it.
+    pub fn is_counted(&self) -> bool {
+        self.version.is_some()
+    }
+
+    /// The whole buffer's counts, brought up to date.
+    pub fn total(&mut self, buf: &Buffer) -> Counts {
+        self.update(buf);
+        let mut total = Counts::default();
+        for chunk in &self.chunks {
+            total += chunk.counts.unwrap_or_default();
+        }
+        total
+    }
+
+    /// The counts of `[start, end)`: chunks inside it from the cache, the
+    /// partial ones at either end counted.
+    pub fn range(&mut self, buf: &Buffer, start: usize, end: usize) -> Counts {
+        self.update(buf);
+        let mut total = Counts::default();
+        let mut at = 0;
+        let mut pending = start; // counted up to here
+        for chunk in &self.chunks {
+            let chunk_end = at + chunk.len;
+            if at >= start && chunk_end <= end && chunk.len > 0 {
+                total += count(&buf.slice(pending, at));
+                total += chunk.counts.unwrap_or_default();
+                pending = chunk_end;
+            }
+            at = chunk_end;
+            if at >= end {
+                break;
+            }
+        }
+        if pending < end {
+            total += count(&buf.slice(pending, end));
+        }
+        total
+    }
+
+    /// Follow the edits since the last count, or count afresh when they
+    /// are no longer all known.
+    pub fn update(&mut self, buf: &Buffer) {
+        if self.version == Some(buf.version()) {
+            return;
+        }
+        let edits = self.version.and_then(|v| buf.edits_since(v));
+        match edits {
+            Some(edits) => {
+                for edit in edits {
+                    self.touch(edit.pos, edit.deleted, edit.inserted);
+                }
+            }
+            None => {
+                self.chunks = vec![Chunk {
+                    len: buf.len(),
+                    counts: None,
+                }]
+            }
+        }
+        self.recount(buf);
+        self.version = Some(buf.version());
+    }
+
+    /// Merge the chunks an edit of `deleted` bytes at `pos` overlaps,
+    /// touching ones included, into one to recount with its new length.
+    fn touch(&mut self, pos: usize, deleted: usize, inserted: usize) {
+        let end = pos + deleted;
+        let mut at = 0;
+        let mut first = None;
+        let mut last = 0;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if at <= end && pos <= at + chunk.len {
+                first.get_or_insert(i);
+                last = i;
+            }
+            at += chunk.len;
+        }
+        let Some(first) = first else {
+            self.chunks.push(Chunk {
+                len: inserted,
+                counts: None,
+            });
+            return;
+        };
+        let len: usize = self.chunks[first..=last].iter().map(|c| c.len).sum();
+        self.chunks.splice(
+            first..=last,
+            [Chunk {
+                len: len + inserted - deleted,
+                counts: None,
+            }],
+        );
+    }
+
+    /// Count the touched chunks, cutting them into runs of whole lines
+    /// about `CHUNK` bytes long.
+    fn recount(&mut self, buf: &Buffer) {
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        let mut at = 0;
+        for chunk in std::mem::take(&mut self.chunks) {
+            let end = at + chunk.len;
+            if chunk.counts.is_some() {
+                chunks.push(chunk);
+            } else {
+                let text = buf.slice(at, end);
+                let mut start = 0;
+                while start < text.len() {
+                    let cut = text[start..]
+                        .char_indices()
+                        .skip_while(|&(i, _)| i < CHUNK)
+                        .find(|&(_, c)| c == '\n')
+                        .map_or(text.len(), |(i, _)| start + i + 1);
+                    chunks.push(Chunk {
+                        len: cut - start,
+                        counts: Some(count(&text[start..cut])),
+                    });
+                    start = cut;
+                }
+            }
+            at = end;
+        }
+        self.chunks = chunks;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench;
+    use std::time::Duration;
+
+    #[test]
+    fn test_count() {
+        let counts = count("Héllo  wörld\ne\u{301}\r\n🇪🇸 x");
+        assert_eq!(
+            counts,
+            Counts {
+                newlines: 2,
+                words: 5,
+                chars: 21,
+                graphemes: 18,
+                bytes: 30,
+            }
+        );
+        assert_eq!(count(""), Counts::default());
+    }
+
+    #[test]
+    fn test_edits_recount_only_what_changed() {
+        let line = "word word word\n";
+        let mut buf = Buffer::new();
+        buf.insert(0, &line.repeat(20_000));
+        let mut stats = TextStats::default();
+        assert_eq!(stats.total(&buf), count(&buf.text()));
+        let before: Vec<usize> = stats.chunks.iter().map(|c| c.len).collect();
+        assert!(before.len() > 3);
+
+        // Joining two chunks' lines, then splitting a word
+        let boundary = stats.chunks[0].len;
+        buf.delete(boundary - 1, 1);
+        buf.insert(100, " \n");
+        buf.insert(buf.len(), "tail");
+        assert_eq!(stats.total(&buf), count(&buf.text()));
+        // The chunks between the edits were not cut again
+        let after: Vec<usize> = stats.chunks.iter().map(|c| c.len).collect();
+        let kept = &before[2..before.len() - 1];
+        assert_eq!(after[after.len() - 1 - kept.len()..after.len() - 1], *kept);
+
+        // A range mixes cached chunks with counted ends
+        let (start, end) = (7, buf.len() - 2);
+        assert_eq!(stats.range(&buf, start, end), count(&buf.slice(start, end)));
+        assert_eq!(stats.range(&buf, 3, 9), count("d word"));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_recount_after_edit() {
+        let mut buf = Buffer::new();
+        buf.insert(0, &bench::large_text(200_000));
+        bench::measure("stats: 200k lines, counted afresh", 5, || {
+            TextStats::default().total(&buf)
+        });
+        let mut stats = TextStats::default();
+        stats.total(&buf);
+        // What an edit in the middle leaves to do
+        let pos = buf.len() / 2;
+        bench::within(
+            "stats: 200k lines, after an edit",
+            200,
+            Duration::from_millis(1),
+            || {
+                stats.touch(pos, 0, 0);
+                stats.recount(&buf);
+                stats.total(&buf)
+            },
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use crate::buffer::Buffer;
+use crate::json::JsonValue;
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Status bar — configurable segments on the left and right
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Mode,
+    Filename,
+    Buffers,
+    Position,
+    Percent,
+    Selection,
+    LineEnding,
+    Encoding,
+    Filetype,
+    Branch,
+    Colors,
+}
+
+/// Config name and truncation priority of each segment. When the bar is too
+/// narrow the lowest priority goes first; the filename is never dropped.
+#[rustfmt::skip]
+const SEGMENTS: &[(Segment, &str, u8)] = &[
+    (Segment::Filename,   "filename",    255),
+    (Segment::Position,   "position",    90),
+    (Segment::Selection,  "selection",   85),
+    (Segment::Mode,       "mode",        80),
+    (Segment::Buffers,    "buffers",     60),
+    (Segment::Filetype,   "filetype",    50),
+    (Segment::Percent,    "percent",     40),
+    (Segment::Branch,     "branch",      30),
+    (Segment::LineEnding, "line_ending", 20),
+    (Segment::Encoding,   "encoding",    10),
+    (Segment::Colors,     "colors",      5),
+];
+
+impl Segment {
+    pub fn from_name(name: &str) -> Option<Segment> {
+        SEGMENTS.iter().find(|s| s.1 == name).map(|s| s.0)
+    }
+
+    fn priority(self) -> u8 {
+        SEGMENTS.iter().find(|s| s.0 == self).map_or(0, |s| s.2)
+    }
+}
+
+const SEPARATOR: &str = " | ";
+
+/// Which segments appear on each side of the bar, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusLayout {
+    pub left: Vec<Segment>,
+    pub right: Vec<Segment>,
+}
+
+impl Default for StatusLayout {
+    fn default() -> Self {
+        StatusLayout {
+            left: vec![
+                Segment::Mode,
+                Segment::Filename,
+                Segment::Buffers,
+                Segment::Branch,
+            ],
+            right: vec![
+                Segment::Selection,
+                Segment::Position,
+                Segment::Percent,
+                Segment::Filetype,
+                Segment::LineEnding,
+                Segment::Colors,
+            ],
+        }
+    }
+}
+
+impl StatusLayout {
+    /// Build a layout from a `"status_bar"` config object with `"left"` and
+    /// `"right"` lists of segment names. Unknown names are skipped and a
+    /// missing side keeps its default.
+    pub fn from_json(json: &JsonValue) -> StatusLayout {
+        let mut layout = StatusLayout::default();
+        let side = |key: &str| {
+            json.get(key).and_then(|v| v.as_array()).map(|names| {
+                names
+                    .iter()
+                    .filter_map(|n| Segment::from_name(n.as_str()?))
+                    .collect::<Vec<_>>()
+            })
+        };
+        if let Some(left) = side("left") {
+            layout.left = left;
+        }
+        if let Some(right) = side("right") {
+            layout.right = right;
+        }
+        layout
+    }
+}
+
+/// Join rendered segments into the left and right halves of a bar `width`
+/// columns wide. Empty segments are skipped, low-priority segments dropped
+/// until both halves fit, and then the filename is shortened from the front.
+pub fn layout(
+    left: &[(Segment, String)],
+    right: &[(Segment, String)],
+    width: usize,
+) -> (String, String) {
+    let keep = |side: &[(Segment, String)]| -> Vec<(Segment, String)> {
+        side.iter()
+            .filter(|(_, t)| !t.is_empty())
+            .cloned()
+            .collect()
+    };
+    let mut left = keep(left);
+    let mut right = keep(right);
+    loop {
+        let (l, r) = (join(&left, true), join(&right, false));
+        let used = unicode::display_width(&l) + unicode::display_width(&r) + 1;
+        if used <= width {
+            return (l, r);
+        }
+        let lowest = left
+            .iter()
+            .chain(&right)
+            .map(|(s, _)| *s)
+            .filter(|s| *s != Segment::Filename)
+            .min_by_key(|s| s.priority());
+        match lowest {
+            Some(segment) => {
+                left.retain(|(s, _)| *s != segment);
+                right.retain(|(s, _)| *s != segment);
+            }
+            None => {
+                let excess = used - width;
+                for (segment, text) in left.iter_mut().chain(right.iter_mut()) {
+                    if *segment == Segment::Filename {
+                        *text = truncate_front(text, excess);
+                    }
+                }
+                return (join(&left, true), join(&right, false));
+            }
+        }
+    }
+}
+
+fn join(segments: &[(Segment, String)], left: bool) -> String {
+    if segments.is_empty() {
+        return String::new();
+    }
+    let texts: Vec<&str> = segments.iter().map(|(_, t)| t.as_str()).collect();
+    if left {
+        format!(" {}", texts.join(SEPARATOR))
+    } else {
+        format!("{} ", texts.join(SEPARATOR))
+    }
+}
+
+/// Drop `excess` columns (plus one for the ellipsis) from the front.
+fn truncate_front(text: &str, excess: usize) -> String {
+    let keep = unicode::display_width(text)
+        .saturating_sub(excess + 1)
+        .max(1);
+    let mut cols = 0;
+    let start = text
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| {
+            cols += unicode::char_width(c);
+            cols <= keep
+        })
+        .last()
+        .map_or(text.len(), |(i, _)| i);
+    format!("…{}", &text[start..])
+}
+
+/// Where the view is in the buffer: `All` when everything fits, `Top`,
+/// `Bot`, or how far down it is scrolled.
+pub fn scroll_percent(first_visible: usize, rows: usize, total_lines: usize) -> String {
+    if total_lines <= rows {
+        "All".to_string()
+    } else if first_visible == 0 {
+        "Top".to_string()
+    } else if first_visible + rows >= total_lines {
+        "Bot".to_string()
+    } else {
+        format!("{}%", first_visible * 100 / (total_lines - rows))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Selection statistics
+// ---------------------------------------------------------------------------
+
+/// Character and word counts of the selection. When only one end moved
+/// since the last measurement, just the bytes in between are scanned, so
+/// growing a selection over a large buffer stays cheap.
+#[derive(Default)]
+pub struct SelectionStats {
+    measured: Option<(u64, usize, usize)>, // buffer version, start, end
+    chars: usize,
+    word_starts: usize,
+}
+
+fn is_space(b: u8) -> bool {
+    b.is_ascii_whitespace()
+}
+
+/// Characters and word starts (text after whitespace or the buffer start)
+/// in `[start, end)`. Both add up across adjacent ranges.
+fn count(buf: &Buffer, start: usize, end: usize) -> (usize, usize) {
+    let mut prev = match start {
+        0 => b' ',
+        _ => buf.bytes(start - 1, start).next().unwrap_or(b' '),
+    };
+    let (mut chars, mut word_starts) = (0, 0);
+    for b in buf.bytes(start, end) {
+        if b & 0xC0 != 0x80 {
+            chars += 1;
+        }
+        if !is_space(b) && is_space(prev) {
+            word_starts += 1;
+        }
+        prev = b;
+    }
+    (chars, word_starts)
+}
+
+impl SelectionStats {
+    /// (characters, words) of `[start, end)`; a word cut by the start of the
+    /// selection still counts.
+    pub fn measure(&mut self, buf: &Buffer, start: usize, end: usize) -> (usize, usize) {
+        // The range added or removed since the last measurement
+        let delta = match self.measured {
+            Some((version, s, e)) if version == buf.version() && s == start => Some((e, end)),
+            Some((version, s, e)) if version == buf.version() && e == end => Some((start, s)),
+            _ => None,
+        };
+        match delta {
+            Some((from, to)) => {
+                let (c, w) = count(buf, from.min(to), from.max(to));
+                if from <= to {
+                    self.chars += c;
+                    self.word_starts += w;
+                } else {
+                    self.chars -= c;
+                    self.word_starts -= w;
+                }
+            }
+            None => (self.chars, self.word_starts) = count(buf, start, end),
+        }
+        self.measured = Some((buf.version(), start, end));
+        let cut_word =
+            start > 0 && start < end && buf.bytes(start - 1, start + 1).all(|b| !is_space(b));
+        (self.chars, self.word_starts + usize::from(cut_word))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(segment: Segment, text: &str) -> (Segment, String) {
+        (segment, text.to_string())
+    }
+
+    #[test]
+    fn test_layout_joins_and_skips_empty() {
+        let left = [seg(Segment::Mode, ""), seg(Segment::Filename, "a.rs [+]")];
+        let right = [
+            seg(Segment::Position, "Ln 1, Col 1"),
+            seg(Segment::Filetype, "rs"),
+        ];
+        assert_eq!(
+            layout(&left, &right, 80),
+            (" a.rs [+]".to_string(), "Ln 1, Col 1 | rs ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_layout_drops_by_priority_then_truncates() {
+        let left = [
+            seg(Segment::Filename, "src/editor.rs"),
+            seg(Segment::Branch, "main"),
+        ];
+        let right = [
+            seg(Segment::Position, "Ln 1, Col 1"),
+            seg(Segment::Colors, "256color"),
+        ];
+        // Colors (lowest) goes first, then the branch and the position
+        let (l, r) = layout(&left, &right, 35);
+        assert_eq!(
+            (l.as_str(), r.as_str()),
+            (" src/editor.rs | main", "Ln 1, Col 1 ")
+        );
+        let (l, r) = layout(&left, &right, 28);
+        assert_eq!((l.as_str(), r.as_str()), (" src/editor.rs", "Ln 1, Col 1 "));
+        let (l, r) = layout(&left, &right, 22);
+        assert_eq!((l.as_str(), r.as_str()), (" src/editor.rs", ""));
+        // Then the filename loses its front
+        let (l, r) = layout(&left, &right, 10);
+        assert_eq!((l.as_str(), r.as_str()), (" …itor.rs", ""));
+        // Wide characters count two columns and are never split
+        let left = [seg(Segment::Filename, "文書/メモ.txt")];
+        let (l, _) = layout(&left, &[], 12);
+        assert_eq!(l, " …/メモ.txt");
+        assert!(unicode::display_width(&l) <= 11);
+    }
+
+    #[test]
+    fn test_scroll_percent() {
+        assert_eq!(scroll_percent(0, 20, 10), "All");
+        assert_eq!(scroll_percent(0, 20, 100), "Top");
+        assert_eq!(scroll_percent(40, 20, 100), "50%");
+        assert_eq!(scroll_percent(80, 20, 100), "Bot");
+    }
+
+    #[test]
+    fn test_selection_stats_incremental() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "one two  thrée\nfour five");
+        let mut stats = SelectionStats::default();
+        let fresh = |start, end| SelectionStats::default().measure(&buf, start, end);
+        assert_eq!(stats.measure(&buf, 0, 7), (7, 2));
+        // Growing and shrinking the end, then moving the start
+        assert_eq!(stats.measure(&buf, 0, 20), fresh(0, 20));
+        assert_eq!(stats.measure(&buf, 0, 20), (19, 4));
+        assert_eq!(stats.measure(&buf, 0, 5), fresh(0, 5));
+        assert_eq!(stats.measure(&buf, 2, 5), (3, 2));
+        assert_eq!(stats.measure(&buf, 0, 5), fresh(0, 5));
+        // A selection starting mid-word counts that word
+        assert_eq!(fresh(1, 3), (2, 1));
+    }
+
+    #[test]
+    fn test_layout_from_json() {
+        let json = JsonValue::parse(r#"{"left": ["filename", "bogus", "branch"]}"#).unwrap();
+        let layout = StatusLayout::from_json(&json);
+        assert_eq!(layout.left, vec![Segment::Filename, Segment::Branch]);
+        assert_eq!(layout.right, StatusLayout::default().right);
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::sync::OnceLock;
+
+use crate::config;
+use crate::json::JsonValue;
+
+// ---------------------------------------------------------------------------
+// Strings — the UI's user-visible text, replaceable by a language file
+// ---------------------------------------------------------------------------
+
+/// The built-in English strings by key. `{}` marks where `format` puts its
+/// arguments, in order.
+const DEFAULTS: &[(&str, &str)] = &[
+    // Prompts
+    ("prompt_open", "Open: "),
+    ("prompt_insert_file", "Insert file: "),
+    ("prompt_write_selection", "Write selection to: "),
+    ("prompt_export", "Export to: "),
+    ("prompt_filetype", "Filetype: "),
+    ("prompt_run", "Run: "),
+    ("prompt_command", "Command: "),
+    ("prompt_ex", ":"),
+    ("prompt_find", "Find: "),
+    ("prompt_replace_with", "Replace with: "),
+    ("prompt_replace_match", "Replace this match? "),
+    ("prompt_replace_in_buffers", "Replace in buffers: "),
+    ("prompt_in_buffers", "In buffers (numbers or names): "),
+    ("prompt_rename_in_project", "Rename in project: "),
+    ("prompt_rename_to", "Rename to: "),
+    ("prompt_overwrite", "Overwrite {}? "),
+    ("prompt_trash", "Move {} to the trash? "),
+    ("prompt_symbol", "Symbol: "),
+    ("prompt_file", "File: "),
+    ("prompt_definition", "Definition of {}: "),
+    ("prompt_character", "Character (name or U+hex): "),
+    ("prompt_digraph", "Digraph: {}"),
+    ("prompt_surround", "Surround with: "),
+    ("prompt_delete_surrounding", "Delete surrounding: "),
+    ("prompt_change_surrounding", "Change surrounding: "),
+    ("prompt_change_pair", "Change {} to: "),
+    // Dialogs
+    ("unsaved_changes", "Unsaved changes"),
+    ("quit_without_saving", "Quit without saving?"),
+    ("close_without_saving", "Close this buffer without saving?"),
+    ("yes", "Yes"),
+    ("no", "No"),
+    ("all", "All"),
+    ("quit", "Quit"),
+    // Context menu
+    ("menu_cut", "Cut"),
+    ("menu_copy", "Copy"),
+    ("menu_paste", "Paste"),
+    ("menu_select_all", "Select All"),
+    ("menu_go_to_definition", "Go to Definition"),
+    // Status bar
+    ("mode_normal", "NORMAL"),
+    ("mode_insert", "INSERT"),
+    ("mode_visual", "VISUAL"),
+    ("status_position", "Ln {}/{}, Col {}"),
+    ("status_selection", "Sel: {}, {}, {}"),
+    ("status_follow", " [follow]"),
+    ("status_follow_paused", " [follow: paused]"),
+    ("one_line", "1 line"),
+    ("lines", "{} lines"),
+    ("one_word", "1 word"),
+    ("words", "{} words"),
+    ("one_char", "1 char"),
+    ("chars", "{} chars"),
+    // Files
+    ("saved", "Saved!"),
+    ("save_failed", "Save failed: {}"),
+    ("saved_through_link", "Saved, but {}"),
+    ("link_to_read_only", "{} links to read-only {}"),
+    ("saved_format_failed", "Saved without formatting: {}"),
+    (
+        "saved_while_locked",
+        "Saved, but zelux PID {} on {} has it open too",
+    ),
+    (
+        "prompt_locked",
+        "{} is being edited by zelux PID {} on {}. Open it read-only? ",
+    ),
+    (
+        "editing_locked",
+        "Editing {} while PID {} on {} has it open",
+    ),
+    ("no_file_name", "The buffer has no file name to save to"),
+    ("opened", "Opened: {}"),
+    ("no_file_to_delete", "The buffer has no file to delete"),
+    ("trashed", "Moved {} to {}"),
+    ("nothing_trashed", "No file was moved to the trash"),
+    ("restored", "Restored {}"),
+    ("no_files", "No files found"),
+    ("opening_url", "Opening {}"),
+    ("nothing_under_cursor", "No file or URL under the cursor"),
+    ("no_file_under_cursor", "No file named {}"),
+    ("read_failed", "Failed to read {}: {}"),
+    ("write_failed", "Failed to write {}: {}"),
+    ("is_directory", "{} is a directory"),
+    ("needs_terminal", "Not available in an embedded editor"),
+    (
+        "cooked_help",
+        "Enter: next page  b: back  NUMBER: go to line  /TEXT: find  q: quit",
+    ),
+    ("filetype_set", "Filetype: {}"),
+    ("inserted_lines", "Inserted {} lines from {}"),
+    ("wrote_bytes", "Wrote {} bytes to {}"),
+    (
+        "export_own_file",
+        "Not exporting over the buffer's own file {}",
+    ),
+    ("read_only", "Buffer is read-only"),
+    ("error", "Error: {}"),
+    ("unknown_command", "Unknown command: {}"),
+    // Document statistics
+    ("stats_document", "Document"),
+    ("stats_selection", "Selection"),
+    ("stats_lines", "Lines"),
+    ("stats_words", "Words"),
+    ("stats_chars", "Characters"),
+    ("stats_graphemes", "Graphemes"),
+    ("stats_bytes", "Bytes"),
+    // Buffer options
+    ("options_title", "Buffer options"),
+    ("options_hint", "←→ change  Space toggle  Esc close"),
+    ("option_text_width", "Wrap width"),
+    ("option_tab_width", "Tab width"),
+    ("option_filetype", "Filetype"),
+    ("option_line_ending", "Line ending"),
+    ("option_encoding", "Encoding"),
+    ("option_read_only", "Read-only"),
+    ("utf8_only", "Files are always read and written as UTF-8"),
+    // Editing
+    ("undo", "Undo"),
+    ("redo", "Redo"),
+    ("nothing_to_undo", "Nothing to undo"),
+    ("nothing_to_redo", "Nothing to redo"),
+    ("no_selection", "No selection"),
+    ("copied_chars", "Copied {} chars"),
+    ("copied_line", "Copied line ({} chars)"),
+    ("cut_chars", "Cut {} chars"),
+    ("cut_line", "Cut line ({} chars)"),
+    ("clipboard_empty", "Clipboard is empty"),
+    ("no_comment_syntax", "No comment syntax for {} files"),
+    ("no_number", "No number under or after the cursor"),
+    ("sorted_lines", "Sorted {} lines"),
+    ("removed_duplicates", "Removed {} duplicate lines"),
+    ("not_a_pair", "Not a pair: {}"),
+    ("no_surrounding", "No surrounding {}"),
+    ("end_of_buffer", "End of buffer"),
+    ("no_digraph", "No digraph {}"),
+    ("json_formatted", "Formatted JSON"),
+    ("json_minified", "Minified JSON"),
+    ("json_error", "Invalid JSON: {}"),
+    // Completion and language servers
+    ("no_word_to_complete", "No word to complete"),
+    ("no_completions", "No completions"),
+    ("no_diagnostics", "No diagnostics on this line"),
+    ("no_diagnostics_anywhere", "No diagnostics"),
+    ("diagnostic_entry", "[{}/{}] {}"),
+    (
+        "diagnostics_panel_header",
+        " Diagnostics: {} of {}, {}, {}  (Enter jump, Tab severity, F file, Esc close)",
+    ),
+    ("diagnostics_filter_all", "all severities"),
+    ("diagnostics_filter_warnings", "warnings and errors"),
+    ("diagnostics_filter_errors", "errors only"),
+    ("diagnostics_filter_any_file", "all files"),
+    ("no_language_server", "No language server for this buffer"),
+    ("no_definition", "No definition found"),
+    ("no_hover", "No hover information"),
+    (
+        "no_symbols",
+        "No symbols (no outline for this filetype, no tags file)",
+    ),
+    ("no_matching_symbol", "No matching symbol"),
+    ("tag_not_found", "Tag {} not found in file"),
+    // Spelling
+    ("spell_check_on", "Spell check on"),
+    ("spell_check_off", "Spell check off"),
+    ("misspelled_words", "{} misspelled words"),
+    ("no_misspelled_words", "No misspelled words"),
+    ("no_misspelled_word", "No misspelled word at the cursor"),
+    ("no_word_under_cursor", "No word under the cursor"),
+    (
+        "no_config_dir",
+        "No config directory: set HOME or XDG_CONFIG_HOME",
+    ),
+    ("word_added", "Added {} to {}"),
+    ("word_listed", "{} is already in {}"),
+    ("no_suggestions", "No suggestions"),
+    // Jumps
+    ("jump_list_needs_name", "Jump list needs a named buffer"),
+    ("no_later_position", "No later position"),
+    ("no_earlier_position", "No earlier position"),
+    // Git
+    ("no_changes", "No changes"),
+    ("no_more_hunks", "No more hunks"),
+    ("not_in_hunk", "Not in a patch hunk"),
+    ("save_first", "Save the file first"),
+    ("no_change_under_cursor", "No change under cursor"),
+    ("hunk_staged", "Hunk staged"),
+    ("hunk_reverted", "Hunk reverted (not saved)"),
+    ("blame_needs_file", "Blame needs a file on disk"),
+    ("not_committed", "Line is not committed yet"),
+    ("no_blame", "No blame info (toggle with Alt+B)"),
+    ("copied_commit", "Copied {} ({})"),
+    // Shell and build
+    ("running", "Running: {}"),
+    ("command_finished", "Command finished ({})"),
+    ("build_succeeded", "Build succeeded"),
+    ("build_failed", "Build failed ({}), no locations found"),
+    (
+        "build_finished",
+        "Build finished ({}): {} locations — F8 to jump",
+    ),
+    ("no_build_errors", "No build errors"),
+    ("quickfix_entry", "[{}/{}] {}"),
+    ("no_messages", "No messages"),
+    // Follow
+    ("following", "Following {}"),
+    ("following_any_key", "Following {} (any key stops)"),
+    ("follow_off", "Stopped following"),
+    ("follow_needs_file", "Follow needs a file on disk"),
+    // Column view
+    ("column_view_offer", "{} is a table: {} shows it as columns"),
+    (
+        "column_view_needs_table",
+        "Column view is for CSV and TSV files",
+    ),
+    // Plugins
+    ("plugin_failed", "Plugin failed to start: {}"),
+    ("plugin_error", "Plugin {}: {}"),
+    ("script_error", "Script {}: {}"),
+    // Profiling
+    (
+        "profiling_off",
+        "Profiling is off; start zelux with --profile",
+    ),
+    // Sessions
+    ("no_session", "No session to continue"),
+    (
+        "session_continued",
+        "Continued the last session: {} buffers, {} with unsaved changes",
+    ),
+    ("session_reopen_failed", "Could not reopen {}: {}"),
+    // Search
+    ("no_search_pattern", "No search pattern"),
+    ("match_of", "Match {} of {}"),
+    ("searching", "Searching… {}%"),
+    ("search_cancelled", "Search cancelled ({} matches found)"),
+    ("matches_capped", "Stopped searching at {} matches"),
+    ("no_match", "No match"),
+    ("no_matches", "No matches"),
+    ("no_matches_to_replace", "No matches to replace"),
+    ("replaced", "Replaced {} occurrences"),
+    (
+        "replaced_in_buffers",
+        "Replaced {} occurrences in {} buffers: {}",
+    ),
+    (
+        "no_buffers_replace",
+        "No replacement across buffers to undo",
+    ),
+    (
+        "buffers_replace_undone",
+        "Undid the replacement in {} buffers",
+    ),
+    (
+        "buffers_replace_undone_partly",
+        "Undid the replacement in {} buffers; {} changed since",
+    ),
+    (
+        "rename_panel_header",
+        " Rename {} → {}: {} of {} lines in {} files  (Space toggle, A all, Enter apply, Esc cancel)",
+    ),
+    ("rename_nothing", "Nothing to rename: no file mentions {}"),
+    ("rename_none_accepted", "No changes accepted"),
+    (
+        "renamed",
+        "Renamed {} lines in {} files ({} on disk, originals kept as .bak)",
+    ),
+    ("renamed_stale", "{}; {} skipped, changed since the preview"),
+];
+
+/// The strings in use: the defaults with a language file's replacements.
+#[derive(Debug, Default)]
+pub struct Strings {
+    overrides: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Load `<config>/lang/<language>.json`. No language means the defaults;
+    /// a missing or malformed file is an error.
+    pub fn load(language: &str) -> Result<Strings, String> {
+        if language.is_empty() {
+            return Ok(Strings::default());
+        }
+        let path = match config::config_dir() {
+            Some(dir) => dir.join("lang").join(format!("{}.json", language)),
+            None => return Ok(Strings::default()),
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read lang/{}.json: {}", language, e))?;
+        let json = JsonValue::parse(&text).map_err(|e| format!("lang/{}.json: {}", language, e))?;
+        Ok(Strings::from_json(&json))
+    }
+
+    /// Take replacements from a JSON object of key to string. Unknown keys,
+    /// non-strings and strings with a different number of `{}` than the
+    /// default are skipped.
+    pub fn from_json(json: &JsonValue) -> Strings {
+        let fields = json.as_object().unwrap_or(&[]);
+        let overrides = fields
+            .iter()
+            .filter_map(|(key, value)| {
+                let default = default(key)?;
+                let text = value.as_str()?;
+                (placeholders(text) == placeholders(default)).then(|| (key.clone(), text.into()))
+            })
+            .collect();
+        Strings { overrides }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.overrides
+            .get(key)
+            .map(String::as_str)
+            .or_else(|| default(key))
+    }
+}
+
+fn default(key: &str) -> Option<&'static str> {
+    DEFAULTS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, text)| *text)
+}
+
+fn placeholders(text: &str) -> usize {
+    text.matches("{}").count()
+}
+
+/// Fill the `{}` in `template` with `args`, in order.
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut pieces = template.split("{}");
+    let mut out = pieces.next().unwrap_or_default().to_string();
+    for piece in pieces {
+        if let Some(arg) = args.next() {
+            out.push_str(&arg.to_string());
+        }
+        out.push_str(piece);
+    }
+    out
+}
+
+static STRINGS: OnceLock<Strings> = OnceLock::new();
+
+/// Use `strings` for the rest of the session. Only the first call counts.
+pub fn init(strings: Strings) {
+    let _ = STRINGS.set(strings);
+}
+
+/// The text for `key`. An unknown key is shown as itself.
+pub fn get(key: &str) -> &str {
+    let text = STRINGS.get_or_init(Strings::default).get(key);
+    debug_assert!(text.is_some(), "unknown string key {}", key);
+    text.unwrap_or(key)
+}
+
+/// The text for `key` with its `{}` filled by `args`.
+pub fn format(key: &str, args: &[&dyn Display]) -> String {
+    fill(get(key), args)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_have_unique_keys() {
+        for (i, (key, _)) in DEFAULTS.iter().enumerate() {
+            assert!(
+                DEFAULTS[i + 1..].iter().all(|(k, _)| k != key),
+                "duplicate key {}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_overrides_from_json() {
+        let json = JsonValue::parse(
+            r#"{
+                "saved": "¡Guardado!",
+                "match_of": "Coincidencia {} de {}",
+                "copied_chars": "Copiado",
+                "undo": 3,
+                "unknown": "x"
+            }"#,
+        )
+        .unwrap();
+        let strings = Strings::from_json(&json);
+        assert_eq!(strings.get("saved"), Some("¡Guardado!"));
+        assert_eq!(strings.get("match_of"), Some("Coincidencia {} de {}"));
+        // Wrong placeholder count or type keeps the default
+        assert_eq!(strings.get("copied_chars"), Some("Copied {} chars"));
+        assert_eq!(strings.get("undo"), Some("Undo"));
+        assert_eq!(strings.get("unknown"), None);
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(fill("Match {} of {}", &[&2, &10]), "Match 2 of 10");
+        assert_eq!(fill("{} at start", &[&"x"]), "x at start");
+        assert_eq!(fill("no args", &[]), "no args");
+        // Missing arguments leave the slot empty
+        assert_eq!(fill("[{}/{}]", &[&1]), "[1/]");
+    }
+}
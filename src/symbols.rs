@@ -0,0 +1,235 @@
+// ---------------------------------------------------------------------------
+// Document outline — definitions found by keyword, per filetype
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: &'static str,
+    pub line: usize,
+}
+
+/// Keywords that introduce a definition, with the kind they name.
+fn definition_keywords(filetype: &str) -> &'static [(&'static str, &'static str)] {
+    match filetype {
+        "rs" => &[
+            ("fn", "function"),
+            ("struct", "struct"),
+            ("enum", "enum"),
+            ("trait", "trait"),
+            ("impl", "impl"),
+            ("mod", "module"),
+            ("type", "type"),
+            ("const", "constant"),
+            ("static", "static"),
+            ("macro_rules!", "macro"),
+        ],
+        "py" => &[("def", "function"), ("class", "class")],
+        "js" | "jsx" | "ts" | "tsx" | "mjs" => &[
+            ("function", "function"),
+            ("class", "class"),
+            ("interface", "interface"),
+            ("type", "type"),
+        ],
+        "go" => &[("func", "function"), ("type", "type")],
+        "c" | "h" | "cc" | "cpp" | "hpp" => &[
+            ("struct", "struct"),
+            ("enum", "enum"),
+            ("union", "union"),
+            ("class", "class"),
+            ("#define", "macro"),
+        ],
+        "sh" | "bash" => &[("function", "function")],
+        "rb" => &[
+            ("def", "function"),
+            ("class", "class"),
+            ("module", "module"),
+        ],
+        _ => &[],
+    }
+}
+
+/// Words that may precede a definition keyword (`pub async fn`).
+const MODIFIERS: &[&str] = &[
+    "pub",
+    "pub(crate)",
+    "pub(super)",
+    "async",
+    "unsafe",
+    "extern",
+    "export",
+    "default",
+    "static",
+    "const",
+    "abstract",
+    "typedef",
+];
+
+/// Definitions in `text`, in file order.
+pub fn outline(text: &str, filetype: &str) -> Vec<Symbol> {
+    let keywords = definition_keywords(filetype);
+    if keywords.is_empty() {
+        return Vec::new();
+    }
+    let is_keyword = |t: &str| keywords.iter().any(|(k, _)| *k == t);
+    let mut symbols = Vec::new();
+    for (line, line_text) in text.lines().enumerate() {
+        let tokens: Vec<&str> = line_text.split_whitespace().collect();
+        // Skip modifiers. `const` and `static` are keywords themselves unless
+        // another keyword or modifier follows (`const fn`).
+        let mut i = 0;
+        while i < tokens.len()
+            && MODIFIERS.contains(&tokens[i])
+            && (!is_keyword(tokens[i])
+                || tokens
+                    .get(i + 1)
+                    .is_some_and(|t| is_keyword(t) || MODIFIERS.contains(t)))
+        {
+            i += 1;
+        }
+        let first = match tokens.get(i) {
+            Some(t) => *t,
+            None => continue,
+        };
+        // Generics may be attached to the keyword (`impl<T>`)
+        let (keyword, attached) = first.split_at(first.find('<').unwrap_or(first.len()));
+        let kind = match keywords.iter().find(|(k, _)| *k == keyword) {
+            Some((_, kind)) => *kind,
+            None => continue,
+        };
+        let rest = format!("{} {}", attached, tokens[i + 1..].join(" "));
+        if let Some(name) = definition_name(&rest, kind) {
+            symbols.push(Symbol { name, kind, line });
+        }
+    }
+    symbols
+}
+
+/// The defined name at the start of `rest`. An `impl` keeps its whole header
+/// (`Display for Editor`); Go methods skip their receiver.
+fn definition_name(rest: &str, kind: &str) -> Option<String> {
+    let rest = rest.trim_start();
+    if kind == "impl" {
+        let mut header = rest.split('{').next()?.trim();
+        if header.starts_with('<') {
+            let mut depth = 0;
+            for (i, c) in header.char_indices() {
+                match c {
+                    '<' => depth += 1,
+                    '>' => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    header = header[i + 1..].trim_start();
+                    break;
+                }
+            }
+        }
+        return (!header.is_empty()).then(|| header.to_string());
+    }
+    let rest = match rest.strip_prefix('(') {
+        Some(receiver) => receiver.split_once(')')?.1.trim_start(),
+        None => rest,
+    };
+    let rest = rest.trim_start_matches('*');
+    let end = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(rest.len());
+    (end > 0).then(|| rest[..end].to_string())
+}
+
+// ---------------------------------------------------------------------------
+// Fuzzy matching
+// ---------------------------------------------------------------------------
+
+/// Score `candidate` against `query` (case-insensitive subsequence), or None
+/// when it does not match. Higher is better: consecutive characters and
+/// characters at word starts score extra, gaps and long candidates cost.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut score = 0i32;
+    let mut chars = candidate.char_indices();
+    let mut prev_match: Option<usize> = None;
+    let mut prev_char: Option<char> = None;
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_lowercase().next().unwrap_or(q);
+        loop {
+            let (i, c) = chars.next()?;
+            let before = prev_char;
+            prev_char = Some(c);
+            if c.to_lowercase().next() != Some(q) {
+                continue;
+            }
+            score += 1;
+            if prev_match.is_some_and(|p| p + 1 == i) {
+                score += 4;
+            }
+            let at_word_start = before
+                .is_none_or(|b| !b.is_alphanumeric() || (b.is_lowercase() && c.is_uppercase()));
+            if at_word_start {
+                score += 3;
+            }
+            prev_match = Some(i);
+            break;
+        }
+    }
+    Some(score * 8 - candidate.len() as i32)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(symbols: &[Symbol]) -> Vec<(&str, &str, usize)> {
+        symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.kind, s.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_outline_rust() {
+        let text = "pub struct Editor {\n}\n\nimpl<T> Display for Editor {\n    pub(crate) async fn render(&self) {}\n    const fn zero() {}\n}\nconst MAX: usize = 3;\nlet fn_name = 1;\n";
+        assert_eq!(
+            names(&outline(text, "rs")),
+            vec![
+                ("Editor", "struct", 0),
+                ("Display for Editor", "impl", 3),
+                ("render", "function", 4),
+                ("zero", "function", 5),
+                ("MAX", "constant", 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outline_other_languages() {
+        let py = "class Foo:\n    def bar(self):\n        pass\n";
+        assert_eq!(
+            names(&outline(py, "py")),
+            vec![("Foo", "class", 0), ("bar", "function", 1)]
+        );
+        let go = "func (e *Editor) Render() {\n}\nfunc main() {}\n";
+        assert_eq!(
+            names(&outline(go, "go")),
+            vec![("Render", "function", 0), ("main", "function", 2)]
+        );
+        assert!(outline("fn x() {}", "text").is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("xyz", "render"), None);
+        assert!(fuzzy_score("", "render").is_some());
+        // Word starts beat scattered matches
+        let camel = fuzzy_score("rb", "renderBlame").unwrap();
+        let scattered = fuzzy_score("rb", "rubble").unwrap();
+        assert!(camel > scattered);
+        // Consecutive beats gapped
+        assert!(fuzzy_score("ren", "render").unwrap() > fuzzy_score("ren", "rowEnd").unwrap());
+        assert!(fuzzy_score("ED", "editor").is_some());
+    }
+}
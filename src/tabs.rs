@@ -0,0 +1,111 @@
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Tab stops — how far each tab character reaches on screen
+// ---------------------------------------------------------------------------
+//
+// A tab runs to the next multiple of `tab_width`. With elastic tabstops,
+// the tab-separated cells of adjacent lines form columns instead: a column
+// runs through the consecutive lines that have a cell in it, and is as
+// wide as its widest cell plus one column of padding (at least
+// `tab_width`). The text after a line's last tab is not part of a column.
+
+/// Where the tabs of one line end.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TabStops {
+    width: usize,
+    /// Screen column after each tab, for elastic tabstops.
+    elastic: Vec<usize>,
+}
+
+impl TabStops {
+    /// A stop every `width` columns.
+    pub fn fixed(width: usize) -> Self {
+        TabStops {
+            width: width.max(1),
+            elastic: Vec::new(),
+        }
+    }
+
+    /// Elastic stops for `lines[index]`, aligned with the cells of its
+    /// neighbours in `lines`.
+    pub fn elastic(lines: &[&str], index: usize, width: usize) -> Self {
+        let width = width.max(1);
+        let cells: Vec<Vec<&str>> = lines.iter().map(|l| l.split('\t').collect()).collect();
+        // A line has a cell in column `j` when a tab ends it
+        let has_column = |i: usize, j: usize| cells[i].len() > j + 1;
+        let mut elastic = Vec::new();
+        let mut stop = 0;
+        for j in 0..cells[index].len() - 1 {
+            let mut first = index;
+            while first > 0 && has_column(first - 1, j) {
+                first -= 1;
+            }
+            let mut last = index;
+            while last + 1 < lines.len() && has_column(last + 1, j) {
+                last += 1;
+            }
+            let widest = cells[first..=last]
+                .iter()
+                .map(|line| unicode::display_width(line[j]))
+                .max()
+                .unwrap_or(0);
+            stop += (widest + 1).max(width);
+            elastic.push(stop);
+        }
+        TabStops { width, elastic }
+    }
+
+    /// The screen column after the line's `n`th tab (from 0), which starts
+    /// at column `col`. A tab is always at least one column wide.
+    pub fn end(&self, n: usize, col: usize) -> usize {
+        match self.elastic.get(n) {
+            Some(&stop) => stop.max(col + 1),
+            None => {
+                let width = self.width.max(1);
+                (col / width + 1) * width
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_stops() {
+        let stops = TabStops::fixed(4);
+        assert_eq!(stops.end(0, 0), 4);
+        assert_eq!(stops.end(0, 3), 4);
+        assert_eq!(stops.end(0, 4), 8);
+        assert_eq!(stops.end(3, 9), 12);
+        assert_eq!(TabStops::fixed(0).end(0, 5), 6);
+    }
+
+    #[test]
+    fn test_elastic_stops() {
+        let lines = [
+            "name\tsize\tkind",
+            "a\t12345678\tfile",
+            "longer name\t1\t",
+            "plain line",
+        ];
+        // Column 0 spans the first three lines, column 1 as well
+        let first = TabStops::elastic(&lines, 0, 4);
+        assert_eq!(first.end(0, 4), 12);
+        assert_eq!(first.end(1, 16), 21);
+        assert_eq!(TabStops::elastic(&lines, 1, 4), first);
+        // Past the elastic columns, tabs fall back to fixed stops
+        assert_eq!(first.end(2, 25), 28);
+        // A line without tabs ends the block; a narrow block keeps the
+        // minimum width
+        let lines = ["ab\tc", "plain", "x\ty"];
+        assert_eq!(TabStops::elastic(&lines, 0, 4).end(0, 2), 4);
+        assert_eq!(TabStops::elastic(&lines, 2, 8).end(0, 1), 8);
+    }
+}
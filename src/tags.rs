@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+
+// ---------------------------------------------------------------------------
+// Tags files — Exuberant / Universal ctags format
+// ---------------------------------------------------------------------------
+
+/// Where a tag's definition is within its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagAddress {
+    Line(usize),     // 0-based line number
+    Pattern(String), // text of the line (a `/^...$/` search)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub path: PathBuf,
+    pub address: TagAddress,
+    pub kind: Option<String>,
+}
+
+/// Find a `tags` (or `.tags`) file in `dir` or its ancestors.
+pub fn find_tags_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|d| [d.join("tags"), d.join(".tags")])
+        .find(|p| p.is_file())
+}
+
+/// Read a tags file. Tag paths are resolved against the file's directory.
+pub fn load(path: &Path) -> Result<Vec<Tag>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(parse(&text, base))
+}
+
+/// Parse tags lines (`name<TAB>file<TAB>address;"<TAB>fields`). Pseudo-tags
+/// (`!_TAG_...`) and malformed lines are skipped.
+pub fn parse(text: &str, base: &Path) -> Vec<Tag> {
+    text.lines()
+        .filter(|l| !l.starts_with("!_"))
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, '\t');
+            let name = parts.next()?;
+            let file = parts.next()?;
+            let rest = parts.next()?;
+            // The address ends at `;"` (extended format) or the line end
+            let (address, fields) = match rest.find(";\"") {
+                Some(i) => (&rest[..i], &rest[i + 2..]),
+                None => (rest, ""),
+            };
+            let kind = fields
+                .split('\t')
+                .filter(|f| !f.is_empty())
+                .find(|f| !f.contains(':') || f.starts_with("kind:"))
+                .map(|f| f.trim_start_matches("kind:").to_string());
+            Some(Tag {
+                name: name.to_string(),
+                path: base.join(file),
+                address: parse_address(address)?,
+                kind,
+            })
+        })
+        .collect()
+}
+
+fn parse_address(address: &str) -> Option<TagAddress> {
+    if let Ok(line) = address.parse::<usize>() {
+        return Some(TagAddress::Line(line.saturating_sub(1)));
+    }
+    let delim = address.chars().next().filter(|c| *c == '/' || *c == '?')?;
+    let inner = address[1..].strip_suffix(delim)?;
+    let inner = inner.strip_prefix('^').unwrap_or(inner);
+    let inner = inner.strip_suffix('$').unwrap_or(inner);
+    let mut pattern = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            pattern.extend(chars.next());
+        } else {
+            pattern.push(c);
+        }
+    }
+    Some(TagAddress::Pattern(pattern))
+}
+
+impl TagAddress {
+    /// The 0-based line the address points at in `text`.
+    pub fn resolve(&self, text: &str) -> Option<usize> {
+        match self {
+            TagAddress::Line(line) => Some(*line),
+            TagAddress::Pattern(pattern) => text.lines().position(|l| l == pattern),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tags() {
+        let text = "!_TAG_FILE_FORMAT\t2\t/extended format/\n\
+                    main\tsrc/main.rs\t/^fn main() {$/;\"\tf\n\
+                    Editor\tsrc/editor.rs\t/^pub struct Editor {$/;\"\tkind:struct\tline:150\n\
+                    MAX\tsrc/a.c\t12;\"\td\n\
+                    broken line\n";
+        let tags = parse(text, Path::new("/proj"));
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[0].name, "main");
+        assert_eq!(tags[0].path, PathBuf::from("/proj/src/main.rs"));
+        assert_eq!(
+            tags[0].address,
+            TagAddress::Pattern("fn main() {".to_string())
+        );
+        assert_eq!(tags[0].kind.as_deref(), Some("f"));
+        assert_eq!(tags[1].kind.as_deref(), Some("struct"));
+        assert_eq!(tags[2].address, TagAddress::Line(11));
+    }
+
+    #[test]
+    fn test_pattern_escapes_and_resolve() {
+        let tags = parse("f\ta.js\t/^  a\\/b \\\\ f() {$/\n", Path::new(""));
+        let address = &tags[0].address;
+        assert_eq!(*address, TagAddress::Pattern("  a/b \\ f() {".to_string()));
+        assert_eq!(address.resolve("x\n  a/b \\ f() {\n"), Some(1));
+        assert_eq!(address.resolve("nothing"), None);
+    }
+}
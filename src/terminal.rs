@@ -1,5 +1,9 @@
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
 use std::io::Write;
+use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 // ---------------------------------------------------------------------------
 // libc FFI — zero external dependencies
@@ -7,9 +11,13 @@ use std::sync::atomic::{AtomicBool, Ordering};
 
 const STDIN_FILENO: i32 = 0;
 const STDOUT_FILENO: i32 = 1;
+const TCSANOW: i32 = 0;
 const TCSAFLUSH: i32 = 2;
 const TIOCGWINSZ: u64 = 0x5413;
+const FIONREAD: u64 = 0x541B;
 const SIGWINCH: i32 = 28;
+const SIGHUP: i32 = 1;
+const POLLIN: i16 = 0x001;
 const NCCS: usize = 32;
 
 // Termios flag constants
@@ -57,6 +65,13 @@ struct Winsize {
     ws_ypixel: u16,
 }
 
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
 // Linux x86-64 sigaction layout
 #[repr(C)]
 struct SigAction {
@@ -72,6 +87,7 @@ unsafe extern "C" {
     fn ioctl(fd: i32, request: u64, ...) -> i32;
     fn sigaction(signum: i32, act: *const SigAction, oldact: *mut SigAction) -> i32;
     fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+    fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> i32;
 }
 
 // ---------------------------------------------------------------------------
@@ -84,6 +100,16 @@ extern "C" fn sigwinch_handler(_sig: i32) {
     RESIZED.store(true, Ordering::SeqCst);
 }
 
+// ---------------------------------------------------------------------------
+// SIGHUP handling — the terminal went away
+// ---------------------------------------------------------------------------
+
+static HUNG_UP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn sighup_handler(_sig: i32) {
+    HUNG_UP.store(true, Ordering::SeqCst);
+}
+
 // ---------------------------------------------------------------------------
 // Color mode detection
 // ---------------------------------------------------------------------------
@@ -93,9 +119,16 @@ pub enum ColorMode {
     TrueColor,
     Color256,
     Color16,
+    /// No colors, only bold, underline and reverse video (`NO_COLOR` or
+    /// `--no-color`).
+    Mono,
 }
 
 pub fn detect_color_mode() -> ColorMode {
+    // https://no-color.org: set and not empty
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return ColorMode::Mono;
+    }
     if let Ok(val) = std::env::var("COLORTERM") {
         let val = val.to_lowercase();
         if val == "truecolor" || val == "24bit" {
@@ -111,12 +144,142 @@ pub fn detect_color_mode() -> ColorMode {
     ColorMode::Color16
 }
 
+// ---------------------------------------------------------------------------
+// Capabilities
+// ---------------------------------------------------------------------------
+
+/// A terminal multiplexer sitting between the editor and the real terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+/// What the terminal supports, detected once from the environment at
+/// startup. Modules that emit anything beyond plain CSI sequences consult
+/// this through `capabilities()` rather than reading `TERM` themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub color_mode: ColorMode,
+    /// tmux or screen, which swallow OSC sequences unless they are wrapped
+    /// in a DCS passthrough.
+    pub multiplexer: Option<Multiplexer>,
+}
+
+impl Capabilities {
+    pub fn detect() -> Capabilities {
+        let var = |name| std::env::var(name).ok();
+        Capabilities {
+            color_mode: detect_color_mode(),
+            multiplexer: detect_multiplexer(
+                var("TERM").as_deref(),
+                var("TMUX").is_some(),
+                var("STY").is_some(),
+            ),
+        }
+    }
+
+    /// Wrap an OSC sequence so a multiplexer hands it to the outer terminal:
+    /// tmux wants `ESC P tmux;` with every ESC doubled, screen a plain DCS,
+    /// cut into pieces short enough for its buffer.
+    pub fn passthrough(&self, seq: &str) -> String {
+        match self.multiplexer {
+            None => seq.to_string(),
+            Some(Multiplexer::Tmux) => {
+                format!("\x1bPtmux;{}\x1b\\", seq.replace('\x1b', "\x1b\x1b"))
+            }
+            Some(Multiplexer::Screen) => seq
+                .as_bytes()
+                .chunks(SCREEN_DCS_CHUNK)
+                .map(|chunk| format!("\x1bP{}\x1b\\", String::from_utf8_lossy(chunk)))
+                .collect(),
+        }
+    }
+}
+
+/// Longest piece of a sequence sent through one screen DCS.
+const SCREEN_DCS_CHUNK: usize = 512;
+
+/// `$TMUX` is set inside tmux whatever `TERM` says; `screen*` terminals
+/// without it are GNU screen (also told by `$STY`).
+fn detect_multiplexer(term: Option<&str>, in_tmux: bool, in_screen: bool) -> Option<Multiplexer> {
+    let term = term.unwrap_or("");
+    if in_tmux || term.starts_with("tmux") {
+        Some(Multiplexer::Tmux)
+    } else if in_screen || term.starts_with("screen") {
+        Some(Multiplexer::Screen)
+    } else {
+        None
+    }
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+/// The terminal's capabilities, detected on first use.
+pub fn capabilities() -> &'static Capabilities {
+    CAPABILITIES.get_or_init(Capabilities::detect)
+}
+
+/// Draw without colors whatever the environment says (`--no-color`). Only
+/// takes effect before the capabilities are first read.
+pub fn disable_color() {
+    let _ = CAPABILITIES.set(Capabilities {
+        color_mode: ColorMode::Mono,
+        ..Capabilities::detect()
+    });
+}
+
+/// Check that stdin is a terminal that can be put in raw mode, without
+/// changing its settings.
+pub fn check_raw_mode() -> Result<(), String> {
+    let mut attrs = Termios::zeroed();
+    if unsafe { tcgetattr(STDIN_FILENO, &mut attrs) } != 0 {
+        return Err("Failed to get terminal attributes".into());
+    }
+    if unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &attrs) } != 0 {
+        return Err("Failed to set raw mode".into());
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Backend
+// ---------------------------------------------------------------------------
+
+/// What input decoding and frame output need from a terminal, so they work
+/// the same on `Terminal` (the Linux FFI below), the mock used in tests and
+/// backends for other platforms.
+pub trait TerminalBackend {
+    /// Read a single byte of input. Returns `None` on timeout / no data.
+    fn read_byte(&self) -> Option<u8>;
+
+    /// Whether input bytes are waiting, so `read_byte` would not wait.
+    fn input_pending(&self) -> bool;
+
+    /// The current size as (width, height).
+    fn size(&mut self) -> (u16, u16);
+
+    /// Switch to raw mode and the alternate screen, with mouse, bracketed
+    /// paste and modified-key reporting on.
+    fn enter_raw_mode(&self) -> Result<(), String>;
+
+    /// Undo `enter_raw_mode`, handing the terminal back as it was found.
+    fn exit_raw_mode(&self);
+
+    /// Queue output for the terminal.
+    fn write(&self, buf: &[u8]);
+
+    /// Send the queued output.
+    fn flush(&self);
+}
+
 // ---------------------------------------------------------------------------
 // Terminal
 // ---------------------------------------------------------------------------
 
 pub struct Terminal {
     original: Termios,
+    raw: Termios,
     width: u16,
     height: u16,
 }
@@ -133,7 +296,7 @@ impl Terminal {
             return Err("Failed to get terminal attributes".into());
         }
 
-        // Enable raw mode
+        // Raw mode settings
         let mut raw = original;
         raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
         raw.c_oflag &= !OPOST;
@@ -143,45 +306,34 @@ impl Terminal {
         raw.c_cc[6] = 0; // VMIN
         raw.c_cc[5] = 1; // VTIME
 
-        if unsafe { tcsetattr(STDIN_FILENO, TCSAFLUSH, &raw) } != 0 {
-            return Err("Failed to set raw mode".into());
-        }
-
         // Query initial size
         let (width, height) = query_terminal_size()?;
 
-        // Register SIGWINCH handler
-        let sa = SigAction {
-            sa_handler: sigwinch_handler,
-            sa_flags: SA_RESTART,
-            sa_restorer: 0,
-            sa_mask: [0; 16],
-        };
-        if unsafe { sigaction(SIGWINCH, &sa, std::ptr::null_mut()) } != 0 {
-            // Restore terminal before returning error
-            unsafe { tcsetattr(STDIN_FILENO, TCSAFLUSH, &original) };
-            return Err("Failed to register SIGWINCH handler".into());
+        // Register SIGWINCH and SIGHUP handlers
+        for (signal, handler, name) in [
+            (SIGWINCH, sigwinch_handler as extern "C" fn(i32), "SIGWINCH"),
+            (SIGHUP, sighup_handler, "SIGHUP"),
+        ] {
+            let sa = SigAction {
+                sa_handler: handler,
+                sa_flags: SA_RESTART,
+                sa_restorer: 0,
+                sa_mask: [0; 16],
+            };
+            if unsafe { sigaction(signal, &sa, std::ptr::null_mut()) } != 0 {
+                return Err(format!("Failed to register {} handler", name));
+            }
         }
 
-        // Enter alternate screen, enable mouse and bracketed paste, hide cursor
-        write_all(b"\x1b[?1049h");
-        enable_mouse();
-        enable_bracketed_paste();
-
-        Ok(Terminal {
+        let terminal = Terminal {
             original,
+            raw,
             width,
             height,
-        })
-    }
-
-    /// Return the current terminal size as (width, height), re-querying via ioctl.
-    pub fn size(&mut self) -> (u16, u16) {
-        if let Ok((w, h)) = query_terminal_size() {
-            self.width = w;
-            self.height = h;
-        }
-        (self.width, self.height)
+        };
+        // Dropping the terminal on failure restores the original state
+        terminal.enter_raw_mode()?;
+        Ok(terminal)
     }
 
     /// Check if a SIGWINCH resize occurred. If so, refresh the cached size and
@@ -195,18 +347,83 @@ impl Terminal {
         }
     }
 
-    /// Read a single byte from stdin. Returns `None` on timeout / no data.
-    pub fn read_byte(&self) -> Option<u8> {
+    /// Whether the terminal hung up (SIGHUP). Nothing written to it will
+    /// be seen any more.
+    pub fn hung_up(&self) -> bool {
+        HUNG_UP.load(Ordering::SeqCst)
+    }
+
+    /// Temporarily hand the terminal back to a child process: restore cooked
+    /// mode, leave the alternate screen, and disable mouse / bracketed paste.
+    pub fn suspend(&self) {
+        self.exit_raw_mode();
+    }
+
+    /// Re-enter raw mode and the alternate screen after `suspend`.
+    pub fn resume(&self) {
+        let _ = self.enter_raw_mode();
+        clear_screen();
+        flush();
+    }
+
+    /// How long `read_byte` waits for input, rounded up to the tenths of a
+    /// second the terminal counts in.
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.raw.c_cc[5] = vtime(timeout); // VTIME
+        unsafe {
+            tcsetattr(STDIN_FILENO, TCSANOW, &self.raw);
+        }
+    }
+
+    /// Wait up to `timeout` for input. True when some arrived.
+    pub fn wait_for_input(&self, timeout: Duration) -> bool {
+        let mut fd = PollFd {
+            fd: STDIN_FILENO,
+            events: POLLIN,
+            revents: 0,
+        };
+        let ms = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        unsafe { poll(&mut fd, 1, ms) > 0 && fd.revents & POLLIN != 0 }
+    }
+}
+
+impl TerminalBackend for Terminal {
+    fn read_byte(&self) -> Option<u8> {
         let mut buf: u8 = 0;
         let n = unsafe { read(STDIN_FILENO, &mut buf, 1) };
         if n == 1 { Some(buf) } else { None }
     }
-}
 
-impl Drop for Terminal {
-    fn drop(&mut self) {
+    fn input_pending(&self) -> bool {
+        let mut n: i32 = 0;
+        unsafe { ioctl(STDIN_FILENO, FIONREAD, &mut n) == 0 && n > 0 }
+    }
+
+    /// Re-queried via ioctl, keeping the last known size if that fails.
+    fn size(&mut self) -> (u16, u16) {
+        if let Ok((w, h)) = query_terminal_size() {
+            self.width = w;
+            self.height = h;
+        }
+        (self.width, self.height)
+    }
+
+    fn enter_raw_mode(&self) -> Result<(), String> {
+        if unsafe { tcsetattr(STDIN_FILENO, TCSAFLUSH, &self.raw) } != 0 {
+            return Err("Failed to set raw mode".into());
+        }
+        write_all(b"\x1b[?1049h"); // enter alternate screen
+        enable_mouse();
+        enable_bracketed_paste();
+        enable_modify_other_keys();
+        flush();
+        Ok(())
+    }
+
+    fn exit_raw_mode(&self) {
         disable_mouse();
         disable_bracketed_paste();
+        disable_modify_other_keys();
         show_cursor();
         write_all(b"\x1b[?1049l"); // leave alternate screen
         flush();
@@ -214,6 +431,92 @@ impl Drop for Terminal {
             tcsetattr(STDIN_FILENO, TCSAFLUSH, &self.original);
         }
     }
+
+    fn write(&self, buf: &[u8]) {
+        write_all(buf);
+    }
+
+    fn flush(&self) {
+        flush();
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if !self.hung_up() {
+            self.exit_raw_mode();
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Mock backend for tests
+// ---------------------------------------------------------------------------
+
+/// A terminal with canned input that records its output. Input runs out
+/// like a timeout.
+#[cfg(test)]
+pub(crate) struct MockTerminal {
+    input: Vec<u8>,
+    pos: Cell<usize>,
+    size: (u16, u16),
+    output: RefCell<Vec<u8>>,
+}
+
+#[cfg(test)]
+impl MockTerminal {
+    pub(crate) fn new(input: &[u8]) -> Self {
+        MockTerminal {
+            input: input.to_vec(),
+            pos: Cell::new(0),
+            size: (80, 24),
+            output: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// How many input bytes have been read.
+    pub(crate) fn consumed(&self) -> usize {
+        self.pos.get()
+    }
+
+    /// Everything written so far, lossily decoded.
+    pub(crate) fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output.borrow()).into_owned()
+    }
+}
+
+#[cfg(test)]
+impl TerminalBackend for MockTerminal {
+    fn read_byte(&self) -> Option<u8> {
+        let byte = self.input.get(self.pos.get()).copied();
+        self.pos.set(self.pos.get() + byte.is_some() as usize);
+        byte
+    }
+
+    fn input_pending(&self) -> bool {
+        self.pos.get() < self.input.len()
+    }
+
+    fn size(&mut self) -> (u16, u16) {
+        self.size
+    }
+
+    fn enter_raw_mode(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn exit_raw_mode(&self) {}
+
+    fn write(&self, buf: &[u8]) {
+        self.output.borrow_mut().extend_from_slice(buf);
+    }
+
+    fn flush(&self) {}
+}
+
+/// `timeout` in the tenths of a second of VTIME, at least 1.
+fn vtime(timeout: Duration) -> u8 {
+    timeout.as_millis().div_ceil(100).clamp(1, 255) as u8
 }
 
 // ---------------------------------------------------------------------------
@@ -246,12 +549,15 @@ fn query_terminal_size() -> Result<(u16, u16), String> {
 // Escape sequence helpers
 // ---------------------------------------------------------------------------
 
+/// Button and drag reporting with SGR coordinates, asking for urxvt-style
+/// decimal coordinates first for terminals that lack SGR. Terminals with
+/// neither (older screen) fall back to X10 bytes, capped at column 223.
 pub fn enable_mouse() {
-    write_all(b"\x1b[?1000h\x1b[?1006h");
+    write_all(b"\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h");
 }
 
 pub fn disable_mouse() {
-    write_all(b"\x1b[?1006l\x1b[?1000l");
+    write_all(b"\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l");
 }
 
 pub fn enable_bracketed_paste() {
@@ -262,6 +568,16 @@ pub fn disable_bracketed_paste() {
     write_all(b"\x1b[?2004l");
 }
 
+/// Ask xterm-compatible terminals to report keys like Ctrl+Shift+letter
+/// that otherwise send the same byte as their unshifted form.
+pub fn enable_modify_other_keys() {
+    write_all(b"\x1b[>4;1m");
+}
+
+pub fn disable_modify_other_keys() {
+    write_all(b"\x1b[>4m");
+}
+
 pub fn hide_cursor() {
     write_all(b"\x1b[?25l");
 }
@@ -270,15 +586,22 @@ pub fn show_cursor() {
     write_all(b"\x1b[?25h");
 }
 
-pub fn move_cursor(row: u16, col: u16) {
-    let seq = format!("\x1b[{};{}H", row, col);
-    write_all(seq.as_bytes());
-}
-
 pub fn clear_screen() {
     write_all(b"\x1b[2J");
 }
 
+// ---------------------------------------------------------------------------
+// OSC 11 background color query
+// ---------------------------------------------------------------------------
+
+/// Ask the terminal for its default background color. Terminals that
+/// support it reply with `\x1b]11;rgb:RRRR/GGGG/BBBB` on the input, which
+/// `input` decodes into `Event::Background`; others ignore it.
+pub fn query_background() {
+    write_all(b"\x1b]11;?\x07");
+    flush();
+}
+
 // ---------------------------------------------------------------------------
 // OSC 52 clipboard (system clipboard via terminal escape)
 // ---------------------------------------------------------------------------
@@ -315,7 +638,7 @@ pub fn base64_encode(data: &[u8]) -> String {
 pub fn set_clipboard_osc52(text: &str) {
     let encoded = base64_encode(text.as_bytes());
     let seq = format!("\x1b]52;c;{}\x07", encoded);
-    write_all(seq.as_bytes());
+    write_all(capabilities().passthrough(&seq).as_bytes());
     flush();
 }
 
@@ -339,11 +662,64 @@ mod tests {
         assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
     }
 
+    #[test]
+    fn test_vtime() {
+        assert_eq!(vtime(Duration::from_millis(100)), 1);
+        assert_eq!(vtime(Duration::from_millis(1)), 1);
+        assert_eq!(vtime(Duration::from_millis(250)), 3);
+        assert_eq!(vtime(Duration::from_secs(60)), 255);
+    }
+
     #[test]
     fn test_base64_encode_utf8() {
         assert_eq!(base64_encode("café".as_bytes()), "Y2Fmw6k=");
     }
 
+    #[test]
+    fn test_detect_multiplexer() {
+        assert_eq!(
+            detect_multiplexer(Some("xterm-256color"), false, false),
+            None
+        );
+        assert_eq!(
+            detect_multiplexer(Some("screen-256color"), true, false),
+            Some(Multiplexer::Tmux)
+        );
+        assert_eq!(
+            detect_multiplexer(Some("tmux-256color"), false, false),
+            Some(Multiplexer::Tmux)
+        );
+        assert_eq!(
+            detect_multiplexer(Some("screen"), false, false),
+            Some(Multiplexer::Screen)
+        );
+        assert_eq!(
+            detect_multiplexer(None, false, true),
+            Some(Multiplexer::Screen)
+        );
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let caps = |multiplexer| Capabilities {
+            color_mode: ColorMode::Color16,
+            multiplexer,
+        };
+        let seq = "\x1b]52;c;Zm9v\x07";
+        assert_eq!(caps(None).passthrough(seq), seq);
+        assert_eq!(
+            caps(Some(Multiplexer::Tmux)).passthrough(seq),
+            "\x1bPtmux;\x1b\x1b]52;c;Zm9v\x07\x1b\\"
+        );
+        assert_eq!(
+            caps(Some(Multiplexer::Screen)).passthrough(seq),
+            "\x1bP\x1b]52;c;Zm9v\x07\x1b\\"
+        );
+        let long = "x".repeat(SCREEN_DCS_CHUNK + 1);
+        let wrapped = caps(Some(Multiplexer::Screen)).passthrough(&long);
+        assert_eq!(wrapped.matches("\x1bP").count(), 2);
+    }
+
     #[test]
     fn test_detect_color_mode_default() {
         // Just ensure it doesn't panic; actual result depends on env
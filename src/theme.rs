@@ -0,0 +1,292 @@
+use crate::highlight::Token;
+use crate::json::JsonValue;
+use crate::render::{self, Color};
+
+// ---------------------------------------------------------------------------
+// Theme — colors of the editor's render layers
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Background of the cursor's line.
+    pub current_line: Color,
+    /// Background of the color column ruler.
+    pub color_column: Color,
+    /// Background of selected text, which keeps its foreground colors.
+    pub selection: Color,
+    /// Search matches: the text on them and their backgrounds, the current
+    /// one apart. Past a couple of matches per row the others are dimmed
+    /// to `search_dim`, which keeps the text's own color.
+    pub search_text: Color,
+    pub search_match: Color,
+    pub search_current: Color,
+    pub search_dim: Color,
+    /// The software cursor's cell, and the character in it.
+    pub cursor: Color,
+    pub cursor_text: Color,
+    /// Syntax colors, used by exports.
+    pub comment: Color,
+    pub string: Color,
+    pub number: Color,
+    pub keyword: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            current_line: Color::Color256(235),
+            color_column: Color::Color256(236),
+            selection: Color::Color256(24),
+            search_text: Color::Ansi(0),
+            search_match: Color::Ansi(3),
+            search_current: Color::Ansi(6),
+            search_dim: Color::Color256(58),
+            cursor: Color::Ansi(7),
+            cursor_text: Color::Ansi(0),
+            comment: Color::Color256(244),
+            string: Color::Ansi(2),
+            number: Color::Ansi(3),
+            keyword: Color::Ansi(5),
+        }
+    }
+}
+
+impl Theme {
+    /// The variant for terminals with a light background.
+    pub fn light() -> Self {
+        Theme {
+            current_line: Color::Color256(255),
+            color_column: Color::Color256(254),
+            selection: Color::Color256(153),
+            search_text: Color::Ansi(0),
+            search_match: Color::Ansi(3),
+            search_current: Color::Ansi(6),
+            search_dim: Color::Color256(230),
+            cursor: Color::Color256(238),
+            cursor_text: Color::Color256(255),
+            comment: Color::Color256(243),
+            string: Color::Color256(28),
+            number: Color::Color256(130),
+            keyword: Color::Color256(90),
+        }
+    }
+
+    /// Build a theme from a `"theme"` config object, keeping the colors of
+    /// `base` for missing or unparsable ones.
+    pub fn from_json(json: &JsonValue, base: Theme) -> Theme {
+        let mut theme = base;
+        let color = |key: &str| json.get(key).and_then(parse_color);
+        if let Some(c) = color("current_line") {
+            theme.current_line = c;
+        }
+        if let Some(c) = color("color_column") {
+            theme.color_column = c;
+        }
+        if let Some(c) = color("selection") {
+            theme.selection = c;
+        }
+        if let Some(c) = color("search_text") {
+            theme.search_text = c;
+        }
+        if let Some(c) = color("search_match") {
+            theme.search_match = c;
+        }
+        if let Some(c) = color("search_current") {
+            theme.search_current = c;
+        }
+        if let Some(c) = color("search_dim") {
+            theme.search_dim = c;
+        }
+        if let Some(c) = color("cursor") {
+            theme.cursor = c;
+        }
+        if let Some(c) = color("cursor_text") {
+            theme.cursor_text = c;
+        }
+        if let Some(c) = color("comment") {
+            theme.comment = c;
+        }
+        if let Some(c) = color("string") {
+            theme.string = c;
+        }
+        if let Some(c) = color("number") {
+            theme.number = c;
+        }
+        if let Some(c) = color("keyword") {
+            theme.keyword = c;
+        }
+        theme
+    }
+
+    /// The color of a highlighted token.
+    pub fn token_color(&self, token: Token) -> Color {
+        match token {
+            Token::Comment => self.comment,
+            Token::String => self.string,
+            Token::Number => self.number,
+            Token::Keyword => self.keyword,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Background — which theme variant to use
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// Ask the terminal for its background color (OSC 11) and follow it.
+    Auto,
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn from_name(name: &str) -> Option<Background> {
+        match name {
+            "auto" => Some(Background::Auto),
+            "dark" => Some(Background::Dark),
+            "light" => Some(Background::Light),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a background color is light: its relative luminance (Rec. 709
+/// weights, ignoring gamma) is over half.
+pub fn is_light(r: u8, g: u8, b: u8) -> bool {
+    luminance((r, g, b)) > 127.5
+}
+
+fn luminance((r, g, b): (u8, u8, u8)) -> f32 {
+    0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32
+}
+
+/// Luminance apart two colors must be to read one on the other.
+const MIN_CONTRAST: f32 = 45.0;
+
+/// `fg` for text drawn on a `bg` layer such as the selection: kept when it
+/// reads well there, else black or white, whichever stands out. The
+/// terminal's own palette (its default and 16 ANSI colors) is kept, not
+/// being known.
+pub fn readable(fg: Color, bg: Color) -> Color {
+    let rgb = |color| match color {
+        Color::Default | Color::Ansi(_) => None,
+        Color::Color256(n) => Some(render::ansi256_to_rgb(n)),
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+    };
+    let (Some(text), Some(back)) = (rgb(fg), rgb(bg)) else {
+        return fg;
+    };
+    let back = luminance(back);
+    if (luminance(text) - back).abs() >= MIN_CONTRAST {
+        fg
+    } else if back > 127.5 {
+        Color::Ansi(0)
+    } else {
+        Color::Ansi(15)
+    }
+}
+
+const ANSI_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Parse a config color: a 256-color index, `"#rrggbb"`, an ANSI color name
+/// (`"red"`, `"bright-red"`) or `"default"`.
+pub fn parse_color(value: &JsonValue) -> Option<Color> {
+    if let Some(n) = value.as_usize() {
+        return u8::try_from(n).ok().map(Color::Color256);
+    }
+    let s = value.as_str()?.trim().to_lowercase();
+    if s == "default" || s == "none" {
+        return Some(Color::Default);
+    }
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    let (name, bright) = match s.strip_prefix("bright-") {
+        Some(name) => (name, 8),
+        None => (s.as_str(), 0),
+    };
+    let index = ANSI_NAMES.iter().position(|n| *n == name)?;
+    Some(Color::Ansi(index as u8 + bright))
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Color> {
+        parse_color(&JsonValue::parse(text).unwrap())
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse("236"), Some(Color::Color256(236)));
+        assert_eq!(parse("300"), None);
+        assert_eq!(parse(r##""#1e2a3B""##), Some(Color::Rgb(0x1e, 0x2a, 0x3b)));
+        assert_eq!(parse(r##""#12345""##), None);
+        assert_eq!(parse(r#""blue""#), Some(Color::Ansi(4)));
+        assert_eq!(parse(r#""bright-red""#), Some(Color::Ansi(9)));
+        assert_eq!(parse(r#""default""#), Some(Color::Default));
+        assert_eq!(parse(r#""mauve""#), None);
+    }
+
+    #[test]
+    fn test_theme_from_json() {
+        let json = JsonValue::parse(
+            r##"{"current_line": "#202020", "color_column": "x", "keyword": "blue",
+                 "search_current": "magenta"}"##,
+        )
+        .unwrap();
+        let theme = Theme::from_json(&json, Theme::default());
+        assert_eq!(theme.current_line, Color::Rgb(0x20, 0x20, 0x20));
+        assert_eq!(theme.color_column, Theme::default().color_column);
+        assert_eq!(theme.token_color(Token::Keyword), Color::Ansi(4));
+        assert_eq!(theme.search_current, Color::Ansi(5));
+        let light = Theme::from_json(&json, Theme::light());
+        assert_eq!(light.current_line, Color::Rgb(0x20, 0x20, 0x20));
+        assert_eq!(light.color_column, Theme::light().color_column);
+    }
+
+    #[test]
+    fn test_readable() {
+        let dark = Theme::default().selection;
+        let light = Theme::light().selection;
+        // Token colors that read on the selection stay
+        assert_eq!(readable(Color::Color256(244), dark), Color::Color256(244));
+        assert_eq!(readable(Color::Color256(28), light), Color::Color256(28));
+        // Ones too close to it give way to white or black
+        assert_eq!(readable(Color::Color256(25), dark), Color::Ansi(15));
+        assert_eq!(readable(Color::Rgb(180, 210, 250), light), Color::Ansi(0));
+        // The terminal's palette is unknown
+        assert_eq!(readable(Color::Default, dark), Color::Default);
+        assert_eq!(readable(Color::Ansi(5), dark), Color::Ansi(5));
+        assert_eq!(
+            readable(Color::Color256(25), Color::Ansi(4)),
+            Color::Color256(25)
+        );
+    }
+
+    #[test]
+    fn test_is_light() {
+        assert!(is_light(255, 255, 255));
+        assert!(is_light(0xfd, 0xf6, 0xe3)); // Solarized Light
+        assert!(!is_light(0, 0, 0));
+        assert!(!is_light(0x1e, 0x1e, 0x2e));
+        // Pure blue is dark, pure green light
+        assert!(!is_light(0, 0, 255));
+        assert!(is_light(0, 255, 0));
+        assert_eq!(Background::from_name("light"), Some(Background::Light));
+        assert_eq!(Background::from_name("bright"), None);
+    }
+}
@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::unicode::is_word_char;
+
+// ---------------------------------------------------------------------------
+// Text transforms — case changes and transposition
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+    Title,
+    Swap,
+}
+
+impl Case {
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Case::Upper => text.to_uppercase(),
+            Case::Lower => text.to_lowercase(),
+            Case::Title => title_case(text),
+            Case::Swap => swap_case(text),
+        }
+    }
+}
+
+fn swap_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_uppercase() {
+            out.extend(c.to_lowercase());
+        } else {
+            out.extend(c.to_uppercase());
+        }
+    }
+    out
+}
+
+/// Capitalize the first letter of every word and lowercase the rest.
+fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_word = false;
+    for c in text.chars() {
+        if !in_word && c.is_alphanumeric() {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        in_word = c.is_alphanumeric() || (in_word && c == '\'');
+    }
+    out
+}
+
+/// A replacement within one line: start and end byte, and the new text.
+pub type LineSwap = (usize, usize, String);
+
+/// Swap the characters on either side of byte `col` of `line` (the last two
+/// at the end of the line). Returns the byte range replaced and its new
+/// text; the cursor goes to the end of the range.
+pub fn transpose_chars(line: &str, col: usize) -> Option<LineSwap> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let at = chars
+        .iter()
+        .position(|&(i, _)| i >= col)
+        .unwrap_or(chars.len());
+    let second = at.min(chars.len().checked_sub(1)?);
+    let first = second.checked_sub(1)?;
+    let (start, a) = chars[first];
+    let (b_start, b) = chars[second];
+    let end = b_start + b.len_utf8();
+    Some((start, end, format!("{}{}", b, a)))
+}
+
+/// Swap the word at (or before) byte `col` of `line` with the word after it;
+/// at the last word, swap it with the one before. Returns the byte range
+/// replaced and its new text.
+pub fn transpose_words(line: &str, col: usize) -> Option<LineSwap> {
+    let mut words: Vec<(usize, usize)> = Vec::new();
+    let mut start = None;
+    for (i, c) in line
+        .char_indices()
+        .chain(std::iter::once((line.len(), ' ')))
+    {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                words.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if words.len() < 2 {
+        return None;
+    }
+    let first = words
+        .iter()
+        .rposition(|&(s, _)| s < col)
+        .unwrap_or(0)
+        .min(words.len() - 2);
+    let (a, b) = (words[first], words[first + 1]);
+    let text = format!("{}{}{}", &line[b.0..b.1], &line[a.1..b.0], &line[a.0..a.1]);
+    Some((a.0, b.1, text))
+}
+
+// ---------------------------------------------------------------------------
+// Number increment and decrement
+// ---------------------------------------------------------------------------
+
+/// Add `delta` to the number under or after byte `col` of `line`: decimal
+/// with an optional `-` sign, or `0x` hex. Leading zeros keep the width and
+/// hex keeps its letter case (and wraps around like a u64). Returns the byte
+/// range replaced and its new text.
+pub fn add_to_number(line: &str, col: usize, delta: i64) -> Option<LineSwap> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes[i] == b'0'
+            && matches!(bytes.get(i + 1), Some(b'x' | b'X'))
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_hexdigit);
+        if hex {
+            let digits = i + 2;
+            let end = digit_run(bytes, digits, u8::is_ascii_hexdigit);
+            if end > col {
+                return Some((digits, end, add_hex(&line[digits..end], delta)?));
+            }
+            i = end;
+        } else if bytes[i].is_ascii_digit() {
+            let end = digit_run(bytes, i, u8::is_ascii_digit);
+            // A `-` right before the digits is a sign unless it follows a word
+            let signed = i > 0
+                && bytes[i - 1] == b'-'
+                && !(i > 1 && (bytes[i - 2].is_ascii_alphanumeric() || bytes[i - 2] == b'_'));
+            let start = if signed { i - 1 } else { i };
+            if end > col {
+                return Some((start, end, add_decimal(&line[start..end], delta)?));
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+fn digit_run(bytes: &[u8], start: usize, is_digit: fn(&u8) -> bool) -> usize {
+    start + bytes[start..].iter().take_while(|b| is_digit(b)).count()
+}
+
+fn add_decimal(text: &str, delta: i64) -> Option<String> {
+    let value: i128 = text.parse().ok()?;
+    let result = value.checked_add(delta as i128)?;
+    let digits = text.trim_start_matches('-');
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        digits.len()
+    } else {
+        0
+    };
+    let sign = if result < 0 { "-" } else { "" };
+    Some(format!("{}{:0width$}", sign, result.unsigned_abs()))
+}
+
+fn add_hex(digits: &str, delta: i64) -> Option<String> {
+    let value = u64::from_str_radix(digits, 16).ok()?;
+    let result = value.wrapping_add(delta as u64);
+    let width = digits.len();
+    if digits.bytes().any(|b| b.is_ascii_uppercase()) {
+        Some(format!("{:0width$X}", result))
+    } else {
+        Some(format!("{:0width$x}", result))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Sorting and de-duplicating lines
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SortOptions {
+    pub descending: bool,
+    /// Compare by the number each line starts with; lines without one come
+    /// first, in text order.
+    pub numeric: bool,
+    pub ignore_case: bool,
+}
+
+/// Sort `lines` in place. The sort is stable, so equal lines keep their order.
+pub fn sort_lines(lines: &mut [String], options: SortOptions) {
+    let text_order = |a: &String, b: &String| {
+        if options.ignore_case {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        } else {
+            a.cmp(b)
+        }
+    };
+    lines.sort_by(|a, b| {
+        let order = if options.numeric {
+            match (leading_number(a), leading_number(b)) {
+                (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+                (None, None) => text_order(a, b),
+            }
+        } else {
+            text_order(a, b)
+        };
+        if options.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+}
+
+/// The number at the start of `line` (after leading whitespace), e.g. 42,
+/// -1.5 or 3e2.
+fn leading_number(line: &str) -> Option<f64> {
+    let text = line.trim_start();
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        let sign = (c == '-' || c == '+') && (i == 0 || text[..i].ends_with(['e', 'E']));
+        if c.is_ascii_digit() || c == '.' || sign || ((c == 'e' || c == 'E') && i > 0) {
+            end = i + 1;
+        } else {
+            break;
+        }
+    }
+    // Back off until it parses ("1." or "2e" at the end of a word)
+    (1..=end)
+        .rev()
+        .find_map(|len| text[..len].parse::<f64>().ok())
+}
+
+/// Drop repeated lines: only runs of equal neighbours, or every repeat
+/// after a line's first appearance. Returns how many were removed.
+pub fn unique_lines(lines: &mut Vec<String>, adjacent_only: bool) -> usize {
+    let before = lines.len();
+    if adjacent_only {
+        lines.dedup();
+    } else {
+        let mut seen = HashSet::new();
+        lines.retain(|line| seen.insert(line.clone()));
+    }
+    before - lines.len()
+}
+
+// ---------------------------------------------------------------------------
+// Whitespace cleanup
+// ---------------------------------------------------------------------------
+
+/// The byte range of spaces and tabs at the end of `line`, before any `\r`.
+pub fn trailing_whitespace(line: &str) -> Option<(usize, usize)> {
+    let end = line.strip_suffix('\r').unwrap_or(line).len();
+    let start = line[..end].trim_end_matches([' ', '\t']).len();
+    (start < end).then_some((start, end))
+}
+
+/// How many lines at the end of `lines` to drop so the text ends with a
+/// single newline: the blank lines after the last line with content, less
+/// the empty line a final newline leaves.
+pub fn excess_final_lines<S: AsRef<str>>(lines: &[S]) -> usize {
+    let blank = lines
+        .iter()
+        .rev()
+        .take_while(|l| l.as_ref().trim().is_empty())
+        .count();
+    if blank == lines.len() {
+        return 0;
+    }
+    blank.saturating_sub(1)
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case() {
+        assert_eq!(Case::Upper.apply("straße"), "STRASSE");
+        assert_eq!(Case::Lower.apply("MiXeD"), "mixed");
+        assert_eq!(
+            Case::Title.apply("the QUICK brown-fox don't"),
+            "The Quick Brown-Fox Don't"
+        );
+        assert_eq!(Case::Swap.apply("Hello World"), "hELLO wORLD");
+    }
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        let mut lines = strings(&["b", "C", "a"]);
+        sort_lines(&mut lines, SortOptions::default());
+        assert_eq!(lines, ["C", "a", "b"]);
+        let ignore_case = SortOptions {
+            ignore_case: true,
+            ..SortOptions::default()
+        };
+        sort_lines(&mut lines, ignore_case);
+        assert_eq!(lines, ["a", "b", "C"]);
+        let descending = SortOptions {
+            descending: true,
+            ..ignore_case
+        };
+        sort_lines(&mut lines, descending);
+        assert_eq!(lines, ["C", "b", "a"]);
+    }
+
+    #[test]
+    fn test_sort_numeric() {
+        let mut lines = strings(&["10 ten", "  9 nine", "x", "-1.5", "1e2", "2."]);
+        let numeric = SortOptions {
+            numeric: true,
+            ..SortOptions::default()
+        };
+        sort_lines(&mut lines, numeric);
+        assert_eq!(lines, ["x", "-1.5", "2.", "  9 nine", "10 ten", "1e2"]);
+    }
+
+    #[test]
+    fn test_unique_lines() {
+        let mut lines = strings(&["a", "a", "b", "a"]);
+        assert_eq!(unique_lines(&mut lines, true), 1);
+        assert_eq!(lines, ["a", "b", "a"]);
+        assert_eq!(unique_lines(&mut lines, false), 1);
+        assert_eq!(lines, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_add_to_number() {
+        let add = |line: &str, col, delta| {
+            add_to_number(line, col, delta).map(|(s, e, t)| {
+                let mut out = line.to_string();
+                out.replace_range(s..e, &t);
+                out
+            })
+        };
+        assert_eq!(add("port = 8080", 0, 1).as_deref(), Some("port = 8081"));
+        assert_eq!(add("x = 5", 4, -10).as_deref(), Some("x = -5"));
+        assert_eq!(add("x = -5", 4, 7).as_deref(), Some("x = 2"));
+        // A dash after a word is not a sign
+        assert_eq!(add("item-9", 0, 1).as_deref(), Some("item-10"));
+        // Leading zeros keep the width
+        assert_eq!(add("v007", 0, 1).as_deref(), Some("v008"));
+        assert_eq!(add("v010", 0, -3).as_deref(), Some("v007"));
+        // Hex keeps its case and width, and wraps
+        assert_eq!(add("0x0f", 0, 1).as_deref(), Some("0x10"));
+        assert_eq!(add("0XFF", 0, 1).as_deref(), Some("0X100"));
+        assert_eq!(add("0x00", 0, -1).as_deref(), Some("0xffffffffffffffff"));
+        // The number under the cursor, not the first on the line
+        assert_eq!(add("1 22 3", 3, 1).as_deref(), Some("1 23 3"));
+        assert_eq!(add("1 2", 3, 1), None);
+    }
+
+    #[test]
+    fn test_trailing_whitespace() {
+        assert_eq!(trailing_whitespace("let x = 1; \t"), Some((10, 12)));
+        assert_eq!(trailing_whitespace("crlf  \r"), Some((4, 6)));
+        assert_eq!(trailing_whitespace("   "), Some((0, 3)));
+        assert_eq!(trailing_whitespace("clean"), None);
+    }
+
+    #[test]
+    fn test_excess_final_lines() {
+        assert_eq!(excess_final_lines(&["a", ""]), 0);
+        assert_eq!(excess_final_lines(&["a"]), 0);
+        assert_eq!(excess_final_lines(&["a", "", "  ", ""]), 2);
+        assert_eq!(excess_final_lines(&["", ""]), 0);
+    }
+
+    #[test]
+    fn test_transpose_chars() {
+        assert_eq!(transpose_chars("abcd", 2), Some((1, 3, "cb".to_string())));
+        // At the end of the line: the last two
+        assert_eq!(transpose_chars("abcd", 4), Some((2, 4, "dc".to_string())));
+        assert_eq!(transpose_chars("aé", 1), Some((0, 3, "éa".to_string())));
+        assert_eq!(transpose_chars("abcd", 0), None);
+        assert_eq!(transpose_chars("a", 1), None);
+    }
+
+    #[test]
+    fn test_transpose_words() {
+        let swap = |line: &str, col| {
+            transpose_words(line, col).map(|(s, e, t)| {
+                let mut out = line.to_string();
+                out.replace_range(s..e, &t);
+                out
+            })
+        };
+        assert_eq!(swap("one two three", 1).as_deref(), Some("two one three"));
+        assert_eq!(swap("one two three", 5).as_deref(), Some("one three two"));
+        // Past the last word: the last two
+        assert_eq!(swap("one, two", 8).as_deref(), Some("two, one"));
+        assert_eq!(swap("alone", 2), None);
+    }
+}
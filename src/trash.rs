@@ -0,0 +1,226 @@
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::script::civil_date;
+
+// ---------------------------------------------------------------------------
+// Trash — deleting files so that they can come back
+// ---------------------------------------------------------------------------
+
+/// The directory beside a file that takes it when the desktop trash
+/// cannot, as when the trash is on another filesystem.
+pub const FALLBACK_DIR: &str = ".zelux-trash";
+
+/// A file moved to the trash, and where it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trashed {
+    pub original: PathBuf,
+    pub trashed: PathBuf,
+    info: Option<PathBuf>, // the `.trashinfo` written with it
+}
+
+/// The desktop trash of the freedesktop.org spec: `$XDG_DATA_HOME/Trash`,
+/// else `~/.local/share/Trash`.
+pub fn trash_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+        .map(|d| d.join("Trash"))
+}
+
+/// Move `path` to the desktop trash at `trash` (see `trash_dir`), with a
+/// `.trashinfo` telling file managers where it came from, or into
+/// `FALLBACK_DIR` beside it when that fails. A symlink goes, not its
+/// target.
+pub fn trash(path: &Path, trash: Option<&Path>) -> Result<Trashed, String> {
+    let err = |e: io::Error| format!("{}: {}", path.display(), e);
+    let original = std::path::absolute(path).map_err(err)?;
+    fs::symlink_metadata(&original).map_err(err)?;
+    if let Some(trash) = trash
+        && let Ok(trashed) = to_desktop_trash(&original, trash)
+    {
+        return Ok(trashed);
+    }
+    let dir = original
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .join(FALLBACK_DIR);
+    fs::create_dir_all(&dir).map_err(err)?;
+    let name = original.file_name().unwrap_or_default().to_string_lossy();
+    let trashed = (1..)
+        .map(|n| dir.join(numbered(&name, n)))
+        .find(|p| fs::symlink_metadata(p).is_err())
+        .unwrap_or_default();
+    fs::rename(&original, &trashed).map_err(err)?;
+    Ok(Trashed {
+        original,
+        trashed,
+        info: None,
+    })
+}
+
+/// Put `original` back where it was, unless something took its place.
+pub fn restore(trashed: &Trashed) -> Result<(), String> {
+    let original = &trashed.original;
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(format!("{} exists", original.display()));
+    }
+    fs::rename(&trashed.trashed, original)
+        .map_err(|e| format!("{}: {}", trashed.trashed.display(), e))?;
+    if let Some(ref info) = trashed.info {
+        let _ = fs::remove_file(info);
+    }
+    Ok(())
+}
+
+/// `name`, then `name.2`, `name.3`...
+fn numbered(name: &str, n: usize) -> String {
+    match n {
+        1 => name.to_string(),
+        n => format!("{}.{}", name, n),
+    }
+}
+
+fn to_desktop_trash(original: &Path, trash: &Path) -> io::Result<Trashed> {
+    let (files, info_dir) = (trash.join("files"), trash.join("info"));
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info_dir)?;
+    let name = original.file_name().unwrap_or_default().to_string_lossy();
+    for n in 1.. {
+        let name = numbered(&name, n);
+        let trashed = files.join(&name);
+        // The spec claims a name by creating its info file first
+        let info = info_dir.join(format!("{}.trashinfo", name));
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let result = if fs::symlink_metadata(&trashed).is_ok() {
+            Err(ErrorKind::AlreadyExists.into())
+        } else {
+            file.write_all(trash_info(original, SystemTime::now()).as_bytes())
+                .and_then(|()| fs::rename(original, &trashed))
+        };
+        match result {
+            Ok(()) => {
+                return Ok(Trashed {
+                    original: original.to_path_buf(),
+                    trashed,
+                    info: Some(info),
+                });
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&info);
+                if e.kind() != ErrorKind::AlreadyExists {
+                    return Err(e);
+                }
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn trash_info(original: &Path, deleted: SystemTime) -> String {
+    format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(original),
+        date(deleted)
+    )
+}
+
+/// `path` with every byte but letters, digits, `/` and `-._~` escaped as
+/// `%XX`, as URLs and the trash spec want.
+fn percent_encode(path: &Path) -> String {
+    let mut out = String::new();
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in UTC; file managers take the zoneless date as
+/// local time, which is off by the zone at worst.
+fn date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rest) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_date(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_trash_info() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(date(time), "2023-11-14T22:13:20");
+        assert_eq!(date(UNIX_EPOCH), "1970-01-01T00:00:00");
+        assert_eq!(
+            trash_info(Path::new("/home/a b/é.txt"), UNIX_EPOCH),
+            "[Trash Info]\nPath=/home/a%20b/%C3%A9.txt\nDeletionDate=1970-01-01T00:00:00\n"
+        );
+    }
+
+    #[test]
+    fn test_trash_and_restore() {
+        let dir = std::env::temp_dir().join(format!("zelux_test_trash_{}", std::process::id()));
+        let trash_dir = dir.join("Trash");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+
+        // Two files of the same name get numbered
+        fs::write(&path, "one").unwrap();
+        let first = trash(&path, Some(&trash_dir)).unwrap();
+        fs::write(&path, "two").unwrap();
+        let second = trash(&path, Some(&trash_dir)).unwrap();
+        assert!(!path.exists());
+        assert_eq!(first.trashed, trash_dir.join("files/a.txt"));
+        assert_eq!(second.trashed, trash_dir.join("files/a.txt.2"));
+        let info = fs::read_to_string(trash_dir.join("info/a.txt.2.trashinfo")).unwrap();
+        assert!(
+            info.contains(&format!("Path={}\n", path.display())),
+            "{}",
+            info
+        );
+
+        restore(&second).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two");
+        assert!(!trash_dir.join("info/a.txt.2.trashinfo").exists());
+        assert!(restore(&first).is_err());
+
+        // Without a desktop trash, beside the file
+        let fallback = trash(&path, None).unwrap();
+        assert_eq!(fallback.trashed, dir.join(".zelux-trash/a.txt"));
+        restore(&fallback).unwrap();
+        assert!(path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
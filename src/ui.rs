@@ -0,0 +1,586 @@
+use crate::input::{Key, KeyEvent};
+use crate::render::{Color, Screen};
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Overlay panels — popups, lists and dialogs drawn over the Screen
+// ---------------------------------------------------------------------------
+
+/// Background of popups and list panels.
+pub const PANEL_BG: Color = Color::Color256(236);
+/// Text panels show at most this many rows and scroll the rest.
+const TEXT_MAX_ROWS: usize = 12;
+/// Widest a text panel or dialog grows.
+const MAX_WIDTH: usize = 80;
+
+/// What a panel did with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyResult {
+    /// Handled inside the panel (selection moved, text scrolled).
+    Consumed,
+    /// Enter on a list.
+    Accept,
+    /// A dialog answer, by its key.
+    Choice(char),
+    /// Escape: close the panel.
+    Cancel,
+    /// Not a panel key; the caller decides.
+    Ignored,
+}
+
+// ---------------------------------------------------------------------------
+// Geometry and frames
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(top: usize, left: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            top,
+            left,
+            width,
+            height,
+        }
+    }
+
+    /// A `width` x `height` box next to the anchor cell inside `area`: on
+    /// the rows below it when they fit, otherwise on whichever side has more
+    /// room, shrunk to fit. Shifted left to stay inside `area`.
+    pub fn near(row: usize, col: usize, width: usize, height: usize, area: Rect) -> Rect {
+        let below = area.bottom().saturating_sub(row + 1);
+        let above = row.saturating_sub(area.top);
+        let (top, height) = if height <= below || below >= above {
+            (row + 1, height.min(below))
+        } else {
+            let height = height.min(above);
+            (row - height, height)
+        };
+        let width = width.min(area.width);
+        let left = col.max(area.left).min(area.left + area.width - width);
+        Rect::new(top, left, width, height)
+    }
+
+    /// A `width` x `height` box in the middle of `area`.
+    pub fn centered(width: usize, height: usize, area: Rect) -> Rect {
+        let width = width.min(area.width);
+        let height = height.min(area.height);
+        Rect::new(
+            area.top + (area.height - height) / 2,
+            area.left + (area.width - width) / 2,
+            width,
+            height,
+        )
+    }
+
+    /// The area inside a one-cell border.
+    pub fn inner(self) -> Rect {
+        Rect::new(
+            self.top + 1,
+            self.left + 1,
+            self.width.saturating_sub(2),
+            self.height.saturating_sub(2),
+        )
+    }
+
+    pub fn bottom(self) -> usize {
+        self.top + self.height
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub border: Option<Color>,
+}
+
+impl Style {
+    /// Borderless panel on the popup background.
+    pub fn plain(fg: Color, bg: Color) -> Style {
+        Style {
+            fg,
+            bg,
+            border: None,
+        }
+    }
+
+    /// Boxed panel on the popup background.
+    pub fn boxed() -> Style {
+        Style {
+            fg: Color::Default,
+            bg: PANEL_BG,
+            border: Some(Color::Color256(244)),
+        }
+    }
+}
+
+/// Clear `rect` to the style's background and draw its border, with
+/// `title` set into the top edge. Returns the area left for content.
+pub fn draw_frame(screen: &mut Screen, rect: Rect, style: Style, title: Option<&str>) -> Rect {
+    for row in rect.top..rect.bottom() {
+        for col in rect.left..rect.left + rect.width {
+            screen.put_char(row, col, ' ', style.fg, style.bg, false);
+        }
+    }
+    let border = match style.border {
+        Some(color) if rect.width >= 2 && rect.height >= 2 => color,
+        _ => return rect,
+    };
+    let right = rect.left + rect.width - 1;
+    let bottom = rect.bottom() - 1;
+    let mut put = |row, col, ch| screen.put_char(row, col, ch, border, style.bg, false);
+    for col in rect.left + 1..right {
+        put(rect.top, col, '─');
+        put(bottom, col, '─');
+    }
+    for row in rect.top + 1..bottom {
+        put(row, rect.left, '│');
+        put(row, right, '│');
+    }
+    put(rect.top, rect.left, '┌');
+    put(rect.top, right, '┐');
+    put(bottom, rect.left, '└');
+    put(bottom, right, '┘');
+    if let Some(title) = title {
+        let title = format!(" {} ", title);
+        let title = unicode::truncate_display(&title, rect.width.saturating_sub(4));
+        screen.put_str(rect.top, rect.left + 2, title, style.fg, style.bg, true);
+    }
+    rect.inner()
+}
+
+// ---------------------------------------------------------------------------
+// Lists
+// ---------------------------------------------------------------------------
+
+/// Selection and scroll position of a list panel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ListState {
+    pub selected: usize,
+    pub scroll: usize,
+}
+
+impl ListState {
+    /// Move down one row, wrapping to the top.
+    pub fn select_next(&mut self, len: usize, visible: usize) {
+        if len > 0 {
+            self.selected = (self.selected + 1) % len;
+            self.follow(visible);
+        }
+    }
+
+    /// Move up one row, wrapping to the bottom.
+    pub fn select_prev(&mut self, len: usize, visible: usize) {
+        if len > 0 {
+            self.selected = self.selected.checked_sub(1).unwrap_or(len - 1);
+            self.follow(visible);
+        }
+    }
+
+    /// Scroll so the selected row is one of the `visible` rows.
+    pub fn follow(&mut self, visible: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible.max(1);
+        }
+    }
+
+    /// Arrows, Ctrl+N/P and paging move the selection; Enter accepts and
+    /// Escape cancels.
+    pub fn handle_key(&mut self, ke: &KeyEvent, len: usize, visible: usize) -> KeyResult {
+        let page = visible.max(1);
+        match ke.key {
+            Key::Up => self.select_prev(len, visible),
+            Key::Down => self.select_next(len, visible),
+            Key::Char('p') if ke.ctrl => self.select_prev(len, visible),
+            Key::Char('n') if ke.ctrl => self.select_next(len, visible),
+            Key::PageUp if len > 0 => {
+                self.selected = self.selected.saturating_sub(page);
+                self.follow(visible);
+            }
+            Key::PageDown if len > 0 => {
+                self.selected = (self.selected + page).min(len - 1);
+                self.follow(visible);
+            }
+            Key::Enter => return KeyResult::Accept,
+            Key::Escape => return KeyResult::Cancel,
+            _ => return KeyResult::Ignored,
+        }
+        KeyResult::Consumed
+    }
+}
+
+/// Draw the rows of a list that fit in `area`, from the scroll position,
+/// with the selected row highlighted. Rows are padded to the full width.
+pub fn draw_list(
+    screen: &mut Screen,
+    area: Rect,
+    rows: &[String],
+    state: &ListState,
+    style: Style,
+) {
+    let visible = rows.iter().enumerate().skip(state.scroll);
+    for (row, (i, text)) in (area.top..area.bottom()).zip(visible) {
+        let (fg, bg) = if i == state.selected {
+            (Color::Ansi(0), Color::Ansi(6))
+        } else {
+            (style.fg, style.bg)
+        };
+        let text = unicode::truncate_display(text, area.width);
+        let padded = format!("{:<w$}", text, w = area.width);
+        screen.put_str(row, area.left, &padded, fg, bg, false);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Text panels
+// ---------------------------------------------------------------------------
+
+/// A boxed, word-wrapped block of text (hover docs, diagnostics) that
+/// scrolls when it has more rows than fit.
+pub struct TextPanel {
+    lines: Vec<(String, Color)>,
+    scroll: usize,
+    overflows: bool, // set while rendering: more rows than fit
+}
+
+impl TextPanel {
+    pub fn new(lines: Vec<(String, Color)>) -> Self {
+        TextPanel {
+            lines,
+            scroll: 0,
+            overflows: false,
+        }
+    }
+
+    /// Escape cancels; arrows and paging scroll an overflowing panel.
+    pub fn handle_key(&mut self, ke: &KeyEvent) -> KeyResult {
+        let page = TEXT_MAX_ROWS / 2;
+        match ke.key {
+            Key::Escape => return KeyResult::Cancel,
+            Key::Down if self.overflows => self.scroll += 1,
+            Key::Up if self.overflows => self.scroll = self.scroll.saturating_sub(1),
+            Key::PageDown if self.overflows => self.scroll += page,
+            Key::PageUp if self.overflows => self.scroll = self.scroll.saturating_sub(page),
+            _ => return KeyResult::Ignored,
+        }
+        KeyResult::Consumed
+    }
+
+    /// Draw the panel next to the anchor cell (the cursor) inside `area`.
+    pub fn render(&mut self, screen: &mut Screen, row: usize, col: usize, area: Rect) {
+        let max_width = area.width.saturating_sub(2).clamp(5, MAX_WIDTH);
+        let mut rows: Vec<(String, Color)> = Vec::new();
+        for (text, fg) in &self.lines {
+            for line in wrap_line(text, max_width - 4) {
+                rows.push((line, *fg));
+            }
+        }
+        let width = rows
+            .iter()
+            .map(|(t, _)| t.chars().count() + 4)
+            .max()
+            .unwrap_or(0)
+            .min(max_width);
+        let height = rows.len().min(TEXT_MAX_ROWS) + 2;
+        let rect = Rect::near(row, col, width, height, area);
+        let inner = draw_frame(screen, rect, Style::boxed(), None);
+        let shown = inner.height;
+        self.overflows = rows.len() > shown;
+        self.scroll = self.scroll.min(rows.len().saturating_sub(shown));
+        let visible = rows.iter().skip(self.scroll).take(shown);
+        for (i, (text, fg)) in visible.enumerate() {
+            screen.put_str(inner.top + i, inner.left + 1, text, *fg, PANEL_BG, false);
+        }
+        // Scroll hint on the right edge
+        if self.overflows && shown > 0 {
+            let more_below = self.scroll + shown < rows.len();
+            let (hint, hint_row) = if more_below {
+                ('↓', inner.bottom() - 1)
+            } else {
+                ('↑', inner.top)
+            };
+            screen.put_char(
+                hint_row,
+                rect.left + rect.width - 1,
+                hint,
+                Color::Ansi(3),
+                PANEL_BG,
+                false,
+            );
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Menus
+// ---------------------------------------------------------------------------
+
+/// A boxed list of actions opened at a screen cell, such as the right-click
+/// menu. It remembers where it was drawn so a click can pick an item.
+pub struct Menu {
+    items: Vec<(String, String)>, // label, key hint
+    pub list: ListState,
+    row: usize,
+    col: usize,
+    drawn: Option<Rect>,
+}
+
+impl Menu {
+    pub fn new(row: usize, col: usize, items: Vec<(String, String)>) -> Self {
+        Menu {
+            items,
+            list: ListState::default(),
+            row,
+            col,
+            drawn: None,
+        }
+    }
+
+    /// List keys move the selection, Enter accepts and Escape cancels.
+    pub fn handle_key(&mut self, ke: &KeyEvent) -> KeyResult {
+        let len = self.items.len();
+        self.list.handle_key(ke, len, len)
+    }
+
+    /// The item drawn at screen cell (`row`, `col`), if any.
+    pub fn item_at(&self, row: usize, col: usize) -> Option<usize> {
+        let inner = self.drawn?.inner();
+        let inside = (inner.top..inner.bottom()).contains(&row)
+            && (inner.left..inner.left + inner.width).contains(&col);
+        let item = row.checked_sub(inner.top)? + self.list.scroll;
+        (inside && item < self.items.len()).then_some(item)
+    }
+
+    /// Draw the menu with its top-left corner at the opening cell, moved
+    /// to stay inside `area`.
+    pub fn render(&mut self, screen: &mut Screen, area: Rect) {
+        let label_width = self.items.iter().map(|(l, _)| l.chars().count()).max();
+        let hint_width = self.items.iter().map(|(_, h)| h.chars().count()).max();
+        let label_width = label_width.unwrap_or(0);
+        let hint_width = hint_width.unwrap_or(0);
+        let rows: Vec<String> = self
+            .items
+            .iter()
+            .map(|(label, hint)| {
+                format!(
+                    " {:<lw$}  {:>hw$} ",
+                    label,
+                    hint,
+                    lw = label_width,
+                    hw = hint_width
+                )
+            })
+            .collect();
+        let width = (label_width + hint_width + 6).min(area.width);
+        let height = (rows.len() + 2).min(area.height);
+        let rect = Rect::near(self.row.saturating_sub(1), self.col, width, height, area);
+        let inner = draw_frame(screen, rect, Style::boxed(), None);
+        self.list.follow(inner.height);
+        draw_list(screen, inner, &rows, &self.list, Style::boxed());
+        self.drawn = Some(rect);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Dialogs
+// ---------------------------------------------------------------------------
+
+/// A modal question answered with one key, e.g. `[y] Yes  [n] No`.
+pub struct Dialog {
+    title: String,
+    message: String,
+    choices: Vec<(char, &'static str)>,
+}
+
+impl Dialog {
+    pub fn new(title: &str, message: &str, choices: &[(char, &'static str)]) -> Self {
+        Dialog {
+            title: title.to_string(),
+            message: message.to_string(),
+            choices: choices.to_vec(),
+        }
+    }
+
+    /// A choice's key picks it and Escape cancels. Every other key is
+    /// swallowed while the dialog is open.
+    pub fn handle_key(&self, ke: &KeyEvent) -> KeyResult {
+        match ke.key {
+            Key::Escape => KeyResult::Cancel,
+            Key::Char(c) if !ke.ctrl && !ke.alt => {
+                let c = c.to_ascii_lowercase();
+                if self.choices.iter().any(|(key, _)| *key == c) {
+                    KeyResult::Choice(c)
+                } else {
+                    KeyResult::Consumed
+                }
+            }
+            _ => KeyResult::Consumed,
+        }
+    }
+
+    /// Draw the dialog boxed in the middle of `area`.
+    pub fn render(&self, screen: &mut Screen, area: Rect) {
+        let choices = self
+            .choices
+            .iter()
+            .map(|(key, label)| format!("[{}] {}", key, label))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let max_text = area.width.saturating_sub(6).clamp(10, MAX_WIDTH);
+        let message = wrap_line(&self.message, max_text);
+        let text_width = message
+            .iter()
+            .map(|l| l.chars().count())
+            .chain([choices.chars().count(), self.title.chars().count() + 2])
+            .max()
+            .unwrap_or(0);
+        let height = message.len() + 4; // border, blank line and choices
+        let rect = Rect::centered(text_width + 4, height, area);
+        let inner = draw_frame(screen, rect, Style::boxed(), Some(&self.title));
+        for (i, line) in message.iter().enumerate() {
+            screen.put_str(
+                inner.top + i,
+                inner.left + 1,
+                line,
+                Color::Default,
+                PANEL_BG,
+                false,
+            );
+        }
+        screen.put_str(
+            inner.bottom().saturating_sub(1),
+            inner.left + 1,
+            &choices,
+            Color::Ansi(3),
+            PANEL_BG,
+            true,
+        );
+    }
+}
+
+/// Split `text` into rows of at most `width` characters, breaking after the
+/// last space that fits and hard-breaking words that are too long.
+pub fn wrap_line(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let chars: Vec<char> = text.chars().collect();
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while chars.len() - start > width {
+        let window = &chars[start..=start + width];
+        match window.iter().rposition(|&c| c == ' ').filter(|&i| i > 0) {
+            Some(i) => {
+                rows.push(chars[start..start + i].iter().collect());
+                start += i + 1;
+            }
+            None => {
+                rows.push(chars[start..start + width].iter().collect());
+                start += width;
+            }
+        }
+    }
+    rows.push(chars[start..].iter().collect());
+    rows
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: Key) -> KeyEvent {
+        KeyEvent {
+            key,
+            ctrl: false,
+            alt: false,
+            shift: false,
+        }
+    }
+
+    #[test]
+    fn test_rect_near_prefers_below() {
+        let area = Rect::new(0, 0, 80, 20);
+        // Fits below the anchor
+        assert_eq!(Rect::near(2, 10, 20, 5, area), Rect::new(3, 10, 20, 5));
+        // Not below: above has more room
+        assert_eq!(Rect::near(17, 10, 20, 5, area), Rect::new(12, 10, 20, 5));
+        // Neither fits: the taller side, shrunk
+        assert_eq!(Rect::near(12, 10, 20, 15, area), Rect::new(0, 10, 20, 12));
+        // Pushed left at the right edge
+        assert_eq!(Rect::near(2, 75, 20, 5, area).left, 60);
+    }
+
+    #[test]
+    fn test_menu_item_at() {
+        let items = ["Cut", "Copy", "Paste"].map(|l| (l.to_string(), String::new()));
+        let mut menu = Menu::new(5, 10, items.to_vec());
+        let mut screen = Screen::new(80, 20);
+        menu.render(&mut screen, Rect::new(0, 0, 80, 20));
+        // Frame at rows 5..10, items on rows 6..9
+        assert_eq!(menu.item_at(6, 12), Some(0));
+        assert_eq!(menu.item_at(8, 12), Some(2));
+        assert_eq!(menu.item_at(5, 12), None);
+        assert_eq!(menu.item_at(7, 40), None);
+        assert_eq!(menu.handle_key(&key(Key::Down)), KeyResult::Consumed);
+        assert_eq!(menu.list.selected, 1);
+    }
+
+    #[test]
+    fn test_rect_centered_and_inner() {
+        let area = Rect::new(0, 0, 80, 20);
+        let rect = Rect::centered(20, 6, area);
+        assert_eq!(rect, Rect::new(7, 30, 20, 6));
+        assert_eq!(rect.inner(), Rect::new(8, 31, 18, 4));
+    }
+
+    #[test]
+    fn test_list_keys_wrap_and_scroll() {
+        let mut list = ListState::default();
+        assert_eq!(list.handle_key(&key(Key::Up), 12, 10), KeyResult::Consumed);
+        assert_eq!((list.selected, list.scroll), (11, 2));
+        list.select_next(12, 10);
+        assert_eq!((list.selected, list.scroll), (0, 0));
+        list.handle_key(&key(Key::PageDown), 12, 10);
+        assert_eq!((list.selected, list.scroll), (10, 1));
+        assert_eq!(list.handle_key(&key(Key::Enter), 12, 10), KeyResult::Accept);
+        assert_eq!(
+            list.handle_key(&key(Key::Escape), 12, 10),
+            KeyResult::Cancel
+        );
+        assert_eq!(
+            list.handle_key(&key(Key::Char('x')), 12, 10),
+            KeyResult::Ignored
+        );
+    }
+
+    #[test]
+    fn test_dialog_keys() {
+        let dialog = Dialog::new("Quit", "Discard changes?", &[('y', "Yes"), ('n', "No")]);
+        assert_eq!(
+            dialog.handle_key(&key(Key::Char('Y'))),
+            KeyResult::Choice('y')
+        );
+        assert_eq!(dialog.handle_key(&key(Key::Char('q'))), KeyResult::Consumed);
+        assert_eq!(dialog.handle_key(&key(Key::Escape)), KeyResult::Cancel);
+    }
+
+    #[test]
+    fn test_wrap_line() {
+        assert_eq!(wrap_line("short", 10), vec!["short"]);
+        assert_eq!(wrap_line("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrap_line("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(wrap_line("  indented text", 11), vec!["  indented", "text"]);
+        assert_eq!(wrap_line("", 5), vec![""]);
+    }
+}
@@ -83,6 +83,8 @@ pub struct UndoStack {
     context: GroupContext,
     last_edit: Option<Instant>,
     saved_at: Option<usize>,
+    // Inside begin_compound/end_compound: never split the pending group
+    compound: bool,
 }
 
 impl UndoStack {
@@ -95,19 +97,21 @@ impl UndoStack {
             context: GroupContext::Other,
             last_edit: None,
             saved_at: Some(0),
+            compound: false,
         }
     }
 
     pub fn record(&mut self, op: Operation, cursor_before: CursorState, ctx: GroupContext) {
         // Start a new group if: context changed, timeout elapsed, or pending is empty
-        let should_split = self.pending.is_empty()
-            || ctx != self.context
-            || ctx == GroupContext::Paste
-            || ctx == GroupContext::Cut
-            || ctx == GroupContext::Other
-            || self
-                .last_edit
-                .is_none_or(|t| t.elapsed().as_millis() >= GROUP_TIMEOUT_MS);
+        let should_split = !self.compound
+            && (self.pending.is_empty()
+                || ctx != self.context
+                || ctx == GroupContext::Paste
+                || ctx == GroupContext::Cut
+                || ctx == GroupContext::Other
+                || self
+                    .last_edit
+                    .is_none_or(|t| t.elapsed().as_millis() >= GROUP_TIMEOUT_MS));
 
         if should_split && !self.pending.is_empty() {
             // Finish current pending group with cursor_before of the new op as cursor_after
@@ -132,6 +136,18 @@ impl UndoStack {
         self.redo.clear();
     }
 
+    /// Start collecting every following `record` into one undo group, until
+    /// `end_compound`. Used by multi-step edits that should undo at once.
+    pub fn begin_compound(&mut self, cursor: CursorState) {
+        self.finish_group(cursor);
+        self.compound = true;
+    }
+
+    pub fn end_compound(&mut self, cursor_after: CursorState) {
+        self.compound = false;
+        self.finish_group(cursor_after);
+    }
+
     pub fn finish_group(&mut self, cursor_after: CursorState) {
         if self.pending.is_empty() {
             return;
@@ -199,6 +215,7 @@ impl UndoStack {
         self.pending_cursor = None;
         self.last_edit = None;
         self.saved_at = Some(0);
+        self.compound = false;
     }
 }
 
@@ -458,4 +475,37 @@ mod tests {
         stack.undo(&mut buf, cursor(0, 11));
         assert!(stack.is_at_saved());
     }
+
+    #[test]
+    fn test_compound_group_undoes_at_once() {
+        let mut buf = Buffer::new();
+        buf.insert(0, "old");
+        let mut stack = UndoStack::new();
+
+        stack.begin_compound(cursor(0, 0));
+        buf.delete(0, 3);
+        stack.record(
+            Operation::Delete {
+                pos: 0,
+                text: "old".to_string(),
+            },
+            cursor(0, 0),
+            GroupContext::Other,
+        );
+        buf.insert(0, "new");
+        stack.record(
+            Operation::Insert {
+                pos: 0,
+                text: "new".to_string(),
+            },
+            cursor(0, 0),
+            GroupContext::Other,
+        );
+        stack.end_compound(cursor(0, 3));
+        assert_eq!(buf.text(), "new");
+
+        assert!(stack.undo(&mut buf, cursor(0, 3)).is_some());
+        assert_eq!(buf.text(), "old");
+        assert!(stack.undo(&mut buf, cursor(0, 0)).is_none());
+    }
 }
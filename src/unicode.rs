@@ -0,0 +1,666 @@
+// ---------------------------------------------------------------------------
+// Unicode — character names, code point input and display width
+// ---------------------------------------------------------------------------
+
+/// Names of the characters people most often insert by hand. Not the whole
+/// Unicode database: the rest are reached by code point.
+#[rustfmt::skip]
+pub const NAMES: &[(char, &str)] = &[
+    // Punctuation and typography
+    ('\u{00A0}', "no-break space"),
+    ('\u{00A7}', "section sign"),
+    ('\u{00B6}', "pilcrow sign"),
+    ('\u{00B7}', "middle dot"),
+    ('\u{00AB}', "left-pointing double angle quotation mark"),
+    ('\u{00BB}', "right-pointing double angle quotation mark"),
+    ('\u{00BF}', "inverted question mark"),
+    ('\u{00A1}', "inverted exclamation mark"),
+    ('\u{2010}', "hyphen"),
+    ('\u{2011}', "non-breaking hyphen"),
+    ('\u{2013}', "en dash"),
+    ('\u{2014}', "em dash"),
+    ('\u{2018}', "left single quotation mark"),
+    ('\u{2019}', "right single quotation mark"),
+    ('\u{201C}', "left double quotation mark"),
+    ('\u{201D}', "right double quotation mark"),
+    ('\u{201E}', "double low-9 quotation mark"),
+    ('\u{2020}', "dagger"),
+    ('\u{2021}', "double dagger"),
+    ('\u{2022}', "bullet"),
+    ('\u{2026}', "horizontal ellipsis"),
+    ('\u{2030}', "per mille sign"),
+    ('\u{2032}', "prime"),
+    ('\u{2033}', "double prime"),
+    ('\u{2039}', "single left-pointing angle quotation mark"),
+    ('\u{203A}', "single right-pointing angle quotation mark"),
+    ('\u{203D}', "interrobang"),
+    ('\u{2042}', "asterism"),
+    ('\u{200B}', "zero width space"),
+    ('\u{200D}', "zero width joiner"),
+    ('\u{FEFF}', "zero width no-break space (byte order mark)"),
+    ('\u{00AD}', "soft hyphen"),
+    // Currency
+    ('\u{00A2}', "cent sign"),
+    ('\u{00A3}', "pound sign"),
+    ('\u{00A5}', "yen sign"),
+    ('\u{20AC}', "euro sign"),
+    ('\u{20B9}', "indian rupee sign"),
+    ('\u{20BD}', "ruble sign"),
+    ('\u{20A9}', "won sign"),
+    ('\u{20BF}', "bitcoin sign"),
+    ('\u{00A4}', "currency sign"),
+    // Legal and marks
+    ('\u{00A9}', "copyright sign"),
+    ('\u{00AE}', "registered sign"),
+    ('\u{2122}', "trade mark sign"),
+    ('\u{2116}', "numero sign"),
+    ('\u{00B0}', "degree sign"),
+    ('\u{2103}', "degree celsius"),
+    ('\u{2109}', "degree fahrenheit"),
+    ('\u{00B5}', "micro sign"),
+    ('\u{2126}', "ohm sign"),
+    // Math
+    ('\u{00B1}', "plus-minus sign"),
+    ('\u{00D7}', "multiplication sign"),
+    ('\u{00F7}', "division sign"),
+    ('\u{2212}', "minus sign"),
+    ('\u{2213}', "minus-or-plus sign"),
+    ('\u{2215}', "division slash"),
+    ('\u{2219}', "bullet operator"),
+    ('\u{221A}', "square root"),
+    ('\u{221B}', "cube root"),
+    ('\u{221E}', "infinity"),
+    ('\u{2248}', "almost equal to"),
+    ('\u{2260}', "not equal to"),
+    ('\u{2261}', "identical to"),
+    ('\u{2264}', "less-than or equal to"),
+    ('\u{2265}', "greater-than or equal to"),
+    ('\u{226A}', "much less-than"),
+    ('\u{226B}', "much greater-than"),
+    ('\u{2200}', "for all"),
+    ('\u{2203}', "there exists"),
+    ('\u{2204}', "there does not exist"),
+    ('\u{2205}', "empty set"),
+    ('\u{2208}', "element of"),
+    ('\u{2209}', "not an element of"),
+    ('\u{220B}', "contains as member"),
+    ('\u{2211}', "n-ary summation"),
+    ('\u{220F}', "n-ary product"),
+    ('\u{2202}', "partial differential"),
+    ('\u{2207}', "nabla"),
+    ('\u{222B}', "integral"),
+    ('\u{2227}', "logical and"),
+    ('\u{2228}', "logical or"),
+    ('\u{00AC}', "not sign"),
+    ('\u{2229}', "intersection"),
+    ('\u{222A}', "union"),
+    ('\u{2282}', "subset of"),
+    ('\u{2283}', "superset of"),
+    ('\u{2286}', "subset of or equal to"),
+    ('\u{2287}', "superset of or equal to"),
+    ('\u{2218}', "ring operator"),
+    ('\u{2234}', "therefore"),
+    ('\u{2235}', "because"),
+    ('\u{221D}', "proportional to"),
+    ('\u{2225}', "parallel to"),
+    ('\u{22A5}', "up tack (perpendicular)"),
+    ('\u{2295}', "circled plus"),
+    ('\u{2297}', "circled times"),
+    ('\u{22C5}', "dot operator"),
+    ('\u{2308}', "left ceiling"),
+    ('\u{2309}', "right ceiling"),
+    ('\u{230A}', "left floor"),
+    ('\u{230B}', "right floor"),
+    ('\u{27E8}', "mathematical left angle bracket"),
+    ('\u{27E9}', "mathematical right angle bracket"),
+    ('\u{2102}', "double-struck capital c"),
+    ('\u{2115}', "double-struck capital n"),
+    ('\u{211A}', "double-struck capital q"),
+    ('\u{211D}', "double-struck capital r"),
+    ('\u{2124}', "double-struck capital z"),
+    ('\u{2135}', "alef symbol"),
+    ('\u{00BD}', "vulgar fraction one half"),
+    ('\u{2153}', "vulgar fraction one third"),
+    ('\u{00BC}', "vulgar fraction one quarter"),
+    ('\u{00BE}', "vulgar fraction three quarters"),
+    ('\u{00B2}', "superscript two"),
+    ('\u{00B3}', "superscript three"),
+    ('\u{00B9}', "superscript one"),
+    ('\u{2070}', "superscript zero"),
+    ('\u{207F}', "superscript latin small letter n"),
+    ('\u{2080}', "subscript zero"),
+    ('\u{2081}', "subscript one"),
+    ('\u{2082}', "subscript two"),
+    // Arrows
+    ('\u{2190}', "leftwards arrow"),
+    ('\u{2191}', "upwards arrow"),
+    ('\u{2192}', "rightwards arrow"),
+    ('\u{2193}', "downwards arrow"),
+    ('\u{2194}', "left right arrow"),
+    ('\u{2195}', "up down arrow"),
+    ('\u{21A9}', "leftwards arrow with hook"),
+    ('\u{21AA}', "rightwards arrow with hook"),
+    ('\u{21B5}', "downwards arrow with corner leftwards (return)"),
+    ('\u{21D0}', "leftwards double arrow"),
+    ('\u{21D2}', "rightwards double arrow"),
+    ('\u{21D4}', "left right double arrow"),
+    ('\u{21E5}', "rightwards arrow to bar (tab)"),
+    ('\u{21E7}', "upwards white arrow (shift)"),
+    ('\u{2318}', "place of interest sign (command)"),
+    ('\u{2325}', "option key"),
+    ('\u{238B}', "broken circle with northwest arrow (escape)"),
+    ('\u{232B}', "erase to the left (backspace)"),
+    ('\u{27F5}', "long leftwards arrow"),
+    ('\u{27F6}', "long rightwards arrow"),
+    ('\u{2197}', "north east arrow"),
+    ('\u{2198}', "south east arrow"),
+    ('\u{21BA}', "anticlockwise open circle arrow"),
+    ('\u{21BB}', "clockwise open circle arrow"),
+    // Greek
+    ('\u{03B1}', "greek small letter alpha"),
+    ('\u{03B2}', "greek small letter beta"),
+    ('\u{03B3}', "greek small letter gamma"),
+    ('\u{03B4}', "greek small letter delta"),
+    ('\u{03B5}', "greek small letter epsilon"),
+    ('\u{03B6}', "greek small letter zeta"),
+    ('\u{03B7}', "greek small letter eta"),
+    ('\u{03B8}', "greek small letter theta"),
+    ('\u{03B9}', "greek small letter iota"),
+    ('\u{03BA}', "greek small letter kappa"),
+    ('\u{03BB}', "greek small letter lamda"),
+    ('\u{03BC}', "greek small letter mu"),
+    ('\u{03BD}', "greek small letter nu"),
+    ('\u{03BE}', "greek small letter xi"),
+    ('\u{03BF}', "greek small letter omicron"),
+    ('\u{03C0}', "greek small letter pi"),
+    ('\u{03C1}', "greek small letter rho"),
+    ('\u{03C3}', "greek small letter sigma"),
+    ('\u{03C4}', "greek small letter tau"),
+    ('\u{03C5}', "greek small letter upsilon"),
+    ('\u{03C6}', "greek small letter phi"),
+    ('\u{03C7}', "greek small letter chi"),
+    ('\u{03C8}', "greek small letter psi"),
+    ('\u{03C9}', "greek small letter omega"),
+    ('\u{0393}', "greek capital letter gamma"),
+    ('\u{0394}', "greek capital letter delta"),
+    ('\u{0398}', "greek capital letter theta"),
+    ('\u{039B}', "greek capital letter lamda"),
+    ('\u{039E}', "greek capital letter xi"),
+    ('\u{03A0}', "greek capital letter pi"),
+    ('\u{03A3}', "greek capital letter sigma"),
+    ('\u{03A6}', "greek capital letter phi"),
+    ('\u{03A8}', "greek capital letter psi"),
+    ('\u{03A9}', "greek capital letter omega"),
+    // Latin letters with diacritics and ligatures
+    ('\u{00E0}', "latin small letter a with grave"),
+    ('\u{00E1}', "latin small letter a with acute"),
+    ('\u{00E2}', "latin small letter a with circumflex"),
+    ('\u{00E4}', "latin small letter a with diaeresis"),
+    ('\u{00E3}', "latin small letter a with tilde"),
+    ('\u{00E5}', "latin small letter a with ring above"),
+    ('\u{00E6}', "latin small letter ae"),
+    ('\u{00E7}', "latin small letter c with cedilla"),
+    ('\u{00E8}', "latin small letter e with grave"),
+    ('\u{00E9}', "latin small letter e with acute"),
+    ('\u{00EA}', "latin small letter e with circumflex"),
+    ('\u{00EB}', "latin small letter e with diaeresis"),
+    ('\u{00ED}', "latin small letter i with acute"),
+    ('\u{00EF}', "latin small letter i with diaeresis"),
+    ('\u{00F1}', "latin small letter n with tilde"),
+    ('\u{00F3}', "latin small letter o with acute"),
+    ('\u{00F4}', "latin small letter o with circumflex"),
+    ('\u{00F6}', "latin small letter o with diaeresis"),
+    ('\u{00F8}', "latin small letter o with stroke"),
+    ('\u{0153}', "latin small ligature oe"),
+    ('\u{00FA}', "latin small letter u with acute"),
+    ('\u{00FC}', "latin small letter u with diaeresis"),
+    ('\u{00FD}', "latin small letter y with acute"),
+    ('\u{00DF}', "latin small letter sharp s"),
+    ('\u{00F0}', "latin small letter eth"),
+    ('\u{00FE}', "latin small letter thorn"),
+    ('\u{0142}', "latin small letter l with stroke"),
+    ('\u{0161}', "latin small letter s with caron"),
+    ('\u{010D}', "latin small letter c with caron"),
+    ('\u{017E}', "latin small letter z with caron"),
+    ('\u{00C9}', "latin capital letter e with acute"),
+    ('\u{00D1}', "latin capital letter n with tilde"),
+    ('\u{00DC}', "latin capital letter u with diaeresis"),
+    // Combining marks
+    ('\u{0300}', "combining grave accent"),
+    ('\u{0301}', "combining acute accent"),
+    ('\u{0302}', "combining circumflex accent"),
+    ('\u{0303}', "combining tilde"),
+    ('\u{0308}', "combining diaeresis"),
+    // Box drawing and blocks
+    ('\u{2500}', "box drawings light horizontal"),
+    ('\u{2502}', "box drawings light vertical"),
+    ('\u{250C}', "box drawings light down and right"),
+    ('\u{2510}', "box drawings light down and left"),
+    ('\u{2514}', "box drawings light up and right"),
+    ('\u{2518}', "box drawings light up and left"),
+    ('\u{251C}', "box drawings light vertical and right"),
+    ('\u{2524}', "box drawings light vertical and left"),
+    ('\u{252C}', "box drawings light down and horizontal"),
+    ('\u{2534}', "box drawings light up and horizontal"),
+    ('\u{253C}', "box drawings light vertical and horizontal"),
+    ('\u{2550}', "box drawings double horizontal"),
+    ('\u{2551}', "box drawings double vertical"),
+    ('\u{256D}', "box drawings light arc down and right"),
+    ('\u{256E}', "box drawings light arc down and left"),
+    ('\u{2570}', "box drawings light arc up and right"),
+    ('\u{256F}', "box drawings light arc up and left"),
+    ('\u{2588}', "full block"),
+    ('\u{2580}', "upper half block"),
+    ('\u{2584}', "lower half block"),
+    ('\u{2591}', "light shade"),
+    ('\u{2592}', "medium shade"),
+    ('\u{2593}', "dark shade"),
+    // Shapes and symbols
+    ('\u{25A0}', "black square"),
+    ('\u{25A1}', "white square"),
+    ('\u{25B2}', "black up-pointing triangle"),
+    ('\u{25B6}', "black right-pointing triangle"),
+    ('\u{25BC}', "black down-pointing triangle"),
+    ('\u{25C0}', "black left-pointing triangle"),
+    ('\u{25CF}', "black circle"),
+    ('\u{25CB}', "white circle"),
+    ('\u{25C6}', "black diamond"),
+    ('\u{25C7}', "white diamond"),
+    ('\u{2605}', "black star"),
+    ('\u{2606}', "white star"),
+    ('\u{2660}', "black spade suit"),
+    ('\u{2663}', "black club suit"),
+    ('\u{2665}', "black heart suit"),
+    ('\u{2666}', "black diamond suit"),
+    ('\u{2713}', "check mark"),
+    ('\u{2714}', "heavy check mark"),
+    ('\u{2717}', "ballot x"),
+    ('\u{2718}', "heavy ballot x"),
+    ('\u{2610}', "ballot box"),
+    ('\u{2611}', "ballot box with check"),
+    ('\u{2612}', "ballot box with x"),
+    ('\u{2620}', "skull and crossbones"),
+    ('\u{2622}', "radioactive sign"),
+    ('\u{262F}', "yin yang"),
+    ('\u{263A}', "white smiling face"),
+    ('\u{2600}', "black sun with rays"),
+    ('\u{2601}', "cloud"),
+    ('\u{2602}', "umbrella"),
+    ('\u{2603}', "snowman"),
+    ('\u{26A0}', "warning sign"),
+    ('\u{26A1}', "high voltage sign"),
+    ('\u{2699}', "gear"),
+    ('\u{269B}', "atom symbol"),
+    ('\u{2709}', "envelope"),
+    ('\u{270E}', "lower right pencil"),
+    ('\u{2702}', "black scissors"),
+    ('\u{2744}', "snowflake"),
+    ('\u{2764}', "heavy black heart"),
+    ('\u{266A}', "eighth note"),
+    ('\u{266B}', "beamed eighth notes"),
+    ('\u{23CE}', "return symbol"),
+    ('\u{2328}', "keyboard"),
+    ('\u{231A}', "watch"),
+    ('\u{231B}', "hourglass"),
+    ('\u{2615}', "hot beverage"),
+    // Emoji
+    ('\u{1F600}', "grinning face"),
+    ('\u{1F601}', "grinning face with smiling eyes"),
+    ('\u{1F602}', "face with tears of joy"),
+    ('\u{1F603}', "smiling face with open mouth"),
+    ('\u{1F609}', "winking face"),
+    ('\u{1F60A}', "smiling face with smiling eyes"),
+    ('\u{1F60D}', "smiling face with heart-shaped eyes"),
+    ('\u{1F60E}', "smiling face with sunglasses"),
+    ('\u{1F610}', "neutral face"),
+    ('\u{1F622}', "crying face"),
+    ('\u{1F62D}', "loudly crying face"),
+    ('\u{1F631}', "face screaming in fear"),
+    ('\u{1F642}', "slightly smiling face"),
+    ('\u{1F643}', "upside-down face"),
+    ('\u{1F644}', "face with rolling eyes"),
+    ('\u{1F914}', "thinking face"),
+    ('\u{1F923}', "rolling on the floor laughing"),
+    ('\u{1F44D}', "thumbs up sign"),
+    ('\u{1F44E}', "thumbs down sign"),
+    ('\u{1F44B}', "waving hand sign"),
+    ('\u{1F44F}', "clapping hands sign"),
+    ('\u{1F64F}', "person with folded hands"),
+    ('\u{1F4AA}', "flexed biceps"),
+    ('\u{1F440}', "eyes"),
+    ('\u{1F525}', "fire"),
+    ('\u{1F4A1}', "electric light bulb"),
+    ('\u{1F4A5}', "collision symbol"),
+    ('\u{1F4AF}', "hundred points symbol"),
+    ('\u{1F389}', "party popper"),
+    ('\u{1F680}', "rocket"),
+    ('\u{1F41B}', "bug"),
+    ('\u{1F527}', "wrench"),
+    ('\u{1F528}', "hammer"),
+    ('\u{1F512}', "lock"),
+    ('\u{1F513}', "open lock"),
+    ('\u{1F511}', "key"),
+    ('\u{1F4DD}', "memo"),
+    ('\u{1F4C1}', "file folder"),
+    ('\u{1F4C4}', "page facing up"),
+    ('\u{1F4E6}', "package"),
+    ('\u{1F50D}', "left-pointing magnifying glass"),
+    ('\u{1F6A7}', "construction sign"),
+    ('\u{1F6A8}', "police cars revolving light"),
+    ('\u{1F6AB}', "no entry sign"),
+    ('\u{2705}', "white heavy check mark"),
+    ('\u{274C}', "cross mark"),
+    ('\u{2753}', "black question mark ornament"),
+    ('\u{2728}', "sparkles"),
+    ('\u{2B50}', "white medium star"),
+    ('\u{1F31F}', "glowing star"),
+    ('\u{1F308}', "rainbow"),
+    ('\u{1F30D}', "earth globe europe-africa"),
+    ('\u{1F3AF}', "direct hit"),
+    ('\u{1F3C1}', "chequered flag"),
+    ('\u{1F381}', "wrapped present"),
+    ('\u{1F382}', "birthday cake"),
+    ('\u{1F37A}', "beer mug"),
+    ('\u{1F355}', "slice of pizza"),
+    ('\u{1F34E}', "red apple"),
+    ('\u{1F436}', "dog face"),
+    ('\u{1F431}', "cat face"),
+    ('\u{1F980}', "crab"),
+    ('\u{1F40D}', "snake"),
+    ('\u{1F427}', "penguin"),
+    ('\u{1F916}', "robot face"),
+    ('\u{1F47B}', "ghost"),
+    ('\u{1F480}', "skull"),
+    ('\u{1F4A9}', "pile of poo"),
+    ('\u{1F648}', "see-no-evil monkey"),
+];
+
+/// The name of `c` from the bundled table.
+pub fn name(c: char) -> Option<&'static str> {
+    NAMES.iter().find(|&&(n, _)| n == c).map(|&(_, name)| name)
+}
+
+/// A code point typed as `U+1F600`, `u+e9`, `0x2192` or plain hex.
+pub fn parse_codepoint(input: &str) -> Option<char> {
+    let input = input.trim();
+    let hex = ["U+", "u+", "0x", "0X"]
+        .iter()
+        .find_map(|p| input.strip_prefix(p))
+        .unwrap_or(input);
+    if hex.is_empty() || hex.len() > 6 {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+/// Combining marks and variation selectors, drawn over the character
+/// before them.
+const COMBINING: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x20D0, 0x20FF),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0xE0100, 0xE01EF),
+];
+
+/// Zero-width spaces and joiners, direction marks, overrides and isolates,
+/// and the byte order mark: format characters with nothing to draw.
+const FORMAT: &[(u32, u32)] = &[
+    (0x200B, 0x200F),
+    (0x202A, 0x202E),
+    (0x2060, 0x2064),
+    (0x2066, 0x2069),
+    (0xFEFF, 0xFEFF),
+];
+
+/// Whether `c` goes in the same grapheme cluster as `prev` before it, an
+/// approximation of UAX #29 without its tables: combining marks,
+/// variation selectors, skin tones and joiners join what they follow,
+/// what follows a joiner joins it, and `\r\n` is one while other control
+/// characters stand alone, so a line always starts a cluster. Regional
+/// indicators pair up into flags, so whether `prev` joined the character
+/// before it is needed too.
+pub fn joins_previous(prev: char, c: char, prev_joined: bool) -> bool {
+    if c.is_ascii() || prev.is_control() || c.is_control() {
+        return prev == '\r' && c == '\n';
+    }
+    let cp = c as u32;
+    let regional = |c: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&c);
+    COMBINING.iter().any(|&(lo, hi)| (lo..=hi).contains(&cp))
+        || (0x1F3FB..=0x1F3FF).contains(&cp)
+        || c == '\u{200D}'
+        || prev == '\u{200D}'
+        || (regional(prev) && regional(c) && !prev_joined)
+}
+
+/// Whether `c` belongs in a word: a letter or digit in any script, an
+/// underscore, or a combining mark on one of those.
+pub fn is_word_char(c: char) -> bool {
+    let cp = c as u32;
+    c.is_alphanumeric() || c == '_' || COMBINING.iter().any(|&(lo, hi)| (lo..=hi).contains(&cp))
+}
+
+/// Terminal columns `c` takes: 0 for control and combining characters, 2 for
+/// East Asian wide characters and emoji, 1 otherwise.
+pub fn char_width(c: char) -> usize {
+    let cp = c as u32;
+    if cp == 0 || cp < 0x20 || (0x7F..0xA0).contains(&cp) {
+        return 0;
+    }
+    const WIDE: &[(u32, u32)] = &[
+        (0x1100, 0x115F),
+        (0x231A, 0x231B),
+        (0x2329, 0x232A),
+        (0x23E9, 0x23EC),
+        (0x2614, 0x2615),
+        (0x26A1, 0x26A1),
+        (0x2705, 0x2705),
+        (0x2728, 0x2728),
+        (0x274C, 0x274C),
+        (0x2753, 0x2755),
+        (0x2B50, 0x2B50),
+        (0x2E80, 0x303E),
+        (0x3041, 0x33FF),
+        (0x3400, 0x4DBF),
+        (0x4E00, 0x9FFF),
+        (0xA000, 0xA4CF),
+        (0xAC00, 0xD7A3),
+        (0xF900, 0xFAFF),
+        (0xFE30, 0xFE4F),
+        (0xFF00, 0xFF60),
+        (0xFFE0, 0xFFE6),
+        (0x1F300, 0x1F64F),
+        (0x1F680, 0x1F6FF),
+        (0x1F900, 0x1F9FF),
+        (0x20000, 0x3FFFD),
+    ];
+    let within = |ranges: &[(u32, u32)]| ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&cp));
+    if within(COMBINING) || within(FORMAT) {
+        0
+    } else if within(WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Screen cells `c` is drawn in: like `char_width`, except that a
+/// zero-width character still gets a cell of its own.
+pub fn cell_width(c: char) -> usize {
+    char_width(c).max(1)
+}
+
+/// What stands for `c` in text when it would be invisible or unsafe to
+/// send to the terminal: `^[` style for C0 controls and DEL, `<U+FEFF>`
+/// style for C1 controls and format characters. None for characters drawn
+/// as themselves, tabs included, which go to tab stops.
+pub fn placeholder(c: char) -> Option<String> {
+    let cp = c as u32;
+    if !has_placeholder(c) {
+        None
+    } else if cp < 0x20 || cp == 0x7F {
+        Some(format!("^{}", char::from((cp ^ 0x40) as u8)))
+    } else {
+        Some(format!("<U+{:04X}>", cp))
+    }
+}
+
+pub fn has_placeholder(c: char) -> bool {
+    let cp = c as u32;
+    (c.is_control() && c != '\t') || FORMAT.iter().any(|&(lo, hi)| (lo..=hi).contains(&cp))
+}
+
+/// Screen cells `c` takes in text: those of its placeholder if it has one,
+/// else its `cell_width`.
+pub fn text_width(c: char) -> usize {
+    placeholder(c).map_or(cell_width(c), |shown| shown.len())
+}
+
+/// Terminal columns `s` takes.
+pub fn display_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// The longest prefix of `s` that fits in `max_cols` columns. It ends on a
+/// character boundary, leaves out a wide character that would straddle the
+/// limit and keeps combining marks with the character before them.
+pub fn truncate_display(s: &str, max_cols: usize) -> &str {
+    let mut cols = 0;
+    for (i, c) in s.char_indices() {
+        cols += char_width(c);
+        if cols > max_cols {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+/// One line about `c`: its code point, name, UTF-8 bytes and width.
+pub fn describe(c: char) -> String {
+    let mut buf = [0u8; 4];
+    let bytes: Vec<String> = c
+        .encode_utf8(&mut buf)
+        .bytes()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    let shown = if char_width(c) == 0 || c.is_whitespace() {
+        String::new()
+    } else {
+        format!("'{}' ", c)
+    };
+    let name = name(c).map_or(String::new(), |n| format!(" {}", n.to_uppercase()));
+    format!(
+        "{}U+{:04X}{}  UTF-8: {}  width: {}",
+        shown,
+        c as u32,
+        name,
+        bytes.join(" "),
+        char_width(c)
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_codepoint() {
+        assert_eq!(parse_codepoint("u+1F600"), Some('\u{1F600}'));
+        assert_eq!(parse_codepoint("U+e9"), Some('é'));
+        assert_eq!(parse_codepoint("0x2192"), Some('→'));
+        assert_eq!(parse_codepoint("41"), Some('A'));
+        // Surrogates are not characters
+        assert_eq!(parse_codepoint("U+D800"), None);
+        assert_eq!(parse_codepoint("alpha"), None);
+        assert_eq!(parse_codepoint("U+"), None);
+    }
+
+    #[test]
+    fn test_truncate_display() {
+        assert_eq!(truncate_display("hello", 3), "hel");
+        assert_eq!(truncate_display("hello", 9), "hello");
+        // Multi-byte characters are never split
+        assert_eq!(truncate_display("café au lait", 4), "café");
+        assert_eq!(truncate_display("日本語", 4), "日本");
+        // A wide character that would cross the limit is left out
+        assert_eq!(truncate_display("日本語", 3), "日");
+        assert_eq!(truncate_display("a😀b", 2), "a");
+        // Combining marks stay with their base character
+        assert_eq!(truncate_display("e\u{0301}x", 1), "e\u{0301}");
+        assert_eq!(truncate_display("abc", 0), "");
+        assert_eq!(display_width("a日😀\u{0301}"), 5);
+    }
+
+    #[test]
+    fn test_joins_previous() {
+        let clusters = |s: &str| {
+            let (mut n, mut prev, mut joined) = (0, None, false);
+            for c in s.chars() {
+                joined = prev.is_some_and(|p| joins_previous(p, c, joined));
+                n += usize::from(!joined);
+                prev = Some(c);
+            }
+            n
+        };
+        assert_eq!(clusters("abc"), 3);
+        assert_eq!(clusters("e\u{301}"), 1);
+        assert_eq!(clusters("a\r\nb"), 3);
+        assert_eq!(clusters("\n\u{301}"), 2);
+        // Family emoji with joiners, and a skin tone
+        assert_eq!(clusters("👨\u{200D}👩\u{200D}👧"), 1);
+        assert_eq!(clusters("👍\u{1F3FD}"), 1);
+        // Three regional indicators: a flag and a lone one
+        assert_eq!(clusters("🇪🇸🇫"), 2);
+    }
+
+    #[test]
+    fn test_placeholder() {
+        assert_eq!(placeholder('\x1b').as_deref(), Some("^["));
+        assert_eq!(placeholder('\x0c').as_deref(), Some("^L"));
+        assert_eq!(placeholder('\0').as_deref(), Some("^@"));
+        assert_eq!(placeholder('\x7f').as_deref(), Some("^?"));
+        assert_eq!(placeholder('\u{85}').as_deref(), Some("<U+0085>"));
+        assert_eq!(placeholder('\u{FEFF}').as_deref(), Some("<U+FEFF>"));
+        assert_eq!(placeholder('\u{202E}').as_deref(), Some("<U+202E>"));
+        assert_eq!(placeholder('\t'), None);
+        assert_eq!(placeholder('a'), None);
+        assert_eq!(placeholder('\u{0301}'), None);
+        assert_eq!(text_width('\x1b'), 2);
+        assert_eq!(text_width('\u{200B}'), 8);
+        assert_eq!(text_width('日'), 2);
+    }
+
+    #[test]
+    fn test_char_width() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('é'), 1);
+        assert_eq!(char_width('\u{0301}'), 0);
+        assert_eq!(char_width('漢'), 2);
+        assert_eq!(char_width('\u{1F600}'), 2);
+        assert_eq!(char_width('\t'), 0);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe('é'),
+            "'é' U+00E9 LATIN SMALL LETTER E WITH ACUTE  UTF-8: C3 A9  width: 1"
+        );
+        assert_eq!(describe(' '), "U+0020  UTF-8: 20  width: 1");
+    }
+
+    #[test]
+    fn test_names_unique() {
+        for (i, (a, _)) in NAMES.iter().enumerate() {
+            assert!(NAMES[i + 1..].iter().all(|(b, _)| a != b), "{:?}", a);
+        }
+    }
+}
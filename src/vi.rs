@@ -0,0 +1,485 @@
+use crate::input::{Key, KeyEvent};
+
+// ---------------------------------------------------------------------------
+// Vi key parser — turns normal/visual mode keystrokes into actions
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    GotoLine(usize), // 1-based
+}
+
+impl Motion {
+    /// Linewise motions make operators act on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FileStart | Motion::FileEnd | Motion::GotoLine(_)
+        )
+    }
+
+    /// Inclusive motions make operators include the character they land on.
+    pub fn is_inclusive(self) -> bool {
+        self == Motion::WordEnd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertAt {
+    Cursor,    // i
+    After,     // a
+    LineStart, // I
+    LineEnd,   // A
+    LineBelow, // o
+    LineAbove, // O
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// More keys are needed (count, operator or `g` prefix).
+    Pending,
+    Move(Motion, usize),
+    Operate(Operator, Motion, usize),
+    OperateLines(Operator, usize),
+    VisualOperate(Operator),
+    Insert(InsertAt),
+    EnterVisual,
+    ExitVisual,
+    Paste {
+        after: bool,
+        count: usize,
+    },
+    Undo,
+    Redo,
+    Repeat(usize),
+    /// Ctrl+A / Ctrl+X: add to the number at or after the cursor.
+    AddToNumber(i64),
+    /// Open the `:` command line.
+    CommandLine,
+    /// Not a vi key: let the regular keymap handle it.
+    PassThrough,
+    /// Unbound or cancelled; do nothing.
+    None,
+}
+
+impl Action {
+    /// Actions that modify the buffer and can be repeated with `.`.
+    pub fn is_change(self) -> bool {
+        match self {
+            Action::Operate(op, _, _) | Action::OperateLines(op, _) => op != Operator::Yank,
+            Action::Insert(_) | Action::Paste { .. } | Action::AddToNumber(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Counts are capped here, far beyond any file's lines, so a long run of
+/// digits can neither overflow nor keep the editor busy.
+pub const MAX_COUNT: usize = 100_000;
+
+pub struct ViState {
+    pub mode: Mode,
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>, // with the count typed before it
+    pending_g: bool,
+}
+
+impl ViState {
+    pub fn new() -> Self {
+        ViState {
+            mode: Mode::Normal,
+            count: None,
+            operator: None,
+            pending_g: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.pending_g = false;
+    }
+
+    fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.pending_g
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1)
+    }
+
+    /// Feed one key in normal or visual mode.
+    pub fn feed(&mut self, ke: &KeyEvent) -> Action {
+        if ke.alt {
+            self.reset();
+            return Action::PassThrough;
+        }
+        if ke.ctrl {
+            let count = self.take_count() as i64;
+            let visual = self.mode == Mode::Visual;
+            self.reset();
+            return match ke.key {
+                Key::Char('r') => Action::Redo,
+                Key::Char('a') if !visual => Action::AddToNumber(count),
+                Key::Char('x') if !visual => Action::AddToNumber(-count),
+                _ => Action::PassThrough,
+            };
+        }
+        match ke.key {
+            Key::Char(c) => self.feed_char(c),
+            Key::Left => self.motion(Motion::Left),
+            Key::Right => self.motion(Motion::Right),
+            Key::Up => self.motion(Motion::Up),
+            Key::Down => self.motion(Motion::Down),
+            Key::Home => self.motion(Motion::LineStart),
+            Key::End => self.motion(Motion::LineEnd),
+            Key::Enter => self.motion(Motion::Down),
+            Key::Backspace => self.motion(Motion::Left),
+            Key::Delete => self.feed_char('x'),
+            Key::Tab => {
+                self.reset();
+                Action::None
+            }
+            Key::Escape if self.is_pending() => {
+                self.reset();
+                Action::None
+            }
+            Key::Escape if self.mode == Mode::Visual => {
+                self.mode = Mode::Normal;
+                Action::ExitVisual
+            }
+            _ => {
+                self.reset();
+                Action::PassThrough
+            }
+        }
+    }
+
+    fn feed_char(&mut self, c: char) -> Action {
+        if self.pending_g {
+            self.pending_g = false;
+            return if c == 'g' {
+                let motion = match self.count.take() {
+                    Some(n) => Motion::GotoLine(n),
+                    None => Motion::FileStart,
+                };
+                self.motion(motion)
+            } else {
+                self.reset();
+                Action::None
+            };
+        }
+
+        match c {
+            '1'..='9' => return self.push_digit(c),
+            '0' if self.count.is_some() => return self.push_digit(c),
+            _ => {}
+        }
+
+        match c {
+            'h' => self.motion(Motion::Left),
+            'l' => self.motion(Motion::Right),
+            'j' => self.motion(Motion::Down),
+            'k' => self.motion(Motion::Up),
+            'w' => self.motion(Motion::WordForward),
+            'b' => self.motion(Motion::WordBackward),
+            'e' => self.motion(Motion::WordEnd),
+            '0' => self.motion(Motion::LineStart),
+            '$' => self.motion(Motion::LineEnd),
+            'G' => {
+                let motion = match self.count.take() {
+                    Some(n) => Motion::GotoLine(n),
+                    None => Motion::FileEnd,
+                };
+                self.motion(motion)
+            }
+            'g' => {
+                self.pending_g = true;
+                Action::Pending
+            }
+            'd' => self.operator(Operator::Delete),
+            'c' => self.operator(Operator::Change),
+            'y' => self.operator(Operator::Yank),
+            _ if self.operator.is_some() => {
+                self.reset();
+                Action::None
+            }
+            'x' if self.mode == Mode::Visual => self.operator(Operator::Delete),
+            'x' => {
+                let count = self.take_count();
+                Action::Operate(Operator::Delete, Motion::Right, count)
+            }
+            'D' => self.line_end_operator(Operator::Delete),
+            'C' => self.line_end_operator(Operator::Change),
+            'i' => self.insert(InsertAt::Cursor),
+            'a' => self.insert(InsertAt::After),
+            'I' => self.insert(InsertAt::LineStart),
+            'A' => self.insert(InsertAt::LineEnd),
+            'o' => self.insert(InsertAt::LineBelow),
+            'O' => self.insert(InsertAt::LineAbove),
+            'v' => {
+                self.reset();
+                if self.mode == Mode::Visual {
+                    self.mode = Mode::Normal;
+                    Action::ExitVisual
+                } else {
+                    self.mode = Mode::Visual;
+                    Action::EnterVisual
+                }
+            }
+            'p' | 'P' => {
+                let count = self.take_count();
+                Action::Paste {
+                    after: c == 'p',
+                    count,
+                }
+            }
+            'u' => {
+                self.reset();
+                Action::Undo
+            }
+            '.' => {
+                let count = self.take_count();
+                Action::Repeat(count)
+            }
+            ':' => {
+                self.reset();
+                Action::CommandLine
+            }
+            _ => {
+                self.reset();
+                Action::None
+            }
+        }
+    }
+
+    fn push_digit(&mut self, c: char) -> Action {
+        let digit = c.to_digit(10).unwrap_or(0) as usize;
+        let count = self.count.unwrap_or(0);
+        self.count = Some((count * 10 + digit).min(MAX_COUNT));
+        Action::Pending
+    }
+
+    fn motion(&mut self, motion: Motion) -> Action {
+        let count = self.take_count();
+        match self.operator.take() {
+            Some((op, op_count)) => {
+                self.reset();
+                if op == Operator::Change {
+                    self.mode = Mode::Insert;
+                }
+                Action::Operate(op, motion, op_count.saturating_mul(count).min(MAX_COUNT))
+            }
+            None => Action::Move(motion, count),
+        }
+    }
+
+    fn operator(&mut self, op: Operator) -> Action {
+        if self.mode == Mode::Visual {
+            self.reset();
+            self.mode = if op == Operator::Change {
+                Mode::Insert
+            } else {
+                Mode::Normal
+            };
+            return Action::VisualOperate(op);
+        }
+        let count = self.take_count();
+        match self.operator {
+            // Doubled operator (dd, cc, yy) acts on whole lines
+            Some((pending, op_count)) if pending == op => {
+                self.reset();
+                if op == Operator::Change {
+                    self.mode = Mode::Insert;
+                }
+                Action::OperateLines(op, op_count.saturating_mul(count).min(MAX_COUNT))
+            }
+            Some(_) => {
+                self.reset();
+                Action::None
+            }
+            None => {
+                self.operator = Some((op, count));
+                Action::Pending
+            }
+        }
+    }
+
+    fn line_end_operator(&mut self, op: Operator) -> Action {
+        self.reset();
+        if op == Operator::Change {
+            self.mode = Mode::Insert;
+        }
+        Action::Operate(op, Motion::LineEnd, 1)
+    }
+
+    fn insert(&mut self, at: InsertAt) -> Action {
+        self.reset();
+        self.mode = Mode::Insert;
+        Action::Insert(at)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_str(vi: &mut ViState, keys: &str) -> Action {
+        let mut last = Action::None;
+        for c in keys.chars() {
+            last = vi.feed(&KeyEvent {
+                key: Key::Char(c),
+                ctrl: false,
+                alt: false,
+                shift: false,
+            });
+        }
+        last
+    }
+
+    #[test]
+    fn test_motions_with_counts() {
+        let mut vi = ViState::new();
+        assert_eq!(feed_str(&mut vi, "j"), Action::Move(Motion::Down, 1));
+        assert_eq!(
+            feed_str(&mut vi, "3w"),
+            Action::Move(Motion::WordForward, 3)
+        );
+        assert_eq!(feed_str(&mut vi, "10l"), Action::Move(Motion::Right, 10));
+        assert_eq!(feed_str(&mut vi, "0"), Action::Move(Motion::LineStart, 1));
+    }
+
+    #[test]
+    fn test_huge_counts() {
+        let mut vi = ViState::new();
+        assert_eq!(
+            feed_str(&mut vi, "99999999999j"),
+            Action::Move(Motion::Down, MAX_COUNT)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "9999999999d9999999999w"),
+            Action::Operate(Operator::Delete, Motion::WordForward, MAX_COUNT)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "99999d99999d"),
+            Action::OperateLines(Operator::Delete, MAX_COUNT)
+        );
+    }
+
+    #[test]
+    fn test_goto_lines() {
+        let mut vi = ViState::new();
+        assert_eq!(feed_str(&mut vi, "gg"), Action::Move(Motion::FileStart, 1));
+        assert_eq!(feed_str(&mut vi, "G"), Action::Move(Motion::FileEnd, 1));
+        assert_eq!(
+            feed_str(&mut vi, "12G"),
+            Action::Move(Motion::GotoLine(12), 1)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "5gg"),
+            Action::Move(Motion::GotoLine(5), 1)
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut vi = ViState::new();
+        assert_eq!(
+            feed_str(&mut vi, "dw"),
+            Action::Operate(Operator::Delete, Motion::WordForward, 1)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "2d3w"),
+            Action::Operate(Operator::Delete, Motion::WordForward, 6)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "3dd"),
+            Action::OperateLines(Operator::Delete, 3)
+        );
+        assert_eq!(
+            feed_str(&mut vi, "yy"),
+            Action::OperateLines(Operator::Yank, 1)
+        );
+        assert_eq!(vi.mode, Mode::Normal);
+
+        assert_eq!(
+            feed_str(&mut vi, "ce"),
+            Action::Operate(Operator::Change, Motion::WordEnd, 1)
+        );
+        assert_eq!(vi.mode, Mode::Insert);
+    }
+
+    #[test]
+    fn test_mismatched_operator_cancels() {
+        let mut vi = ViState::new();
+        assert_eq!(feed_str(&mut vi, "dy"), Action::None);
+        assert_eq!(feed_str(&mut vi, "dz"), Action::None);
+        assert_eq!(feed_str(&mut vi, "j"), Action::Move(Motion::Down, 1));
+    }
+
+    #[test]
+    fn test_visual_mode() {
+        let mut vi = ViState::new();
+        assert_eq!(feed_str(&mut vi, "v"), Action::EnterVisual);
+        assert_eq!(vi.mode, Mode::Visual);
+        assert_eq!(feed_str(&mut vi, "e"), Action::Move(Motion::WordEnd, 1));
+        assert_eq!(
+            feed_str(&mut vi, "d"),
+            Action::VisualOperate(Operator::Delete)
+        );
+        assert_eq!(vi.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_ctrl_keys_pass_through() {
+        let mut vi = ViState::new();
+        let ctrl = |c| KeyEvent {
+            key: Key::Char(c),
+            ctrl: true,
+            alt: false,
+            shift: false,
+        };
+        assert_eq!(vi.feed(&ctrl('s')), Action::PassThrough);
+        assert_eq!(vi.feed(&ctrl('r')), Action::Redo);
+        assert_eq!(vi.feed(&ctrl('a')), Action::AddToNumber(1));
+        feed_str(&mut vi, "12");
+        assert_eq!(vi.feed(&ctrl('x')), Action::AddToNumber(-12));
+    }
+
+    #[test]
+    fn test_is_change() {
+        assert!(Action::Operate(Operator::Delete, Motion::Right, 1).is_change());
+        assert!(Action::Insert(InsertAt::After).is_change());
+        assert!(!Action::OperateLines(Operator::Yank, 1).is_change());
+        assert!(!Action::Move(Motion::Down, 1).is_change());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::render::Color;
+use crate::unicode;
+
+// ---------------------------------------------------------------------------
+// Virtual text — annotations drawn with a buffer's lines but not part of it
+// ---------------------------------------------------------------------------
+//
+// Diagnostics or inlay hints attach short texts to a line end or
+// before a byte of a line. They are only drawn: the buffer, its offsets and
+// the saved file never see them. Positions do not follow edits; a source
+// replaces its annotations whenever they change.
+
+/// Who attached an annotation. Each source replaces only its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Diagnostics,
+    InlayHints,
+}
+
+/// Where an annotation is drawn on its line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// After the text, past a space.
+    LineEnd,
+    /// Before the character at this byte column, pushing the rest right.
+    Inline(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub line: usize,
+    pub placement: Placement,
+    pub text: String,
+    pub fg: Color,
+}
+
+impl Annotation {
+    pub fn width(&self) -> usize {
+        unicode::display_width(&self.text)
+    }
+}
+
+/// The annotations of one buffer, ordered by line.
+#[derive(Debug, Default)]
+pub struct VirtualText {
+    annotations: Vec<(Source, Annotation)>,
+}
+
+impl VirtualText {
+    /// Replace the annotations of `source`.
+    pub fn set(&mut self, source: Source, annotations: Vec<Annotation>) {
+        self.annotations.retain(|(s, _)| *s != source);
+        self.annotations
+            .extend(annotations.into_iter().map(|a| (source, a)));
+        self.annotations.sort_by_key(|(_, a)| a.line);
+    }
+
+    pub fn clear(&mut self, source: Source) {
+        self.set(source, Vec::new());
+    }
+
+    /// The annotations of `line`: inline ones by column, then line ends.
+    pub fn line(&self, line: usize) -> Vec<Annotation> {
+        let first = self.annotations.partition_point(|(_, a)| a.line < line);
+        let mut found: Vec<Annotation> = self.annotations[first..]
+            .iter()
+            .take_while(|(_, a)| a.line == line)
+            .map(|(_, a)| a.clone())
+            .collect();
+        found.sort_by_key(|a| match a.placement {
+            Placement::Inline(col) => (0, col),
+            Placement::LineEnd => (1, 0),
+        });
+        found
+    }
+}
+
+/// Shift the `cells` of a line (byte offset, character, column) right to
+/// make room for its inline `annotations`, and return the column each one
+/// starts at. An annotation past the text goes after it.
+pub fn make_room(cells: &mut [(usize, char, usize)], annotations: &[Annotation]) -> Vec<usize> {
+    let end = cells
+        .iter()
+        .map(|&(_, ch, col)| col + unicode::cell_width(ch))
+        .max()
+        .unwrap_or(0);
+    let mut starts = Vec::new();
+    let mut shift = 0;
+    for annotation in annotations {
+        let Placement::Inline(at) = annotation.placement else {
+            continue;
+        };
+        // Cells are already shifted for the annotations before this one
+        let col = cells
+            .iter()
+            .filter(|&&(offset, _, _)| offset >= at)
+            .map(|&(_, _, col)| col)
+            .min()
+            .unwrap_or(end + shift);
+        starts.push(col);
+        shift += annotation.width();
+        for cell in cells.iter_mut().filter(|cell| cell.0 >= at) {
+            cell.2 += annotation.width();
+        }
+    }
+    starts
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(line: usize, placement: Placement, text: &str) -> Annotation {
+        Annotation {
+            line,
+            placement,
+            text: text.to_string(),
+            fg: Color::Default,
+        }
+    }
+
+    fn texts(vt: &VirtualText, line: usize) -> Vec<String> {
+        vt.line(line).into_iter().map(|a| a.text).collect()
+    }
+
+    #[test]
+    fn test_sources_replace_their_own() {
+        let mut vt = VirtualText::default();
+        vt.set(
+            Source::Diagnostics,
+            vec![
+                note(3, Placement::LineEnd, "unused"),
+                note(1, Placement::LineEnd, "x"),
+            ],
+        );
+        vt.set(
+            Source::InlayHints,
+            vec![note(3, Placement::Inline(4), ": i32")],
+        );
+        assert_eq!(texts(&vt, 3), [": i32", "unused"]);
+        assert_eq!(texts(&vt, 1), ["x"]);
+        assert!(texts(&vt, 2).is_empty());
+
+        vt.set(Source::Diagnostics, vec![note(2, Placement::LineEnd, "y")]);
+        assert_eq!(texts(&vt, 3), [": i32"]);
+        assert_eq!(texts(&vt, 2), ["y"]);
+        vt.clear(Source::InlayHints);
+        assert!(texts(&vt, 3).is_empty());
+    }
+
+    #[test]
+    fn test_make_room() {
+        // "let x = 1" with hints before "=", before "1" and past the end
+        let mut cells: Vec<_> = "let x = 1"
+            .char_indices()
+            .map(|(at, ch)| (at, ch, at))
+            .collect();
+        let notes = [
+            note(0, Placement::Inline(6), ": i32 "),
+            note(0, Placement::Inline(8), "v:"),
+            note(0, Placement::Inline(20), ";"),
+        ];
+        assert_eq!(make_room(&mut cells, &notes), [6, 14, 17]);
+        assert_eq!(cells[5], (5, ' ', 5));
+        assert_eq!(cells[6], (6, '=', 12));
+        assert_eq!(cells[8], (8, '1', 16));
+    }
+}